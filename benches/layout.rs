@@ -0,0 +1,111 @@
+//! Benchmarks of the layout engine on deep and wide element trees.
+
+use std::hint::black_box;
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use scratchbar::tui::{self, testing};
+
+const SIZE: tui::Size = tui::Size {
+    width: 200,
+    height: 40,
+};
+
+fn tag(i: usize) -> tui::CustomId {
+    tui::CustomId::from_bytes(&i.to_le_bytes())
+}
+
+/// Stacks nested `depth` times, alternating between the axes, each with a label and a button.
+fn deep(depth: usize) -> tui::Elem {
+    (0..depth).fold(tui::Elem::text("leaf", Default::default()), |inner, i| {
+        let axis = if i % 2 == 0 {
+            tui::Axis::X
+        } else {
+            tui::Axis::Y
+        };
+        tui::Elem::stack(
+            axis,
+            [
+                tui::Elem::text(format_args!("{i}"), Default::default()),
+                inner,
+                tui::Elem::text("x", Default::default()).interactive(tag(i)),
+            ],
+            tui::StackOpts::default(),
+        )
+    })
+}
+
+/// A menu of `rows` rows, each with wrapped text and interactive cells.
+fn wide(rows: usize) -> tui::Elem {
+    tui::Elem::stack(
+        tui::Axis::Y,
+        (0..rows).map(|row| {
+            tui::Elem::stack(
+                tui::Axis::X,
+                [
+                    tui::Elem::wrapped_text(
+                        "some text that wraps when the menu is narrow",
+                        20,
+                        tui::WrapMode::Word,
+                    ),
+                    tui::Elem::fill_cells_single(" "),
+                ]
+                .into_iter()
+                .chain((0..8).map(|col| {
+                    tui::Elem::text(format_args!(" {col} "), Default::default())
+                        .interactive(tag(row * 8 + col))
+                })),
+                tui::StackOpts::default(),
+            )
+        }),
+        tui::StackOpts::default(),
+    )
+}
+
+fn trees() -> [(&'static str, tui::Elem); 4] {
+    [
+        ("deep", deep(16)),
+        ("deeper", deep(64)),
+        ("wide", wide(40)),
+        ("wider", wide(400)),
+    ]
+}
+
+fn render(c: &mut Criterion) {
+    let mut group = c.benchmark_group("render");
+    for (name, elem) in trees() {
+        group.bench_with_input(BenchmarkId::from_parameter(name), &elem, |b, elem| {
+            b.iter(|| testing::render_to_bytes(elem, SIZE));
+        });
+    }
+    group.finish();
+}
+
+fn calc_min_size(c: &mut Criterion) {
+    let mut group = c.benchmark_group("calc_min_size");
+    for (name, elem) in trees() {
+        group.bench_with_input(BenchmarkId::from_parameter(name), &elem, |b, elem| {
+            b.iter(|| testing::min_size(elem));
+        });
+    }
+    group.finish();
+}
+
+fn interpret_mouse_event(c: &mut Criterion) {
+    let mut group = c.benchmark_group("interpret_mouse_event");
+    for (name, elem) in trees() {
+        let mut rendered = testing::RenderedElem::render(&elem, SIZE);
+        let mut cell = 0u16;
+        group.bench_function(BenchmarkId::from_parameter(name), |b| {
+            b.iter(|| {
+                // Move over every cell in turn, so that the hovered element changes
+                cell = cell.wrapping_add(1);
+                let (column, row) = (cell % SIZE.width, cell / SIZE.width % SIZE.height);
+                black_box(rendered.mouse(tui::InteractKind::Hover, column, row))
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, render, calc_min_size, interpret_mouse_event);
+criterion_main!(benches);
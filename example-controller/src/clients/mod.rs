@@ -1,5 +1,5 @@
-pub mod hypr;
+pub mod niri;
+pub mod portal;
 pub mod ppd;
-pub mod pulse;
 pub mod tray;
 pub mod upower;
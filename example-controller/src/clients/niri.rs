@@ -0,0 +1,161 @@
+use crate::desktop::{BasicDesktopState, BasicWorkspace, WorkspaceId};
+use crate::utils::{ReloadRx, ResultExt, run_or_retry};
+use anyhow::Context;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt as _, AsyncWriteExt as _};
+use tokio::sync::watch;
+use tokio_util::task::AbortOnDropHandle;
+
+// https://yalter.github.io/niri/niri_ipc/
+// Requests and replies are newline-terminated JSON.
+const NIRI_SOCKET_VAR: &str = "NIRI_SOCKET";
+
+pub fn is_running() -> bool {
+    std::env::var_os(NIRI_SOCKET_VAR).is_some()
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct NiriWorkspace {
+    id: u64,
+    idx: u8,
+    name: Option<String>,
+    output: Option<String>,
+    is_active: bool,
+}
+
+#[derive(serde::Deserialize, Debug)]
+enum NiriEvent {
+    WorkspacesChanged { workspaces: Vec<NiriWorkspace> },
+    WorkspaceActivated { id: u64, focused: bool },
+}
+
+pub struct NiriClient {
+    pub basic_rx: watch::Receiver<BasicDesktopState>,
+    _background: AbortOnDropHandle<()>,
+}
+impl NiriClient {
+    pub fn switch_workspace(&self, id: WorkspaceId) {
+        tokio::spawn(async move {
+            let Some(id) = id
+                .as_str()
+                .parse::<u64>()
+                .context("Invalid niri workspace id")
+                .ok_or_log()
+            else {
+                return;
+            };
+            let request = serde_json::json!({
+                "Action": { "FocusWorkspace": { "reference": { "Id": id } } }
+            });
+            async {
+                let mut stream = connect_socket().await?;
+                send_request(&mut stream, &request).await?;
+                anyhow::Ok(())
+            }
+            .await
+            .context("Failed to switch niri workspace")
+            .ok_or_log();
+        });
+    }
+}
+
+async fn connect_socket() -> anyhow::Result<tokio::net::UnixStream> {
+    let path = std::env::var_os(NIRI_SOCKET_VAR).context("Missing niri socket env var")?;
+    tokio::net::UnixStream::connect(&path)
+        .await
+        .with_context(|| format!("Failed to connect to niri socket {path:?}"))
+}
+
+async fn send_request(
+    stream: &mut tokio::net::UnixStream,
+    request: &serde_json::Value,
+) -> anyhow::Result<()> {
+    let mut line = serde_json::to_vec(request)?;
+    line.push(b'\n');
+    stream.write_all(&line).await?;
+    Ok(())
+}
+
+async fn run_event_stream(basic_tx: &watch::Sender<BasicDesktopState>) -> anyhow::Result<()> {
+    let mut stream = connect_socket().await?;
+    send_request(&mut stream, &serde_json::json!("EventStream")).await?;
+
+    let mut lines = tokio::io::BufReader::new(stream).lines();
+
+    let reply = lines
+        .next_line()
+        .await?
+        .context("niri closed the socket before replying")?;
+    serde_json::from_str::<Result<serde_json::Value, String>>(&reply)
+        .context("Failed to deserialize niri reply")?
+        .map_err(|err| anyhow::anyhow!("niri refused event stream: {err}"))?;
+
+    // The full workspace list is sent as the first event, so we do not need to keep it around
+    // across reconnects.
+    let mut workspaces = HashMap::<u64, NiriWorkspace>::new();
+
+    while let Some(line) = lines.next_line().await? {
+        // Events we do not handle fail to deserialize and are skipped
+        let Ok(ev) = serde_json::from_str::<NiriEvent>(&line) else {
+            log::trace!("Ignoring niri event {line}");
+            continue;
+        };
+        match ev {
+            NiriEvent::WorkspacesChanged { workspaces: wss } => {
+                workspaces = wss.into_iter().map(|ws| (ws.id, ws)).collect();
+            }
+            NiriEvent::WorkspaceActivated { id, focused: _ } => {
+                let Some(output) = workspaces.get(&id).map(|ws| ws.output.clone()) else {
+                    continue;
+                };
+                for ws in workspaces.values_mut().filter(|ws| ws.output == output) {
+                    ws.is_active = ws.id == id;
+                }
+            }
+        }
+
+        let mut basic: Vec<_> = workspaces.values().collect();
+        basic.sort_unstable_by(|w1, w2| (&w1.output, w1.idx).cmp(&(&w2.output, w2.idx)));
+        let workspaces = basic
+            .into_iter()
+            .map(
+                |&NiriWorkspace {
+                     id,
+                     idx,
+                     ref name,
+                     ref output,
+                     is_active,
+                 }| BasicWorkspace {
+                    id: id.to_string().into(),
+                    name: name.clone().unwrap_or_else(|| idx.to_string()).into(),
+                    monitor: output.as_deref().map(Arc::from),
+                    is_active,
+                },
+            )
+            .collect();
+        basic_tx.send_replace(BasicDesktopState { workspaces });
+    }
+
+    anyhow::bail!("niri closed the event stream")
+}
+
+async fn run_bg(basic_tx: watch::Sender<BasicDesktopState>, mut reload_rx: ReloadRx) {
+    run_or_retry(
+        async |_: &mut ()| run_event_stream(&basic_tx).await,
+        (),
+        |res| res.context("niri event stream failed"),
+        Duration::from_secs(10),
+        Some(&mut reload_rx),
+    )
+    .await
+}
+
+pub fn connect(reload_rx: ReloadRx) -> NiriClient {
+    let (basic_tx, basic_rx) = watch::channel(BasicDesktopState::default());
+    NiriClient {
+        _background: AbortOnDropHandle::new(tokio::spawn(run_bg(basic_tx, reload_rx))),
+        basic_rx,
+    }
+}
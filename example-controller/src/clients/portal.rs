@@ -0,0 +1,107 @@
+use anyhow::Context as _;
+use futures::StreamExt as _;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio_util::task::AbortOnDropHandle;
+
+use crate::utils::{ReloadRx, run_or_retry};
+
+mod dbus {
+    use zbus::{
+        proxy,
+        zvariant::{OwnedValue, Value},
+    };
+
+    #[proxy(
+        interface = "org.freedesktop.portal.Settings",
+        default_service = "org.freedesktop.portal.Desktop",
+        default_path = "/org/freedesktop/portal/desktop"
+    )]
+    pub trait Settings {
+        fn read_one(&self, namespace: &str, key: &str) -> zbus::Result<OwnedValue>;
+
+        #[zbus(signal)]
+        fn setting_changed(&self, namespace: &str, key: &str, value: Value<'_>)
+        -> zbus::Result<()>;
+    }
+}
+
+const APPEARANCE_NAMESPACE: &str = "org.freedesktop.appearance";
+const COLOR_SCHEME_KEY: &str = "color-scheme";
+
+/// The system-wide light/dark preference, as reported by xdg-desktop-portal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorScheme {
+    #[default]
+    NoPreference,
+    Dark,
+    Light,
+}
+impl ColorScheme {
+    fn from_portal(value: u32) -> Self {
+        // https://flatpak.github.io/xdg-desktop-portal/docs/doc-org.freedesktop.portal.Settings.html
+        match value {
+            1 => Self::Dark,
+            2 => Self::Light,
+            _ => Self::NoPreference,
+        }
+    }
+    /// Whether a dark theme should be used. No preference is treated as dark.
+    pub fn is_dark(self) -> bool {
+        self != Self::Light
+    }
+}
+
+pub struct PortalClient {
+    pub color_scheme_rx: watch::Receiver<ColorScheme>,
+    _background: AbortOnDropHandle<()>,
+}
+
+async fn watch_color_scheme(color_scheme_tx: &watch::Sender<ColorScheme>) -> anyhow::Result<()> {
+    let connection = zbus::Connection::session().await?;
+    let proxy = dbus::SettingsProxy::new(&connection).await?;
+
+    // Subscribe before reading so that changes in between are not lost
+    let mut changes = proxy
+        .receive_setting_changed_with_args(&[(0, APPEARANCE_NAMESPACE), (1, COLOR_SCHEME_KEY)])
+        .await?;
+
+    let init = proxy
+        .read_one(APPEARANCE_NAMESPACE, COLOR_SCHEME_KEY)
+        .await
+        .context("Failed to read color scheme")?;
+    color_scheme_tx.send_replace(ColorScheme::from_portal(u32::try_from(init)?));
+
+    while let Some(change) = changes.next().await {
+        let args = change.args()?;
+        let value = args
+            .value()
+            .downcast_ref::<u32>()
+            .context("Invalid color scheme value")?;
+        color_scheme_tx.send_if_modified(|cur| {
+            let new = ColorScheme::from_portal(value);
+            std::mem::replace(cur, new) != new
+        });
+    }
+
+    anyhow::bail!("Portal setting stream ended")
+}
+
+async fn run_bg(color_scheme_tx: watch::Sender<ColorScheme>, mut reload_rx: ReloadRx) {
+    run_or_retry(
+        async |_: &mut ()| watch_color_scheme(&color_scheme_tx).await,
+        (),
+        |res| res.context("Portal settings client failed"),
+        Duration::from_secs(30),
+        Some(&mut reload_rx),
+    )
+    .await
+}
+
+pub fn connect(reload_rx: ReloadRx) -> PortalClient {
+    let (color_scheme_tx, color_scheme_rx) = watch::channel(ColorScheme::default());
+    PortalClient {
+        _background: AbortOnDropHandle::new(tokio::spawn(run_bg(color_scheme_tx, reload_rx))),
+        color_scheme_rx,
+    }
+}
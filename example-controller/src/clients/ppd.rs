@@ -44,16 +44,25 @@ mod dbus {
 pub struct PpdClient {
     pub profile_rx: watch::Receiver<Option<Arc<str>>>,
     cycle: Arc<Semaphore>,
+    /// The number of requested cycles that were not applied yet.
+    pending_tx: Arc<watch::Sender<usize>>,
     _background: AbortOnDropHandle<()>,
 }
 impl PpdClient {
     pub fn cycle_profile(&self) {
+        self.pending_tx.send_modify(|it| *it += 1);
         self.cycle.add_permits(1);
     }
+    /// Waits until the profile changes that were requested so far are applied.
+    pub async fn settled(&self) {
+        let mut pending_rx = self.pending_tx.subscribe();
+        pending_rx.wait_for(|&it| it == 0).await.ok_or_debug();
+    }
 }
 
 async fn run_bg(
     cycle_rx: Arc<Semaphore>,
+    pending_tx: Arc<watch::Sender<usize>>,
     profile_tx: watch::Sender<Option<Arc<str>>>,
     mut reload_rx: ReloadRx,
 ) {
@@ -91,30 +100,9 @@ async fn run_bg(
                 steps += 1;
             }
 
-            let Some((profiles, cur)) = tokio::try_join!(proxy.profiles(), proxy.active_profile())
-                .context("Failed to get ppd profiles")
-                .ok_or_log()
-            else {
-                continue;
-            };
-
-            if profiles.is_empty() {
-                log::error!("No ppd profiles found");
-                continue;
-            }
-
-            let idx = profiles
-                .iter()
-                .position(|p| p.profile.as_str() == &cur as &str)
-                .map_or(0, |i| i + steps)
-                % profiles.len();
-            let profile = profiles.into_iter().nth(idx).expect("Index < Length");
-
-            proxy
-                .set_active_profile(profile.profile)
-                .await
-                .context("Failed to set ppd profile")
-                .ok_or_log();
+            cycle(&proxy, steps).await;
+            // Failed cycles count as applied as well
+            pending_tx.send_modify(|it| *it = it.saturating_sub(steps));
         }
     };
 
@@ -124,16 +112,46 @@ async fn run_bg(
     }
 }
 
+async fn cycle(proxy: &dbus::PpdProxy<'_>, steps: usize) {
+    let Some((profiles, cur)) = tokio::try_join!(proxy.profiles(), proxy.active_profile())
+        .context("Failed to get ppd profiles")
+        .ok_or_log()
+    else {
+        return;
+    };
+
+    if profiles.is_empty() {
+        log::error!("No ppd profiles found");
+        return;
+    }
+
+    let idx = profiles
+        .iter()
+        .position(|p| p.profile.as_str() == &cur as &str)
+        .map_or(0, |i| i + steps)
+        % profiles.len();
+    let profile = profiles.into_iter().nth(idx).expect("Index < Length");
+
+    proxy
+        .set_active_profile(profile.profile)
+        .await
+        .context("Failed to set ppd profile")
+        .ok_or_log();
+}
+
 pub fn connect(reload_rx: ReloadRx) -> PpdClient {
     let cycle = Arc::new(Semaphore::new(0));
+    let pending_tx = Arc::new(watch::Sender::new(0));
     let (profile_tx, profile_rx) = watch::channel(Default::default());
     PpdClient {
         _background: AbortOnDropHandle::new(tokio::spawn(run_bg(
             cycle.clone(),
+            pending_tx.clone(),
             profile_tx,
             reload_rx,
         ))),
         cycle,
+        pending_tx,
         profile_rx,
     }
 }
@@ -0,0 +1,269 @@
+//! A menu that shows the output of a long-running command, e.g. `journalctl -f`.
+
+use std::{collections::VecDeque, ffi::OsString, process::Stdio};
+
+use crate::{
+    control::{ModuleControlTx, interact_callback_with, mk_fresh_interact_tag},
+    utils::ResultExt as _,
+    xtui::{self, text},
+};
+use anyhow::Context as _;
+use scratchbar::tui;
+use tokio::{
+    io::{AsyncBufReadExt as _, BufReader, Lines},
+    process::{Child, ChildStdout},
+    sync::{mpsc, watch},
+};
+use tokio_util::task::AbortOnDropHandle;
+
+#[derive(Debug, Clone)]
+pub struct CommandOutputOpts {
+    /// The number of lines shown at once.
+    pub lines: u16,
+    /// The width of the output in cells. Longer lines are cut off.
+    pub width: u16,
+    /// The number of lines kept for scrolling back.
+    pub history: usize,
+}
+
+#[derive(Debug)]
+enum Control {
+    StartOrKill,
+    ToggleFollow,
+    Scroll(tui::Direction),
+}
+
+struct Controls {
+    start_kill: tui::CustomId,
+    follow: tui::CustomId,
+    output: tui::CustomId,
+}
+
+/// The state of the output view.
+struct View {
+    lines: VecDeque<String>,
+    /// The number of lines between the last shown line and the newest one.
+    scroll: usize,
+    /// Whether to keep showing the newest lines.
+    follow: bool,
+    running: bool,
+    /// Why the command is not running.
+    status: Option<String>,
+}
+
+const SCROLL_STEP: usize = 3;
+
+/// Creates a menu that runs `cmd` when its start button is clicked and shows its output, with
+/// buttons to kill the command and to toggle following new output. Scrolling the output
+/// scrolls back through the last [`CommandOutputOpts::history`] lines.
+///
+/// The command is killed when the returned handle is dropped.
+pub fn command_output_menu(
+    cmd: OsString,
+    args: Vec<OsString>,
+    opts: CommandOutputOpts,
+    ctrl_tx: &ModuleControlTx,
+) -> (watch::Receiver<tui::Elem>, AbortOnDropHandle<()>) {
+    let (control_tx, control_rx) = mpsc::unbounded_channel();
+    let controls = Controls {
+        start_kill: mk_fresh_interact_tag(),
+        follow: mk_fresh_interact_tag(),
+        output: mk_fresh_interact_tag(),
+    };
+    let register = |tag: &tui::CustomId, to_control: fn(tui::InteractKind) -> Option<Control>| {
+        ctrl_tx.register_callback(
+            tag.clone(),
+            interact_callback_with(control_tx.clone(), move |control_tx, interact| {
+                if let Some(control) = to_control(interact.kind) {
+                    control_tx.send(control).ok_or_debug();
+                }
+            }),
+        );
+    };
+    const LEFT_CLICK: tui::InteractKind = tui::InteractKind::Click(tui::MouseButton::Left);
+    register(&controls.start_kill, |kind| {
+        (kind == LEFT_CLICK).then_some(Control::StartOrKill)
+    });
+    register(&controls.follow, |kind| {
+        (kind == LEFT_CLICK).then_some(Control::ToggleFollow)
+    });
+    register(&controls.output, |kind| match kind {
+        tui::InteractKind::Scroll(direction) => Some(Control::Scroll(direction)),
+        _ => None,
+    });
+
+    let view = View {
+        lines: VecDeque::new(),
+        scroll: 0,
+        follow: true,
+        running: false,
+        status: None,
+    };
+    let (tui_tx, tui_rx) = watch::channel(render(&view, &controls, &opts));
+    let task = AbortOnDropHandle::new(tokio::spawn(run_command_output(
+        cmd, args, opts, controls, view, control_rx, tui_tx,
+    )));
+    (tui_rx, task)
+}
+
+fn spawn(
+    cmd: &OsString,
+    args: &[OsString],
+) -> anyhow::Result<(Child, Lines<BufReader<ChildStdout>>)> {
+    let mut child = tokio::process::Command::new(cmd)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("Failed to run {cmd:?}"))?;
+    let stdout = child.stdout.take().context("Missing stdout")?;
+    Ok((child, BufReader::new(stdout).lines()))
+}
+
+async fn run_command_output(
+    cmd: OsString,
+    args: Vec<OsString>,
+    opts: CommandOutputOpts,
+    controls: Controls,
+    mut view: View,
+    mut control_rx: mpsc::UnboundedReceiver<Control>,
+    tui_tx: watch::Sender<tui::Elem>,
+) {
+    let mut child = None::<(Child, Lines<BufReader<ChildStdout>>)>;
+    loop {
+        tokio::select! {
+            control = control_rx.recv() => {
+                let Some(control) = control else {
+                    break;
+                };
+                match control {
+                    Control::StartOrKill if child.is_some() => {
+                        if let Some((mut proc, _)) = child.take() {
+                            proc.kill().await.context("Failed to kill command").ok_or_log();
+                        }
+                        view.running = false;
+                        view.status = Some("Killed".into());
+                    }
+                    Control::StartOrKill => {
+                        match spawn(&cmd, &args) {
+                            Ok(spawned) => {
+                                child = Some(spawned);
+                                view.running = true;
+                                view.status = None;
+                            }
+                            Err(err) => {
+                                view.status = Some(format!("{err:#}"));
+                                log::error!("{err:?}");
+                            }
+                        }
+                    }
+                    Control::ToggleFollow => {
+                        view.follow = !view.follow;
+                        if view.follow {
+                            view.scroll = 0;
+                        }
+                    }
+                    Control::Scroll(tui::Direction::Up) => {
+                        let max = view.lines.len().saturating_sub(opts.lines.into());
+                        view.scroll = (view.scroll + SCROLL_STEP).min(max);
+                        view.follow = false;
+                    }
+                    Control::Scroll(tui::Direction::Down) => {
+                        view.scroll = view.scroll.saturating_sub(SCROLL_STEP);
+                    }
+                    Control::Scroll(_) => continue,
+                }
+            }
+            Some(line) = async { Some(child.as_mut()?.1.next_line().await) } => {
+                match line {
+                    Ok(Some(line)) => {
+                        view.lines.push_back(line);
+                        if view.lines.len() > opts.history {
+                            view.lines.pop_front();
+                        }
+                        if !view.follow {
+                            // Keep showing the same lines
+                            let max = view.lines.len().saturating_sub(opts.lines.into());
+                            view.scroll = (view.scroll + 1).min(max);
+                        }
+                    }
+                    Ok(None) | Err(_) => {
+                        if let Some((mut proc, _)) = child.take() {
+                            view.status = Some(match proc.wait().await {
+                                Ok(status) => format!("Exited ({status})"),
+                                Err(err) => format!("Failed to wait for command: {err}"),
+                            });
+                        }
+                        view.running = false;
+                    }
+                }
+            }
+        }
+        tui_tx.send_replace(render(&view, &controls, &opts));
+    }
+}
+
+fn truncate(line: &str, width: usize) -> &str {
+    let mut used = 0;
+    let mut end = 0;
+    for grapheme in text::graphemes(line) {
+        used += text::width(grapheme);
+        if used > width {
+            break;
+        }
+        end += grapheme.len();
+    }
+    &line[..end]
+}
+
+fn render(view: &View, controls: &Controls, opts: &CommandOutputOpts) -> tui::Elem {
+    let button_opts = text::TextOpts::default();
+
+    let mut header = xtui::StackBuilder::new(tui::Axis::X);
+    header.push(
+        button_opts
+            .render_line(if view.running {
+                "[ Kill ]"
+            } else {
+                "[ Start ]"
+            })
+            .interactive(controls.start_kill.clone()),
+    );
+    header.spacing(2);
+    header.push(
+        button_opts
+            .render_line(if view.follow {
+                "[x] Follow"
+            } else {
+                "[ ] Follow"
+            })
+            .interactive(controls.follow.clone()),
+    );
+    if let Some(status) = &view.status {
+        header.spacing(2);
+        header.push(text::TextOpts::default().render_line(truncate(status, opts.width.into())));
+    }
+
+    let height = usize::from(opts.lines);
+    let end = view.lines.len().saturating_sub(view.scroll);
+    let start = end.saturating_sub(height);
+    let output = text::TextOpts::default()
+        .render_lines(
+            view.lines
+                .range(start..end)
+                .map(|line| truncate(line, opts.width.into())),
+        )
+        .with_min_size(tui::Size {
+            width: opts.width,
+            height: opts.lines,
+        })
+        .interactive(controls.output.clone());
+
+    let mut stack = xtui::StackBuilder::new(tui::Axis::Y);
+    stack.push(header.build());
+    stack.spacing(1);
+    stack.push(output);
+    stack.build()
+}
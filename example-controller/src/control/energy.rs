@@ -3,13 +3,19 @@ use std::sync::Arc;
 use crate::{
     clients,
     control::{
-        BarTuiElem, MenuKind, ModuleArgs, RegisterMenu, interact_callback_with,
+        BarTuiElem, MenuKind, ModuleArgs, RegisterMenu, cmd_output, interact_callback_with,
         mk_fresh_interact_tag,
     },
     utils::ResultExt as _,
     xtui::text,
 };
-use scratchbar::tui;
+use scratchbar::{
+    modules,
+    tui::{
+        self,
+        symbols::{self, Symbol},
+    },
+};
 use tokio::sync::watch;
 
 pub async fn ppd_module(
@@ -19,7 +25,7 @@ pub async fn ppd_module(
         ctrl_tx,
         ..
     }: ModuleArgs,
-) {
+) -> modules::ModuleExit {
     let ppd = Arc::new(clients::ppd::connect(reload_rx));
 
     let interact_tag = mk_fresh_interact_tag();
@@ -31,6 +37,31 @@ pub async fn ppd_module(
         ppd.cycle_profile();
     });
     ctrl_tx.register_callback(interact_tag.clone(), on_interact);
+    // A click right before the controller exits would otherwise be lost
+    ctrl_tx.on_shutdown({
+        let ppd = ppd.clone();
+        async move { ppd.settled().await }
+    });
+
+    let (logs_rx, _logs) = cmd_output::command_output_menu(
+        "journalctl".into(),
+        ["--follow", "--lines=100", "--unit=power-profiles-daemon"]
+            .map(std::ffi::OsString::from)
+            .into(),
+        cmd_output::CommandOutputOpts {
+            lines: 20,
+            width: 100,
+            history: 1000,
+        },
+        &ctrl_tx,
+    );
+    ctrl_tx.register_menu(RegisterMenu {
+        on_tag: interact_tag.clone(),
+        on_kind: tui::InteractKind::Click(tui::MouseButton::Right),
+        tui_rx: logs_rx,
+        menu_kind: MenuKind::Context,
+        opts: Default::default(),
+    });
 
     let mut profile_rx = ppd.profile_rx.clone();
     while let Some(()) = profile_rx.changed().await.ok_or_debug() {
@@ -44,24 +75,23 @@ pub async fn ppd_module(
             opts: Default::default(),
         });
 
-        let icon = text::TextOpts::from(text::HorizontalAlign::Center).render_cell(
-            match profile.as_deref() {
-                Some("balanced") => "",
-                Some("performance") => "",
-                Some("power-saver") => "",
-                _ => {
-                    tui_tx.send_if_modified(|tui| {
-                        let old = std::mem::replace(tui, BarTuiElem::Hide);
-                        !matches!(old, BarTuiElem::Hide)
-                    });
-                    continue;
-                }
-            },
-            2.try_into().unwrap(),
-        );
+        let icon = Symbol::nerd(match profile.as_deref() {
+            Some("balanced") => symbols::BALANCE_SCALE,
+            Some("performance") => symbols::BOLT,
+            Some("power-saver") => symbols::LEAF,
+            _ => {
+                tui_tx.send_if_modified(|tui| {
+                    let old = std::mem::replace(tui, BarTuiElem::Hide);
+                    !matches!(old, BarTuiElem::Hide)
+                });
+                continue;
+            }
+        })
+        .render();
 
         tui_tx.send_replace(BarTuiElem::Shared(icon.interactive(interact_tag.clone())));
     }
+    modules::ModuleExit::Failed
 }
 
 pub async fn energy_module(
@@ -71,7 +101,7 @@ pub async fn energy_module(
         ctrl_tx,
         ..
     }: ModuleArgs,
-) {
+) -> modules::ModuleExit {
     use crate::clients::upower::*;
     let energy = Arc::new(clients::upower::connect(reload_rx));
 
@@ -138,4 +168,5 @@ pub async fn energy_module(
             }
         }
     }
+    modules::ModuleExit::Failed
 }
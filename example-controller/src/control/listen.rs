@@ -0,0 +1,103 @@
+//! A module that shows the output of a script that prints a line whenever its content changes,
+//! like the scripts that eww's `deflisten` and ags' `Variable.listen` run. This makes it
+//! possible to reuse such scripts as content sources for the bar.
+//!
+//! Each line replaces the content of the module. Lines may contain SGR escape sequences for
+//! colors and text attributes. An empty line hides the module.
+
+use std::{ffi::OsString, process::Stdio};
+
+use anyhow::Context as _;
+use scratchbar::{modules, tui};
+use tokio::io::AsyncBufReadExt as _;
+
+use crate::{
+    control::{BarTuiElem, ModuleArgs},
+    utils::ResultExt as _,
+    xtui::text,
+};
+
+/// The environment variable with a shell command that is run by [`listen_module`], if set.
+pub const LISTEN_CMD_VAR: &str = "SCRATCHBAR_LISTEN_CMD";
+
+#[derive(Debug, Clone)]
+pub struct ListenModuleArgs {
+    /// A shell command, run using `sh -c`.
+    pub cmd: OsString,
+}
+impl ListenModuleArgs {
+    pub fn from_env() -> Option<Self> {
+        let cmd = std::env::var_os(LISTEN_CMD_VAR).filter(|it| !it.is_empty())?;
+        Some(Self { cmd })
+    }
+}
+
+fn line_to_tui(line: &str) -> BarTuiElem {
+    if line.trim().is_empty() {
+        return BarTuiElem::Hide;
+    }
+    match tui::Elem::from_ansi(line) {
+        Ok(elem) => elem.into(),
+        Err(err) => {
+            log::warn!("Failed to parse line {line:?}: {err}. Showing it without escapes");
+            let plain: String = line.chars().filter(|c| !c.is_control()).collect();
+            text::TextOpts::default().render_line(&plain).into()
+        }
+    }
+}
+
+/// Runs the command and shows its output until it exits.
+pub async fn listen_module(
+    ListenModuleArgs { cmd }: ListenModuleArgs,
+    ModuleArgs {
+        tui_tx, ctrl_tx, ..
+    }: ModuleArgs,
+) -> modules::ModuleExit {
+    let Some(mut child) = tokio::process::Command::new("sh")
+        .arg("-c")
+        .arg(&cmd)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .process_group(0)
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("Failed to run {cmd:?}"))
+        .ok_or_log()
+    else {
+        return modules::ModuleExit::Failed;
+    };
+    if let Some(pid) = child.id() {
+        // Dropping the child only kills the shell, so let the commands that it started, e.g.
+        // the parts of a pipeline, clean up as well
+        ctrl_tx.on_shutdown(async move {
+            tokio::process::Command::new("kill")
+                .args(["-TERM", "--", &format!("-{pid}")])
+                .status()
+                .await
+                .context("Failed to stop the listen command")
+                .ok_or_log();
+        });
+    }
+    let Some(stdout) = child.stdout.take() else {
+        return modules::ModuleExit::Failed;
+    };
+    let mut lines = tokio::io::BufReader::new(stdout).lines();
+    loop {
+        match lines.next_line().await {
+            Ok(Some(line)) => {
+                tui_tx.send_replace(line_to_tui(&line));
+            }
+            Ok(None) => break,
+            Err(err) => {
+                log::error!("Failed to read the output of {cmd:?}: {err}");
+                break;
+            }
+        }
+    }
+    // The module is restarted after a delay
+    match child.wait().await {
+        Ok(status) => log::warn!("{cmd:?} exited with {status}"),
+        Err(err) => log::error!("Failed to wait for {cmd:?}: {err}"),
+    }
+    modules::ModuleExit::Failed
+}
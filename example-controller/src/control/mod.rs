@@ -1,17 +1,26 @@
+mod cmd_output;
 mod energy;
-mod hypr;
-mod pulse;
-mod time;
+mod listen;
+mod scratch;
+mod stats;
 mod tray;
+mod workspaces;
 
 use std::{collections::HashMap, sync::Arc};
 
 use crate::{
     utils::{ReloadRx, ReloadTx, ResultExt as _},
-    xtui::{self, text},
+    xtui,
+};
+use scratchbar::{
+    host, modules,
+    tui::{
+        self,
+        symbols::{self, Symbol},
+    },
 };
-use scratchbar::{host, tui};
 use tokio::{sync::watch, task::JoinSet};
+use tokio_util::time::FutureExt as _;
 
 use crate::clients;
 
@@ -22,6 +31,8 @@ enum BarTuiElem {
     Hide,
     FillSpace(u16),
     Spacing(u16),
+    /// Only shown on monitors with the given orientation. See [`only_on`].
+    Only(host::Orientation, Box<BarTuiElem>),
 }
 impl From<tui::Elem> for BarTuiElem {
     fn from(value: tui::Elem) -> Self {
@@ -35,10 +46,8 @@ struct InteractTagRegistry<K, V> {
 }
 
 fn mk_fresh_interact_tag() -> tui::CustomId {
-    use std::sync::atomic::*;
-
-    static TAG_COUNTER: AtomicU64 = AtomicU64::new(0);
-    tui::CustomId::from_bytes(&TAG_COUNTER.fetch_add(1, Ordering::Relaxed).to_be_bytes())
+    // Shared with the modules from the library, so that the tags never collide
+    modules::fresh_tag()
 }
 
 impl<K: std::hash::Hash + std::cmp::Eq + Clone, V> InteractTagRegistry<K, V> {
@@ -85,16 +94,44 @@ type BarMenus = HashMap<tui::CustomId, HashMap<tui::InteractKind, BarMenu>>;
 #[derive(Default)]
 struct Callbacks {
     cbs: HashMap<tui::CustomId, InteractCallback>,
+    /// By the first argument of [`host::HostEvent::Command`].
+    cmds: HashMap<Arc<str>, modules::CommandCallback>,
 }
 impl std::fmt::Debug for Callbacks {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        std::fmt::Debug::fmt(&self.cbs.keys(), f)
+        f.debug_struct("Callbacks")
+            .field("cbs", &self.cbs.keys())
+            .field("cmds", &self.cmds.keys())
+            .finish()
+    }
+}
+/// Replies to [`modules::ModuleRequest::RunCommand`] by the id of the command.
+type PendingCommands =
+    Arc<std::sync::Mutex<HashMap<Arc<str>, tokio::sync::oneshot::Sender<host::CommandOutput>>>>;
+type ShutdownHook = std::pin::Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+#[derive(Default)]
+struct ShutdownHooks {
+    /// The hooks with the [`ModuleControlTx::instance`] that registered them.
+    hooks: Vec<(u64, ShutdownHook)>,
+}
+impl std::fmt::Debug for ShutdownHooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ShutdownHooks")
+            .field("count", &self.hooks.len())
+            .finish()
     }
 }
+
 #[derive(Debug, Clone)]
 struct ModuleControlTx {
     tag_cb_tx: watch::Sender<Callbacks>,
     bar_menus_tx: watch::Sender<BarMenus>,
+    shutdown_hooks: Arc<std::sync::Mutex<ShutdownHooks>>,
+    update_tx: host::HostUpdateSender,
+    pending_cmds: PendingCommands,
+    /// The module instance that this is passed to, see [`Self::for_instance`]. Zero outside of
+    /// modules.
+    instance: u64,
 }
 struct RegisterMenu {
     pub on_tag: tui::CustomId,
@@ -133,77 +170,700 @@ impl ModuleControlTx {
             cbs.cbs.insert(tag, cb);
         })
     }
+    fn register_command(&self, name: Arc<str>, cb: modules::CommandCallback) {
+        self.tag_cb_tx.send_modify(|cbs| {
+            cbs.cmds.insert(name, cb);
+        })
+    }
+    /// Calls the command callback named by the first argument with the remaining ones.
+    fn run_command(&self, args: &[String]) {
+        let Some((name, args)) = args.split_first() else {
+            return;
+        };
+        let callback = self.tag_cb_tx.borrow().cmds.get(name.as_str()).cloned();
+        match callback {
+            Some(cb) => cb(args),
+            None => log::warn!("No module handles the command {name:?}"),
+        }
+    }
+    /// Runs the command in the host and sends its output to `reply_tx` once it finishes.
+    fn spawn_host_command(
+        &self,
+        cmd: Vec<String>,
+        opts: host::RunCommandOpts,
+        reply_tx: tokio::sync::oneshot::Sender<host::CommandOutput>,
+    ) {
+        use std::sync::atomic::{AtomicU64, Ordering};
+
+        static COMMAND_COUNTER: AtomicU64 = AtomicU64::new(0);
+        let id: Arc<str> =
+            format!("module-{}", COMMAND_COUNTER.fetch_add(1, Ordering::Relaxed)).into();
+        self.pending_cmds
+            .lock()
+            .unwrap_or_else(|poison| poison.into_inner())
+            .insert(id.clone(), reply_tx);
+        self.update_tx
+            .send(host::HostUpdate::RunCommand(host::RunCommand {
+                id,
+                cmd,
+                opts,
+            }))
+            .ok_or_debug();
+    }
+    /// Runs `hook` when the controller shuts down, before the module tasks are aborted. The
+    /// hook is dropped if the module instance that registered it exits before that.
+    ///
+    /// All hooks run concurrently and are cancelled after [`SHUTDOWN_DEADLINE`].
+    fn on_shutdown(&self, hook: impl Future<Output = ()> + Send + 'static) {
+        self.lock_shutdown_hooks()
+            .hooks
+            .push((self.instance, Box::pin(hook)));
+    }
+    fn lock_shutdown_hooks(&self) -> std::sync::MutexGuard<'_, ShutdownHooks> {
+        self.shutdown_hooks
+            .lock()
+            .unwrap_or_else(|poison| poison.into_inner())
+    }
+    /// A sender for a new module instance, whose shutdown hooks can be dropped with
+    /// [`Self::drop_shutdown_hooks`] once it exits.
+    fn for_instance(&self) -> Self {
+        use std::sync::atomic::{AtomicU64, Ordering};
+
+        static INSTANCE_COUNTER: AtomicU64 = AtomicU64::new(1);
+        Self {
+            instance: INSTANCE_COUNTER.fetch_add(1, Ordering::Relaxed),
+            ..self.clone()
+        }
+    }
+    fn drop_shutdown_hooks(&self) {
+        self.lock_shutdown_hooks()
+            .hooks
+            .retain(|&(instance, _)| instance != self.instance);
+    }
 }
 
+/// How long the menus and callbacks of a tag are kept after it was last seen, unless overridden
+/// by [`TAG_EXPIRY_VAR`]. See [`run_tag_gc`].
+const TAG_EXPIRY: std::time::Duration = std::time::Duration::from_secs(600);
+/// The environment variable that overrides [`TAG_EXPIRY`], in seconds.
+pub const TAG_EXPIRY_VAR: &str = "SCRATCHBAR_TAG_EXPIRY_SECS";
+const TAG_GC_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// How the modules that hide themselves make room, see [`collapse_hidden`].
+const SEGMENT_COLLAPSE: tui::HiddenSpace = tui::HiddenSpace::Collapse {
+    axis: tui::Axis::X,
+    duration: std::time::Duration::from_millis(150),
+};
+
+pub const SHUTDOWN_DEADLINE: std::time::Duration = std::time::Duration::from_secs(3);
+
 struct ModuleArgs {
     tui_tx: watch::Sender<BarTuiElem>,
     reload_rx: ReloadRx,
     ctrl_tx: ModuleControlTx,
+    /// Modules that pick colors should read this on every reload, since a change of the system
+    /// preference triggers one.
+    color_scheme_rx: watch::Receiver<clients::portal::ColorScheme>,
     _unused: (),
 }
 
 struct BarModuleFactory {
     reload_tx: ReloadTx,
     ctrl_tx: ModuleControlTx,
+    color_scheme_rx: watch::Receiver<clients::portal::ColorScheme>,
+    health: ModuleHealths,
     tasks: JoinSet<()>,
 }
+
+#[derive(Debug)]
+struct ModuleHealth {
+    name: &'static str,
+    state: host::ModuleState,
+    last_update: Option<std::time::Instant>,
+    restarts: u32,
+    updates: u64,
+    throttled: u64,
+    last_error: Option<String>,
+}
+
+/// The health of all modules spawned by the [`BarModuleFactory`], for
+/// [`host::HostEvent::ModuleStatusRequested`].
+#[derive(Debug, Clone, Default)]
+struct ModuleHealths(Arc<std::sync::Mutex<Vec<ModuleHealth>>>);
+impl ModuleHealths {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<ModuleHealth>> {
+        self.0.lock().unwrap_or_else(|poison| poison.into_inner())
+    }
+    fn update(&self, idx: usize, f: impl FnOnce(&mut ModuleHealth)) {
+        if let Some(health) = self.lock().get_mut(idx) {
+            f(health)
+        }
+    }
+    fn status(&self) -> Vec<host::ModuleStatus> {
+        self.lock()
+            .iter()
+            .map(|health| host::ModuleStatus {
+                name: health.name.into(),
+                state: health.state,
+                ms_since_update: health
+                    .last_update
+                    .map(|it| it.elapsed().as_millis().try_into().unwrap_or(u64::MAX)),
+                restarts: health.restarts,
+                updates: health.updates,
+                throttled: health.throttled,
+                last_error: health.last_error.clone(),
+                ..Default::default()
+            })
+            .collect()
+    }
+}
+const MODULE_RESTART_DELAY: std::time::Duration = std::time::Duration::from_secs(5);
+/// The longest delay between restarts of a module that keeps failing.
+const MODULE_MAX_RESTART_DELAY: std::time::Duration = std::time::Duration::from_secs(300);
+/// How long a module instance has to run for the restart delay to be reset.
+const MODULE_HEALTHY_AFTER: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// How many updates of its content a module can send per second before they are throttled.
+const MODULE_UPDATE_RATE: f64 = 20.0;
+/// How many updates a module can send at once, e.g. when it starts.
+const MODULE_UPDATE_BURST: f64 = 20.0;
+
+/// A token bucket that limits how often the content of a module is forwarded, so that a
+/// runaway module cannot flood the connection to the host.
+struct UpdateBudget {
+    tokens: f64,
+    refilled: std::time::Instant,
+}
+impl UpdateBudget {
+    fn new() -> Self {
+        Self {
+            tokens: MODULE_UPDATE_BURST,
+            refilled: std::time::Instant::now(),
+        }
+    }
+
+    /// Uses up one update. If there is none left, returns how long to wait for the next one.
+    fn take(&mut self) -> Option<std::time::Duration> {
+        let now = std::time::Instant::now();
+        self.tokens = (self.tokens
+            + now.duration_since(self.refilled).as_secs_f64() * MODULE_UPDATE_RATE)
+            .min(MODULE_UPDATE_BURST);
+        self.refilled = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            None
+        } else {
+            Some(std::time::Duration::from_secs_f64(
+                (1.0 - self.tokens) / MODULE_UPDATE_RATE,
+            ))
+        }
+    }
+}
+
+/// A short name of the module function, for logs.
+fn module_name<T: ?Sized>(task: &T) -> &'static str {
+    let name = std::any::type_name_of_val(task);
+    name.rsplit("::")
+        .find(|it| !it.starts_with('{'))
+        .unwrap_or(name)
+}
+
+/// Replaces the output of a module that panicked with a badge that shows the panic message on
+/// hover.
+fn panic_badge(
+    name: &str,
+    msg: &str,
+    tag: &tui::CustomId,
+    ctrl_tx: &ModuleControlTx,
+) -> BarTuiElem {
+    ctrl_tx.register_menu(RegisterMenu {
+        on_tag: tag.clone(),
+        on_kind: tui::InteractKind::Hover,
+        tui_rx: watch::channel(
+            xtui::text::TextOpts::default().render(&format!("Module {name} panicked:\n{msg}")),
+        )
+        .1,
+        menu_kind: MenuKind::Tooltip,
+        opts: Default::default(),
+    });
+    xtui::text::TextOpts::default()
+        .with(|it| it.fg_color = xtui::text::Color::Red)
+        .render_line("!")
+        .interactive(tag.clone())
+        .into()
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("Unknown panic payload")
+}
+
 impl BarModuleFactory {
-    fn spawn<F: Future<Output = ()> + 'static + Send>(
+    /// Spawns a module, restarting it if it fails or panics.
+    fn spawn<F: Future<Output = modules::ModuleExit> + 'static + Send>(
         &mut self,
-        task: impl FnOnce(ModuleArgs) -> F,
+        task: impl Fn(ModuleArgs) -> F + Send + 'static,
+    ) -> watch::Receiver<BarTuiElem> {
+        let name = module_name(&task);
+        self.spawn_named(name, task)
+    }
+    fn spawn_named<F: Future<Output = modules::ModuleExit> + 'static + Send>(
+        &mut self,
+        name: &'static str,
+        task: impl Fn(ModuleArgs) -> F + Send + 'static,
     ) -> watch::Receiver<BarTuiElem> {
         let (tui_tx, tui_rx) = watch::channel(BarTuiElem::Hide);
-        self.tasks.spawn(task(ModuleArgs {
-            reload_rx: self.reload_tx.subscribe(),
-            ctrl_tx: self.ctrl_tx.clone(),
-            tui_tx,
-            _unused: (),
-        }));
-        tui_rx
-    }
-    fn spawn_with<F: Future<Output = ()> + 'static + Send, C>(
+        let reload_tx = self.reload_tx.clone();
+        let ctrl_tx = self.ctrl_tx.clone();
+        let color_scheme_rx = self.color_scheme_rx.clone();
+
+        let health = self.health.clone();
+        let idx = {
+            let mut healths = health.lock();
+            healths.push(ModuleHealth {
+                name,
+                state: host::ModuleState::Running,
+                last_update: None,
+                restarts: 0,
+                updates: 0,
+                throttled: 0,
+                last_error: None,
+            });
+            healths.len() - 1
+        };
+        // Forwards the content at the rate allowed by the module's budget. Updates that arrive
+        // while waiting replace the pending one.
+        let (limited_tx, limited_rx) = watch::channel(BarTuiElem::Hide);
+        {
+            let health = health.clone();
+            let mut tui_rx = tui_rx.clone();
+            self.tasks.spawn(async move {
+                let mut budget = UpdateBudget::new();
+                while let Ok(()) = tui_rx.changed().await {
+                    health.update(idx, |it| {
+                        // Ignore the panic badge
+                        if it.state == host::ModuleState::Running {
+                            it.last_update = Some(std::time::Instant::now());
+                        }
+                        it.updates += 1;
+                    });
+                    if let Some(mut wait) = budget.take() {
+                        health.update(idx, |it| it.throttled += 1);
+                        loop {
+                            tokio::time::sleep(wait).await;
+                            match budget.take() {
+                                Some(next) => wait = next,
+                                None => break,
+                            }
+                        }
+                    }
+                    limited_tx.send_replace(tui_rx.borrow_and_update().clone());
+                }
+            });
+        }
+
+        self.tasks.spawn(async move {
+            let badge_tag = mk_fresh_interact_tag();
+            let mut delay = MODULE_RESTART_DELAY;
+            loop {
+                let started = std::time::Instant::now();
+                // The channel outlives the module instance, so the last output of the module
+                // stays on the bar until the restarted instance replaces it.
+                //
+                // Each instance runs in its own task, so that a panic only takes down the
+                // instance. The handle aborts the instance when the factory shuts down.
+                let instance_ctrl_tx = ctrl_tx.for_instance();
+                let instance =
+                    tokio_util::task::AbortOnDropHandle::new(tokio::spawn(task(ModuleArgs {
+                        reload_rx: reload_tx.subscribe(),
+                        ctrl_tx: instance_ctrl_tx.clone(),
+                        tui_tx: tui_tx.clone(),
+                        color_scheme_rx: color_scheme_rx.clone(),
+                        _unused: (),
+                    })));
+                let res = instance.await;
+                // The hooks would act on the state of the exited instance
+                instance_ctrl_tx.drop_shutdown_hooks();
+                if started.elapsed() >= MODULE_HEALTHY_AFTER {
+                    delay = MODULE_RESTART_DELAY;
+                }
+                health.update(idx, |it| it.state = host::ModuleState::Restarting);
+                match res {
+                    Ok(modules::ModuleExit::Done) => {
+                        log::info!("Module {name} is done");
+                        health.update(idx, |it| it.state = host::ModuleState::Done);
+                        break;
+                    }
+                    Ok(_) => {
+                        log::warn!("Module {name} failed. Restarting in {}s", delay.as_secs())
+                    }
+                    Err(err) => {
+                        let Ok(payload) = err.try_into_panic() else {
+                            // Cancelled by the runtime shutting down
+                            break;
+                        };
+                        let msg = panic_message(&*payload);
+                        log::error!(
+                            "Module {name} panicked: {msg}. Restarting in {}s",
+                            delay.as_secs()
+                        );
+                        health.update(idx, |it| it.last_error = Some(msg.to_owned()));
+                        tui_tx.send_replace(panic_badge(name, msg, &badge_tag, &ctrl_tx));
+                    }
+                }
+                tokio::time::sleep(delay).await;
+                health.update(idx, |it| {
+                    it.state = host::ModuleState::Running;
+                    it.restarts += 1;
+                });
+                delay = (delay * 2).min(MODULE_MAX_RESTART_DELAY);
+            }
+        });
+        limited_rx
+    }
+    fn spawn_with<
+        F: Future<Output = modules::ModuleExit> + 'static + Send,
+        C: Clone + Send + 'static,
+    >(
         &mut self,
         ctx: C,
-        task: impl FnOnce(C, ModuleArgs) -> F,
+        task: impl Fn(C, ModuleArgs) -> F + Send + 'static,
+    ) -> watch::Receiver<BarTuiElem> {
+        let name = module_name(&task);
+        self.spawn_named(name, move |args| task(ctx.clone(), args))
+    }
+    /// Spawns one of the ready-made modules from [`modules`].
+    fn spawn_module<M: modules::Module>(
+        &mut self,
+        config: M::Config,
     ) -> watch::Receiver<BarTuiElem> {
-        self.spawn(|args| task(ctx, args))
+        let name = std::any::type_name::<M>();
+        let name = name.rsplit("::").next().unwrap_or(name);
+        self.spawn_named(name, move |args| run_module::<M>(config.clone(), args))
     }
     fn fixed(&mut self, elem: BarTuiElem) -> watch::Receiver<BarTuiElem> {
         let (_, rx) = watch::channel(elem);
         rx
     }
+    async fn shutdown(mut self) {
+        let ShutdownHooks { hooks } = std::mem::take(&mut *self.ctrl_tx.lock_shutdown_hooks());
+        if !hooks.is_empty() {
+            log::debug!("Running {} shutdown hooks", hooks.len());
+
+            let mut hook_tasks = JoinSet::new();
+            for (_, hook) in hooks {
+                hook_tasks.spawn(hook);
+            }
+            if hook_tasks
+                .join_all()
+                .timeout(SHUTDOWN_DEADLINE)
+                .await
+                .is_err()
+            {
+                log::warn!(
+                    "Shutdown hooks did not finish within {}s",
+                    SHUTDOWN_DEADLINE.as_secs()
+                );
+            }
+        }
+        self.tasks.shutdown().await;
+    }
 }
 
-fn send_bar_tui(bar_tui: &[BarTuiElem], ctrl_tx: &host::HostUpdateSender) {
-    let mut by_monitor = HashMap::new();
-    let mut fallback = xtui::StackBuilder::new(tui::Axis::X);
-    for elem in bar_tui {
-        match elem {
-            BarTuiElem::Shared(elem) => {
-                for stack in by_monitor.values_mut().chain(Some(&mut fallback)) {
-                    stack.push(elem.clone());
+/// Runs a module from [`modules`], forwarding its requests to the controller.
+async fn run_module<M: modules::Module>(
+    config: M::Config,
+    ModuleArgs {
+        tui_tx,
+        mut reload_rx,
+        ctrl_tx,
+        ..
+    }: ModuleArgs,
+) -> modules::ModuleExit {
+    let (bar_tx, mut bar_rx) = watch::channel(modules::BarContent::default());
+    let (module_reload_tx, module_reload_rx) = watch::channel(());
+    let (cx, mut req_rx) = modules::ModuleCx::new(bar_tx, module_reload_rx);
+
+    let forward = async move {
+        loop {
+            tokio::select! {
+                Ok(()) = bar_rx.changed() => {
+                    let content = match bar_rx.borrow_and_update().clone() {
+                        modules::BarContent::Shared(elem) => BarTuiElem::Shared(elem),
+                        modules::BarContent::ByMonitor(elems) => BarTuiElem::ByMonitor(elems),
+                        content => {
+                            log::warn!("Unsupported module content: {content:?}");
+                            continue;
+                        }
+                    };
+                    tui_tx.send_replace(content);
                 }
-            }
-            BarTuiElem::ByMonitor(elems) => {
-                for (mtr, elem) in elems {
-                    by_monitor
-                        .entry(mtr.clone())
-                        .or_insert_with(|| fallback.clone())
-                        .push(elem.clone());
+                Some(req) = req_rx.recv() => match req {
+                    modules::ModuleRequest::RegisterMenu(modules::RegisterMenu {
+                        on_tag,
+                        on_kind,
+                        tui_rx,
+                        kind,
+                    }) => ctrl_tx.register_menu(RegisterMenu {
+                        on_tag,
+                        on_kind,
+                        tui_rx,
+                        menu_kind: match kind {
+                            modules::MenuKind::Tooltip => MenuKind::Tooltip,
+                            _ => MenuKind::Context,
+                        },
+                        opts: Default::default(),
+                    }),
+                    modules::ModuleRequest::OnInteract(tag, cb) => {
+                        ctrl_tx.register_callback(tag, Arc::new(move |args: InteractArgs| cb(&args.kind)));
+                    }
+                    modules::ModuleRequest::OnCommand(name, cb) => {
+                        ctrl_tx.register_command(name, cb);
+                    }
+                    modules::ModuleRequest::EmitSignal(args) => ctrl_tx.run_command(&args),
+                    modules::ModuleRequest::RunCommand(cmd, opts, reply_tx) => {
+                        ctrl_tx.spawn_host_command(cmd, opts, reply_tx);
+                    }
+                    req => log::warn!("Unsupported module request: {req:?}"),
+                },
+                Some(()) = reload_rx.wait() => {
+                    module_reload_tx.send_replace(());
                 }
+                else => break,
             }
-            BarTuiElem::Hide => {}
-            BarTuiElem::FillSpace(weight) => {
-                for stack in by_monitor.values_mut().chain(Some(&mut fallback)) {
-                    stack.fill(*weight, tui::Elem::empty());
-                }
+        }
+    };
+    tokio::select! {
+        exit = M::run(config, cx) => exit,
+        () = forward => modules::ModuleExit::Failed,
+    }
+}
+
+/// The orientation of each monitor, from [`host::HostEvent::Monitors`].
+type Orientations = HashMap<Arc<str>, host::Orientation>;
+
+/// Wraps the output of a module so that it is only shown on monitors with the given
+/// orientation, e.g. to leave out less important modules on narrow portrait monitors.
+fn only_on(
+    orientation: host::Orientation,
+    mut module: watch::Receiver<BarTuiElem>,
+) -> watch::Receiver<BarTuiElem> {
+    let wrap = move |elem| BarTuiElem::Only(orientation, Box::new(elem));
+    let (tx, rx) = watch::channel(wrap(module.borrow_and_update().clone()));
+    tokio::spawn(async move {
+        while let Ok(()) = module.changed().await {
+            if tx.send(wrap(module.borrow_and_update().clone())).is_err() {
+                break;
             }
-            BarTuiElem::Spacing(len) => {
-                for stack in by_monitor.values_mut().chain(Some(&mut fallback)) {
-                    stack.spacing(*len);
+        }
+    });
+    rx
+}
+
+/// Makes a module that hides itself collapse instead of making its neighbors jump, see
+/// [`tui::Elem::collapsible`]. Hiding keeps the last content, so that the host can measure it.
+fn collapse_hidden(
+    space: tui::HiddenSpace,
+    mut module: watch::Receiver<BarTuiElem>,
+) -> watch::Receiver<BarTuiElem> {
+    let key = mk_fresh_interact_tag();
+    let wrap = move |content: &BarTuiElem, shown: bool| {
+        let wrap_elem = |elem: &tui::Elem| elem.clone().collapsible(key.clone(), shown, space);
+        match content {
+            BarTuiElem::Shared(elem) => BarTuiElem::Shared(wrap_elem(elem)),
+            BarTuiElem::ByMonitor(elems) => BarTuiElem::ByMonitor(
+                elems
+                    .iter()
+                    .map(|(monitor, elem)| (monitor.clone(), wrap_elem(elem)))
+                    .collect(),
+            ),
+            other => other.clone(),
+        }
+    };
+    let mut last_shown = None;
+    let mut convert = move |elem: BarTuiElem| match elem {
+        BarTuiElem::Hide => last_shown
+            .as_ref()
+            .map_or(BarTuiElem::Hide, |it| wrap(it, false)),
+        elem @ (BarTuiElem::Shared(_) | BarTuiElem::ByMonitor(_)) => {
+            let shown = wrap(&elem, true);
+            last_shown = Some(elem);
+            shown
+        }
+        elem => elem,
+    };
+    let (tx, rx) = watch::channel(convert(module.borrow_and_update().clone()));
+    tokio::spawn(async move {
+        while let Ok(()) = module.changed().await {
+            if tx
+                .send(convert(module.borrow_and_update().clone()))
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+fn bar_elem_tags(elem: &BarTuiElem, f: &mut impl FnMut(&tui::CustomId)) {
+    match elem {
+        BarTuiElem::Shared(elem) => elem.for_each_tag(f),
+        BarTuiElem::ByMonitor(elems) => {
+            for elem in elems.values() {
+                elem.for_each_tag(f);
+            }
+        }
+        BarTuiElem::Only(_, elem) => bar_elem_tags(elem, f),
+        BarTuiElem::Hide | BarTuiElem::FillSpace(_) | BarTuiElem::Spacing(_) => {}
+    }
+}
+
+/// Removes the menus and callbacks of tags that have been neither on the bar nor in the menus of
+/// the tags on the bar for [`TAG_EXPIRY`], e.g. those of removed tray items, which would
+/// otherwise accumulate forever.
+async fn run_tag_gc(bar_tui_rx: watch::Receiver<Vec<BarTuiElem>>, ctrl_tx: ModuleControlTx) {
+    let expiry = std::env::var(TAG_EXPIRY_VAR)
+        .ok()
+        .and_then(|it| it.parse().ok())
+        .map_or(TAG_EXPIRY, std::time::Duration::from_secs);
+    let mut last_seen = HashMap::<tui::CustomId, std::time::Instant>::new();
+    let mut interval = tokio::time::interval(TAG_GC_INTERVAL);
+    loop {
+        interval.tick().await;
+        let now = std::time::Instant::now();
+
+        let mut pending = Vec::new();
+        for elem in bar_tui_rx.borrow().iter() {
+            bar_elem_tags(elem, &mut |tag| pending.push(tag.clone()));
+        }
+        {
+            let menus = ctrl_tx.bar_menus_tx.borrow();
+            while let Some(tag) = pending.pop() {
+                // Already visited in this pass
+                if last_seen.insert(tag.clone(), now) == Some(now) {
+                    continue;
+                }
+                for menu in menus.get(&tag).into_iter().flat_map(HashMap::values) {
+                    menu.tui_rx
+                        .borrow()
+                        .for_each_tag(&mut |tag| pending.push(tag.clone()));
                 }
             }
+        }
+
+        // Tags that were registered since the last pass get the full expiry to show up
+        let mut expired = |tag: &tui::CustomId| {
+            now.duration_since(*last_seen.entry(tag.clone()).or_insert(now)) >= expiry
         };
+        let mut removed = 0;
+        ctrl_tx.bar_menus_tx.send_if_modified(|menus| {
+            let len = menus.len();
+            menus.retain(|tag, _| !expired(tag));
+            removed += len - menus.len();
+            len != menus.len()
+        });
+        ctrl_tx.tag_cb_tx.send_if_modified(|cbs| {
+            let len = cbs.cbs.len();
+            cbs.cbs.retain(|tag, _| !expired(tag));
+            removed += len - cbs.cbs.len();
+            len != cbs.cbs.len()
+        });
+        if removed != 0 {
+            log::debug!("Removed the menus and callbacks of {removed} unused tags");
+        }
+
+        let menus = ctrl_tx.bar_menus_tx.borrow();
+        let cbs = ctrl_tx.tag_cb_tx.borrow();
+        last_seen.retain(|tag, _| menus.contains_key(tag) || cbs.cbs.contains_key(tag));
+    }
+}
+
+fn orientation_of(orientations: &Orientations, monitor: &Arc<str>) -> host::Orientation {
+    orientations
+        .get(monitor)
+        .copied()
+        .unwrap_or(host::Orientation::Landscape)
+}
+
+/// The stacks of monitors with the given orientation, or all stacks if `only` is `None`.
+fn matching_stacks<'a>(
+    only: Option<host::Orientation>,
+    orientations: &'a Orientations,
+    by_monitor: &'a mut HashMap<Arc<str>, xtui::StackBuilder>,
+    fallback: &'a mut xtui::StackBuilder,
+) -> impl Iterator<Item = &'a mut xtui::StackBuilder> {
+    // The fallback is used for monitors without their own stack, which are all landscape
+    let fallback_matches = only.is_none_or(|it| it == host::Orientation::Landscape);
+    by_monitor
+        .iter_mut()
+        .filter(move |(monitor, _)| {
+            only.is_none_or(|it| it == orientation_of(orientations, monitor))
+        })
+        .map(|(_, stack)| stack)
+        .chain(fallback_matches.then_some(fallback))
+}
+
+fn push_bar_elem(
+    elem: &BarTuiElem,
+    only: Option<host::Orientation>,
+    orientations: &Orientations,
+    by_monitor: &mut HashMap<Arc<str>, xtui::StackBuilder>,
+    fallback: &mut xtui::StackBuilder,
+) {
+    match elem {
+        BarTuiElem::Shared(elem) => {
+            for stack in matching_stacks(only, orientations, by_monitor, fallback) {
+                stack.push(elem.clone());
+            }
+        }
+        BarTuiElem::ByMonitor(elems) => {
+            for (mtr, elem) in elems {
+                if only.is_some_and(|it| it != orientation_of(orientations, mtr)) {
+                    continue;
+                }
+                by_monitor
+                    .entry(mtr.clone())
+                    .or_insert_with(|| fallback.clone())
+                    .push(elem.clone());
+            }
+        }
+        BarTuiElem::Hide => {}
+        BarTuiElem::FillSpace(weight) => {
+            for stack in matching_stacks(only, orientations, by_monitor, fallback) {
+                stack.fill(*weight, tui::Elem::empty());
+            }
+        }
+        BarTuiElem::Spacing(len) => {
+            for stack in matching_stacks(only, orientations, by_monitor, fallback) {
+                stack.spacing(*len);
+            }
+        }
+        BarTuiElem::Only(orientation, elem) => {
+            if only.is_none_or(|it| it == *orientation) {
+                push_bar_elem(elem, Some(*orientation), orientations, by_monitor, fallback);
+            }
+        }
+    }
+}
+
+fn send_bar_tui(
+    bar_tui: &[BarTuiElem],
+    orientations: &Orientations,
+    ctrl_tx: &host::HostUpdateSender,
+) {
+    let mut fallback = xtui::StackBuilder::new(tui::Axis::X);
+    // Portrait monitors always get their own bar, since they may leave out some elements
+    let mut by_monitor = orientations
+        .iter()
+        .filter(|&(_, &it)| it == host::Orientation::Portrait)
+        .map(|(monitor, _)| (monitor.clone(), fallback.clone()))
+        .collect();
+    for elem in bar_tui {
+        push_bar_elem(elem, None, orientations, &mut by_monitor, &mut fallback);
     }
 
     ctrl_tx
@@ -256,16 +916,19 @@ async fn run_menu_mgr(
             .send(match cur.as_mut() {
                 Some(cur) => {
                     let mut tui = cur.tui_rx.borrow_and_update().clone();
+                    let mut opts = host::OpenMenuOpts::default();
                     if cur.menu_kind == MenuKind::Tooltip {
                         // Make the entire tui interactive because we want to close
                         // tooltips on any interaction
                         tui = tui.interactive(mk_fresh_interact_tag());
+                        // Do not flicker tooltips while moving across the bar
+                        opts.open_delay = std::time::Duration::from_millis(300);
                     }
                     host::HostUpdate::OpenMenu(host::OpenMenu {
                         tui,
                         monitor: cur.monitor.clone(),
                         bar_anchor: cur.bar_anchor.clone(),
-                        opts: Default::default(),
+                        opts,
                     })
                 }
                 None => host::HostUpdate::CloseMenu,
@@ -274,13 +937,17 @@ async fn run_menu_mgr(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn run_event_handler(
     ctrl_upd_tx: host::HostUpdateSender,
     mut ctrl_ev_rx: tokio::sync::mpsc::UnboundedReceiver<host::HostEvent>,
     mut bar_menus_rx: watch::Receiver<BarMenus>,
     tag_cb_rx: watch::Receiver<Callbacks>,
-    // TODO: Reload on certain events (monitor changes)
-    _reload_tx: ReloadTx,
+    pending_cmds: PendingCommands,
+    orientations_tx: watch::Sender<Orientations>,
+    module_health: ModuleHealths,
+    metrics_tx: watch::Sender<Option<host::HostMetrics>>,
+    mut reload_tx: ReloadTx,
 ) {
     let cur_menu_tx = watch::Sender::new(None);
     tokio::spawn(run_menu_mgr(ctrl_upd_tx.clone(), cur_menu_tx.subscribe()));
@@ -304,6 +971,11 @@ async fn run_event_handler(
                 }
 
                 let is_hover = matches!(ikind, tui::InteractKind::Hover);
+                // Scrolling over the anchor of a tooltip may change its content, e.g. the month
+                // of a calendar, so it should stay open
+                let scrolled_tag = matches!(ikind, tui::InteractKind::Scroll(_))
+                    .then(|| tag.clone())
+                    .flatten();
 
                 match term.kind {
                     host::TermKind::Bar => {
@@ -337,7 +1009,10 @@ async fn run_event_handler(
                         } else {
                             cur_menu_tx.send_if_modified(|cur_opt| {
                                 cur_opt
-                                    .take_if(|cur| cur.menu_kind == MenuKind::Tooltip || !is_hover)
+                                    .take_if(|cur| {
+                                        scrolled_tag.as_ref() != Some(&cur.bar_anchor)
+                                            && (cur.menu_kind == MenuKind::Tooltip || !is_hover)
+                                    })
                                     .is_some()
                             });
                         }
@@ -361,6 +1036,70 @@ async fn run_event_handler(
             ) => {
                 cur_menu_tx.send_replace(None);
             }
+            host::HostEvent::MenuRequested { monitor, tag } => {
+                let menu = bar_menus_rx
+                    .borrow_and_update()
+                    .get(&tag)
+                    .and_then(|tag_menus| {
+                        // Prefer the menu that clicking opens over the tooltip
+                        tag_menus
+                            .values()
+                            .find(|it| it.kind != MenuKind::Tooltip)
+                            .or_else(|| tag_menus.values().next())
+                            .cloned()
+                    });
+                match menu {
+                    Some(BarMenu { tui_rx, kind }) => {
+                        cur_menu_tx.send_replace(Some(CurMenu {
+                            bar_anchor: tag,
+                            menu_kind: kind,
+                            monitor,
+                            tui_rx,
+                        }));
+                    }
+                    None => log::warn!("There is no menu for the element {tag:?}"),
+                }
+            }
+            host::HostEvent::ReloadRequested => {
+                reload_tx.reload();
+            }
+            host::HostEvent::Command(args) => {
+                let Some((name, args)) = args.split_first() else {
+                    continue;
+                };
+                let callback = tag_cb_rx.borrow().cmds.get(name.as_str()).cloned();
+                match callback {
+                    Some(cb) => cb(args),
+                    None => log::warn!("No module handles the command {name:?}"),
+                }
+            }
+            host::HostEvent::CommandFinished(output) => {
+                let reply_tx = pending_cmds
+                    .lock()
+                    .unwrap_or_else(|poison| poison.into_inner())
+                    .remove(&output.id);
+                match reply_tx {
+                    // The module may have stopped waiting
+                    Some(reply_tx) => _ = reply_tx.send(output),
+                    None => log::debug!("Output of unknown command {:?}", output.id),
+                }
+            }
+            host::HostEvent::Monitors(monitors) => {
+                let orientations = monitors
+                    .iter()
+                    .map(|it| (it.name.clone(), it.orientation()))
+                    .collect();
+                orientations_tx
+                    .send_if_modified(|cur| std::mem::replace(cur, orientations) != *cur);
+            }
+            host::HostEvent::ModuleStatusRequested => {
+                ctrl_upd_tx
+                    .send(host::HostUpdate::ModuleStatus(module_health.status()))
+                    .ok_or_debug();
+            }
+            host::HostEvent::Metrics(metrics) => {
+                metrics_tx.send_replace(Some(metrics));
+            }
             ev => {
                 log::trace!("Ignoring event {ev:?}");
             }
@@ -368,9 +1107,77 @@ async fn run_event_handler(
     }
 }
 
+/// The secret with the location of the weather module, as `<latitude>,<longitude>`. Like any
+/// secret, it is looked up from `SCRATCHBAR_SECRET_WEATHER_LOCATION`, `pass` or libsecret, see
+/// [`crate::secrets::lookup`].
+const WEATHER_LOCATION_SECRET: &str = "weather-location";
+
+/// How long the weather module waits for [`WEATHER_LOCATION_SECRET`], e.g. while `pass` waits
+/// for a pinentry that nobody answers.
+const WEATHER_SECRET_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// The location from [`WEATHER_LOCATION_SECRET`], if any. The weather is only shown if it is set.
+async fn weather_location() -> Option<modules::weather::Location> {
+    let secret = crate::secrets::lookup(WEATHER_LOCATION_SECRET)
+        .await
+        .ok_or_debug()?;
+    let location = secret.expose().split_once(',').and_then(|(lat, lon)| {
+        Some(modules::weather::Location::new(
+            lat.trim().parse().ok()?,
+            lon.trim().parse().ok()?,
+        ))
+    });
+    if location.is_none() {
+        log::error!(
+            "The {WEATHER_LOCATION_SECRET} secret is not of the form <latitude>,<longitude>"
+        );
+    }
+    location
+}
+
+/// Shows the weather at [`weather_location`]. The secret is looked up here rather than before
+/// spawning the modules, so that a slow lookup only delays this module.
+async fn weather_module(args: ModuleArgs) -> modules::ModuleExit {
+    let location = match weather_location().timeout(WEATHER_SECRET_TIMEOUT).await {
+        Ok(Some(location)) => location,
+        Ok(None) => return modules::ModuleExit::Done,
+        Err(_) => {
+            log::warn!("Timed out looking up the {WEATHER_LOCATION_SECRET} secret");
+            return modules::ModuleExit::Done;
+        }
+    };
+    let config = modules::weather::WeatherConfig {
+        location: Some(location),
+        ..Default::default()
+    };
+    run_module::<modules::weather::Weather>(config, args).await
+}
+
+/// Shows `elem` only while `module` is not hidden, e.g. the spacing after a module that may
+/// never show anything.
+fn shown_with(
+    elem: BarTuiElem,
+    mut module: watch::Receiver<BarTuiElem>,
+) -> watch::Receiver<BarTuiElem> {
+    let convert = move |module: &BarTuiElem| match module {
+        BarTuiElem::Hide => BarTuiElem::Hide,
+        _ => elem.clone(),
+    };
+    let (tx, rx) = watch::channel(convert(&module.borrow_and_update()));
+    tokio::spawn(async move {
+        while let Ok(()) = module.changed().await {
+            if tx.send(convert(&module.borrow_and_update())).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
 pub async fn control_main(
     connect: host::HostConnection,
     ctrl_ev_rx: tokio::sync::mpsc::UnboundedReceiver<host::HostEvent>,
+    stop: tokio_util::sync::CancellationToken,
 ) -> std::process::ExitCode {
     let mut required_tasks = JoinSet::new();
 
@@ -378,56 +1185,104 @@ pub async fn control_main(
 
     let tag_cb_tx = watch::Sender::new(Callbacks::default());
     let bar_menus_tx = watch::Sender::new(BarMenus::default());
+    let orientations_tx = watch::Sender::new(Orientations::default());
+    let mut orientations_rx = orientations_tx.subscribe();
+    let module_health = ModuleHealths::default();
+    let metrics_tx = watch::Sender::new(None);
+    let pending_cmds = PendingCommands::default();
     tokio::spawn(run_event_handler(
         connect.update_tx.clone(),
         ctrl_ev_rx,
         bar_menus_tx.subscribe(),
         tag_cb_tx.subscribe(),
+        pending_cmds.clone(),
+        orientations_tx,
+        module_health.clone(),
+        metrics_tx.clone(),
         reload_tx.clone(),
     ));
+
+    let portal = clients::portal::connect(reload_tx.subscribe());
+
     let mut fac = BarModuleFactory {
         reload_tx: reload_tx.clone(),
         ctrl_tx: ModuleControlTx {
             tag_cb_tx,
             bar_menus_tx,
+            shutdown_hooks: Default::default(),
+            update_tx: connect.update_tx.clone(),
+            pending_cmds,
+            instance: 0,
         },
+        color_scheme_rx: portal.color_scheme_rx.clone(),
+        health: module_health,
         tasks: JoinSet::new(),
     };
+    {
+        // Theme switch hook: re-render everything when the system light/dark preference changes
+        let mut color_scheme_rx = portal.color_scheme_rx.clone();
+        let mut reload_tx = reload_tx.clone();
+        fac.tasks.spawn(async move {
+            while let Ok(()) = color_scheme_rx.changed().await {
+                log::info!(
+                    "Color scheme changed to {:?}",
+                    *color_scheme_rx.borrow_and_update()
+                );
+                reload_tx.reload();
+            }
+        });
+    }
 
-    let pulse = Arc::new(clients::pulse::PulseClient::connect(reload_tx.subscribe()));
-    let pulse_symbol_opts = text::TextOpts::from(text::HorizontalAlign::Center);
-    let pulse_symbol_width = 2.try_into().unwrap();
-
+    let weather = fac.spawn_named("Weather", weather_module);
     let mut modules = [
         fac.fixed(BarTuiElem::Spacing(1)),
-        fac.spawn(hypr::hypr_module),
+        if clients::niri::is_running() {
+            fac.spawn(workspaces::workspaces_module)
+        } else {
+            fac.spawn_module::<modules::hypr::Hyprland>(Default::default())
+        },
         fac.fixed(BarTuiElem::FillSpace(1)),
         fac.spawn(tray::tray_module),
         fac.fixed(BarTuiElem::Spacing(3)),
-        fac.spawn_with(
-            pulse::PulseModuleArgs {
-                pulse: pulse.clone(),
-                device_kind: clients::pulse::PulseDeviceKind::Source,
-                muted_sym: pulse_symbol_opts.render_cell("", pulse_symbol_width),
-                unmuted_sym: pulse_symbol_opts.render_cell("", pulse_symbol_width),
-            },
-            pulse::pulse_module,
-        ),
+        fac.spawn_with(connect.update_tx.clone(), scratch::scratch_module),
+        fac.fixed(BarTuiElem::Spacing(3)),
+        fac.spawn_module::<modules::pulse::Pulse>(modules::pulse::PulseConfig {
+            device: modules::pulse::PulseDeviceKind::Source,
+            muted_sym: Symbol::nerd(symbols::MICROPHONE_SLASH).render(),
+            unmuted_sym: Symbol::nerd(symbols::MICROPHONE).render(),
+            ..Default::default()
+        }),
+        fac.fixed(BarTuiElem::Spacing(3)),
+        fac.spawn_module::<modules::pulse::Pulse>(modules::pulse::PulseConfig {
+            device: modules::pulse::PulseDeviceKind::Sink,
+            muted_sym: Symbol::nerd(symbols::VOLUME_XMARK).render(),
+            unmuted_sym: Symbol::nerd(symbols::VOLUME_HIGH).render(),
+            ..Default::default()
+        }),
         fac.fixed(BarTuiElem::Spacing(3)),
-        fac.spawn_with(
-            pulse::PulseModuleArgs {
-                pulse,
-                device_kind: clients::pulse::PulseDeviceKind::Sink,
-                muted_sym: pulse_symbol_opts.render_cell("", pulse_symbol_width),
-                unmuted_sym: pulse_symbol_opts.render_cell("", pulse_symbol_width),
-            },
-            pulse::pulse_module,
-        ),
+        match listen::ListenModuleArgs::from_env() {
+            Some(args) => fac.spawn_with(args, listen::listen_module),
+            None => fac.fixed(BarTuiElem::Hide),
+        },
+        if stats::StatsModuleArgs::enabled() {
+            fac.spawn_with(
+                stats::StatsModuleArgs {
+                    update_tx: connect.update_tx.clone(),
+                    metrics_rx: metrics_tx.subscribe(),
+                },
+                stats::stats_module,
+            )
+        } else {
+            fac.fixed(BarTuiElem::Hide)
+        },
+        collapse_hidden(SEGMENT_COLLAPSE, fac.spawn(energy::ppd_module)),
+        collapse_hidden(SEGMENT_COLLAPSE, fac.spawn(energy::energy_module)),
         fac.fixed(BarTuiElem::Spacing(3)),
-        fac.spawn(energy::ppd_module),
-        fac.spawn(energy::energy_module),
+        fac.spawn_module::<modules::timer::Timer>(Default::default()),
         fac.fixed(BarTuiElem::Spacing(3)),
-        fac.spawn(time::time_module),
+        weather.clone(),
+        shown_with(BarTuiElem::Spacing(3), weather),
+        fac.spawn_module::<modules::time::Clock>(Default::default()),
         fac.fixed(BarTuiElem::Spacing(1)),
     ];
 
@@ -446,23 +1301,40 @@ pub async fn control_main(
                 }
             });
         }
+        fac.tasks.spawn(run_tag_gc(
+            bar_tui_tx_inner.subscribe(),
+            fac.ctrl_tx.clone(),
+        ));
         let mut bar_tui_rx_inner = bar_tui_tx_inner.subscribe();
         required_tasks.spawn(async move {
-            while let Ok(()) = bar_tui_rx_inner.changed().await {
-                send_bar_tui(&bar_tui_rx_inner.borrow_and_update(), &connect.update_tx);
+            loop {
+                tokio::select! {
+                    Ok(()) = bar_tui_rx_inner.changed() => {}
+                    Ok(()) = orientations_rx.changed() => {}
+                    else => break,
+                }
+                send_bar_tui(
+                    &bar_tui_rx_inner.borrow_and_update(),
+                    &orientations_rx.borrow_and_update(),
+                    &connect.update_tx,
+                );
             }
         });
     }
 
     reload_tx.reload();
 
-    match required_tasks
-        .join_next()
-        .await
-        .unwrap_or_else(|| unreachable!())
-        .ok_or_log()
-    {
-        Some(_) => std::process::ExitCode::SUCCESS,
-        None => std::process::ExitCode::FAILURE,
-    }
+    let code = tokio::select! {
+        res = required_tasks.join_next() => {
+            match res.unwrap_or_else(|| unreachable!()).ok_or_log() {
+                Some(_) => std::process::ExitCode::SUCCESS,
+                None => std::process::ExitCode::FAILURE,
+            }
+        }
+        () = stop.cancelled() => std::process::ExitCode::SUCCESS,
+    };
+
+    fac.shutdown().await;
+
+    code
 }
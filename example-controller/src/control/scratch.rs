@@ -0,0 +1,42 @@
+//! A button that toggles a dropdown terminal, see [`host::HostUpdate::ToggleScratch`].
+
+use scratchbar::{
+    host, modules,
+    tui::{
+        self,
+        symbols::{self, Symbol},
+    },
+};
+
+use crate::{
+    control::{BarTuiElem, ModuleArgs, interact_callback_with, mk_fresh_interact_tag},
+    utils::ResultExt as _,
+};
+
+const SCRATCH_ID: &str = "dropdown";
+
+pub async fn scratch_module(
+    update_tx: host::HostUpdateSender,
+    ModuleArgs {
+        tui_tx, ctrl_tx, ..
+    }: ModuleArgs,
+) -> modules::ModuleExit {
+    let tag = mk_fresh_interact_tag();
+    ctrl_tx.register_callback(
+        tag.clone(),
+        interact_callback_with(update_tx, |update_tx, interact| {
+            if interact.kind != tui::InteractKind::Click(tui::MouseButton::Left) {
+                return;
+            }
+            update_tx
+                .send(host::HostUpdate::ToggleScratch(host::ToggleScratch::new(
+                    SCRATCH_ID,
+                )))
+                .ok_or_debug();
+        }),
+    );
+    tui_tx.send_replace(BarTuiElem::Shared(
+        Symbol::nerd(symbols::TERMINAL).render().interactive(tag),
+    ));
+    std::future::pending().await
+}
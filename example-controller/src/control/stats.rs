@@ -0,0 +1,108 @@
+//! A module that shows the host's render performance, see [`host::HostMetrics`]. Hovering it
+//! shows the metrics of each panel.
+
+use std::{fmt::Write as _, time::Duration};
+
+use scratchbar::{host, modules, tui};
+use tokio::sync::watch;
+
+use crate::{
+    control::{BarTuiElem, MenuKind, ModuleArgs, RegisterMenu, mk_fresh_interact_tag},
+    utils::ResultExt as _,
+    xtui::text,
+};
+
+/// The environment variable that enables [`stats_module`] if set to a non-empty value.
+pub const STATS_VAR: &str = "SCRATCHBAR_STATS";
+
+const QUERY_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone)]
+pub struct StatsModuleArgs {
+    pub update_tx: host::HostUpdateSender,
+    /// The last answer to [`host::HostUpdate::QueryMetrics`].
+    pub metrics_rx: watch::Receiver<Option<host::HostMetrics>>,
+}
+impl StatsModuleArgs {
+    pub fn enabled() -> bool {
+        std::env::var_os(STATS_VAR).is_some_and(|it| !it.is_empty())
+    }
+}
+
+fn details(metrics: &host::HostMetrics) -> String {
+    let mut out = String::new();
+    for panel in &metrics.panels {
+        _ = writeln!(
+            out,
+            "{} {:?}: {} frames, {:.1}ms avg, {:.1}ms max, {} KiB",
+            panel.term.monitor,
+            panel.term.kind,
+            panel.frames,
+            panel.avg_render_us as f64 / 1000.0,
+            panel.max_render_us as f64 / 1000.0,
+            panel.bytes_written / 1024,
+        );
+    }
+    _ = write!(out, "{:.1} events/s", metrics.events_per_sec);
+    if let Some(latency) = metrics.menu_open_latency_us {
+        _ = write!(
+            out,
+            "\nLast menu opened in {:.1}ms",
+            latency as f64 / 1000.0
+        );
+    }
+    out
+}
+
+pub async fn stats_module(
+    StatsModuleArgs {
+        update_tx,
+        mut metrics_rx,
+    }: StatsModuleArgs,
+    ModuleArgs {
+        tui_tx, ctrl_tx, ..
+    }: ModuleArgs,
+) -> modules::ModuleExit {
+    let tag = mk_fresh_interact_tag();
+    let (details_tx, details_rx) = watch::channel(tui::Elem::empty());
+    ctrl_tx.register_menu(RegisterMenu {
+        on_tag: tag.clone(),
+        on_kind: tui::InteractKind::Hover,
+        tui_rx: details_rx,
+        menu_kind: MenuKind::Tooltip,
+        opts: Default::default(),
+    });
+
+    let mut interval = tokio::time::interval(QUERY_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                update_tx.send(host::HostUpdate::QueryMetrics).ok_or_debug();
+            }
+            Ok(()) = metrics_rx.changed() => {
+                let Some(metrics) = metrics_rx.borrow_and_update().clone() else {
+                    continue;
+                };
+                // The slowest panel is the one worth looking at
+                let avg_render_us = metrics
+                    .panels
+                    .iter()
+                    .map(|it| it.avg_render_us)
+                    .max()
+                    .unwrap_or(0);
+                let summary = format!(
+                    "{:.1}ms {:.0}ev/s",
+                    avg_render_us as f64 / 1000.0,
+                    metrics.events_per_sec
+                );
+                tui_tx.send_replace(BarTuiElem::Shared(
+                    text::TextOpts::default()
+                        .render_line(&summary)
+                        .interactive(tag.clone()),
+                ));
+                details_tx.send_replace(text::TextOpts::default().render(&details(&metrics)));
+            }
+        }
+    }
+}
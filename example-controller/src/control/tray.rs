@@ -10,9 +10,14 @@ use crate::{
     xtui::{self, text},
 };
 use anyhow::Context as _;
-use scratchbar::tui;
+use scratchbar::{modules, tui};
 use tokio::sync::watch;
 
+/// The environment variable with the number of bits per color channel that tray icons are
+/// reduced to, see [`xtui::quantize_img`]. Animated icons, e.g. of download progress, otherwise
+/// transmit a new image for every frame.
+pub const TRAY_ICON_BITS_VAR: &str = "SCRATCHBAR_TRAY_ICON_BITS";
+
 pub async fn tray_module(
     ModuleArgs {
         tui_tx,
@@ -20,9 +25,13 @@ pub async fn tray_module(
         ctrl_tx,
         ..
     }: ModuleArgs,
-) {
+) -> modules::ModuleExit {
     use crate::clients::tray::*;
     let tray = Arc::new(clients::tray::connect(reload_rx));
+    let icon_bits = std::env::var(TRAY_ICON_BITS_VAR)
+        .ok()
+        .and_then(|it| it.parse::<u8>().ok())
+        .filter(|it| (1..8).contains(it));
 
     let mut entry_reg = InteractTagRegistry::new();
 
@@ -77,7 +86,7 @@ pub async fn tray_module(
                 ..
             }) = menu.as_ref()
             {
-                let menu_tui = tray_menu_to_tui(0, submenus, &|id| {
+                let menu_items = tray_menu_to_tui(submenus, &|id| {
                     let tag = mk_fresh_interact_tag();
                     let Some(menu_path) = menu_path.clone() else {
                         return tag;
@@ -114,7 +123,13 @@ pub async fn tray_module(
                     }),
                     xtui::BlockOpts {
                         borders: xtui::BlockBorders::all(),
-                        inner: Some(menu_tui),
+                        inner: Some(
+                            tui::menu::Menu {
+                                items: menu_items,
+                                ..Default::default()
+                            }
+                            .build(),
+                        ),
                     },
                 );
                 ctrl_tx.register_menu(RegisterMenu {
@@ -127,12 +142,22 @@ pub async fn tray_module(
             }
 
             // FIXME: Handle the other options
+            let pixmaps = item.icon_pixmap.as_deref().unwrap_or(&[]);
+            if pixmaps.is_empty()
+                && let Some(icon) = item
+                    .icon_name
+                    .as_deref()
+                    .and_then(|name| xtui::icons::icon(name, tui::Axis::Y, 1))
+            {
+                tui_stack.push(icon.interactive(tag.clone()));
+                tui_stack.spacing(1);
+            }
             // FIXME: Why are we showing all icons?
             for system_tray::item::IconPixmap {
                 width,
                 height,
                 pixels,
-            } in item.icon_pixmap.as_deref().unwrap_or(&[])
+            } in pixmaps
             {
                 let mut img = match image::RgbaImage::from_vec(
                     width.cast_unsigned(),
@@ -150,6 +175,9 @@ pub async fn tray_module(
                 for image::Rgba(pixel) in img.pixels_mut() {
                     *pixel = u32::from_be_bytes(*pixel).rotate_left(8).to_be_bytes();
                 }
+                if let Some(bits) = icon_bits {
+                    xtui::quantize_img(&mut img, bits);
+                }
 
                 let img_elem =
                     xtui::rgba_img_fill_axis(img, tui::Axis::Y, 1).interactive(tag.clone());
@@ -160,89 +188,78 @@ pub async fn tray_module(
         }
         tui_tx.send_replace(BarTuiElem::Shared(tui_stack.build()));
     }
+    return modules::ModuleExit::Failed;
+
     fn tray_menu_item_to_tui(
-        depth: u16,
         item: &system_tray::menu::MenuItem,
         mk_interact: &impl Fn(i32) -> tui::CustomId,
-    ) -> Option<tui::Elem> {
+    ) -> Option<tui::menu::MenuItem> {
         use system_tray::menu::*;
-        let main_elem = match item {
-            MenuItem { visible: false, .. } => return None,
+        match item {
+            MenuItem { visible: false, .. } => None,
             MenuItem {
                 visible: true,
                 menu_type: MenuType::Separator,
                 ..
-            } => xtui::block(
-                xtui::BlockLines::normal().apply_crossterm(crossterm::style::ContentStyle {
-                    foreground_color: Some(crossterm::style::Color::DarkGrey),
-                    ..Default::default()
-                }),
-                xtui::BlockOpts {
-                    borders: xtui::BlockBorders {
-                        top: true,
-                        ..Default::default()
-                    },
-                    ..Default::default()
-                },
-            ),
+            } => Some(tui::menu::MenuItem::Separator),
             MenuItem {
                 id,
                 menu_type: MenuType::Standard,
                 label: Some(label),
-                enabled: _,
+                enabled,
                 visible: true,
-                icon_name: _,
+                icon_name,
                 icon_data,
                 shortcut: _,
-                toggle_type: _, // TODO: implement toggle
-                toggle_state: _,
+                toggle_type,
+                toggle_state,
                 children_display: _,
                 disposition: _, // TODO: what to do with this?
-                submenu: _,
+                submenu,
             } => {
-                let mut stack = xtui::StackBuilder::new(tui::Axis::X);
-                stack.spacing(depth + 1);
-                if let Some(icon) = icon_data
-                    && let Some(img) =
+                let icon = match icon_data {
+                    Some(icon) => {
                         image::load_from_memory_with_format(icon, image::ImageFormat::Png)
                             .context("Systray icon has invalid png data")
                             .ok_or_log()
-                {
-                    stack.push(xtui::rgba_img_fill_axis(img.into_rgba8(), tui::Axis::Y, 1));
-                    stack.spacing(1);
-                }
-                stack.push(text::TextOpts::default().render_line(label));
-                // FIXME: Add hover
-                stack.build().interactive(mk_interact(*id))
+                            .map(|img| xtui::rgba_img_fill_axis(img.into_rgba8(), tui::Axis::Y, 1))
+                    }
+                    None => icon_name
+                        .as_deref()
+                        .and_then(|name| xtui::icons::icon(name, tui::Axis::Y, 1)),
+                };
+                let checked = match toggle_type {
+                    ToggleType::CannotBeToggled => None,
+                    ToggleType::Checkmark | ToggleType::Radio => {
+                        Some(matches!(toggle_state, ToggleState::On))
+                    }
+                };
+                Some(
+                    tui::menu::MenuEntry {
+                        icon,
+                        checked,
+                        enabled: *enabled,
+                        submenu: tray_menu_to_tui(submenu, mk_interact),
+                        ..tui::menu::MenuEntry::new(label.as_str(), mk_interact(*id))
+                    }
+                    .into(),
+                )
             }
 
             _ => {
                 log::error!("Unhandled menu item: {item:#?}");
-                return None;
+                None
             }
-        };
-
-        Some(if item.submenu.is_empty() {
-            main_elem
-        } else {
-            let mut stack = xtui::StackBuilder::new(tui::Axis::Y);
-            stack.push(main_elem);
-            stack.push(tray_menu_to_tui(depth + 1, &item.submenu, mk_interact));
-            stack.build()
-        })
+        }
     }
 
     fn tray_menu_to_tui(
-        depth: u16,
         items: &[system_tray::menu::MenuItem],
         mk_interact: &impl Fn(i32) -> tui::CustomId,
-    ) -> tui::Elem {
-        let mut stack = xtui::StackBuilder::new(tui::Axis::Y);
-        for item in items {
-            if let Some(item) = tray_menu_item_to_tui(depth, item, mk_interact) {
-                stack.push(item)
-            }
-        }
-        stack.build()
+    ) -> Vec<tui::menu::MenuItem> {
+        items
+            .iter()
+            .filter_map(|item| tray_menu_item_to_tui(item, mk_interact))
+            .collect()
     }
 }
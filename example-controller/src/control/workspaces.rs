@@ -0,0 +1,105 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    clients::{self, portal::ColorScheme},
+    control::{BarTuiElem, InteractTagRegistry, ModuleArgs, interact_callback_with},
+    desktop::{BasicDesktopState, WorkspaceId},
+    utils::ResultExt as _,
+    xtui::{self, text},
+};
+use scratchbar::{modules, tui};
+use tokio::sync::watch;
+
+/// Shows the workspaces of niri. Hyprland is handled by [`scratchbar::modules::hypr`].
+pub async fn workspaces_module(args: ModuleArgs) -> modules::ModuleExit {
+    let niri = Arc::new(clients::niri::connect(args.reload_rx.clone()));
+    let basic_rx = niri.basic_rx.clone();
+    run_workspaces(
+        niri,
+        basic_rx,
+        clients::niri::NiriClient::switch_workspace,
+        args,
+    )
+    .await
+}
+
+async fn run_workspaces<C: Send + Sync + 'static>(
+    client: Arc<C>,
+    mut basic_rx: watch::Receiver<BasicDesktopState>,
+    switch_workspace: fn(&C, WorkspaceId),
+    ModuleArgs {
+        tui_tx,
+        mut reload_rx,
+        ctrl_tx,
+        color_scheme_rx,
+        ..
+    }: ModuleArgs,
+) -> modules::ModuleExit {
+    let mut ws_reg = InteractTagRegistry::new();
+
+    loop {
+        // Green is hard to read on light backgrounds
+        let active_color = match *color_scheme_rx.borrow() {
+            ColorScheme::Light => text::Color::DarkGreen,
+            ColorScheme::Dark | ColorScheme::NoPreference => text::Color::Green,
+        };
+
+        let mut by_monitor = HashMap::new();
+        for ws in basic_rx.borrow_and_update().workspaces.iter() {
+            let Some(monitor) = ws.monitor.clone() else {
+                continue;
+            };
+
+            // Key by name as well, since niri renumbers unnamed workspaces while keeping their id
+            let key = (ws.id.clone(), ws.name.clone());
+            let (tag, ()) = ws_reg.get_or_init(&key, |tag| {
+                let on_interact = interact_callback_with(
+                    (client.clone(), ws.id.clone()),
+                    move |(client, ws_id), interact| {
+                        if interact.kind != tui::InteractKind::Click(tui::MouseButton::Left) {
+                            return;
+                        }
+                        switch_workspace(client, ws_id.clone());
+                    },
+                );
+
+                ctrl_tx.register_callback(tag.clone(), on_interact);
+            });
+
+            let base = text::TextOpts::default().with(|it| {
+                if ws.is_active {
+                    it.fg_color = active_color
+                }
+            });
+            let hovered = base.clone().with(|it| it.attrs.set_underlined(true));
+
+            let wss = by_monitor
+                .entry(monitor)
+                .or_insert_with(|| xtui::StackBuilder::new(tui::Axis::X));
+            wss.push(text::render_with_hover(
+                &base,
+                tag.clone(),
+                &hovered,
+                |it| it.render_line(&ws.name),
+            ));
+            wss.spacing(1);
+        }
+        let by_monitor = by_monitor
+            .into_iter()
+            .map(|(k, v)| (k, v.build()))
+            .collect();
+
+        tui_tx.send_replace(BarTuiElem::ByMonitor(by_monitor));
+
+        // A change of the color scheme triggers a reload
+        tokio::select! {
+            res = basic_rx.changed() => {
+                if res.ok_or_debug().is_none() {
+                    break;
+                }
+            }
+            Some(()) = reload_rx.wait() => {}
+        }
+    }
+    modules::ModuleExit::Failed
+}
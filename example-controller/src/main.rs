@@ -1,10 +1,23 @@
 mod clients;
 mod control;
 mod desktop;
+mod scaffold;
+mod secrets;
 mod utils;
 mod xtui;
 
 fn main() -> std::process::ExitCode {
+    let mut args = std::env::args_os().skip(1);
+    if args.next().as_deref() == Some(std::ffi::OsStr::new(scaffold::NEW_MODULE_ARG)) {
+        let name = args.next().and_then(|it| it.into_string().ok());
+        return match scaffold::new_module_main(name, args.next().map(Into::into)) {
+            Ok(()) => std::process::ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("{err:?}");
+                std::process::ExitCode::FAILURE
+            }
+        };
+    }
     main_inner().unwrap_or(std::process::ExitCode::FAILURE)
 }
 
@@ -40,6 +53,7 @@ fn main_inner() -> Option<std::process::ExitCode> {
     let exit_tx_clone = exit_tx.clone();
     let connect = scratchbar::host::connect(
         scratchbar::host::HostConnectOpts {
+            restart_controller: scratchbar::host::RestartPolicy::OnFailure,
             ..Default::default()
         },
         move |ev| ev_tx.send(ev).map_err(|err| err.0),
@@ -57,10 +71,27 @@ fn main_inner() -> Option<std::process::ExitCode> {
 
     let _guard = runtime.enter();
 
-    runtime.spawn(async move {
-        let code = control::control_main(connect, ev_rx).await;
-        exit_tx.send(code).ok_or_log();
+    let stop = tokio_util::sync::CancellationToken::new();
+    let control_task = runtime.spawn({
+        let stop = stop.clone();
+        async move {
+            let code = control::control_main(connect, ev_rx, stop).await;
+            exit_tx.send(code).ok_or_log();
+        }
+    });
+
+    let code = runtime.block_on(exit_rx.recv());
+
+    // Give the modules a chance to shut down properly. The host kills us after 5s.
+    stop.cancel();
+    runtime.block_on(async {
+        use tokio_util::time::FutureExt as _;
+        control_task
+            .timeout(control::SHUTDOWN_DEADLINE + std::time::Duration::from_secs(1))
+            .await
+            .context("Controller failed to shut down in time")
+            .ok_or_log();
     });
 
-    runtime.block_on(exit_rx.recv())
+    code
 }
@@ -0,0 +1,139 @@
+//! `scratchbar-controller new-module <name>`: Creates the skeleton of a new bar module.
+
+use anyhow::Context as _;
+use std::path::PathBuf;
+
+pub const NEW_MODULE_ARG: &str = "new-module";
+
+const TEMPLATE: &str = r#"use std::{sync::Arc, time::Duration};
+
+use crate::{
+    control::{BarTuiElem, MenuKind, ModuleArgs, RegisterMenu, mk_fresh_interact_tag},
+    xtui::text,
+};
+use scratchbar::{modules, tui};
+use tokio::sync::watch;
+
+#[derive(Clone)]
+pub struct __Name__ModuleArgs {
+    pub label: Arc<str>,
+    pub interval: Duration,
+}
+
+pub async fn __name___module(
+    __Name__ModuleArgs { label, interval }: __Name__ModuleArgs,
+    ModuleArgs {
+        tui_tx,
+        mut reload_rx,
+        ctrl_tx,
+        ..
+    }: ModuleArgs,
+) -> modules::ModuleExit {
+    let interact_tag = mk_fresh_interact_tag();
+
+    let (tooltip_tx, tooltip_rx) = watch::channel(tui::Elem::empty());
+    ctrl_tx.register_menu(RegisterMenu {
+        on_tag: interact_tag.clone(),
+        on_kind: tui::InteractKind::Hover,
+        tui_rx: tooltip_rx,
+        menu_kind: MenuKind::Tooltip,
+        opts: Default::default(),
+    });
+
+    loop {
+        // TODO: Query the state to display
+        tui_tx.send_replace(BarTuiElem::Shared(render_bar(&label, &interact_tag)));
+        tooltip_tx.send_replace(text::TextOpts::default().render_line("TODO"));
+
+        tokio::select! {
+            Some(()) = reload_rx.wait() => {}
+            () = tokio::time::sleep(interval) => {}
+        }
+    }
+}
+
+fn render_bar(label: &str, interact_tag: &tui::CustomId) -> tui::Elem {
+    text::TextOpts::default()
+        .render_line(label)
+        .interactive(interact_tag.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use scratchbar::tui::{self, testing};
+
+    // Renders without a terminal, see `tui::testing`
+    #[test]
+    fn bar_shows_the_label() {
+        let elem = super::render_bar("label", &tui::CustomId::from_bytes(b"tag"));
+        let size = tui::Size {
+            width: 10,
+            height: 1,
+        };
+        let out = testing::render_to_string(&elem, size);
+        assert_eq!(out.lines().next(), Some("label"), "{out}");
+    }
+}
+"#;
+
+fn is_valid_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+fn to_pascal_case(name: &str) -> String {
+    name.split('_')
+        .flat_map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|c| c.to_ascii_uppercase())
+                .into_iter()
+                .chain(chars)
+        })
+        .collect()
+}
+
+/// Where the module is created unless another directory is given, relative to the working
+/// directory, i.e. the root of the controller's repo when using `cargo run`.
+const DEFAULT_DIR: &str = "src/control";
+
+pub fn new_module_main(name: Option<String>, dir: Option<PathBuf>) -> anyhow::Result<()> {
+    let name = name.with_context(|| {
+        format!("Usage: {NEW_MODULE_ARG} <snake_case_name> [directory, default: {DEFAULT_DIR}]")
+    })?;
+    anyhow::ensure!(
+        is_valid_name(&name),
+        "Module names must be snake_case identifiers, got {name:?}"
+    );
+
+    let dir = dir.unwrap_or_else(|| DEFAULT_DIR.into());
+    anyhow::ensure!(
+        dir.is_dir(),
+        "{dir:?} is not a directory. Run this in the root of the controller's repo or pass the directory of the modules"
+    );
+    let path = dir.join(&name).with_extension("rs");
+    let code = TEMPLATE
+        .replace("__Name__", &to_pascal_case(&name))
+        .replace("__name__", &name);
+
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .and_then(|mut file| std::io::Write::write_all(&mut file, code.as_bytes()))
+        .with_context(|| format!("Failed to create {path:?}"))?;
+
+    let pascal = to_pascal_case(&name);
+    println!(
+        "Created {}\n\
+        To add it to the bar:\n\
+        - Declare it in src/control/mod.rs: `mod {name};`\n\
+        - Spawn it in `control_main`: `fac.spawn_with({name}::{pascal}ModuleArgs {{ .. }}, {name}::{name}_module)`\n\
+        - Run its test with `cargo test {name}`",
+        path.display(),
+    );
+    Ok(())
+}
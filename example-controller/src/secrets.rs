@@ -0,0 +1,112 @@
+//! Credential storage for modules that talk to online services.
+//!
+//! A secret named e.g. `github-token` is looked up, in order, from
+//! - the environment variable `SCRATCHBAR_SECRET_GITHUB_TOKEN`,
+//! - `pass show scratchbar/github-token` (first line),
+//! - libsecret, using `secret-tool lookup service scratchbar key github-token`.
+//!
+//! Lookups are cached for the lifetime of the controller, including secrets that were not
+//! found, so that `pass` and `secret-tool` only run once per secret.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock, Mutex},
+};
+
+use anyhow::Context as _;
+
+use crate::utils::ResultExt as _;
+
+const ENV_PREFIX: &str = "SCRATCHBAR_SECRET_";
+const PASS_DIR: &str = "scratchbar";
+const SECRET_TOOL_SERVICE: &str = "scratchbar";
+
+/// A secret value. Its [`Debug`] impl does not show the value, so it is safe to log.
+#[derive(Clone)]
+pub struct Secret(Arc<str>);
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Secret(<redacted>)")
+    }
+}
+
+/// `None` for secrets that were not found.
+static CACHE: LazyLock<Mutex<HashMap<String, Option<Secret>>>> = LazyLock::new(Default::default);
+
+fn cache() -> std::sync::MutexGuard<'static, HashMap<String, Option<Secret>>> {
+    CACHE.lock().unwrap_or_else(|poison| poison.into_inner())
+}
+
+pub async fn lookup(name: &str) -> anyhow::Result<Secret> {
+    let cached = cache().get(name).cloned();
+    let secret = match cached {
+        Some(secret) => secret,
+        None => {
+            let secret = match lookup_env(name) {
+                Some(secret) => Some(secret),
+                None => match lookup_pass(name).await {
+                    Some(secret) => Some(secret),
+                    None => lookup_secret_tool(name).await,
+                },
+            }
+            .map(|it| Secret(it.into()));
+            cache().insert(name.into(), secret.clone());
+            secret
+        }
+    };
+    secret.with_context(|| format!("Secret {name:?} not found"))
+}
+
+fn lookup_env(name: &str) -> Option<String> {
+    let var = format!("{ENV_PREFIX}{}", name.to_uppercase().replace('-', "_"));
+    std::env::var(var).ok().filter(|it| !it.is_empty())
+}
+
+async fn lookup_pass(name: &str) -> Option<String> {
+    let out = run_quiet(
+        tokio::process::Command::new("pass")
+            .arg("show")
+            .arg(format!("{PASS_DIR}/{name}")),
+    )
+    .await?;
+    // pass stores the secret in the first line, followed by optional metadata
+    out.lines().next().map(str::to_owned)
+}
+
+async fn lookup_secret_tool(name: &str) -> Option<String> {
+    run_quiet(tokio::process::Command::new("secret-tool").args([
+        "lookup",
+        "service",
+        SECRET_TOOL_SERVICE,
+        "key",
+        name,
+    ]))
+    .await
+}
+
+/// Runs a lookup command, returning its stdout if it succeeded.
+///
+/// Failures are expected if the secret is stored elsewhere, so they are not logged as errors.
+async fn run_quiet(cmd: &mut tokio::process::Command) -> Option<String> {
+    let out = cmd
+        .stdin(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .with_context(|| format!("Failed to run {cmd:?}"))
+        .ok_or_debug()?;
+    if !out.status.success() {
+        log::debug!("{cmd:?} exited with status {}", out.status);
+        return None;
+    }
+    let out = String::from_utf8(out.stdout)
+        .context("Secret is not valid UTF-8")
+        .ok_or_log()?;
+    let out = out.trim_end_matches('\n');
+    (!out.is_empty()).then(|| out.to_owned())
+}
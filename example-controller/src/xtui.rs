@@ -2,6 +2,7 @@ use std::fmt;
 
 use scratchbar::tui;
 
+pub mod icons;
 pub mod text;
 
 #[derive(Clone, Debug)]
@@ -44,34 +45,64 @@ impl StackBuilder {
     }
 }
 
+/// The largest number of pixels per cell that images are sent with. The host downscales images
+/// to the actual size of the cells anyway, but doing it here means less data is sent to it.
+pub const MAX_CELL_PIXELS: u32 = 48;
+
+/// Shrinks the image so that it is at most `fill_len` cells of [`MAX_CELL_PIXELS`] long along
+/// `fill_axis`, keeping its aspect ratio. Smaller images are returned unchanged.
+pub fn downscale_img(
+    img: image::RgbaImage,
+    fill_axis: tui::Axis,
+    fill_len: u16,
+) -> image::RgbaImage {
+    let (width, height) = img.dimensions();
+    let max = u32::from(fill_len) * MAX_CELL_PIXELS;
+    let len = match fill_axis {
+        tui::Axis::X => width,
+        tui::Axis::Y => height,
+    };
+    if len <= max || max == 0 {
+        return img;
+    }
+    let scale = |it: u32| (u64::from(it) * u64::from(max) / u64::from(len)).max(1) as u32;
+    image::imageops::resize(
+        &img,
+        scale(width),
+        scale(height),
+        image::imageops::FilterType::Triangle,
+    )
+}
+
+/// Reduces each color channel to its `bits` most significant bits, which is barely visible on
+/// small icons. Pixels that are almost transparent become fully transparent.
+///
+/// Since images are identified by their content, this lets icons that only differ slightly,
+/// e.g. the frames of a slowly changing animation, share one image in the terminal.
+pub fn quantize_img(img: &mut image::RgbaImage, bits: u8) {
+    let mask = !(u8::MAX.checked_shr(bits.into()).unwrap_or(0));
+    for image::Rgba(pixel) in img.pixels_mut() {
+        for channel in pixel.iter_mut() {
+            *channel &= mask;
+        }
+        if pixel[3] == 0 {
+            *pixel = [0; 4];
+        }
+    }
+}
+
+/// Displays the image `fill_len` cells long along `fill_axis`, downscaling it first with
+/// [`downscale_img`].
 pub fn rgba_img_fill_axis(img: image::RgbaImage, fill_axis: tui::Axis, fill_len: u16) -> tui::Elem {
-    // https://sw.kovidgoyal.net/kitty/graphics-protocol/#control-data-reference
-    // - \x1b_G...\x1b\\: kitty graphics apc
-    // - a=T: Transfer and display
-    // - f=32: 32-bit RGBA
-    // - C=1: Do not move the cursor behind the image after drawing. If the image is on the
-    //   last line, the first line would move to scrollback (effectively a clear if there is
-    //   only one line, like in the bar).
-    // - s and v specify the image's dimensions
-    tui::Elem::raw_print(format_args!(
-        "\x1b_Ga=T,f=32,C=1,s={},v={},{}={fill_len};{}\x1b\\",
-        img.width(),
-        img.height(),
-        match fill_axis {
-            tui::Axis::X => "c",
-            tui::Axis::Y => "r",
-        },
-        base64::display::Base64Display::new(
-            img.as_raw(),
-            &base64::engine::general_purpose::STANDARD,
-        )
-    ))
-    .with_min_axis(tui::MinAxis {
-        axis: fill_axis,
-        len: fill_len,
-        aspect_width: img.width(),
-        aspect_height: img.height(),
-    })
+    let img = downscale_img(img, fill_axis, fill_len);
+    let (width, height) = img.dimensions();
+    match tui::Image::from_rgba(width, height, img.into_raw()) {
+        Some(img) => tui::Elem::image(img, fill_axis, fill_len),
+        None => {
+            log::error!("Cannot display empty image");
+            tui::Elem::empty()
+        }
+    }
 }
 
 #[derive(Debug, Clone, Default)]
@@ -0,0 +1,295 @@
+//! Lookup of freedesktop icons by name, following the icon theme specification:
+//! https://specifications.freedesktop.org/icon-theme-spec/latest/
+//!
+//! Lookups read the file system, so they block. Results are cached.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock, Mutex},
+};
+
+use anyhow::Context as _;
+use scratchbar::tui;
+
+use crate::{utils::ResultExt as _, xtui};
+
+/// The size in pixels that icons are looked up at. Icons are scaled to the cells they are
+/// displayed in, so this only has to be large enough to look sharp.
+pub const DEFAULT_ICON_SIZE: u32 = 32;
+
+const FALLBACK_THEME: &str = "hicolor";
+const EXTENSIONS: [&str; 2] = ["png", "svg"];
+
+/// Loads the icon `name` from the user's icon theme and displays it `len` cells long along
+/// `axis`. Returns `None` if there is no such icon.
+pub fn icon(name: &str, axis: tui::Axis, len: u16) -> Option<tui::Elem> {
+    let path = find_icon(name, DEFAULT_ICON_SIZE)?;
+    let data = std::fs::read(&path)
+        .with_context(|| format!("Failed to read icon {path:?}"))
+        .ok_or_log()?;
+    if path.extension().is_some_and(|it| it == "svg") {
+        return Some(tui::Elem::svg(
+            tui::Svg::from_data(data),
+            tui::ImageSizeMode::Axis { axis, len },
+        ));
+    }
+    let img = image::load_from_memory_with_format(&data, image::ImageFormat::Png)
+        .with_context(|| format!("Icon {path:?} has invalid png data"))
+        .ok_or_log()?;
+    Some(xtui::rgba_img_fill_axis(img.into_rgba8(), axis, len))
+}
+
+/// Finds the file of the icon `name` in the user's icon theme (or the themes it inherits from)
+/// that is closest to `size` pixels.
+pub fn find_icon(name: &str, size: u32) -> Option<PathBuf> {
+    static CACHE: LazyLock<Mutex<HashMap<(String, u32), Option<PathBuf>>>> =
+        LazyLock::new(Default::default);
+    static THEMES: LazyLock<IconThemes> = LazyLock::new(IconThemes::load);
+
+    let key = (name.to_owned(), size);
+    if let Some(path) = CACHE
+        .lock()
+        .unwrap_or_else(|poison| poison.into_inner())
+        .get(&key)
+    {
+        return path.clone();
+    }
+    let path = THEMES.find(name, size);
+    if path.is_none() {
+        log::debug!("Icon {name:?} not found");
+    }
+    CACHE
+        .lock()
+        .unwrap_or_else(|poison| poison.into_inner())
+        .insert(key, path.clone());
+    path
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DirType {
+    Fixed,
+    Scalable,
+    Threshold,
+}
+
+/// A subdirectory of a theme that contains icons of one size, see `Directories` in
+/// `index.theme`.
+#[derive(Debug)]
+struct ThemeDir {
+    path: Arc<str>,
+    size: u32,
+    min_size: u32,
+    max_size: u32,
+    threshold: u32,
+    kind: DirType,
+}
+impl ThemeDir {
+    /// How far the icons in this directory are from `size`. Zero if they match.
+    fn distance(&self, size: u32) -> u32 {
+        match self.kind {
+            DirType::Fixed => self.size.abs_diff(size),
+            DirType::Scalable if size < self.min_size => self.min_size - size,
+            DirType::Scalable if size > self.max_size => size - self.max_size,
+            DirType::Scalable => 0,
+            DirType::Threshold if size.abs_diff(self.size) <= self.threshold => 0,
+            DirType::Threshold => self.size.abs_diff(size) - self.threshold,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Theme {
+    /// The directories of the theme in each base directory.
+    roots: Vec<PathBuf>,
+    dirs: Vec<ThemeDir>,
+    inherits: Vec<String>,
+}
+
+#[derive(Debug)]
+struct IconThemes {
+    /// The user's theme, followed by the themes it inherits from and [`FALLBACK_THEME`].
+    chain: Vec<Theme>,
+    /// Directories with unthemed icons.
+    pixmaps: Vec<PathBuf>,
+}
+impl IconThemes {
+    fn load() -> Self {
+        let base_dirs = base_dirs();
+
+        let mut pending = vec![user_theme_name().unwrap_or_else(|| FALLBACK_THEME.into())];
+        let mut loaded = Vec::<String>::new();
+        let mut chain = Vec::new();
+        while let Some(name) = pending.pop() {
+            if loaded.contains(&name) {
+                continue;
+            }
+            if let Some(theme) = Theme::load(&name, &base_dirs) {
+                // Inherited themes are searched in order, before the themes they inherit
+                pending.extend(theme.inherits.iter().rev().cloned());
+                chain.push(theme);
+            }
+            loaded.push(name);
+            if pending.is_empty() && !loaded.iter().any(|it| it == FALLBACK_THEME) {
+                pending.push(FALLBACK_THEME.into());
+            }
+        }
+        log::debug!("Loaded icon themes {loaded:?}");
+
+        Self {
+            chain,
+            pixmaps: vec!["/usr/share/pixmaps".into()],
+        }
+    }
+
+    fn find(&self, name: &str, size: u32) -> Option<PathBuf> {
+        self.chain
+            .iter()
+            .find_map(|theme| theme.find(name, size))
+            .or_else(|| find_in(&self.pixmaps, name))
+    }
+}
+
+impl Theme {
+    fn load(name: &str, base_dirs: &[PathBuf]) -> Option<Self> {
+        let roots: Vec<_> = base_dirs
+            .iter()
+            .map(|base| base.join(name))
+            .filter(|it| it.is_dir())
+            .collect();
+        let index = roots
+            .iter()
+            .find_map(|root| std::fs::read_to_string(root.join("index.theme")).ok())?;
+        let index = parse_ini(&index);
+
+        let main = index.get("Icon Theme")?;
+        let list = |key: &str| {
+            main.get(key)
+                .into_iter()
+                .flat_map(|it| it.split(','))
+                .map(str::trim)
+                .filter(|it| !it.is_empty())
+        };
+        let dirs = list("Directories")
+            .chain(list("ScaledDirectories"))
+            .filter_map(|path| {
+                let section = index.get(path)?;
+                let num = |key: &str| section.get(key)?.trim().parse::<u32>().ok();
+                let size = num("Size")?;
+                Some(ThemeDir {
+                    path: path.into(),
+                    size,
+                    min_size: num("MinSize").unwrap_or(size),
+                    max_size: num("MaxSize").unwrap_or(size),
+                    threshold: num("Threshold").unwrap_or(2),
+                    kind: match section.get("Type").map(|it| it.trim()) {
+                        Some("Fixed") => DirType::Fixed,
+                        Some("Scalable") => DirType::Scalable,
+                        _ => DirType::Threshold,
+                    },
+                })
+            })
+            .collect();
+        let inherits = list("Inherits").map(str::to_owned).collect();
+
+        Some(Self {
+            roots,
+            dirs,
+            inherits,
+        })
+    }
+
+    fn find(&self, name: &str, size: u32) -> Option<PathBuf> {
+        let mut best = None::<(u32, PathBuf)>;
+        for dir in &self.dirs {
+            let distance = dir.distance(size);
+            if best.as_ref().is_some_and(|(it, _)| *it <= distance) {
+                continue;
+            }
+            let dirs = self.roots.iter().map(|root| root.join(&*dir.path));
+            if let Some(path) = find_in(dirs, name) {
+                if distance == 0 {
+                    return Some(path);
+                }
+                best = Some((distance, path));
+            }
+        }
+        best.map(|(_, path)| path)
+    }
+}
+
+fn find_in(dirs: impl IntoIterator<Item: AsRef<Path>>, name: &str) -> Option<PathBuf> {
+    dirs.into_iter().find_map(|dir| {
+        EXTENSIONS
+            .iter()
+            .map(|ext| dir.as_ref().join(format!("{name}.{ext}")))
+            .find(|path| path.is_file())
+    })
+}
+
+fn env_path(var: &str) -> Option<PathBuf> {
+    std::env::var_os(var)
+        .filter(|it| !it.is_empty())
+        .map(PathBuf::from)
+}
+
+/// The directories that contain icon themes, in order of precedence.
+fn base_dirs() -> Vec<PathBuf> {
+    let home = env_path("HOME");
+    let data_home = env_path("XDG_DATA_HOME").or_else(|| Some(home.as_ref()?.join(".local/share")));
+    let data_dirs = std::env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|it| !it.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".into());
+
+    home.map(|it| it.join(".icons"))
+        .into_iter()
+        .chain(data_home.map(|it| it.join("icons")))
+        .chain(
+            data_dirs
+                .split(':')
+                .filter(|it| !it.is_empty())
+                .map(|it| Path::new(it).join("icons")),
+        )
+        .collect()
+}
+
+/// The icon theme configured for GTK applications, which most desktops keep in sync with their
+/// own setting.
+fn user_theme_name() -> Option<String> {
+    let config_home =
+        env_path("XDG_CONFIG_HOME").or_else(|| Some(env_path("HOME")?.join(".config")))?;
+    ["gtk-4.0", "gtk-3.0"].iter().find_map(|gtk| {
+        let settings = std::fs::read_to_string(config_home.join(gtk).join("settings.ini")).ok()?;
+        let name = parse_ini(&settings)
+            .get("Settings")?
+            .get("gtk-icon-theme-name")?
+            .trim()
+            .trim_matches('"')
+            .to_owned();
+        (!name.is_empty()).then_some(name)
+    })
+}
+
+/// Parses the sections of a desktop entry style ini file.
+fn parse_ini(content: &str) -> HashMap<&str, HashMap<&str, &str>> {
+    let mut sections = HashMap::<_, HashMap<_, _>>::new();
+    let mut cur = None;
+    for line in content.lines().map(str::trim) {
+        if line.starts_with('#') || line.is_empty() {
+            continue;
+        }
+        if let Some(section) = line.strip_prefix('[').and_then(|it| it.strip_suffix(']')) {
+            cur = Some(section);
+            sections.entry(section).or_default();
+        } else if let Some(section) = cur
+            && let Some((key, value)) = line.split_once('=')
+        {
+            sections
+                .entry(section)
+                .or_default()
+                .insert(key.trim(), value.trim());
+        }
+    }
+    sections
+}
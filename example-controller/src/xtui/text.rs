@@ -213,7 +213,7 @@ struct EscapeSafeWriter<W> {
 impl<W: BorrowMut<String>> fmt::Write for EscapeSafeWriter<W> {
     fn write_str(&mut self, mut s: &str) -> fmt::Result {
         let inner = self.inner.borrow_mut();
-        while let Some((good, rest)) = s.split_once(|c: char| c.is_ascii_control()) {
+        while let Some((good, rest)) = s.split_once(char::is_control) {
             s = rest;
             inner.push_str(good);
         }
@@ -222,7 +222,7 @@ impl<W: BorrowMut<String>> fmt::Write for EscapeSafeWriter<W> {
     }
 
     fn write_char(&mut self, c: char) -> fmt::Result {
-        if !c.is_ascii_control() {
+        if !c.is_control() {
             self.inner.borrow_mut().push(c);
         }
         Ok(())
@@ -373,7 +373,9 @@ where
 impl<O: Borrow<TextOpts>, W: fmt::Display + BorrowMut<String>> LineFormatter<W, O> {
     fn render(self) -> tui::Elem {
         let (text, _, size) = self.finish();
-        tui::Elem::raw_print(text).with_min_size(size)
+        // The text sizing escape sequences would be removed by `raw_print`. The text inside
+        // them went through `EscapeSafeWriter`.
+        tui::Elem::trusted_raw_print(text).with_min_size(size)
     }
 }
 
@@ -0,0 +1,107 @@
+//! The command line of the `scratchbar` binary.
+
+use std::{ffi::OsString, path::PathBuf, sync::Arc};
+
+/// A bar made of kitty panels, drawn by a controller program.
+#[derive(Debug, clap::Parser)]
+#[command(name = "scratchbar", version)]
+pub(crate) struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub(crate) enum Command {
+    /// Start the bar and the controller that draws it
+    Run(RunArgs),
+    /// Control the running host through its control socket
+    #[command(subcommand)]
+    Msg(super::host::MsgArgs),
+    /// Replay a session recorded with `scratchbar run --record` without showing the panels,
+    /// then print the panels that are visible at the end
+    Replay(ReplayArgs),
+    /// Check that the programs and monitors that the host needs are available
+    CheckConfig(HostArgs),
+    /// The program in the terminal of a panel, started by the host
+    #[command(hide = true)]
+    Inst,
+}
+
+#[derive(Debug, clap::Args)]
+pub(crate) struct RunArgs {
+    #[command(flatten)]
+    pub host: HostArgs,
+    /// Record the updates and events of the controller connection to this file, for
+    /// `scratchbar replay`
+    #[arg(long, value_name = "FILE")]
+    pub record: Option<PathBuf>,
+    /// The controller program. If `SCRATCHBAR_CONNECT` is set, it can be omitted to wait for a
+    /// controller that is started separately.
+    pub controller: Option<OsString>,
+    /// The arguments of the controller
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    pub args: Vec<OsString>,
+}
+
+#[derive(Debug, clap::Args)]
+pub(crate) struct ReplayArgs {
+    /// The recording
+    pub file: PathBuf,
+    /// How many times faster than recorded to replay. 0 replays without any delays.
+    #[arg(long, default_value_t = 1.0)]
+    pub speed: f64,
+    /// The edge of the monitors that the bars were shown at
+    #[arg(long, value_enum, default_value_t)]
+    pub edge: BarEdge,
+    /// The log level of the host, until the recorded controller changes it
+    #[arg(long, value_name = "LEVEL")]
+    pub log_level: Option<log::LevelFilter>,
+}
+
+/// Options of the host that are not up to the controller.
+#[derive(Debug, Clone, Default, clap::Args)]
+pub(crate) struct HostArgs {
+    /// The log level of the host and its panels, until the controller changes it
+    #[arg(long, value_name = "LEVEL")]
+    pub log_level: Option<log::LevelFilter>,
+    /// Only show bars on this monitor. Can be given multiple times. Defaults to all monitors.
+    #[arg(long = "monitor", value_name = "NAME")]
+    pub monitors: Vec<Arc<str>>,
+    /// The edge of the monitors that the bars are shown at
+    #[arg(long, value_enum, default_value_t)]
+    pub edge: BarEdge,
+    /// The terminal that shows the panels. Panels in other terminals than kitty cannot be
+    /// hidden or moved, so their placement is left to the compositor. `layer-shell` draws
+    /// the panels without a terminal.
+    #[arg(long, value_enum, default_value_t)]
+    pub terminal: Terminal,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum Terminal {
+    /// The first of kitty, wezterm and foot that is installed
+    #[default]
+    Auto,
+    Kitty,
+    Wezterm,
+    Foot,
+    /// Draw the panels as wlr-layer-shell surfaces, without a terminal
+    #[cfg(feature = "layer-shell")]
+    LayerShell,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum BarEdge {
+    #[default]
+    Top,
+    Bottom,
+}
+impl BarEdge {
+    /// The value of `kitten panel --edge`.
+    pub fn kitty_edge(self) -> &'static str {
+        match self {
+            Self::Top => "top",
+            Self::Bottom => "bottom",
+        }
+    }
+}
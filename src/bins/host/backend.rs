@@ -0,0 +1,348 @@
+//! The programs that show the panels, see [`PanelBackend`] and `docs/panel-backends.md`.
+
+use std::{ffi::OsString, sync::Arc, time::Duration};
+
+use futures::future::BoxFuture;
+use tempfile::TempDir;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_util::{sync::CancellationToken, time::FutureExt as _};
+
+use crate::{
+    bins::{
+        cli::{BarEdge, Terminal},
+        inst::{TermEvent, TermUpdate},
+    },
+    host,
+};
+
+/// Whether a panel takes the keyboard focus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum FocusPolicy {
+    NotAllowed,
+    /// When it is clicked.
+    OnDemand,
+    /// While it is shown.
+    Exclusive,
+}
+
+/// What a panel is for and where it is shown. How this is implemented is up to the backend.
+#[derive(Debug, Clone)]
+pub(super) struct PanelSpec {
+    /// Names the panel in logs, e.g. `BAR@DP-1`.
+    pub log_name: String,
+    pub kind: host::TermKind,
+    pub monitor: Arc<str>,
+    /// Lets compositor rules target the panel.
+    pub app_id: Arc<str>,
+    pub edge: BarEdge,
+    /// Overlays are shown above the other windows without taking space from them, unlike the
+    /// bar. They are positioned with [`PanelGeometry`].
+    pub overlay: bool,
+    pub focus: FocusPolicy,
+    /// The systemd slice for the panel's processes, see
+    /// [`crate::host::HostConnectOpts::systemd_scopes`].
+    pub slice: Option<Arc<str>>,
+}
+
+/// The position and size of an overlay panel. Margins are in logical pixels from the edges of
+/// the monitor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct PanelGeometry {
+    pub margin_left: u32,
+    pub margin_right: u32,
+    /// `None` keeps the panel at its edge.
+    pub margin_top: Option<u32>,
+    pub lines: u16,
+}
+
+/// A running panel.
+pub(super) struct Panel {
+    pub upd_tx: tokio::sync::mpsc::Sender<TermUpdate>,
+    /// Cancelled when the panel exits.
+    pub cancel: CancellationToken,
+}
+
+/// What the terminal of a backend's panels supports.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct PanelCapabilities {
+    /// Kitty's graphics protocol, for images and SVGs. Without it, images are left blank.
+    pub images: bool,
+}
+
+/// Starts panels and translates what the host wants from them into [`TermUpdate`]s.
+///
+/// A panel prints the rendered output it receives as [`TermUpdate::Print`], and reports its
+/// size and input as [`TermEvent`]s, starting with [`TermEvent::Sizes`]. Updates from
+/// [`PanelBackend::set_visible`] and [`PanelBackend::set_geometry`] are sent to the panel like
+/// any other update, so that they are replayed to restarted panels.
+pub(super) trait PanelBackend: std::fmt::Debug + Send + Sync + 'static {
+    /// Starts a panel. It stops when `cancel` is cancelled.
+    fn spawn<'a>(
+        &'a self,
+        spec: &'a PanelSpec,
+        ev_tx: UnboundedSender<TermEvent>,
+        cancel: &'a CancellationToken,
+    ) -> BoxFuture<'a, anyhow::Result<Panel>>;
+
+    fn capabilities(&self) -> PanelCapabilities;
+
+    /// `None` if the backend cannot hide panels.
+    fn set_visible(&self, visible: bool) -> Option<TermUpdate>;
+
+    /// Only used for overlays, see [`PanelSpec::overlay`]. `None` if the backend cannot move
+    /// panels.
+    fn set_geometry(&self, geometry: &PanelGeometry) -> Option<TermUpdate>;
+}
+
+/// Resolves [`Terminal::Auto`] to the first of kitty, wezterm and foot that is installed,
+/// falling back to kitty.
+pub(super) fn detect_terminal(terminal: Terminal) -> Terminal {
+    if terminal != Terminal::Auto {
+        return terminal;
+    }
+    let installed = |program: &str| {
+        std::env::var_os("PATH")
+            .is_some_and(|path| std::env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+    };
+    [Terminal::Kitty, Terminal::Wezterm, Terminal::Foot]
+        .into_iter()
+        .find(|it| {
+            terminal_programs(*it)
+                .iter()
+                .all(|program| installed(program))
+        })
+        .unwrap_or(Terminal::Kitty)
+}
+
+/// The programs that the backend for `terminal` runs.
+pub(super) fn terminal_programs(terminal: Terminal) -> &'static [&'static str] {
+    match terminal {
+        Terminal::Auto | Terminal::Kitty => &["kitty", "kitten"],
+        Terminal::Wezterm => &["wezterm"],
+        Terminal::Foot => &["foot"],
+        #[cfg(feature = "layer-shell")]
+        Terminal::LayerShell => &[],
+    }
+}
+
+pub(super) fn backend_for(terminal: Terminal) -> Arc<dyn PanelBackend> {
+    match detect_terminal(terminal) {
+        Terminal::Auto | Terminal::Kitty => Arc::new(KittyBackend),
+        Terminal::Wezterm => Arc::new(WindowBackend::Wezterm),
+        Terminal::Foot => Arc::new(WindowBackend::Foot),
+        #[cfg(feature = "layer-shell")]
+        Terminal::LayerShell => Arc::new(super::layer_shell::LayerShellBackend),
+    }
+}
+
+/// Runs each panel in `kitten panel`, with `scratchbar inst` inside of it.
+#[derive(Debug)]
+pub(super) struct KittyBackend;
+
+const NERD_FONT_CONFIG_OVERRIDE: &str = "-o=symbol_map U+e000-U+e00a,U+ea60-U+ebeb,U+e0a0-U+e0c8,U+e0ca,U+e0cc-U+e0d7,U+e200-U+e2a9,U+e300-U+e3e3,U+e5fa-U+e6b7,U+e700-U+e8ef,U+ed00-U+efc1,U+f000-U+f2ff,U+f000-U+f2e0,U+f300-U+f381,U+f400-U+f533,U+f0001-U+f1af0 Symbols Nerd Font Mono";
+
+impl KittyBackend {
+    fn args(spec: &PanelSpec) -> Vec<OsString> {
+        let listen_on = match spec.kind {
+            host::TermKind::Bar => "/tmp/kitty-bar-panel.sock",
+            host::TermKind::Menu => "/tmp/kitty-bar-menu-panel.sock",
+            host::TermKind::Osd => "/tmp/kitty-bar-osd-panel.sock",
+            host::TermKind::Prompt => "/tmp/kitty-bar-prompt-panel.sock",
+        };
+        let focus_policy = match spec.focus {
+            FocusPolicy::NotAllowed => "not-allowed",
+            FocusPolicy::OnDemand => "on-demand",
+            FocusPolicy::Exclusive => "exclusive",
+        };
+        let mut args = vec![
+            NERD_FONT_CONFIG_OVERRIDE.into(),
+            format!("--output-name={}", spec.monitor).into(),
+            format!("--app-id={}", spec.app_id).into(),
+            // Allow remote control
+            "-o=allow_remote_control=socket-only".into(),
+            format!("--listen-on=unix:{listen_on}").into(),
+            // Allow logging to $KITTY_STDIO_FORWARDED
+            "-o=forward_stdio=yes".into(),
+            // Do not use the system's kitty.conf
+            "--config=NONE".into(),
+            "-o=foreground=white".into(),
+            "-o=background=black".into(),
+            format!("--edge={}", spec.edge.kitty_edge()).into(),
+            // disable hiding the mouse
+            "-o=mouse_hide_wait=0".into(),
+            format!("--focus-policy={focus_policy}").into(),
+        ];
+        if spec.overlay {
+            args.extend(
+                [
+                    "-o=background_opacity=0.85",
+                    // Center within leftover pixels if cell size does not divide window size.
+                    "-o=placement_strategy=center",
+                    // Taken from the args that quick-access-terminal uses, so that tilers do not
+                    // dedicate space to the panel.
+                    "--exclusive-zone=0",
+                    "--override-exclusive-zone",
+                    "--layer=overlay",
+                    // Since we control resizes from the program and not from
+                    // a somewhat continuous drag-resize, debouncing between
+                    // resize and reloads is completely inappropriate and
+                    // just results in a larger delay between resize and
+                    // the old content being replaced with the new one.
+                    "-o=resize_debounce_time=0 0",
+                    // TODO: Mess with repaint_delay, input_delay
+                ]
+                .map(OsString::from),
+            );
+        }
+        args
+    }
+}
+
+impl PanelBackend for KittyBackend {
+    fn spawn<'a>(
+        &'a self,
+        spec: &'a PanelSpec,
+        ev_tx: UnboundedSender<TermEvent>,
+        cancel: &'a CancellationToken,
+    ) -> BoxFuture<'a, anyhow::Result<Panel>> {
+        let args = ["panel".into()].into_iter().chain(Self::args(spec));
+        Box::pin(start_panel(spec, "kitten", args, ev_tx, cancel))
+    }
+
+    fn capabilities(&self) -> PanelCapabilities {
+        PanelCapabilities { images: true }
+    }
+
+    fn set_visible(&self, visible: bool) -> Option<TermUpdate> {
+        let action = if visible { "show" } else { "hide" };
+        Some(TermUpdate::RemoteControl(vec![
+            "resize-os-window".into(),
+            format!("--action={action}").into(),
+        ]))
+    }
+
+    fn set_geometry(&self, geometry: &PanelGeometry) -> Option<TermUpdate> {
+        let &PanelGeometry {
+            margin_left,
+            margin_right,
+            margin_top,
+            lines,
+        } = geometry;
+        let mut args = vec![
+            "resize-os-window".into(),
+            "--incremental".into(),
+            "--action=os-panel".into(),
+            format!("margin-left={margin_left}").into(),
+            format!("margin-right={margin_right}").into(),
+        ];
+        if let Some(margin_top) = margin_top {
+            args.push(format!("margin-top={margin_top}").into());
+        }
+        args.push(format!("lines={lines}").into());
+        Some(TermUpdate::RemoteControl(args))
+    }
+}
+
+/// Runs each panel in a regular window of a terminal without kitty's panel mode. This is a
+/// degraded mode: Where the windows go is up to the compositor's rules for
+/// [`PanelSpec::app_id`], and panels are never hidden or moved. See `docs/panel-backends.md`.
+#[derive(Debug, Clone, Copy)]
+pub(super) enum WindowBackend {
+    /// Does not support kitty's graphics protocol.
+    Foot,
+    Wezterm,
+}
+
+impl WindowBackend {
+    fn program(self) -> &'static str {
+        match self {
+            Self::Foot => "foot",
+            Self::Wezterm => "wezterm",
+        }
+    }
+
+    /// The arguments up to the command that runs in the terminal.
+    fn args(self, spec: &PanelSpec) -> Vec<OsString> {
+        match self {
+            Self::Foot => vec![
+                format!("--app-id={}", spec.app_id).into(),
+                "--override=colors.foreground=ffffff".into(),
+                "--override=colors.background=000000".into(),
+            ],
+            Self::Wezterm => vec![
+                "start".into(),
+                "--always-new-process".into(),
+                "--class".into(),
+                (*spec.app_id).into(),
+                "--".into(),
+            ],
+        }
+    }
+}
+
+impl PanelBackend for WindowBackend {
+    fn spawn<'a>(
+        &'a self,
+        spec: &'a PanelSpec,
+        ev_tx: UnboundedSender<TermEvent>,
+        cancel: &'a CancellationToken,
+    ) -> BoxFuture<'a, anyhow::Result<Panel>> {
+        Box::pin(start_panel(
+            spec,
+            self.program(),
+            self.args(spec),
+            ev_tx,
+            cancel,
+        ))
+    }
+
+    fn capabilities(&self) -> PanelCapabilities {
+        PanelCapabilities {
+            images: matches!(self, Self::Wezterm),
+        }
+    }
+
+    fn set_visible(&self, _: bool) -> Option<TermUpdate> {
+        None
+    }
+
+    fn set_geometry(&self, _: &PanelGeometry) -> Option<TermUpdate> {
+        None
+    }
+}
+
+#[tracing::instrument(skip_all, fields(panel = spec.log_name))]
+async fn start_panel(
+    spec: &PanelSpec,
+    terminal: &str,
+    terminal_args: impl IntoIterator<Item = OsString>,
+    term_ev_tx: UnboundedSender<TermEvent>,
+    cancel: &CancellationToken,
+) -> anyhow::Result<Panel> {
+    let (upd_tx, mut upd_rx) =
+        tokio::sync::mpsc::channel(super::monitor_inst::PANEL_QUEUE_CAPACITY);
+    let panel_cancel = cancel.child_token();
+
+    let tmpdir = tokio::task::spawn_blocking(TempDir::new).await??;
+    let res = crate::bins::inst::start_generic_panel(
+        &tmpdir.path().join("term-socket.sock"),
+        &spec.log_name,
+        futures::stream::poll_fn(move |cx| upd_rx.poll_recv(cx)),
+        terminal,
+        terminal_args,
+        spec.slice.as_deref(),
+        term_ev_tx,
+        panel_cancel.clone(),
+    )
+    .timeout(Duration::from_secs(10))
+    .await;
+
+    // We have connected to the socket, there is no need to keep the file around.
+    tokio::task::spawn_blocking(move || drop(tmpdir));
+
+    () = res??;
+    Ok(Panel {
+        upd_tx,
+        cancel: panel_cancel,
+    })
+}
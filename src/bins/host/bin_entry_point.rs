@@ -1,25 +1,28 @@
-use std::process::ExitCode;
+use std::{process::ExitCode, sync::Arc, time::Duration};
 
 use anyhow::Context as _;
+use futures::StreamExt as _;
 use tokio_util::time::FutureExt as _;
 
-use crate::{ctrl_ipc, utils::ResultExt as _};
+use super::HostExit;
+use crate::{ctrl_ipc, transport, utils::ResultExt as _};
+
+pub(super) fn host_main_inner(args: crate::bins::cli::RunArgs) -> Option<ExitCode> {
+    let crate::bins::cli::RunArgs {
+        host: host_args,
+        record,
+        controller,
+        args: ctrl_args,
+    } = args;
 
-pub(super) fn host_main_inner() -> Option<ExitCode> {
     crate::logging::init_logger("HOST".into());
+    if let Some(level) = host_args.log_level {
+        crate::logging::set_log_level(level);
+    }
 
     let (exit_tx, mut exit_rx) = tokio::sync::mpsc::unbounded_channel();
 
-    {
-        let exit_tx_clone = exit_tx.clone();
-
-        let hook = std::panic::take_hook();
-        std::panic::set_hook(Box::new(move |info| {
-            hook(info);
-            log::error!("{info}");
-            exit_tx_clone.send(ExitCode::FAILURE).ok_or_debug();
-        }));
-    }
+    install_panic_hook(exit_tx.clone());
 
     let runtime = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
@@ -29,144 +32,623 @@ pub(super) fn host_main_inner() -> Option<ExitCode> {
 
     let _guard = runtime.enter();
 
-    // FIXME: Proper arg parsing
-    let ctrl_cmd = std::env::args_os()
-        .nth(1)
-        .context("Missing controller command")
-        .ok_or_log()?;
-
-    let (mut ctrl_child, ctrl_socket) = {
-        let socket_dir = tempfile::TempDir::new().ok_or_log()?;
-        let sock_path = socket_dir.path().join("host.sock");
-        let socket = std::os::unix::net::UnixListener::bind(&sock_path).ok_or_log()?;
+    // Also while waiting for the controller, so that the socket file is removed
+    spawn_signal_handlers(&runtime, &exit_tx);
 
-        let child = tokio::process::Command::new(ctrl_cmd)
-            .kill_on_drop(true)
-            .args(std::env::args_os().skip(2))
-            .env(ctrl_ipc::HOST_SOCK_PATH_VAR, sock_path)
-            .spawn()
-            .ok_or_log()?;
+    let connect_addr = transport::ConnectAddr::from_env().ok_or_log()?;
 
-        let (conn, _) = socket.accept().ok_or_log()?;
+    if controller.is_none() && connect_addr.is_none() {
+        log::error!(
+            "Missing controller command, which is only optional if {} is set",
+            transport::CONNECT_VAR
+        );
+        return None;
+    }
 
-        (child, conn)
+    let _socket_dir;
+    let addr = match connect_addr {
+        Some(addr) => addr,
+        None => {
+            _socket_dir = tempfile::TempDir::new().ok_or_log()?;
+            transport::ConnectAddr::Unix(_socket_dir.path().join("host.sock"))
+        }
     };
+    let (listener, _socket_file) = addr
+        .listen()
+        .with_context(|| format!("Failed to listen on {addr}"))
+        .ok_or_log()?;
 
     let (update_tx, mut update_rx) = tokio::sync::mpsc::unbounded_channel();
 
-    let exit_tx_clone = exit_tx.clone();
-    let (opts, event_tx) = ctrl_ipc::connect_from_host(
-        ctrl_socket,
-        |init| {
-            let ctrl_ipc::HostCtrlInit { version, opts } = init;
-            check_version(&version)?;
-            Ok((ctrl_ipc::HostInitResponse {}, opts))
-        },
-        move |upd| update_tx.send(upd).ok(),
-        move |res| {
-            exit_tx_clone
-                .send(if res.ok_or_log().is_some() {
-                    ExitCode::SUCCESS
-                } else {
-                    ExitCode::FAILURE
-                })
-                .ok_or_debug();
-        },
-    )
-    .ok_or_log()?;
+    // Events are relayed to whichever controller is currently connected
+    let ctrl_event_tx = Arc::new(std::sync::Mutex::new(None));
+    let launcher = Arc::new(CtrlLauncher {
+        cmd: controller.map(|cmd| (cmd, ctrl_args)),
+        addr,
+        listener,
+        update_tx,
+        ctrl_event_tx: ctrl_event_tx.clone(),
+        connected_tx: tokio::sync::watch::Sender::new(()),
+        shutdown_requested: Default::default(),
+    });
+    let launched = runtime.block_on(async {
+        tokio::select! {
+            launched = launcher.clone().launch() => launched.ok_or(ExitCode::FAILURE),
+            Some(exit) = exit_rx.recv() => Err(exit.code()),
+        }
+    });
+    let (ctrl, opts) = match launched {
+        Ok(launched) => launched,
+        Err(code) => return Some(code),
+    };
 
-    let crate::host::HostConnectOpts {
-        #[expect(deprecated)]
-            __non_exhaustive_struct_update: (),
-    } = opts;
+    let recorder = match record {
+        Some(path) => Some(Arc::new(
+            super::record::Recorder::create(&path, &opts).ok_or_log()?,
+        )),
+        None => None,
+    };
 
+    let restart_policy = opts.restart_controller;
+    let config = Arc::new(super::HostConfig {
+        snapshot_controller: Some(launcher.controller_id().into()),
+        ..host_config(opts, host_args)
+    });
+
+    let (event_tx, event_rx) = std::sync::mpsc::channel::<crate::host::HostEvent>();
     {
-        type SK = tokio::signal::unix::SignalKind;
-
-        for kind in [
-            SK::interrupt(),
-            SK::quit(),
-            SK::alarm(),
-            SK::hangup(),
-            SK::pipe(),
-            SK::terminate(),
-            SK::user_defined1(),
-            SK::user_defined2(),
-        ] {
-            let Some(mut signal) = tokio::signal::unix::signal(kind).ok_or_log() else {
-                continue;
-            };
-            let exit_tx = exit_tx.clone();
-            runtime.spawn(async move {
-                if let Some(()) = signal.recv().await {
-                    let code = kind.as_raw_value().wrapping_add(128);
-                    exit_tx.send(ExitCode::from(code as u8)).ok_or_debug();
+        let ctrl_event_tx = ctrl_event_tx.clone();
+        let recorder = recorder.clone();
+        std::thread::spawn(move || {
+            for ev in event_rx {
+                if let Some(recorder) = &recorder {
+                    recorder.event(&ev);
                 }
-            });
-        }
+                let ctrl_event_tx = ctrl_event_tx.lock().unwrap_or_else(|pe| pe.into_inner());
+                if let Some(tx) = &*ctrl_event_tx {
+                    tx.send(ev).ok_or_debug();
+                }
+            }
+        });
     }
 
-    let exit_tx_clone = exit_tx.clone();
+    let connected_rx = launcher.connected_tx.subscribe();
     runtime.spawn(async move {
-        let code = super::run_host(
-            futures::stream::poll_fn(move |cx| update_rx.poll_recv(cx)),
+        let exit = super::run_host(
+            futures::stream::poll_fn(move |cx| update_rx.poll_recv(cx)).inspect(move |upd| {
+                if let Some(recorder) = &recorder {
+                    recorder.update(upd);
+                }
+            }),
             event_tx,
+            connected_rx,
+            config,
         )
         .await;
 
-        exit_tx_clone.send(code).ok_or_debug();
+        exit_tx.send(exit).ok_or_debug();
     });
 
-    let exit_task = runtime.spawn(async move {
-        let host_code;
-        let ctrl_status;
-        tokio::select! {
-            res = ctrl_child.wait() => {
-                host_code = ExitCode::SUCCESS;
-                ctrl_status = res.ok_or_log();
-            },
-            Some(code) = exit_rx.recv() => {
-                host_code = code;
-                let res = ctrl_child
-                    .wait()
-                    .timeout(std::time::Duration::from_secs(5))
-                    .await
-                    .context("Controller failed to exit on its own")
-                    .ok_or_log();
-
-                if let Some(res) = res {
-                    ctrl_status = res.ok_or_log();
+    let exit_task = runtime.spawn(supervise_ctrl(launcher, ctrl, restart_policy, exit_rx));
+
+    runtime.block_on(async move { exit_task.await.ok_or_log() })
+}
+
+/// Prints which of the host's requirements are met. Options that the controller chooses are
+/// only known once it connects, so they are not checked.
+pub(crate) fn check_config_main(host_args: crate::bins::cli::HostArgs) -> ExitCode {
+    crate::logging::init_logger("CHECK".into());
+    if let Some(level) = host_args.log_level {
+        crate::logging::set_log_level(level);
+    }
+
+    let mut ok = true;
+    let mut report = |res: anyhow::Result<String>| match res {
+        Ok(msg) => println!("ok: {msg}"),
+        Err(err) => {
+            ok = false;
+            println!("error: {err:#}");
+        }
+    };
+
+    report(transport::ConnectAddr::from_env().map(|addr| match addr {
+        Some(addr) => format!("Listening for controllers on {addr}"),
+        None => "Using a private socket for the controller".into(),
+    }));
+    let terminal = super::backend::detect_terminal(host_args.terminal);
+    let capabilities = super::backend::backend_for(terminal).capabilities();
+    report(Ok(format!(
+        "Showing panels in {terminal:?}, with images {}",
+        if capabilities.images {
+            "enabled"
+        } else {
+            "disabled"
+        }
+    )));
+    #[cfg(feature = "layer-shell")]
+    if terminal == crate::bins::cli::Terminal::LayerShell {
+        report(super::layer_shell::check());
+    }
+    for &program in super::backend::terminal_programs(terminal) {
+        report(
+            std::process::Command::new(program)
+                .arg("--version")
+                .stdin(std::process::Stdio::null())
+                .output()
+                .with_context(|| format!("Failed to run {program:?}, which draws the panels"))
+                .and_then(|output| {
+                    anyhow::ensure!(
+                        output.status.success(),
+                        "`{program} --version` exited with {}",
+                        output.status
+                    );
+                    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+                }),
+        );
+    }
+
+    let monitors = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("Failed to start the tokio runtime")
+        .and_then(|runtime| {
+            runtime
+                .block_on(super::monitor_listen::monitor_names())
+                .context("Failed to list the monitors")
+        });
+    match monitors {
+        Ok(monitors) => {
+            report(Ok(format!("Found monitors {monitors:?}")));
+            for name in &host_args.monitors {
+                report(if monitors.contains(name) {
+                    Ok(format!("Showing bars on {name:?}"))
                 } else {
-                    ctrl_status = None;
+                    Err(anyhow::anyhow!("There is no monitor named {name:?}"))
+                });
+            }
+        }
+        Err(err) => report(Err(err)),
+    }
 
-                    if ctrl_child.start_kill().context("Failed to kill controller").ok_or_log().is_some() {
-                       ctrl_child.wait().await.ok_or_log();
-                    }
+    if ok {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+/// Spawns the controller (unless it is remote) and accepts its connection.
+struct CtrlLauncher {
+    cmd: Option<(std::ffi::OsString, Vec<std::ffi::OsString>)>,
+    addr: transport::ConnectAddr,
+    listener: transport::IpcListener,
+    update_tx: tokio::sync::mpsc::UnboundedSender<crate::host::HostUpdate>,
+    /// Where the host's events are relayed to. Cleared while no controller is connected.
+    ctrl_event_tx: Arc<std::sync::Mutex<Option<std::sync::mpsc::Sender<crate::host::HostEvent>>>>,
+    /// Notified when a controller connects, before any of its updates are passed on.
+    connected_tx: tokio::sync::watch::Sender<()>,
+    /// Set as soon as a controller sends [`crate::host::HostUpdate::Shutdown`], so that it is
+    /// not restarted when it exits before the host has handled the update.
+    shutdown_requested: Arc<std::sync::atomic::AtomicBool>,
+}
+struct Ctrl {
+    child: Option<tokio::process::Child>,
+    /// Receives whether the connection stopped without an error.
+    stop_rx: tokio::sync::oneshot::Receiver<bool>,
+}
+impl CtrlLauncher {
+    /// The command line of the controller, or the address that a remote controller connected
+    /// to. See [`super::HostConfig::snapshot_controller`].
+    fn controller_id(&self) -> String {
+        match &self.cmd {
+            Some((cmd, args)) => std::iter::once(cmd)
+                .chain(args)
+                .map(|it| it.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(" "),
+            None => self.addr.to_string(),
+        }
+    }
+
+    async fn launch(self: Arc<Self>) -> Option<(Ctrl, crate::host::HostConnectOpts)> {
+        let child = match &self.cmd {
+            Some((cmd, args)) => {
+                let mut command = tokio::process::Command::new(cmd);
+                command
+                    .kill_on_drop(true)
+                    .args(args)
+                    .env(transport::CONNECT_VAR, self.addr.to_string());
+                for var in super::SERVICE_ENV_VARS {
+                    command.env_remove(var);
                 }
-            },
+                Some(command.spawn().ok_or_log()?)
+            }
+            None => {
+                log::info!("Waiting for a controller to connect to {}", self.addr);
+                None
+            }
         };
-        let ctrl_code = ctrl_status.map_or(ExitCode::FAILURE, |status| {
-            ExitCode::from(status.code().unwrap_or(0) as u8)
+
+        // Not spawn_blocking, since the runtime would wait for a pending accept on shutdown
+        let (res_tx, res_rx) = tokio::sync::oneshot::channel();
+        std::thread::spawn(move || {
+            let (stop_tx, stop_rx) = tokio::sync::oneshot::channel();
+            // Holds back the controller's updates until the host knows it is connected
+            let (connected_tx, connected_rx) = std::sync::mpsc::channel::<()>();
+            let mut connected_rx = Some(connected_rx);
+            let update_tx = self.update_tx.clone();
+            let shutdown_requested = self.shutdown_requested.clone();
+            let res = self
+                .listener
+                .accept()
+                .context("Failed to accept controller connection")
+                .and_then(|socket| {
+                    ctrl_ipc::connect_from_host(
+                        socket,
+                        |init| {
+                            let ctrl_ipc::HostCtrlInit { opts } = init;
+                            Ok((ctrl_ipc::HostInitResponse {}, opts))
+                        },
+                        move |upd| {
+                            if let Some(connected_rx) = connected_rx.take() {
+                                _ = connected_rx.recv();
+                            }
+                            if let crate::host::HostUpdate::Shutdown { .. } = upd {
+                                shutdown_requested
+                                    .store(true, std::sync::atomic::Ordering::Relaxed);
+                            }
+                            update_tx.send(upd).ok()
+                        },
+                        move |res| _ = stop_tx.send(res.ok_or_log().is_some()),
+                    )
+                })
+                .map(|(opts, event_tx)| {
+                    // The connection is closed once the event sender is dropped
+                    *self
+                        .ctrl_event_tx
+                        .lock()
+                        .unwrap_or_else(|pe| pe.into_inner()) = Some(event_tx);
+                    self.connected_tx.send_replace(());
+                    _ = connected_tx.send(());
+                    (opts, stop_rx)
+                });
+            _ = res_tx.send(res);
         });
+        let (opts, stop_rx) = res_rx.await.ok_or_log()?.ok_or_log()?;
 
-        // Prefer the controller's code if it did not exit correctly
-        if ctrl_code != ExitCode::SUCCESS {
-            ctrl_code
-        } else {
-            host_code
+        Some((Ctrl { child, stop_rx }, opts))
+    }
+}
+
+const RESTART_DELAY_MIN: Duration = Duration::from_secs(1);
+const RESTART_DELAY_MAX: Duration = Duration::from_secs(60);
+/// Controllers that stay up for this long reset the restart backoff.
+const RESTART_HEALTHY_AFTER: Duration = Duration::from_secs(60);
+
+async fn supervise_ctrl(
+    launcher: Arc<CtrlLauncher>,
+    mut ctrl: Ctrl,
+    mut restart_policy: crate::host::RestartPolicy,
+    mut exit_rx: tokio::sync::mpsc::UnboundedReceiver<HostExit>,
+) -> ExitCode {
+    let mut restart_delay = RESTART_DELAY_MIN;
+    loop {
+        let started = std::time::Instant::now();
+
+        let ctrl_code = tokio::select! {
+            Some(res) = async { Some(ctrl.child.as_mut()?.wait().await) } => {
+                status_code(res.ok_or_log())
+            }
+            ok = &mut ctrl.stop_rx => {
+                let conn_code = if ok.unwrap_or(false) {
+                    ExitCode::SUCCESS
+                } else {
+                    ExitCode::FAILURE
+                };
+                match &mut ctrl.child {
+                    Some(child) => prefer_ctrl_code(status_code(reap_ctrl(child).await), conn_code),
+                    None => conn_code,
+                }
+            }
+            Some(exit) = exit_rx.recv() => {
+                if let HostExit::Shutdown(_) = exit {
+                    // Closing the connection lets the controller exit on its own
+                    *launcher.ctrl_event_tx.lock().unwrap_or_else(|pe| pe.into_inner()) = None;
+                }
+                let Some(child) = &mut ctrl.child else {
+                    // A remote controller is not ours to wait for
+                    return exit.code();
+                };
+                let ctrl_code = status_code(reap_ctrl(child).await);
+                return match exit {
+                    HostExit::Shutdown(_) => exit.code(),
+                    _ => prefer_ctrl_code(ctrl_code, exit.code()),
+                };
+            }
+        };
+
+        if launcher
+            .shutdown_requested
+            .load(std::sync::atomic::Ordering::Relaxed)
+        {
+            // The controller exited right after requesting the shutdown, which the host is
+            // still carrying out
+            return exit_rx
+                .recv()
+                .await
+                .map_or(ExitCode::FAILURE, HostExit::code);
         }
-    });
 
-    runtime.block_on(async move { exit_task.await.ok_or_log() })
+        let restart = match restart_policy {
+            crate::host::RestartPolicy::Never => false,
+            crate::host::RestartPolicy::OnFailure => ctrl_code != ExitCode::SUCCESS,
+            crate::host::RestartPolicy::Always => true,
+        };
+        if !restart {
+            return ctrl_code;
+        }
+
+        *launcher
+            .ctrl_event_tx
+            .lock()
+            .unwrap_or_else(|pe| pe.into_inner()) = None;
+        if started.elapsed() >= RESTART_HEALTHY_AFTER {
+            restart_delay = RESTART_DELAY_MIN;
+        }
+
+        ctrl = loop {
+            log::warn!("Controller exited, restarting it in {restart_delay:?}");
+            tokio::select! {
+                () = tokio::time::sleep(restart_delay) => {}
+                Some(exit) = exit_rx.recv() => return exit.code(),
+            }
+            restart_delay = (restart_delay * 2).min(RESTART_DELAY_MAX);
+
+            tokio::select! {
+                Some((ctrl, opts)) = launcher.clone().launch() => {
+                    restart_policy = opts.restart_controller;
+                    break ctrl;
+                }
+                Some(exit) = exit_rx.recv() => return exit.code(),
+                else => {}
+            }
+        };
+    }
 }
 
-fn check_version(version: &str) -> anyhow::Result<()> {
-    let this_ver = ctrl_ipc::VERSION;
-    if version == this_ver {
-        Ok(())
+/// Waits for the controller to exit after its connection was closed, killing it if it does not.
+async fn reap_ctrl(child: &mut tokio::process::Child) -> Option<std::process::ExitStatus> {
+    let res = child
+        .wait()
+        .timeout(Duration::from_secs(5))
+        .await
+        .context("Controller failed to exit on its own")
+        .ok_or_log();
+
+    if let Some(res) = res {
+        res.ok_or_log()
     } else {
-        anyhow::bail!(
-            "Cannot run controller built against version {version:?} under version {this_ver:?}"
-        )
+        if child
+            .start_kill()
+            .context("Failed to kill controller")
+            .ok_or_log()
+            .is_some()
+        {
+            child.wait().await.ok_or_log();
+        }
+        None
+    }
+}
+
+fn status_code(status: Option<std::process::ExitStatus>) -> ExitCode {
+    use std::os::unix::process::ExitStatusExt as _;
+
+    status.map_or(ExitCode::FAILURE, |status| {
+        match (status.code(), status.signal()) {
+            (Some(code), _) => ExitCode::from(code as u8),
+            (None, Some(signal)) => HostExit::Signal(signal).code(),
+            (None, None) => ExitCode::FAILURE,
+        }
+    })
+}
+
+/// Prefers the controller's code if it did not exit correctly.
+fn prefer_ctrl_code(ctrl_code: ExitCode, host_code: ExitCode) -> ExitCode {
+    if ctrl_code != ExitCode::SUCCESS {
+        ctrl_code
+    } else {
+        host_code
+    }
+}
+
+fn install_panic_hook(exit_tx: tokio::sync::mpsc::UnboundedSender<HostExit>) {
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        hook(info);
+        log::error!("{info}");
+        exit_tx.send(HostExit::Failed).ok_or_debug();
+    }));
+}
+
+pub(super) fn host_config(
+    opts: crate::host::HostConnectOpts,
+    host_args: crate::bins::cli::HostArgs,
+) -> super::HostConfig {
+    let crate::bins::cli::HostArgs {
+        log_level,
+        monitors,
+        edge,
+        terminal,
+    } = host_args;
+    let crate::host::HostConnectOpts {
+        bar_app_id,
+        menu_app_id,
+        osd_app_id,
+        prompt_app_id,
+        dim_bar_on_menu,
+        restart_controller: _,
+        max_fps,
+        min_interactive_size,
+        systemd_scopes,
+        forward_logs,
+        max_commands,
+        ambiguous_width,
+        // Only used by the controller
+        connect_addr: _,
+        connect_retry: _,
+        connect_timeout: _,
+        handshake_timeout: _,
+        reconnect: _,
+        max_buffered_updates: _,
+        event_buffer: _,
+        event_overflow: _,
+        #[expect(deprecated)]
+            __non_exhaustive_struct_update: (),
+    } = opts;
+    super::HostConfig {
+        bar_app_id: bar_app_id.unwrap_or_else(|| crate::host::DEFAULT_BAR_APP_ID.into()),
+        menu_app_id: menu_app_id.unwrap_or_else(|| crate::host::DEFAULT_MENU_APP_ID.into()),
+        osd_app_id: osd_app_id.unwrap_or_else(|| crate::host::DEFAULT_OSD_APP_ID.into()),
+        prompt_app_id: prompt_app_id.unwrap_or_else(|| crate::host::DEFAULT_PROMPT_APP_ID.into()),
+        dim_bar_on_menu,
+        frame_interval: std::time::Duration::from_secs(1)
+            / max_fps.unwrap_or(crate::host::DEFAULT_MAX_FPS).get().into(),
+        min_interactive_size,
+        systemd_scopes,
+        forward_logs,
+        max_commands: max_commands.unwrap_or(crate::host::DEFAULT_MAX_COMMANDS),
+        ambiguous_width,
+        log_level,
+        monitors,
+        edge,
+        backend: super::backend::backend_for(terminal),
+        fixed_monitors: None,
+        snapshot_controller: None,
+    }
+}
+
+fn spawn_signal_handlers(
+    runtime: &tokio::runtime::Runtime,
+    exit_tx: &tokio::sync::mpsc::UnboundedSender<HostExit>,
+) {
+    type SK = tokio::signal::unix::SignalKind;
+
+    // `SIGHUP` reloads the bar instead, see `run_reload_on_hangup`
+    for kind in [
+        SK::interrupt(),
+        SK::quit(),
+        SK::alarm(),
+        SK::pipe(),
+        SK::terminate(),
+        SK::user_defined1(),
+        SK::user_defined2(),
+    ] {
+        let Some(mut signal) = tokio::signal::unix::signal(kind).ok_or_log() else {
+            continue;
+        };
+        let exit_tx = exit_tx.clone();
+        runtime.spawn(async move {
+            if let Some(()) = signal.recv().await {
+                exit_tx
+                    .send(HostExit::Signal(kind.as_raw_value()))
+                    .ok_or_debug();
+            }
+        });
     }
 }
+
+#[cfg(feature = "embedded")]
+pub(crate) fn embedded_main(
+    opts: crate::host::HostConnectOpts,
+    controller: impl FnOnce(
+        crate::host::HostConnection,
+        std::sync::mpsc::Receiver<crate::host::HostEvent>,
+    ) -> ExitCode
+    + Send
+    + 'static,
+) -> ExitCode {
+    // The panels run the current executable
+    if std::env::args_os().nth(1).as_deref()
+        == Some(std::ffi::OsStr::new(crate::bins::inst::INTERNAL_INST_ARG))
+    {
+        return crate::bins::inst::inst_main();
+    }
+
+    crate::logging::init_logger("HOST".into());
+
+    let (exit_tx, mut exit_rx) = tokio::sync::mpsc::unbounded_channel();
+    install_panic_hook(exit_tx.clone());
+
+    let Some(runtime) = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .context("Failed to start the tokio runtime")
+        .ok_or_log()
+    else {
+        return ExitCode::FAILURE;
+    };
+    let _guard = runtime.enter();
+
+    let (update_tx, update_rx) = std::sync::mpsc::channel();
+    let (event_tx, event_rx) = std::sync::mpsc::channel();
+
+    let (update_fwd_tx, mut update_fwd_rx) = tokio::sync::mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        while let Ok(update) = update_rx.recv() {
+            if update_fwd_tx.send(update).is_err() {
+                break;
+            }
+        }
+    });
+
+    // Responses to queries are taken out of the events, like `host::connect` does
+    let queries = Arc::new(crate::host::PendingQueries::new());
+    let (ctrl_event_tx, ctrl_event_rx) = std::sync::mpsc::channel();
+    {
+        let queries = queries.clone();
+        std::thread::spawn(move || {
+            for ev in event_rx {
+                if let Some(ev) = queries.intercept(ev) {
+                    _ = ctrl_event_tx.send(ev);
+                }
+            }
+            queries.close();
+        });
+    }
+
+    let (ctrl_code_tx, ctrl_code_rx) = tokio::sync::oneshot::channel();
+    std::thread::spawn(move || {
+        let connection = crate::host::HostConnection::new(update_tx, queries);
+        let code = controller(connection, ctrl_event_rx);
+        _ = ctrl_code_tx.send(code);
+    });
+
+    spawn_signal_handlers(&runtime, &exit_tx);
+
+    let config = Arc::new(super::HostConfig {
+        snapshot_controller: std::env::current_exe()
+            .ok_or_log()
+            .map(|exe| exe.to_string_lossy().into()),
+        ..host_config(opts, Default::default())
+    });
+    runtime.spawn(async move {
+        let exit = super::run_host(
+            futures::stream::poll_fn(move |cx| update_fwd_rx.poll_recv(cx)),
+            event_tx,
+            // The embedded controller stays connected for as long as the host runs
+            tokio::sync::watch::Sender::new(()).subscribe(),
+            config,
+        )
+        .await;
+
+        exit_tx.send(exit).ok_or_debug();
+    });
+
+    runtime.block_on(async {
+        tokio::select! {
+            Ok(code) = ctrl_code_rx => code,
+            Some(exit) = exit_rx.recv() => exit.code(),
+            else => ExitCode::FAILURE,
+        }
+    })
+}
@@ -0,0 +1,134 @@
+//! Helper commands of the controller, see [`host::HostUpdate::RunCommand`].
+
+use std::sync::Arc;
+
+use tokio::io::{AsyncRead, AsyncReadExt as _};
+
+use crate::{host, utils::ResultExt as _};
+
+pub(super) async fn run_commands(
+    mut command_rx: tokio::sync::mpsc::UnboundedReceiver<host::RunCommand>,
+    event_tx: std::sync::mpsc::Sender<host::HostEvent>,
+    max_commands: std::num::NonZeroU16,
+) {
+    let permits = Arc::new(tokio::sync::Semaphore::new(max_commands.get().into()));
+    while let Some(run) = command_rx.recv().await {
+        let permits = permits.clone();
+        let event_tx = event_tx.clone();
+        tokio::spawn(async move {
+            let id = run.id.clone();
+            let started = tokio::time::Instant::now();
+            let timeout = run.opts.timeout;
+            let output = tokio::time::timeout(timeout, async {
+                // Never closed
+                let _permit = permits.acquire().await.ok();
+                log::debug!(
+                    "Running command {id:?} after waiting {:?}",
+                    started.elapsed()
+                );
+                run_command(run).await
+            })
+            .await
+            .unwrap_or_else(|_| {
+                log::warn!("Command {id:?} timed out after {timeout:?}");
+                host::CommandOutput {
+                    id,
+                    status: host::CommandStatus::TimedOut,
+                    stdout: String::new(),
+                    stderr: String::new(),
+                }
+            });
+            event_tx
+                .send(host::HostEvent::CommandFinished(output))
+                .ok_or_debug();
+        });
+    }
+}
+
+/// Runs the command to completion. The process is killed if this is cancelled.
+async fn run_command(run: host::RunCommand) -> host::CommandOutput {
+    let host::RunCommand {
+        id,
+        cmd,
+        opts:
+            host::RunCommandOpts {
+                timeout: _,
+                clear_env,
+                env,
+                cwd,
+                #[expect(deprecated)]
+                    __non_exhaustive_struct_update: (),
+            },
+    } = run;
+    let failed = |id, err: String| host::CommandOutput {
+        id,
+        status: host::CommandStatus::Failed(err),
+        stdout: String::new(),
+        stderr: String::new(),
+    };
+
+    let Some((program, args)) = cmd.split_first() else {
+        return failed(id, "The command is empty".into());
+    };
+    let mut command = tokio::process::Command::new(program);
+    command
+        .args(args)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true);
+    if clear_env {
+        command.env_clear();
+    }
+    for var in super::SERVICE_ENV_VARS {
+        command.env_remove(var);
+    }
+    command.envs(env);
+    if let Some(cwd) = cwd {
+        command.current_dir(cwd);
+    }
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(err) => return failed(id, format!("Failed to start {program:?}: {err}")),
+    };
+
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    let (stdout, stderr, status) =
+        tokio::join!(read_capped(stdout), read_capped(stderr), child.wait());
+    let status = match status {
+        Ok(status) => {
+            use std::os::unix::process::ExitStatusExt as _;
+            match (status.code(), status.signal()) {
+                (Some(code), _) => host::CommandStatus::Exited(code),
+                (None, Some(signal)) => host::CommandStatus::Signaled(signal),
+                (None, None) => host::CommandStatus::Failed(status.to_string()),
+            }
+        }
+        Err(err) => host::CommandStatus::Failed(format!("Failed to wait for {program:?}: {err}")),
+    };
+    host::CommandOutput {
+        id,
+        status,
+        stdout,
+        stderr,
+    }
+}
+
+/// Reads up to [`host::COMMAND_OUTPUT_LIMIT`] bytes and discards the rest, so that the command
+/// does not block on a full pipe.
+async fn read_capped(reader: Option<impl AsyncRead + Unpin>) -> String {
+    let Some(mut reader) = reader else {
+        return String::new();
+    };
+    let mut buf = Vec::new();
+    (&mut reader)
+        .take(host::COMMAND_OUTPUT_LIMIT as u64)
+        .read_to_end(&mut buf)
+        .await
+        .ok_or_debug();
+    tokio::io::copy(&mut reader, &mut tokio::io::sink())
+        .await
+        .ok_or_debug();
+    String::from_utf8_lossy(&buf).into_owned()
+}
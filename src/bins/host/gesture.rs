@@ -0,0 +1,71 @@
+//! Recognizes gestures from the mouse events of a bar, see [`crate::host::Gesture`].
+
+use std::time::Duration;
+
+use crate::tui;
+
+/// How far a swipe has to move horizontally, in cells.
+const SWIPE_MIN_CELLS: u32 = 6;
+/// Slower movements are drags, not swipes.
+const SWIPE_MAX_DURATION: Duration = Duration::from_millis(800);
+
+#[derive(Debug, Default)]
+pub(super) struct SwipeRecognizer {
+    /// Where and when the left button was pressed, in pixels.
+    start: Option<(tui::Vec2<u16>, tokio::time::Instant)>,
+}
+impl SwipeRecognizer {
+    /// Feeds a mouse event of the bar after the bar's layout has handled it. Returns the
+    /// direction of a completed swipe.
+    pub fn mouse_event(
+        &mut self,
+        event: &crossterm::event::MouseEvent,
+        layout: &tui::RenderedLayout,
+        font_size: tui::Vec2<u16>,
+    ) -> Option<tui::Direction> {
+        type MK = crossterm::event::MouseEventKind;
+        type MB = crossterm::event::MouseButton;
+
+        let pos = tui::Vec2 {
+            x: event.column,
+            y: event.row,
+        };
+        match event.kind {
+            // Dragging a slider is not a swipe
+            MK::Down(MB::Left) if !layout.is_dragging() => {
+                self.start = Some((pos, tokio::time::Instant::now()));
+                None
+            }
+            MK::Up(MB::Left) => {
+                let (start, at) = self.start.take()?;
+                if at.elapsed() > SWIPE_MAX_DURATION {
+                    return None;
+                }
+                let dx = i32::from(pos.x) - i32::from(start.x);
+                let dy = i32::from(pos.y) - i32::from(start.y);
+                // Mostly horizontal
+                if dx.unsigned_abs() < SWIPE_MIN_CELLS * u32::from(font_size.x)
+                    || dx.unsigned_abs() < 2 * dy.unsigned_abs()
+                {
+                    return None;
+                }
+                Some(if dx < 0 {
+                    tui::Direction::Left
+                } else {
+                    tui::Direction::Right
+                })
+            }
+            MK::Down(_) | MK::KittyLeaveWindow => {
+                self.start = None;
+                None
+            }
+            MK::Up(_)
+            | MK::Drag(_)
+            | MK::Moved
+            | MK::ScrollDown
+            | MK::ScrollUp
+            | MK::ScrollLeft
+            | MK::ScrollRight => None,
+        }
+    }
+}
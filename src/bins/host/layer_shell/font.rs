@@ -0,0 +1,382 @@
+//! Draws a [`tui::Grid`] with a built-in font rasterizer, see [`Fonts`].
+//!
+//! Fonts are looked up with fontconfig's `fc-match`, like kitty's defaults: `monospace` at 11pt,
+//! with fallback fonts for characters that it does not have (e.g. Nerd Font symbols). Color
+//! fonts (emoji) are not supported.
+
+use std::{
+    collections::HashMap, ffi::OsString, os::unix::ffi::OsStringExt as _, path::PathBuf,
+    process::Command,
+};
+
+use anyhow::Context as _;
+
+use crate::{tui, utils::ResultExt as _};
+
+const FAMILY: &str = "monospace";
+const SIZE_PT: f32 = 11.0;
+/// The opacity of the default background of overlays, like `background_opacity` of the kitty
+/// backend.
+const OVERLAY_OPACITY: u8 = 217;
+
+/// The fontconfig styles of [`Variant`]s.
+const VARIANT_STYLES: [&str; 4] = [
+    "",
+    ":weight=bold",
+    ":slant=italic",
+    ":weight=bold:slant=italic",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Variant {
+    bold: bool,
+    italic: bool,
+}
+impl Variant {
+    fn style(self) -> &'static str {
+        VARIANT_STYLES[usize::from(self.bold) | usize::from(self.italic) << 1]
+    }
+}
+
+struct Glyph {
+    metrics: fontdue::Metrics,
+    coverage: Vec<u8>,
+}
+
+/// The fonts of a panel at its current scale, with caches of what was rasterized so far.
+pub(super) struct Fonts {
+    px: f32,
+    cell: tui::Vec2<u16>,
+    /// The distance from the top of a cell to the baseline.
+    ascent: i32,
+    faces: Vec<fontdue::Font>,
+    face_paths: HashMap<PathBuf, usize>,
+    /// The face of each variant, if it was looked up already.
+    variants: HashMap<Variant, usize>,
+    /// The face that has a glyph for a character, `None` if no font has one.
+    fallbacks: HashMap<(Variant, char), Option<usize>>,
+    glyphs: HashMap<(usize, char), Glyph>,
+}
+
+impl Fonts {
+    /// Loads the regular font for an output with the given scale.
+    pub(super) fn new(scale: f64) -> anyhow::Result<Self> {
+        let mut fonts = Self {
+            // Points are 1/72 inch, at 96 DPI per logical pixel
+            px: (f64::from(SIZE_PT) * 96.0 / 72.0 * scale) as f32,
+            cell: tui::Vec2 { x: 1, y: 1 },
+            ascent: 0,
+            faces: Vec::new(),
+            face_paths: HashMap::new(),
+            variants: HashMap::new(),
+            fallbacks: HashMap::new(),
+            glyphs: HashMap::new(),
+        };
+        let regular = Variant {
+            bold: false,
+            italic: false,
+        };
+        let face = fonts
+            .variant_face(regular)
+            .with_context(|| format!("Failed to load the font {FAMILY:?}"))?;
+        let face = &fonts.faces[face];
+        let line = face
+            .horizontal_line_metrics(fonts.px)
+            .context("The font has no horizontal metrics")?;
+        fonts.cell = tui::Vec2 {
+            x: (face.metrics('M', fonts.px).advance_width.round() as u16).max(1),
+            y: (line.new_line_size.ceil() as u16).max(1),
+        };
+        fonts.ascent = (line.ascent + line.line_gap / 2.0).round() as i32;
+        Ok(fonts)
+    }
+
+    /// The size of a cell in physical pixels.
+    pub(super) fn cell_size(&self) -> tui::Vec2<u16> {
+        self.cell
+    }
+
+    /// The path of the regular font.
+    pub(super) fn path(&self) -> Option<&std::path::Path> {
+        self.face_paths
+            .iter()
+            .find(|&(_, &idx)| idx == 0)
+            .map(|(path, _)| &**path)
+    }
+
+    fn load(&mut self, path: PathBuf) -> anyhow::Result<usize> {
+        if let Some(&idx) = self.face_paths.get(&path) {
+            return Ok(idx);
+        }
+        let data = std::fs::read(&path).with_context(|| format!("Failed to read {path:?}"))?;
+        let face = fontdue::Font::from_bytes(data, fontdue::FontSettings::default())
+            .map_err(|err| anyhow::anyhow!("Failed to parse {path:?}: {err}"))?;
+        self.faces.push(face);
+        self.face_paths.insert(path, self.faces.len() - 1);
+        Ok(self.faces.len() - 1)
+    }
+
+    fn variant_face(&mut self, variant: Variant) -> anyhow::Result<usize> {
+        if let Some(&idx) = self.variants.get(&variant) {
+            return Ok(idx);
+        }
+        let idx = self.load(fc_match(&format!("{FAMILY}{}", variant.style()))?)?;
+        self.variants.insert(variant, idx);
+        Ok(idx)
+    }
+
+    /// The face to draw `ch` with. Falls back to the regular font, which then draws its
+    /// replacement glyph.
+    fn face_for(&mut self, variant: Variant, ch: char) -> usize {
+        let regular = 0;
+        let face = self.variant_face(variant).ok_or_debug().unwrap_or(regular);
+        if self.faces[face].has_glyph(ch) {
+            return face;
+        }
+        if let Some(&fallback) = self.fallbacks.get(&(variant, ch)) {
+            return fallback.unwrap_or(face);
+        }
+        let pattern = format!("{FAMILY}{}:charset={:x}", variant.style(), u32::from(ch));
+        let fallback = fc_match(&pattern)
+            .and_then(|path| self.load(path))
+            .ok_or_debug()
+            .filter(|&idx| self.faces[idx].has_glyph(ch));
+        if fallback.is_none() {
+            log::debug!("No font has a glyph for {ch:?}");
+        }
+        self.fallbacks.insert((variant, ch), fallback);
+        fallback.unwrap_or(face)
+    }
+
+    fn glyph(&mut self, variant: Variant, ch: char) -> &Glyph {
+        let face = self.face_for(variant, ch);
+        let px = self.px;
+        let faces = &self.faces;
+        self.glyphs.entry((face, ch)).or_insert_with(|| {
+            let (metrics, coverage) = faces[face].rasterize(ch, px);
+            Glyph { metrics, coverage }
+        })
+    }
+
+    /// Draws the grid into an ARGB8888 buffer of `width` pixels per row, which must be large
+    /// enough for it.
+    pub(super) fn draw(
+        &mut self,
+        grid: &tui::Grid,
+        canvas: &mut [u8],
+        width: usize,
+        overlay: bool,
+    ) {
+        let mut canvas = Canvas { canvas, width };
+        let default_alpha = if overlay { OVERLAY_OPACITY } else { u8::MAX };
+        // Including the pixels that are left over below and right of the cells
+        let height = canvas.height();
+        canvas.rect(
+            0,
+            0,
+            width,
+            height,
+            tui::CellStyle::default().bg,
+            default_alpha,
+        );
+        let cell = tui::Vec2 {
+            x: usize::from(self.cell.x),
+            y: usize::from(self.cell.y),
+        };
+        for (row_idx, row) in grid.rows().enumerate() {
+            let top = row_idx * cell.y;
+            let mut style = tui::CellStyle::default();
+            for (col_idx, it) in row.iter().enumerate() {
+                // Wide graphemes are drawn in their first cell, on the background of all cells
+                if !it.continuation {
+                    style = tui::cell_style(&it.sgr);
+                }
+                let alpha = if style.bg == tui::CellStyle::default().bg {
+                    default_alpha
+                } else {
+                    u8::MAX
+                };
+                canvas.rect(col_idx * cell.x, top, cell.x, cell.y, style.bg, alpha);
+            }
+
+            for (col_idx, it) in row.iter().enumerate() {
+                if it.continuation || it.text.is_empty() {
+                    continue;
+                }
+                let style = tui::cell_style(&it.sgr);
+                let span = 1 + row[col_idx + 1..]
+                    .iter()
+                    .take_while(|it| it.continuation)
+                    .count();
+                let left = col_idx * cell.x;
+                let baseline = (top as i32) + self.ascent;
+                let variant = Variant {
+                    bold: style.bold,
+                    italic: style.italic,
+                };
+
+                let mut pen = left as i32;
+                for ch in it.text.chars() {
+                    // Only has a background, which is drawn already
+                    if ch.is_whitespace() {
+                        pen += cell.x as i32;
+                        continue;
+                    }
+                    let glyph = self.glyph(variant, ch);
+                    let metrics = glyph.metrics;
+                    // Glyphs across several cells are centered in them, like the terminals do
+                    let x = if span > 1 {
+                        left as i32 + (span * cell.x) as i32 / 2 - metrics.width as i32 / 2
+                    } else {
+                        pen + metrics.xmin
+                    };
+                    let y = baseline - metrics.ymin - metrics.height as i32;
+                    canvas.blend(x, y, metrics.width, &glyph.coverage, style.fg);
+                    pen += metrics.advance_width.round() as i32;
+                }
+
+                let thickness = (self.px / 14.0).round().max(1.0) as usize;
+                let width = span * cell.x;
+                if style.underline {
+                    let y = (baseline + 1).max(0) as usize;
+                    canvas.rect(left, y, width, thickness, style.fg, u8::MAX);
+                }
+                if style.strikethrough {
+                    let y = (baseline - self.ascent / 3).max(0) as usize;
+                    canvas.rect(left, y, width, thickness, style.fg, u8::MAX);
+                }
+            }
+        }
+    }
+}
+
+/// An ARGB8888 buffer with premultiplied alpha, in the byte order of little endian machines.
+struct Canvas<'a> {
+    canvas: &'a mut [u8],
+    width: usize,
+}
+impl Canvas<'_> {
+    fn height(&self) -> usize {
+        self.canvas.len() / 4 / self.width.max(1)
+    }
+
+    fn pixel(&mut self, x: usize, y: usize) -> Option<&mut [u8]> {
+        if x >= self.width {
+            return None;
+        }
+        let idx = (y * self.width + x) * 4;
+        self.canvas.get_mut(idx..idx + 4)
+    }
+
+    fn rect(&mut self, x: usize, y: usize, w: usize, h: usize, [r, g, b]: [u8; 3], alpha: u8) {
+        let premultiply = |it: u8| (u16::from(it) * u16::from(alpha) / 255) as u8;
+        let bgra = [premultiply(b), premultiply(g), premultiply(r), alpha];
+        for y in y..(y + h).min(self.height()) {
+            for x in x..(x + w).min(self.width) {
+                if let Some(pixel) = self.pixel(x, y) {
+                    pixel.copy_from_slice(&bgra);
+                }
+            }
+        }
+    }
+
+    /// Draws an opaque color over the canvas, with the coverage of each pixel of a glyph.
+    fn blend(&mut self, x: i32, y: i32, width: usize, coverage: &[u8], [r, g, b]: [u8; 3]) {
+        let color = [b, g, r, u8::MAX];
+        for (row_idx, row) in coverage.chunks(width.max(1)).enumerate() {
+            for (col_idx, &cov) in row.iter().enumerate() {
+                let (Ok(px), Ok(py)) = (
+                    usize::try_from(x + col_idx as i32),
+                    usize::try_from(y + row_idx as i32),
+                ) else {
+                    continue;
+                };
+                if cov == 0 {
+                    continue;
+                }
+                let Some(pixel) = self.pixel(px, py) else {
+                    continue;
+                };
+                for (dst, src) in pixel.iter_mut().zip(color) {
+                    *dst = ((u16::from(src) * u16::from(cov)
+                        + u16::from(*dst) * u16::from(u8::MAX - cov))
+                        / 255) as u8;
+                }
+            }
+        }
+    }
+}
+
+/// The file of the font that fontconfig picks for `pattern`.
+fn fc_match(pattern: &str) -> anyhow::Result<PathBuf> {
+    let output = Command::new("fc-match")
+        .args(["--format=%{file}", pattern])
+        .stdin(std::process::Stdio::null())
+        .output()
+        .context("Failed to run fc-match")?;
+    anyhow::ensure!(
+        output.status.success() && !output.stdout.is_empty(),
+        "fc-match found no font for {pattern:?}"
+    );
+    Ok(OsString::from_vec(output.stdout).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cells of 2x4 pixels, without any faces. Blank cells are drawn without looking up glyphs.
+    fn fonts() -> Fonts {
+        Fonts {
+            px: 4.0,
+            cell: tui::Vec2 { x: 2, y: 4 },
+            ascent: 2,
+            faces: Vec::new(),
+            face_paths: HashMap::new(),
+            variants: HashMap::new(),
+            fallbacks: HashMap::new(),
+            glyphs: HashMap::new(),
+        }
+    }
+
+    fn draw(output: &[u8], overlay: bool) -> (Vec<u8>, usize) {
+        let mut grid = tui::Grid::new(tui::Vec2 { x: 3, y: 1 });
+        grid.apply(output);
+        // One column of pixels is left over right of the cells
+        let width = 7;
+        let mut canvas = vec![0; width * 4 * 4];
+        fonts().draw(&grid, &mut canvas, width, overlay);
+        (canvas, width)
+    }
+
+    fn pixel(canvas: &[u8], width: usize, x: usize, y: usize) -> [u8; 4] {
+        let idx = (y * width + x) * 4;
+        canvas[idx..idx + 4].try_into().unwrap()
+    }
+
+    #[test]
+    fn draws_backgrounds_and_underlines() {
+        let (canvas, width) = draw(b"\x1b[41m \x1b[0m\x1b[4m  ", false);
+        let black = [0, 0, 0, u8::MAX];
+        let red = [0, 0, 0xcd, u8::MAX];
+        let white = [u8::MAX; 4];
+        assert_eq!(pixel(&canvas, width, 0, 0), red);
+        assert_eq!(pixel(&canvas, width, 1, 3), red);
+        // The baseline is at the ascent, the underline below it
+        assert_eq!(pixel(&canvas, width, 2, 2), black);
+        assert_eq!(pixel(&canvas, width, 2, 3), white);
+        assert_eq!(pixel(&canvas, width, 5, 3), white);
+        assert_eq!(pixel(&canvas, width, 6, 3), black);
+    }
+
+    #[test]
+    fn overlays_are_translucent_on_the_default_background() {
+        let (canvas, width) = draw(b"\x1b[41m \x1b[0m\x1b[7m ", true);
+        let translucent = [0, 0, 0, OVERLAY_OPACITY];
+        assert_eq!(pixel(&canvas, width, 0, 0), [0, 0, 0xcd, u8::MAX]);
+        // Reversed, the background is the default foreground
+        assert_eq!(pixel(&canvas, width, 2, 0), [u8::MAX; 4]);
+        assert_eq!(pixel(&canvas, width, 4, 0), translucent);
+        assert_eq!(pixel(&canvas, width, 6, 3), translucent);
+    }
+}
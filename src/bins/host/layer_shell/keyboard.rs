@@ -0,0 +1,157 @@
+//! Translates keys to crossterm's key events with the compositor's keymap, see [`Keymap`].
+//!
+//! libxkbcommon is loaded at runtime, so that the backend works without it. Panels then get no
+//! keyboard input.
+
+use std::{ffi::c_char, os::fd::OwnedFd, os::unix::fs::FileExt as _};
+
+use anyhow::Context as _;
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use xkbcommon_dl::{self as xkb, keysyms};
+
+/// A compiled keymap and the state of its modifiers.
+pub(super) struct Keymap {
+    lib: &'static xkb::XkbCommon,
+    context: *mut xkb::xkb_context,
+    keymap: *mut xkb::xkb_keymap,
+    state: *mut xkb::xkb_state,
+}
+
+// SAFETY: The pointers are owned by the `Keymap` and only used through `&self`/`&mut self`.
+// libxkbcommon's objects are not tied to the thread that created them.
+unsafe impl Send for Keymap {}
+
+impl Keymap {
+    /// Compiles the keymap that the compositor sent as a file of `size` bytes.
+    pub(super) fn new(fd: OwnedFd, size: u32) -> anyhow::Result<Self> {
+        let lib = xkb::xkbcommon_option().context("libxkbcommon is not installed")?;
+        let mut text = vec![0; usize::try_from(size)?];
+        // The file is shared with other clients, so its offset must not be used
+        std::fs::File::from(fd)
+            .read_exact_at(&mut text, 0)
+            .context("Failed to read the keymap")?;
+        // The keymap is a null-terminated string
+        if text.last() != Some(&0) {
+            text.push(0);
+        }
+
+        // SAFETY: The functions are called as documented by libxkbcommon, and each object is
+        // checked for null before it is used. Objects that were created are released by
+        // `Drop`, or here if a later step fails.
+        unsafe {
+            let context = (lib.xkb_context_new)(xkb::xkb_context_flags::XKB_CONTEXT_NO_FLAGS);
+            anyhow::ensure!(!context.is_null(), "Failed to create an xkb context");
+            let keymap = (lib.xkb_keymap_new_from_string)(
+                context,
+                text.as_ptr().cast::<c_char>(),
+                xkb::xkb_keymap_format::XKB_KEYMAP_FORMAT_TEXT_V1,
+                xkb::xkb_keymap_compile_flags::XKB_KEYMAP_COMPILE_NO_FLAGS,
+            );
+            if keymap.is_null() {
+                (lib.xkb_context_unref)(context);
+                anyhow::bail!("Failed to compile the keymap");
+            }
+            let state = (lib.xkb_state_new)(keymap);
+            if state.is_null() {
+                (lib.xkb_keymap_unref)(keymap);
+                (lib.xkb_context_unref)(context);
+                anyhow::bail!("Failed to create an xkb state");
+            }
+            Ok(Self {
+                lib,
+                context,
+                keymap,
+                state,
+            })
+        }
+    }
+
+    pub(super) fn update_modifiers(
+        &mut self,
+        depressed: u32,
+        latched: u32,
+        locked: u32,
+        group: u32,
+    ) {
+        // SAFETY: `self.state` is valid while `self` exists
+        unsafe {
+            (self.lib.xkb_state_update_mask)(self.state, depressed, latched, locked, 0, 0, group);
+        }
+    }
+
+    pub(super) fn modifiers(&self) -> KeyModifiers {
+        let active = |name: &[u8]| {
+            // SAFETY: `self.state` is valid while `self` exists, and the names are
+            // null-terminated
+            unsafe {
+                (self.lib.xkb_state_mod_name_is_active)(
+                    self.state,
+                    name.as_ptr().cast::<c_char>(),
+                    xkb::xkb_state_component::XKB_STATE_MODS_EFFECTIVE,
+                ) > 0
+            }
+        };
+        let mut modifiers = KeyModifiers::NONE;
+        modifiers.set(KeyModifiers::SHIFT, active(xkb::XKB_MOD_NAME_SHIFT));
+        modifiers.set(KeyModifiers::CONTROL, active(xkb::XKB_MOD_NAME_CTRL));
+        modifiers.set(KeyModifiers::ALT, active(xkb::XKB_MOD_NAME_ALT));
+        modifiers
+    }
+
+    /// Whether holding the key repeats it. `key` is the key code of `wl_keyboard`.
+    pub(super) fn repeats(&self, key: u32) -> bool {
+        // SAFETY: `self.keymap` is valid while `self` exists
+        unsafe { (self.lib.xkb_keymap_key_repeats)(self.keymap, xkb_keycode(key)) > 0 }
+    }
+
+    /// The event for pressing a key, `None` for modifiers and other keys without a
+    /// [`KeyCode`]. `key` is the key code of `wl_keyboard`.
+    pub(super) fn key_event(&self, key: u32) -> Option<KeyEvent> {
+        // SAFETY: `self.state` is valid while `self` exists
+        let sym = unsafe { (self.lib.xkb_state_key_get_one_sym)(self.state, xkb_keycode(key)) };
+        let code = match sym {
+            keysyms::Return | keysyms::KP_Enter => KeyCode::Enter,
+            keysyms::Escape => KeyCode::Esc,
+            keysyms::Tab => KeyCode::Tab,
+            keysyms::ISO_Left_Tab => KeyCode::BackTab,
+            keysyms::BackSpace => KeyCode::Backspace,
+            keysyms::Delete | keysyms::KP_Delete => KeyCode::Delete,
+            keysyms::Insert | keysyms::KP_Insert => KeyCode::Insert,
+            keysyms::Left | keysyms::KP_Left => KeyCode::Left,
+            keysyms::Right | keysyms::KP_Right => KeyCode::Right,
+            keysyms::Up | keysyms::KP_Up => KeyCode::Up,
+            keysyms::Down | keysyms::KP_Down => KeyCode::Down,
+            keysyms::Home | keysyms::KP_Home => KeyCode::Home,
+            keysyms::End | keysyms::KP_End => KeyCode::End,
+            keysyms::Page_Up | keysyms::KP_Page_Up => KeyCode::PageUp,
+            keysyms::Page_Down | keysyms::KP_Page_Down => KeyCode::PageDown,
+            keysyms::F1..=keysyms::F35 => KeyCode::F((sym - keysyms::F1 + 1) as u8),
+            _ => {
+                // SAFETY: Converting a keysym has no preconditions
+                let ch = unsafe { (self.lib.xkb_keysym_to_utf32)(sym) };
+                KeyCode::Char(char::from_u32(ch).filter(|it| !it.is_control())?)
+            }
+        };
+        Some(KeyEvent::new_with_kind(
+            code,
+            self.modifiers(),
+            KeyEventKind::Press,
+        ))
+    }
+}
+
+impl Drop for Keymap {
+    fn drop(&mut self) {
+        // SAFETY: The objects were created in `Keymap::new` and are not used after this
+        unsafe {
+            (self.lib.xkb_state_unref)(self.state);
+            (self.lib.xkb_keymap_unref)(self.keymap);
+            (self.lib.xkb_context_unref)(self.context);
+        }
+    }
+}
+
+/// Key codes of `wl_keyboard` are evdev codes, which xkb offsets by 8.
+fn xkb_keycode(key: u32) -> u32 {
+    key + 8
+}
@@ -0,0 +1,1058 @@
+//! Panels that are drawn by the host itself as wlr-layer-shell surfaces, without a terminal,
+//! see [`LayerShellBackend`] and `docs/panel-backends.md`.
+//!
+//! Each panel has its own Wayland connection, which is handled by a task of the host. Like a
+//! simulated panel, it interprets the printed output into a [`tui::Grid`], which is then drawn
+//! with a built-in font rasterizer, see [`font::Fonts`]. Input is reported like kitty does, with
+//! mouse positions in pixels.
+
+mod font;
+mod keyboard;
+
+use std::{
+    os::fd::{AsFd as _, AsRawFd as _},
+    time::Duration,
+};
+
+use anyhow::Context as _;
+use crossterm::event::{KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+use futures::future::BoxFuture;
+use smithay_client_toolkit::{
+    compositor::{CompositorHandler, CompositorState},
+    delegate_compositor, delegate_layer, delegate_output, delegate_pointer, delegate_registry,
+    delegate_seat, delegate_shm,
+    output::{OutputHandler, OutputState},
+    reexports::{
+        client::{
+            self, Connection, Dispatch, EventQueue, QueueHandle, WEnum,
+            globals::registry_queue_init,
+            protocol::{wl_keyboard, wl_output, wl_pointer, wl_seat, wl_shm, wl_surface},
+        },
+        protocols::wp::{
+            fractional_scale::v1::client::{
+                wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1,
+                wp_fractional_scale_v1::{self, WpFractionalScaleV1},
+            },
+            viewporter::client::{wp_viewport::WpViewport, wp_viewporter::WpViewporter},
+        },
+    },
+    registry::{ProvidesRegistryState, RegistryState},
+    registry_handlers,
+    seat::{
+        Capability, SeatHandler, SeatState,
+        pointer::{
+            CursorIcon, PointerEvent, PointerEventKind, PointerHandler, ThemeSpec, ThemedPointer,
+        },
+    },
+    shell::{
+        WaylandSurface as _,
+        wlr_layer::{
+            Anchor, KeyboardInteractivity, Layer, LayerShell, LayerShellHandler, LayerSurface,
+            LayerSurfaceConfigure,
+        },
+    },
+    shm::{Shm, ShmHandler, slot::SlotPool},
+};
+use tokio::{io::unix::AsyncFd, sync::mpsc::UnboundedSender, time::Instant};
+use tokio_util::{sync::CancellationToken, time::FutureExt as _};
+use tracing::Instrument as _;
+
+use super::backend::{
+    FocusPolicy, KittyBackend, Panel, PanelBackend, PanelCapabilities, PanelGeometry, PanelSpec,
+};
+use crate::{
+    bins::{
+        cli::BarEdge,
+        inst::{TermEvent, TermUpdate},
+    },
+    tui,
+    utils::ResultExt as _,
+};
+
+/// Scales of `wp_fractional_scale_v1` are in 120ths.
+const SCALE_DENOMINATOR: u32 = 120;
+/// The distance of a click of the mouse wheel in libinput, for scrolling with touchpads.
+const SCROLL_STEP: f64 = 15.0;
+
+const BTN_LEFT: u32 = 0x110;
+const BTN_RIGHT: u32 = 0x111;
+const BTN_MIDDLE: u32 = 0x112;
+
+/// Draws each panel as a layer surface in the host's process.
+#[derive(Debug)]
+pub(super) struct LayerShellBackend;
+impl PanelBackend for LayerShellBackend {
+    fn spawn<'a>(
+        &'a self,
+        spec: &'a PanelSpec,
+        ev_tx: UnboundedSender<TermEvent>,
+        cancel: &'a CancellationToken,
+    ) -> BoxFuture<'a, anyhow::Result<Panel>> {
+        Box::pin(start_panel(spec, ev_tx, cancel))
+    }
+
+    // The grid does not keep the pixels of images
+    fn capabilities(&self) -> PanelCapabilities {
+        PanelCapabilities { images: false }
+    }
+
+    // Panels understand the remote control commands of kitty, see `Placement::remote_control`
+    fn set_visible(&self, visible: bool) -> Option<TermUpdate> {
+        KittyBackend.set_visible(visible)
+    }
+
+    fn set_geometry(&self, geometry: &PanelGeometry) -> Option<TermUpdate> {
+        KittyBackend.set_geometry(geometry)
+    }
+}
+
+/// Checks that panels can be shown, for `scratchbar check-config`.
+pub(super) fn check() -> anyhow::Result<String> {
+    let conn = Connection::connect_to_env().context("Failed to connect to the compositor")?;
+    let (globals, queue) = registry_queue_init::<PanelState>(&conn)
+        .context("Failed to list the compositor's globals")?;
+    LayerShell::bind(&globals, &queue.handle())
+        .context("The compositor does not support wlr-layer-shell")?;
+    let fonts = font::Fonts::new(1.0)?;
+    let cell = fonts.cell_size();
+    Ok(format!(
+        "Drawing panels with {} in cells of {}x{} logical pixels{}",
+        fonts
+            .path()
+            .map_or("no font".into(), |it| it.display().to_string()),
+        cell.x,
+        cell.y,
+        if xkbcommon_dl::xkbcommon_option().is_some() {
+            ""
+        } else {
+            ", without keyboard input since libxkbcommon is missing"
+        },
+    ))
+}
+
+#[tracing::instrument(skip_all, fields(panel = spec.log_name))]
+async fn start_panel(
+    spec: &PanelSpec,
+    ev_tx: UnboundedSender<TermEvent>,
+    cancel: &CancellationToken,
+) -> anyhow::Result<Panel> {
+    let (upd_tx, upd_rx) = tokio::sync::mpsc::channel(super::monitor_inst::PANEL_QUEUE_CAPACITY);
+    let panel_cancel = cancel.child_token();
+
+    let (conn, queue, state) = {
+        let spec = spec.clone();
+        tokio::task::spawn_blocking(move || connect(&spec, ev_tx))
+            .timeout(Duration::from_secs(10))
+            .await
+            .context("Timed out connecting to the compositor")???
+    };
+
+    let auto_cancel = panel_cancel.clone().drop_guard();
+    let task_cancel = panel_cancel.clone();
+    tokio::spawn(
+        async move {
+            let _auto_cancel = auto_cancel;
+            run_panel(conn, queue, state, upd_rx, task_cancel)
+                .await
+                .context("Layer shell panel failed")
+                .ok_or_log();
+        }
+        .in_current_span(),
+    );
+
+    Ok(Panel {
+        upd_tx,
+        cancel: panel_cancel,
+    })
+}
+
+/// Creates the panel's surface. It is shown once the compositor configures it.
+fn connect(
+    spec: &PanelSpec,
+    ev_tx: UnboundedSender<TermEvent>,
+) -> anyhow::Result<(Connection, EventQueue<PanelState>, PanelState)> {
+    let conn = Connection::connect_to_env().context("Failed to connect to the compositor")?;
+    let (globals, mut queue) = registry_queue_init::<PanelState>(&conn)
+        .context("Failed to list the compositor's globals")?;
+    let qh = queue.handle();
+    let compositor =
+        CompositorState::bind(&globals, &qh).context("The compositor has no wl_compositor")?;
+    let layer_shell = LayerShell::bind(&globals, &qh)
+        .context("The compositor does not support wlr-layer-shell")?;
+    let shm = Shm::bind(&globals, &qh).context("The compositor has no wl_shm")?;
+    let pool = SlotPool::new(1, &shm).context("Failed to create a shared memory pool")?;
+
+    let mut state = PanelState {
+        registry: RegistryState::new(&globals),
+        outputs: OutputState::new(&globals, &qh),
+        seats: SeatState::new(&globals, &qh),
+        compositor,
+        shm,
+        pool,
+        ev_tx,
+        surface: None,
+        input: Input::default(),
+        closed: false,
+    };
+    // Receives the names of the outputs
+    queue
+        .roundtrip(&mut state)
+        .context("Failed to query the outputs")?;
+    let (output, scale) = state
+        .outputs
+        .outputs()
+        .find_map(|output| {
+            let info = state.outputs.info(&output)?;
+            (info.name.as_deref() == Some(&*spec.monitor)).then_some((output, info.scale_factor))
+        })
+        .with_context(|| format!("No output named {:?}", spec.monitor))?;
+
+    let wl_surface = state.compositor.create_surface(&qh);
+    let fractional_scale = match (
+        globals.bind::<WpFractionalScaleManagerV1, _, _>(&qh, 1..=1, ()),
+        globals.bind::<WpViewporter, _, _>(&qh, 1..=1, ()),
+    ) {
+        (Ok(manager), Ok(viewporter)) => Some((
+            manager.get_fractional_scale(&wl_surface, &qh, ()),
+            viewporter.get_viewport(&wl_surface, &qh, ()),
+        )),
+        _ => None,
+    };
+    let layer = layer_shell.create_layer_surface(
+        &qh,
+        wl_surface,
+        if spec.overlay {
+            Layer::Overlay
+        } else {
+            Layer::Top
+        },
+        Some(&*spec.app_id),
+        Some(&output),
+    );
+    layer.set_keyboard_interactivity(match spec.focus {
+        FocusPolicy::NotAllowed => KeyboardInteractivity::None,
+        FocusPolicy::OnDemand => KeyboardInteractivity::OnDemand,
+        FocusPolicy::Exclusive => KeyboardInteractivity::Exclusive,
+    });
+
+    let scale = u32::try_from(scale).unwrap_or(1).max(1) * SCALE_DENOMINATOR;
+    let fonts = font::Fonts::new(f64::from(scale) / f64::from(SCALE_DENOMINATOR))?;
+    let mut surface = Surface {
+        layer,
+        fractional_scale,
+        fonts,
+        grid: tui::Grid::new(tui::Vec2 { x: 0, y: 0 }),
+        scale,
+        size: None,
+        mapped: false,
+        sent_sizes: None,
+        edge: spec.edge,
+        overlay: spec.overlay,
+        placement: Placement::default(),
+    };
+    surface.place();
+    state.surface = Some(surface);
+    state
+        .ev_tx
+        .send(TermEvent::PanelIds(Default::default()))
+        .ok_or_debug();
+    Ok((conn, queue, state))
+}
+
+async fn run_panel(
+    conn: Connection,
+    mut queue: EventQueue<PanelState>,
+    mut state: PanelState,
+    mut upd_rx: tokio::sync::mpsc::Receiver<TermUpdate>,
+    cancel: CancellationToken,
+) -> anyhow::Result<()> {
+    let fd = AsyncFd::with_interest(conn.as_fd().as_raw_fd(), tokio::io::Interest::READABLE)?;
+    loop {
+        queue.dispatch_pending(&mut state)?;
+        if state.closed {
+            anyhow::bail!("The compositor closed the panel");
+        }
+        queue.flush()?;
+        let Some(guard) = queue.prepare_read() else {
+            // There are events to dispatch already
+            continue;
+        };
+
+        let repeat_at = state.input.repeat.as_ref().map(|it| it.at);
+        tokio::select! {
+            () = cancel.cancelled() => return Ok(()),
+            ready = fd.readable() => {
+                let mut ready = ready?;
+                match guard.read() {
+                    Ok(_) => {}
+                    Err(client::backend::WaylandError::Io(err))
+                        if err.kind() == std::io::ErrorKind::WouldBlock =>
+                    {
+                        ready.clear_ready();
+                    }
+                    Err(err) => return Err(err.into()),
+                }
+            }
+            upd = upd_rx.recv() => {
+                drop(guard);
+                let Some(upd) = upd else {
+                    return Ok(());
+                };
+                state.update(upd);
+            }
+            () = tokio::time::sleep_until(repeat_at.unwrap_or_else(Instant::now)),
+                if repeat_at.is_some() =>
+            {
+                drop(guard);
+                state.repeat_key();
+            }
+        }
+    }
+}
+
+/// What kitty's remote control set for the panel, see [`Placement::remote_control`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Placement {
+    visible: bool,
+    margin_left: u32,
+    margin_right: u32,
+    margin_top: Option<u32>,
+    lines: u16,
+}
+impl Default for Placement {
+    fn default() -> Self {
+        Self {
+            visible: true,
+            margin_left: 0,
+            margin_right: 0,
+            margin_top: None,
+            // Like `kitten panel`
+            lines: 1,
+        }
+    }
+}
+impl Placement {
+    /// Applies the `resize-os-window` commands of [`KittyBackend`]. Other commands do not
+    /// apply to layer surfaces and are ignored.
+    fn remote_control(&mut self, args: &[std::ffi::OsString]) {
+        let args = Vec::from_iter(args.iter().filter_map(|it| it.to_str()));
+        if args.first() != Some(&"resize-os-window") {
+            log::trace!("Ignoring remote control command {args:?}");
+            return;
+        }
+        for arg in &args[1..] {
+            let parse = |key: &str| arg.strip_prefix(key)?.parse().ok();
+            match arg.strip_prefix("--action=") {
+                Some("show") => self.visible = true,
+                Some("hide") => self.visible = false,
+                Some("toggle-visibility") => self.visible = !self.visible,
+                _ => {}
+            }
+            if let Some(margin) = parse("margin-left=") {
+                self.margin_left = margin;
+            }
+            if let Some(margin) = parse("margin-right=") {
+                self.margin_right = margin;
+            }
+            if let Some(margin) = parse("margin-top=") {
+                self.margin_top = Some(margin);
+            }
+            if let Some(lines) = arg.strip_prefix("lines=").and_then(|it| it.parse().ok()) {
+                self.lines = lines;
+            }
+        }
+    }
+}
+
+/// The panel's layer surface and what is drawn on it.
+struct Surface {
+    layer: LayerSurface,
+    /// `None` if the compositor does not support fractional scales, in which case the
+    /// output's integer scale is used.
+    fractional_scale: Option<(WpFractionalScaleV1, WpViewport)>,
+    fonts: font::Fonts,
+    grid: tui::Grid,
+    /// In 120ths, see [`SCALE_DENOMINATOR`].
+    scale: u32,
+    /// The size in logical pixels, from the last configure. `None` until the surface is
+    /// configured, and after it was unmapped.
+    size: Option<(u32, u32)>,
+    /// Whether a buffer is attached.
+    mapped: bool,
+    sent_sizes: Option<tui::Sizes>,
+    edge: BarEdge,
+    overlay: bool,
+    placement: Placement,
+}
+impl Surface {
+    fn to_physical(&self, logical: u32) -> u32 {
+        (logical * self.scale).div_ceil(SCALE_DENOMINATOR)
+    }
+
+    fn to_logical(&self, physical: u32) -> u32 {
+        (physical * SCALE_DENOMINATOR).div_ceil(self.scale)
+    }
+
+    /// Requests the size and position from [`Self::placement`]. The compositor answers with a
+    /// configure.
+    fn place(&mut self) {
+        let Placement {
+            visible: _,
+            margin_left,
+            margin_right,
+            margin_top,
+            lines,
+        } = self.placement;
+        let edge = match self.edge {
+            BarEdge::Top => Anchor::TOP,
+            BarEdge::Bottom => Anchor::BOTTOM,
+        };
+        let to_i32 = |it: u32| i32::try_from(it).unwrap_or(i32::MAX);
+        let height = self.to_logical(u32::from(lines) * u32::from(self.fonts.cell_size().y));
+        match margin_top {
+            Some(margin_top) => {
+                self.layer
+                    .set_anchor(Anchor::TOP | Anchor::LEFT | Anchor::RIGHT);
+                self.layer.set_margin(
+                    to_i32(margin_top),
+                    to_i32(margin_right),
+                    0,
+                    to_i32(margin_left),
+                );
+            }
+            None => {
+                self.layer.set_anchor(edge | Anchor::LEFT | Anchor::RIGHT);
+                self.layer
+                    .set_margin(0, to_i32(margin_right), 0, to_i32(margin_left));
+            }
+        }
+        // Like the exclusive zones of the kitty backend
+        self.layer
+            .set_exclusive_zone(if self.overlay { 0 } else { to_i32(height) });
+        // The width is up to the compositor since the surface is anchored left and right
+        self.layer.set_size(0, height);
+        self.layer.commit();
+    }
+
+    /// Returns whether the scale changed.
+    fn set_scale(&mut self, scale: u32) -> bool {
+        if scale == self.scale || scale == 0 {
+            return false;
+        }
+        self.scale = scale;
+        match font::Fonts::new(f64::from(scale) / f64::from(SCALE_DENOMINATOR)) {
+            Ok(fonts) => self.fonts = fonts,
+            Err(err) => log::error!("Failed to load the font for the new scale: {err:#}"),
+        }
+        if self.fractional_scale.is_none() {
+            self.layer
+                .wl_surface()
+                .set_buffer_scale(i32::try_from(scale / SCALE_DENOMINATOR).unwrap_or(1));
+        }
+        // The height of the lines changes with the font
+        self.place();
+        true
+    }
+
+    /// The panel's size in cells and pixels, from the last configure.
+    fn sizes(&self) -> Option<tui::Sizes> {
+        let (width, height) = self.size?;
+        let cell = self.fonts.cell_size();
+        let cells = tui::Vec2 {
+            x: u16::try_from(self.to_physical(width) / u32::from(cell.x)).unwrap_or(u16::MAX),
+            y: u16::try_from(self.to_physical(height) / u32::from(cell.y)).unwrap_or(u16::MAX),
+        };
+        Some(tui::Sizes {
+            cell_size: cells,
+            pix_size: tui::Vec2 {
+                x: cells.x.saturating_mul(cell.x),
+                y: cells.y.saturating_mul(cell.y),
+            },
+        })
+    }
+
+    /// Reports the size if it changed, with an empty grid of that size.
+    fn report_sizes(&mut self, ev_tx: &UnboundedSender<TermEvent>) {
+        let Some(sizes) = self.sizes() else {
+            return;
+        };
+        if self.sent_sizes == Some(sizes) {
+            return;
+        }
+        self.grid = tui::Grid::new(sizes.cell_size);
+        self.sent_sizes = Some(sizes);
+        ev_tx.send(TermEvent::Sizes(sizes)).ok_or_debug();
+    }
+
+    fn draw(&mut self, pool: &mut SlotPool) -> anyhow::Result<()> {
+        let (Some((width, height)), true) = (self.size, self.placement.visible) else {
+            return Ok(());
+        };
+        let (phys_width, phys_height) = (self.to_physical(width), self.to_physical(height));
+        let stride = phys_width * 4;
+        let (buffer, canvas) = pool
+            .create_buffer(
+                i32::try_from(phys_width)?,
+                i32::try_from(phys_height)?,
+                i32::try_from(stride)?,
+                wl_shm::Format::Argb8888,
+            )
+            .context("Failed to create a buffer")?;
+        self.fonts
+            .draw(&self.grid, canvas, phys_width as usize, self.overlay);
+
+        let wl_surface = self.layer.wl_surface();
+        if let Some((_, viewport)) = &self.fractional_scale {
+            viewport.set_destination(i32::try_from(width)?, i32::try_from(height)?);
+        }
+        wl_surface.damage_buffer(
+            0,
+            0,
+            i32::try_from(phys_width)?,
+            i32::try_from(phys_height)?,
+        );
+        buffer.attach_to(wl_surface)?;
+        wl_surface.commit();
+        self.mapped = true;
+        Ok(())
+    }
+
+    fn set_visible(&mut self, visible: bool, pool: &mut SlotPool) {
+        self.placement.visible = visible;
+        if !visible && self.mapped {
+            // Unmapped layer surfaces have to be configured again before they are shown
+            let wl_surface = self.layer.wl_surface();
+            wl_surface.attach(None, 0, 0);
+            wl_surface.commit();
+            self.mapped = false;
+            self.size = None;
+        } else if visible && !self.mapped {
+            match self.size {
+                Some(_) => {
+                    self.draw(pool).ok_or_log();
+                }
+                None => self.layer.commit(),
+            }
+        }
+    }
+}
+
+/// The state of the pointer and keyboard of the panel's seat.
+#[derive(Default)]
+struct Input {
+    pointer: Option<ThemedPointer>,
+    /// The last position of the pointer in logical pixels.
+    position: (f64, f64),
+    pressed: Option<MouseButton>,
+    /// The distance scrolled since the last scroll event, see [`SCROLL_STEP`].
+    scrolled: (f64, f64),
+    keyboard: Option<wl_keyboard::WlKeyboard>,
+    /// `None` if libxkbcommon is missing.
+    keymap: Option<keyboard::Keymap>,
+    /// The delay until keys repeat and the interval between repeats. `None` if keys do not
+    /// repeat.
+    repeat_info: Option<(Duration, Duration)>,
+    repeat: Option<KeyRepeat>,
+}
+
+struct KeyRepeat {
+    key: u32,
+    at: Instant,
+}
+
+struct PanelState {
+    registry: RegistryState,
+    outputs: OutputState,
+    seats: SeatState,
+    compositor: CompositorState,
+    shm: Shm,
+    pool: SlotPool,
+    ev_tx: UnboundedSender<TermEvent>,
+    /// `None` until the output is known.
+    surface: Option<Surface>,
+    input: Input,
+    /// Whether the compositor closed the surface, e.g. because its output was removed.
+    closed: bool,
+}
+
+impl PanelState {
+    fn update(&mut self, upd: TermUpdate) {
+        let Some(surface) = &mut self.surface else {
+            return;
+        };
+        match upd {
+            TermUpdate::Print(buf) => surface.grid.apply(&buf),
+            TermUpdate::Flush => {
+                surface.draw(&mut self.pool).ok_or_log();
+            }
+            TermUpdate::RemoteControl(args) => {
+                let old = surface.placement.clone();
+                surface.placement.remote_control(&args);
+                let new = surface.placement.clone();
+                if new.visible != old.visible {
+                    surface.set_visible(new.visible, &mut self.pool);
+                }
+                let moved = Placement {
+                    visible: old.visible,
+                    ..new
+                } != old;
+                if moved {
+                    surface.place();
+                }
+            }
+            // There is no shell in the panel and the host's log level applies to it
+            TermUpdate::Shell(..) | TermUpdate::SetLogLevel(_) => {}
+        }
+    }
+
+    fn set_scale(&mut self, scale: u32) {
+        let Some(surface) = &mut self.surface else {
+            return;
+        };
+        if surface.set_scale(scale) {
+            surface.report_sizes(&self.ev_tx);
+            surface.draw(&mut self.pool).ok_or_log();
+        }
+    }
+
+    fn send_mouse(&self, kind: MouseEventKind) {
+        let Some(surface) = &self.surface else {
+            return;
+        };
+        let scale = f64::from(surface.scale) / f64::from(SCALE_DENOMINATOR);
+        let (x, y) = self.input.position;
+        let modifiers = self
+            .input
+            .keymap
+            .as_ref()
+            .map_or(KeyModifiers::NONE, |it| it.modifiers());
+        self.ev_tx
+            .send(TermEvent::Crossterm(crossterm::event::Event::Mouse(
+                MouseEvent {
+                    kind,
+                    column: (x * scale).clamp(0.0, f64::from(u16::MAX)) as u16,
+                    row: (y * scale).clamp(0.0, f64::from(u16::MAX)) as u16,
+                    modifiers,
+                },
+            )))
+            .ok_or_debug();
+    }
+
+    fn press_key(&mut self, key: u32) {
+        let Some(keymap) = &self.input.keymap else {
+            return;
+        };
+        if let Some(ev) = keymap.key_event(key) {
+            self.ev_tx
+                .send(TermEvent::Crossterm(crossterm::event::Event::Key(ev)))
+                .ok_or_debug();
+        }
+    }
+
+    fn repeat_key(&mut self) {
+        let (Some(repeat), Some((_, interval))) = (&mut self.input.repeat, self.input.repeat_info)
+        else {
+            return;
+        };
+        repeat.at += interval;
+        let key = repeat.key;
+        self.press_key(key);
+    }
+}
+
+impl CompositorHandler for PanelState {
+    fn scale_factor_changed(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &wl_surface::WlSurface,
+        new_factor: i32,
+    ) {
+        if self
+            .surface
+            .as_ref()
+            .is_some_and(|it| it.fractional_scale.is_none())
+        {
+            self.set_scale(u32::try_from(new_factor).unwrap_or(1) * SCALE_DENOMINATOR);
+        }
+    }
+
+    fn transform_changed(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &wl_surface::WlSurface,
+        _: wl_output::Transform,
+    ) {
+    }
+
+    fn frame(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &wl_surface::WlSurface, _: u32) {}
+
+    fn surface_enter(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &wl_surface::WlSurface,
+        _: &wl_output::WlOutput,
+    ) {
+    }
+
+    fn surface_leave(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &wl_surface::WlSurface,
+        _: &wl_output::WlOutput,
+    ) {
+    }
+}
+
+impl OutputHandler for PanelState {
+    fn output_state(&mut self) -> &mut OutputState {
+        &mut self.outputs
+    }
+
+    fn new_output(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_output::WlOutput) {}
+
+    fn update_output(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_output::WlOutput) {}
+
+    fn output_destroyed(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_output::WlOutput) {}
+}
+
+impl LayerShellHandler for PanelState {
+    fn closed(&mut self, _: &Connection, _: &QueueHandle<Self>, _: &LayerSurface) {
+        self.closed = true;
+    }
+
+    fn configure(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: &LayerSurface,
+        configure: LayerSurfaceConfigure,
+        _: u32,
+    ) {
+        let Some(surface) = &mut self.surface else {
+            return;
+        };
+        let (width, height) = configure.new_size;
+        surface.size = Some((width.max(1), height.max(1)));
+        surface.report_sizes(&self.ev_tx);
+        if surface.placement.visible {
+            surface.draw(&mut self.pool).ok_or_log();
+        }
+    }
+}
+
+impl ShmHandler for PanelState {
+    fn shm_state(&mut self) -> &mut Shm {
+        &mut self.shm
+    }
+}
+
+impl SeatHandler for PanelState {
+    fn seat_state(&mut self) -> &mut SeatState {
+        &mut self.seats
+    }
+
+    fn new_seat(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_seat::WlSeat) {}
+
+    fn new_capability(
+        &mut self,
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+        seat: wl_seat::WlSeat,
+        capability: Capability,
+    ) {
+        match capability {
+            Capability::Keyboard if self.input.keyboard.is_none() => {
+                self.input.keyboard = Some(seat.get_keyboard(qh, ()));
+            }
+            Capability::Pointer if self.input.pointer.is_none() => {
+                self.input.pointer = self
+                    .seats
+                    .get_pointer_with_theme(
+                        qh,
+                        &seat,
+                        self.shm.wl_shm(),
+                        self.compositor.create_surface(qh),
+                        ThemeSpec::default(),
+                    )
+                    .context("Failed to get the pointer")
+                    .ok_or_log();
+            }
+            _ => {}
+        }
+    }
+
+    fn remove_capability(
+        &mut self,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+        _: wl_seat::WlSeat,
+        capability: Capability,
+    ) {
+        match capability {
+            Capability::Keyboard => {
+                if let Some(keyboard) = self.input.keyboard.take() {
+                    keyboard.release();
+                }
+                self.input.repeat = None;
+            }
+            Capability::Pointer => self.input.pointer = None,
+            _ => {}
+        }
+    }
+
+    fn remove_seat(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_seat::WlSeat) {}
+}
+
+impl PointerHandler for PanelState {
+    fn pointer_frame(
+        &mut self,
+        conn: &Connection,
+        _: &QueueHandle<Self>,
+        _: &wl_pointer::WlPointer,
+        events: &[PointerEvent],
+    ) {
+        for event in events {
+            if self
+                .surface
+                .as_ref()
+                .is_none_or(|it| *it.layer.wl_surface() != event.surface)
+            {
+                continue;
+            }
+            self.input.position = event.position;
+            let kind = match event.kind {
+                PointerEventKind::Enter { .. } => {
+                    if let Some(pointer) = &self.input.pointer {
+                        pointer.set_cursor(conn, CursorIcon::Default).ok_or_debug();
+                    }
+                    MouseEventKind::Moved
+                }
+                PointerEventKind::Leave { .. } => {
+                    self.input.pressed = None;
+                    MouseEventKind::KittyLeaveWindow
+                }
+                PointerEventKind::Motion { .. } => match self.input.pressed {
+                    Some(button) => MouseEventKind::Drag(button),
+                    None => MouseEventKind::Moved,
+                },
+                PointerEventKind::Press { button, .. } => {
+                    let Some(button) = mouse_button(button) else {
+                        continue;
+                    };
+                    self.input.pressed = Some(button);
+                    MouseEventKind::Down(button)
+                }
+                PointerEventKind::Release { button, .. } => {
+                    let Some(button) = mouse_button(button) else {
+                        continue;
+                    };
+                    self.input.pressed = None;
+                    MouseEventKind::Up(button)
+                }
+                PointerEventKind::Axis {
+                    horizontal,
+                    vertical,
+                    ..
+                } => {
+                    let (scrolled_x, scrolled_y) = &mut self.input.scrolled;
+                    let mut scrolls = Vec::new();
+                    for (axis, scrolled, kinds) in [
+                        (
+                            horizontal,
+                            scrolled_x,
+                            [MouseEventKind::ScrollLeft, MouseEventKind::ScrollRight],
+                        ),
+                        (
+                            vertical,
+                            scrolled_y,
+                            [MouseEventKind::ScrollUp, MouseEventKind::ScrollDown],
+                        ),
+                    ] {
+                        // Wheels report clicks, touchpads only a distance
+                        *scrolled += if axis.discrete != 0 {
+                            f64::from(axis.discrete) * SCROLL_STEP
+                        } else {
+                            axis.absolute
+                        };
+                        if axis.stop {
+                            *scrolled = 0.0;
+                        }
+                        while scrolled.abs() >= SCROLL_STEP {
+                            let forward = *scrolled > 0.0;
+                            *scrolled -= SCROLL_STEP.copysign(*scrolled);
+                            scrolls.push(kinds[usize::from(forward)]);
+                        }
+                    }
+                    for kind in scrolls {
+                        self.send_mouse(kind);
+                    }
+                    continue;
+                }
+            };
+            self.send_mouse(kind);
+        }
+    }
+}
+
+fn mouse_button(button: u32) -> Option<MouseButton> {
+    match button {
+        BTN_LEFT => Some(MouseButton::Left),
+        BTN_RIGHT => Some(MouseButton::Right),
+        BTN_MIDDLE => Some(MouseButton::Middle),
+        _ => None,
+    }
+}
+
+// sctk's keyboard handling needs libxkbcommon when building, so the keyboard is handled here
+impl Dispatch<wl_keyboard::WlKeyboard, ()> for PanelState {
+    fn event(
+        state: &mut Self,
+        _: &wl_keyboard::WlKeyboard,
+        event: wl_keyboard::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let input = &mut state.input;
+        match event {
+            wl_keyboard::Event::Keymap {
+                format: WEnum::Value(wl_keyboard::KeymapFormat::XkbV1),
+                fd,
+                size,
+            } => {
+                input.keymap = keyboard::Keymap::new(fd, size)
+                    .context("Keyboard input is not available")
+                    .ok_or_log();
+            }
+            wl_keyboard::Event::Leave { .. } => input.repeat = None,
+            wl_keyboard::Event::Key {
+                key,
+                state: key_state,
+                ..
+            } => match key_state {
+                WEnum::Value(wl_keyboard::KeyState::Pressed) => {
+                    input.repeat = match (&input.keymap, input.repeat_info) {
+                        (Some(keymap), Some((delay, _))) if keymap.repeats(key) => {
+                            Some(KeyRepeat {
+                                key,
+                                at: Instant::now() + delay,
+                            })
+                        }
+                        _ => None,
+                    };
+                    state.press_key(key);
+                }
+                _ => {
+                    if input.repeat.as_ref().is_some_and(|it| it.key == key) {
+                        input.repeat = None;
+                    }
+                }
+            },
+            wl_keyboard::Event::Modifiers {
+                mods_depressed,
+                mods_latched,
+                mods_locked,
+                group,
+                ..
+            } => {
+                if let Some(keymap) = &mut input.keymap {
+                    keymap.update_modifiers(mods_depressed, mods_latched, mods_locked, group);
+                }
+            }
+            wl_keyboard::Event::RepeatInfo { rate, delay } => {
+                input.repeat_info = (rate > 0).then(|| {
+                    (
+                        Duration::from_millis(delay.max(0) as u64),
+                        Duration::from_secs(1) / rate as u32,
+                    )
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<WpFractionalScaleV1, ()> for PanelState {
+    fn event(
+        state: &mut Self,
+        _: &WpFractionalScaleV1,
+        event: wp_fractional_scale_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let wp_fractional_scale_v1::Event::PreferredScale { scale } = event {
+            state.set_scale(scale);
+        }
+    }
+}
+
+impl ProvidesRegistryState for PanelState {
+    fn registry(&mut self) -> &mut RegistryState {
+        &mut self.registry
+    }
+    registry_handlers![OutputState, SeatState];
+}
+
+delegate_compositor!(PanelState);
+delegate_output!(PanelState);
+delegate_shm!(PanelState);
+delegate_seat!(PanelState);
+delegate_pointer!(PanelState);
+delegate_layer!(PanelState);
+delegate_registry!(PanelState);
+client::delegate_noop!(PanelState: ignore WpFractionalScaleManagerV1);
+client::delegate_noop!(PanelState: ignore WpViewporter);
+client::delegate_noop!(PanelState: ignore WpViewport);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<std::ffi::OsString> {
+        args.iter().map(Into::into).collect()
+    }
+
+    #[test]
+    fn placement_follows_resize_commands() {
+        let mut placement = Placement::default();
+        placement.remote_control(&args(&[
+            "resize-os-window",
+            "--incremental",
+            "--action=os-panel",
+            "margin-left=10",
+            "margin-right=20",
+            "margin-top=5",
+            "lines=3",
+        ]));
+        assert_eq!(
+            placement,
+            Placement {
+                visible: true,
+                margin_left: 10,
+                margin_right: 20,
+                margin_top: Some(5),
+                lines: 3,
+            }
+        );
+
+        placement.remote_control(&args(&["resize-os-window", "--action=hide"]));
+        assert!(!placement.visible);
+        placement.remote_control(&args(&["resize-os-window", "--action=toggle-visibility"]));
+        assert!(placement.visible);
+        // Unchanged by the visibility
+        assert_eq!(placement.lines, 3);
+    }
+
+    #[test]
+    fn placement_ignores_other_commands() {
+        let mut placement = Placement::default();
+        placement.remote_control(&args(&["set-spacing", "padding-top=1", "lines=4"]));
+        placement.remote_control(&args(&["resize-os-window", "margin-left=x", "lines=-1"]));
+        assert_eq!(placement, Placement::default());
+    }
+}
@@ -0,0 +1,110 @@
+//! Performance metrics of the host, see [`host::HostMetrics`].
+
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use crate::host;
+
+/// The period over which [`host::HostMetrics::events_per_sec`] is averaged.
+const EVENT_RATE_WINDOW: Duration = Duration::from_secs(5);
+
+#[derive(Default)]
+struct PanelCounters {
+    frames: u64,
+    bytes_written: u64,
+    render_total: Duration,
+    render_max: Duration,
+}
+
+struct Metrics {
+    panels: HashMap<host::TermInfo, PanelCounters>,
+    events: u64,
+    events_since: Instant,
+    events_per_sec: f32,
+    menu_open_latency: Option<Duration>,
+}
+impl Metrics {
+    /// Starts a new averaging window for the event rate if the current one is over.
+    fn roll_event_window(&mut self) {
+        let elapsed = self.events_since.elapsed();
+        if elapsed >= EVENT_RATE_WINDOW {
+            self.events_per_sec = self.events as f32 / elapsed.as_secs_f32();
+            self.events = 0;
+            self.events_since = Instant::now();
+        }
+    }
+}
+
+fn metrics() -> MutexGuard<'static, Metrics> {
+    static METRICS: LazyLock<Mutex<Metrics>> = LazyLock::new(|| {
+        Mutex::new(Metrics {
+            panels: HashMap::new(),
+            events: 0,
+            events_since: Instant::now(),
+            events_per_sec: 0.0,
+            menu_open_latency: None,
+        })
+    });
+    METRICS.lock().unwrap_or_else(|poison| poison.into_inner())
+}
+
+fn micros(duration: Duration) -> u64 {
+    duration.as_micros().try_into().unwrap_or(u64::MAX)
+}
+
+/// Records a frame that took `elapsed` to render and resulted in `bytes` of output.
+pub(super) fn record_render(term: &host::TermInfo, elapsed: Duration, bytes: usize) {
+    let mut metrics = metrics();
+    let panel = metrics.panels.entry(term.clone()).or_default();
+    panel.frames += 1;
+    panel.bytes_written += bytes as u64;
+    panel.render_total += elapsed;
+    panel.render_max = panel.render_max.max(elapsed);
+}
+
+/// Records an input event from a panel.
+pub(super) fn record_event() {
+    let mut metrics = metrics();
+    metrics.roll_event_window();
+    metrics.events += 1;
+}
+
+/// Records the time between a menu being opened and its first frame being sent.
+pub(super) fn record_menu_open(latency: Duration) {
+    metrics().menu_open_latency = Some(latency);
+}
+
+/// Forgets the panels of a monitor that was removed.
+pub(super) fn remove_monitor(monitor: &str) {
+    metrics().panels.retain(|term, _| &*term.monitor != monitor);
+}
+
+pub(super) fn snapshot() -> host::HostMetrics {
+    let mut metrics = metrics();
+    metrics.roll_event_window();
+    let mut panels: Vec<_> = metrics
+        .panels
+        .iter()
+        .map(|(term, counters)| host::PanelMetrics {
+            term: term.clone(),
+            frames: counters.frames,
+            bytes_written: counters.bytes_written,
+            avg_render_us: micros(counters.render_total) / counters.frames.max(1),
+            max_render_us: micros(counters.render_max),
+        })
+        .collect();
+    panels.sort_by_key(|it| {
+        (
+            it.term.monitor.clone(),
+            it.term.kind == host::TermKind::Menu,
+        )
+    });
+    host::HostMetrics {
+        panels,
+        events_per_sec: metrics.events_per_sec,
+        menu_open_latency_us: metrics.menu_open_latency.map(micros),
+    }
+}
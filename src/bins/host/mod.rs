@@ -1,16 +1,37 @@
+mod backend;
 mod bin_entry_point;
+mod commands;
+mod gesture;
+#[cfg(feature = "layer-shell")]
+mod layer_shell;
+mod metrics;
 mod monitor_inst;
 mod monitor_listen;
+mod msg;
+mod record;
+mod scratch;
+#[cfg(feature = "testing")]
+pub(crate) mod simulate;
+mod snapshot;
+mod systemd;
+
+pub(crate) use bin_entry_point::check_config_main;
+#[cfg(feature = "embedded")]
+pub(crate) use bin_entry_point::embedded_main;
+pub(crate) use msg::{MsgArgs, msg_main};
+pub(crate) use record::replay_main;
+pub(crate) use systemd::SERVICE_ENV_VARS;
 
 use std::{collections::HashMap, sync::Arc};
 
 use futures::{Stream, StreamExt};
 use tokio::sync::watch;
+use tokio_util::time::FutureExt as _;
 
 use crate::{host, tui, utils::ResultExt};
 
-pub(crate) fn host_main() -> std::process::ExitCode {
-    bin_entry_point::host_main_inner().unwrap_or(std::process::ExitCode::FAILURE)
+pub(crate) fn host_main(args: super::cli::RunArgs) -> std::process::ExitCode {
+    bin_entry_point::host_main_inner(args).unwrap_or(std::process::ExitCode::FAILURE)
 }
 
 // TODO: Consider stopping/starting the monitor instance instead of having this hide system
@@ -36,19 +57,90 @@ impl BarTuiStates {
             .or_insert_with(|| watch::Sender::new(self.defaults.clone()))
     }
 }
-#[derive(PartialEq, Clone, Debug)]
-pub(crate) struct MonitorInfo {
-    pub name: Arc<str>,
-    pub scale: f64,
-    pub width: u32,
-    pub height: u32,
+/// Window ids reported by the panels, see [`host::HostUpdate::QueryPanels`].
+type PanelInfos = HashMap<host::TermInfo, host::PanelIds>;
+
+/// What [`host::HostUpdate::Query`] is answered with, apart from the open menu.
+#[derive(Debug, Default)]
+struct QueryState {
+    monitors: Vec<MonitorInfo>,
+    /// The geometry of each monitor's bar, see [`host::HostEvent::BarGeometry`].
+    bar_geometry: HashMap<Arc<str>, host::BarGeometry>,
+}
+
+/// See [`host::HostUpdate::UpdateMenuSlot`].
+#[derive(Debug, Clone)]
+struct MenuSlotUpdate {
+    slot: tui::CustomId,
+    tui: tui::Elem,
+}
+
+/// Options from [`host::HostConnectOpts`], with defaults applied.
+#[derive(Debug)]
+struct HostConfig {
+    bar_app_id: Arc<str>,
+    menu_app_id: Arc<str>,
+    osd_app_id: Arc<str>,
+    prompt_app_id: Arc<str>,
+    dim_bar_on_menu: bool,
+    frame_interval: std::time::Duration,
+    min_interactive_size: Option<host::MinInteractiveSize>,
+    systemd_scopes: bool,
+    forward_logs: bool,
+    max_commands: std::num::NonZeroU16,
+    ambiguous_width: tui::AmbiguousWidth,
+    /// The log level from the command line, until the controller sets one.
+    log_level: Option<log::LevelFilter>,
+    /// The monitors to show bars on. All monitors if empty.
+    monitors: Vec<Arc<str>>,
+    edge: super::cli::BarEdge,
+    backend: Arc<dyn backend::PanelBackend>,
+    /// Used instead of the monitors of the compositor, e.g. those of a replayed recording.
+    fixed_monitors: Option<Vec<MonitorInfo>>,
+    /// Identifies the controller whose bars are restored after a restart, see [`snapshot`].
+    /// `None` if the bars are not restored.
+    snapshot_controller: Option<Arc<str>>,
 }
 
+use host::MonitorInfo;
+
+/// Why the host stops, which decides its exit code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HostExit {
+    /// The controller's updates ended, e.g. because an embedded controller returned.
+    Stopped,
+    /// A task of the host failed or panicked.
+    Failed,
+    /// The host received a signal that stops it, like `SIGTERM`.
+    Signal(i32),
+    /// See [`host::HostUpdate::Shutdown`].
+    Shutdown(u8),
+}
+impl HostExit {
+    fn code(self) -> std::process::ExitCode {
+        match self {
+            Self::Stopped => std::process::ExitCode::SUCCESS,
+            Self::Failed => std::process::ExitCode::FAILURE,
+            // Like shells report processes that were killed by a signal
+            Self::Signal(signal) => std::process::ExitCode::from(signal.wrapping_add(128) as u8),
+            Self::Shutdown(code) => std::process::ExitCode::from(code),
+        }
+    }
+}
+
+/// How long [`host::HostUpdate::Shutdown`] waits for the panels to close.
+const SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// `ctrl_connected_rx` is notified when a new controller connects, before its updates arrive.
 async fn run_host(
     update_rx: impl Stream<Item = host::HostUpdate> + Send + 'static,
     event_tx: std::sync::mpsc::Sender<host::HostEvent>,
-) -> std::process::ExitCode {
-    let mut required_tasks = tokio::task::JoinSet::<std::process::ExitCode>::new();
+    ctrl_connected_rx: watch::Receiver<()>,
+    config: Arc<HostConfig>,
+) -> HostExit {
+    let mut required_tasks = tokio::task::JoinSet::<HostExit>::new();
+    let event_filter_tx = watch::Sender::new(host::EventFilter::default());
+    let event_tx = filter_events(event_tx, event_filter_tx.subscribe());
 
     let bar_tui_states_tx = watch::Sender::new(BarTuiStates {
         by_monitor: Default::default(),
@@ -58,33 +150,259 @@ async fn run_host(
         },
     });
 
+    let snapshot_controller = config.snapshot_controller.clone();
+    let snapshot_writer = if let Some(controller) = &snapshot_controller {
+        if snapshot::restore_snapshot(controller, &bar_tui_states_tx).await {
+            event_tx.send(host::HostEvent::StateRestored).ok_or_debug();
+        }
+        Some(tokio::spawn(snapshot::run_snapshot_writer(
+            controller.clone(),
+            bar_tui_states_tx.subscribe(),
+        )))
+    } else {
+        None
+    };
+
     let open_menu_tx = watch::Sender::new(None);
-    required_tasks.spawn(monitor_listen::run_monitor_listener(
-        bar_tui_states_tx.clone(),
-        open_menu_tx.subscribe(),
+    let osd_tx = watch::Sender::new(None);
+    let prompt_tx = watch::Sender::new(None);
+    let (scratch_tx, scratch_rx) = tokio::sync::mpsc::unbounded_channel();
+    let (command_tx, command_rx) = tokio::sync::mpsc::unbounded_channel();
+    let panels_tx = watch::Sender::new(PanelInfos::new());
+    let query_state_tx = watch::Sender::new(QueryState::default());
+    let reload_tx = watch::Sender::new(());
+    let show_interactive_tx = watch::Sender::new(None);
+    let (menu_slot_tx, _) = tokio::sync::broadcast::channel(16);
+    let (msg_update_tx, mut msg_update_rx) = tokio::sync::mpsc::unbounded_channel();
+    let module_status_tx = watch::Sender::new(Vec::new());
+    let log_level_tx = watch::Sender::new(config.log_level);
+    let batching_tx = watch::Sender::new(false);
+    let stop_panels = tokio_util::sync::CancellationToken::new();
+
+    if config.forward_logs {
+        let event_tx = event_tx.clone();
+        crate::logging::set_log_sink(move |record| {
+            // Only the host's own logs, an embedded controller already has its own
+            if record
+                .module_path()
+                .is_some_and(|it| it.starts_with("scratchbar::"))
+            {
+                _ = event_tx.send(host::HostEvent::Log(crate::logging::log_record(record)));
+            }
+        });
+    }
+
+    // Not required, the host works without the control socket
+    tokio::spawn(msg::run_msg_listener(msg::MsgCtx {
+        update_tx: msg_update_tx,
+        reload_tx: reload_tx.clone(),
+        event_tx: event_tx.clone(),
+        bar_tui_states_rx: bar_tui_states_tx.subscribe(),
+        open_menu_rx: open_menu_tx.subscribe(),
+        show_interactive_tx: show_interactive_tx.clone(),
+        module_status_rx: module_status_tx.subscribe(),
+    }));
+
+    tokio::spawn(run_reload_on_hangup(reload_tx.clone(), event_tx.clone()));
+    tokio::spawn(run_diagnostics(event_tx.clone()));
+    tokio::spawn(systemd::run_service_notifier());
+    tokio::spawn(scratch::run_scratchpads(scratch_rx));
+    tokio::spawn(commands::run_commands(
+        command_rx,
         event_tx.clone(),
+        config.max_commands,
+    ));
+
+    required_tasks.spawn(monitor_listen::run_monitor_listener(
+        monitor_listen::MonitorListenerArgs {
+            bar_tui_states_tx: bar_tui_states_tx.clone(),
+            open_menu_rx: open_menu_tx.subscribe(),
+            osd_rx: osd_tx.subscribe(),
+            prompt_rx: prompt_tx.subscribe(),
+            panels_tx: panels_tx.clone(),
+            query_state_tx: query_state_tx.clone(),
+            event_tx: event_tx.clone(),
+            reload_rx: reload_tx.subscribe(),
+            show_interactive_rx: show_interactive_tx.subscribe(),
+            log_level_rx: log_level_tx.subscribe(),
+            batching_rx: batching_tx.subscribe(),
+            menu_slot_tx: menu_slot_tx.clone(),
+            stop: stop_panels.clone(),
+            config,
+        },
     ));
     required_tasks.spawn(run_update_handler(
-        update_rx,
+        futures::stream::select(
+            update_rx,
+            futures::stream::poll_fn(move |cx| msg_update_rx.poll_recv(cx)),
+        ),
+        ctrl_connected_rx,
         open_menu_tx,
+        menu_slot_tx,
+        osd_tx,
+        prompt_tx,
+        scratch_tx,
+        command_tx,
         bar_tui_states_tx,
+        panels_tx.subscribe(),
+        query_state_tx.subscribe(),
+        module_status_tx,
+        log_level_tx,
+        event_filter_tx,
+        batching_tx,
+        event_tx,
     ));
 
-    if let Some(res) = required_tasks.join_next().await {
-        res.ok_or_log().unwrap_or(std::process::ExitCode::FAILURE)
+    let exit = if let Some(res) = required_tasks.join_next().await {
+        res.ok_or_log().unwrap_or(HostExit::Failed)
     } else {
         unreachable!()
+    };
+
+    systemd::notify_stopping();
+    if let HostExit::Shutdown(_) = exit {
+        if let Some(snapshot_writer) = snapshot_writer {
+            snapshot_writer.abort();
+        }
+        if let Some(controller) = &snapshot_controller {
+            snapshot::remove_snapshot(controller).await;
+        }
+
+        // The monitor listener stops the panels of all monitors when it returns
+        stop_panels.cancel();
+        let stopped = async {
+            required_tasks.join_all().await;
+            super::inst::panels_exited().await;
+        };
+        if stopped.timeout(SHUTDOWN_TIMEOUT).await.is_err() {
+            log::warn!("Some panels did not close within {SHUTDOWN_TIMEOUT:?}");
+        }
+    }
+    exit
+}
+
+/// Passes on the events that the controller subscribed to, see
+/// [`host::HostUpdate::SetEventFilter`].
+fn filter_events(
+    event_tx: std::sync::mpsc::Sender<host::HostEvent>,
+    filter_rx: watch::Receiver<host::EventFilter>,
+) -> std::sync::mpsc::Sender<host::HostEvent> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        for ev in rx {
+            if filter_rx.borrow().allows(&ev) && event_tx.send(ev).is_err() {
+                break;
+            }
+        }
+    });
+    tx
+}
+
+/// Restarts the panels and lets the controller reload its modules, see
+/// [`host::MsgCommand::Reload`].
+fn request_reload(
+    reload_tx: &watch::Sender<()>,
+    event_tx: &std::sync::mpsc::Sender<host::HostEvent>,
+) {
+    reload_tx.send_replace(());
+    event_tx
+        .send(host::HostEvent::ReloadRequested)
+        .ok_or_debug();
+}
+
+/// Other bars reload their configuration on `SIGHUP` instead of exiting.
+async fn run_reload_on_hangup(
+    reload_tx: watch::Sender<()>,
+    event_tx: std::sync::mpsc::Sender<host::HostEvent>,
+) {
+    let Some(mut signal) =
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()).ok_or_log()
+    else {
+        return;
+    };
+    while let Some(()) = signal.recv().await {
+        log::info!("Reload requested by SIGHUP");
+        request_reload(&reload_tx, &event_tx);
     }
 }
 
+async fn run_diagnostics(event_tx: std::sync::mpsc::Sender<host::HostEvent>) {
+    const INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+    let mut last = crate::framing::stats();
+    loop {
+        tokio::time::sleep(INTERVAL).await;
+
+        let frames = crate::framing::stats();
+        if (frames.corrupt, frames.undecodable) != (last.corrupt, last.undecodable) {
+            let diagnostics = host::Diagnostics {
+                frames: frames.clone(),
+            };
+            if event_tx
+                .send(host::HostEvent::Diagnostics(diagnostics))
+                .ok_or_debug()
+                .is_none()
+            {
+                break;
+            }
+        }
+        last = frames;
+    }
+}
+
+#[expect(clippy::too_many_arguments)]
 async fn run_update_handler(
     update_rx: impl Stream<Item = host::HostUpdate> + Send + 'static,
+    mut ctrl_connected_rx: watch::Receiver<()>,
     open_menu_tx: watch::Sender<Option<host::OpenMenu>>,
+    menu_slot_tx: tokio::sync::broadcast::Sender<MenuSlotUpdate>,
+    osd_tx: watch::Sender<Option<host::ShowOsd>>,
+    prompt_tx: watch::Sender<Option<host::OpenPrompt>>,
+    scratch_tx: tokio::sync::mpsc::UnboundedSender<host::ToggleScratch>,
+    command_tx: tokio::sync::mpsc::UnboundedSender<host::RunCommand>,
     bar_tui_states_tx: watch::Sender<BarTuiStates>,
-) -> std::process::ExitCode {
+    panels_rx: watch::Receiver<PanelInfos>,
+    query_state_rx: watch::Receiver<QueryState>,
+    module_status_tx: watch::Sender<Vec<host::ModuleStatus>>,
+    log_level_tx: watch::Sender<Option<log::LevelFilter>>,
+    event_filter_tx: watch::Sender<host::EventFilter>,
+    batching_tx: watch::Sender<bool>,
+    event_tx: std::sync::mpsc::Sender<host::HostEvent>,
+) -> HostExit {
     tokio::pin!(update_rx);
-    while let Some(update) = update_rx.next().await {
+    let mut watchdog_probe = systemd::WatchdogProbe::new();
+    // The remaining updates of a batch
+    let mut batched = std::collections::VecDeque::new();
+    loop {
+        let update = match batched.pop_front() {
+            Some(update) => update,
+            None => {
+                batching_tx.send_if_modified(|batching| std::mem::replace(batching, false));
+                let update = tokio::select! {
+                    biased;
+                    Ok(()) = ctrl_connected_rx.changed() => {
+                        // The filter of the previous controller does not apply to the new one
+                        event_filter_tx.send_replace(host::EventFilter::default());
+                        send_bar_geometry(&query_state_rx, &event_tx);
+                        continue;
+                    }
+                    () = watchdog_probe.probed() => continue,
+                    update = update_rx.next() => update,
+                };
+                let Some(update) = update else {
+                    break;
+                };
+                update
+            }
+        };
+        let _span = tracing::debug_span!("host_update").entered();
         match update {
+            host::HostUpdate::Batch(updates) => {
+                batching_tx.send_replace(true);
+                for update in updates.into_iter().rev() {
+                    batched.push_front(update);
+                }
+            }
             host::HostUpdate::UpdateBars(host::BarSelect::All, update) => {
                 fn doit<T>(
                     bar_tui_states: &mut BarTuiStates,
@@ -178,11 +496,143 @@ async fn run_update_handler(
             host::HostUpdate::OpenMenu(open) => {
                 open_menu_tx.send_replace(Some(open));
             }
+            host::HostUpdate::UpdateMenuSlot(host::UpdateMenuSlot {
+                slot,
+                tui,
+                opts:
+                    host::UpdateMenuSlotOpts {
+                        #[expect(deprecated)]
+                            __non_exhaustive_struct_update: (),
+                    },
+            }) => {
+                // Keep the stored menu up to date without making the monitors re-layout it
+                open_menu_tx.send_if_modified(|open| {
+                    if let Some(open) = open
+                        && let Some((new_tui, _)) = open.tui.replace_slot(&slot, &tui)
+                    {
+                        open.tui = new_tui;
+                    }
+                    false
+                });
+                // Fails if there are no monitors
+                _ = menu_slot_tx.send(MenuSlotUpdate { slot, tui });
+            }
             host::HostUpdate::CloseMenu => {
                 open_menu_tx.send_replace(None);
             }
+            host::HostUpdate::QueryPanels => {
+                let panels = panels_rx
+                    .borrow()
+                    .iter()
+                    .map(|(term, ids)| host::PanelInfo {
+                        term: term.clone(),
+                        ids: ids.clone(),
+                    })
+                    .collect();
+                event_tx.send(host::HostEvent::Panels(panels)).ok_or_debug();
+            }
+            host::HostUpdate::ModuleStatus(modules) => {
+                module_status_tx.send_replace(modules);
+            }
+            host::HostUpdate::QueryMetrics => {
+                event_tx
+                    .send(host::HostEvent::Metrics(metrics::snapshot()))
+                    .ok_or_debug();
+            }
+            host::HostUpdate::SetLogLevel(level) => {
+                crate::logging::set_log_level(level);
+                log_level_tx.send_replace(Some(level));
+            }
+            host::HostUpdate::Query { id, query } => {
+                let result = match query {
+                    host::Query::Monitors => {
+                        host::QueryResult::Monitors(query_state_rx.borrow().monitors.clone())
+                    }
+                    host::Query::BarSize { monitor } => host::QueryResult::BarSize(
+                        query_state_rx
+                            .borrow()
+                            .bar_geometry
+                            .get(&monitor)
+                            .map(|it| it.cells),
+                    ),
+                    host::Query::OpenMenu => host::QueryResult::OpenMenu(
+                        open_menu_tx.borrow().as_ref().map(|it| it.monitor.clone()),
+                    ),
+                };
+                event_tx
+                    .send(host::HostEvent::QueryResponse { id, result })
+                    .ok_or_debug();
+            }
+            host::HostUpdate::SetEventFilter(filter) => {
+                let resend_geometry = filter.geometry;
+                event_filter_tx.send_replace(filter);
+                if resend_geometry {
+                    send_bar_geometry(&query_state_rx, &event_tx);
+                }
+            }
+            host::HostUpdate::ShowOsd(osd) => {
+                // Also replaces an identical popup, which restarts its timeout
+                osd_tx.send_replace(Some(osd));
+            }
+            host::HostUpdate::HideOsd => {
+                osd_tx.send_replace(None);
+            }
+            host::HostUpdate::OpenPrompt(open) => {
+                prompt_tx.send_replace(Some(open));
+            }
+            host::HostUpdate::ClosePrompt => {
+                prompt_tx.send_replace(None);
+            }
+            host::HostUpdate::ToggleScratch(toggle) => {
+                scratch_tx.send(toggle).ok_or_debug();
+            }
+            host::HostUpdate::RunCommand(run) => {
+                command_tx.send(run).ok_or_debug();
+            }
+            host::HostUpdate::Shutdown { code } => {
+                log::info!("Shutting down with exit code {code}");
+                return HostExit::Shutdown(code);
+            }
+            host::HostUpdate::UpdateBarSlot(host::UpdateBarSlot {
+                slot,
+                tui,
+                opts:
+                    host::UpdateBarSlotOpts {
+                        #[expect(deprecated)]
+                            __non_exhaustive_struct_update: (),
+                    },
+            }) => {
+                let bar_tui_states = bar_tui_states_tx.borrow();
+                let defaults = &bar_tui_states.defaults.tui;
+                let by_monitor = bar_tui_states
+                    .by_monitor
+                    .values()
+                    .map(|state| state.borrow().tui.clone())
+                    .filter(|tx| !tx.same_channel(defaults));
+                for tx in std::iter::once(defaults.clone()).chain(by_monitor) {
+                    tx.send_if_modified(|cur| {
+                        let Some((new_tui, _)) = cur.replace_slot(&slot, &tui) else {
+                            return false;
+                        };
+                        *cur = new_tui;
+                        true
+                    });
+                }
+            }
         }
     }
 
-    std::process::ExitCode::SUCCESS
+    HostExit::Stopped
+}
+
+/// Sends the current [`host::HostEvent::BarGeometry`] of every bar.
+fn send_bar_geometry(
+    query_state_rx: &watch::Receiver<QueryState>,
+    event_tx: &std::sync::mpsc::Sender<host::HostEvent>,
+) {
+    for geometry in query_state_rx.borrow().bar_geometry.values() {
+        event_tx
+            .send(host::HostEvent::BarGeometry(geometry.clone()))
+            .ok_or_debug();
+    }
 }
@@ -1,6 +1,4 @@
-use tempfile::TempDir;
-
-use std::{ffi::OsString, time::Duration};
+use std::{collections::HashMap, ffi::OsString, sync::Arc, time::Duration};
 
 use anyhow::Context;
 use tokio::{
@@ -11,8 +9,12 @@ use tokio_util::{sync::CancellationToken, time::FutureExt as _};
 
 use crate::{
     bins::{
-        host::MonitorInfo,
-        inst::{TermEvent, TermUpdate},
+        host::{
+            MonitorInfo,
+            backend::{FocusPolicy, Panel, PanelBackend, PanelGeometry, PanelSpec},
+            systemd,
+        },
+        inst::{BufPool, TermEvent, TermUpdate},
     },
     host, tui,
     utils::ResultExt,
@@ -24,11 +26,29 @@ pub(super) struct RunMonitorArgs {
     pub cancel_monitor: CancellationToken,
     pub bar_state_tx: watch::Sender<super::BarTuiStateSender>,
     pub open_menu_rx: watch::Receiver<Option<host::OpenMenu>>,
+    pub osd_rx: watch::Receiver<Option<host::ShowOsd>>,
+    pub prompt_rx: watch::Receiver<Option<host::OpenPrompt>>,
+    pub panels_tx: watch::Sender<super::PanelInfos>,
+    pub query_state_tx: watch::Sender<super::QueryState>,
     pub event_tx: std::sync::mpsc::Sender<host::HostEvent>,
+    /// The time until which interactive elements are highlighted.
+    pub show_interactive_rx: watch::Receiver<Option<tokio::time::Instant>>,
+    /// The log level set by the controller, if any.
+    pub log_level_rx: watch::Receiver<Option<log::LevelFilter>>,
+    /// Whether a [`host::HostUpdate::Batch`] is being applied, during which the bar's content
+    /// is not passed on.
+    pub batching_rx: watch::Receiver<bool>,
+    pub menu_slot_tx: tokio::sync::broadcast::Sender<super::MenuSlotUpdate>,
+    pub config: Arc<super::HostConfig>,
 }
 pub(super) async fn run_monitor(mut args: RunMonitorArgs) {
     let monitor = args.monitor.name.clone();
     let _auto_cancel = args.cancel_monitor.clone().drop_guard();
+    // Kills everything that the panels left behind when the monitor is removed
+    let _stop_slice = args
+        .config
+        .systemd_scopes
+        .then(|| systemd::StopSliceOnDrop(systemd::monitor_slice(&monitor)));
 
     loop {
         const TIMEOUT: Duration = Duration::from_secs(20);
@@ -47,19 +67,57 @@ pub(super) async fn run_monitor(mut args: RunMonitorArgs) {
 struct Term {
     term_ev_rx: tokio::sync::mpsc::UnboundedReceiver<TermEvent>,
     term_upd_tx: UnboundedSender<TermUpdate>,
+    backend: Arc<dyn PanelBackend>,
     sizes: tui::Sizes,
+    ambiguous_width: tui::AmbiguousWidth,
     layout: tui::RenderedLayout,
+    /// The elements reported by [`check_interactive_sizes`] after the last render.
+    small_interactive: Vec<tui::CustomId>,
+    bufs: BufPool,
+}
+impl Term {
+    fn set_visible(&self, visible: bool) {
+        if let Some(upd) = self.backend.set_visible(visible) {
+            self.term_upd_tx.send(upd).ok_or_debug();
+        }
+    }
+
+    fn set_geometry(&self, geometry: &PanelGeometry) {
+        if let Some(upd) = self.backend.set_geometry(geometry) {
+            self.term_upd_tx.send(upd).ok_or_log();
+        }
+    }
+
+    fn sizing(&self) -> tui::SizingArgs {
+        tui::SizingArgs::new(self.sizes.font_size())
+            .with_images(self.backend.capabilities().images)
+            .with_ambiguous_width(self.ambiguous_width)
+    }
 }
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum TermKind {
     Menu,
     Bar,
+    Osd,
+    Prompt,
+}
+impl TermKind {
+    fn log_name(self) -> &'static str {
+        match self {
+            Self::Menu => "MENU",
+            Self::Bar => "BAR",
+            Self::Osd => "OSD",
+            Self::Prompt => "PROMPT",
+        }
+    }
 }
 impl From<TermKind> for host::TermKind {
     fn from(value: TermKind) -> Self {
         match value {
             TermKind::Menu => Self::Menu,
             TermKind::Bar => Self::Bar,
+            TermKind::Osd => Self::Osd,
+            TermKind::Prompt => Self::Prompt,
         }
     }
 }
@@ -71,18 +129,32 @@ enum Upd {
 struct StartedMonitorEnv {
     bar: Term,
     menu: Term,
-    bar_tui_rx: watch::Receiver<tui::Elem>,
-    bar_hide_rx: watch::Receiver<bool>,
+    osd: Term,
+    prompt: Term,
+    bar_state_rx: watch::Receiver<super::BarTuiState>,
     event_tx: std::sync::mpsc::Sender<host::HostEvent>,
     open_menu_rx: watch::Receiver<Option<host::OpenMenu>>,
+    osd_rx: watch::Receiver<Option<host::ShowOsd>>,
+    prompt_rx: watch::Receiver<Option<host::OpenPrompt>>,
+    panels_tx: watch::Sender<super::PanelInfos>,
+    query_state_tx: watch::Sender<super::QueryState>,
+    show_interactive_rx: watch::Receiver<Option<tokio::time::Instant>>,
+    log_level_rx: watch::Receiver<Option<log::LevelFilter>>,
+    menu_slot_rx: tokio::sync::broadcast::Receiver<super::MenuSlotUpdate>,
+    config: Arc<super::HostConfig>,
 }
 
 async fn try_run_monitor(args: &mut RunMonitorArgs) -> anyhow::Result<()> {
     log::debug!("Starting panel manager for monitor {:?}", args.monitor);
+    systemd::set_bar_up(&args.monitor.name, false);
 
     let mut required_tasks = JoinSet::<anyhow::Result<std::convert::Infallible>>::new();
     let cancel = args.cancel_monitor.child_token();
     let _auto_cancel = cancel.clone().drop_guard();
+    if args.config.systemd_scopes {
+        // Clean up after a previous attempt or a previous host that crashed
+        systemd::stop_slice(&systemd::monitor_slice(&args.monitor.name)).await;
+    }
     let env = try_init_monitor(args, &mut required_tasks, &cancel).await?;
     required_tasks.spawn(run_monitor_main(args.monitor.clone(), env));
 
@@ -106,8 +178,10 @@ async fn try_run_monitor(args: &mut RunMonitorArgs) -> anyhow::Result<()> {
     }
 }
 
-// FIXME: Add to update enum
-const EDGE: &str = "top";
+/// How long the bar's size has to stay the same before the bar is rendered at that size.
+/// Terminals report intermediate sizes while they are being resized, and rendering at each of
+/// them produces garbled frames.
+const RESIZE_SETTLE_DELAY: Duration = Duration::from_millis(50);
 
 /// Adds an extra line and centers the content of the menu with padding of half a cell.
 const VERTICAL_PADDING: bool = false;
@@ -116,90 +190,431 @@ const HORIZONTAL_PADDING: u16 = 4;
 #[derive(Debug)]
 struct ShowMenu {
     pix_location: tui::Vec2<u32>,
+    close_delay: Duration,
     cached_size: tui::Vec2<u16>,
     sizing: tui::SizingArgs,
     tui: tui::Elem,
     bar_anchor: tui::CustomId,
 }
 impl ShowMenu {
-    fn update(this: &mut Option<Self>, open: host::OpenMenu, env: &StartedMonitorEnv) {
+    /// Measures the menu on the blocking pool, since large menus take a while. Aborting the task
+    /// does not stop the measurement, see [`MeasuringMenu`].
+    ///
+    /// Menus that are taller than [`host::OpenMenuOpts::max_height`] are split into columns.
+    /// Menus that are wider than the monitor are narrowed to fit, so that their wrapped text
+    /// takes more lines instead of being clipped.
+    fn measure(
+        open: host::OpenMenu,
+        shown: Option<&Self>,
+        monitor: &MonitorInfo,
+        env: &StartedMonitorEnv,
+    ) -> tokio::task::JoinHandle<Self> {
         let host::OpenMenu {
             tui,
             monitor: _,
             bar_anchor,
             opts:
                 host::OpenMenuOpts {
+                    max_height,
+                    open_delay: _,
+                    close_delay,
                     #[expect(deprecated)]
                         __non_exhaustive_struct_update: (),
                 },
         } = open;
 
-        let pix_location = if let Some(this) = this
-            && this.bar_anchor == bar_anchor
+        let pix_location = if let Some(shown) = shown
+            && shown.bar_anchor == bar_anchor
         {
-            this.pix_location
+            shown.pix_location
         } else {
             env.bar
                 .layout
                 .get_pix_location(env.bar.sizes.font_size(), &bar_anchor)
+                // The anchor may not be rendered yet, see the bar's rendering
+                .or(shown.map(|it| it.pix_location))
                 .unwrap_or_default()
         };
 
-        let sizing = tui::SizingArgs {
-            font_size: env.menu.sizes.font_size(),
-        };
-        this.replace(ShowMenu {
-            pix_location,
-            cached_size: tui::calc_min_size(&tui, &sizing),
-            sizing,
-            tui,
-            bar_anchor,
+        let sizing = env.menu.sizing();
+        let cell_pix_w = u32::from(sizing.font_size.x).max(1);
+        let max_width = u16::try_from(monitor.width / cell_pix_w)
+            .unwrap_or(u16::MAX)
+            .saturating_sub(HORIZONTAL_PADDING);
+        let max_lines = max_height.map(|fraction| {
+            let pix_h = f64::from(monitor.height) * f64::from(fraction.clamp(0.0, 1.0));
+            (pix_h / f64::from(sizing.font_size.y.max(1))) as u16
         });
+        let span = tracing::debug_span!("measure_menu");
+        tokio::task::spawn_blocking(move || {
+            let _span = span.entered();
+            let mut tui = tui;
+            let mut cached_size = tui::calc_min_size_within(&tui, &sizing, max_width);
+            if let Some(max_lines) = max_lines
+                && cached_size.y > max_lines
+                && let Some(columns) = tui::split_columns(&tui, &sizing, max_lines)
+            {
+                tui = columns;
+                cached_size = tui::calc_min_size_within(&tui, &sizing, max_width);
+            }
+            ShowMenu {
+                pix_location,
+                close_delay,
+                cached_size,
+                sizing,
+                tui,
+                bar_anchor,
+            }
+        })
+    }
+
+    /// Where the menu panel goes so that it is centered below its anchor, as far as it fits.
+    fn geometry(&self, monitor: &MonitorInfo, font_size: tui::Vec2<u16>) -> PanelGeometry {
+        // HACK: This minimizes the rounding error for some reason (as far as I can tell).
+        let scale = (monitor.scale * 1000.0).ceil() / 1000.0;
+
+        // NOTE: There is no absolute positioning system, nor a way to directly specify the
+        // geometry (since this is controlled by the compositor). So we have to get creative by
+        // using the right and left margin to control both position and size of the panel.
+
+        let lines = self.cached_size.y.saturating_add(VERTICAL_PADDING.into());
+
+        // Find the distance between window edge and center
+        let half_pix_w = {
+            let cell_pix_w = u32::from(font_size.x);
+            let cell_w = self.cached_size.x + HORIZONTAL_PADDING;
+            let pix_w = u32::from(cell_w) * cell_pix_w;
+            pix_w.div_ceil(2)
+        };
+
+        // Clamp position such that we fit. The menu is at most as wide as the monitor,
+        // see [`ShowMenu::measure`].
+        let x = self.pix_location.x.clamp(
+            half_pix_w, //
+            monitor.width.saturating_sub(half_pix_w),
+        );
+
+        // The left margin should be such that half the space is between
+        // left margin and x. Use saturating_sub so that the left
+        // margin becomes zero if the width would reach outside the screen.
+        let mleft = x.saturating_sub(half_pix_w);
+
+        // The right margin is calculated the same way, but starting from the right edge.
+        let mright = (monitor.width - x).saturating_sub(half_pix_w);
+
+        // The font size (on which cell->pixel conversion is based) and the monitor's
+        // size are in physical pixels. This makes sense because different monitors can
+        // have different scales, and the application should not be affected by that
+        // (this is not x11 after all).
+        // However, panels are bound to a monitor and the margins are in scaled pixels,
+        // so we have to make this correction.
+        PanelGeometry {
+            margin_left: (f64::from(mleft) / scale) as u32,
+            margin_right: (f64::from(mright) / scale) as u32,
+            margin_top: None,
+            lines,
+        }
     }
 }
 // FIXME: This function is way too large
+/// The measurement of the menu that replaces the shown one. Aborting a blocking task does not
+/// stop it, so only one measurement runs at a time. Menus requested in the meantime replace each
+/// other and the latest one is measured once the running measurement finishes.
+#[derive(Default)]
+struct MeasuringMenu {
+    task: Option<tokio::task::JoinHandle<ShowMenu>>,
+    /// Whether the result of `task` is outdated and discarded
+    superseded: bool,
+    pending: Option<host::OpenMenu>,
+}
+impl MeasuringMenu {
+    /// Whether a menu is going to replace the shown one.
+    fn is_active(&self) -> bool {
+        (self.task.is_some() && !self.superseded) || self.pending.is_some()
+    }
+
+    fn start(
+        &mut self,
+        open: host::OpenMenu,
+        shown: Option<&ShowMenu>,
+        monitor: &MonitorInfo,
+        env: &StartedMonitorEnv,
+    ) {
+        if self.task.is_some() {
+            self.superseded = true;
+            self.pending = Some(open);
+        } else {
+            self.task = Some(ShowMenu::measure(open, shown, monitor, env));
+        }
+    }
+
+    /// Discards the running and pending measurements. Returns whether there were any.
+    fn cancel(&mut self) -> bool {
+        let was_active = self.is_active();
+        self.superseded = self.task.is_some();
+        self.pending = None;
+        was_active
+    }
+
+    /// Waits for the running measurement, whose result is outdated if `superseded` is set.
+    async fn finished(&mut self) -> Option<Result<ShowMenu, tokio::task::JoinError>> {
+        let measured = self.task.as_mut()?.await;
+        self.task = None;
+        Some(measured)
+    }
+}
+
 async fn run_monitor_main(
     monitor: MonitorInfo,
     mut env: StartedMonitorEnv,
 ) -> anyhow::Result<std::convert::Infallible> {
+    // The panels have reported their sizes
+    systemd::set_bar_up(&monitor.name, true);
     let mut show_menu = None::<ShowMenu>;
+    // The menu that replaces `show_menu` once it is measured
+    let mut measuring_menu = MeasuringMenu::default();
+    // When the menu is opened or closed after [`host::OpenMenuOpts::open_delay`] or
+    // [`host::OpenMenuOpts::close_delay`]
+    let mut menu_open_at = None::<tokio::time::Instant>;
+    let mut menu_close_at = None::<tokio::time::Instant>;
     let mut bar_tui_state = super::BarTuiState {
         tui: tui::Elem::empty(),
         hidden: false,
     };
+    let mut show_interactive_until = None::<tokio::time::Instant>;
+    let mut frames = FrameScheduler::new(env.config.frame_interval);
+    // When the bar's size is considered stable, see [`RESIZE_SETTLE_DELAY`]
+    let mut bar_resize_settled = None::<tokio::time::Instant>;
+    // When the oldest bar content that has not been drawn yet arrived, to trace the latency
+    let mut bar_tui_received = None::<tokio::time::Instant>;
+    // When the menu that is about to be drawn for the first time was opened
+    let mut menu_opened = None::<tokio::time::Instant>;
+    // The last geometry and visibility sent to the menu panel. Restarted panels get them
+    // replayed, see [`TermState`].
+    let mut sent_menu_geometry = None::<PanelGeometry>;
+    let mut sent_menu_vis = None::<bool>;
+    let mut osd = OsdState::default();
+    let mut prompt = PromptState::default();
+    let mut swipes = super::gesture::SwipeRecognizer::default();
+    // Send the log level to the new panels
+    env.log_level_rx.mark_changed();
+    publish_bar_geometry(&monitor, env.bar.sizes, &env.query_state_tx, &env.event_tx);
+    let mut watchdog_probe = systemd::WatchdogProbe::new();
     loop {
         let mut rerender_menu = false;
+        let mut rerender_osd = false;
+        let mut rerender_prompt = false;
         let mut bar_tui_changed = false;
         let mut bar_vis_changed = false;
+        // Whether the rerender is caused by content updates rather than interactions
+        let mut coalesce = false;
 
         let upd = tokio::select! {
+            () = watchdog_probe.probed() => continue,
             Some(ev) = env.bar.term_ev_rx.recv() => Upd::Term(TermKind::Bar, ev),
             Some(ev) = env.menu.term_ev_rx.recv() => Upd::Term(TermKind::Menu, ev),
-            Ok(()) = env.bar_hide_rx.changed() => {
-                let hidden = *env.bar_hide_rx.borrow_and_update();
+            Some(ev) = env.osd.term_ev_rx.recv() => Upd::Term(TermKind::Osd, ev),
+            Some(ev) = env.prompt.term_ev_rx.recv() => Upd::Term(TermKind::Prompt, ev),
+            Ok(()) = env.bar_state_rx.changed() => {
+                let super::BarTuiState { tui, hidden } = env.bar_state_rx.borrow_and_update().clone();
                 bar_vis_changed = hidden != std::mem::replace(&mut bar_tui_state.hidden, hidden);
-                Upd::Noop
-            }
-            Ok(()) = env.bar_tui_rx.changed() => {
-                bar_tui_state.tui = env.bar_tui_rx.borrow_and_update().clone();
-                bar_tui_changed = true;
+                if !Arc::ptr_eq(&tui.0, &bar_tui_state.tui.0) {
+                    bar_tui_state.tui = tui;
+                    bar_tui_received.get_or_insert_with(tokio::time::Instant::now);
+                    bar_tui_changed = true;
+                    coalesce = true;
+                }
                 Upd::Noop
             },
             Ok(()) = env.open_menu_rx.changed() => {
                 let open = env.open_menu_rx.borrow_and_update().clone();
+                let was_measuring = measuring_menu.cancel();
+                menu_close_at = None;
                 if let Some(open) = open && open.monitor == monitor.name {
-                    ShowMenu::update(&mut show_menu, open, &env);
-                } else {
-                    if show_menu.is_none() {
+                    // Only the first menu waits, so that moving between tooltips is immediate
+                    let opening = show_menu.is_none() && !was_measuring;
+                    if opening && !open.opts.open_delay.is_zero() {
+                        menu_open_at.get_or_insert_with(|| {
+                            tokio::time::Instant::now() + open.opts.open_delay
+                        });
                         continue;
                     }
-                    show_menu = None;
+                    menu_open_at = None;
+                    if show_menu.is_none() {
+                        menu_opened.get_or_insert_with(tokio::time::Instant::now);
+                    }
+                    measuring_menu.start(open, show_menu.as_ref(), &monitor, &env);
+                    continue;
+                }
+                // The menu was closed before its open delay passed
+                menu_open_at = None;
+                menu_opened = None;
+                let Some(shown) = &show_menu else {
+                    continue;
+                };
+                if !shown.close_delay.is_zero() {
+                    menu_close_at = Some(tokio::time::Instant::now() + shown.close_delay);
+                    continue;
                 }
-                env.menu.layout = Default::default(); // TODO: optionally keep layout
+                show_menu = None;
+                env.menu.layout.reset();
                 rerender_menu = true;
+                bar_tui_changed = env.config.dim_bar_on_menu;
                 Upd::Noop
             },
+            Some(()) = async {
+                tokio::time::sleep_until(menu_open_at?).await;
+                Some(())
+            } => {
+                menu_open_at = None;
+                // Slot updates may have changed the menu since it was requested
+                let open = env.open_menu_rx.borrow().clone();
+                if let Some(open) = open && open.monitor == monitor.name {
+                    menu_opened.get_or_insert_with(tokio::time::Instant::now);
+                    measuring_menu.start(open, show_menu.as_ref(), &monitor, &env);
+                }
+                continue;
+            }
+            Some(()) = async {
+                tokio::time::sleep_until(menu_close_at?).await;
+                Some(())
+            } => {
+                menu_close_at = None;
+                if show_menu.take().is_none() {
+                    continue;
+                }
+                env.menu.layout.reset();
+                rerender_menu = true;
+                bar_tui_changed = env.config.dim_bar_on_menu;
+                Upd::Noop
+            }
+            Some(measured) = measuring_menu.finished() => {
+                if std::mem::take(&mut measuring_menu.superseded) {
+                    if let Some(open) = measuring_menu.pending.take() {
+                        measuring_menu.start(open, show_menu.as_ref(), &monitor, &env);
+                    }
+                    continue;
+                }
+                let Some(measured) = measured
+                    .context("Failed to measure menu")
+                    .ok_or_log()
+                else {
+                    continue;
+                };
+                // Refreshing the content of the shown menu is not latency sensitive
+                coalesce = show_menu
+                    .as_ref()
+                    .is_some_and(|it| it.bar_anchor == measured.bar_anchor);
+                let was_open = show_menu.replace(measured).is_some();
+                env.menu.layout.reset(); // TODO: optionally keep layout
+                rerender_menu = true;
+                if env.config.dim_bar_on_menu && !was_open {
+                    bar_tui_changed = true;
+                }
+                Upd::Noop
+            },
+            Ok(()) = env.osd_rx.changed() => {
+                let show = env.osd_rx.borrow_and_update().clone();
+                rerender_osd = osd.update(show, &monitor);
+                Upd::Noop
+            }
+            Some(()) = async {
+                tokio::time::sleep_until(osd.until?).await;
+                Some(())
+            } => {
+                rerender_osd = osd.hide();
+                Upd::Noop
+            }
+            Some(()) = async {
+                tokio::time::sleep_until(osd.panel.next_frame?).await;
+                Some(())
+            } => {
+                osd.panel.next_frame = None;
+                rerender_osd = true;
+                Upd::Noop
+            }
+            Ok(()) = env.prompt_rx.changed() => {
+                let open = env.prompt_rx.borrow_and_update().clone();
+                rerender_prompt = prompt.update(open, &monitor, &mut env.prompt, &env.event_tx);
+                Upd::Noop
+            }
+            Some(()) = async {
+                tokio::time::sleep_until(prompt.panel.next_frame?).await;
+                Some(())
+            } => {
+                prompt.panel.next_frame = None;
+                rerender_prompt = true;
+                Upd::Noop
+            }
+            Some(slot_upd) = async {
+                match env.menu_slot_rx.recv().await {
+                    Ok(upd) => Some(Some(upd)),
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => Some(None),
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => None,
+                }
+            } => {
+                match slot_upd {
+                    Some(super::MenuSlotUpdate { slot, tui }) if !measuring_menu.is_active() => {
+                        if let Some(menu) = &mut show_menu {
+                            rerender_menu = update_menu_slot(menu, &slot, &tui, &mut env.menu);
+                        }
+                    }
+                    _ => {
+                        // Some updates were missed or the menu they are for is still being
+                        // measured, but the stored menu has all of them
+                        let open = env.open_menu_rx.borrow().clone();
+                        if (show_menu.is_some() || measuring_menu.is_active())
+                            && let Some(open) = open
+                            && open.monitor == monitor.name
+                        {
+                            measuring_menu.start(open, show_menu.as_ref(), &monitor, &env);
+                        }
+                    }
+                }
+                Upd::Noop
+            }
+            Ok(()) = env.log_level_rx.changed() => {
+                if let Some(level) = *env.log_level_rx.borrow_and_update() {
+                    for term in [&env.bar, &env.menu, &env.osd, &env.prompt] {
+                        term.term_upd_tx.send(TermUpdate::SetLogLevel(level)).ok_or_debug();
+                    }
+                }
+                Upd::Noop
+            }
+            Ok(()) = env.show_interactive_rx.changed() => {
+                show_interactive_until = *env.show_interactive_rx.borrow_and_update();
+                bar_tui_changed = true;
+                rerender_menu = show_menu.is_some();
+                Upd::Noop
+            }
+            Some(()) = async {
+                tokio::time::sleep_until(show_interactive_until?).await;
+                Some(())
+            } => {
+                show_interactive_until = None;
+                bar_tui_changed = true;
+                rerender_menu = show_menu.is_some();
+                Upd::Noop
+            }
+            Some(()) = async {
+                tokio::time::sleep_until(frames.due?).await;
+                Some(())
+            } => {
+                (bar_tui_changed, rerender_menu) = frames.take_deferred();
+                Upd::Noop
+            }
+            Some(()) = async {
+                tokio::time::sleep_until(bar_resize_settled?).await;
+                Some(())
+            } => {
+                bar_resize_settled = None;
+                bar_tui_changed = true;
+                Upd::Noop
+            }
         };
+        if let Upd::Term(_, TermEvent::Crossterm(_)) = upd {
+            super::metrics::record_event();
+        }
         match upd {
             Upd::Noop => {}
             Upd::Term(term_kind, TermEvent::Crossterm(ev)) => match ev {
@@ -207,6 +622,8 @@ async fn run_monitor_main(
                     let term = match term_kind {
                         TermKind::Menu => &mut env.menu,
                         TermKind::Bar => &mut env.bar,
+                        TermKind::Osd => &mut env.osd,
+                        TermKind::Prompt => &mut env.prompt,
                     };
 
                     match term
@@ -225,6 +642,8 @@ async fn run_monitor_main(
                                 match term_kind {
                                     TermKind::Menu => rerender_menu = true,
                                     TermKind::Bar => bar_tui_changed = true,
+                                    TermKind::Osd => rerender_osd = true,
+                                    TermKind::Prompt => rerender_prompt = true,
                                 }
                             }
 
@@ -238,6 +657,8 @@ async fn run_monitor_main(
                                         host::TermEvent::Interact(host::InteractEvent {
                                             kind,
                                             tag,
+                                            geometry: matches!(term_kind, TermKind::Bar)
+                                                .then(|| bar_geometry(&monitor, term.sizes)),
                                         }),
                                     ))
                                     .ok_or_debug();
@@ -248,6 +669,8 @@ async fn run_monitor_main(
                                 match term_kind {
                                     TermKind::Menu => rerender_menu = true,
                                     TermKind::Bar => bar_tui_changed = true,
+                                    TermKind::Osd => rerender_osd = true,
+                                    TermKind::Prompt => rerender_prompt = true,
                                 }
                             }
 
@@ -262,6 +685,86 @@ async fn run_monitor_main(
                                 .ok_or_debug();
                         }
                     }
+
+                    if term_kind == TermKind::Bar
+                        && let Some(direction) =
+                            swipes.mouse_event(&ev, &env.bar.layout, env.bar.sizes.font_size())
+                    {
+                        env.event_tx
+                            .send(host::HostEvent::Gesture(host::Gesture::Swipe {
+                                direction,
+                                monitor: monitor.name.clone(),
+                            }))
+                            .ok_or_debug();
+                    }
+                }
+                crossterm::event::Event::Key(crossterm::event::KeyEvent {
+                    code: crossterm::event::KeyCode::Esc,
+                    kind: crossterm::event::KeyEventKind::Press,
+                    ..
+                }) if term_kind == TermKind::Prompt => {
+                    rerender_prompt =
+                        prompt.close(host::PromptCloseReason::Dismissed, &monitor, &env.event_tx);
+                }
+                crossterm::event::Event::Key(key) => {
+                    let term = match term_kind {
+                        TermKind::Menu => &mut env.menu,
+                        TermKind::Bar => &mut env.bar,
+                        TermKind::Osd => &mut env.osd,
+                        TermKind::Prompt => &mut env.prompt,
+                    };
+                    let term_info = host::TermInfo {
+                        monitor: monitor.name.clone(),
+                        kind: term_kind.into(),
+                    };
+                    // A focused text input takes all key presses
+                    let res = term.layout.interpret_key_event(key);
+                    if let Some(tui::InputRes::Changed { .. } | tui::InputRes::Redraw) = res {
+                        match term_kind {
+                            TermKind::Menu => rerender_menu = true,
+                            TermKind::Bar => bar_tui_changed = true,
+                            TermKind::Osd => rerender_osd = true,
+                            TermKind::Prompt => rerender_prompt = true,
+                        }
+                    }
+                    let ev = match res {
+                        Some(tui::InputRes::Changed { tag, text }) => {
+                            Some(host::TermEvent::InputChanged(host::InputEvent {
+                                tag,
+                                text,
+                            }))
+                        }
+                        Some(tui::InputRes::Submitted { tag, text }) => {
+                            Some(host::TermEvent::InputSubmitted(host::InputEvent {
+                                tag,
+                                text,
+                            }))
+                        }
+                        Some(tui::InputRes::Redraw) => None,
+                        Some(tui::InputRes::Ignored) => key_event(key).map(host::TermEvent::Key),
+                        None => match key {
+                            crossterm::event::KeyEvent {
+                                code: crossterm::event::KeyCode::Char(c),
+                                kind: crossterm::event::KeyEventKind::Press,
+                                ..
+                            } if show_interactive_until.is_some() => {
+                                term.layout.hint_target(c).map(|(tag, kind)| {
+                                    host::TermEvent::Interact(host::InteractEvent {
+                                        kind,
+                                        tag: Some(tag),
+                                        geometry: matches!(term_kind, TermKind::Bar)
+                                            .then(|| bar_geometry(&monitor, term.sizes)),
+                                    })
+                                })
+                            }
+                            _ => key_event(key).map(host::TermEvent::Key),
+                        },
+                    };
+                    if let Some(ev) = ev {
+                        env.event_tx
+                            .send(host::HostEvent::Term(term_info, ev))
+                            .ok_or_debug();
+                    }
                 }
                 _ => {
                     //
@@ -269,129 +772,191 @@ async fn run_monitor_main(
             },
             Upd::Term(TermKind::Menu, TermEvent::Sizes(sizes)) => {
                 if sizes.font_size() != env.menu.sizes.font_size() {
+                    env.menu.layout.force_full_redraw();
                     rerender_menu = true;
                 }
                 env.menu.sizes = sizes;
             }
+            Upd::Term(TermKind::Osd, TermEvent::Sizes(sizes)) => {
+                if sizes.font_size() != env.osd.sizes.font_size() {
+                    env.osd.layout.force_full_redraw();
+                    rerender_osd = osd.shown.is_some();
+                }
+                env.osd.sizes = sizes;
+            }
+            Upd::Term(TermKind::Prompt, TermEvent::Sizes(sizes)) => {
+                if sizes.font_size() != env.prompt.sizes.font_size() {
+                    env.prompt.layout.force_full_redraw();
+                    rerender_prompt = prompt.shown.is_some();
+                }
+                env.prompt.sizes = sizes;
+            }
             Upd::Term(TermKind::Bar, TermEvent::Sizes(sizes)) => {
+                publish_bar_geometry(&monitor, sizes, &env.query_state_tx, &env.event_tx);
                 env.bar.sizes = sizes;
-                bar_tui_changed = true;
+                env.bar.layout.force_full_redraw();
+                bar_resize_settled = Some(tokio::time::Instant::now() + RESIZE_SETTLE_DELAY);
+            }
+            Upd::Term(_, TermEvent::Log(record)) => {
+                if env.config.forward_logs {
+                    env.event_tx
+                        .send(host::HostEvent::Log(record))
+                        .ok_or_debug();
+                }
+            }
+            Upd::Term(term_kind, TermEvent::PanelIds(ids)) => {
+                env.panels_tx.send_modify(|panels| {
+                    panels.insert(
+                        host::TermInfo {
+                            monitor: monitor.name.clone(),
+                            kind: term_kind.into(),
+                        },
+                        ids,
+                    );
+                });
             }
         }
 
-        if rerender_menu {
-            if let Some(&ShowMenu {
-                pix_location: location,
-                cached_size: cached_tui_size,
-                ref tui,
-                ref sizing,
-                bar_anchor: _,
-            }) = show_menu.as_ref()
-            {
-                // HACK: This minimizes the rounding error for some reason (as far as I can tell).
-                let scale = (monitor.scale * 1000.0).ceil() / 1000.0;
-
-                // NOTE: There is no absolute positioning system, nor a way to directly specify the
-                // geometry (since this is controlled by the compositor). So we have to get creative by
-                // using the right and left margin to control both position and size of the panel.
-
-                let lines = cached_tui_size.y.saturating_add(VERTICAL_PADDING.into());
+        if coalesce && frames.defer(bar_tui_changed, rerender_menu) {
+            continue;
+        }
+        frames.rendering(bar_tui_changed, rerender_menu);
 
-                // Find the distance between window edge and center
-                let half_pix_w = {
-                    let cell_pix_w = u32::from(env.menu.sizes.font_size().x);
-                    let cell_w = cached_tui_size.x + HORIZONTAL_PADDING;
-                    let pix_w = u32::from(cell_w) * cell_pix_w;
-                    pix_w.div_ceil(2)
-                };
+        if rerender_menu {
+            let _span = tracing::debug_span!("render_menu", monitor = %monitor.name).entered();
+            if let Some(menu) = show_menu.as_ref() {
+                let geometry = menu.geometry(&monitor, env.menu.sizes.font_size());
+                // Each remote control command starts a process, which transitions would
+                // otherwise do every frame
+                if sent_menu_geometry.as_ref() != Some(&geometry) {
+                    env.menu.set_geometry(&geometry);
+                    sent_menu_geometry = Some(geometry);
+                }
 
-                // Clamp position such that we fit. Note that this does not guarantee
-                // that there is enough space for the entire width.
-                let x = location.x.clamp(
-                    half_pix_w, //
-                    monitor.width.saturating_sub(half_pix_w),
-                );
-
-                // The left margin should be such that half the space is between
-                // left margin and x. Use saturating_sub so that the left
-                // margin becomes zero if the width would reach outside the screen.
-                let mleft = x.saturating_sub(half_pix_w);
-
-                // The right margin is calculated the same way, but starting from the right edge.
-                let mright = (monitor.width - x).saturating_sub(half_pix_w);
-
-                // The font size (on which cell->pixel conversion is based) and the monitor's
-                // size are in physical pixels. This makes sense because different monitors can
-                // have different scales, and the application should not be affected by that
-                // (this is not x11 after all).
-                // However, panels are bound to a monitor and the margins are in scaled pixels,
-                // so we have to make this correction.
-                let margin_left = (f64::from(mleft) / scale) as u32;
-                let margin_right = (f64::from(mright) / scale) as u32;
+                let render_start = tokio::time::Instant::now();
+                let mut buf = env.menu.bufs.take();
 
                 env.menu
-                    .term_upd_tx
-                    .send(TermUpdate::RemoteControl(vec![
-                        "resize-os-window".into(),
-                        "--incremental".into(),
-                        "--action=os-panel".into(),
-                        format!("margin-left={margin_left}").into(),
-                        format!("margin-right={margin_right}").into(),
-                        format!("lines={lines}").into(),
-                    ]))
-                    .ok_or_log();
-
-                let mut buf = Vec::new();
+                    .layout
+                    .set_showing_hints(show_interactive_until.is_some());
 
                 // NOTE: The terminal might not be done resizing at this point,
                 // which would cause issues if passing the terminal's size here.
                 // Passing the tui's desired size sidesteps this because kitty
                 // will rerender it correctly once the resize is done.
-                if let Some(layout) = tui::render(
-                    tui,
-                    tui::Area {
-                        size: cached_tui_size,
-                        pos: tui::Vec2 {
-                            x: HORIZONTAL_PADDING / 2,
-                            y: 0,
-                        },
+                let area = tui::Area {
+                    size: menu.cached_size,
+                    pos: tui::Vec2 {
+                        x: HORIZONTAL_PADDING / 2,
+                        y: 0,
                     },
-                    &mut buf,
-                    sizing,
-                    &env.menu.layout,
-                )
-                .context("Failed to draw menu")
-                .ok_or_log()
+                };
+                if let Some(layout) =
+                    tui::render(&menu.tui, area, &mut buf, &menu.sizing, &env.menu.layout)
+                        .context("Failed to draw menu")
+                        .ok_or_log()
                 {
                     env.menu.layout = layout;
+                    frames.animate(false, env.menu.layout.is_animating());
+                    check_interactive_sizes(
+                        &mut env.menu,
+                        TermKind::Menu,
+                        area,
+                        &monitor,
+                        &env.config,
+                        &env.event_tx,
+                    );
+                    super::metrics::record_render(
+                        &host::TermInfo {
+                            monitor: monitor.name.clone(),
+                            kind: host::TermKind::Menu,
+                        },
+                        render_start.elapsed(),
+                        buf.len(),
+                    );
                     env.menu
                         .term_upd_tx
                         .send(TermUpdate::Print(buf))
                         .ok_or_log();
                     env.menu.term_upd_tx.send(TermUpdate::Flush).ok_or_log();
+                    if let Some(opened) = menu_opened.take() {
+                        super::metrics::record_menu_open(opened.elapsed());
+                    }
                 }
             }
 
-            // FIXME: Only send when necessary
-            env.menu
-                .term_upd_tx
-                .send(set_vis_update(show_menu.is_some()))
-                .ok_or_debug();
+            if sent_menu_vis != Some(show_menu.is_some()) {
+                sent_menu_vis = Some(show_menu.is_some());
+                env.menu.set_visible(show_menu.is_some());
+            }
+        }
+
+        if rerender_osd {
+            let _span = tracing::debug_span!("render_osd", monitor = %monitor.name).entered();
+            match &osd.shown {
+                Some(shown) => {
+                    let size = tui::calc_min_size(&shown.tui, &env.osd.sizing());
+                    osd.panel.show(
+                        &mut env.osd,
+                        TermKind::Osd,
+                        &shown.tui,
+                        size,
+                        shown.position,
+                        &monitor,
+                        env.config.frame_interval,
+                    );
+                }
+                None => osd.panel.hide(&mut env.osd),
+            }
         }
+        if rerender_prompt {
+            let _span = tracing::debug_span!("render_prompt", monitor = %monitor.name).entered();
+            match &prompt.shown {
+                Some(shown) => {
+                    let size = tui::calc_min_size(&shown.tui, &env.prompt.sizing());
+                    let size = tui::Vec2 {
+                        x: shown.opts.columns.unwrap_or(size.x),
+                        y: shown.opts.lines.unwrap_or(size.y),
+                    };
+                    prompt.panel.show(
+                        &mut env.prompt,
+                        TermKind::Prompt,
+                        &shown.tui,
+                        size,
+                        host::OsdPosition::Center,
+                        &monitor,
+                        env.config.frame_interval,
+                    );
+                }
+                None => prompt.panel.hide(&mut env.prompt),
+            }
+        }
+
+        // Renders during a resize are redone once it settles
+        if !bar_tui_state.hidden
+            && bar_resize_settled.is_none()
+            && (bar_vis_changed || bar_tui_changed)
+        {
+            let _span = tracing::debug_span!("render_bar", monitor = %monitor.name).entered();
+            let render_start = tokio::time::Instant::now();
+            let mut buf = env.bar.bufs.take();
 
-        if !bar_tui_state.hidden && (bar_vis_changed || bar_tui_changed) {
-            let mut buf = Vec::new();
+            env.bar
+                .layout
+                .set_dimmed(env.config.dim_bar_on_menu && show_menu.is_some());
+            env.bar
+                .layout
+                .set_showing_hints(show_interactive_until.is_some());
 
+            let area = tui::Area {
+                size: env.bar.sizes.cell_size,
+                pos: Default::default(),
+            };
             let Some(layout) = tui::render(
                 &bar_tui_state.tui,
-                tui::Area {
-                    size: env.bar.sizes.cell_size,
-                    pos: Default::default(),
-                },
+                area,
                 &mut buf,
-                &tui::SizingArgs {
-                    font_size: env.bar.sizes.font_size(),
-                },
+                &env.bar.sizing(),
                 &env.bar.layout,
             )
             .context("Failed to render bar")
@@ -399,60 +964,469 @@ async fn run_monitor_main(
                 continue;
             };
             env.bar.layout = layout;
+            frames.animate(env.bar.layout.is_animating(), false);
+            // Keep the open menu at its anchor if the anchor moved. If the anchor is gone, the
+            // menu stays where it is until the controller closes it.
+            if let Some(menu) = &mut show_menu
+                && let Some(location) = env
+                    .bar
+                    .layout
+                    .get_pix_location(env.bar.sizes.font_size(), &menu.bar_anchor)
+                && location != menu.pix_location
+            {
+                menu.pix_location = location;
+                let geometry = menu.geometry(&monitor, env.menu.sizes.font_size());
+                if sent_menu_geometry.as_ref() != Some(&geometry) {
+                    env.menu.set_geometry(&geometry);
+                    sent_menu_geometry = Some(geometry);
+                }
+            }
+            check_interactive_sizes(
+                &mut env.bar,
+                TermKind::Bar,
+                area,
+                &monitor,
+                &env.config,
+                &env.event_tx,
+            );
+
+            super::metrics::record_render(
+                &host::TermInfo {
+                    monitor: monitor.name.clone(),
+                    kind: host::TermKind::Bar,
+                },
+                render_start.elapsed(),
+                buf.len(),
+            );
 
             env.bar
                 .term_upd_tx
                 .send(TermUpdate::Print(buf))
                 .ok_or_debug();
             env.bar.term_upd_tx.send(TermUpdate::Flush).ok_or_debug();
+            if let Some(received) = bar_tui_received.take() {
+                tracing::debug!(latency = ?received.elapsed(), "Sent bar content to the panel");
+            }
         }
         if bar_vis_changed {
-            env.bar
-                .term_upd_tx
-                .send(set_vis_update(!bar_tui_state.hidden))
-                .ok_or_debug();
+            env.bar.set_visible(!bar_tui_state.hidden);
+        }
+    }
+}
+
+/// The popup of [`host::HostUpdate::ShowOsd`] on this monitor.
+#[derive(Default)]
+struct OsdState {
+    shown: Option<host::ShowOsd>,
+    /// When the shown popup hides itself.
+    until: Option<tokio::time::Instant>,
+    panel: PopupPanel,
+}
+impl OsdState {
+    /// Returns whether the popup needs to be rerendered.
+    fn update(&mut self, show: Option<host::ShowOsd>, monitor: &MonitorInfo) -> bool {
+        match show {
+            Some(show) if show.monitor.as_ref().is_none_or(|it| *it == monitor.name) => {
+                self.until = Some(tokio::time::Instant::now() + show.timeout);
+                self.shown = Some(show);
+                true
+            }
+            // There is only one popup, even if it is shown on another monitor
+            _ => self.hide(),
+        }
+    }
+
+    /// Returns whether the popup needs to be rerendered.
+    fn hide(&mut self) -> bool {
+        self.until = None;
+        self.shown.take().is_some()
+    }
+}
+
+/// The prompt of [`host::HostUpdate::OpenPrompt`] on this monitor.
+#[derive(Default)]
+struct PromptState {
+    shown: Option<host::OpenPrompt>,
+    panel: PopupPanel,
+}
+impl PromptState {
+    /// Returns whether the prompt needs to be rerendered.
+    fn update(
+        &mut self,
+        open: Option<host::OpenPrompt>,
+        monitor: &MonitorInfo,
+        term: &mut Term,
+        event_tx: &std::sync::mpsc::Sender<host::HostEvent>,
+    ) -> bool {
+        match open {
+            Some(open) if open.monitor == monitor.name => {
+                // Updates of the open prompt keep what the user typed and where
+                if self.shown.is_none()
+                    && let Some(focus) = &open.opts.focus
+                {
+                    term.layout.focus_input(focus.clone());
+                }
+                self.shown = Some(open);
+                true
+            }
+            Some(_) => self.close(host::PromptCloseReason::Replaced, monitor, event_tx),
+            None => self.close(host::PromptCloseReason::Closed, monitor, event_tx),
+        }
+    }
+
+    /// Returns whether the prompt needs to be rerendered.
+    fn close(
+        &mut self,
+        reason: host::PromptCloseReason,
+        monitor: &MonitorInfo,
+        event_tx: &std::sync::mpsc::Sender<host::HostEvent>,
+    ) -> bool {
+        if self.shown.take().is_none() {
+            return false;
+        }
+        event_tx
+            .send(host::HostEvent::PromptClosed(host::PromptClosed {
+                monitor: monitor.name.clone(),
+                reason,
+            }))
+            .ok_or_debug();
+        true
+    }
+}
+
+/// How far a popup at [`host::OsdPosition::Top`] or [`host::OsdPosition::Bottom`] is from the
+/// edge of the monitor, as a fraction of the monitor's height.
+const OSD_EDGE_DISTANCE: u32 = 10;
+
+/// A panel that is shown above the other windows on demand, like the OSD and the prompt.
+#[derive(Default)]
+struct PopupPanel {
+    /// When the next frame of a running transition is due. Popups are not coalesced with the
+    /// bar and the menu by the [`FrameScheduler`], since they are only shown briefly.
+    next_frame: Option<tokio::time::Instant>,
+    /// Like the menu's, see [`run_monitor_main`].
+    sent_geometry: Option<PanelGeometry>,
+    sent_vis: Option<bool>,
+}
+impl PopupPanel {
+    fn hide(&mut self, term: &mut Term) {
+        self.next_frame = None;
+        // Transitions are replayed the next time the panel is shown
+        term.layout.reset();
+        if self.sent_vis != Some(false) {
+            self.sent_vis = Some(false);
+            term.set_visible(false);
+        }
+    }
+
+    /// Resizes the panel to `size` cells at `position`, draws `tui` into it and shows it.
+    #[expect(clippy::too_many_arguments)]
+    fn show(
+        &mut self,
+        term: &mut Term,
+        kind: TermKind,
+        tui: &tui::Elem,
+        size: tui::Vec2<u16>,
+        position: host::OsdPosition,
+        monitor: &MonitorInfo,
+        frame_interval: Duration,
+    ) {
+        // See the menu's positioning in [`run_monitor_main`]
+        let scale = (monitor.scale * 1000.0).ceil() / 1000.0;
+        let font_size = term.sizes.font_size();
+
+        let pix_w = u32::from(size.x.saturating_add(HORIZONTAL_PADDING)) * u32::from(font_size.x);
+        let pix_h = u32::from(size.y) * u32::from(font_size.y);
+        let free_w = monitor.width.saturating_sub(pix_w);
+        let free_h = monitor.height.saturating_sub(pix_h);
+        let edge_distance = (monitor.height / OSD_EDGE_DISTANCE).min(free_h);
+        let mtop = match position {
+            host::OsdPosition::Top => edge_distance,
+            host::OsdPosition::Center => free_h / 2,
+            host::OsdPosition::Bottom => free_h - edge_distance,
+        };
+        let mleft = free_w / 2;
+        let mright = free_w - mleft;
+
+        let geometry = PanelGeometry {
+            margin_left: (f64::from(mleft) / scale) as u32,
+            margin_right: (f64::from(mright) / scale) as u32,
+            margin_top: Some((f64::from(mtop) / scale) as u32),
+            lines: size.y,
+        };
+        if self.sent_geometry.as_ref() != Some(&geometry) {
+            term.set_geometry(&geometry);
+            self.sent_geometry = Some(geometry);
+        }
+
+        let render_start = tokio::time::Instant::now();
+        let mut buf = term.bufs.take();
+        let area = tui::Area {
+            size,
+            pos: tui::Vec2 {
+                x: HORIZONTAL_PADDING / 2,
+                y: 0,
+            },
+        };
+        let Some(layout) = tui::render(tui, area, &mut buf, &term.sizing(), &term.layout)
+            .with_context(|| format!("Failed to draw {kind:?} panel"))
+            .ok_or_log()
+        else {
+            return;
+        };
+        term.layout = layout;
+        self.next_frame = term
+            .layout
+            .is_animating()
+            .then(|| tokio::time::Instant::now() + frame_interval);
+        super::metrics::record_render(
+            &host::TermInfo {
+                monitor: monitor.name.clone(),
+                kind: kind.into(),
+            },
+            render_start.elapsed(),
+            buf.len(),
+        );
+        term.term_upd_tx.send(TermUpdate::Print(buf)).ok_or_log();
+        term.term_upd_tx.send(TermUpdate::Flush).ok_or_log();
+
+        if self.sent_vis != Some(true) {
+            self.sent_vis = Some(true);
+            term.set_visible(true);
         }
     }
 }
 
-fn set_vis_update(vis: bool) -> TermUpdate {
-    let action = if vis { "show" } else { "hide" };
-    TermUpdate::RemoteControl(vec![
-        "resize-os-window".into(),
-        format!("--action={}", action).into(),
-    ])
+fn bar_geometry(monitor: &MonitorInfo, sizes: tui::Sizes) -> host::BarGeometry {
+    host::BarGeometry {
+        monitor: monitor.name.clone(),
+        cells: sizes.cell_size.into(),
+        font_px: sizes.font_size().into(),
+        scale: monitor.scale,
+    }
+}
+
+/// Stores the bar's geometry for [`host::Query::BarSize`] and sends
+/// [`host::HostEvent::BarGeometry`] if it changed.
+fn publish_bar_geometry(
+    monitor: &MonitorInfo,
+    sizes: tui::Sizes,
+    query_state_tx: &watch::Sender<super::QueryState>,
+    event_tx: &std::sync::mpsc::Sender<host::HostEvent>,
+) {
+    let geometry = bar_geometry(monitor, sizes);
+    let changed = query_state_tx.send_if_modified(|it| {
+        it.bar_geometry
+            .insert(monitor.name.clone(), geometry.clone())
+            .is_none_or(|old| old != geometry)
+    });
+    if changed {
+        event_tx
+            .send(host::HostEvent::BarGeometry(geometry))
+            .ok_or_debug();
+    }
 }
 
+/// Converts a key press for [`host::TermEvent::Key`].
+fn key_event(key: crossterm::event::KeyEvent) -> Option<host::KeyEvent> {
+    use crossterm::event::{KeyCode, KeyEventKind, KeyModifiers};
+
+    if key.kind == KeyEventKind::Release {
+        return None;
+    }
+    let mut modifiers = host::KeyModifiers {
+        shift: key.modifiers.contains(KeyModifiers::SHIFT),
+        ctrl: key.modifiers.contains(KeyModifiers::CONTROL),
+        alt: key.modifiers.contains(KeyModifiers::ALT),
+    };
+    let key = match key.code {
+        KeyCode::Char(c) => host::Key::Char(c),
+        KeyCode::Enter => host::Key::Enter,
+        KeyCode::Esc => host::Key::Escape,
+        KeyCode::Tab => host::Key::Tab,
+        KeyCode::BackTab => {
+            modifiers.shift = true;
+            host::Key::Tab
+        }
+        KeyCode::Backspace => host::Key::Backspace,
+        KeyCode::Delete => host::Key::Delete,
+        KeyCode::Insert => host::Key::Insert,
+        KeyCode::Up => host::Key::Up,
+        KeyCode::Down => host::Key::Down,
+        KeyCode::Left => host::Key::Left,
+        KeyCode::Right => host::Key::Right,
+        KeyCode::Home => host::Key::Home,
+        KeyCode::End => host::Key::End,
+        KeyCode::PageUp => host::Key::PageUp,
+        KeyCode::PageDown => host::Key::PageDown,
+        KeyCode::F(n) => host::Key::F(n),
+        _ => return None,
+    };
+    Some(host::KeyEvent { key, modifiers })
+}
+
+/// Sends [`host::HostEvent::SmallInteractive`] for the elements of a freshly rendered layout
+/// that are smaller than [`host::HostConnectOpts::min_interactive_size`] and were not already
+/// too small after the previous render.
+fn check_interactive_sizes(
+    term: &mut Term,
+    kind: TermKind,
+    bounds: tui::Area,
+    monitor: &MonitorInfo,
+    config: &super::HostConfig,
+    event_tx: &std::sync::mpsc::Sender<host::HostEvent>,
+) {
+    let Some(host::MinInteractiveSize {
+        size,
+        expand_hitboxes,
+        ..
+    }) = &config.min_interactive_size
+    else {
+        return;
+    };
+    let small = term
+        .layout
+        .check_interactive_sizes((*size).into(), bounds, *expand_hitboxes);
+    for (tag, size) in &small {
+        if term.small_interactive.contains(tag) {
+            continue;
+        }
+        event_tx
+            .send(host::HostEvent::SmallInteractive(host::SmallInteractive {
+                term: host::TermInfo {
+                    monitor: monitor.name.clone(),
+                    kind: kind.into(),
+                },
+                tag: tag.clone(),
+                size: (*size).into(),
+            }))
+            .ok_or_debug();
+    }
+    term.small_interactive = small.into_iter().map(|(tag, _)| tag).collect();
+}
+
+/// Coalesces rerenders caused by content updates into at most one frame per interval, so that
+/// only the latest state is rendered. Rerenders caused by interactions are not delayed.
+struct FrameScheduler {
+    interval: Duration,
+    last_frame: Option<tokio::time::Instant>,
+    /// When the deferred rerenders should happen.
+    due: Option<tokio::time::Instant>,
+    deferred_bar: bool,
+    deferred_menu: bool,
+}
+impl FrameScheduler {
+    fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_frame: None,
+            due: None,
+            deferred_bar: false,
+            deferred_menu: false,
+        }
+    }
+
+    /// Defers the rerenders if the last frame was too recent. Returns whether they were deferred.
+    fn defer(&mut self, bar: bool, menu: bool) -> bool {
+        let Some(next_frame) = self.last_frame.map(|it| it + self.interval) else {
+            return false;
+        };
+        if tokio::time::Instant::now() >= next_frame {
+            return false;
+        }
+        self.deferred_bar |= bar;
+        self.deferred_menu |= menu;
+        self.due = Some(next_frame);
+        true
+    }
+
+    /// Schedules another frame after the interval, to advance running transitions.
+    fn animate(&mut self, bar: bool, menu: bool) {
+        if !bar && !menu {
+            return;
+        }
+        let next_frame = self.last_frame.unwrap_or_else(tokio::time::Instant::now) + self.interval;
+        self.deferred_bar |= bar;
+        self.deferred_menu |= menu;
+        self.due = Some(self.due.map_or(next_frame, |it| it.min(next_frame)));
+    }
+
+    fn take_deferred(&mut self) -> (bool, bool) {
+        self.due = None;
+        (
+            std::mem::take(&mut self.deferred_bar),
+            std::mem::take(&mut self.deferred_menu),
+        )
+    }
+
+    /// Records a frame. Deferred rerenders of the rendered terms are no longer needed.
+    fn rendering(&mut self, bar: bool, menu: bool) {
+        if !bar && !menu {
+            return;
+        }
+        self.last_frame = Some(tokio::time::Instant::now());
+        self.deferred_bar &= !bar;
+        self.deferred_menu &= !menu;
+        if !self.deferred_bar && !self.deferred_menu {
+            self.due = None;
+        }
+    }
+}
+
+/// Applies a slot update to the shown menu, redrawing only the slot if possible.
+///
+/// Returns whether the whole menu needs to be rerendered.
+fn update_menu_slot(
+    menu: &mut ShowMenu,
+    slot: &tui::CustomId,
+    content: &tui::Elem,
+    term: &mut Term,
+) -> bool {
+    let Some((new_tui, old_content)) = menu.tui.replace_slot(slot, content) else {
+        return false;
+    };
+    menu.tui = new_tui;
+
+    let new_size = tui::calc_min_size(&menu.tui, &menu.sizing);
+    let area = term.layout.slot_area(slot);
+    if let Some(area) = area
+        && new_size == menu.cached_size
+        && tui::calc_min_size(&old_content, &menu.sizing)
+            == tui::calc_min_size(content, &menu.sizing)
+    {
+        let mut buf = term.bufs.take();
+        if tui::render_slot(content, area, &mut buf, &menu.sizing, &mut term.layout)
+            .context("Failed to draw menu slot")
+            .ok_or_log()
+            .is_some()
+        {
+            term.term_upd_tx.send(TermUpdate::Print(buf)).ok_or_log();
+            term.term_upd_tx.send(TermUpdate::Flush).ok_or_log();
+        }
+        false
+    } else {
+        menu.cached_size = new_size;
+        true
+    }
+}
+
+/// Starts a panel and keeps it running, restarting it if it dies.
 async fn init_term(
-    sock_path: std::path::PathBuf,
-    log_name: String,
-    extra_args: impl IntoIterator<Item = OsString>,
-    extra_envs: impl IntoIterator<Item = (OsString, OsString)>,
+    config: &super::HostConfig,
+    spec: PanelSpec,
     cancel: &CancellationToken,
 ) -> anyhow::Result<Term> {
-    let (term_upd_tx, mut term_upd_rx) = {
-        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
-        (tx, rx)
-    };
-    let (term_ev_tx, mut term_ev_rx) = {
-        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
-        (tx, rx)
-    };
+    let backend = config.backend.clone();
+    let (term_upd_tx, term_upd_rx) = tokio::sync::mpsc::unbounded_channel();
+    let (term_ev_tx, mut term_ev_rx) = tokio::sync::mpsc::unbounded_channel();
 
-    crate::bins::inst::start_generic_panel(
-        &sock_path,
-        &log_name,
-        futures::stream::poll_fn(move |cx| term_upd_rx.poll_recv(cx)),
-        extra_args,
-        extra_envs,
-        term_ev_tx,
-        cancel.clone(),
-    )
-    .await?;
+    let panel = backend.spawn(&spec, term_ev_tx.clone(), cancel).await?;
 
     let sizes = loop {
         match term_ev_rx.recv().await {
             Some(TermEvent::Sizes(sizes)) => break sizes,
+            // Logs from before the panel knows its size are not forwarded
+            Some(TermEvent::Log(_)) => {}
             Some(ev) => {
                 log::error!("Ignoring term event {ev:?}. The first event should be _::Sizes");
             }
@@ -462,15 +1436,201 @@ async fn init_term(
         }
     };
 
+    tokio::spawn(supervise_term(
+        backend.clone(),
+        spec,
+        panel,
+        term_upd_rx,
+        term_ev_tx,
+        cancel.clone(),
+    ));
+
     anyhow::Ok(Term {
         sizes,
+        ambiguous_width: config.ambiguous_width,
         layout: Default::default(),
+        small_interactive: Default::default(),
+        bufs: Default::default(),
         term_ev_rx,
         term_upd_tx,
+        backend,
     })
 }
 
-const NERD_FONT_CONFIG_OVERRIDE: &str = "-o=symbol_map U+e000-U+e00a,U+ea60-U+ebeb,U+e0a0-U+e0c8,U+e0ca,U+e0cc-U+e0d7,U+e200-U+e2a9,U+e300-U+e3e3,U+e5fa-U+e6b7,U+e700-U+e8ef,U+ed00-U+efc1,U+f000-U+f2ff,U+f000-U+f2e0,U+f300-U+f381,U+f400-U+f533,U+f0001-U+f1af0 Symbols Nerd Font Mono";
+/// How many updates can be queued for a panel. If a panel stops reading its socket, further
+/// updates are only recorded in its [`TermState`] until the queue is empty again, and are then
+/// replaced by its replay. Otherwise, the queue would grow without bound.
+pub(super) const PANEL_QUEUE_CAPACITY: usize = 64;
+
+const PANEL_RESTART_DELAY_MIN: Duration = Duration::from_secs(1);
+const PANEL_RESTART_DELAY_MAX: Duration = Duration::from_secs(60);
+/// Panels that stay up for this long reset the restart backoff.
+const PANEL_HEALTHY_AFTER: Duration = Duration::from_secs(60);
+
+/// Forwards updates to the current panel. If it dies, it is replaced by a new one, which is sent
+/// the updates needed to restore its state.
+///
+/// Other panels on the monitor are not affected. The restarted panel reports its size like any
+/// new panel, which makes the monitor rerender it.
+async fn supervise_term(
+    backend: Arc<dyn PanelBackend>,
+    spec: PanelSpec,
+    mut panel: Panel,
+    mut term_upd_rx: tokio::sync::mpsc::UnboundedReceiver<TermUpdate>,
+    term_ev_tx: UnboundedSender<TermEvent>,
+    cancel: CancellationToken,
+) {
+    let log_name = &spec.log_name;
+    let mut state = TermState::default();
+    // Set while the panel's queue is full. Holds the commands that are not part of the state
+    // and have to be sent after the replay.
+    let mut behind = None::<Vec<TermUpdate>>;
+    let mut restart_delay = PANEL_RESTART_DELAY_MIN;
+    let mut started = tokio::time::Instant::now();
+    loop {
+        // The permits borrow the panel, so it is replaced after the select
+        let exited = tokio::select! {
+            upd = term_upd_rx.recv() => {
+                let Some(upd) = upd else {
+                    break;
+                };
+                state.record(&upd);
+                let upd = match behind {
+                    Some(_) => upd,
+                    None => match panel.upd_tx.try_send(upd) {
+                        // A closed panel is restarted with the replay
+                        Ok(()) | Err(tokio::sync::mpsc::error::TrySendError::Closed(_)) => continue,
+                        Err(tokio::sync::mpsc::error::TrySendError::Full(upd)) => {
+                            log::warn!("Panel {log_name} is not keeping up, skipping to its latest frame");
+                            upd
+                        }
+                    },
+                };
+                let commands = behind.get_or_insert_default();
+                // Everything else is part of the state
+                if let TermUpdate::Shell(..) = upd {
+                    if commands.len() < PANEL_QUEUE_CAPACITY / 2 {
+                        commands.push(upd);
+                    } else {
+                        log::warn!("Panel {log_name} is not keeping up, dropping {upd:?}");
+                    }
+                }
+                false
+            }
+            Ok(permits) = panel.upd_tx.reserve_many(PANEL_QUEUE_CAPACITY), if behind.is_some() => {
+                let commands = behind.take().unwrap_or_default();
+                // The replay starts with a full frame, so the skipped updates are not needed. It is
+                // a handful of updates, so it fits next to the capped commands.
+                for (permit, upd) in permits.zip(state.replay().chain(commands)) {
+                    permit.send(upd);
+                }
+                false
+            }
+            () = panel.cancel.cancelled(), if !cancel.is_cancelled() => true,
+            () = cancel.cancelled() => break,
+        };
+        if !exited {
+            continue;
+        }
+
+        if started.elapsed() >= PANEL_HEALTHY_AFTER {
+            restart_delay = PANEL_RESTART_DELAY_MIN;
+        }
+        panel = loop {
+            log::warn!("Panel {log_name} exited, restarting it in {restart_delay:?}");
+            tokio::select! {
+                () = tokio::time::sleep(restart_delay) => {}
+                () = cancel.cancelled() => return,
+            }
+            restart_delay = (restart_delay * 2).min(PANEL_RESTART_DELAY_MAX);
+
+            if let Some(panel) = backend
+                .spawn(&spec, term_ev_tx.clone(), &cancel)
+                .await
+                .with_context(|| format!("Failed to restart panel {log_name}"))
+                .ok_or_log()
+            {
+                break panel;
+            }
+        };
+        started = tokio::time::Instant::now();
+        // Sends the replay
+        behind.get_or_insert_default();
+    }
+}
+
+/// The updates needed to bring a new panel into the state of the previous one.
+#[derive(Default)]
+struct TermState {
+    /// The output since the last full frame.
+    frame: Vec<u8>,
+    /// The transmissions of the images that the terminal has, which full frames only contain
+    /// for new images.
+    images: HashMap<std::num::NonZeroU32, Vec<u8>>,
+    /// The last remote control command of each kind, see [`TermState::remote_control_key`].
+    remote_control: Vec<(Vec<OsString>, Vec<OsString>)>,
+    log_level: Option<log::LevelFilter>,
+}
+impl TermState {
+    fn record(&mut self, upd: &TermUpdate) {
+        match upd {
+            TermUpdate::Print(buf) => {
+                for (id, transmission) in tui::image_transmissions(buf) {
+                    self.images.insert(id, transmission.to_vec());
+                }
+                for id in tui::image_deletions(buf) {
+                    self.images.remove(&id);
+                }
+                if tui::is_full_frame(buf) {
+                    self.frame.clear();
+                }
+                self.frame.extend_from_slice(buf);
+            }
+            TermUpdate::RemoteControl(args) => {
+                let key = Self::remote_control_key(args);
+                self.remote_control.retain(|(it, _)| *it != key);
+                self.remote_control.push((key, args.clone()));
+            }
+            &TermUpdate::SetLogLevel(level) => self.log_level = Some(level),
+            TermUpdate::Flush | TermUpdate::Shell(..) => {}
+        }
+    }
+
+    /// Commands with the same key override each other, e.g. showing and hiding a panel.
+    fn remote_control_key(args: &[OsString]) -> Vec<OsString> {
+        let mut key = Vec::from_iter(args.first().cloned());
+        if let Some(action) = args
+            .iter()
+            .find_map(|arg| arg.to_str()?.strip_prefix("--action="))
+        {
+            key.push(match action {
+                "show" | "hide" | "toggle-visibility" => "visibility".into(),
+                action => action.into(),
+            });
+        }
+        key
+    }
+
+    fn replay(&self) -> impl Iterator<Item = TermUpdate> {
+        let print = (!self.frame.is_empty())
+            .then(|| {
+                let images = self.images.values().flatten().copied();
+                let frame = images.chain(self.frame.iter().copied()).collect::<Vec<_>>();
+                [TermUpdate::Print(frame.into()), TermUpdate::Flush]
+            })
+            .into_iter()
+            .flatten();
+        self.log_level
+            .map(TermUpdate::SetLogLevel)
+            .into_iter()
+            .chain(
+                self.remote_control
+                    .iter()
+                    .map(|(_, args)| TermUpdate::RemoteControl(args.clone())),
+            )
+            .chain(print)
+    }
+}
 
 async fn try_init_monitor(
     args: &RunMonitorArgs,
@@ -478,85 +1638,49 @@ async fn try_init_monitor(
     cancel: &CancellationToken,
 ) -> anyhow::Result<StartedMonitorEnv> {
     let monitor = args.monitor.clone();
+    let slice = args
+        .config
+        .systemd_scopes
+        .then(|| systemd::monitor_slice(&monitor.name).into());
 
-    let tmpdir = tokio::task::spawn_blocking(TempDir::new).await??;
+    let spec = |kind: TermKind, app_id: &Arc<str>, overlay: bool, focus: FocusPolicy| PanelSpec {
+        log_name: format!("{}@{}", kind.log_name(), monitor.name),
+        kind: kind.into(),
+        monitor: monitor.name.clone(),
+        app_id: app_id.clone(),
+        edge: args.config.edge,
+        overlay,
+        focus,
+        slice: slice.clone(),
+    };
 
     let bar_fut = init_term(
-        tmpdir.path().join("bar-term-socket.sock"),
-        format!("BAR@{}", monitor.name),
-        [
-            NERD_FONT_CONFIG_OVERRIDE.into(),
-            format!("--output-name={}", monitor.name).into(),
-            // Allow remote control
-            "-o=allow_remote_control=socket-only".into(),
-            "--listen-on=unix:/tmp/kitty-bar-panel.sock".into(),
-            // Allow logging to $KITTY_STDIO_FORWARDED
-            "-o=forward_stdio=yes".into(),
-            // Do not use the system's kitty.conf
-            "--config=NONE".into(),
-            // Basic look of the bar
-            "-o=foreground=white".into(),
-            "-o=background=black".into(),
-            // location of the bar
-            format!("--edge={}", EDGE).into(),
-            // disable hiding the mouse
-            "-o=mouse_hide_wait=0".into(),
-        ],
-        [],
+        &args.config,
+        spec(
+            TermKind::Bar,
+            &args.config.bar_app_id,
+            false,
+            FocusPolicy::NotAllowed,
+        ),
         cancel,
     );
 
     let menu_fut = async {
         let menu = init_term(
-            tmpdir.path().join("menu-term-socket.sock"),
-            format!("MENU@{}", monitor.name),
-            [
-                NERD_FONT_CONFIG_OVERRIDE.into(),
-                format!("--output-name={}", monitor.name).into(),
-                // Configure remote control via socket
-                "-o=allow_remote_control=socket-only".into(),
-                "--listen-on=unix:/tmp/kitty-bar-menu-panel.sock".into(),
-                // Allow logging to $KITTY_STDIO_FORWARDED
-                "-o=forward_stdio=yes".into(),
-                // Do not use the system's kitty.conf
-                "--config=NONE".into(),
-                // Basic look of the menu
-                "-o=background_opacity=0.85".into(),
-                "-o=background=black".into(),
-                "-o=foreground=white".into(),
-                // Center within leftover pixels if cell size does not divide window size.
-                "-o=placement_strategy=center".into(),
-                // location of the menu
-                "--edge=top".into(),
-                // disable hiding the mouse
-                "-o=mouse_hide_wait=0".into(),
-                // Window behavior of the menu panel. Makes panel
-                // act as an overlay on top of other windows.
-                // We do not want tilers to dedicate space to it.
-                // Taken from the args that quick-access-terminal uses.
-                "--exclusive-zone=0".into(),
-                "--override-exclusive-zone".into(),
-                "--layer=overlay".into(),
-                // Since we control resizes from the program and not from
-                // a somewhat continuous drag-resize, debouncing between
-                // resize and reloads is completely inappropriate and
-                // just results in a larger delay between resize and
-                // the old menu content being replaced with the new one.
-                "-o=resize_debounce_time=0 0".into(),
-                // TODO: Mess with repaint_delay, input_delay
-            ],
-            [],
+            &args.config,
+            spec(
+                TermKind::Menu,
+                &args.config.menu_app_id,
+                true,
+                // Let text inputs in the menu receive key presses after it is clicked
+                FocusPolicy::OnDemand,
+            ),
             cancel,
         )
         .await?;
 
         // NOTE: Never pass start-as-hidden!
-        menu.term_upd_tx
-            .send(TermUpdate::RemoteControl(vec![
-                "resize-os-window".into(),
-                "--action=hide".into(),
-            ]))
-            .ok_or_log();
+        menu.set_visible(false);
 
         if VERTICAL_PADDING {
             // HACK: For some reason, using half font height padding at top and bottom
@@ -575,19 +1699,49 @@ async fn try_init_monitor(
         anyhow::Ok(menu)
     };
 
-    let res = async { tokio::try_join!(bar_fut, menu_fut) }
+    // Popups are always at the top, see [`PopupPanel::show`]
+    let osd_fut = init_popup(
+        &args.config,
+        PanelSpec {
+            edge: crate::bins::cli::BarEdge::Top,
+            ..spec(
+                TermKind::Osd,
+                &args.config.osd_app_id,
+                true,
+                // The popup must not take the focus from the window that the user is typing in
+                FocusPolicy::NotAllowed,
+            )
+        },
+        cancel,
+    );
+    let prompt_fut = init_popup(
+        &args.config,
+        PanelSpec {
+            edge: crate::bins::cli::BarEdge::Top,
+            ..spec(
+                TermKind::Prompt,
+                &args.config.prompt_app_id,
+                true,
+                // Keeps the keyboard focus while it is shown
+                FocusPolicy::Exclusive,
+            )
+        },
+        cancel,
+    );
+
+    let res = async { tokio::try_join!(bar_fut, menu_fut, osd_fut, prompt_fut) }
         .timeout(Duration::from_secs(10))
         .await;
 
-    // We have connected to the sockets, there is no need to keep the files around.
-    tokio::task::spawn_blocking(move || drop(tmpdir));
+    let (bar, menu, osd, prompt) = res??;
 
-    let (bar, menu) = res??;
-
-    let (bar_tui_tx, bar_tui_rx) = watch::channel(tui::Elem::empty());
-    let (bar_hide_tx, bar_hide_rx) = watch::channel(false);
+    let (bar_state_tx, bar_state_rx) = watch::channel(super::BarTuiState {
+        tui: tui::Elem::empty(),
+        hidden: false,
+    });
     {
         let mut bar_state_tx_rx = args.bar_state_tx.subscribe();
+        let mut batching_rx = args.batching_rx.clone();
         required_tasks.spawn(async move {
             'outer: loop {
                 let mut tui_rx;
@@ -605,18 +1759,21 @@ async fn try_init_monitor(
 
                 loop {
                     tokio::select! {
-                        Ok(()) = tui_rx.changed() => {
-                            let tui = tui_rx.borrow_and_update().clone();
-                            bar_tui_tx.send_replace(tui);
-                        }
-                        Ok(()) = hide_rx.changed() => {
-                            let hidden = *hide_rx.borrow_and_update();
-                            bar_hide_tx.send_replace(hidden);
-                        }
+                        Ok(()) = tui_rx.changed() => {}
+                        Ok(()) = hide_rx.changed() => {}
                         Ok(()) = bar_state_tx_rx.changed() => {
                             continue 'outer;
                         }
                     }
+                    // The changes of a batch are passed on together, once all of them are made
+                    _ = batching_rx.wait_for(|batching| !batching).await;
+                    if bar_state_tx_rx.has_changed().unwrap_or(false) {
+                        continue 'outer;
+                    }
+                    bar_state_tx.send_replace(super::BarTuiState {
+                        tui: tui_rx.borrow_and_update().clone(),
+                        hidden: *hide_rx.borrow_and_update(),
+                    });
                 }
             }
         });
@@ -625,9 +1782,32 @@ async fn try_init_monitor(
     Ok(StartedMonitorEnv {
         bar,
         menu,
-        bar_tui_rx,
-        bar_hide_rx,
+        osd,
+        prompt,
+        bar_state_rx,
         event_tx: args.event_tx.clone(),
         open_menu_rx: args.open_menu_rx.clone(),
+        osd_rx: args.osd_rx.clone(),
+        prompt_rx: args.prompt_rx.clone(),
+        panels_tx: args.panels_tx.clone(),
+        query_state_tx: args.query_state_tx.clone(),
+        show_interactive_rx: args.show_interactive_rx.clone(),
+        log_level_rx: args.log_level_rx.clone(),
+        menu_slot_rx: args.menu_slot_tx.subscribe(),
+        config: args.config.clone(),
     })
 }
+
+/// Starts a hidden panel for a [`PopupPanel`].
+async fn init_popup(
+    config: &super::HostConfig,
+    spec: PanelSpec,
+    cancel: &CancellationToken,
+) -> anyhow::Result<Term> {
+    let term = init_term(config, spec, cancel).await?;
+
+    // NOTE: Never pass start-as-hidden!
+    term.set_visible(false);
+
+    Ok(term)
+}
@@ -4,60 +4,164 @@ use anyhow::Context as _;
 use tokio::sync::watch;
 use tokio_util::sync::CancellationToken;
 
-use crate::{bins::host::MonitorInfo, utils::ResultExt as _};
+use crate::{
+    bins::host::MonitorInfo,
+    host::{HostEvent, MonitorTransform},
+    utils::ResultExt as _,
+};
 
 const NO_CHANGE_SLEEP: Duration = Duration::from_millis(1000);
 const CHANGE_SLEEP: Duration = Duration::from_millis(500);
 
-pub(super) async fn run_monitor_listener(
-    bar_tui_states_tx: watch::Sender<super::BarTuiStates>,
-    open_menu_rx: watch::Receiver<Option<crate::host::OpenMenu>>,
-    event_tx: std::sync::mpsc::Sender<crate::host::HostEvent>,
-) -> std::process::ExitCode {
+/// Set by niri for processes it spawns. If present, monitors are queried over niri's IPC
+/// socket instead of using `wlr-randr`.
+const NIRI_SOCKET_VAR: &str = "NIRI_SOCKET";
+
+pub(super) struct MonitorListenerArgs {
+    pub bar_tui_states_tx: watch::Sender<super::BarTuiStates>,
+    pub open_menu_rx: watch::Receiver<Option<crate::host::OpenMenu>>,
+    pub osd_rx: watch::Receiver<Option<crate::host::ShowOsd>>,
+    pub prompt_rx: watch::Receiver<Option<crate::host::OpenPrompt>>,
+    pub panels_tx: watch::Sender<super::PanelInfos>,
+    pub query_state_tx: watch::Sender<super::QueryState>,
+    pub event_tx: std::sync::mpsc::Sender<crate::host::HostEvent>,
+    pub reload_rx: watch::Receiver<()>,
+    pub show_interactive_rx: watch::Receiver<Option<tokio::time::Instant>>,
+    pub log_level_rx: watch::Receiver<Option<log::LevelFilter>>,
+    pub batching_rx: watch::Receiver<bool>,
+    pub menu_slot_tx: tokio::sync::broadcast::Sender<super::MenuSlotUpdate>,
+    /// Stops the panels of all monitors, after which the listener returns.
+    pub stop: CancellationToken,
+    pub config: Arc<super::HostConfig>,
+}
+pub(super) async fn run_monitor_listener(args: MonitorListenerArgs) -> super::HostExit {
+    let MonitorListenerArgs {
+        bar_tui_states_tx,
+        open_menu_rx,
+        osd_rx,
+        prompt_rx,
+        panels_tx,
+        query_state_tx,
+        event_tx,
+        mut reload_rx,
+        show_interactive_rx,
+        log_level_rx,
+        batching_rx,
+        menu_slot_tx,
+        stop,
+        config,
+    } = args;
+
     // TODO: Consider moving this to BarTuiStates to ensure consistent data
     let mut monitors_auto_cancel = HashMap::<Arc<str>, tokio_util::sync::DropGuard>::new();
 
     let mut state = MonitorState::default();
-    loop {
-        let old_state = {
-            let Some(new_state) = MonitorState::fetch().await.take_if(|it| *it != state) else {
-                tokio::time::sleep(NO_CHANGE_SLEEP).await;
-                continue;
+    let listen = async {
+        loop {
+            let old_state = {
+                let fetched = match &config.fixed_monitors {
+                    Some(monitors) => Some(MonitorState {
+                        mtrs: monitors
+                            .iter()
+                            .map(|it| (it.name.clone(), it.clone()))
+                            .collect(),
+                    }),
+                    None => MonitorState::fetch().await,
+                };
+                let Some(new_state) = fetched
+                    .map(|it| it.only(&config.monitors))
+                    .inspect(|it| super::systemd::set_monitors(it.mtrs.keys().cloned()))
+                    .take_if(|it| *it != state)
+                else {
+                    if sleep_or_reload(&mut reload_rx, NO_CHANGE_SLEEP).await {
+                        // Forgetting the monitors makes the next iteration restart all of them
+                        monitors_auto_cancel.clear();
+                        state = MonitorState::default();
+                    }
+                    continue;
+                };
+                std::mem::replace(&mut state, new_state)
             };
-            std::mem::replace(&mut state, new_state)
-        };
-
-        bar_tui_states_tx.send_modify(|bar_tui_states| {
-            for monitor in old_state
-                .mtrs
-                .keys()
-                .filter(|&it| !state.mtrs.contains_key(it))
-            {
-                drop(monitors_auto_cancel.remove(monitor));
-                bar_tui_states.by_monitor.remove(monitor);
-            }
-            for monitor in state
-                .mtrs
-                .values()
-                .filter(|&new| old_state.mtrs.get(&new.name).is_none_or(|old| old != new))
-            {
-                let bar_state_tx = bar_tui_states.get_or_mk_monitor(monitor.name.clone());
-
-                let cancel = CancellationToken::new();
-                tokio::spawn(super::monitor_inst::run_monitor(
-                    super::monitor_inst::RunMonitorArgs {
-                        monitor: monitor.clone(),
-                        cancel_monitor: cancel.clone(),
-                        bar_state_tx: bar_state_tx.clone(),
-                        open_menu_rx: open_menu_rx.clone(),
-                        event_tx: event_tx.clone(),
-                    },
-                ));
-                monitors_auto_cancel.insert(monitor.name.clone(), cancel.drop_guard());
+
+            let mut monitors = state.mtrs.values().cloned().collect::<Vec<_>>();
+            monitors.sort_by(|a, b| a.name.cmp(&b.name));
+            query_state_tx.send_modify(|it| it.monitors = monitors.clone());
+            event_tx.send(HostEvent::Monitors(monitors)).ok_or_debug();
+
+            bar_tui_states_tx.send_modify(|bar_tui_states| {
+                for monitor in old_state
+                    .mtrs
+                    .keys()
+                    .filter(|&it| !state.mtrs.contains_key(it))
+                {
+                    drop(monitors_auto_cancel.remove(monitor));
+                    bar_tui_states.by_monitor.remove(monitor);
+                    panels_tx
+                        .send_modify(|panels| panels.retain(|term, _| term.monitor != *monitor));
+                    query_state_tx.send_modify(|it| _ = it.bar_geometry.remove(monitor));
+                    super::metrics::remove_monitor(monitor);
+                }
+                for monitor in state
+                    .mtrs
+                    .values()
+                    .filter(|&new| old_state.mtrs.get(&new.name).is_none_or(|old| old != new))
+                {
+                    let bar_state_tx = bar_tui_states.get_or_mk_monitor(monitor.name.clone());
+
+                    let cancel = CancellationToken::new();
+                    tokio::spawn(super::monitor_inst::run_monitor(
+                        super::monitor_inst::RunMonitorArgs {
+                            monitor: monitor.clone(),
+                            cancel_monitor: cancel.clone(),
+                            bar_state_tx: bar_state_tx.clone(),
+                            open_menu_rx: open_menu_rx.clone(),
+                            osd_rx: osd_rx.clone(),
+                            prompt_rx: prompt_rx.clone(),
+                            panels_tx: panels_tx.clone(),
+                            query_state_tx: query_state_tx.clone(),
+                            event_tx: event_tx.clone(),
+                            show_interactive_rx: show_interactive_rx.clone(),
+                            log_level_rx: log_level_rx.clone(),
+                            batching_rx: batching_rx.clone(),
+                            menu_slot_tx: menu_slot_tx.clone(),
+                            config: config.clone(),
+                        },
+                    ));
+                    monitors_auto_cancel.insert(monitor.name.clone(), cancel.drop_guard());
+                }
+            });
+
+            if sleep_or_reload(&mut reload_rx, CHANGE_SLEEP).await {
+                monitors_auto_cancel.clear();
+                state = MonitorState::default();
             }
-        });
+        }
+    };
+    tokio::select! {
+        () = listen => {}
+        () = stop.cancelled() => {}
+    }
+    // Cancels the panel managers
+    monitors_auto_cancel.clear();
+    super::HostExit::Stopped
+}
+
+/// The names of the connected monitors, for [`super::check_config_main`].
+pub(super) async fn monitor_names() -> Option<Vec<Arc<str>>> {
+    let mut names = MonitorState::fetch()
+        .await?
+        .mtrs
+        .into_keys()
+        .collect::<Vec<_>>();
+    names.sort();
+    Some(names)
+}
 
-        tokio::time::sleep(CHANGE_SLEEP).await;
+/// Returns `true` if the sleep was cut short by a reload request.
+async fn sleep_or_reload(reload_rx: &mut watch::Receiver<()>, dur: Duration) -> bool {
+    tokio::select! {
+        () = tokio::time::sleep(dur) => false,
+        Ok(()) = reload_rx.changed() => true,
     }
 }
 
@@ -66,13 +170,31 @@ struct MonitorState {
     mtrs: HashMap<Arc<str>, MonitorInfo>,
 }
 impl MonitorState {
+    /// Removes the monitors that are not in `names`, unless it is empty.
+    fn only(mut self, names: &[Arc<str>]) -> Self {
+        if !names.is_empty() {
+            self.mtrs.retain(|name, _| names.contains(name));
+        }
+        self
+    }
+
     async fn fetch() -> Option<Self> {
+        if let Some(niri_socket) = std::env::var_os(NIRI_SOCKET_VAR) {
+            Self::fetch_niri(niri_socket).await
+        } else {
+            Self::fetch_wlr_randr().await
+        }
+    }
+
+    async fn fetch_wlr_randr() -> Option<Self> {
         #[derive(serde::Deserialize)]
         struct MonitorData {
             name: Arc<str>,
             scale: f64,
             modes: Vec<MonitorMode>,
             enabled: bool,
+            #[serde(default)]
+            transform: Option<String>,
         }
         #[derive(serde::Deserialize)]
         struct MonitorMode {
@@ -112,21 +234,157 @@ impl MonitorState {
             .filter(|md| md.enabled)
             .filter_map(|md| {
                 let MonitorData {
-                    name, scale, modes, ..
+                    name,
+                    scale,
+                    modes,
+                    transform,
+                    ..
                 } = md;
                 let MonitorMode { width, height, .. } = modes.into_iter().find(|it| it.current)?;
+                let transform =
+                    transform.map_or(MonitorTransform::Normal, |it| parse_wlr_transform(&it));
                 Some((
                     name.clone(),
-                    MonitorInfo {
-                        name,
-                        scale,
-                        width,
-                        height,
-                    },
+                    monitor_info(name, scale, width, height, transform),
                 ))
             })
             .collect();
 
         Some(MonitorState { mtrs: monitors })
     }
+
+    async fn fetch_niri(socket: std::ffi::OsString) -> Option<Self> {
+        use tokio::io::{AsyncBufReadExt as _, AsyncWriteExt as _};
+
+        #[derive(serde::Deserialize)]
+        enum NiriResponse {
+            Outputs(HashMap<Arc<str>, NiriOutput>),
+        }
+        #[derive(serde::Deserialize)]
+        struct NiriOutput {
+            name: Arc<str>,
+            modes: Vec<NiriMode>,
+            current_mode: Option<usize>,
+            logical: Option<NiriLogical>,
+        }
+        #[derive(serde::Deserialize)]
+        struct NiriMode {
+            width: u32,
+            height: u32,
+        }
+        #[derive(serde::Deserialize)]
+        struct NiriLogical {
+            scale: f64,
+            #[serde(default)]
+            transform: Option<String>,
+        }
+
+        let mut stream = tokio::net::UnixStream::connect(&socket)
+            .await
+            .with_context(|| format!("Failed to connect to niri socket {socket:?}"))
+            .ok_or_log()?;
+
+        // https://yalter.github.io/niri/niri_ipc/
+        // Requests are JSON-encoded and newline-terminated. The reply is a
+        // single line containing a `Result<Response, String>`.
+        stream
+            .write_all(b"\"Outputs\"\n")
+            .await
+            .context("Failed to send niri outputs request")
+            .ok_or_log()?;
+
+        let mut reply = String::new();
+        tokio::io::BufReader::new(stream)
+            .read_line(&mut reply)
+            .await
+            .context("Failed to read niri outputs reply")
+            .ok_or_log()?;
+
+        let NiriResponse::Outputs(outputs) =
+            serde_json::from_str::<Result<NiriResponse, String>>(&reply)
+                .context("Failed to deserialize niri outputs reply")
+                .ok_or_log()?
+                .map_err(|err| anyhow::anyhow!("niri returned an error: {err}"))
+                .ok_or_log()?;
+
+        let monitors: HashMap<_, _> = outputs
+            .into_values()
+            .filter_map(|output| {
+                let NiriOutput {
+                    name,
+                    modes,
+                    current_mode,
+                    logical,
+                } = output;
+                // Disabled outputs have neither a current mode nor a logical size
+                let NiriLogical { scale, transform } = logical?;
+                let &NiriMode { width, height } = modes.get(current_mode?)?;
+                let transform =
+                    transform.map_or(MonitorTransform::Normal, |it| parse_niri_transform(&it));
+                Some((
+                    name.clone(),
+                    monitor_info(name, scale, width, height, transform),
+                ))
+            })
+            .collect();
+
+        Some(MonitorState { mtrs: monitors })
+    }
+}
+
+/// Creates a [`MonitorInfo`] from the size of the monitor's current mode, which does not take
+/// rotation into account.
+fn monitor_info(
+    name: Arc<str>,
+    scale: f64,
+    mode_width: u32,
+    mode_height: u32,
+    transform: MonitorTransform,
+) -> MonitorInfo {
+    let (width, height) = if transform.is_sideways() {
+        (mode_height, mode_width)
+    } else {
+        (mode_width, mode_height)
+    };
+    MonitorInfo {
+        name,
+        scale,
+        width,
+        height,
+        transform,
+    }
+}
+
+fn parse_wlr_transform(transform: &str) -> MonitorTransform {
+    match transform {
+        "normal" => MonitorTransform::Normal,
+        "90" => MonitorTransform::Rotate90,
+        "180" => MonitorTransform::Rotate180,
+        "270" => MonitorTransform::Rotate270,
+        "flipped" => MonitorTransform::Flipped,
+        "flipped-90" => MonitorTransform::Flipped90,
+        "flipped-180" => MonitorTransform::Flipped180,
+        "flipped-270" => MonitorTransform::Flipped270,
+        _ => {
+            log::warn!("Unknown monitor transform {transform:?} reported by wlr-randr");
+            MonitorTransform::Normal
+        }
+    }
+}
+
+fn parse_niri_transform(transform: &str) -> MonitorTransform {
+    match transform {
+        "Normal" => MonitorTransform::Normal,
+        "_90" => MonitorTransform::Rotate90,
+        "_180" => MonitorTransform::Rotate180,
+        "_270" => MonitorTransform::Rotate270,
+        "Flipped" => MonitorTransform::Flipped,
+        "Flipped90" => MonitorTransform::Flipped90,
+        "Flipped180" => MonitorTransform::Flipped180,
+        "Flipped270" => MonitorTransform::Flipped270,
+        _ => {
+            log::warn!("Unknown monitor transform {transform:?} reported by niri");
+            MonitorTransform::Normal
+        }
+    }
 }
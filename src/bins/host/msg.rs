@@ -0,0 +1,503 @@
+use std::{
+    io::{BufRead as _, Write as _},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::Arc,
+};
+
+use anyhow::Context as _;
+use tokio::{
+    io::{AsyncBufReadExt as _, AsyncWriteExt as _},
+    sync::watch,
+};
+
+use crate::{host, utils::ResultExt as _};
+
+const MSG_SOCK_NAME: &str = "scratchbar.sock";
+
+fn msg_sock_path() -> PathBuf {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+        .join(MSG_SOCK_NAME)
+}
+
+#[derive(Clone)]
+pub(super) struct MsgCtx {
+    pub update_tx: tokio::sync::mpsc::UnboundedSender<host::HostUpdate>,
+    pub reload_tx: watch::Sender<()>,
+    pub event_tx: std::sync::mpsc::Sender<host::HostEvent>,
+    pub bar_tui_states_rx: watch::Receiver<super::BarTuiStates>,
+    pub open_menu_rx: watch::Receiver<Option<host::OpenMenu>>,
+    pub show_interactive_tx: watch::Sender<Option<tokio::time::Instant>>,
+    pub module_status_rx: watch::Receiver<Vec<host::ModuleStatus>>,
+}
+
+/// How often [`host::MsgCommand::Listen`] checks for changes of the state.
+const LISTEN_INTERVAL: std::time::Duration = std::time::Duration::from_millis(200);
+const SHOW_INTERACTIVE_DEFAULT: std::time::Duration = std::time::Duration::from_secs(3);
+/// How long [`host::MsgCommand::QueryModules`] waits for the controller to answer.
+const QUERY_MODULES_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+struct RemoveOnDrop(PathBuf);
+impl Drop for RemoveOnDrop {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.0)
+            .with_context(|| format!("Failed to remove control socket {:?}", self.0))
+            .ok_or_debug();
+    }
+}
+
+fn bind(path: &Path) -> anyhow::Result<tokio::net::UnixListener> {
+    crate::transport::remove_stale_socket(path)?;
+    Ok(tokio::net::UnixListener::bind(path)?)
+}
+
+pub(super) async fn run_msg_listener(ctx: MsgCtx) {
+    let path = msg_sock_path();
+    let Some(listener) = bind(&path)
+        .with_context(|| format!("Failed to bind control socket {path:?}"))
+        .ok_or_log()
+    else {
+        return;
+    };
+    let _auto_remove = RemoveOnDrop(path);
+
+    loop {
+        let Some((stream, _)) = listener
+            .accept()
+            .await
+            .context("Failed to accept control socket connection")
+            .ok_or_log()
+        else {
+            continue;
+        };
+        let ctx = ctx.clone();
+        tokio::spawn(async move {
+            handle_client(stream, ctx)
+                .await
+                .context("Control socket connection failed")
+                .ok_or_log();
+        });
+    }
+}
+
+async fn handle_client(stream: tokio::net::UnixStream, ctx: MsgCtx) -> anyhow::Result<()> {
+    let (read, mut write) = stream.into_split();
+    let mut lines = tokio::io::BufReader::new(read).lines();
+    while let Some(line) = lines.next_line().await? {
+        let resp = match serde_json::from_str::<host::MsgCommand>(&line) {
+            Ok(host::MsgCommand::Listen) => return ctx.listen(&mut write).await,
+            Ok(cmd) => ctx.handle(cmd).await,
+            Err(err) => host::MsgResponse::Error(format!("Invalid command: {err}")),
+        };
+        let mut buf = serde_json::to_vec(&resp)?;
+        buf.push(b'\n');
+        write.write_all(&buf).await?;
+    }
+    Ok(())
+}
+
+impl MsgCtx {
+    async fn handle(&self, cmd: host::MsgCommand) -> host::MsgResponse {
+        fn bar_select(monitor: Option<Arc<str>>) -> host::BarSelect {
+            match monitor {
+                Some(monitor_name) => host::BarSelect::OnMonitor { monitor_name },
+                None => host::BarSelect::All,
+            }
+        }
+        let update = match cmd {
+            host::MsgCommand::ShowBar { monitor } => {
+                host::HostUpdate::UpdateBars(bar_select(monitor), host::BarUpdate::Show)
+            }
+            host::MsgCommand::HideBar { monitor } => {
+                host::HostUpdate::UpdateBars(bar_select(monitor), host::BarUpdate::Hide)
+            }
+            host::MsgCommand::OpenMenu { monitor, tag } => {
+                if !self
+                    .bar_tui_states_rx
+                    .borrow()
+                    .by_monitor
+                    .contains_key(&monitor)
+                {
+                    return host::MsgResponse::Error(format!("Unknown monitor {monitor:?}"));
+                }
+                // Only the controller knows the menus
+                return match self
+                    .event_tx
+                    .send(host::HostEvent::MenuRequested { monitor, tag })
+                {
+                    Ok(()) => host::MsgResponse::Ok,
+                    Err(_) => host::MsgResponse::Error("The host is shutting down".into()),
+                };
+            }
+            host::MsgCommand::CloseMenu => host::HostUpdate::CloseMenu,
+            host::MsgCommand::Reload => {
+                log::info!("Reload requested through control socket");
+                super::request_reload(&self.reload_tx, &self.event_tx);
+                return host::MsgResponse::Ok;
+            }
+            host::MsgCommand::QueryState => return host::MsgResponse::State(self.state()),
+            host::MsgCommand::Listen => {
+                return host::MsgResponse::Error("Cannot listen in this context".into());
+            }
+            host::MsgCommand::ShowInteractive { duration_ms } => {
+                let duration = duration_ms
+                    .map(std::time::Duration::from_millis)
+                    .unwrap_or(SHOW_INTERACTIVE_DEFAULT);
+                self.show_interactive_tx
+                    .send_replace(Some(tokio::time::Instant::now() + duration));
+                return host::MsgResponse::Ok;
+            }
+            host::MsgCommand::QueryMetrics => {
+                return host::MsgResponse::Metrics(super::metrics::snapshot());
+            }
+            host::MsgCommand::QueryModules => {
+                return match self.query_modules().await {
+                    Some(modules) => host::MsgResponse::Modules(modules),
+                    None => host::MsgResponse::Error(
+                        "The controller did not report the status of its modules".into(),
+                    ),
+                };
+            }
+            host::MsgCommand::Controller { args } => {
+                return match self.event_tx.send(host::HostEvent::Command(args)) {
+                    Ok(()) => host::MsgResponse::Ok,
+                    Err(_) => host::MsgResponse::Error("The host is shutting down".into()),
+                };
+            }
+            host::MsgCommand::Capture { monitor, dir } => {
+                return match self.capture(&monitor, &dir).await {
+                    Ok(files) => host::MsgResponse::Captured(files),
+                    Err(err) => host::MsgResponse::Error(format!("{err:?}")),
+                };
+            }
+        };
+        match self.update_tx.send(update) {
+            Ok(()) => host::MsgResponse::Ok,
+            Err(_) => host::MsgResponse::Error("The host is shutting down".into()),
+        }
+    }
+
+    /// Writes the state whenever it changes, until the client disconnects.
+    async fn listen(&self, write: &mut (impl tokio::io::AsyncWrite + Unpin)) -> anyhow::Result<()> {
+        let mut last = None;
+        loop {
+            let mut buf = serde_json::to_vec(&host::MsgResponse::State(self.state()))?;
+            buf.push(b'\n');
+            if last.as_ref() != Some(&buf) {
+                write.write_all(&buf).await?;
+                last = Some(buf);
+            }
+            tokio::time::sleep(LISTEN_INTERVAL).await;
+        }
+    }
+
+    async fn query_modules(&self) -> Option<Vec<host::ModuleStatus>> {
+        let mut module_status_rx = self.module_status_rx.clone();
+        module_status_rx.mark_unchanged();
+        self.event_tx
+            .send(host::HostEvent::ModuleStatusRequested)
+            .ok_or_debug()?;
+        tokio::time::timeout(QUERY_MODULES_TIMEOUT, module_status_rx.changed())
+            .await
+            .ok()?
+            .ok()?;
+        let modules = module_status_rx.borrow_and_update().clone();
+        Some(modules)
+    }
+
+    async fn capture(&self, monitor: &str, dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+        // The panels' font size is not known here, it only affects the size of images
+        let sizing = crate::tui::SizingArgs::new(crate::tui::Vec2 { x: 8, y: 16 });
+        let render = |elem: &crate::tui::Elem| {
+            crate::tui::render_headless(elem, crate::tui::calc_min_size(elem, &sizing), &sizing)
+                .context("Failed to render")
+        };
+        let (dump, rendered, png) = {
+            let bar_tui_states = self.bar_tui_states_rx.borrow();
+            let bar = bar_tui_states
+                .by_monitor
+                .get(monitor)
+                .with_context(|| format!("Unknown monitor {monitor:?}"))?
+                .borrow();
+            let open_menu = self.open_menu_rx.borrow();
+            let menu = open_menu.as_ref().filter(|it| *it.monitor == *monitor);
+
+            let grids = std::iter::once(render(&bar.tui.borrow()))
+                .chain(menu.map(|it| render(&it.tui)))
+                .collect::<Vec<_>>();
+            let rendered = grids
+                .iter()
+                .map(|grid| match grid {
+                    Ok(grid) => grid.to_text(true),
+                    Err(err) => format!("{err:#}\n"),
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            let png = crate::tui::grids_to_png(&grids.iter().flatten().collect::<Vec<_>>());
+            let dump = format!(
+                "scratchbar {}\nmonitor: {monitor}\nbar hidden: {}\n\nbar: {:#?}\n\nmenu: {:#?}\n",
+                crate::ctrl_ipc::VERSION,
+                *bar.hidden.borrow(),
+                *bar.tui.borrow(),
+                menu,
+            );
+            (dump, rendered, png)
+        };
+
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let base = dir.join(format!("scratchbar-{monitor}-{timestamp}"));
+
+        let dump_path = base.with_extension("txt");
+        tokio::fs::write(&dump_path, dump)
+            .await
+            .with_context(|| format!("Failed to write {dump_path:?}"))?;
+        let mut files = vec![dump_path];
+
+        // The bar and menu at their minimum size, viewable with `cat`
+        let rendered_path = base.with_extension("ans");
+        tokio::fs::write(&rendered_path, rendered)
+            .await
+            .with_context(|| format!("Failed to write {rendered_path:?}"))?;
+        files.push(rendered_path);
+
+        // The same, drawn with a built-in font
+        let render_png_path = base.with_extension("render.png");
+        tokio::fs::write(&render_png_path, png)
+            .await
+            .with_context(|| format!("Failed to write {render_png_path:?}"))?;
+        files.push(render_png_path);
+
+        // The dump is useful on its own, so a failing screenshot is not an error
+        let png_path = base.with_extension("png");
+        let screenshot = async {
+            let std::process::Output { status, stderr, .. } = tokio::process::Command::new("grim")
+                .arg("-o")
+                .arg(monitor)
+                .arg(&png_path)
+                .stdin(std::process::Stdio::null())
+                .output()
+                .await
+                .context("Failed to run grim")?;
+            if !status.success() {
+                anyhow::bail!(
+                    "grim exited with status {status}. Stderr:\n{}",
+                    String::from_utf8_lossy(&stderr)
+                );
+            }
+            anyhow::Ok(())
+        };
+        if screenshot
+            .await
+            .context("Failed to take screenshot")
+            .ok_or_log()
+            .is_some()
+        {
+            files.push(png_path);
+        }
+
+        Ok(files)
+    }
+
+    fn state(&self) -> host::HostState {
+        let mut bars: Vec<_> = self
+            .bar_tui_states_rx
+            .borrow()
+            .by_monitor
+            .iter()
+            .map(|(monitor, state)| host::BarState {
+                monitor: monitor.clone(),
+                hidden: *state.borrow().hidden.borrow(),
+            })
+            .collect();
+        bars.sort_unstable_by(|b1, b2| b1.monitor.cmp(&b2.monitor));
+
+        host::HostState {
+            bars,
+            open_menu: self
+                .open_menu_rx
+                .borrow()
+                .as_ref()
+                .map(|open| open.monitor.clone()),
+        }
+    }
+}
+
+/// The commands of `scratchbar msg`, see [`host::MsgCommand`].
+#[derive(Debug, clap::Subcommand)]
+pub(crate) enum MsgArgs {
+    /// Show the bar
+    Show {
+        /// Defaults to all monitors
+        monitor: Option<Arc<str>>,
+    },
+    /// Hide the bar
+    Hide {
+        /// Defaults to all monitors
+        monitor: Option<Arc<str>>,
+    },
+    /// Open the menu of an element on the bar, as if it was clicked
+    OpenMenu {
+        monitor: Arc<str>,
+        /// The tag of the element. Taken as text, or as bytes if it starts with `0x`
+        #[arg(value_parser = parse_tag)]
+        tag: crate::tui::CustomId,
+    },
+    /// Close the open menu
+    CloseMenu,
+    /// Restart the panels and notify the controller
+    Reload,
+    /// Print the host's state as JSON
+    State,
+    /// Print the host's state as a line of JSON whenever it changes
+    Listen,
+    /// Print the status of the controller's modules as JSON
+    Modules,
+    /// Print render times, output sizes and event rates as JSON
+    Metrics,
+    /// Highlight clickable elements
+    ShowInteractive {
+        /// Defaults to 3 seconds
+        #[arg(value_parser = parse_secs)]
+        seconds: Option<std::time::Duration>,
+    },
+    /// Save a screenshot, a rendering and a dump of the bar and menu for bug reports
+    Capture {
+        monitor: Arc<str>,
+        #[arg(default_value = ".")]
+        dir: PathBuf,
+    },
+    /// Send a command to the controller, e.g. `send timer toggle`
+    Send {
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Send a raw JSON command
+    Json { command: String },
+}
+impl MsgArgs {
+    fn into_command(self) -> anyhow::Result<host::MsgCommand> {
+        Ok(match self {
+            Self::Show { monitor } => host::MsgCommand::ShowBar { monitor },
+            Self::Hide { monitor } => host::MsgCommand::HideBar { monitor },
+            Self::OpenMenu { monitor, tag } => host::MsgCommand::OpenMenu { monitor, tag },
+            Self::CloseMenu => host::MsgCommand::CloseMenu,
+            Self::Reload => host::MsgCommand::Reload,
+            Self::State => host::MsgCommand::QueryState,
+            Self::Listen => host::MsgCommand::Listen,
+            Self::Modules => host::MsgCommand::QueryModules,
+            Self::Metrics => host::MsgCommand::QueryMetrics,
+            Self::ShowInteractive { seconds } => host::MsgCommand::ShowInteractive {
+                duration_ms: seconds.map(|it| it.as_millis() as u64),
+            },
+            Self::Capture { monitor, dir } => host::MsgCommand::Capture {
+                monitor,
+                // Relative to the client, not the host
+                dir: std::path::absolute(dir)?,
+            },
+            Self::Send { args } => host::MsgCommand::Controller { args },
+            Self::Json { command } => serde_json::from_str(&command).context("Invalid command")?,
+        })
+    }
+}
+
+fn parse_secs(secs: &str) -> anyhow::Result<std::time::Duration> {
+    Ok(std::time::Duration::try_from_secs_f64(secs.parse()?)?)
+}
+
+/// Hex bytes like the [`Debug`] output of [`crate::tui::CustomId`], e.g. `0x0107`, or text.
+fn parse_tag(tag: &str) -> anyhow::Result<crate::tui::CustomId> {
+    let Some(hex) = tag.strip_prefix("0x") else {
+        return Ok(crate::tui::CustomId::from_bytes(tag.as_bytes()));
+    };
+    anyhow::ensure!(
+        hex.len() % 2 == 0 && hex.is_ascii(),
+        "Expected two hex digits per byte in {tag:?}"
+    );
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Invalid hex in {tag:?}"))?;
+    Ok(crate::tui::CustomId::from_bytes(&bytes))
+}
+
+pub(crate) fn msg_main(args: MsgArgs) -> ExitCode {
+    let cmd = match args.into_command() {
+        Ok(cmd) => cmd,
+        Err(err) => {
+            eprintln!("{err:?}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let res = (|| {
+        let path = msg_sock_path();
+        let mut stream = std::os::unix::net::UnixStream::connect(&path)
+            .with_context(|| format!("Failed to connect to control socket {path:?}"))?;
+
+        let mut buf = serde_json::to_vec(&cmd)?;
+        buf.push(b'\n');
+        stream.write_all(&buf)?;
+
+        if let host::MsgCommand::Listen = cmd {
+            // Print the states compactly, one per line, until the host exits
+            for line in std::io::BufReader::new(&stream).lines() {
+                match serde_json::from_str::<host::MsgResponse>(&line?)? {
+                    host::MsgResponse::State(state) => {
+                        println!("{}", serde_json::to_string(&state)?)
+                    }
+                    resp => return Ok(resp),
+                }
+            }
+            return Ok(host::MsgResponse::Ok);
+        }
+
+        let mut line = String::new();
+        std::io::BufReader::new(&stream).read_line(&mut line)?;
+        anyhow::Ok(serde_json::from_str::<host::MsgResponse>(&line)?)
+    })();
+
+    match res {
+        Ok(host::MsgResponse::Ok) => ExitCode::SUCCESS,
+        Ok(host::MsgResponse::State(state)) => {
+            match serde_json::to_string_pretty(&state) {
+                Ok(json) => println!("{json}"),
+                Err(err) => eprintln!("Failed to serialize state: {err}"),
+            }
+            ExitCode::SUCCESS
+        }
+        Ok(host::MsgResponse::Modules(modules)) => {
+            match serde_json::to_string_pretty(&modules) {
+                Ok(json) => println!("{json}"),
+                Err(err) => eprintln!("Failed to serialize module status: {err}"),
+            }
+            ExitCode::SUCCESS
+        }
+        Ok(host::MsgResponse::Metrics(metrics)) => {
+            match serde_json::to_string_pretty(&metrics) {
+                Ok(json) => println!("{json}"),
+                Err(err) => eprintln!("Failed to serialize metrics: {err}"),
+            }
+            ExitCode::SUCCESS
+        }
+        Ok(host::MsgResponse::Captured(files)) => {
+            for file in files {
+                println!("{}", file.display());
+            }
+            ExitCode::SUCCESS
+        }
+        Ok(host::MsgResponse::Error(err)) => {
+            eprintln!("{err}");
+            ExitCode::FAILURE
+        }
+        Err(err) => {
+            eprintln!("{err:?}");
+            ExitCode::FAILURE
+        }
+    }
+}
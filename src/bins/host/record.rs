@@ -0,0 +1,413 @@
+//! Records the updates and events of the controller connection, and replays recordings into a
+//! host whose panels are not shown, see `scratchbar run --record` and `scratchbar replay`.
+//!
+//! A recording is newline-delimited JSON, starting with an [`Entry::Start`] with the options of
+//! the controller, followed by [`Entry::Update`]s and [`Entry::Event`]s in the order that the
+//! host received and sent them. Recordings of other protocol versions cannot be replayed.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::OsString,
+    io::Write as _,
+    path::Path,
+    process::ExitCode,
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context as _;
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_util::sync::CancellationToken;
+
+use super::backend::{
+    KittyBackend, Panel, PanelBackend, PanelCapabilities, PanelGeometry, PanelSpec,
+};
+use crate::{
+    bins::inst::{TermEvent, TermUpdate},
+    ctrl_ipc::PROTOCOL_VERSION,
+    host, tui,
+    utils::ResultExt as _,
+};
+
+/// The cell size of replayed panels, in pixels.
+const HEADLESS_FONT_SIZE: tui::Vec2<u16> = tui::Vec2 { x: 8, y: 16 };
+/// How long a replay keeps running after the last update, so that the panels catch up.
+const REPLAY_SETTLE: Duration = Duration::from_secs(1);
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Entry<O, U, E> {
+    Start {
+        protocol: u32,
+        opts: O,
+    },
+    /// `at_ms` is the time since the start of the recording.
+    Update {
+        at_ms: u64,
+        update: U,
+    },
+    Event {
+        at_ms: u64,
+        event: E,
+    },
+}
+type RecordedEntry = Entry<host::HostConnectOpts, host::HostUpdate, host::HostEvent>;
+
+/// Appends entries to a recording. The file is written by a separate thread, so that recording
+/// does not block the host.
+pub(super) struct Recorder {
+    start: Instant,
+    line_tx: std::sync::mpsc::Sender<Vec<u8>>,
+}
+impl Recorder {
+    /// Creates the recording and starts it with the options of the controller.
+    pub fn create(path: &Path, opts: &host::HostConnectOpts) -> anyhow::Result<Self> {
+        let file = std::fs::File::create(path)
+            .with_context(|| format!("Failed to create recording {path:?}"))?;
+        let (line_tx, line_rx) = std::sync::mpsc::channel::<Vec<u8>>();
+        std::thread::spawn(move || {
+            let mut file = std::io::BufWriter::new(file);
+            for line in line_rx {
+                // Flushed after every entry, so that the recording is usable if the host crashes
+                file.write_all(&line)
+                    .and_then(|()| file.flush())
+                    .context("Failed to write recording")
+                    .ok_or_log();
+            }
+        });
+
+        let recorder = Self {
+            start: Instant::now(),
+            line_tx,
+        };
+        recorder.write(&Entry::<_, (), ()>::Start {
+            protocol: PROTOCOL_VERSION,
+            opts,
+        });
+        Ok(recorder)
+    }
+
+    fn at_ms(&self) -> u64 {
+        u64::try_from(self.start.elapsed().as_millis()).unwrap_or(u64::MAX)
+    }
+
+    fn write<O: Serialize, U: Serialize, E: Serialize>(&self, entry: &Entry<O, U, E>) {
+        let Some(mut line) = serde_json::to_vec(entry)
+            .context("Failed to serialize recorded entry")
+            .ok_or_log()
+        else {
+            return;
+        };
+        line.push(b'\n');
+        self.line_tx.send(line).ok_or_debug();
+    }
+
+    pub fn update(&self, update: &host::HostUpdate) {
+        self.write(&Entry::<(), _, ()>::Update {
+            at_ms: self.at_ms(),
+            update,
+        });
+    }
+
+    pub fn event(&self, event: &host::HostEvent) {
+        self.write(&Entry::<(), (), _>::Event {
+            at_ms: self.at_ms(),
+            event,
+        });
+    }
+}
+
+fn read_recording(path: &Path) -> anyhow::Result<(host::HostConnectOpts, Vec<RecordedEntry>)> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read recording {path:?}"))?;
+    let mut entries = content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str::<RecordedEntry>(line)
+                .with_context(|| format!("Invalid entry on line {} of {path:?}", i + 1))
+        });
+
+    let Some(Entry::Start { protocol, opts }) = entries.next().transpose()? else {
+        anyhow::bail!("{path:?} does not start with the options of a controller");
+    };
+    anyhow::ensure!(
+        protocol == PROTOCOL_VERSION,
+        "{path:?} was recorded with protocol version {protocol}, but this host uses version {PROTOCOL_VERSION}"
+    );
+    Ok((opts, entries.collect::<anyhow::Result<_>>()?))
+}
+
+/// Replays the updates of a recording with their original timing and prints the visible
+/// panels once they are done. Commands from the recording are not run.
+pub(crate) fn replay_main(args: crate::bins::cli::ReplayArgs) -> ExitCode {
+    crate::logging::init_logger("REPLAY".into());
+    if let Some(level) = args.log_level {
+        crate::logging::set_log_level(level);
+    }
+    replay(args).ok_or_log().unwrap_or(ExitCode::FAILURE)
+}
+
+fn replay(args: crate::bins::cli::ReplayArgs) -> anyhow::Result<ExitCode> {
+    let crate::bins::cli::ReplayArgs {
+        file,
+        speed,
+        edge,
+        log_level,
+    } = args;
+    anyhow::ensure!(
+        speed.is_finite() && speed >= 0.0,
+        "The speed has to be a positive number or 0, not {speed}"
+    );
+
+    let (opts, entries) = read_recording(&file)?;
+    // Later changes of the monitors are not replayed
+    let monitors = entries
+        .iter()
+        .find_map(|entry| match entry {
+            Entry::Event {
+                event: host::HostEvent::Monitors(monitors),
+                ..
+            } => Some(monitors.clone()),
+            _ => None,
+        })
+        .with_context(|| format!("{file:?} does not contain the monitors of the host"))?;
+    let updates = entries
+        .into_iter()
+        .filter_map(|entry| match entry {
+            Entry::Update { at_ms, update } => Some((at_ms, update)),
+            Entry::Start { .. } | Entry::Event { .. } => None,
+        })
+        .collect::<Vec<_>>();
+    log::info!(
+        "Replaying {} updates on monitors {:?}",
+        updates.len(),
+        Vec::from_iter(monitors.iter().map(|it| &it.name))
+    );
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .context("Failed to start the tokio runtime")?;
+    let _guard = runtime.enter();
+
+    let backend = Arc::new(HeadlessBackend {
+        monitors: monitors
+            .iter()
+            .map(|it| (it.name.clone(), it.clone()))
+            .collect(),
+        panels: Default::default(),
+    });
+    let config = Arc::new(super::HostConfig {
+        backend: backend.clone(),
+        fixed_monitors: Some(monitors),
+        // Replays must not replace the bars of the running host
+        snapshot_controller: None,
+        ..super::bin_entry_point::host_config(
+            opts,
+            crate::bins::cli::HostArgs {
+                log_level,
+                edge,
+                ..Default::default()
+            },
+        )
+    });
+
+    let (update_tx, mut update_rx) = tokio::sync::mpsc::unbounded_channel();
+    let feed = async move {
+        let start = tokio::time::Instant::now();
+        for (at_ms, update) in updates {
+            if speed > 0.0 {
+                tokio::time::sleep_until(start + Duration::from_millis(at_ms).div_f64(speed)).await;
+            }
+            update_tx.send(update).ok_or_debug();
+        }
+        tokio::time::sleep(REPLAY_SETTLE).await;
+    };
+
+    let (event_tx, event_rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        for ev in event_rx {
+            log::debug!("Replayed event: {ev:?}");
+        }
+    });
+
+    // The host keeps running after the updates end, since the control socket can still send
+    // updates
+    let exit = runtime.block_on(async {
+        tokio::select! {
+            exit = super::run_host(
+                futures::stream::poll_fn(move |cx| update_rx.poll_recv(cx)),
+                event_tx,
+                tokio::sync::watch::Sender::new(()).subscribe(),
+                config,
+            ) => exit,
+            () = feed => super::HostExit::Stopped,
+        }
+    });
+
+    for (log_name, screen) in backend.screens() {
+        println!("{log_name}:\n{screen}");
+    }
+    Ok(exit.code())
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poison| poison.into_inner())
+}
+
+/// Panels that render into a [`tui::Grid`] instead of a terminal. Like kitty's panels, they
+/// are resized and hidden using remote control.
+#[derive(Debug)]
+struct HeadlessBackend {
+    monitors: HashMap<Arc<str>, host::MonitorInfo>,
+    /// The most recently started panel of each log name.
+    panels: Mutex<BTreeMap<String, Arc<Mutex<HeadlessPanel>>>>,
+}
+impl HeadlessBackend {
+    /// The content of the visible panels, by log name.
+    fn screens(&self) -> Vec<(String, String)> {
+        lock(&self.panels)
+            .iter()
+            .filter_map(|(log_name, panel)| {
+                let panel = lock(panel);
+                panel
+                    .visible
+                    .then(|| (log_name.clone(), panel.grid.to_text(false)))
+            })
+            .collect()
+    }
+}
+
+impl PanelBackend for HeadlessBackend {
+    fn spawn<'a>(
+        &'a self,
+        spec: &'a PanelSpec,
+        ev_tx: UnboundedSender<TermEvent>,
+        cancel: &'a CancellationToken,
+    ) -> BoxFuture<'a, anyhow::Result<Panel>> {
+        Box::pin(async move {
+            let monitor = self
+                .monitors
+                .get(&spec.monitor)
+                .with_context(|| format!("Unknown monitor {:?}", spec.monitor))?;
+            let panel = Arc::new(Mutex::new(HeadlessPanel::new(
+                monitor.clone(),
+                spec.kind == host::TermKind::Bar,
+            )));
+            lock(&self.panels).insert(spec.log_name.clone(), panel.clone());
+            ev_tx
+                .send(TermEvent::Sizes(lock(&panel).sizes()))
+                .ok_or_debug();
+
+            let (upd_tx, mut upd_rx) =
+                tokio::sync::mpsc::channel(super::monitor_inst::PANEL_QUEUE_CAPACITY);
+            let panel_cancel = cancel.child_token();
+            let auto_cancel = panel_cancel.clone().drop_guard();
+            tokio::spawn(async move {
+                let _auto_cancel = auto_cancel;
+                while let Some(upd) = upd_rx.recv().await {
+                    match upd {
+                        TermUpdate::Print(buf) => lock(&panel).grid.apply(&buf),
+                        TermUpdate::RemoteControl(args) => {
+                            let mut panel = lock(&panel);
+                            if panel.remote_control(&args) {
+                                ev_tx.send(TermEvent::Sizes(panel.sizes())).ok_or_debug();
+                            }
+                        }
+                        TermUpdate::Flush | TermUpdate::Shell(..) | TermUpdate::SetLogLevel(_) => {}
+                    }
+                }
+            });
+
+            Ok(Panel {
+                upd_tx,
+                cancel: panel_cancel,
+            })
+        })
+    }
+
+    // Images are only placed, so they are blank either way
+    fn capabilities(&self) -> PanelCapabilities {
+        KittyBackend.capabilities()
+    }
+
+    fn set_visible(&self, visible: bool) -> Option<TermUpdate> {
+        KittyBackend.set_visible(visible)
+    }
+
+    fn set_geometry(&self, geometry: &PanelGeometry) -> Option<TermUpdate> {
+        KittyBackend.set_geometry(geometry)
+    }
+}
+
+#[derive(Debug)]
+struct HeadlessPanel {
+    monitor: host::MonitorInfo,
+    grid: tui::Grid,
+    visible: bool,
+}
+impl HeadlessPanel {
+    fn new(monitor: host::MonitorInfo, visible: bool) -> Self {
+        let width =
+            u16::try_from(monitor.width / u32::from(HEADLESS_FONT_SIZE.x)).unwrap_or(u16::MAX);
+        Self {
+            monitor,
+            grid: tui::Grid::new(tui::Vec2 { x: width, y: 1 }),
+            visible,
+        }
+    }
+
+    fn sizes(&self) -> tui::Sizes {
+        let size = self.grid.size();
+        tui::Sizes {
+            cell_size: size,
+            pix_size: tui::Vec2 {
+                x: size.x.saturating_mul(HEADLESS_FONT_SIZE.x),
+                y: size.y.saturating_mul(HEADLESS_FONT_SIZE.y),
+            },
+        }
+    }
+
+    /// Applies the arguments of [`KittyBackend`]'s remote control commands. Returns whether the
+    /// size changed.
+    fn remote_control(&mut self, args: &[OsString]) -> bool {
+        let args = Vec::from_iter(args.iter().filter_map(|it| it.to_str()));
+        if args.first() != Some(&"resize-os-window") {
+            return false;
+        }
+        let mut size = self.grid.size();
+        let mut margins = None;
+        for arg in &args[1..] {
+            match arg.strip_prefix("--action=") {
+                Some("show") => self.visible = true,
+                Some("hide") => self.visible = false,
+                Some("toggle-visibility") => self.visible = !self.visible,
+                _ => {}
+            }
+            let Some((key, value)) = arg.split_once('=') else {
+                continue;
+            };
+            match (key, value.parse::<u32>()) {
+                ("lines", Ok(lines)) => size.y = u16::try_from(lines).unwrap_or(u16::MAX),
+                ("margin-left" | "margin-right", Ok(margin)) => {
+                    *margins.get_or_insert(0) += margin;
+                }
+                _ => {}
+            }
+        }
+        // Margins are in logical pixels
+        if let Some(margins) = margins {
+            let width = f64::from(self.monitor.width) - f64::from(margins) * self.monitor.scale;
+            size.x = (width.max(0.0) / f64::from(HEADLESS_FONT_SIZE.x)) as u16;
+        }
+        if size == self.grid.size() {
+            return false;
+        }
+        self.grid = tui::Grid::new(size);
+        true
+    }
+}
@@ -0,0 +1,129 @@
+//! Scratchpad terminals, see [`host::HostUpdate::ToggleScratch`].
+
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
+
+use anyhow::Context as _;
+use tempfile::TempDir;
+
+use crate::{host, utils::ResultExt as _};
+
+struct Scratchpad {
+    child: tokio::process::Child,
+    /// Contains the remote control socket of the panel.
+    dir: TempDir,
+}
+
+pub(super) async fn run_scratchpads(
+    mut toggle_rx: tokio::sync::mpsc::UnboundedReceiver<host::ToggleScratch>,
+) {
+    let mut running = HashMap::<Arc<str>, Scratchpad>::new();
+    while let Some(toggle) = toggle_rx.recv().await {
+        if let Some(pad) = running.get_mut(&toggle.id) {
+            match pad.child.try_wait() {
+                Ok(None) => {
+                    toggle_visibility(pad).await;
+                    continue;
+                }
+                Ok(Some(status)) => {
+                    log::debug!("Scratchpad {:?} exited with {status}", toggle.id);
+                }
+                Err(err) => log::error!("Failed to check scratchpad {:?}: {err}", toggle.id),
+            }
+            running.remove(&toggle.id);
+        }
+        if let Some(pad) = start(&toggle)
+            .await
+            .with_context(|| format!("Failed to start scratchpad {:?}", toggle.id))
+            .ok_or_log()
+        {
+            running.insert(toggle.id, pad);
+        }
+    }
+}
+
+async fn start(toggle: &host::ToggleScratch) -> anyhow::Result<Scratchpad> {
+    let host::ToggleScratch {
+        id,
+        cmd,
+        size:
+            host::ScratchSize {
+                lines,
+                columns,
+                #[expect(deprecated)]
+                    __non_exhaustive_struct_update: (),
+            },
+        monitor,
+        #[expect(deprecated)]
+            __non_exhaustive_struct_update: (),
+    } = toggle;
+
+    let dir = tokio::task::spawn_blocking(TempDir::new).await??;
+    let mut command = tokio::process::Command::new("kitten");
+    command
+        .arg("panel")
+        .arg(format!("--app-id=scratchbar-scratch-{id}"))
+        .arg("-o=allow_remote_control=socket-only")
+        .arg(format!(
+            "--listen-on=unix:{}",
+            dir.path().join("kitty.sock").display()
+        ))
+        // Like kitty's quick-access-terminal
+        .args([
+            "--layer=overlay",
+            "--exclusive-zone=0",
+            "--override-exclusive-zone",
+            "--focus-policy=exclusive",
+        ])
+        .arg(format!("--lines={lines}"));
+    match columns {
+        Some(columns) => command
+            .arg("--edge=center-sized")
+            .arg(format!("--columns={columns}")),
+        None => command.arg("--edge=top"),
+    };
+    if let Some(monitor) = monitor {
+        command.arg(format!("--output-name={monitor}"));
+    }
+    let child = command
+        .args(cmd)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::io::stderr())
+        .kill_on_drop(true)
+        .spawn()
+        .context("Failed to spawn kitten panel")?;
+    Ok(Scratchpad { child, dir })
+}
+
+async fn toggle_visibility(pad: &Scratchpad) {
+    let Some(socket) = find_socket(pad).await else {
+        log::error!("Scratchpad has no remote control socket yet");
+        return;
+    };
+    let output = tokio::process::Command::new("kitten")
+        .arg("@")
+        .arg("--to")
+        .arg(format!("unix:{}", socket.display()))
+        .args(["resize-os-window", "--action=toggle-visibility"])
+        .stdin(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .context("Failed to run kitten @")
+        .ok_or_log();
+    if let Some(output) = output
+        && !output.status.success()
+    {
+        log::error!(
+            "Failed to toggle scratchpad: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+}
+
+/// Kitty may add its pid to the name of the socket, so this takes the only file in the
+/// directory.
+async fn find_socket(pad: &Scratchpad) -> Option<PathBuf> {
+    let mut entries = tokio::fs::read_dir(pad.dir.path()).await.ok_or_log()?;
+    let entry = entries.next_entry().await.ok_or_log()??;
+    Some(entry.path())
+}
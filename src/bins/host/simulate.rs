@@ -0,0 +1,509 @@
+//! Panels that run in-process instead of in a terminal, see [`host::testing`].
+//!
+//! A simulated panel speaks the same [`TermUpdate`]/[`TermEvent`] protocol as a real one, but
+//! interprets the printed output into a [`tui::Grid`] instead of displaying it. Panels are
+//! registered by their log name before the monitor is started, and started by
+//! [`SimulatedBackend`] instead of spawning a terminal.
+
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    sync::{Arc, LazyLock, Mutex, MutexGuard},
+};
+
+use anyhow::Context as _;
+use futures::future::BoxFuture;
+use tokio::sync::{mpsc::UnboundedSender, watch};
+use tokio_util::sync::CancellationToken;
+
+use super::backend::{
+    KittyBackend, Panel, PanelBackend, PanelCapabilities, PanelGeometry, PanelSpec,
+};
+use crate::{
+    bins::inst::{TermEvent, TermUpdate},
+    host, tui,
+    utils::ResultExt as _,
+};
+
+type Registry = HashMap<String, Arc<PanelShared>>;
+
+fn registry() -> MutexGuard<'static, Registry> {
+    static REGISTRY: LazyLock<Mutex<Registry>> = LazyLock::new(Default::default);
+    REGISTRY.lock().unwrap_or_else(|poison| poison.into_inner())
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poison| poison.into_inner())
+}
+
+struct PanelState {
+    /// The size in cells.
+    size: tui::Vec2<u16>,
+    grid: tui::Grid,
+    output: Vec<u8>,
+    remote_control: Vec<Vec<OsString>>,
+    visible: bool,
+}
+impl PanelState {
+    fn sizes(&self) -> tui::Sizes {
+        let font = tui::Vec2::from(tui::testing::FONT_SIZE);
+        tui::Sizes {
+            cell_size: self.size,
+            pix_size: tui::Vec2 {
+                x: self.size.x.saturating_mul(font.x),
+                y: self.size.y.saturating_mul(font.y),
+            },
+        }
+    }
+
+    fn resize(&mut self, size: tui::Vec2<u16>) {
+        self.size = size;
+        self.grid = tui::Grid::new(size);
+    }
+
+    /// Applies the parts of kitty's remote control that affect the panel's content. Returns
+    /// whether the size changed.
+    fn remote_control(&mut self, args: &[OsString]) -> bool {
+        self.remote_control.push(args.to_vec());
+        let args = Vec::from_iter(args.iter().filter_map(|it| it.to_str()));
+        if args.first() != Some(&"resize-os-window") {
+            return false;
+        }
+        for arg in &args[1..] {
+            match arg.strip_prefix("--action=") {
+                Some("show") => self.visible = true,
+                Some("hide") => self.visible = false,
+                Some("toggle-visibility") => self.visible = !self.visible,
+                _ => {}
+            }
+        }
+        if let Some(lines) = args
+            .iter()
+            .find_map(|it| it.strip_prefix("lines=")?.parse::<u16>().ok())
+            && lines != self.size.y
+        {
+            self.resize(tui::Vec2 {
+                x: self.size.x,
+                y: lines,
+            });
+            return true;
+        }
+        false
+    }
+}
+
+struct PanelShared {
+    state: Mutex<PanelState>,
+    /// The number of flushes so far.
+    flushes: watch::Sender<u64>,
+    /// The event sender of the currently running panel.
+    ev_tx: Mutex<Option<UnboundedSender<TermEvent>>>,
+}
+
+/// Starts the simulated panels that are registered by log name.
+#[derive(Debug)]
+pub(super) struct SimulatedBackend;
+impl PanelBackend for SimulatedBackend {
+    fn spawn<'a>(
+        &'a self,
+        spec: &'a PanelSpec,
+        ev_tx: UnboundedSender<TermEvent>,
+        cancel: &'a CancellationToken,
+    ) -> BoxFuture<'a, anyhow::Result<Panel>> {
+        Box::pin(async move {
+            start_panel(&spec.log_name, &ev_tx, cancel)
+                .with_context(|| format!("No simulated panel named {:?}", spec.log_name))
+        })
+    }
+
+    // Simulated panels understand the remote control commands of kitty
+    fn capabilities(&self) -> PanelCapabilities {
+        KittyBackend.capabilities()
+    }
+
+    fn set_visible(&self, visible: bool) -> Option<TermUpdate> {
+        KittyBackend.set_visible(visible)
+    }
+
+    fn set_geometry(&self, geometry: &PanelGeometry) -> Option<TermUpdate> {
+        KittyBackend.set_geometry(geometry)
+    }
+}
+
+fn start_panel(
+    log_name: &str,
+    term_ev_tx: &UnboundedSender<TermEvent>,
+    cancel: &CancellationToken,
+) -> Option<Panel> {
+    let shared = registry().get(log_name)?.clone();
+    log::debug!("Starting simulated panel {log_name}");
+
+    let (upd_tx, mut upd_rx) =
+        tokio::sync::mpsc::channel(super::monitor_inst::PANEL_QUEUE_CAPACITY);
+    let panel_cancel = cancel.child_token();
+
+    *lock(&shared.ev_tx) = Some(term_ev_tx.clone());
+    term_ev_tx
+        .send(TermEvent::Sizes(lock(&shared.state).sizes()))
+        .ok_or_debug();
+
+    let term_ev_tx = term_ev_tx.clone();
+    let auto_cancel = panel_cancel.clone().drop_guard();
+    tokio::spawn(async move {
+        let _auto_cancel = auto_cancel;
+        while let Some(upd) = upd_rx.recv().await {
+            match upd {
+                TermUpdate::Print(buf) => {
+                    let mut state = lock(&shared.state);
+                    state.grid.apply(&buf);
+                    state.output.extend_from_slice(&buf);
+                }
+                TermUpdate::Flush => shared.flushes.send_modify(|it| *it += 1),
+                TermUpdate::RemoteControl(args) => {
+                    let mut state = lock(&shared.state);
+                    if state.remote_control(&args) {
+                        term_ev_tx
+                            .send(TermEvent::Sizes(state.sizes()))
+                            .ok_or_debug();
+                    }
+                }
+                TermUpdate::Shell(..) | TermUpdate::SetLogLevel(_) => {}
+            }
+        }
+    });
+
+    Some(Panel {
+        upd_tx,
+        cancel: panel_cancel,
+    })
+}
+
+/// A panel of a [`SimulatedMonitor`].
+#[derive(Clone)]
+pub struct SimulatedPanel {
+    shared: Arc<PanelShared>,
+}
+impl SimulatedPanel {
+    fn new(size: tui::Vec2<u16>, visible: bool) -> Self {
+        Self {
+            shared: Arc::new(PanelShared {
+                state: Mutex::new(PanelState {
+                    size,
+                    grid: tui::Grid::new(size),
+                    output: Vec::new(),
+                    remote_control: Vec::new(),
+                    visible,
+                }),
+                flushes: watch::Sender::new(0),
+                ev_tx: Mutex::new(None),
+            }),
+        }
+    }
+
+    /// The size of the panel in cells. Each cell is [`tui::testing::FONT_SIZE`] pixels large.
+    pub fn size(&self) -> tui::Size {
+        lock(&self.shared.state).size.into()
+    }
+
+    /// Whether the panel was last shown or hidden using kitty's remote control.
+    pub fn is_visible(&self) -> bool {
+        lock(&self.shared.state).visible
+    }
+
+    /// The content of the panel, in the format of the text rows of
+    /// [`tui::testing::render_to_string`].
+    pub fn screen(&self) -> String {
+        lock(&self.shared.state).grid.to_text(false)
+    }
+
+    /// Everything that was printed to the panel so far.
+    pub fn output(&self) -> Vec<u8> {
+        lock(&self.shared.state).output.clone()
+    }
+
+    /// The arguments of the kitty remote control commands that the panel received so far.
+    pub fn remote_control(&self) -> Vec<Vec<OsString>> {
+        lock(&self.shared.state).remote_control.clone()
+    }
+
+    /// Waits until the panel is flushed, i.e. until the next frame is complete.
+    pub async fn flushed(&self) {
+        let mut flushes = self.shared.flushes.subscribe();
+        _ = flushes.changed().await;
+    }
+
+    fn send(&self, ev: crossterm::event::Event) {
+        if let Some(ev_tx) = &*lock(&self.shared.ev_tx) {
+            ev_tx.send(TermEvent::Crossterm(ev)).ok_or_debug();
+        }
+    }
+
+    /// Simulates the mouse interacting with the cell at the given column and row.
+    /// [`tui::InteractKind::Toggle`] and [`tui::InteractKind::ValueChange`] are simulated as a
+    /// left click.
+    pub fn mouse(&self, kind: tui::InteractKind, column: u16, row: u16) {
+        self.send_mouse(tui::testing::mouse_event_kind(kind), column, row);
+    }
+
+    /// Simulates releasing the left mouse button over the cell at the given column and row.
+    pub fn mouse_release(&self, column: u16, row: u16) {
+        use crossterm::event::{MouseButton as MB, MouseEventKind as MK};
+
+        self.send_mouse(MK::Up(MB::Left), column, row);
+    }
+
+    fn send_mouse(&self, kind: crossterm::event::MouseEventKind, column: u16, row: u16) {
+        self.send(crossterm::event::Event::Mouse(tui::testing::mouse_event(
+            kind, column, row,
+        )));
+    }
+
+    /// Simulates typing `text` into the panel, with `\n` pressing enter.
+    pub fn type_text(&self, text: &str) {
+        for c in text.chars() {
+            let code = match c {
+                '\n' => crossterm::event::KeyCode::Enter,
+                c => crossterm::event::KeyCode::Char(c),
+            };
+            self.send(crossterm::event::Event::Key(
+                crossterm::event::KeyEvent::new(code, crossterm::event::KeyModifiers::NONE),
+            ));
+        }
+    }
+
+    /// Simulates pressing a single key.
+    pub fn press_key(&self, key: host::Key, modifiers: host::KeyModifiers) {
+        use crossterm::event::{KeyCode, KeyModifiers};
+
+        let code = match key {
+            host::Key::Char(c) => KeyCode::Char(c),
+            host::Key::Enter => KeyCode::Enter,
+            host::Key::Escape => KeyCode::Esc,
+            host::Key::Tab => KeyCode::Tab,
+            host::Key::Backspace => KeyCode::Backspace,
+            host::Key::Delete => KeyCode::Delete,
+            host::Key::Insert => KeyCode::Insert,
+            host::Key::Up => KeyCode::Up,
+            host::Key::Down => KeyCode::Down,
+            host::Key::Left => KeyCode::Left,
+            host::Key::Right => KeyCode::Right,
+            host::Key::Home => KeyCode::Home,
+            host::Key::End => KeyCode::End,
+            host::Key::PageUp => KeyCode::PageUp,
+            host::Key::PageDown => KeyCode::PageDown,
+            host::Key::F(n) => KeyCode::F(n),
+        };
+        let host::KeyModifiers { shift, ctrl, alt } = modifiers;
+        let mut mods = KeyModifiers::NONE;
+        mods.set(KeyModifiers::SHIFT, shift);
+        mods.set(KeyModifiers::CONTROL, ctrl);
+        mods.set(KeyModifiers::ALT, alt);
+        self.send(crossterm::event::Event::Key(
+            crossterm::event::KeyEvent::new(code, mods),
+        ));
+    }
+
+    /// Simulates the mouse leaving the panel.
+    pub fn mouse_leave(&self) {
+        self.send(crossterm::event::Event::Mouse(
+            crossterm::event::MouseEvent {
+                kind: crossterm::event::MouseEventKind::KittyLeaveWindow,
+                column: 0,
+                row: 0,
+                modifiers: crossterm::event::KeyModifiers::NONE,
+            },
+        ));
+    }
+}
+
+/// A monitor whose bar, menu, OSD and prompt are [`SimulatedPanel`]s. The monitor is stopped when this is
+/// dropped.
+pub struct SimulatedMonitor {
+    name: Arc<str>,
+    bar: SimulatedPanel,
+    menu: SimulatedPanel,
+    osd: SimulatedPanel,
+    prompt: SimulatedPanel,
+    bar_state: super::BarTuiStateSender,
+    open_menu_tx: watch::Sender<Option<host::OpenMenu>>,
+    menu_slot_tx: tokio::sync::broadcast::Sender<super::MenuSlotUpdate>,
+    osd_tx: watch::Sender<Option<host::ShowOsd>>,
+    prompt_tx: watch::Sender<Option<host::OpenPrompt>>,
+    event_rx: std::sync::mpsc::Receiver<host::HostEvent>,
+    _cancel: tokio_util::sync::DropGuard,
+}
+impl SimulatedMonitor {
+    /// Starts the panels of an unscaled monitor that is `width` physical pixels wide, like the
+    /// host does, with simulated panels instead of terminals. The options that concern the
+    /// controller connection are ignored.
+    ///
+    /// # Panics
+    /// If called outside of a tokio runtime, or if a simulated monitor with the same name is
+    /// already running.
+    pub fn start(
+        name: impl Into<Arc<str>>,
+        width: u32,
+        height: u32,
+        opts: host::HostConnectOpts,
+    ) -> Self {
+        let monitor = host::MonitorInfo {
+            name: name.into(),
+            scale: 1.0,
+            width,
+            height,
+            transform: host::MonitorTransform::Normal,
+        };
+        let width = u16::try_from(monitor.width / u32::from(tui::testing::FONT_SIZE.width))
+            .unwrap_or(u16::MAX);
+        let bar = SimulatedPanel::new(tui::Vec2 { x: width, y: 1 }, true);
+        let menu = SimulatedPanel::new(tui::Vec2 { x: width, y: 1 }, false);
+        let osd = SimulatedPanel::new(tui::Vec2 { x: width, y: 1 }, false);
+        let prompt = SimulatedPanel::new(tui::Vec2 { x: width, y: 1 }, false);
+        {
+            let mut registry = registry();
+            for (kind, panel) in [
+                ("BAR", &bar),
+                ("MENU", &menu),
+                ("OSD", &osd),
+                ("PROMPT", &prompt),
+            ] {
+                let prev =
+                    registry.insert(format!("{kind}@{}", monitor.name), panel.shared.clone());
+                assert!(
+                    prev.is_none(),
+                    "Monitor {:?} is already simulated",
+                    monitor.name
+                );
+            }
+        }
+
+        let bar_state = super::BarTuiStateSender {
+            tui: watch::Sender::new(tui::Elem::empty()),
+            hidden: watch::Sender::new(false),
+        };
+        let open_menu_tx = watch::Sender::new(None);
+        let (menu_slot_tx, _) = tokio::sync::broadcast::channel(16);
+        let osd_tx = watch::Sender::new(None);
+        let prompt_tx = watch::Sender::new(None);
+        let (event_tx, event_rx) = std::sync::mpsc::channel();
+        let cancel = CancellationToken::new();
+
+        tokio::spawn(super::monitor_inst::run_monitor(
+            super::monitor_inst::RunMonitorArgs {
+                monitor: monitor.clone(),
+                cancel_monitor: cancel.clone(),
+                bar_state_tx: watch::Sender::new(bar_state.clone()),
+                open_menu_rx: open_menu_tx.subscribe(),
+                osd_rx: osd_tx.subscribe(),
+                prompt_rx: prompt_tx.subscribe(),
+                panels_tx: watch::Sender::new(super::PanelInfos::new()),
+                query_state_tx: watch::Sender::new(Default::default()),
+                event_tx,
+                show_interactive_rx: watch::Sender::new(None).subscribe(),
+                log_level_rx: watch::Sender::new(None).subscribe(),
+                batching_rx: watch::Sender::new(false).subscribe(),
+                menu_slot_tx: menu_slot_tx.clone(),
+                config: Arc::new(super::HostConfig {
+                    backend: Arc::new(SimulatedBackend),
+                    ..super::bin_entry_point::host_config(opts, Default::default())
+                }),
+            },
+        ));
+
+        Self {
+            name: monitor.name,
+            bar,
+            menu,
+            osd,
+            prompt,
+            bar_state,
+            open_menu_tx,
+            menu_slot_tx,
+            osd_tx,
+            prompt_tx,
+            event_rx,
+            _cancel: cancel.drop_guard(),
+        }
+    }
+
+    pub fn bar(&self) -> &SimulatedPanel {
+        &self.bar
+    }
+
+    pub fn menu(&self) -> &SimulatedPanel {
+        &self.menu
+    }
+
+    pub fn osd(&self) -> &SimulatedPanel {
+        &self.osd
+    }
+
+    pub fn prompt(&self) -> &SimulatedPanel {
+        &self.prompt
+    }
+
+    /// The events that the host would send to the controller.
+    pub fn events(&self) -> &std::sync::mpsc::Receiver<host::HostEvent> {
+        &self.event_rx
+    }
+
+    /// Like [`host::HostUpdate::UpdateBars`].
+    pub fn set_bar(&self, tui: tui::Elem) {
+        self.bar_state.tui.send_replace(tui);
+    }
+
+    /// Like [`host::HostUpdate::UpdateBarSlot`].
+    pub fn update_bar_slot(&self, slot: tui::CustomId, tui: tui::Elem) {
+        self.bar_state.tui.send_if_modified(|cur| {
+            let Some((new_tui, _)) = cur.replace_slot(&slot, &tui) else {
+                return false;
+            };
+            *cur = new_tui;
+            true
+        });
+    }
+
+    /// Like [`host::HostUpdate::UpdateBars`] with [`host::BarUpdate::Hide`] and
+    /// [`host::BarUpdate::Show`].
+    pub fn set_bar_hidden(&self, hidden: bool) {
+        self.bar_state.hidden.send_replace(hidden);
+    }
+
+    /// Like [`host::HostUpdate::OpenMenu`] and [`host::HostUpdate::CloseMenu`]. The menu's
+    /// monitor is ignored.
+    pub fn set_menu(&self, menu: Option<host::OpenMenu>) {
+        self.open_menu_tx
+            .send_replace(menu.map(|menu| host::OpenMenu {
+                monitor: self.name.clone(),
+                ..menu
+            }));
+    }
+
+    /// Like [`host::HostUpdate::UpdateMenuSlot`].
+    pub fn update_menu_slot(&self, slot: tui::CustomId, tui: tui::Elem) {
+        _ = self.menu_slot_tx.send(super::MenuSlotUpdate { slot, tui });
+    }
+
+    /// Like [`host::HostUpdate::ShowOsd`] and [`host::HostUpdate::HideOsd`].
+    pub fn set_osd(&self, osd: Option<host::ShowOsd>) {
+        self.osd_tx.send_replace(osd);
+    }
+
+    /// Like [`host::HostUpdate::OpenPrompt`] and [`host::HostUpdate::ClosePrompt`]. The prompt's
+    /// monitor is ignored.
+    pub fn set_prompt(&self, prompt: Option<host::OpenPrompt>) {
+        self.prompt_tx
+            .send_replace(prompt.map(|prompt| host::OpenPrompt {
+                monitor: self.name.clone(),
+                ..prompt
+            }));
+    }
+}
+impl Drop for SimulatedMonitor {
+    fn drop(&mut self) {
+        let mut registry = registry();
+        for kind in ["BAR", "MENU", "OSD", "PROMPT"] {
+            registry.remove(&format!("{kind}@{}", self.name));
+        }
+    }
+}
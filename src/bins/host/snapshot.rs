@@ -0,0 +1,188 @@
+//! Persists the bar state to a runtime file, so that a restarted host can show the bars
+//! immediately instead of waiting for the controller to send everything again.
+//!
+//! Menus are not persisted, since the open menu is closed by a restart anyway.
+//!
+//! Each controller has its own file, so that hosts of different controllers do not show each
+//! other's bars. Controllers are told apart by their command line, or the address that remote
+//! controllers connect to, see [`super::HostConfig::snapshot_controller`].
+//!
+//! The file contains the protocol version (`u32`, little endian), followed by a frame (see
+//! [`crate::framing`]). Snapshots of other protocol versions are ignored.
+
+use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Duration};
+
+use anyhow::Context as _;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::{tui, utils::ResultExt as _};
+
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(2);
+
+fn snapshot_path(controller: &str) -> PathBuf {
+    // FNV-1a, since the hash has to stay the same across versions of the host
+    let hash = controller.bytes().fold(0xcbf29ce484222325_u64, |hash, b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x100000001b3)
+    });
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+        .join(format!("scratchbar-state-{hash:016x}.bin"))
+}
+
+#[derive(Serialize, Deserialize)]
+struct BarSnapshot {
+    tui: tui::Elem,
+    hidden: bool,
+}
+/// Monitors whose state follows the defaults are not stored separately.
+#[derive(Serialize, Deserialize)]
+struct MonitorSnapshot {
+    tui: Option<tui::Elem>,
+    hidden: Option<bool>,
+}
+#[derive(Serialize, Deserialize)]
+struct HostStateSnapshot {
+    /// Checked on restore, in case the hashes in the file names collide.
+    controller: Arc<str>,
+    defaults: BarSnapshot,
+    by_monitor: HashMap<Arc<str>, MonitorSnapshot>,
+}
+impl HostStateSnapshot {
+    fn take(controller: &Arc<str>, states: &super::BarTuiStates) -> Self {
+        let super::BarTuiStates {
+            by_monitor,
+            defaults,
+        } = states;
+        Self {
+            controller: controller.clone(),
+            defaults: BarSnapshot {
+                tui: defaults.tui.borrow().clone(),
+                hidden: *defaults.hidden.borrow(),
+            },
+            by_monitor: by_monitor
+                .iter()
+                .map(|(monitor, state)| {
+                    let state = state.borrow();
+                    let snapshot = MonitorSnapshot {
+                        tui: (!state.tui.same_channel(&defaults.tui))
+                            .then(|| state.tui.borrow().clone()),
+                        hidden: (!state.hidden.same_channel(&defaults.hidden))
+                            .then(|| *state.hidden.borrow()),
+                    };
+                    (monitor.clone(), snapshot)
+                })
+                .collect(),
+        }
+    }
+
+    fn restore(self, states: &mut super::BarTuiStates) {
+        let Self {
+            controller: _,
+            defaults: BarSnapshot { tui, hidden },
+            by_monitor,
+        } = self;
+        states.defaults.tui.send_replace(tui);
+        states.defaults.hidden.send_replace(hidden);
+        for (monitor, MonitorSnapshot { tui, hidden }) in by_monitor {
+            states.get_or_mk_monitor(monitor).send_modify(|state| {
+                if let Some(tui) = tui {
+                    state.tui = watch::Sender::new(tui);
+                }
+                if let Some(hidden) = hidden {
+                    state.hidden = watch::Sender::new(hidden);
+                }
+            });
+        }
+    }
+}
+
+/// Restores the snapshot left by a previous host of the same controller, if there is a
+/// compatible one.
+pub(super) async fn restore_snapshot(
+    controller: &str,
+    bar_tui_states_tx: &watch::Sender<super::BarTuiStates>,
+) -> bool {
+    let path = snapshot_path(controller);
+    let mut buf = match tokio::fs::read(&path).await {
+        Ok(buf) => buf,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return false,
+        Err(err) => {
+            log::error!("Failed to read state snapshot {path:?}: {err}");
+            return false;
+        }
+    };
+    let Some((protocol, frame)) = buf.split_first_chunk_mut::<4>() else {
+        log::error!("Ignoring truncated state snapshot {path:?}");
+        return false;
+    };
+    if u32::from_le_bytes(*protocol) != crate::ctrl_ipc::PROTOCOL_VERSION {
+        log::info!("Ignoring state snapshot {path:?} of a different version");
+        return false;
+    }
+    let Some(snapshot) = crate::framing::decode::<HostStateSnapshot>(frame)
+        .with_context(|| format!("Ignoring invalid state snapshot {path:?}"))
+        .ok_or_log()
+    else {
+        return false;
+    };
+    if *snapshot.controller != *controller {
+        log::info!("Ignoring state snapshot {path:?} of another controller");
+        return false;
+    }
+    bar_tui_states_tx.send_modify(|states| snapshot.restore(states));
+    log::info!("Restored state snapshot from {path:?}");
+    true
+}
+
+/// Removes the snapshot, so that the next host starts without one.
+pub(super) async fn remove_snapshot(controller: &str) {
+    let path = snapshot_path(controller);
+    match tokio::fs::remove_file(&path).await {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+            log::error!("Failed to remove state snapshot {path:?}: {err}");
+        }
+        _ => {}
+    }
+}
+
+/// Periodically writes the state to the snapshot file if it changed.
+pub(super) async fn run_snapshot_writer(
+    controller: Arc<str>,
+    bar_tui_states_rx: watch::Receiver<super::BarTuiStates>,
+) {
+    let path = snapshot_path(&controller);
+    let tmp_path = path.with_extension("tmp");
+
+    let mut last = None;
+    loop {
+        tokio::time::sleep(SNAPSHOT_INTERVAL).await;
+
+        let snapshot = HostStateSnapshot::take(&controller, &bar_tui_states_rx.borrow());
+        let Some(frame) = crate::framing::encode(&snapshot)
+            .context("Failed to encode state snapshot")
+            .ok_or_log()
+        else {
+            continue;
+        };
+        let buf = [&crate::ctrl_ipc::PROTOCOL_VERSION.to_le_bytes()[..], &frame].concat();
+        if last.as_ref() == Some(&buf) {
+            continue;
+        }
+
+        // Write to a temporary file first, so that a crash never leaves a partial snapshot
+        let res = async {
+            tokio::fs::write(&tmp_path, &buf).await?;
+            tokio::fs::rename(&tmp_path, &path).await
+        };
+        if res
+            .await
+            .with_context(|| format!("Failed to write state snapshot {path:?}"))
+            .ok_or_log()
+            .is_some()
+        {
+            last = Some(buf);
+        }
+    }
+}
@@ -0,0 +1,289 @@
+//! Transient systemd units for the panels, see [`crate::host::HostConnectOpts::systemd_scopes`],
+//! and the notify protocol for running the host as a `Type=notify` service.
+
+use std::{
+    collections::HashSet,
+    sync::{
+        Arc, LazyLock, Mutex, Weak,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use anyhow::Context as _;
+use tokio::sync::watch;
+
+use crate::utils::ResultExt as _;
+
+/// Escapes a string for use in a unit name, like `systemd-escape`. In particular, this escapes
+/// `-`, which separates the levels of the slice hierarchy.
+fn escape(name: &str) -> String {
+    use std::fmt::Write as _;
+
+    let mut escaped = String::with_capacity(name.len());
+    for (i, b) in name.bytes().enumerate() {
+        if b.is_ascii_alphanumeric() || b == b'_' || (b == b'.' && i != 0) {
+            escaped.push(b.into());
+        } else {
+            _ = write!(escaped, "\\x{b:02x}");
+        }
+    }
+    escaped
+}
+
+/// The slice that contains the scopes of a monitor's panels.
+pub(super) fn monitor_slice(monitor: &str) -> String {
+    format!("scratchbar-{}.slice", escape(monitor))
+}
+
+/// Stops all units in the slice, killing their processes.
+pub(super) async fn stop_slice(slice: &str) {
+    let output = tokio::process::Command::new("systemctl")
+        .args(["--user", "stop", slice])
+        .stdin(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .context("Failed to run systemctl")
+        .ok_or_log();
+    if let Some(output) = output
+        && !output.status.success()
+    {
+        // Also fails if the slice does not exist
+        log::debug!(
+            "Failed to stop {slice}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+}
+
+/// Stops the slice when dropped, including when the task that owns it is aborted.
+pub(super) struct StopSliceOnDrop(pub String);
+impl Drop for StopSliceOnDrop {
+    fn drop(&mut self) {
+        // --no-block only queues the stop job, so this does not block for long
+        std::process::Command::new("systemctl")
+            .args(["--user", "stop", "--no-block", &self.0])
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status()
+            .with_context(|| format!("Failed to stop {}", self.0))
+            .ok_or_debug();
+    }
+}
+
+/// How often the status is sent even if no bar changed, since the number of panels is not
+/// watched.
+const STATUS_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Default)]
+struct ServiceStatus {
+    /// `None` until the monitors were queried for the first time.
+    monitors: Option<HashSet<Arc<str>>>,
+    /// The monitors whose bar has reported its size.
+    bars_up: HashSet<Arc<str>>,
+}
+impl ServiceStatus {
+    fn ready(&self) -> bool {
+        self.monitors
+            .as_ref()
+            .is_some_and(|monitors| monitors.iter().all(|it| self.bars_up.contains(it)))
+    }
+
+    fn message(&self) -> String {
+        match &self.monitors {
+            None => "Querying monitors".into(),
+            Some(monitors) => format!(
+                "Bars running on {} of {} monitors, {} panels",
+                self.bars_up.len(),
+                monitors.len(),
+                crate::bins::inst::running_panels(),
+            ),
+        }
+    }
+}
+
+fn service_status() -> &'static watch::Sender<ServiceStatus> {
+    static STATUS: LazyLock<watch::Sender<ServiceStatus>> =
+        LazyLock::new(|| watch::Sender::new(ServiceStatus::default()));
+    &STATUS
+}
+
+/// Sets the monitors that should have a bar before the host is ready.
+pub(super) fn set_monitors(monitors: impl IntoIterator<Item = Arc<str>>) {
+    let monitors = monitors.into_iter().collect::<HashSet<_>>();
+    service_status().send_if_modified(|status| {
+        if status.monitors.as_ref() == Some(&monitors) {
+            return false;
+        }
+        status.bars_up.retain(|it| monitors.contains(it));
+        status.monitors = Some(monitors);
+        true
+    });
+}
+
+/// Records whether the bar of a monitor is running.
+pub(super) fn set_bar_up(monitor: &Arc<str>, up: bool) {
+    service_status().send_if_modified(|status| {
+        if up {
+            status.bars_up.insert(monitor.clone())
+        } else {
+            status.bars_up.remove(monitor)
+        }
+    });
+}
+
+/// The variables that systemd sets for the service. Removed from the environment of the host's
+/// children, so that they do not notify systemd in the host's name.
+pub(crate) const SERVICE_ENV_VARS: [&str; 3] = ["NOTIFY_SOCKET", "WATCHDOG_USEC", "WATCHDOG_PID"];
+
+/// Incremented whenever the watchdog would be pinged, see [`WatchdogProbe`].
+fn watchdog_probes() -> &'static watch::Sender<u64> {
+    static PROBES: LazyLock<watch::Sender<u64>> = LazyLock::new(|| watch::Sender::new(0));
+    &PROBES
+}
+
+/// The last probe that each [`WatchdogProbe`] answered.
+fn watchdog_answers() -> &'static Mutex<Vec<Weak<AtomicU64>>> {
+    static ANSWERS: Mutex<Vec<Weak<AtomicU64>>> = Mutex::new(Vec::new());
+    &ANSWERS
+}
+
+/// A loop that has to keep running for the host to work, like a monitor's main loop. The
+/// watchdog is only pinged if every such loop answered the previous probe, so systemd restarts
+/// the host if one of them hangs.
+pub(super) struct WatchdogProbe {
+    probe_rx: watch::Receiver<u64>,
+    answered: Arc<AtomicU64>,
+}
+impl WatchdogProbe {
+    pub(super) fn new() -> Self {
+        let probe_rx = watchdog_probes().subscribe();
+        let answered = Arc::new(AtomicU64::new(*probe_rx.borrow()));
+        watchdog_answers()
+            .lock()
+            .unwrap_or_else(|pe| pe.into_inner())
+            .push(Arc::downgrade(&answered));
+        Self { probe_rx, answered }
+    }
+
+    /// Waits for the next probe and answers it. Meant to be polled by the loop's `select!`.
+    pub(super) async fn probed(&mut self) {
+        // The sender is never dropped, so this only returns for new probes
+        _ = self.probe_rx.changed().await;
+        let probe = *self.probe_rx.borrow_and_update();
+        self.answered.store(probe, Ordering::Relaxed);
+    }
+}
+
+/// Whether every running [`WatchdogProbe`] answered the current probe. Starts the next one.
+fn probes_answered() -> bool {
+    let probe = *watchdog_probes().borrow();
+    let answered = {
+        let mut answers = watchdog_answers()
+            .lock()
+            .unwrap_or_else(|pe| pe.into_inner());
+        answers.retain(|it| it.strong_count() > 0);
+        answers
+            .iter()
+            .filter_map(Weak::upgrade)
+            .all(|it| it.load(Ordering::Relaxed) == probe)
+    };
+    watchdog_probes().send_replace(probe.wrapping_add(1));
+    answered
+}
+
+struct NotifySocket {
+    socket: std::os::unix::net::UnixDatagram,
+    addr: std::os::unix::net::SocketAddr,
+}
+impl NotifySocket {
+    /// Systemd sets `NOTIFY_SOCKET` for services with `Type=notify`.
+    fn from_env() -> Option<Self> {
+        let path = std::env::var_os("NOTIFY_SOCKET")?;
+        let addr = match path.to_str().and_then(|it| it.strip_prefix('@')) {
+            Some(name) => crate::transport::abstract_addr(name),
+            None => std::os::unix::net::SocketAddr::from_pathname(&path).map_err(Into::into),
+        }
+        .with_context(|| format!("Invalid NOTIFY_SOCKET {path:?}"))
+        .ok_or_log()?;
+        let socket = std::os::unix::net::UnixDatagram::unbound()
+            .context("Failed to create socket for systemd notifications")
+            .ok_or_log()?;
+        Some(Self { socket, addr })
+    }
+
+    fn send(&self, msg: &str) {
+        self.socket
+            .send_to_addr(msg.as_bytes(), &self.addr)
+            .with_context(|| format!("Failed to send {msg:?} to systemd"))
+            .ok_or_debug();
+    }
+}
+
+/// The interval in which systemd expects `WATCHDOG=1`, if it watches the host.
+fn watchdog_interval() -> Option<Duration> {
+    let timeout = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    // Like sd_watchdog_enabled, since the variables may have been meant for a parent
+    if let Ok(pid) = std::env::var("WATCHDOG_PID")
+        && pid.parse() != Ok(std::process::id())
+    {
+        return None;
+    }
+    // Half the timeout, as recommended by sd_watchdog_enabled(3)
+    Some(Duration::from_micros(timeout) / 2)
+}
+
+/// Sends `READY=1` once every monitor has a bar, keeps `STATUS=` up to date and pings the
+/// watchdog while the loops of the host respond, see [`WatchdogProbe`]. Does nothing unless
+/// the host runs as a `Type=notify` service.
+pub(super) async fn run_service_notifier() {
+    let Some(socket) = NotifySocket::from_env() else {
+        return;
+    };
+    let mut status_rx = service_status().subscribe();
+    let mut status_interval = tokio::time::interval(STATUS_INTERVAL);
+    let mut watchdog = watchdog_interval().map(tokio::time::interval);
+
+    let mut ready = false;
+    let mut last_status = String::new();
+    loop {
+        tokio::select! {
+            Ok(()) = status_rx.changed() => {}
+            _ = status_interval.tick() => {}
+            Some(_) = async { Some(watchdog.as_mut()?.tick().await) } => {
+                if probes_answered() {
+                    socket.send("WATCHDOG=1");
+                } else {
+                    log::warn!("Not pinging the watchdog, since a loop of the host is stuck");
+                }
+                continue;
+            }
+        }
+        let (status, now_ready) = {
+            let status = status_rx.borrow_and_update();
+            (status.message(), status.ready())
+        };
+
+        let mut msg = String::new();
+        if now_ready && !ready {
+            ready = true;
+            msg.push_str("READY=1\n");
+        }
+        if status != last_status {
+            msg.push_str(&format!("STATUS={status}\n"));
+            last_status = status;
+        }
+        if !msg.is_empty() {
+            socket.send(&msg);
+        }
+    }
+}
+
+/// Tells systemd that the host is stopping on purpose.
+pub(super) fn notify_stopping() {
+    if let Some(socket) = NotifySocket::from_env() {
+        socket.send("STOPPING=1");
+    }
+}
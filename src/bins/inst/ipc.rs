@@ -3,26 +3,145 @@ use std::ffi::OsString;
 use futures::{Stream, StreamExt as _};
 use serde::{Deserialize, Serialize};
 use tokio_util::{sync::CancellationToken, time::FutureExt as _};
+use tracing::Instrument as _;
 
 pub(crate) const SOCK_PATH_VAR: &str = "BAR_TERM_INSTANCE_SOCK_PATH";
 pub(crate) const PROC_LOG_NAME_VAR: &str = "BAR_TERM_INSTANCE_NAME";
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[non_exhaustive]
 pub(crate) enum TermUpdate {
-    Print(Vec<u8>),
+    Print(PrintBuf),
     Flush,
     RemoteControl(Vec<OsString>),
     Shell(OsString, Vec<OsString>), // TODO: Envs
+    SetLogLevel(log::LevelFilter),
+}
+
+/// The number of buffers that a [`BufPool`] keeps around. A panel rarely has more than a frame
+/// or two in flight.
+const BUF_POOL_CAPACITY: usize = 4;
+
+/// Recycles the buffers of a panel's [`TermUpdate::Print`]s, so that animated panels do not
+/// allocate a new buffer for every frame.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct BufPool(std::sync::Arc<std::sync::Mutex<Vec<Vec<u8>>>>);
+impl BufPool {
+    /// Takes an empty buffer that is returned to the pool when dropped.
+    pub(crate) fn take(&self) -> PrintBuf {
+        let buf = self.lock().pop().unwrap_or_default();
+        PrintBuf {
+            buf,
+            pool: Some(self.clone()),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Vec<u8>>> {
+        self.0.lock().unwrap_or_else(|poison| poison.into_inner())
+    }
+}
+
+/// The output of a [`TermUpdate::Print`]. Serialized like a `Vec<u8>`.
+#[derive(Debug, Default)]
+pub(crate) struct PrintBuf {
+    buf: Vec<u8>,
+    pool: Option<BufPool>,
+}
+impl From<Vec<u8>> for PrintBuf {
+    fn from(buf: Vec<u8>) -> Self {
+        Self { buf, pool: None }
+    }
+}
+impl Clone for PrintBuf {
+    fn clone(&self) -> Self {
+        self.buf.clone().into()
+    }
+}
+impl Drop for PrintBuf {
+    fn drop(&mut self) {
+        if let Some(pool) = &self.pool {
+            let mut pool = pool.lock();
+            if pool.len() < BUF_POOL_CAPACITY {
+                let mut buf = std::mem::take(&mut self.buf);
+                buf.clear();
+                pool.push(buf);
+            }
+        }
+    }
+}
+impl std::ops::Deref for PrintBuf {
+    type Target = Vec<u8>;
+    fn deref(&self) -> &Self::Target {
+        &self.buf
+    }
+}
+impl std::ops::DerefMut for PrintBuf {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.buf
+    }
+}
+impl std::io::Write for PrintBuf {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buf.write(buf)
+    }
+    fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        self.buf.write_all(buf)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+impl Serialize for PrintBuf {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.buf.serialize(serializer)
+    }
+}
+impl<'de> Deserialize<'de> for PrintBuf {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::deserialize(deserializer).map(Self::from)
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub(crate) enum TermEvent {
     Crossterm(crossterm::event::Event),
     Sizes(crate::tui::Sizes),
+    PanelIds(crate::host::PanelIds),
+    Log(crate::host::LogRecord),
+}
+
+/// Exchanges [`crate::ctrl_ipc::Handshake`]s with the other end of the connection.
+pub(crate) async fn exchange_handshake(
+    stream: &mut tokio::net::UnixStream,
+    peer: &str,
+) -> anyhow::Result<()> {
+    use anyhow::Context as _;
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+    let buf = postcard::to_stdvec_cobs(&crate::ctrl_ipc::Handshake::current())?;
+    stream
+        .write_all(&buf)
+        .await
+        .context("Failed to send handshake")?;
+
+    // Read bytewise to avoid consuming anything past the handshake
+    let mut buf = Vec::new();
+    loop {
+        let byte = stream
+            .read_u8()
+            .await
+            .with_context(|| format!("Failed to read handshake from {peer}"))?;
+        buf.push(byte);
+        if byte == 0 {
+            break;
+        }
+    }
+    postcard::from_bytes_cobs::<crate::ctrl_ipc::Handshake>(&mut buf)
+        .with_context(|| format!("Failed to deserialize handshake from {peer}. Is it outdated?"))?
+        .check_compat(peer)
 }
 
-pub(crate) async fn read_cobs_sock<T: serde::de::DeserializeOwned>(
+pub(crate) async fn read_frames_sock<T: serde::de::DeserializeOwned>(
     read: tokio::net::unix::OwnedReadHalf,
     tx: impl Fn(T),
     cancel: CancellationToken,
@@ -42,12 +161,11 @@ pub(crate) async fn read_cobs_sock<T: serde::de::DeserializeOwned>(
                 Ok(n) => log::trace!("Received {n} bytes"),
             }
 
-            match postcard::from_bytes_cobs(&mut buf) {
+            let _span = tracing::trace_span!("ipc_read", bytes = buf.len()).entered();
+            // Damaged frames are skipped, the next one starts after the delimiter
+            match crate::framing::decode(&mut buf) {
                 Err(err) => {
-                    log::error!(
-                        "Failed to deserialize {} from socket: {err}",
-                        std::any::type_name::<T>()
-                    );
+                    log::error!("Skipping frame from socket: {err:?}");
                 }
                 Ok(ev) => {
                     tx(ev);
@@ -61,7 +179,7 @@ pub(crate) async fn read_cobs_sock<T: serde::de::DeserializeOwned>(
     .await;
 }
 
-pub(crate) async fn write_cobs_sock<T: serde::Serialize>(
+pub(crate) async fn write_frames_sock<T: serde::Serialize>(
     mut write: tokio::net::unix::OwnedWriteHalf,
     stream: impl Stream<Item = T>,
     cancel: CancellationToken,
@@ -71,13 +189,14 @@ pub(crate) async fn write_cobs_sock<T: serde::Serialize>(
         use tokio::io::AsyncWriteExt as _;
         tokio::pin!(stream);
         while let Some(item) = stream.next().await {
-            let Ok(buf) = postcard::to_stdvec_cobs(&item)
+            let Ok(buf) = crate::framing::encode(&item)
                 .map_err(|err| log::error!("Failed to serialize update: {err}"))
             else {
                 continue;
             };
 
-            if let Err(err) = write.write_all(&buf).await {
+            let span = tracing::trace_span!("ipc_write", bytes = buf.len());
+            if let Err(err) = write.write_all(&buf).instrument(span).await {
                 log::error!(
                     "Failed to write {} to socket: {err}",
                     std::any::type_name::<T>()
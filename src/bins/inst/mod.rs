@@ -3,7 +3,6 @@ mod ipc;
 use crate::tui;
 use crate::utils::ResultExt as _;
 
-use std::ffi::OsString;
 use std::process::ExitCode;
 use std::{ffi::OsStr, path::Path, time::Duration};
 
@@ -12,38 +11,72 @@ use futures::{Stream, StreamExt as _};
 use tokio::task::JoinSet;
 use tokio_util::{sync::CancellationToken, time::FutureExt as _};
 
-pub(crate) use ipc::{TermEvent, TermUpdate};
+pub(crate) use ipc::{BufPool, TermEvent, TermUpdate};
 
+/// The tasks that wait for the terminals of the panels to exit, see [`panels_exited`].
+static PANEL_TASKS: std::sync::LazyLock<tokio_util::task::TaskTracker> =
+    std::sync::LazyLock::new(tokio_util::task::TaskTracker::new);
+
+/// Waits until the terminals of all panels have exited, e.g. after they were cancelled.
+pub(crate) async fn panels_exited() {
+    PANEL_TASKS.close();
+    PANEL_TASKS.wait().await;
+}
+
+/// The number of panels whose terminals are running.
+pub(crate) fn running_panels() -> usize {
+    PANEL_TASKS.len()
+}
+
+#[expect(clippy::too_many_arguments)]
 pub(crate) async fn start_generic_panel(
     sock_path: &Path,
     log_name: &str,
     upd_rx: impl Stream<Item = TermUpdate> + 'static + Send,
-    extra_args: impl IntoIterator<Item: AsRef<OsStr>>,
-    extra_envs: impl IntoIterator<Item = (OsString, OsString)>,
+    terminal: &str,
+    terminal_args: impl IntoIterator<Item: AsRef<OsStr>>,
+    slice: Option<&str>,
     term_ev_tx: tokio::sync::mpsc::UnboundedSender<TermEvent>,
     cancel: CancellationToken,
 ) -> anyhow::Result<()> {
     let socket = tokio::net::UnixListener::bind(sock_path)?;
 
-    let mut child = tokio::process::Command::new("kitten")
-        .arg("panel")
-        .args(extra_args)
+    // In scope mode, systemd-run runs the command itself after moving into the new scope, so
+    // the child is still the terminal
+    let mut cmd = match slice {
+        Some(slice) => {
+            let mut cmd = tokio::process::Command::new("systemd-run");
+            cmd.args(["--user", "--scope", "--quiet", "--collect"])
+                .arg(format!("--slice={slice}"))
+                .args(["--", terminal]);
+            cmd
+        }
+        None => tokio::process::Command::new(terminal),
+    };
+    for var in crate::bins::host::SERVICE_ENV_VARS {
+        cmd.env_remove(var);
+    }
+    let mut child = cmd
+        .args(terminal_args)
         .arg(std::env::current_exe().context("Failed to get current executable")?)
         .arg(INTERNAL_INST_ARG)
-        .envs(extra_envs)
         .env(ipc::SOCK_PATH_VAR, sock_path)
+        // Kitty sets this for the panel itself. Other terminals would inherit the one of the
+        // kitty window that the host was started from.
+        .env_remove("KITTY_LISTEN_ON")
         .env(ipc::PROC_LOG_NAME_VAR, log_name)
         .kill_on_drop(true)
         .stdout(std::io::stderr())
         .spawn()
         .context("Failed to spawn terminal")?;
 
-    let (socket, _) = socket
+    let (mut socket, _) = socket
         .accept()
         .await
         .context("Failed to accept socket connection")?;
+    ipc::exchange_handshake(&mut socket, "terminal instance").await?;
 
-    tokio::spawn(async move {
+    PANEL_TASKS.spawn(async move {
         let mut mgr = tokio_util::task::AbortOnDropHandle::<()>::new(tokio::spawn(
             run_term_inst_mgr(socket, term_ev_tx, upd_rx, cancel.clone()),
         ));
@@ -97,14 +130,14 @@ async fn run_term_inst_mgr(
 
     let (read_half, write_half) = connection.into_split();
 
-    tasks.spawn(ipc::read_cobs_sock::<TermEvent>(
+    tasks.spawn(ipc::read_frames_sock::<TermEvent>(
         read_half,
         move |x| {
             ev_tx.send(x).ok_or_debug();
         },
         cancel.clone(),
     ));
-    tasks.spawn(ipc::write_cobs_sock::<TermUpdate>(
+    tasks.spawn(ipc::write_frames_sock::<TermUpdate>(
         write_half,
         updates,
         cancel.clone(),
@@ -119,6 +152,10 @@ async fn run_term_inst_mgr(
 }
 
 const KITTY_FWD_VAR: &str = "KITTY_STDIO_FORWARDED";
+
+/// How often the terminal size is checked, since kitty occasionally misses resize notifications
+/// (e.g. after the dock panel's space changes).
+const SIZES_WATCHDOG_INTERVAL: Duration = Duration::from_secs(2);
 fn fwd_log_self_exe() -> Option<std::process::Command> {
     let fwd_raw = std::env::var_os(KITTY_FWD_VAR).take_if(|it| !it.is_empty())?;
 
@@ -144,7 +181,70 @@ fn fwd_log_self_exe() -> Option<std::process::Command> {
     Some(cmd)
 }
 
-pub(crate) const INTERNAL_INST_ARG: &str = "--internal-inst";
+fn query_panel_ids() -> anyhow::Result<crate::host::PanelIds> {
+    #[derive(serde::Deserialize)]
+    struct OsWindow {
+        id: u64,
+        platform_window_id: Option<u64>,
+        wm_class: Option<String>,
+        wm_name: Option<String>,
+        tabs: Vec<Tab>,
+    }
+    #[derive(serde::Deserialize)]
+    struct Tab {
+        windows: Vec<Window>,
+    }
+    #[derive(serde::Deserialize)]
+    struct Window {
+        id: u64,
+    }
+
+    let listen_on = std::env::var_os("KITTY_LISTEN_ON").context("Missing KITTY_LISTEN_ON")?;
+    let std::process::Output {
+        status,
+        stdout,
+        stderr,
+    } = std::process::Command::new("kitten")
+        .arg("@")
+        .arg("--to")
+        .arg(listen_on)
+        .arg("ls")
+        .output()?;
+    if !status.success() {
+        anyhow::bail!(
+            "kitten @ ls exited with status {status}. Stderr:\n{}",
+            String::from_utf8_lossy(&stderr)
+        );
+    }
+
+    // Every panel is its own kitty instance with a single OS window.
+    let OsWindow {
+        id,
+        platform_window_id,
+        wm_class,
+        wm_name,
+        tabs,
+    } = serde_json::from_slice::<Vec<OsWindow>>(&stdout)
+        .context("Failed to deserialize output of kitten @ ls")?
+        .into_iter()
+        .next()
+        .context("kitten @ ls did not list any OS windows")?;
+
+    Ok(crate::host::PanelIds {
+        kitty_window_id: tabs
+            .iter()
+            .flat_map(|tab| &tab.windows)
+            .map(|window| window.id)
+            .next(),
+        os_window_id: Some(id),
+        platform_window_id,
+        wm_class,
+        wm_name,
+    })
+}
+
+/// The subcommand of [`crate::bins::cli::Command::Inst`].
+pub(crate) const INTERNAL_INST_ARG: &str = "inst";
 pub(crate) fn inst_main() -> ExitCode {
     let (log_name, res) =
         match std::env::var(ipc::PROC_LOG_NAME_VAR).context("Bad log name env var") {
@@ -189,29 +289,35 @@ async fn term_proc_main_inner() -> anyhow::Result<()> {
     let (ev_tx, upd_rx);
     {
         let socket = std::env::var_os(ipc::SOCK_PATH_VAR).context("Missing socket path env var")?;
-        let socket = tokio::net::UnixStream::connect(socket)
+        let mut socket = tokio::net::UnixStream::connect(socket)
             .await
             .context("Failed to connect to socket")?;
+        ipc::exchange_handshake(&mut socket, "host").await?;
         let (read, write) = socket.into_split();
 
         let (upd_tx, mut ev_rx);
         (ev_tx, ev_rx) = tokio::sync::mpsc::unbounded_channel();
         (upd_tx, upd_rx) = std::sync::mpsc::channel::<TermUpdate>();
 
-        tasks.spawn(ipc::read_cobs_sock(
+        tasks.spawn(ipc::read_frames_sock(
             read,
             move |x| {
                 upd_tx.send(x).ok_or_debug();
             },
             cancel.clone(),
         ));
-        tasks.spawn(ipc::write_cobs_sock(
+        tasks.spawn(ipc::write_frames_sock(
             write,
             futures::stream::poll_fn(move |cx| ev_rx.poll_recv(cx)),
             cancel.clone(),
         ));
     }
 
+    let log_ev_tx = ev_tx.clone();
+    crate::logging::set_log_sink(move |record| {
+        _ = log_ev_tx.send(TermEvent::Log(crate::logging::log_record(record)));
+    });
+
     crossterm::execute!(
         std::io::stdout(),
         crossterm::terminal::EnterAlternateScreen,
@@ -228,23 +334,78 @@ async fn term_proc_main_inner() -> anyhow::Result<()> {
         .send(TermEvent::Sizes(init_sizes))
         .context("Failed to send initial font size while starting panel. Exiting.")?;
 
+    let ids_ev_tx = ev_tx.clone();
+    tokio::spawn(async move {
+        // Panels in other terminals are reported without ids
+        let ids = if std::env::var_os("KITTY_LISTEN_ON").is_some() {
+            tokio::task::spawn_blocking(query_panel_ids)
+                .await
+                .context("Failed to join panel id query")
+                .flatten()
+                .context("Failed to query panel ids")
+                .ok_or_log()
+        } else {
+            Some(Default::default())
+        };
+        if let Some(ids) = ids {
+            ids_ev_tx.send(TermEvent::PanelIds(ids)).ok_or_debug();
+        }
+    });
+
+    // Panels run the same executable as the host, so `pkill -HUP scratchbar` reaches them too.
+    // They only stop if their terminal hung up.
+    if let Some(mut hangup) =
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()).ok_or_log()
+    {
+        tasks.spawn(async move {
+            while let Some(()) = hangup.recv().await {
+                if crossterm::terminal::window_size().is_err() {
+                    log::debug!("Terminal hung up");
+                    break;
+                }
+                log::debug!("Ignoring SIGHUP, which only reloads the host");
+            }
+        });
+    }
+
     tasks.spawn(async move {
         let events = crossterm::event::EventStream::new()
             .filter_map(async |res| res.context("Crossterm error").ok_or_log());
         tokio::pin!(events);
-        while let Some(ev) = events.next().await {
-            if let crossterm::event::Event::Resize(_, _) = &ev
+        let mut last_sizes = init_sizes;
+        let mut watchdog = tokio::time::interval(SIZES_WATCHDOG_INTERVAL);
+        watchdog.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            // `None` if the watchdog fired
+            let ev = tokio::select! {
+                ev = events.next() => match ev {
+                    Some(ev) => Some(ev),
+                    None => break,
+                },
+                _ = watchdog.tick() => None,
+            };
+            let is_resize = matches!(ev, Some(crossterm::event::Event::Resize(_, _)));
+            if (is_resize || ev.is_none())
                 && let Some(sizes) = tui::Sizes::query().ok_or_log()
             {
-                if let Some(sizes) = sizes {
-                    ev_tx.send(TermEvent::Sizes(sizes)).ok_or_debug();
-                } else {
-                    log::debug!(
+                match sizes {
+                    Some(sizes) if is_resize || sizes != last_sizes => {
+                        if !is_resize {
+                            log::debug!("Terminal was resized without notification: {sizes:?}");
+                        }
+                        last_sizes = sizes;
+                        ev_tx.send(TermEvent::Sizes(sizes)).ok_or_debug();
+                    }
+                    Some(_) => {}
+                    None if is_resize => log::debug!(
                         "Terminal reported window size of 0 (this is expected if the terminal is hidden)"
-                    );
+                    ),
+                    None => {}
                 }
             }
-            ev_tx.send(TermEvent::Crossterm(ev)).ok_or_debug();
+            if let Some(ev) = ev {
+                ev_tx.send(TermEvent::Crossterm(ev)).ok_or_debug();
+            }
         }
     });
 
@@ -278,12 +439,14 @@ async fn term_proc_main_inner() -> anyhow::Result<()> {
         {
             match upd {
                 TermUpdate::Print(bytes) => {
+                    let _span = tracing::debug_span!("print", bytes = bytes.len()).entered();
                     stdout
                         .write_all(&bytes)
                         .context("Failed to print")
                         .ok_or_log();
                 }
                 TermUpdate::Flush => {
+                    let _span = tracing::debug_span!("flush").entered();
                     stdout.flush().context("Failed to flush").ok_or_log();
                 }
                 TermUpdate::RemoteControl(args) => {
@@ -304,6 +467,7 @@ async fn term_proc_main_inner() -> anyhow::Result<()> {
                 TermUpdate::Shell(cmd, args) => {
                     run_cmd(std::process::Command::new(cmd).args(args));
                 }
+                TermUpdate::SetLogLevel(level) => crate::logging::set_log_level(level),
             }
         }
     });
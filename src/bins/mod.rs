@@ -1,14 +1,16 @@
+mod cli;
 pub(crate) mod host;
 pub(crate) mod inst;
 
 #[doc(hidden)]
 #[cfg(feature = "__bin")]
 pub fn __scratchbar_bin_main() -> std::process::ExitCode {
-    if std::env::args_os().nth(1).as_deref()
-        == Some(std::ffi::OsStr::new(crate::bins::inst::INTERNAL_INST_ARG))
-    {
-        inst::inst_main()
-    } else {
-        host::host_main()
+    let cli = <cli::Cli as clap::Parser>::parse();
+    match cli.command {
+        cli::Command::Run(args) => host::host_main(args),
+        cli::Command::Msg(args) => host::msg_main(args),
+        cli::Command::Replay(args) => host::replay_main(args),
+        cli::Command::CheckConfig(args) => host::check_config_main(args),
+        cli::Command::Inst => inst::inst_main(),
     }
 }
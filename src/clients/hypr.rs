@@ -0,0 +1,288 @@
+//! A client for Hyprland's IPC sockets.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Context as _;
+use serde::{Deserialize, de::DeserializeOwned};
+use tokio::{
+    io::{AsyncBufReadExt as _, AsyncReadExt as _, AsyncWriteExt as _},
+    net::UnixStream,
+    sync::watch,
+};
+use tokio_util::task::AbortOnDropHandle;
+
+use crate::utils::ResultExt as _;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct HyprState {
+    /// Sorted by id. Special workspaces (scratchpads) are left out.
+    pub workspaces: Vec<HyprWorkspace>,
+    pub monitors: Vec<HyprMonitor>,
+    pub active_window: Option<HyprWindow>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct HyprWorkspace {
+    pub id: i64,
+    pub name: Arc<str>,
+    pub monitor: Option<Arc<str>>,
+    /// Whether the workspace is shown on its monitor.
+    pub is_active: bool,
+    /// Whether a window on the workspace requested attention.
+    pub is_urgent: bool,
+    pub windows: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct HyprMonitor {
+    pub name: Arc<str>,
+    pub is_focused: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct HyprWindow {
+    pub title: Arc<str>,
+    pub class: Arc<str>,
+    pub workspace: Option<i64>,
+}
+
+/// A connection to the running Hyprland instance.
+#[derive(Debug)]
+pub struct HyprClient {
+    state_rx: watch::Receiver<HyprState>,
+    socket_dir: PathBuf,
+    _background: AbortOnDropHandle<()>,
+}
+impl HyprClient {
+    /// Whether the current session is running under Hyprland.
+    pub fn is_running() -> bool {
+        std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some()
+    }
+
+    /// Starts following the state of Hyprland in the background. A change of `reload_rx`
+    /// fetches the complete state again.
+    pub fn connect(reload_rx: watch::Receiver<()>) -> anyhow::Result<Self> {
+        let socket_dir = socket_dir()?;
+        let (state_tx, state_rx) = watch::channel(HyprState::default());
+        Ok(Self {
+            state_rx,
+            _background: AbortOnDropHandle::new(tokio::spawn(run_bg(
+                socket_dir.clone(),
+                state_tx,
+                reload_rx,
+            ))),
+            socket_dir,
+        })
+    }
+
+    pub fn state(&self) -> watch::Receiver<HyprState> {
+        self.state_rx.clone()
+    }
+
+    /// Runs a dispatcher, e.g. `workspace 1`.
+    pub async fn dispatch(&self, dispatcher: &str) -> anyhow::Result<()> {
+        let resp = request(&self.socket_dir, &format!("dispatch {dispatcher}")).await?;
+        if resp.trim() != "ok" {
+            anyhow::bail!("Hyprland rejected dispatcher {dispatcher:?}: {resp}");
+        }
+        Ok(())
+    }
+
+    pub async fn switch_workspace(&self, name: &str) -> anyhow::Result<()> {
+        self.dispatch(&format!("workspace name:{name}")).await
+    }
+}
+
+fn socket_dir() -> anyhow::Result<PathBuf> {
+    let signature = std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE")
+        .context("Hyprland is not running: HYPRLAND_INSTANCE_SIGNATURE is not set")?;
+    let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(|it| Path::new(&it).join("hypr").join(&signature))
+        .filter(|it| it.exists());
+    // Versions before 0.40 use /tmp
+    Ok(runtime_dir.unwrap_or_else(|| Path::new("/tmp/hypr").join(signature)))
+}
+
+async fn request(socket_dir: &Path, req: &str) -> anyhow::Result<String> {
+    let mut socket = UnixStream::connect(socket_dir.join(".socket.sock"))
+        .await
+        .context("Failed to connect to Hyprland")?;
+    socket.write_all(req.as_bytes()).await?;
+    let mut resp = String::new();
+    socket.read_to_string(&mut resp).await?;
+    Ok(resp)
+}
+
+async fn query<T: DeserializeOwned>(socket_dir: &Path, what: &str) -> anyhow::Result<T> {
+    let resp = request(socket_dir, &format!("j/{what}")).await?;
+    serde_json::from_str(&resp).with_context(|| format!("Failed to parse Hyprland {what}"))
+}
+
+#[derive(Deserialize)]
+struct RawWorkspace {
+    id: i64,
+    name: String,
+    monitor: String,
+    windows: u32,
+}
+#[derive(Deserialize)]
+struct RawWorkspaceRef {
+    id: i64,
+}
+#[derive(Deserialize)]
+struct RawMonitor {
+    name: String,
+    #[serde(rename = "activeWorkspace")]
+    active_workspace: RawWorkspaceRef,
+    focused: bool,
+}
+/// `activewindow` is an empty object if no window is focused, so everything is optional.
+#[derive(Deserialize)]
+struct RawWindow {
+    #[serde(default)]
+    address: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    class: String,
+    workspace: Option<RawWorkspaceRef>,
+}
+
+/// Window addresses are prefixed with `0x` in queries, but not in events.
+fn window_addr(addr: &str) -> &str {
+    addr.trim_start_matches("0x")
+}
+
+async fn fetch(socket_dir: &Path, urgent: &HashSet<String>) -> anyhow::Result<HyprState> {
+    let (workspaces, monitors, active_window) = tokio::try_join!(
+        query::<Vec<RawWorkspace>>(socket_dir, "workspaces"),
+        query::<Vec<RawMonitor>>(socket_dir, "monitors"),
+        query::<RawWindow>(socket_dir, "activewindow"),
+    )?;
+    let urgent_workspaces: HashSet<i64> = if urgent.is_empty() {
+        HashSet::new()
+    } else {
+        query::<Vec<RawWindow>>(socket_dir, "clients")
+            .await?
+            .into_iter()
+            .filter(|it| urgent.contains(window_addr(&it.address)))
+            .filter_map(|it| Some(it.workspace?.id))
+            .collect()
+    };
+
+    let mut workspaces: Vec<_> = workspaces
+        .into_iter()
+        .filter(|ws| ws.id > 0)
+        .map(|ws| HyprWorkspace {
+            id: ws.id,
+            is_active: monitors
+                .iter()
+                .any(|mon| mon.name == ws.monitor && mon.active_workspace.id == ws.id),
+            is_urgent: urgent_workspaces.contains(&ws.id),
+            monitor: (!ws.monitor.is_empty()).then(|| ws.monitor.into()),
+            name: ws.name.into(),
+            windows: ws.windows,
+        })
+        .collect();
+    workspaces.sort_by_key(|ws| ws.id);
+
+    Ok(HyprState {
+        workspaces,
+        monitors: monitors
+            .into_iter()
+            .map(|mon| HyprMonitor {
+                name: mon.name.into(),
+                is_focused: mon.focused,
+            })
+            .collect(),
+        active_window: (!active_window.address.is_empty()).then(|| HyprWindow {
+            title: active_window.title.into(),
+            class: active_window.class.into(),
+            workspace: active_window.workspace.map(|it| it.id),
+        }),
+    })
+}
+
+/// Updates the addresses of urgent windows. Returns whether the state has to be fetched again.
+fn handle_event(line: &str, urgent: &mut HashSet<String>) -> bool {
+    let Some((event, data)) = line.split_once(">>") else {
+        return false;
+    };
+    match event {
+        "urgent" => {
+            urgent.insert(window_addr(data).to_owned());
+            true
+        }
+        "activewindowv2" | "closewindow" => {
+            urgent.remove(window_addr(data));
+            true
+        }
+        "windowtitlev2" | "openwindow" | "movewindowv2" | "workspacev2" | "focusedmonv2"
+        | "createworkspacev2" | "destroyworkspacev2" | "moveworkspacev2" | "renameworkspace"
+        | "monitoraddedv2" | "monitorremovedv2" => true,
+        _ => false,
+    }
+}
+
+async fn run_bg(
+    socket_dir: PathBuf,
+    state_tx: watch::Sender<HyprState>,
+    mut reload_rx: watch::Receiver<()>,
+) {
+    let mut urgent = HashSet::new();
+    loop {
+        let events = match UnixStream::connect(socket_dir.join(".socket2.sock")).await {
+            Ok(events) => events,
+            Err(err) => {
+                log::error!(
+                    "Failed to connect to Hyprland events. Retrying in {}s: {err}",
+                    RECONNECT_DELAY.as_secs()
+                );
+                tokio::time::sleep(RECONNECT_DELAY).await;
+                continue;
+            }
+        };
+        let mut lines = tokio::io::BufReader::new(events).lines();
+        let mut refetch = true;
+        loop {
+            if refetch
+                && let Some(state) = fetch(&socket_dir, &urgent)
+                    .await
+                    .context("Failed to fetch Hyprland state")
+                    .ok_or_log()
+            {
+                state_tx.send_if_modified(|cur| {
+                    let changed = *cur != state;
+                    *cur = state;
+                    changed
+                });
+            }
+            refetch = tokio::select! {
+                line = lines.next_line() => match line {
+                    Ok(Some(line)) => handle_event(&line, &mut urgent),
+                    Ok(None) => {
+                        log::warn!("Hyprland closed the event socket");
+                        break;
+                    }
+                    Err(err) => {
+                        log::error!("Failed to read Hyprland events: {err}");
+                        break;
+                    }
+                },
+                Ok(()) = reload_rx.changed() => true,
+            };
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
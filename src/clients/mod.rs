@@ -0,0 +1,4 @@
+//! Clients for the services that [`crate::modules`] display.
+
+pub mod hypr;
+pub mod pulse;
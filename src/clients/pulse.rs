@@ -0,0 +1,298 @@
+//! A client for PulseAudio and PipeWire (through `pipewire-pulse`), using the `pactl` command.
+
+use std::{process::Stdio, sync::Arc, time::Duration};
+
+use anyhow::Context as _;
+use serde::{Deserialize, de::DeserializeOwned};
+use tokio::{io::AsyncBufReadExt as _, sync::watch};
+use tokio_util::task::AbortOnDropHandle;
+
+use crate::utils::ResultExt as _;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// Changes usually cause several events at once, which are handled together.
+const EVENT_DEBOUNCE: Duration = Duration::from_millis(20);
+/// The raw volume that corresponds to 100%.
+const VOLUME_NORM: f64 = 0x10000 as f64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PulseDeviceKind {
+    /// An output, e.g. speakers.
+    Sink,
+    /// An input, e.g. a microphone.
+    Source,
+}
+impl PulseDeviceKind {
+    fn pactl_name(self) -> &'static str {
+        match self {
+            Self::Sink => "sink",
+            Self::Source => "source",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+#[non_exhaustive]
+pub struct PulseState {
+    pub sinks: Vec<PulseDevice>,
+    /// Monitors of sinks are left out.
+    pub sources: Vec<PulseDevice>,
+    pub default_sink: Option<Arc<str>>,
+    pub default_source: Option<Arc<str>>,
+}
+impl PulseState {
+    pub fn devices(&self, kind: PulseDeviceKind) -> &[PulseDevice] {
+        match kind {
+            PulseDeviceKind::Sink => &self.sinks,
+            PulseDeviceKind::Source => &self.sources,
+        }
+    }
+
+    pub fn default_name(&self, kind: PulseDeviceKind) -> Option<&Arc<str>> {
+        match kind {
+            PulseDeviceKind::Sink => self.default_sink.as_ref(),
+            PulseDeviceKind::Source => self.default_source.as_ref(),
+        }
+    }
+
+    pub fn default_device(&self, kind: PulseDeviceKind) -> Option<&PulseDevice> {
+        let name = self.default_name(kind)?;
+        self.devices(kind).iter().find(|it| it.name == *name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct PulseDevice {
+    pub name: Arc<str>,
+    /// A human readable name.
+    pub description: Arc<str>,
+    /// The average volume of all channels, where `1.0` is 100%.
+    pub volume: f64,
+    pub muted: bool,
+}
+
+/// A connection to the sound server.
+#[derive(Debug)]
+pub struct PulseClient {
+    state_rx: watch::Receiver<PulseState>,
+    _background: AbortOnDropHandle<()>,
+}
+impl PulseClient {
+    /// Starts following the devices of the sound server in the background. A change of
+    /// `reload_rx` fetches them again.
+    pub fn connect(reload_rx: watch::Receiver<()>) -> Self {
+        let (state_tx, state_rx) = watch::channel(PulseState::default());
+        Self {
+            state_rx,
+            _background: AbortOnDropHandle::new(tokio::spawn(run_bg(state_tx, reload_rx))),
+        }
+    }
+
+    pub fn state(&self) -> watch::Receiver<PulseState> {
+        self.state_rx.clone()
+    }
+
+    /// Changes the volume of the default device by `delta_percent` percentage points.
+    pub async fn change_volume(
+        &self,
+        kind: PulseDeviceKind,
+        delta_percent: i32,
+    ) -> anyhow::Result<()> {
+        pactl_set(kind, "volume", &format!("{delta_percent:+}%")).await
+    }
+
+    /// Sets the volume of the default device, where `1.0` is 100%.
+    pub async fn set_volume(&self, kind: PulseDeviceKind, volume: f64) -> anyhow::Result<()> {
+        pactl_set(
+            kind,
+            "volume",
+            &format!("{}", (volume * VOLUME_NORM).round() as u32),
+        )
+        .await
+    }
+
+    pub async fn toggle_mute(&self, kind: PulseDeviceKind) -> anyhow::Result<()> {
+        pactl_set(kind, "mute", "toggle").await
+    }
+
+    /// Makes the device with the given [`PulseDevice::name`] the default.
+    pub async fn set_default(&self, kind: PulseDeviceKind, name: &str) -> anyhow::Result<()> {
+        pactl(&[&format!("set-default-{}", kind.pactl_name()), name])
+            .await
+            .map(drop)
+    }
+}
+
+async fn pactl_set(kind: PulseDeviceKind, what: &str, value: &str) -> anyhow::Result<()> {
+    let kind = kind.pactl_name();
+    pactl(&[
+        &format!("set-{kind}-{what}"),
+        &format!("@DEFAULT_{}@", kind.to_uppercase()),
+        value,
+    ])
+    .await
+    .map(drop)
+}
+
+async fn pactl(args: &[&str]) -> anyhow::Result<Vec<u8>> {
+    let std::process::Output {
+        status,
+        stdout,
+        stderr,
+    } = tokio::process::Command::new("pactl")
+        .args(args)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .context("Failed to run pactl")?;
+    if !status.success() {
+        anyhow::bail!(
+            "pactl {} exited with {status}. Stderr: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&stderr).trim(),
+        );
+    }
+    Ok(stdout)
+}
+
+async fn query<T: DeserializeOwned>(args: &[&str]) -> anyhow::Result<T> {
+    let out = pactl(&[&["--format=json"], args].concat()).await?;
+    serde_json::from_slice(&out)
+        .with_context(|| format!("Failed to parse pactl {}", args.join(" ")))
+}
+
+#[derive(Deserialize)]
+struct RawInfo {
+    default_sink_name: Option<String>,
+    default_source_name: Option<String>,
+}
+#[derive(Deserialize)]
+struct RawDevice {
+    name: String,
+    #[serde(default)]
+    description: Option<String>,
+    mute: bool,
+    volume: std::collections::BTreeMap<String, RawChannelVolume>,
+    /// Only present on sources. `"n/a"` or missing if the source is not a monitor.
+    #[serde(default)]
+    monitor_of_sink: Option<String>,
+}
+#[derive(Deserialize)]
+struct RawChannelVolume {
+    value: u32,
+}
+
+impl RawDevice {
+    fn is_monitor(&self) -> bool {
+        self.monitor_of_sink
+            .as_deref()
+            .is_some_and(|it| !it.is_empty() && it != "n/a")
+    }
+
+    fn into_device(self) -> PulseDevice {
+        let channels = self.volume.len().max(1);
+        let sum: u64 = self.volume.values().map(|it| u64::from(it.value)).sum();
+        PulseDevice {
+            description: self.description.unwrap_or_else(|| self.name.clone()).into(),
+            name: self.name.into(),
+            volume: sum as f64 / channels as f64 / VOLUME_NORM,
+            muted: self.mute,
+        }
+    }
+}
+
+async fn fetch() -> anyhow::Result<PulseState> {
+    let (info, sinks, sources) = tokio::try_join!(
+        query::<RawInfo>(&["info"]),
+        query::<Vec<RawDevice>>(&["list", "sinks"]),
+        query::<Vec<RawDevice>>(&["list", "sources"]),
+    )?;
+    Ok(PulseState {
+        sinks: sinks.into_iter().map(RawDevice::into_device).collect(),
+        sources: sources
+            .into_iter()
+            .filter(|it| !it.is_monitor())
+            .map(RawDevice::into_device)
+            .collect(),
+        default_sink: info.default_sink_name.map(Into::into),
+        default_source: info.default_source_name.map(Into::into),
+    })
+}
+
+/// Whether an event line of `pactl subscribe` (e.g. `Event 'change' on sink #52`) affects the
+/// state.
+fn is_relevant_event(line: &str) -> bool {
+    let Some((_, facility)) = line.split_once(" on ") else {
+        return false;
+    };
+    let facility = facility.split(" #").next().unwrap_or(facility);
+    matches!(facility, "sink" | "source" | "server")
+}
+
+async fn run_bg(state_tx: watch::Sender<PulseState>, mut reload_rx: watch::Receiver<()>) {
+    loop {
+        let mut subscribe = match tokio::process::Command::new("pactl")
+            .arg("subscribe")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+        {
+            Ok(subscribe) => subscribe,
+            Err(err) => {
+                log::error!(
+                    "Failed to run pactl subscribe. Retrying in {}s: {err}",
+                    RECONNECT_DELAY.as_secs()
+                );
+                tokio::time::sleep(RECONNECT_DELAY).await;
+                continue;
+            }
+        };
+        let Some(stdout) = subscribe.stdout.take() else {
+            log::error!("pactl subscribe has no stdout");
+            return;
+        };
+        let mut lines = tokio::io::BufReader::new(stdout).lines();
+        let mut refetch = true;
+        loop {
+            if refetch
+                && let Some(state) = fetch()
+                    .await
+                    .context("Failed to fetch PulseAudio state")
+                    .ok_or_log()
+            {
+                state_tx.send_if_modified(|cur| {
+                    let changed = *cur != state;
+                    *cur = state;
+                    changed
+                });
+            }
+            refetch = tokio::select! {
+                line = lines.next_line() => match line {
+                    Ok(Some(line)) => {
+                        let mut relevant = is_relevant_event(&line);
+                        while let Ok(Ok(Some(line))) =
+                            tokio::time::timeout(EVENT_DEBOUNCE, lines.next_line()).await
+                        {
+                            relevant |= is_relevant_event(&line);
+                        }
+                        relevant
+                    }
+                    Ok(None) => {
+                        log::warn!("pactl subscribe exited");
+                        break;
+                    }
+                    Err(err) => {
+                        log::error!("Failed to read PulseAudio events: {err}");
+                        break;
+                    }
+                },
+                Ok(()) = reload_rx.changed() => true,
+            };
+        }
+        subscribe.kill().await.ok_or_debug();
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
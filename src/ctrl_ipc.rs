@@ -1,5 +1,4 @@
 use std::io::{BufRead, Write};
-use std::os::unix::net::UnixStream;
 use std::sync::Arc;
 use std::sync::mpsc as stdchan;
 use std::time::Duration;
@@ -7,13 +6,15 @@ use std::time::Duration;
 use anyhow::Context as _;
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
 
-use crate::utils::ResultExt as _;
-
-pub(crate) const HOST_SOCK_PATH_VAR: &str = "BAR_INTERNAL_SOCK_PATH";
+use crate::{
+    framing,
+    transport::{self, IpcStream},
+    update_queue::{Next, SentState, UpdateQueue},
+    utils::ResultExt as _,
+};
 
 #[derive(Serialize, Deserialize)]
 pub(crate) struct HostCtrlInit {
-    pub version: String,
     pub opts: crate::host::HostConnectOpts,
 }
 #[derive(Serialize, Deserialize)]
@@ -21,13 +22,90 @@ pub(crate) struct HostInitResponse {}
 
 pub(crate) const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+/// Must be bumped whenever the serialized form of any type sent between the processes changes.
+/// `docs/json-protocol.md` documents the current version.
+pub(crate) const PROTOCOL_VERSION: u32 = 44;
+/// Bitset of optional protocol features supported by this build. None are defined yet.
+pub(crate) const FEATURES: u64 = 0;
+
+/// The first message sent by both sides of every connection (host, controller and terminal
+/// instances), so that incompatible peers are reported instead of failing to decode messages.
+///
+/// The layout of this struct and its plain COBS framing must never change. Controllers using
+/// [`framing::Format::Json`] send it as a JSON line instead.
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct Handshake {
+    pub protocol: u32,
+    pub version: String,
+    pub features: u64,
+}
+impl Handshake {
+    pub(crate) fn current() -> Self {
+        Self {
+            protocol: PROTOCOL_VERSION,
+            version: VERSION.into(),
+            features: FEATURES,
+        }
+    }
+    pub(crate) fn check_compat(&self, peer: &str) -> anyhow::Result<()> {
+        let Self {
+            protocol,
+            ref version,
+            features,
+        } = *self;
+        if protocol != PROTOCOL_VERSION {
+            anyhow::bail!(
+                "Incompatible {peer}: It uses protocol version {protocol} (scratchbar {version:?}), \
+                but this process uses protocol version {PROTOCOL_VERSION} (scratchbar {VERSION:?})"
+            );
+        }
+        if version != VERSION {
+            log::warn!(
+                "The {peer} was built against scratchbar {version:?}, this process against {VERSION:?}"
+            );
+        }
+        log::debug!("Handshake with {peer} succeeded (features: {features:#x})");
+        Ok(())
+    }
+}
+
+fn send_handshake(write: &mut impl Write, format: framing::Format) -> anyhow::Result<()> {
+    let handshake = match format {
+        framing::Format::Postcard => postcard::to_stdvec_cobs(&Handshake::current())?,
+        #[cfg(feature = "__bin")]
+        framing::Format::Json => format.encode(&Handshake::current())?,
+    };
+    write.write_all(&handshake)?;
+    write.flush()?;
+    Ok(())
+}
+fn read_handshake(
+    read: &mut impl BufRead,
+    format: framing::Format,
+    peer: &str,
+) -> anyhow::Result<()> {
+    let mut buf = Vec::new();
+    read.read_until(format.delimiter(), &mut buf)?;
+    let handshake = match format {
+        framing::Format::Postcard => postcard::from_bytes_cobs::<Handshake>(&mut buf).ok(),
+        #[cfg(feature = "__bin")]
+        framing::Format::Json => serde_json::from_slice(buf.trim_ascii_end()).ok(),
+    };
+    handshake
+        .with_context(|| format!("Failed to read handshake from {peer}. Is it outdated?"))?
+        .check_compat(peer)
+}
+
+#[cfg(feature = "__bin")]
 enum StopStateInner<S> {
     Running { stop: S },
     Stopped { err: bool },
 }
+#[cfg(feature = "__bin")]
 struct StopState<S> {
     shared: Arc<std::sync::Mutex<StopStateInner<S>>>,
 }
+#[cfg(feature = "__bin")]
 impl<S> Clone for StopState<S> {
     fn clone(&self) -> Self {
         Self {
@@ -35,6 +113,7 @@ impl<S> Clone for StopState<S> {
         }
     }
 }
+#[cfg(feature = "__bin")]
 impl<S> StopState<S> {
     fn new(stop: S) -> Self {
         Self {
@@ -43,6 +122,7 @@ impl<S> StopState<S> {
     }
 }
 
+#[cfg(feature = "__bin")]
 #[track_caller]
 fn try_run<S: FnOnce(anyhow::Result<()>), I, IO>(
     mut io: IO,
@@ -99,6 +179,7 @@ impl<F: FnOnce()> DropGuard<F> {
     fn new(f: F) -> Self {
         Self(Some(f))
     }
+    #[cfg(feature = "__bin")]
     fn disable(mut self) {
         _ = self.0.take();
     }
@@ -106,7 +187,12 @@ impl<F: FnOnce()> DropGuard<F> {
 
 #[derive(Clone)]
 struct SharedSocket {
-    socket: Arc<UnixStream>,
+    socket: Arc<IpcStream>,
+}
+impl std::io::Read for SharedSocket {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        (&*self.socket).read(buf)
+    }
 }
 impl Drop for SharedSocket {
     fn drop(&mut self) {
@@ -114,7 +200,7 @@ impl Drop for SharedSocket {
     }
 }
 
-fn socket_guard(stream: Arc<UnixStream>) -> DropGuard<impl FnOnce()> {
+fn socket_guard(stream: Arc<IpcStream>) -> DropGuard<impl FnOnce()> {
     DropGuard::new(move || {
         stream
             .shutdown(std::net::Shutdown::Both)
@@ -123,10 +209,12 @@ fn socket_guard(stream: Arc<UnixStream>) -> DropGuard<impl FnOnce()> {
     })
 }
 
+#[cfg(feature = "__bin")]
 #[derive(Clone)]
 struct Ready {
     state: Arc<std::sync::OnceLock<bool>>,
 }
+#[cfg(feature = "__bin")]
 impl Ready {
     fn new() -> Self {
         Self {
@@ -150,7 +238,7 @@ impl Ready {
 
 #[cfg(feature = "__bin")]
 pub(crate) fn connect_from_host<T>(
-    socket: UnixStream,
+    socket: IpcStream,
     mk_response: impl FnOnce(HostCtrlInit) -> anyhow::Result<(HostInitResponse, T)>,
     upd_tx: impl FnMut(crate::host::HostUpdate) -> Option<()> + Send + 'static,
     on_stop: impl FnOnce(anyhow::Result<()>) + Send + 'static,
@@ -158,6 +246,19 @@ pub(crate) fn connect_from_host<T>(
     let socket = Arc::new(socket);
     let sock_init_guard = socket_guard(socket.clone());
 
+    // Controllers that are not written in Rust can use JSON, which is detected from the
+    // first byte of their handshake. It is put back in front of the rest of the stream.
+    let mut first = [0];
+    std::io::Read::read_exact(&mut &*socket, &mut first)
+        .context("Failed to read handshake from controller")?;
+    let format = match first {
+        [b'{'] => framing::Format::Json,
+        _ => framing::Format::Postcard,
+    };
+    log::debug!("Controller connected using {format:?}");
+
+    send_handshake(&mut &*socket, format).context("Failed to send handshake")?;
+
     let run_ready = Ready::new();
     let init_ready_guard = run_ready.drop_guard();
 
@@ -172,12 +273,18 @@ pub(crate) fn connect_from_host<T>(
     let reader_ready = run_ready.clone();
     std::thread::spawn(move || {
         try_run(
-            std::io::BufReader::new(&*reader_sock.socket),
+            std::io::BufReader::new(std::io::Read::chain(
+                std::io::Cursor::new(first),
+                &*reader_sock.socket,
+            )),
             reader_ready,
             init_res_tx,
-            |read| read_once(read).context("Failed to read host connection request"),
+            |read| {
+                read_handshake(read, format, "controller")?;
+                read_once(read, format).context("Failed to read host connection request")
+            },
             reader_stop,
-            |read| run_ipc_reader(read, upd_tx).context("Host update reader failed"),
+            |read| run_ipc_reader(read, format, upd_tx).context("Host update reader failed"),
         );
     });
 
@@ -194,9 +301,11 @@ pub(crate) fn connect_from_host<T>(
             std::io::BufWriter::new(&*writer_sock.socket),
             writer_ready,
             resp_res_tx,
-            |write| send_once(write, resp).context("Failed to send host connection response"),
+            |write| {
+                send_once(write, format, resp).context("Failed to send host connection response")
+            },
             writer_stop,
-            |write| run_ipc_writer(write, ev_rx).context("Host event writer failed"),
+            |write| run_ipc_writer(write, format, ev_rx).context("Host event writer failed"),
         );
     });
     () = resp_res_rx
@@ -210,90 +319,261 @@ pub(crate) fn connect_from_host<T>(
     Ok((ret, ev_tx))
 }
 
+/// The options of [`crate::host::HostConnectOpts`] that are only used by the controller.
+struct CtrlConnectOpts {
+    addr: transport::ConnectAddr,
+    retry: crate::host::ConnectRetry,
+    connect_timeout: Option<Duration>,
+    handshake_timeout: Duration,
+    reconnect: Option<crate::host::ConnectRetry>,
+}
+
 pub(crate) fn connect_from_ctrl(
     init: HostCtrlInit,
     ev_tx: impl FnMut(crate::host::HostEvent) -> Option<()> + Send + 'static,
     on_stop: impl FnOnce(anyhow::Result<()>) + Send + 'static,
 ) -> anyhow::Result<(HostInitResponse, stdchan::Sender<crate::host::HostUpdate>)> {
-    let sock_path = std::env::var_os(HOST_SOCK_PATH_VAR).context("Missing socket path env var")?;
-    let socket =
-        Arc::new(UnixStream::connect(sock_path).context("Failed to connect to controller socket")?);
-    let sock_init_guard = socket_guard(socket.clone());
-
-    let run_ready = Ready::new();
-    let init_ready_guard = run_ready.drop_guard();
-
-    let reader_sock = SharedSocket { socket };
-    let reader_stop = StopState::new(on_stop);
+    let opts = CtrlConnectOpts {
+        addr: match &init.opts.connect_addr {
+            Some(addr) => addr.parse()?,
+            None => transport::ConnectAddr::from_env()?
+                .with_context(|| format!("Missing {} env var", transport::CONNECT_VAR))?,
+        },
+        retry: init.opts.connect_retry.clone(),
+        connect_timeout: init.opts.connect_timeout,
+        handshake_timeout: init
+            .opts
+            .handshake_timeout
+            .unwrap_or(crate::host::DEFAULT_HANDSHAKE_TIMEOUT),
+        reconnect: init.opts.reconnect.clone(),
+    };
+    let queue = Arc::new(UpdateQueue::new(init.opts.max_buffered_updates));
+    // Kept for reconnecting
+    let init = framing::Format::Postcard.encode(&init)?;
+
+    let socket = with_retry(&opts.retry, opts.connect_timeout, |timeout| {
+        opts.addr
+            .connect(timeout)
+            .with_context(|| format!("Failed to connect to host at {}", opts.addr))
+    })?;
+    let (resp, read) = open_ctrl_session(socket, &init, opts.handshake_timeout)?;
 
     let (upd_tx, upd_rx) = stdchan::channel();
-
-    let (req_res_tx, req_res_rx) = stdchan::sync_channel(0);
-
-    let writer_sock = reader_sock.clone();
-    let writer_stop = reader_stop.clone();
-    let writer_ready = run_ready.clone();
+    let feeder_queue = queue.clone();
     std::thread::spawn(move || {
-        try_run(
-            std::io::BufWriter::new(&*writer_sock.socket),
-            writer_ready,
-            req_res_tx,
-            |write| send_once(write, init).context("Failed to send host connection response"),
-            writer_stop,
-            |write| run_ipc_writer(write, upd_rx).context("Host event writer failed"),
-        );
+        while let Ok(update) = upd_rx.recv() {
+            feeder_queue.push(update);
+        }
+        feeder_queue.close();
     });
+    std::thread::spawn(move || on_stop(run_ctrl_sessions(read, &opts, &init, &queue, ev_tx)));
 
-    () = req_res_rx
-        .recv_timeout(Duration::from_secs(5))
-        .context("Failed to handle connection request")??;
+    Ok((resp, upd_tx))
+}
 
-    let (resp_res_tx, resp_res_rx) = stdchan::sync_channel(0);
-    let reader_ready = run_ready.clone();
-    std::thread::spawn(move || {
-        try_run(
-            std::io::BufReader::new(&*reader_sock.socket),
-            reader_ready,
-            resp_res_tx,
-            |read| read_once(read).context("Failed to read host connection request"),
-            reader_stop,
-            |read| run_ipc_reader(read, ev_tx).context("Host update reader failed"),
-        );
-    });
+/// Performs the handshake and sends the connection request.
+fn open_ctrl_session(
+    socket: IpcStream,
+    init: &[u8],
+    timeout: Duration,
+) -> anyhow::Result<(HostInitResponse, std::io::BufReader<SharedSocket>)> {
+    let format = framing::Format::Postcard;
+    let socket = SharedSocket {
+        socket: Arc::new(socket),
+    };
+    socket.socket.set_timeout(Some(timeout))?;
+
+    send_handshake(&mut &*socket.socket, format).context("Failed to send handshake")?;
+    (&*socket.socket)
+        .write_all(init)
+        .and_then(|()| (&*socket.socket).flush())
+        .context("Failed to send host connection request")?;
+
+    let mut read = std::io::BufReader::new(socket);
+    read_handshake(&mut read, format, "host")?;
+    let resp = read_once(&mut read, format).context("Failed to read host connection response")?;
+
+    read.get_ref().socket.set_timeout(None)?;
+    Ok((resp, read))
+}
 
-    let resp = resp_res_rx
-        .recv_timeout(Duration::from_secs(10))
-        .context("Failed to receive host connection response")??;
+enum SessionEnd {
+    /// The controller dropped the update sender.
+    Closed,
+    Disconnected,
+}
 
-    run_ready.set_ready();
-    sock_init_guard.disable();
-    init_ready_guard.disable();
+/// Runs the connection, reconnecting as configured by [`crate::host::HostConnectOpts::reconnect`].
+fn run_ctrl_sessions(
+    mut read: std::io::BufReader<SharedSocket>,
+    opts: &CtrlConnectOpts,
+    init: &[u8],
+    queue: &Arc<UpdateQueue>,
+    mut ev_tx: impl FnMut(crate::host::HostEvent) -> Option<()> + Send + 'static,
+) -> anyhow::Result<()> {
+    let format = framing::Format::Postcard;
+    let mut sent = SentState::default();
+    let mut shutdown = false;
+    loop {
+        let socket = read.get_ref().clone();
+        let reader_queue = queue.clone();
+        let reader = std::thread::spawn(move || {
+            let mut events_closed = false;
+            let res = run_ipc_reader(&mut read, format, |ev| {
+                let res = ev_tx(ev);
+                events_closed = res.is_none();
+                res
+            })
+            .context("Host event reader failed");
+            // Dropping the reader shuts down the socket, which stops the writer once it writes
+            drop(read);
+            reader_queue.disconnect();
+            (res, events_closed, ev_tx)
+        });
+
+        let write_res = write_ctrl_updates(
+            &mut std::io::BufWriter::new(&*socket.socket),
+            format,
+            queue,
+            &mut sent,
+            &mut shutdown,
+        )
+        .context("Host update writer failed");
+        drop(socket);
+
+        let (read_res, events_closed, reader_ev_tx) = reader
+            .join()
+            .map_err(|_| anyhow::anyhow!("Host event reader panicked"))?;
+        ev_tx = reader_ev_tx;
+
+        let lost = match write_res {
+            Ok(SessionEnd::Closed) => return Ok(()),
+            Ok(SessionEnd::Disconnected) => read_res.err(),
+            Err(err) => Some(err),
+        };
+        let Some(retry) = opts
+            .reconnect
+            .as_ref()
+            .filter(|_| !events_closed && !shutdown)
+        else {
+            return lost.map_or(Ok(()), Err);
+        };
+        match lost {
+            Some(err) => log::warn!("Lost the connection to the host, reconnecting: {err:?}"),
+            None => log::warn!("The host closed the connection, reconnecting"),
+        }
 
-    Ok((resp, upd_tx))
+        let resp;
+        (resp, read) = with_retry(retry, opts.connect_timeout, |timeout| {
+            let socket = opts
+                .addr
+                .connect(timeout)
+                .with_context(|| format!("Failed to connect to host at {}", opts.addr))?;
+            open_ctrl_session(socket, init, opts.handshake_timeout)
+        })
+        .context("Failed to reconnect to host")?;
+        let HostInitResponse {} = resp;
+        queue.reconnected();
+        log::info!("Reconnected to the host");
+    }
+}
+
+/// Sends the state of the bars from a previous connection, then the updates of the queue.
+fn write_ctrl_updates(
+    write: &mut impl Write,
+    format: framing::Format,
+    queue: &UpdateQueue,
+    sent: &mut SentState,
+    shutdown: &mut bool,
+) -> anyhow::Result<SessionEnd> {
+    for frame in sent.frames() {
+        write.write_all(frame)?;
+    }
+    write.flush()?;
+
+    loop {
+        let updates = match queue.next() {
+            Next::Updates(updates) => updates,
+            Next::Closed => return Ok(SessionEnd::Closed),
+            Next::Disconnected => return Ok(SessionEnd::Disconnected),
+        };
+        let _span = tracing::trace_span!("ipc_write").entered();
+        for (key, update) in updates {
+            let Some(frame) = format.encode(&update).ok_or_log() else {
+                continue;
+            };
+            *shutdown |= matches!(update, crate::host::HostUpdate::Shutdown { .. });
+            match (&key, &update) {
+                (Some(key), _) => sent.record(key, &frame),
+                (None, crate::host::HostUpdate::Batch(updates)) => {
+                    sent.record_batch(updates, format);
+                }
+                (None, _) => {}
+            }
+            write.write_all(&frame)?;
+        }
+        write.flush()?;
+    }
+}
+
+/// Calls `attempt` with the time left until `timeout` as configured by `retry`, see
+/// [`crate::host::HostConnectOpts::connect_timeout`].
+fn with_retry<T>(
+    retry: &crate::host::ConnectRetry,
+    timeout: Option<Duration>,
+    mut attempt: impl FnMut(Option<Duration>) -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    let deadline = timeout.map(|it| std::time::Instant::now() + it);
+    let mut delay = retry.initial_delay;
+    let mut retries_left = retry.retries;
+    loop {
+        let remaining = deadline.map(|it| it.saturating_duration_since(std::time::Instant::now()));
+        match attempt(remaining) {
+            Ok(val) => return Ok(val),
+            Err(err) if retries_left > 0 && remaining.is_none_or(|it| it > delay) => {
+                log::debug!("{err:#}, retrying in {delay:?}");
+                std::thread::sleep(delay);
+                delay = (delay * 2).min(retry.max_delay);
+                retries_left -= 1;
+            }
+            Err(err) => return Err(err),
+        }
+    }
 }
 
-fn send_once<IT: Serialize>(write: &mut impl Write, init: IT) -> anyhow::Result<()> {
-    let init = postcard::to_stdvec_cobs(&init)?;
+#[cfg(feature = "__bin")]
+fn send_once<IT: Serialize>(
+    write: &mut impl Write,
+    format: framing::Format,
+    init: IT,
+) -> anyhow::Result<()> {
+    let init = format.encode(&init)?;
     write.write_all(&init)?;
     write.flush()?;
     Ok(())
 }
-fn read_once<IR: DeserializeOwned>(read: &mut impl BufRead) -> anyhow::Result<IR> {
+fn read_once<IR: DeserializeOwned>(
+    read: &mut impl BufRead,
+    format: framing::Format,
+) -> anyhow::Result<IR> {
     let mut init = Vec::new();
-    read.read_until(0, &mut init)?;
-    let init = postcard::from_bytes_cobs(&mut init)?;
-    Ok(init)
+    read.read_until(format.delimiter(), &mut init)?;
+    format.decode(&mut init)
 }
 
 fn run_ipc_reader<R: DeserializeOwned>(
     read: &mut impl BufRead,
+    format: framing::Format,
     mut tx: impl FnMut(R) -> Option<()>,
 ) -> anyhow::Result<()> {
     let mut buf = Vec::new();
 
-    while read.read_until(0, &mut buf)? > 0 {
-        if let Some(val) = postcard::from_bytes_cobs(&mut buf)
-            .context("Failed to deserialize")
+    while read.read_until(format.delimiter(), &mut buf)? > 0 {
+        let _span = tracing::trace_span!("ipc_read", bytes = buf.len()).entered();
+        // Damaged frames are skipped, the next one starts after the delimiter
+        if let Some(val) = format
+            .decode(&mut buf)
+            .context("Skipping frame")
             .ok_or_log()
             && tx(val).is_none()
         {
@@ -304,17 +584,20 @@ fn run_ipc_reader<R: DeserializeOwned>(
     Ok(())
 }
 
+#[cfg(feature = "__bin")]
 fn run_ipc_writer<T: Serialize>(
     write: &mut impl Write,
+    format: framing::Format,
     rx: stdchan::Receiver<T>,
 ) -> anyhow::Result<()> {
     while let Ok(ready) = rx.recv() {
+        let _span = tracing::trace_span!("ipc_write").entered();
         let vals = std::iter::chain(
             std::iter::once(ready),
             std::iter::from_fn(|| rx.try_recv().ok()),
         );
         for val in vals {
-            if let Some(buf) = postcard::to_stdvec_cobs(&val).ok_or_log() {
+            if let Some(buf) = format.encode(&val).ok_or_log() {
                 write.write_all(&buf)?;
             }
         }
@@ -0,0 +1,145 @@
+//! Framing used by all IPC connections except for the handshake.
+//!
+//! A frame consists of the payload's length (`u32`, little endian), its CRC32 (same encoding) and
+//! the postcard-serialized payload, all of which is COBS-encoded and terminated by a zero byte.
+//! Since zero bytes only occur at the end of frames, a damaged frame never affects the next one.
+//!
+//! Controller connections may use [`Format::Json`] instead, see `docs/json-protocol.md`.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::Context as _;
+use serde::{Serialize, de::DeserializeOwned};
+
+const HEADER_LEN: usize = 8;
+
+/// The encoding of the messages on a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Format {
+    /// The framing described above.
+    Postcard,
+    /// One JSON value per line.
+    #[cfg(feature = "__bin")]
+    Json,
+}
+impl Format {
+    /// The byte that terminates every frame.
+    pub(crate) fn delimiter(self) -> u8 {
+        match self {
+            Self::Postcard => 0,
+            #[cfg(feature = "__bin")]
+            Self::Json => b'\n',
+        }
+    }
+
+    pub(crate) fn encode<T: Serialize>(self, val: &T) -> anyhow::Result<Vec<u8>> {
+        match self {
+            Self::Postcard => encode(val),
+            #[cfg(feature = "__bin")]
+            Self::Json => {
+                // Compact JSON never contains a raw newline
+                let mut frame = serde_json::to_vec(val)?;
+                frame.push(b'\n');
+                Ok(frame)
+            }
+        }
+    }
+
+    /// Decodes a frame, with or without the delimiter.
+    pub(crate) fn decode<T: DeserializeOwned>(self, frame: &mut [u8]) -> anyhow::Result<T> {
+        match self {
+            Self::Postcard => decode(frame),
+            #[cfg(feature = "__bin")]
+            Self::Json => {
+                STATS.received.fetch_add(1, Ordering::Relaxed);
+                serde_json::from_slice(frame.trim_ascii_end())
+                    .with_context(|| {
+                        format!("Failed to deserialize {}", std::any::type_name::<T>())
+                    })
+                    .inspect_err(|_| {
+                        STATS.undecodable.fetch_add(1, Ordering::Relaxed);
+                    })
+            }
+        }
+    }
+}
+
+pub(crate) fn encode<T: Serialize>(val: &T) -> anyhow::Result<Vec<u8>> {
+    let mut raw = postcard::to_extend(val, vec![0; HEADER_LEN])?;
+    let payload_len = u32::try_from(raw.len() - HEADER_LEN).context("Frame is too large")?;
+    let crc = crc32fast::hash(&raw[HEADER_LEN..]);
+    raw[..4].copy_from_slice(&payload_len.to_le_bytes());
+    raw[4..HEADER_LEN].copy_from_slice(&crc.to_le_bytes());
+
+    let mut frame = cobs::encode_vec(&raw);
+    frame.push(0);
+    Ok(frame)
+}
+
+/// Decodes a frame, with or without the terminating zero byte.
+///
+/// The buffer is modified even if decoding fails.
+pub(crate) fn decode<T: DeserializeOwned>(frame: &mut [u8]) -> anyhow::Result<T> {
+    STATS.received.fetch_add(1, Ordering::Relaxed);
+
+    let payload = check_frame(frame).inspect_err(|_| {
+        STATS.corrupt.fetch_add(1, Ordering::Relaxed);
+    })?;
+    postcard::from_bytes(payload)
+        .with_context(|| format!("Failed to deserialize {}", std::any::type_name::<T>()))
+        .inspect_err(|_| {
+            STATS.undecodable.fetch_add(1, Ordering::Relaxed);
+        })
+}
+
+fn check_frame(frame: &mut [u8]) -> anyhow::Result<&[u8]> {
+    let frame = match frame {
+        [frame @ .., 0] => frame,
+        frame => frame,
+    };
+    let len = cobs::decode_in_place(frame).context("Corrupt frame: Bad COBS encoding")?;
+    let raw = &frame[..len];
+
+    let Some((header, payload)) = raw.split_first_chunk::<HEADER_LEN>() else {
+        anyhow::bail!("Corrupt frame: Missing header");
+    };
+    let [l0, l1, l2, l3, c0, c1, c2, c3] = *header;
+    let payload_len = u32::from_le_bytes([l0, l1, l2, l3]);
+    if usize::try_from(payload_len).ok() != Some(payload.len()) {
+        anyhow::bail!(
+            "Corrupt frame: Expected {payload_len} bytes of payload, got {}",
+            payload.len()
+        );
+    }
+    if u32::from_le_bytes([c0, c1, c2, c3]) != crc32fast::hash(payload) {
+        anyhow::bail!("Corrupt frame: Checksum mismatch");
+    }
+    Ok(payload)
+}
+
+#[derive(Debug)]
+struct FrameStats {
+    received: AtomicU64,
+    corrupt: AtomicU64,
+    undecodable: AtomicU64,
+}
+static STATS: FrameStats = FrameStats {
+    received: AtomicU64::new(0),
+    corrupt: AtomicU64::new(0),
+    undecodable: AtomicU64::new(0),
+};
+
+/// Statistics about all frames received by this process so far.
+#[cfg(feature = "__bin")]
+pub(crate) fn stats() -> crate::host::FrameStats {
+    let FrameStats {
+        received,
+        corrupt,
+        undecodable,
+    } = &STATS;
+    crate::host::FrameStats {
+        received: received.load(Ordering::Relaxed),
+        corrupt: corrupt.load(Ordering::Relaxed),
+        undecodable: undecodable.load(Ordering::Relaxed),
+    }
+}
@@ -18,25 +18,314 @@ impl std::fmt::Display for HostError {
 impl std::error::Error for HostError {}
 
 #[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct HostConnectOpts {
+    /// The app id of the bar panels (the layer namespace on Wayland, the class on X11), which
+    /// compositors use to match rules, e.g. for blur and shadows.
+    /// Defaults to [`DEFAULT_BAR_APP_ID`].
+    pub bar_app_id: Option<Arc<str>>,
+    /// The app id of the menu panels. Defaults to [`DEFAULT_MENU_APP_ID`].
+    pub menu_app_id: Option<Arc<str>>,
+    /// The app id of the OSD panels. Defaults to [`DEFAULT_OSD_APP_ID`].
+    pub osd_app_id: Option<Arc<str>>,
+    /// The app id of the prompt panels. Defaults to [`DEFAULT_PROMPT_APP_ID`].
+    pub prompt_app_id: Option<Arc<str>>,
+    /// Render the bar faint while a menu is open on its monitor.
+    pub dim_bar_on_menu: bool,
+    /// What the host does when the controller exits. While it is being restarted, the bars
+    /// keep showing their last content.
+    ///
+    /// Restarted controllers can change the policy, but their other options are ignored.
+    /// Has no effect on embedded controllers.
+    pub restart_controller: RestartPolicy,
+    /// The maximum rate at which bars and menus are redrawn for content updates. Updates that
+    /// arrive faster are coalesced, so that only the latest state is drawn. Redraws caused by
+    /// interactions, like hovering, are never delayed.
+    /// Defaults to [`DEFAULT_MAX_FPS`].
+    pub max_fps: Option<std::num::NonZeroU16>,
+    /// Report interactive elements that are smaller than a minimum size with
+    /// [`HostEvent::SmallInteractive`], and optionally enlarge their hitboxes. Useful for
+    /// touch screens. Disabled by default.
+    pub min_interactive_size: Option<MinInteractiveSize>,
+    /// Run the panels of each monitor in transient systemd user scopes (using
+    /// `systemd-run --user --scope`), grouped in a slice per monitor. When a monitor is removed,
+    /// the slice is stopped, which also kills processes that the panels left behind. Slices left
+    /// behind by a host that crashed are stopped when the monitor is set up again.
+    pub systemd_scopes: bool,
+    /// Send the logs of the host and its panels to the controller as [`HostEvent::Log`], e.g.
+    /// to collect them in one place. The logs are still written to stderr.
+    pub forward_logs: bool,
+    /// How many commands of [`HostUpdate::RunCommand`] run at the same time. Further commands
+    /// wait until one of them finishes. Defaults to [`DEFAULT_MAX_COMMANDS`].
+    pub max_commands: Option<std::num::NonZeroU16>,
+    /// How wide the terminal of the panels draws characters of ambiguous width. Set it to
+    /// [`tui::AmbiguousWidth::Wide`] if text in a CJK font overlaps the elements next to it.
+    pub ambiguous_width: tui::AmbiguousWidth,
+    /// The address of the host to connect to, instead of the one the host passes in the
+    /// `SCRATCHBAR_CONNECT` environment variable. Supported forms are `unix:<path>`,
+    /// `unix-abstract:<name>` and `tcp:<host>:<port>`.
+    ///
+    /// This and the following options only affect the controller's side of the connection.
+    #[serde(skip)]
+    pub connect_addr: Option<Arc<str>>,
+    /// How often to retry connecting if the host is not reachable, e.g. because it is started
+    /// separately and has not created its socket yet. Does not retry by default.
+    #[serde(skip)]
+    pub connect_retry: ConnectRetry,
+    /// How long connecting to the host may take in total, including the retries of
+    /// [`Self::connect_retry`] or [`Self::reconnect`], but not the handshake. Also limits each
+    /// attempt to connect over TCP. Unlimited by default.
+    #[serde(skip)]
+    pub connect_timeout: Option<std::time::Duration>,
+    /// How long to wait for the host to answer the connection request. Defaults to
+    /// [`DEFAULT_HANDSHAKE_TIMEOUT`].
+    #[serde(skip)]
+    pub handshake_timeout: Option<std::time::Duration>,
+    /// Connect again if the connection to the host is lost, e.g. because the host was
+    /// restarted, retrying as configured. The last content and visibility of the bars, the
+    /// content of their slots, the log level and the event filter are then sent to the new host.
+    /// Menus, popups and prompts are not restored.
+    ///
+    /// The event callback keeps receiving events from the new connection, and `on_stop` is only
+    /// called once reconnecting fails. The connection is not restored after
+    /// [`HostUpdate::Shutdown`] or once the event callback fails. Does not reconnect by default.
+    #[serde(skip)]
+    pub reconnect: Option<ConnectRetry>,
+    /// Once this many updates are waiting to be sent, e.g. while reconnecting or while the host
+    /// is busy, new updates replace the waiting updates that they make irrelevant, like earlier
+    /// content of the same bar or slot, or an earlier menu. Other updates are kept. By default,
+    /// every update is sent.
+    #[serde(skip)]
+    pub max_buffered_updates: Option<std::num::NonZeroUsize>,
+    /// Buffer up to this many events for the event callback on a separate thread, so that a
+    /// slow callback does not stall reading updates from the socket. By default, the callback
+    /// is called directly by the thread reading the socket.
+    #[serde(skip)]
+    pub event_buffer: Option<std::num::NonZeroUsize>,
+    /// What happens when [`Self::event_buffer`] is full.
+    #[serde(skip)]
+    pub event_overflow: EventOverflow,
     #[doc(hidden)]
     #[deprecated = warn_non_exhaustive!()]
+    #[serde(skip)]
     pub __non_exhaustive_struct_update: (),
 }
 
+/// See [`HostConnectOpts::min_interactive_size`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MinInteractiveSize {
+    /// The minimum size in cells. Defaults to 2x1.
+    pub size: tui::Size,
+    /// Grow the area that reacts to the mouse around smaller elements to the minimum size.
+    /// The enlarged area only receives events that do not hit another interactive element.
+    pub expand_hitboxes: bool,
+    #[doc(hidden)]
+    #[deprecated = warn_non_exhaustive!()]
+    #[serde(skip)]
+    pub __non_exhaustive_struct_update: (),
+}
+impl Default for MinInteractiveSize {
+    fn default() -> Self {
+        Self {
+            size: tui::Size {
+                width: 2,
+                height: 1,
+            },
+            expand_hitboxes: false,
+            #[expect(deprecated)]
+            __non_exhaustive_struct_update: (),
+        }
+    }
+}
+
+/// See [`HostConnectOpts::restart_controller`].
+///
+/// Restarts are delayed by an exponential backoff between 1s and 60s, which is reset once a
+/// controller stays up for a minute.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum RestartPolicy {
+    /// Exit together with the controller.
+    #[default]
+    Never,
+    /// Restart the controller if it exits with an error or its connection fails.
+    OnFailure,
+    /// Restart the controller whenever it exits.
+    Always,
+}
+
+/// See [`HostConnectOpts::connect_retry`] and [`HostConnectOpts::reconnect`].
+///
+/// The delay between attempts doubles after each one, up to `max_delay`.
+#[derive(Debug, Clone)]
+pub struct ConnectRetry {
+    /// The number of attempts after the first one. Use [`u32::MAX`] to keep trying.
+    pub retries: u32,
+    pub initial_delay: std::time::Duration,
+    pub max_delay: std::time::Duration,
+    #[doc(hidden)]
+    #[deprecated = warn_non_exhaustive!()]
+    pub __non_exhaustive_struct_update: (),
+}
+impl Default for ConnectRetry {
+    fn default() -> Self {
+        Self {
+            retries: 0,
+            initial_delay: std::time::Duration::from_millis(100),
+            max_delay: std::time::Duration::from_secs(5),
+            #[expect(deprecated)]
+            __non_exhaustive_struct_update: (),
+        }
+    }
+}
+
+/// See [`HostConnectOpts::event_overflow`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum EventOverflow {
+    /// Stop reading from the socket until there is space again. The host buffers the events
+    /// in the meantime.
+    #[default]
+    Block,
+    /// Drop new events, logging a warning.
+    Drop,
+}
+
+/// The app id of the bars on every monitor. The default app ids are shared by all hosts on
+/// purpose: Compositor rules match fixed app ids (e.g. `layerrule = blur, scratchbar-bar` in
+/// Hyprland), which a suffix per host or monitor would break. Each kind of panel has its own
+/// app id, so that rules can tell them apart. Set [`HostConnectOpts::bar_app_id`] and the
+/// others to tell the panels of different hosts apart.
+pub const DEFAULT_BAR_APP_ID: &str = "scratchbar-bar";
+/// See [`DEFAULT_BAR_APP_ID`].
+pub const DEFAULT_MENU_APP_ID: &str = "scratchbar-menu";
+/// See [`DEFAULT_BAR_APP_ID`].
+pub const DEFAULT_OSD_APP_ID: &str = "scratchbar-osd";
+/// See [`DEFAULT_BAR_APP_ID`].
+pub const DEFAULT_PROMPT_APP_ID: &str = "scratchbar-prompt";
+pub const DEFAULT_MAX_FPS: std::num::NonZeroU16 = std::num::NonZeroU16::new(30).unwrap();
+pub const DEFAULT_HANDSHAKE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+pub const DEFAULT_MAX_COMMANDS: std::num::NonZeroU16 = std::num::NonZeroU16::new(4).unwrap();
+pub const DEFAULT_COMMAND_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+/// The number of bytes of each output stream that [`CommandOutput`] keeps.
+pub const COMMAND_OUTPUT_LIMIT: usize = 64 * 1024;
+
 #[derive(Debug)]
 #[non_exhaustive]
 pub struct HostConnection {
     pub update_tx: HostUpdateSender,
 }
+impl HostConnection {
+    #[cfg(feature = "embedded")]
+    pub(crate) fn new(
+        tx: std::sync::mpsc::Sender<HostUpdate>,
+        queries: Arc<PendingQueries>,
+    ) -> Self {
+        Self {
+            update_tx: HostUpdateSender { tx, queries },
+        }
+    }
+
+    /// See [`HostUpdateSender::query`].
+    pub fn query(
+        &self,
+        query: Query,
+    ) -> impl Future<Output = Result<QueryResult, HostError>> + Send + 'static {
+        self.update_tx.query(query)
+    }
+}
 #[derive(Clone, Debug)]
 pub struct HostUpdateSender {
     tx: std::sync::mpsc::Sender<HostUpdate>,
+    queries: Arc<PendingQueries>,
 }
 impl HostUpdateSender {
     pub fn send(&self, update: HostUpdate) -> Result<(), std::sync::mpsc::SendError<HostUpdate>> {
         self.tx.send(update)
     }
+
+    /// Asks the host for information with [`HostUpdate::Query`] and waits for the answer, which
+    /// is not passed on as an event. Fails if the connection is closed before the host answers.
+    pub fn query(
+        &self,
+        query: Query,
+    ) -> impl Future<Output = Result<QueryResult, HostError>> + Send + 'static {
+        let (id, response_rx) = self.queries.register();
+        let sent = self.tx.send(HostUpdate::Query { id, query });
+        if sent.is_err() {
+            self.queries.remove(id);
+        }
+        async move {
+            sent.map_err(|_| HostError(anyhow::anyhow!("The connection to the host is closed")))?;
+            response_rx.await.map_err(|_| {
+                HostError(anyhow::anyhow!(
+                    "The connection to the host was closed before it answered the query"
+                ))
+            })
+        }
+    }
+}
+
+/// The queries of a connection that wait for their response, see [`HostUpdateSender::query`].
+#[derive(Debug)]
+pub(crate) struct PendingQueries {
+    next_id: std::sync::atomic::AtomicU64,
+    /// `None` once the connection is closed.
+    waiting: std::sync::Mutex<
+        Option<std::collections::HashMap<u64, futures::channel::oneshot::Sender<QueryResult>>>,
+    >,
+}
+impl PendingQueries {
+    pub(crate) fn new() -> Self {
+        Self {
+            next_id: Default::default(),
+            waiting: std::sync::Mutex::new(Some(Default::default())),
+        }
+    }
+
+    fn lock(
+        &self,
+    ) -> std::sync::MutexGuard<
+        '_,
+        Option<std::collections::HashMap<u64, futures::channel::oneshot::Sender<QueryResult>>>,
+    > {
+        self.waiting.lock().unwrap_or_else(|pe| pe.into_inner())
+    }
+
+    fn register(&self) -> (u64, futures::channel::oneshot::Receiver<QueryResult>) {
+        let id = self
+            .next_id
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let (tx, rx) = futures::channel::oneshot::channel();
+        // Otherwise, `tx` is dropped, which fails the query
+        if let Some(waiting) = &mut *self.lock() {
+            waiting.insert(id, tx);
+        }
+        (id, rx)
+    }
+
+    fn remove(&self, id: u64) {
+        if let Some(waiting) = &mut *self.lock() {
+            waiting.remove(&id);
+        }
+    }
+
+    /// Answers the query of a [`HostEvent::QueryResponse`]. Other events are returned.
+    pub(crate) fn intercept(&self, event: HostEvent) -> Option<HostEvent> {
+        let HostEvent::QueryResponse { id, result } = event else {
+            return Some(event);
+        };
+        match self.lock().as_mut().and_then(|it| it.remove(&id)) {
+            Some(tx) => _ = tx.send(result),
+            None => log::debug!("Received a response to unknown query {id}"),
+        }
+        None
+    }
+
+    /// Fails the waiting queries and all further ones.
+    pub(crate) fn close(&self) {
+        *self.lock() = None;
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -46,6 +335,218 @@ pub enum HostUpdate {
     SetDefaultTui(SetBarTui),
     OpenMenu(OpenMenu),
     CloseMenu,
+    /// Request a [`HostEvent::Panels`] event listing the kitty window ids of all panels.
+    QueryPanels,
+    /// Replace the content of a [`tui::Elem::slot`] in the open menu. Ignored if the open menu
+    /// does not contain the slot.
+    ///
+    /// If the slot's minimum size stays the same, only the slot is redrawn, without resizing the
+    /// menu. This makes it suitable for frequent updates, like an animated image in a tooltip.
+    UpdateMenuSlot(UpdateMenuSlot),
+    /// Response to [`HostEvent::ModuleStatusRequested`].
+    ModuleStatus(Vec<ModuleStatus>),
+    /// Request a [`HostEvent::Metrics`] event.
+    QueryMetrics,
+    /// Change the log level of the host and its panels. Defaults to [`log::LevelFilter::Info`],
+    /// or [`log::LevelFilter::Debug`] in debug builds.
+    SetLogLevel(log::LevelFilter),
+    /// Stop sending the kinds of events that the controller ignores, e.g. hovering. Replaces
+    /// the previous filter. The filter is applied by the host, so the events are not sent at
+    /// all. By default, all events are sent.
+    ///
+    /// The filter is reset to the default when a new controller connects. Controllers that
+    /// reconnect with [`connect`] send their filter again.
+    SetEventFilter(EventFilter),
+    /// Show a short-lived popup over the other windows, e.g. when the volume changes. Replaces
+    /// the shown popup and restarts its timeout. Independent of the menu.
+    ShowOsd(ShowOsd),
+    /// Hide the popup of [`HostUpdate::ShowOsd`] before its timeout.
+    HideOsd,
+    /// Open a panel in the middle of a monitor that takes the keyboard focus, e.g. for a
+    /// launcher. Replaces the open prompt, on any monitor. Independent of the menu.
+    ///
+    /// Key presses that no focused text input handles are reported as [`TermEvent::Key`].
+    /// Escape closes the prompt.
+    OpenPrompt(OpenPrompt),
+    /// Close the prompt of [`HostUpdate::OpenPrompt`].
+    ClosePrompt,
+    /// Show or hide a scratchpad terminal, e.g. a dropdown terminal. The first toggle of an id
+    /// starts the terminal, which then keeps running while it is hidden. If it has exited since,
+    /// it is started again.
+    ///
+    /// Scratchpads are ordinary kitty panels that use the user's `kitty.conf`. Their app id is
+    /// `scratchbar-scratch-{id}`. They are stopped when the host exits.
+    ToggleScratch(ToggleScratch),
+    /// Replace the content of a [`tui::Elem::slot`] in the bars of all monitors, e.g. the text of
+    /// a clock. Bars that do not contain the slot are not affected.
+    ///
+    /// The rest of the bar keeps its layout and hover state, and is only redrawn if the slot's
+    /// minimum size changes.
+    UpdateBarSlot(UpdateBarSlot),
+    /// Run a helper command in the host, e.g. `playerctl metadata`, and report its output with
+    /// [`HostEvent::CommandFinished`].
+    ///
+    /// Unlike commands started by the controller itself, the command is killed when it runs
+    /// longer than its timeout, and at most [`HostConnectOpts::max_commands`] commands run at
+    /// the same time.
+    RunCommand(RunCommand),
+    /// Stop the host, which then exits with `code`, e.g. for a logout button.
+    ///
+    /// The panels are closed, the controller is disconnected and not restarted, regardless of
+    /// [`HostConnectOpts::restart_controller`], and the saved state of the bars is discarded,
+    /// so that the next host starts with empty, visible bars.
+    Shutdown {
+        code: u8,
+    },
+    /// Ask the host for information, which it answers with [`HostEvent::QueryResponse`] with the
+    /// same `id`. [`HostUpdateSender::query`] sends this and waits for the response.
+    Query {
+        id: u64,
+        query: Query,
+    },
+    /// Apply several updates at once, e.g. [`HostUpdate::SetDefaultTui`] and the bars of some
+    /// monitors, so that no bar is drawn with only some of them applied. The bars are redrawn
+    /// once all updates are applied. Batches can be nested.
+    Batch(Vec<HostUpdate>),
+}
+
+/// The status of one of the controller's modules, for debugging. The host does not know about
+/// modules, it only passes this on to [`MsgCommand::QueryModules`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModuleStatus {
+    pub name: Arc<str>,
+    pub state: ModuleState,
+    /// How long ago the module last updated its content, if ever.
+    pub ms_since_update: Option<u64>,
+    /// How often the module was restarted.
+    pub restarts: u32,
+    /// How often the module updated its content. Updates that were replaced before the
+    /// controller handled them are not counted.
+    pub updates: u64,
+    /// How many of the updates were delayed by the controller's rate limit. A module with many
+    /// throttled updates is updating more often than the bar can show.
+    pub throttled: u64,
+    /// The message of the last panic of the module, if any.
+    pub last_error: Option<String>,
+    #[doc(hidden)]
+    #[deprecated = warn_non_exhaustive!()]
+    #[serde(skip)]
+    pub __non_exhaustive_struct_update: (),
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum ModuleState {
+    #[default]
+    Running,
+    /// The module exited or panicked and is waiting to be restarted.
+    Restarting,
+    /// The module exited because it has nothing to do, and is not restarted.
+    Done,
+}
+
+/// See [`HostUpdate::Query`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum Query {
+    /// The connected monitors, like [`HostEvent::Monitors`].
+    Monitors,
+    /// The size of the bar on a monitor in cells.
+    BarSize { monitor: Arc<str> },
+    /// The monitor that the menu is open on, if any.
+    OpenMenu,
+}
+
+/// See [`HostEvent::QueryResponse`]. The variant is the one of the [`Query`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum QueryResult {
+    Monitors(Vec<MonitorInfo>),
+    /// `None` if the monitor has no bar, or its size is not known yet.
+    BarSize(Option<tui::Size>),
+    OpenMenu(Option<Arc<str>>),
+}
+
+/// See [`HostUpdate::SetEventFilter`]. Each field enables a kind of event. Events that answer
+/// an update, like [`HostEvent::Panels`], and clicks are always sent.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EventFilter {
+    /// Hovering interactive elements ([`tui::InteractKind::Hover`]) and
+    /// [`TermEvent::MouseLeave`].
+    pub hover: bool,
+    /// Scrolling over interactive elements ([`tui::InteractKind::Scroll`]). Sliders still
+    /// report scrolling as [`tui::InteractKind::ValueChange`].
+    pub scroll: bool,
+    /// [`TermEvent::Key`].
+    pub keys: bool,
+    /// [`HostEvent::Gesture`].
+    pub gestures: bool,
+    /// [`HostEvent::Monitors`].
+    pub monitors: bool,
+    /// The sizes of interactive elements, see [`HostEvent::SmallInteractive`].
+    pub sizes: bool,
+    /// [`HostEvent::Log`].
+    pub logs: bool,
+    /// [`HostEvent::Diagnostics`].
+    pub diagnostics: bool,
+    /// [`HostEvent::BarGeometry`]. Enabling it sends the current geometry of every bar
+    /// again.
+    pub geometry: bool,
+    #[doc(hidden)]
+    #[deprecated = warn_non_exhaustive!()]
+    #[serde(skip)]
+    pub __non_exhaustive_struct_update: (),
+}
+impl Default for EventFilter {
+    fn default() -> Self {
+        Self {
+            hover: true,
+            scroll: true,
+            keys: true,
+            gestures: true,
+            monitors: true,
+            sizes: true,
+            logs: true,
+            diagnostics: true,
+            geometry: true,
+            #[expect(deprecated)]
+            __non_exhaustive_struct_update: (),
+        }
+    }
+}
+impl EventFilter {
+    /// Whether the filter lets `event` through.
+    pub fn allows(&self, event: &HostEvent) -> bool {
+        match event {
+            HostEvent::Term(_, TermEvent::Interact(InteractEvent { kind, .. })) => match kind {
+                tui::InteractKind::Hover => self.hover,
+                tui::InteractKind::Scroll(_) => self.scroll,
+                tui::InteractKind::Click(_)
+                | tui::InteractKind::Toggle(_)
+                | tui::InteractKind::ValueChange(_) => true,
+            },
+            HostEvent::Term(_, TermEvent::MouseLeave) => self.hover,
+            HostEvent::Term(_, TermEvent::Key(_)) => self.keys,
+            HostEvent::Gesture(_) => self.gestures,
+            HostEvent::Monitors(_) => self.monitors,
+            HostEvent::SmallInteractive(_) => self.sizes,
+            HostEvent::Log(_) => self.logs,
+            HostEvent::Diagnostics(_) => self.diagnostics,
+            HostEvent::BarGeometry(_) => self.geometry,
+            HostEvent::Term(_, TermEvent::InputChanged(_) | TermEvent::InputSubmitted(_))
+            | HostEvent::Panels(_)
+            | HostEvent::ReloadRequested
+            | HostEvent::StateRestored
+            | HostEvent::ModuleStatusRequested
+            | HostEvent::Metrics(_)
+            | HostEvent::Command(_)
+            | HostEvent::MenuRequested { .. }
+            | HostEvent::PromptClosed(_)
+            | HostEvent::CommandFinished(_)
+            | HostEvent::QueryResponse { .. } => true,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -53,13 +554,223 @@ pub struct OpenMenu {
     pub tui: tui::Elem,
     pub monitor: Arc<str>,
     pub bar_anchor: tui::CustomId,
+    #[serde(default)]
     pub opts: OpenMenuOpts,
 }
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct OpenMenuOpts {
     // TODO: Option to keep location, layout
+    /// The fraction of the monitor's height that the menu may take up. If the menu is a
+    /// vertical [`tui::Elem::stack`] that is taller than this, its items are split into columns.
+    #[serde(default)]
+    pub max_height: Option<f32>,
+    /// How long the menu waits before it opens if no menu is open, e.g. for tooltips that
+    /// should not flicker while the mouse moves across the bar. Closing or replacing the menu
+    /// before then cancels it. Switching from an open menu is immediate.
+    #[serde(default)]
+    pub open_delay: std::time::Duration,
+    /// How long the menu stays open after [`HostUpdate::CloseMenu`]. Opening a menu before
+    /// then cancels the close.
+    #[serde(default)]
+    pub close_delay: std::time::Duration,
+    #[doc(hidden)]
+    #[deprecated = warn_non_exhaustive!()]
+    #[serde(skip)]
+    pub __non_exhaustive_struct_update: (),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShowOsd {
+    pub tui: tui::Elem,
+    /// How long the popup is shown before it hides itself.
+    pub timeout: std::time::Duration,
+    #[serde(default)]
+    pub position: OsdPosition,
+    /// The monitor to show the popup on. Shown on all monitors if `None`.
+    #[serde(default)]
+    pub monitor: Option<Arc<str>>,
+    #[doc(hidden)]
+    #[deprecated = warn_non_exhaustive!()]
+    #[serde(skip)]
+    pub __non_exhaustive_struct_update: (),
+}
+impl ShowOsd {
+    pub fn new(tui: tui::Elem, timeout: std::time::Duration) -> Self {
+        Self {
+            tui,
+            timeout,
+            position: Default::default(),
+            monitor: None,
+            #[expect(deprecated)]
+            __non_exhaustive_struct_update: (),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenPrompt {
+    pub tui: tui::Elem,
+    pub monitor: Arc<str>,
+    #[serde(default)]
+    pub opts: OpenPromptOpts,
+}
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OpenPromptOpts {
+    /// The width of the prompt in cells. Defaults to the width of the content.
+    pub columns: Option<u16>,
+    /// The height of the prompt in lines. Defaults to the height of the content.
+    pub lines: Option<u16>,
+    /// The [`tui::Elem::text_input`] that has the focus when the prompt is opened.
+    pub focus: Option<tui::CustomId>,
+    #[doc(hidden)]
+    #[deprecated = warn_non_exhaustive!()]
+    #[serde(skip)]
+    pub __non_exhaustive_struct_update: (),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToggleScratch {
+    pub id: Arc<str>,
+    /// The program and its arguments. Runs the user's shell if empty.
+    #[serde(default)]
+    pub cmd: Vec<String>,
+    #[serde(default)]
+    pub size: ScratchSize,
+    /// The monitor that the scratchpad is started on. Decided by the compositor if `None`.
+    #[serde(default)]
+    pub monitor: Option<Arc<str>>,
+    #[doc(hidden)]
+    #[deprecated = warn_non_exhaustive!()]
+    #[serde(skip)]
+    pub __non_exhaustive_struct_update: (),
+}
+impl ToggleScratch {
+    pub fn new(id: impl Into<Arc<str>>) -> Self {
+        Self {
+            id: id.into(),
+            cmd: Vec::new(),
+            size: Default::default(),
+            monitor: None,
+            #[expect(deprecated)]
+            __non_exhaustive_struct_update: (),
+        }
+    }
+}
+
+/// The size of a [`ToggleScratch`] scratchpad, in cells of its font. Only used when the
+/// scratchpad is started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScratchSize {
+    pub lines: u16,
+    /// If `None`, the scratchpad spans the width of the monitor at its top edge. Otherwise, it
+    /// is centered on the monitor.
+    pub columns: Option<u16>,
+    #[doc(hidden)]
+    #[deprecated = warn_non_exhaustive!()]
+    #[serde(skip)]
+    pub __non_exhaustive_struct_update: (),
+}
+impl Default for ScratchSize {
+    fn default() -> Self {
+        Self {
+            lines: 25,
+            columns: None,
+            #[expect(deprecated)]
+            __non_exhaustive_struct_update: (),
+        }
+    }
+}
+
+/// Where a [`ShowOsd`] popup is shown. It is always centered horizontally.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum OsdPosition {
+    /// Near the top edge of the monitor, below the bar.
+    Top,
+    #[default]
+    Center,
+    /// Near the bottom edge of the monitor.
+    Bottom,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunCommand {
+    /// Returned in [`CommandOutput::id`], to match the output to the command.
+    pub id: Arc<str>,
+    /// The program and its arguments.
+    pub cmd: Vec<String>,
+    #[serde(default)]
+    pub opts: RunCommandOpts,
+}
+impl RunCommand {
+    pub fn new(id: impl Into<Arc<str>>, cmd: Vec<String>) -> Self {
+        Self {
+            id: id.into(),
+            cmd,
+            opts: Default::default(),
+        }
+    }
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RunCommandOpts {
+    /// How long the command may run, including the time it waits for other commands to
+    /// finish. Defaults to [`DEFAULT_COMMAND_TIMEOUT`].
+    pub timeout: std::time::Duration,
+    /// Start from an empty environment instead of the host's, e.g. to avoid passing on
+    /// `SCRATCHBAR_CONNECT`. Variables from [`Self::env`] are still set.
+    pub clear_env: bool,
+    /// Environment variables to set for the command.
+    pub env: Vec<(String, String)>,
+    /// The working directory of the command. Defaults to the host's.
+    pub cwd: Option<std::path::PathBuf>,
+    #[doc(hidden)]
+    #[deprecated = warn_non_exhaustive!()]
+    #[serde(skip)]
+    pub __non_exhaustive_struct_update: (),
+}
+impl Default for RunCommandOpts {
+    fn default() -> Self {
+        Self {
+            timeout: DEFAULT_COMMAND_TIMEOUT,
+            clear_env: false,
+            env: Vec::new(),
+            cwd: None,
+            #[expect(deprecated)]
+            __non_exhaustive_struct_update: (),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateMenuSlot {
+    pub slot: tui::CustomId,
+    pub tui: tui::Elem,
+    #[serde(default)]
+    pub opts: UpdateMenuSlotOpts,
+}
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateMenuSlotOpts {
     #[doc(hidden)]
     #[deprecated = warn_non_exhaustive!()]
+    #[serde(skip)]
+    pub __non_exhaustive_struct_update: (),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateBarSlot {
+    pub slot: tui::CustomId,
+    pub tui: tui::Elem,
+    #[serde(default)]
+    pub opts: UpdateBarSlotOpts,
+}
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateBarSlotOpts {
+    #[doc(hidden)]
+    #[deprecated = warn_non_exhaustive!()]
+    #[serde(skip)]
     pub __non_exhaustive_struct_update: (),
 }
 
@@ -67,6 +778,7 @@ pub struct OpenMenuOpts {
 pub struct CloseMenuOpts {
     #[doc(hidden)]
     #[deprecated = warn_non_exhaustive!()]
+    #[serde(skip)]
     pub __non_exhaustive_struct_update: (),
 }
 
@@ -85,12 +797,14 @@ impl From<SetBarTui> for BarUpdate {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SetBarTui {
     pub tui: tui::Elem,
+    #[serde(default)]
     pub options: SetBarTuiOpts,
 }
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct SetBarTuiOpts {
     #[doc(hidden)]
     #[deprecated = warn_non_exhaustive!()]
+    #[serde(skip)]
     pub __non_exhaustive_struct_update: (),
 }
 // FIXME: Use a struct similar to TermInfo instead
@@ -107,6 +821,7 @@ pub struct RegisterMenuOpts {
     // TODO: Option to set font size of menu / other options temporarily / run commands when menu is shown / hidden?
     #[doc(hidden)]
     #[deprecated = warn_non_exhaustive!()]
+    #[serde(skip)]
     pub __non_exhaustive_struct_update: (),
 }
 
@@ -114,14 +829,78 @@ pub struct RegisterMenuOpts {
 #[non_exhaustive]
 pub enum HostEvent {
     Term(TermInfo, TermEvent),
-    // TODO: Add monitor change event
+    /// Response to [`HostUpdate::QueryPanels`].
+    Panels(Vec<PanelInfo>),
+    /// A reload was requested through the control socket (`scratchbar msg reload`) or by
+    /// sending `SIGHUP` to the host.
+    ReloadRequested,
+    /// Sent when the number of damaged or undecodable frames received by the host changes.
+    Diagnostics(Diagnostics),
+    /// Sent after connecting if the host restored the bars' content and visibility from a
+    /// previous host of the same controller. Otherwise, the bars start out empty.
+    StateRestored,
+    /// The connected monitors. Sent after connecting and whenever a monitor is added, removed
+    /// or changed, e.g. when it is rotated.
+    Monitors(Vec<MonitorInfo>),
+    /// An interactive element is smaller than [`HostConnectOpts::min_interactive_size`].
+    /// Sent when the element starts being too small on a panel.
+    SmallInteractive(SmallInteractive),
+    /// The status of the controller's modules was queried through the control socket
+    /// (`scratchbar msg modules`). Answer with [`HostUpdate::ModuleStatus`].
+    ModuleStatusRequested,
+    /// A log record of the host or one of its panels, see [`HostConnectOpts::forward_logs`].
+    Log(LogRecord),
+    /// Response to [`HostUpdate::QueryMetrics`].
+    Metrics(HostMetrics),
+    /// Arguments for the controller from [`MsgCommand::Controller`], e.g. to control a module.
+    Command(Vec<String>),
+    /// A menu was requested through the control socket (`scratchbar msg open-menu`). Open the
+    /// menu of the element with `tag` on the bar of `monitor`, as if it was clicked.
+    MenuRequested {
+        monitor: Arc<str>,
+        tag: tui::CustomId,
+    },
+    /// The prompt of [`HostUpdate::OpenPrompt`] was closed.
+    PromptClosed(PromptClosed),
+    /// Response to [`HostUpdate::RunCommand`].
+    CommandFinished(CommandOutput),
+    /// A gesture on a bar, e.g. for switching workspaces.
+    Gesture(Gesture),
+    /// Response to [`HostUpdate::Query`].
+    QueryResponse {
+        id: u64,
+        result: QueryResult,
+    },
+    /// The size of a bar. Sent when a bar is started or resized, and for every bar when a
+    /// controller connects. See also [`EventFilter::geometry`].
+    BarGeometry(BarGeometry),
     // TODO: Menu closed
 }
+
+/// A gesture recognized from the mouse events of a bar. Touch screens report touches as
+/// mouse events with the left button.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum Gesture {
+    /// A quick horizontal drag across the bar. The press where it started is still reported
+    /// as a click.
+    Swipe {
+        direction: tui::Direction,
+        monitor: Arc<str>,
+    },
+}
 #[derive(Debug, Serialize, Deserialize)]
 #[non_exhaustive]
 pub enum TermEvent {
     Interact(InteractEvent),
     MouseLeave,
+    /// The text of a focused [`tui::Elem::text_input`] was edited.
+    InputChanged(InputEvent),
+    /// Enter was pressed in a focused [`tui::Elem::text_input`].
+    InputSubmitted(InputEvent),
+    /// A key press that no focused [`tui::Elem::text_input`] handled. Only panels with the
+    /// keyboard focus receive key presses, i.e. the prompt and a menu that was clicked.
+    Key(KeyEvent),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -129,6 +908,115 @@ pub enum TermEvent {
 pub struct InteractEvent {
     pub kind: tui::InteractKind,
     pub tag: Option<tui::CustomId>,
+    /// The geometry of the bar the event happened on. `None` for other panels.
+    #[serde(default)]
+    pub geometry: Option<BarGeometry>,
+}
+
+/// See [`HostEvent::BarGeometry`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct BarGeometry {
+    pub monitor: Arc<str>,
+    /// The size of the bar in cells.
+    pub cells: tui::Size,
+    /// The size of a single cell in physical pixels.
+    pub font_px: tui::Size,
+    /// The scale of the monitor.
+    pub scale: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct InputEvent {
+    pub tag: tui::CustomId,
+    /// The whole text of the input.
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct KeyEvent {
+    pub key: Key,
+    pub modifiers: KeyModifiers,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum Key {
+    Char(char),
+    Enter,
+    Escape,
+    /// Shift-Tab is reported as `Tab` with [`KeyModifiers::shift`].
+    Tab,
+    Backspace,
+    Delete,
+    Insert,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    F(u8),
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct KeyModifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct CommandOutput {
+    /// The id of the [`RunCommand`].
+    pub id: Arc<str>,
+    pub status: CommandStatus,
+    /// The first [`COMMAND_OUTPUT_LIMIT`] bytes of the command's stdout, with invalid UTF-8
+    /// replaced.
+    pub stdout: String,
+    /// Like [`Self::stdout`].
+    pub stderr: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum CommandStatus {
+    Exited(i32),
+    /// The command was killed by the signal.
+    Signaled(i32),
+    /// The command was killed because it ran longer than [`RunCommandOpts::timeout`].
+    TimedOut,
+    /// The command could not be started, e.g. because the program does not exist.
+    Failed(String),
+}
+impl CommandStatus {
+    pub fn success(&self) -> bool {
+        *self == Self::Exited(0)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct PromptClosed {
+    pub monitor: Arc<str>,
+    pub reason: PromptCloseReason,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum PromptCloseReason {
+    /// Escape was pressed.
+    Dismissed,
+    /// [`HostUpdate::ClosePrompt`] was sent.
+    Closed,
+    /// A prompt was opened on another monitor.
+    Replaced,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -138,18 +1026,232 @@ pub struct FocusEvent {
     pub is_focused: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[non_exhaustive]
 pub struct TermInfo {
     pub monitor: Arc<str>,
     pub kind: TermKind,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[non_exhaustive]
 pub enum TermKind {
     Menu,
     Bar,
+    Osd,
+    Prompt,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct PanelInfo {
+    pub term: TermInfo,
+    pub ids: PanelIds,
+}
+
+/// Identifiers of a panel's window, as reported by `kitten @ ls`.
+///
+/// These can be used to target the panels with compositor rules. Fields are `None` if kitty
+/// does not report them (e.g. `platform_window_id` on Wayland).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct PanelIds {
+    pub kitty_window_id: Option<u64>,
+    pub os_window_id: Option<u64>,
+    pub platform_window_id: Option<u64>,
+    pub wm_class: Option<String>,
+    pub wm_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct SmallInteractive {
+    pub term: TermInfo,
+    pub tag: tui::CustomId,
+    /// The size of the element in cells.
+    pub size: tui::Size,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct MonitorInfo {
+    pub name: Arc<str>,
+    pub scale: f64,
+    /// The width in physical pixels, with the transform applied. For a monitor rotated by 90
+    /// degrees, this is the height of its mode.
+    pub width: u32,
+    /// The height in physical pixels, with the transform applied.
+    pub height: u32,
+    pub transform: MonitorTransform,
+}
+impl MonitorInfo {
+    pub fn orientation(&self) -> Orientation {
+        if self.height > self.width {
+            Orientation::Portrait
+        } else {
+            Orientation::Landscape
+        }
+    }
+}
+
+/// The rotation (counter-clockwise) and flipping applied to a monitor's output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum MonitorTransform {
+    #[default]
+    Normal,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    Flipped,
+    Flipped90,
+    Flipped180,
+    Flipped270,
+}
+impl MonitorTransform {
+    /// Whether the transform swaps the monitor's width and height.
+    pub fn is_sideways(self) -> bool {
+        matches!(
+            self,
+            Self::Rotate90 | Self::Rotate270 | Self::Flipped90 | Self::Flipped270
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum Orientation {
+    Landscape,
+    Portrait,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct LogRecord {
+    pub level: log::Level,
+    /// The name of the process that logged the record, e.g. `HOST` or `BAR@DP-1`.
+    pub process: Arc<str>,
+    pub target: String,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub message: String,
+}
+
+/// Performance metrics of the host, since it started.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct HostMetrics {
+    pub panels: Vec<PanelMetrics>,
+    /// The rate of input events from all panels, averaged over the last few seconds.
+    pub events_per_sec: f32,
+    /// The time between the last menu being opened and its content being sent to the panel.
+    pub menu_open_latency_us: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct PanelMetrics {
+    pub term: TermInfo,
+    /// The number of frames rendered.
+    pub frames: u64,
+    /// The size of the rendered output sent to the panel.
+    pub bytes_written: u64,
+    pub avg_render_us: u64,
+    pub max_render_us: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Diagnostics {
+    pub frames: FrameStats,
+}
+
+/// Statistics about the IPC frames received by a process.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct FrameStats {
+    pub received: u64,
+    /// Frames with a bad encoding, length or checksum.
+    pub corrupt: u64,
+    /// Intact frames whose content could not be deserialized.
+    pub undecodable: u64,
+}
+
+/// A command sent to the host's control socket, e.g. using `scratchbar msg`.
+///
+/// Commands are sent as newline-terminated JSON, and each command is answered with a single
+/// line containing a [`MsgResponse`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum MsgCommand {
+    ShowBar {
+        monitor: Option<Arc<str>>,
+    },
+    HideBar {
+        monitor: Option<Arc<str>>,
+    },
+    /// Ask the controller to open the menu of the element with `tag` on the bar of `monitor`,
+    /// see [`HostEvent::MenuRequested`].
+    OpenMenu {
+        monitor: Arc<str>,
+        tag: tui::CustomId,
+    },
+    CloseMenu,
+    /// Restart the panels and send [`HostEvent::ReloadRequested`] to the controller.
+    Reload,
+    QueryState,
+    /// Keep the connection open and answer with a [`MsgResponse::State`] line whenever the
+    /// state changes, starting with the current state. One JSON value per line is what widget
+    /// systems like eww expect from `deflisten` scripts.
+    Listen,
+    /// Briefly highlight all interactive elements and label them with keys that activate them
+    /// while the panel has keyboard focus. Defaults to 3 seconds.
+    ShowInteractive {
+        duration_ms: Option<u64>,
+    },
+    /// Save a screenshot of the monitor (using `grim`), a dump of the bar's and menu's
+    /// content and a PNG of their cell grids to `dir`, for bug reports.
+    Capture {
+        monitor: Arc<str>,
+        dir: std::path::PathBuf,
+    },
+    /// Ask the controller for the status of its modules, see [`ModuleStatus`].
+    QueryModules,
+    QueryMetrics,
+    /// Send the arguments to the controller as [`HostEvent::Command`]. The first argument
+    /// usually names the receiver, e.g. a module.
+    Controller {
+        args: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum MsgResponse {
+    Ok,
+    State(HostState),
+    /// The files written by [`MsgCommand::Capture`].
+    Captured(Vec<std::path::PathBuf>),
+    /// Response to [`MsgCommand::QueryModules`].
+    Modules(Vec<ModuleStatus>),
+    /// Response to [`MsgCommand::QueryMetrics`].
+    Metrics(HostMetrics),
+    Error(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct HostState {
+    pub bars: Vec<BarState>,
+    /// The monitor the menu is open on, if any.
+    pub open_menu: Option<Arc<str>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct BarState {
+    pub monitor: Arc<str>,
+    pub hidden: bool,
 }
 
 pub fn connect(
@@ -157,25 +1259,94 @@ pub fn connect(
     mut event_tx: impl FnMut(HostEvent) -> Result<(), HostEvent> + Send + 'static,
     on_stop: impl FnOnce(Result<(), HostError>) + Send + 'static,
 ) -> Result<HostConnection, HostError> {
-    match ctrl_ipc::connect_from_ctrl(
-        ctrl_ipc::HostCtrlInit {
-            version: ctrl_ipc::VERSION.into(),
-            opts,
-        },
-        move |ev| {
-            event_tx(ev)
-                .map_err(std::sync::mpsc::SendError)
-                .ok_or_debug()
-        },
-        |res| on_stop(res.map_err(HostError)),
-    ) {
+    let mut event_tx: Box<dyn FnMut(HostEvent) -> Option<()> + Send> = Box::new(move |ev| {
+        event_tx(ev)
+            .map_err(std::sync::mpsc::SendError)
+            .ok_or_debug()
+    });
+    if let Some(capacity) = opts.event_buffer {
+        event_tx = buffer_events(event_tx, capacity, opts.event_overflow);
+    }
+    let queries = Arc::new(PendingQueries::new());
+    let event_tx = {
+        let queries = queries.clone();
+        move |ev| match queries.intercept(ev) {
+            Some(ev) => event_tx(ev),
+            None => Some(()),
+        }
+    };
+    let on_stop = {
+        let queries = queries.clone();
+        move |res: anyhow::Result<()>| {
+            queries.close();
+            on_stop(res.map_err(HostError))
+        }
+    };
+    match ctrl_ipc::connect_from_ctrl(ctrl_ipc::HostCtrlInit { opts }, event_tx, on_stop) {
         Ok((ctrl_ipc::HostInitResponse {}, tx)) => Ok(HostConnection {
-            update_tx: HostUpdateSender { tx },
+            update_tx: HostUpdateSender { tx, queries },
         }),
         Err(err) => Err(HostError(err)),
     }
 }
 
+/// Passes events to `event_tx` on a separate thread, see [`HostConnectOpts::event_buffer`].
+fn buffer_events(
+    mut event_tx: impl FnMut(HostEvent) -> Option<()> + Send + 'static,
+    capacity: std::num::NonZeroUsize,
+    overflow: EventOverflow,
+) -> Box<dyn FnMut(HostEvent) -> Option<()> + Send> {
+    use std::sync::mpsc::TrySendError;
+
+    let (tx, rx) = std::sync::mpsc::sync_channel(capacity.get());
+    std::thread::spawn(move || {
+        while let Ok(ev) = rx.recv() {
+            if event_tx(ev).is_none() {
+                break;
+            }
+        }
+    });
+    Box::new(move |ev| match overflow {
+        EventOverflow::Block => tx.send(ev).ok(),
+        EventOverflow::Drop => match tx.try_send(ev) {
+            Ok(()) => Some(()),
+            Err(TrySendError::Full(ev)) => {
+                log::warn!("Event buffer is full, dropping {ev:?}");
+                Some(())
+            }
+            Err(TrySendError::Disconnected(_)) => None,
+        },
+    })
+}
+
+/// Runs the host in this process instead of connecting to it, calling `controller` on a
+/// separate thread.
+///
+/// Updates and events are passed through channels directly, without serialization.
+/// Since the panels are started by running the current executable with special arguments,
+/// this must be called at the start of `main`.
+#[cfg(feature = "embedded")]
+pub fn run_embedded(
+    opts: HostConnectOpts,
+    controller: impl FnOnce(
+        HostConnection,
+        std::sync::mpsc::Receiver<HostEvent>,
+    ) -> std::process::ExitCode
+    + Send
+    + 'static,
+) -> std::process::ExitCode {
+    crate::bins::host::embedded_main(opts, controller)
+}
+
 pub fn init_controller_logger() {
     crate::logging::init_logger("CONTROLLER".into());
 }
+
+/// Running the panels of a monitor without a compositor, for integration tests of bars and menus.
+///
+/// The panels are simulated in-process: what the host prints to them is interpreted like
+/// [`tui::testing::render_to_string`] does, and mouse input can be injected.
+#[cfg(feature = "testing")]
+pub mod testing {
+    pub use crate::bins::host::simulate::{SimulatedMonitor, SimulatedPanel};
+}
@@ -6,11 +6,19 @@ macro_rules! warn_non_exhaustive {
     };
 }
 
+#[cfg(feature = "modules")]
+pub mod clients;
 pub mod host;
+#[cfg(feature = "modules")]
+pub mod modules;
+pub mod prelude;
 pub mod tui;
 
 mod ctrl_ipc;
+mod framing;
 mod logging;
+mod transport;
+mod update_queue;
 mod utils;
 
 #[cfg(feature = "__bin")]
@@ -1,4 +1,7 @@
-use std::sync::{LazyLock, OnceLock};
+use std::{
+    io::Write as _,
+    sync::{LazyLock, OnceLock},
+};
 
 const COLOR_VAR: &str = "COLOR";
 
@@ -77,6 +80,67 @@ pub(crate) fn init_logger(log_name: String) {
     }
 }
 
+static LOGGER_HANDLE: OnceLock<flexi_logger::LoggerHandle> = OnceLock::new();
+
+type LogSink = Box<dyn Fn(&log::Record) + Send + Sync>;
+/// Receives every record that is logged, in addition to stderr.
+static LOG_SINK: OnceLock<LogSink> = OnceLock::new();
+
+#[cfg(feature = "__bin")]
+/// Sets a function that receives every record that is logged from now on. Can only be set once.
+///
+/// The function must not log anything itself.
+pub(crate) fn set_log_sink(sink: impl Fn(&log::Record) + Send + Sync + 'static) {
+    if LOG_SINK.set(Box::new(sink)).is_err() {
+        log::error!("Log sink was already set");
+    }
+}
+
+#[cfg(feature = "__bin")]
+pub(crate) fn set_log_level(level: log::LevelFilter) {
+    match LOGGER_HANDLE.get() {
+        Some(handle) => {
+            handle.set_new_spec(level.into());
+            log::info!("Set log level to {level}");
+        }
+        None => log::warn!("Cannot set log level without a logger"),
+    }
+}
+
+#[cfg(feature = "__bin")]
+/// Converts a record for [`crate::host::HostEvent::Log`].
+pub(crate) fn log_record(record: &log::Record) -> crate::host::LogRecord {
+    crate::host::LogRecord {
+        level: record.level(),
+        process: PROC_NAME.get().map_or("UNKNOWN", |s| &**s).into(),
+        target: record.target().into(),
+        file: record.file().map(Into::into),
+        line: record.line(),
+        message: record.args().to_string(),
+    }
+}
+
+/// Writes to stderr and passes the records on to the [`LOG_SINK`].
+struct Writer;
+impl flexi_logger::writers::LogWriter for Writer {
+    fn write(
+        &self,
+        now: &mut flexi_logger::DeferredNow,
+        record: &log::Record,
+    ) -> std::io::Result<()> {
+        if let Some(sink) = LOG_SINK.get() {
+            sink(record);
+        }
+        let mut stderr = std::io::stderr().lock();
+        format_log(&mut stderr, now, record)?;
+        writeln!(stderr)
+    }
+
+    fn flush(&self) -> std::io::Result<()> {
+        std::io::stderr().flush()
+    }
+}
+
 fn try_init_logger() -> anyhow::Result<()> {
     use flexi_logger::*;
 
@@ -88,10 +152,9 @@ fn try_init_logger() -> anyhow::Result<()> {
     };
 
     let logger_handle = Logger::with(log_spec)
-        .format(format_log)
-        .log_to_stderr()
+        .log_to_writer(Box::new(Writer))
         .start()?;
-    std::mem::forget(logger_handle);
+    _ = LOGGER_HANDLE.set(logger_handle);
 
     Ok(())
 }
@@ -0,0 +1,126 @@
+//! Configurable reactions to clicks, so that modules do not have to hardcode them.
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, watch};
+
+use crate::{
+    modules::{MenuKind, ModuleCx, ModuleRequest, RegisterMenu},
+    tui,
+};
+
+/// What happens when an element is clicked, see [`ClickActions`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum Action {
+    #[default]
+    Noop,
+    /// Run the program with the arguments. Its output is discarded.
+    RunCommand(Vec<String>),
+    /// Open the menu of the module as a context menu. Does nothing for modules without a menu.
+    OpenMenu,
+    /// Call the callback that a module registered with [`ModuleCx::on_command`] for the first
+    /// argument with the remaining ones, like `scratchbar msg send` does.
+    EmitSignal(Vec<String>),
+}
+
+/// The [`Action`] of each mouse button.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClickActions {
+    pub left: Action,
+    pub middle: Action,
+    pub right: Action,
+    #[doc(hidden)]
+    #[deprecated = warn_non_exhaustive!()]
+    #[serde(skip)]
+    pub __non_exhaustive_struct_update: (),
+}
+impl ClickActions {
+    /// The action for an interaction, if it is a click.
+    pub fn get(&self, kind: &tui::InteractKind) -> Option<&Action> {
+        match kind {
+            tui::InteractKind::Click(tui::MouseButton::Left) => Some(&self.left),
+            tui::InteractKind::Click(tui::MouseButton::Middle) => Some(&self.middle),
+            tui::InteractKind::Click(tui::MouseButton::Right) => Some(&self.right),
+            _ => None,
+        }
+    }
+
+    /// Registers `menu_rx` for the buttons with [`Action::OpenMenu`] on the element tagged
+    /// with `tag`. The other actions have to be run from the element's callback with an
+    /// [`ActionRunner`], since an element only has one.
+    pub fn register_menus(
+        &self,
+        cx: &ModuleCx,
+        tag: &tui::CustomId,
+        menu_rx: &watch::Receiver<tui::Elem>,
+    ) {
+        for button in [
+            tui::MouseButton::Left,
+            tui::MouseButton::Middle,
+            tui::MouseButton::Right,
+        ] {
+            let on_kind = tui::InteractKind::Click(button);
+            if self.get(&on_kind) == Some(&Action::OpenMenu) {
+                cx.register_menu(RegisterMenu {
+                    on_tag: tag.clone(),
+                    on_kind,
+                    tui_rx: menu_rx.clone(),
+                    kind: MenuKind::Context,
+                });
+            }
+        }
+    }
+}
+
+/// Runs [`Action`]s from the callbacks of a module, see [`ModuleCx::action_runner`].
+#[derive(Debug, Clone)]
+pub struct ActionRunner {
+    pub(super) req_tx: mpsc::UnboundedSender<ModuleRequest>,
+}
+impl ActionRunner {
+    /// Runs the action for an interaction. Returns `false` if the interaction is not a click,
+    /// so that the callback can handle it itself.
+    pub fn on_interact(&self, actions: &ClickActions, kind: &tui::InteractKind) -> bool {
+        let Some(action) = actions.get(kind) else {
+            return false;
+        };
+        self.run(action);
+        true
+    }
+
+    pub fn run(&self, action: &Action) {
+        match action {
+            // Menus are opened by the controller, see `ClickActions::register_menus`
+            Action::Noop | Action::OpenMenu => {}
+            Action::RunCommand(args) => {
+                let Some((program, args)) = args.split_first() else {
+                    return;
+                };
+                let mut cmd = tokio::process::Command::new(program);
+                cmd.args(args)
+                    .stdin(std::process::Stdio::null())
+                    .stdout(std::process::Stdio::null());
+                let program = program.clone();
+                tokio::spawn(async move {
+                    match cmd.status().await {
+                        Ok(status) if !status.success() => {
+                            log::error!("Click action {program:?} exited with {status}")
+                        }
+                        Ok(_) => {}
+                        Err(err) => log::error!("Failed to run click action {program:?}: {err}"),
+                    }
+                });
+            }
+            Action::EmitSignal(args) => {
+                if self
+                    .req_tx
+                    .send(ModuleRequest::EmitSignal(args.clone()))
+                    .is_err()
+                {
+                    log::debug!("Signal was dropped because the controller is gone");
+                }
+            }
+        }
+    }
+}
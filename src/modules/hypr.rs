@@ -0,0 +1,193 @@
+//! Hyprland workspaces and the title of the focused window.
+
+use std::{borrow::Cow, collections::HashMap, sync::Arc};
+
+use anyhow::Context as _;
+use unicode_segmentation::UnicodeSegmentation as _;
+use unicode_width::UnicodeWidthStr as _;
+
+use crate::{
+    clients::hypr::HyprClient,
+    modules::{BarContent, Module, ModuleCx, ModuleExit, fresh_tag},
+    tui,
+    utils::ResultExt as _,
+};
+
+/// Shows a button for each workspace on the monitor it belongs to, which switches to the
+/// workspace when clicked. The title of the focused window follows the workspaces of the
+/// focused monitor.
+pub struct Hyprland;
+
+#[derive(Debug, Clone)]
+pub struct HyprConfig {
+    pub workspace_style: tui::TextStyle,
+    /// The style of workspaces that are shown on their monitor.
+    pub active_style: tui::TextStyle,
+    /// The style of workspaces with a window that requested attention.
+    pub urgent_style: tui::TextStyle,
+    /// Played when a workspace becomes active.
+    pub active_transition: Option<tui::Transition>,
+    /// The cells between workspaces.
+    pub spacing: u16,
+    pub show_title: bool,
+    /// Longer titles are truncated.
+    pub title_max_width: u16,
+    pub title_style: tui::TextStyle,
+    #[doc(hidden)]
+    #[deprecated = warn_non_exhaustive!()]
+    pub __non_exhaustive_struct_update: (),
+}
+impl Default for HyprConfig {
+    fn default() -> Self {
+        Self {
+            workspace_style: Default::default(),
+            active_style: tui::TextStyle {
+                fg: Some(tui::Color::token(tui::StyleContext::ACCENT)),
+                bold: true,
+                ..Default::default()
+            },
+            urgent_style: tui::TextStyle {
+                fg: Some(tui::Color::Palette(1)),
+                bold: true,
+                ..Default::default()
+            },
+            active_transition: Some(Default::default()),
+            spacing: 1,
+            show_title: true,
+            title_max_width: 60,
+            title_style: Default::default(),
+            #[expect(deprecated)]
+            __non_exhaustive_struct_update: (),
+        }
+    }
+}
+
+impl Module for Hyprland {
+    type Config = HyprConfig;
+
+    async fn run(config: HyprConfig, cx: ModuleCx) -> ModuleExit {
+        let HyprConfig {
+            workspace_style,
+            active_style,
+            urgent_style,
+            active_transition,
+            spacing,
+            show_title,
+            title_max_width,
+            title_style,
+            #[expect(deprecated)]
+                __non_exhaustive_struct_update: (),
+        } = config;
+
+        let Some(client) = HyprClient::connect(cx.subscribe_reload()).ok_or_log() else {
+            return ModuleExit::Failed;
+        };
+        let client = Arc::new(client);
+        let mut tags = HashMap::new();
+        cx.bind_bar(client.state(), |state| {
+            let mut by_monitor: HashMap<Arc<str>, Vec<tui::Elem>> = state
+                .monitors
+                .iter()
+                .map(|mon| (mon.name.clone(), Vec::new()))
+                .collect();
+
+            for ws in &state.workspaces {
+                let Some(items) = ws.monitor.as_ref().and_then(|it| by_monitor.get_mut(it)) else {
+                    continue;
+                };
+                let (tag, transition_key) = tags.entry(ws.name.clone()).or_insert_with(|| {
+                    let tag = fresh_tag();
+                    let client = client.clone();
+                    let name = ws.name.clone();
+                    cx.on_interact(tag.clone(), move |kind| {
+                        if *kind != tui::InteractKind::Click(tui::MouseButton::Left) {
+                            return;
+                        }
+                        let client = client.clone();
+                        let name = name.clone();
+                        tokio::spawn(async move {
+                            client
+                                .switch_workspace(&name)
+                                .await
+                                .with_context(|| format!("Failed to switch to workspace {name}"))
+                                .ok_or_log();
+                        });
+                    });
+                    (tag, fresh_tag())
+                });
+
+                let style = if ws.is_urgent {
+                    &urgent_style
+                } else if ws.is_active {
+                    &active_style
+                } else {
+                    &workspace_style
+                };
+                if !items.is_empty() {
+                    items.push(tui::Elem::spacing(tui::Axis::X, spacing));
+                }
+                let mut elem = tui::Elem::text(&ws.name, style.clone()).interactive_hover(
+                    tag.clone(),
+                    tui::Elem::text(
+                        &ws.name,
+                        tui::TextStyle {
+                            underline: true,
+                            ..style.clone()
+                        },
+                    ),
+                );
+                if ws.is_active
+                    && let Some(transition) = &active_transition
+                {
+                    elem = elem.with_transition(transition_key.clone(), transition.clone());
+                }
+                items.push(elem);
+            }
+
+            if show_title
+                && let Some(window) = &state.active_window
+                && !window.title.is_empty()
+                && let Some(focused) = state.monitors.iter().find(|it| it.is_focused)
+                && let Some(items) = by_monitor.get_mut(&focused.name)
+            {
+                items.push(tui::Elem::spacing(tui::Axis::X, 2));
+                items.push(tui::Elem::text(
+                    truncate(&window.title, title_max_width),
+                    title_style.clone(),
+                ));
+            }
+
+            BarContent::ByMonitor(
+                by_monitor
+                    .into_iter()
+                    .map(|(monitor, items)| {
+                        let elem = tui::Elem::stack(tui::Axis::X, items, tui::StackOpts::default());
+                        (monitor, elem)
+                    })
+                    .collect(),
+            )
+        })
+        .await;
+        ModuleExit::Failed
+    }
+}
+
+/// Cuts off `text` with an ellipsis if it is wider than `max_width`.
+fn truncate(text: &str, max_width: u16) -> Cow<'_, str> {
+    let max_width = usize::from(max_width);
+    if text.width() <= max_width {
+        return text.into();
+    }
+    let mut truncated = String::new();
+    let mut width = 0;
+    for grapheme in text.graphemes(true) {
+        width += grapheme.width();
+        // Leave room for the ellipsis
+        if width >= max_width {
+            break;
+        }
+        truncated.push_str(grapheme);
+    }
+    truncated.push('…');
+    truncated.into()
+}
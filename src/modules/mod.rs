@@ -0,0 +1,296 @@
+//! Ready-made bar modules.
+//!
+//! A module is a task that renders one part of the bar and reacts to interactions with it. It
+//! is not tied to a particular controller: it only talks to its [`ModuleCx`], and the
+//! controller forwards the resulting [`ModuleRequest`]s to wherever it keeps its menus and
+//! callbacks.
+
+pub mod actions;
+pub mod hypr;
+pub mod pulse;
+pub mod sysinfo;
+pub mod time;
+pub mod timer;
+pub mod weather;
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use tokio::sync::{mpsc, oneshot, watch};
+
+use crate::{host, tui, utils::ResultExt as _};
+
+/// A bar module, configured by [`Module::Config`].
+pub trait Module: Send + 'static {
+    type Config: Clone + Send + Sync + 'static;
+
+    /// Runs the module until it is cancelled. Should only return if it cannot continue, see
+    /// [`ModuleExit`].
+    fn run(config: Self::Config, cx: ModuleCx) -> impl Future<Output = ModuleExit> + Send;
+}
+
+/// Why [`Module::run`] returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ModuleExit {
+    /// The module ran into an unrecoverable error, e.g. a lost connection. A controller may
+    /// restart it with the same config.
+    Failed,
+    /// The module has nothing to do with its config, e.g. the weather module without a
+    /// location. Restarting it would not change that, so a controller should not.
+    Done,
+}
+
+/// Creates an interact tag that is distinct from all other tags created by this function.
+///
+/// Controllers that run modules should create their own tags with this as well.
+pub fn fresh_tag() -> tui::CustomId {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    static TAG_COUNTER: AtomicU64 = AtomicU64::new(0);
+    tui::CustomId::from_bytes(&TAG_COUNTER.fetch_add(1, Ordering::Relaxed).to_be_bytes())
+}
+
+/// Runs `f` until it succeeds, logging its errors.
+///
+/// The delay before the next attempt starts at `min_delay` and doubles after every failure, up
+/// to `max_delay`. A change of `reload_rx` retries right away.
+pub async fn run_or_retry<T, F: Future<Output = anyhow::Result<T>>>(
+    mut f: impl FnMut() -> F,
+    min_delay: Duration,
+    max_delay: Duration,
+    reload_rx: &mut watch::Receiver<()>,
+) -> T {
+    let mut delay = min_delay;
+    loop {
+        let res = f().await.map_err(|err| {
+            err.context(format!(
+                "Failed to run task. Retrying in {}s",
+                delay.as_secs()
+            ))
+        });
+        if let Some(val) = res.ok_or_log() {
+            return val;
+        }
+        tokio::select! {
+            () = tokio::time::sleep(delay) => delay = (delay * 2).min(max_delay),
+            Ok(()) = reload_rx.changed() => delay = min_delay,
+        }
+    }
+}
+
+/// Keeps an element up to date with the value of `rx`, e.g. for the content of a menu.
+///
+/// `render` is called with the current value and then on a background task with every new one.
+/// The task stops once the sender of `rx` or all receivers of the element are dropped.
+pub fn bind<T: Clone + Send + Sync + 'static>(
+    mut rx: watch::Receiver<T>,
+    mut render: impl FnMut(&T) -> tui::Elem + Send + 'static,
+) -> watch::Receiver<tui::Elem> {
+    let (elem_tx, elem_rx) = watch::channel(render(&rx.borrow_and_update().clone()));
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                res = rx.changed() => {
+                    if res.is_err() {
+                        break;
+                    }
+                }
+                () = elem_tx.closed() => break,
+            }
+            let val = rx.borrow_and_update().clone();
+            elem_tx.send_replace(render(&val));
+        }
+    });
+    elem_rx
+}
+
+pub type InteractCallback = Arc<dyn Fn(&tui::InteractKind) + Send + Sync + 'static>;
+pub type CommandCallback = Arc<dyn Fn(&[String]) + Send + Sync + 'static>;
+
+/// The part of the bar that belongs to a module.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum BarContent {
+    /// The same content on every monitor.
+    Shared(tui::Elem),
+    /// Different content for each monitor, by monitor name. Empty on missing monitors.
+    ByMonitor(HashMap<Arc<str>, tui::Elem>),
+}
+impl Default for BarContent {
+    fn default() -> Self {
+        Self::Shared(tui::Elem::empty())
+    }
+}
+
+/// The kind of menu opened by [`RegisterMenu`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MenuKind {
+    /// Closed when the mouse leaves the anchor or interacts with anything else.
+    Tooltip,
+    /// Stays open until the mouse leaves it or another menu is opened.
+    Context,
+}
+
+/// Opens a menu with the content of `tui_rx` when the element tagged with `on_tag` receives an
+/// interaction of kind `on_kind`.
+#[derive(Debug, Clone)]
+pub struct RegisterMenu {
+    pub on_tag: tui::CustomId,
+    pub on_kind: tui::InteractKind,
+    pub tui_rx: watch::Receiver<tui::Elem>,
+    pub kind: MenuKind,
+}
+
+/// A request of a module to its controller, see [`ModuleCx::new`].
+#[non_exhaustive]
+pub enum ModuleRequest {
+    RegisterMenu(RegisterMenu),
+    /// Call the callback whenever an element with the tag receives an interaction, both on
+    /// the bar and in menus.
+    OnInteract(tui::CustomId, InteractCallback),
+    /// Call the callback with the remaining arguments whenever the controller receives
+    /// [`crate::host::HostEvent::Command`] with the name as the first argument, e.g. from
+    /// `scratchbar msg send <name> <args...>`.
+    OnCommand(Arc<str>, CommandCallback),
+    /// Call the callback registered with [`ModuleRequest::OnCommand`] for the first argument
+    /// with the remaining ones, see [`actions::Action::EmitSignal`].
+    EmitSignal(Vec<String>),
+    /// Run the program and its arguments with [`host::HostUpdate::RunCommand`] and send the
+    /// output back. The controller chooses the id of the command.
+    RunCommand(
+        Vec<String>,
+        host::RunCommandOpts,
+        oneshot::Sender<host::CommandOutput>,
+    ),
+}
+impl std::fmt::Debug for ModuleRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::RegisterMenu(menu) => f.debug_tuple("RegisterMenu").field(menu).finish(),
+            Self::OnInteract(tag, _) => f.debug_tuple("OnInteract").field(tag).finish(),
+            Self::OnCommand(name, _) => f.debug_tuple("OnCommand").field(name).finish(),
+            Self::EmitSignal(args) => f.debug_tuple("EmitSignal").field(args).finish(),
+            Self::RunCommand(cmd, opts, _) => {
+                f.debug_tuple("RunCommand").field(cmd).field(opts).finish()
+            }
+        }
+    }
+}
+
+/// The connection of a running module to its controller.
+#[derive(Debug)]
+pub struct ModuleCx {
+    bar_tx: watch::Sender<BarContent>,
+    reload_rx: watch::Receiver<()>,
+    req_tx: mpsc::UnboundedSender<ModuleRequest>,
+}
+impl ModuleCx {
+    /// Creates the context for one run of a module.
+    ///
+    /// The module's part of the bar is sent to `bar_tx`. A change of `reload_rx` asks the
+    /// module to re-render and refresh its data. The controller has to handle the requests
+    /// from the returned receiver for menus and interactions to work.
+    pub fn new(
+        bar_tx: watch::Sender<BarContent>,
+        reload_rx: watch::Receiver<()>,
+    ) -> (Self, mpsc::UnboundedReceiver<ModuleRequest>) {
+        let (req_tx, req_rx) = mpsc::unbounded_channel();
+        (
+            Self {
+                bar_tx,
+                reload_rx,
+                req_tx,
+            },
+            req_rx,
+        )
+    }
+
+    pub fn set_bar(&self, elem: tui::Elem) {
+        self.bar_tx.send_replace(BarContent::Shared(elem));
+    }
+
+    pub fn set_bar_by_monitor(&self, elems: HashMap<Arc<str>, tui::Elem>) {
+        self.bar_tx.send_replace(BarContent::ByMonitor(elems));
+    }
+
+    /// Waits until a reload is requested. Returns `None` if the controller can no longer
+    /// request reloads.
+    pub async fn reloaded(&mut self) -> Option<()> {
+        self.reload_rx.changed().await.ok()
+    }
+
+    /// Renders the module's part of the bar from the current value of `rx`, then from every new
+    /// one and on reloads. Returns once the sender of `rx` is dropped.
+    ///
+    /// Unlike [`bind`], `render` runs on the module's task, so it can use the context.
+    pub async fn bind_bar<T: Clone>(
+        &self,
+        mut rx: watch::Receiver<T>,
+        mut render: impl FnMut(&T) -> BarContent,
+    ) {
+        let mut reload_rx = self.reload_rx.clone();
+        loop {
+            let val = rx.borrow_and_update().clone();
+            self.bar_tx.send_replace(render(&val));
+            tokio::select! {
+                res = rx.changed() => {
+                    if res.is_err() {
+                        break;
+                    }
+                }
+                Ok(()) = reload_rx.changed() => {}
+            }
+        }
+    }
+
+    /// A receiver that changes whenever a reload is requested, e.g. for [`crate::clients`].
+    pub fn subscribe_reload(&self) -> watch::Receiver<()> {
+        self.reload_rx.clone()
+    }
+
+    pub fn register_menu(&self, menu: RegisterMenu) {
+        self.request(ModuleRequest::RegisterMenu(menu));
+    }
+
+    pub fn on_interact(
+        &self,
+        tag: tui::CustomId,
+        callback: impl Fn(&tui::InteractKind) + Send + Sync + 'static,
+    ) {
+        self.request(ModuleRequest::OnInteract(tag, Arc::new(callback)));
+    }
+
+    pub fn on_command(
+        &self,
+        name: impl Into<Arc<str>>,
+        callback: impl Fn(&[String]) + Send + Sync + 'static,
+    ) {
+        self.request(ModuleRequest::OnCommand(name.into(), Arc::new(callback)));
+    }
+
+    /// Runs a helper command in the host, see [`host::HostUpdate::RunCommand`]. Returns `None`
+    /// if the controller does not run commands for modules.
+    pub async fn run_command(
+        &self,
+        cmd: Vec<String>,
+        opts: host::RunCommandOpts,
+    ) -> Option<host::CommandOutput> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.request(ModuleRequest::RunCommand(cmd, opts, reply_tx));
+        reply_rx.await.ok()
+    }
+
+    /// Runs the configured [`actions::Action`]s of the module from its callbacks.
+    pub fn action_runner(&self) -> actions::ActionRunner {
+        actions::ActionRunner {
+            req_tx: self.req_tx.clone(),
+        }
+    }
+
+    fn request(&self, req: ModuleRequest) {
+        if self.req_tx.send(req).is_err() {
+            log::debug!("Module request was dropped because the controller is gone");
+        }
+    }
+}
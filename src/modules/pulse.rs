@@ -0,0 +1,233 @@
+//! The volume of the default audio output or input.
+
+use std::{collections::HashMap, sync::Arc};
+
+use tokio::sync::watch;
+
+pub use crate::clients::pulse::PulseDeviceKind;
+use crate::{
+    clients::pulse::{PulseClient, PulseState},
+    modules::{BarContent, MenuKind, Module, ModuleCx, ModuleExit, RegisterMenu, fresh_tag},
+    tui,
+    utils::ResultExt as _,
+};
+
+/// Shows the volume of the default sink or source.
+///
+/// Scrolling changes the volume, a left click toggles mute and a middle click resets the volume
+/// to 100%. A right click opens a menu to pick the default device and set its volume.
+pub struct Pulse;
+
+#[derive(Debug, Clone)]
+pub struct PulseConfig {
+    pub device: PulseDeviceKind,
+    /// Shown in front of the volume while the device is not muted.
+    pub unmuted_sym: tui::Elem,
+    /// Shown in front of the volume while the device is muted.
+    pub muted_sym: tui::Elem,
+    pub style: tui::TextStyle,
+    /// The style of the volume while the device is muted.
+    pub muted_style: tui::TextStyle,
+    /// The change in percentage points per scroll step.
+    pub scroll_step: u8,
+    /// The style of the menu that lists the devices.
+    pub menu_style: tui::menu::MenuStyle,
+    #[doc(hidden)]
+    #[deprecated = warn_non_exhaustive!()]
+    pub __non_exhaustive_struct_update: (),
+}
+impl Default for PulseConfig {
+    fn default() -> Self {
+        Self {
+            device: PulseDeviceKind::Sink,
+            unmuted_sym: tui::Elem::empty(),
+            muted_sym: tui::Elem::empty(),
+            style: Default::default(),
+            muted_style: tui::TextStyle {
+                fg: Some(tui::Color::Palette(8)),
+                ..Default::default()
+            },
+            scroll_step: 2,
+            menu_style: Default::default(),
+            #[expect(deprecated)]
+            __non_exhaustive_struct_update: (),
+        }
+    }
+}
+
+impl Module for Pulse {
+    type Config = PulseConfig;
+
+    async fn run(config: PulseConfig, cx: ModuleCx) -> ModuleExit {
+        let PulseConfig {
+            device,
+            unmuted_sym,
+            muted_sym,
+            style,
+            muted_style,
+            scroll_step,
+            menu_style,
+            #[expect(deprecated)]
+                __non_exhaustive_struct_update: (),
+        } = config;
+
+        let client = Arc::new(PulseClient::connect(cx.subscribe_reload()));
+
+        let bar_tag = fresh_tag();
+        {
+            let client = client.clone();
+            let step = i32::from(scroll_step);
+            cx.on_interact(bar_tag.clone(), move |kind| {
+                let client = client.clone();
+                match kind {
+                    tui::InteractKind::Scroll(dir) => {
+                        let delta = match dir {
+                            tui::Direction::Up | tui::Direction::Right => step,
+                            tui::Direction::Down | tui::Direction::Left => -step,
+                        };
+                        tokio::spawn(async move {
+                            client.change_volume(device, delta).await.ok_or_log();
+                        });
+                    }
+                    tui::InteractKind::Click(tui::MouseButton::Left) => {
+                        tokio::spawn(async move {
+                            client.toggle_mute(device).await.ok_or_log();
+                        });
+                    }
+                    tui::InteractKind::Click(tui::MouseButton::Middle) => {
+                        tokio::spawn(async move {
+                            client.set_volume(device, 1.0).await.ok_or_log();
+                        });
+                    }
+                    _ => {}
+                }
+            });
+        }
+
+        let slider_tag = fresh_tag();
+        {
+            let client = client.clone();
+            cx.on_interact(slider_tag.clone(), move |kind| {
+                let &tui::InteractKind::ValueChange(volume) = kind else {
+                    return;
+                };
+                let client = client.clone();
+                tokio::spawn(async move {
+                    client
+                        .set_volume(device, f64::from(volume))
+                        .await
+                        .ok_or_log();
+                });
+            });
+        }
+
+        let menu_tx = watch::Sender::new(tui::Elem::empty());
+        cx.register_menu(RegisterMenu {
+            on_tag: bar_tag.clone(),
+            on_kind: tui::InteractKind::Click(tui::MouseButton::Right),
+            tui_rx: menu_tx.subscribe(),
+            kind: MenuKind::Context,
+        });
+
+        let mut device_tags = HashMap::new();
+        cx.bind_bar(client.state(), |state| {
+            let mut device_tag = |name: &Arc<str>| {
+                device_tags
+                    .entry(name.clone())
+                    .or_insert_with(|| {
+                        let tag = fresh_tag();
+                        let client = client.clone();
+                        let name = name.clone();
+                        cx.on_interact(tag.clone(), move |kind| {
+                            if *kind != tui::InteractKind::Click(tui::MouseButton::Left) {
+                                return;
+                            }
+                            let client = client.clone();
+                            let name = name.clone();
+                            tokio::spawn(async move {
+                                client.set_default(device, &name).await.ok_or_log();
+                            });
+                        });
+                        tag
+                    })
+                    .clone()
+            };
+            menu_tx.send_replace(device_menu(
+                state,
+                device,
+                &menu_style,
+                &mut device_tag,
+                &slider_tag,
+            ));
+
+            BarContent::Shared(match state.default_device(device) {
+                Some(dev) => {
+                    let (sym, style) = if dev.muted {
+                        (&muted_sym, &muted_style)
+                    } else {
+                        (&unmuted_sym, &style)
+                    };
+                    tui::Elem::stack(
+                        tui::Axis::X,
+                        [
+                            sym.clone(),
+                            tui::Elem::text(
+                                format_args!("{:>3}%", (dev.volume * 100.0).round() as u32),
+                                style.clone(),
+                            ),
+                        ],
+                        tui::StackOpts::default(),
+                    )
+                    .interactive(bar_tag.clone())
+                }
+                None => tui::Elem::empty(),
+            })
+        })
+        .await;
+        ModuleExit::Failed
+    }
+}
+
+/// Lists the devices of the given kind, checking the default one, followed by a slider for the
+/// volume of the default device.
+fn device_menu(
+    state: &PulseState,
+    kind: PulseDeviceKind,
+    style: &tui::menu::MenuStyle,
+    device_tag: &mut impl FnMut(&Arc<str>) -> tui::CustomId,
+    slider_tag: &tui::CustomId,
+) -> tui::Elem {
+    let title = match kind {
+        PulseDeviceKind::Sink => "Output",
+        PulseDeviceKind::Source => "Input",
+    };
+    let default = state.default_name(kind);
+    let menu = tui::menu::Menu {
+        title: Some(title.into()),
+        items: state
+            .devices(kind)
+            .iter()
+            .map(|dev| {
+                tui::menu::MenuEntry {
+                    checked: Some(default == Some(&dev.name)),
+                    ..tui::menu::MenuEntry::new(dev.description.clone(), device_tag(&dev.name))
+                }
+                .into()
+            })
+            .collect(),
+        style: style.clone(),
+        ..Default::default()
+    }
+    .build();
+    let Some(dev) = state.default_device(kind) else {
+        return menu;
+    };
+    tui::Elem::stack(
+        tui::Axis::Y,
+        [
+            menu,
+            tui::Elem::slider(dev.volume as f32, 0.0..=1.0, slider_tag.clone()),
+        ],
+        tui::StackOpts::default(),
+    )
+}
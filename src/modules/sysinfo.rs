@@ -0,0 +1,303 @@
+//! CPU usage, memory usage and load average, read from `/proc`.
+
+use std::{collections::VecDeque, time::Duration};
+
+use anyhow::Context as _;
+use tokio::sync::watch;
+
+use crate::{
+    modules::{MenuKind, Module, ModuleCx, ModuleExit, RegisterMenu, fresh_tag},
+    tui,
+    utils::ResultExt as _,
+};
+
+/// Shows the total CPU usage, the memory usage and the load average on the bar. Hovering it
+/// shows the recent usage of each core.
+pub struct SysInfo;
+
+#[derive(Debug, Clone)]
+pub struct SysInfoConfig {
+    /// How often the values are read.
+    pub interval: Duration,
+    /// Shown in front of the CPU usage. The segment is left out if `None`.
+    pub cpu: Option<tui::Elem>,
+    /// Shown in front of the memory usage. The segment is left out if `None`.
+    pub ram: Option<tui::Elem>,
+    /// Shown in front of the load average of the last minute. The segment is left out if
+    /// `None`.
+    pub load: Option<tui::Elem>,
+    pub style: tui::TextStyle,
+    /// The cells between segments.
+    pub spacing: u16,
+    /// The number of values kept for the graphs in the tooltip.
+    pub history: u16,
+    pub graph_style: tui::TextStyle,
+    #[doc(hidden)]
+    #[deprecated = warn_non_exhaustive!()]
+    pub __non_exhaustive_struct_update: (),
+}
+impl Default for SysInfoConfig {
+    fn default() -> Self {
+        let label = |text: &str| Some(tui::Elem::text(text, Default::default()));
+        Self {
+            interval: Duration::from_secs(2),
+            cpu: label("CPU "),
+            ram: label("RAM "),
+            load: label("LOAD "),
+            style: Default::default(),
+            spacing: 2,
+            history: 20,
+            graph_style: tui::TextStyle {
+                fg: Some(tui::Color::token(tui::StyleContext::ACCENT)),
+                ..Default::default()
+            },
+            #[expect(deprecated)]
+            __non_exhaustive_struct_update: (),
+        }
+    }
+}
+
+impl Module for SysInfo {
+    type Config = SysInfoConfig;
+
+    async fn run(config: SysInfoConfig, mut cx: ModuleCx) -> ModuleExit {
+        let SysInfoConfig {
+            interval,
+            cpu,
+            ram,
+            load,
+            style,
+            spacing,
+            history,
+            graph_style,
+            #[expect(deprecated)]
+                __non_exhaustive_struct_update: (),
+        } = config;
+
+        let bar_tag = fresh_tag();
+        let tooltip_tx = watch::Sender::new(tui::Elem::empty());
+        cx.register_menu(RegisterMenu {
+            on_tag: bar_tag.clone(),
+            on_kind: tui::InteractKind::Hover,
+            tui_rx: tooltip_tx.subscribe(),
+            kind: MenuKind::Tooltip,
+        });
+
+        let mut ticks = tokio::time::interval(interval.max(Duration::from_millis(100)));
+        ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+        let mut last_times: Option<Vec<CpuTimes>> = None;
+        let mut core_history: Vec<VecDeque<f64>> = Vec::new();
+        loop {
+            tokio::select! {
+                _ = ticks.tick() => {}
+                Some(()) = cx.reloaded() => {}
+            }
+            let Some(sample) = read_sample().await.ok_or_log() else {
+                continue;
+            };
+
+            // The first value of CPU usage needs a previous sample
+            let usage = last_times
+                .replace(sample.cpu_times.clone())
+                .map(|last| {
+                    sample
+                        .cpu_times
+                        .iter()
+                        .zip(last)
+                        .map(|(cur, last)| cur.usage_since(&last))
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default();
+            // The first entry is the total of all cores
+            let (total, cores) = usage.split_first().unzip();
+            let cores = cores.unwrap_or_default();
+
+            core_history.resize_with(cores.len(), VecDeque::new);
+            for (hist, &usage) in core_history.iter_mut().zip(cores) {
+                hist.push_back(usage);
+                while hist.len() > usize::from(history) {
+                    hist.pop_front();
+                }
+            }
+
+            let percent = |frac: f64| format!("{:>3.0}%", frac * 100.0);
+            let mut segments = Vec::new();
+            if let Some(cpu) = &cpu {
+                segments.push((cpu, total.map(|&it| percent(it)).unwrap_or_default()));
+            }
+            if let Some(ram) = &ram {
+                segments.push((ram, percent(sample.mem.used_frac())));
+            }
+            if let Some(load) = &load {
+                segments.push((load, format!("{:.2}", sample.load[0])));
+            }
+            let mut items = Vec::new();
+            for (label, value) in segments {
+                if !items.is_empty() {
+                    items.push(tui::Elem::spacing(tui::Axis::X, spacing));
+                }
+                items.push(label.clone());
+                items.push(tui::Elem::text(value, style.clone()));
+            }
+            cx.set_bar(
+                tui::Elem::stack(tui::Axis::X, items, tui::StackOpts::default())
+                    .interactive(bar_tag.clone()),
+            );
+
+            tooltip_tx.send_replace(tooltip(&sample, &core_history, &graph_style));
+        }
+    }
+}
+
+fn tooltip(
+    sample: &Sample,
+    core_history: &[VecDeque<f64>],
+    graph_style: &tui::TextStyle,
+) -> tui::Elem {
+    const GIB: f64 = (1 << 20) as f64;
+    let mut rows = vec![
+        tui::Elem::text(
+            format_args!(
+                "Memory {:.1} / {:.1} GiB",
+                sample.mem.used_kib() as f64 / GIB,
+                sample.mem.total_kib as f64 / GIB,
+            ),
+            Default::default(),
+        ),
+        tui::Elem::text(
+            format_args!(
+                "Load   {:.2} {:.2} {:.2}",
+                sample.load[0], sample.load[1], sample.load[2],
+            ),
+            Default::default(),
+        ),
+    ];
+    for (i, hist) in core_history.iter().enumerate() {
+        rows.push(tui::Elem::stack(
+            tui::Axis::X,
+            [
+                tui::Elem::text(format_args!("CPU{i:<3} "), Default::default()),
+                tui::Elem::text(sparkline(hist), graph_style.clone()),
+                tui::Elem::text(
+                    format_args!(
+                        " {:>3.0}%",
+                        hist.back().copied().unwrap_or_default() * 100.0
+                    ),
+                    Default::default(),
+                ),
+            ],
+            tui::StackOpts::default(),
+        ));
+    }
+    tui::Elem::stack(tui::Axis::Y, rows, tui::StackOpts::default())
+}
+
+/// Draws values between `0.0` and `1.0` as a line of block characters.
+fn sparkline(values: &VecDeque<f64>) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    values
+        .iter()
+        .map(|&it| BARS[((it.clamp(0.0, 1.0) * 7.0).round() as usize).min(7)])
+        .collect()
+}
+
+struct Sample {
+    /// The total of all cores, followed by each core.
+    cpu_times: Vec<CpuTimes>,
+    mem: MemInfo,
+    /// The load average of the last 1, 5 and 15 minutes.
+    load: [f64; 3],
+}
+
+async fn read_sample() -> anyhow::Result<Sample> {
+    let read = |path: &'static str| async move {
+        tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| format!("Failed to read {path}"))
+    };
+    let (stat, meminfo, loadavg) = tokio::try_join!(
+        read("/proc/stat"),
+        read("/proc/meminfo"),
+        read("/proc/loadavg"),
+    )?;
+    Ok(Sample {
+        cpu_times: parse_stat(&stat),
+        mem: parse_meminfo(&meminfo).context("Failed to parse /proc/meminfo")?,
+        load: parse_loadavg(&loadavg).context("Failed to parse /proc/loadavg")?,
+    })
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct CpuTimes {
+    busy: u64,
+    total: u64,
+}
+impl CpuTimes {
+    fn usage_since(&self, last: &Self) -> f64 {
+        let total = self.total.saturating_sub(last.total);
+        if total == 0 {
+            return 0.0;
+        }
+        self.busy.saturating_sub(last.busy) as f64 / total as f64
+    }
+}
+
+/// Reads the `cpu` and `cpuN` lines of `/proc/stat`.
+fn parse_stat(stat: &str) -> Vec<CpuTimes> {
+    stat.lines()
+        .filter(|line| line.starts_with("cpu"))
+        .map(|line| {
+            // user nice system idle iowait irq softirq steal, followed by guest times that are
+            // already included in user and nice
+            let times: Vec<u64> = line
+                .split_whitespace()
+                .skip(1)
+                .take(8)
+                .map(|it| it.parse().unwrap_or(0))
+                .collect();
+            let total = times.iter().sum();
+            let idle = times.get(3).copied().unwrap_or(0) + times.get(4).copied().unwrap_or(0);
+            CpuTimes {
+                busy: total - idle,
+                total,
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy)]
+struct MemInfo {
+    total_kib: u64,
+    available_kib: u64,
+}
+impl MemInfo {
+    fn used_kib(&self) -> u64 {
+        self.total_kib.saturating_sub(self.available_kib)
+    }
+
+    fn used_frac(&self) -> f64 {
+        if self.total_kib == 0 {
+            return 0.0;
+        }
+        self.used_kib() as f64 / self.total_kib as f64
+    }
+}
+
+fn parse_meminfo(meminfo: &str) -> Option<MemInfo> {
+    let field = |name: &str| {
+        meminfo.lines().find_map(|line| {
+            let value = line.strip_prefix(name)?.strip_prefix(':')?;
+            value.trim().trim_end_matches("kB").trim().parse().ok()
+        })
+    };
+    Some(MemInfo {
+        total_kib: field("MemTotal")?,
+        available_kib: field("MemAvailable")?,
+    })
+}
+
+fn parse_loadavg(loadavg: &str) -> Option<[f64; 3]> {
+    let mut values = loadavg.split_whitespace().map(|it| it.parse().ok());
+    Some([values.next()??, values.next()??, values.next()??])
+}
@@ -0,0 +1,291 @@
+//! A clock with a calendar tooltip.
+
+use std::{sync::Arc, time::Duration};
+
+use chrono::{Datelike as _, Timelike as _};
+use tokio::sync::watch;
+
+pub use chrono::Weekday;
+
+use crate::{
+    modules::{
+        MenuKind, Module, ModuleCx, ModuleExit, RegisterMenu,
+        actions::{Action, ClickActions},
+        fresh_tag,
+    },
+    tui,
+};
+
+/// Shows the local time on the bar and the calendar of the current month when hovered.
+///
+/// Scrolling over the clock switches the month shown by the calendar. By default, the calendar
+/// is also opened as a context menu by a right click, where it can be scrolled as well and a
+/// left click goes back to the current month.
+pub struct Clock;
+
+#[derive(Debug, Clone)]
+pub struct ClockConfig {
+    /// The format of the time on the bar, see [`chrono::format::strftime`].
+    /// Defaults to `%H:%M %d/%m`.
+    pub format: Arc<str>,
+    pub first_weekday: FirstWeekday,
+    /// How often the time on the bar changes. Should match the precision of [`Self::format`].
+    pub granularity: Granularity,
+    pub style: tui::TextStyle,
+    /// Defaults to opening the calendar on a right click.
+    pub actions: ClickActions,
+    #[doc(hidden)]
+    #[deprecated = warn_non_exhaustive!()]
+    pub __non_exhaustive_struct_update: (),
+}
+impl Default for ClockConfig {
+    fn default() -> Self {
+        Self {
+            format: "%H:%M %d/%m".into(),
+            first_weekday: Default::default(),
+            granularity: Default::default(),
+            style: Default::default(),
+            actions: ClickActions {
+                right: Action::OpenMenu,
+                ..Default::default()
+            },
+            #[expect(deprecated)]
+            __non_exhaustive_struct_update: (),
+        }
+    }
+}
+
+/// The first column of the calendar.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum FirstWeekday {
+    /// The first day of the week in the region of the `LC_TIME` locale, or Monday if it is
+    /// unset or has no region.
+    #[default]
+    Locale,
+    Fixed(Weekday),
+}
+impl FirstWeekday {
+    fn resolve(self) -> Weekday {
+        match self {
+            Self::Locale => locale_first_weekday(),
+            Self::Fixed(day) => day,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Granularity {
+    Second,
+    #[default]
+    Minute,
+}
+
+impl Module for Clock {
+    type Config = ClockConfig;
+
+    async fn run(config: ClockConfig, mut cx: ModuleCx) -> ModuleExit {
+        let ClockConfig {
+            format,
+            first_weekday,
+            granularity,
+            style,
+            actions,
+            #[expect(deprecated)]
+                __non_exhaustive_struct_update: (),
+        } = config;
+
+        let format = if has_valid_format(&format) {
+            format
+        } else {
+            log::error!("Invalid clock format {format:?}, using the default");
+            ClockConfig::default().format
+        };
+
+        let bar_tag = fresh_tag();
+        let cal_tag = fresh_tag();
+
+        let month_tx = watch::Sender::new(first_of_month(chrono::Local::now().date_naive()));
+        for tag in [&bar_tag, &cal_tag] {
+            let month_tx = month_tx.clone();
+            let is_cal = *tag == cal_tag;
+            let actions = actions.clone();
+            let runner = cx.action_runner();
+            cx.on_interact(tag.clone(), move |kind| match kind {
+                tui::InteractKind::Scroll(dir) => {
+                    let months = chrono::Months::new(1);
+                    month_tx.send_if_modified(|month| {
+                        let new = match dir {
+                            tui::Direction::Up | tui::Direction::Left => {
+                                month.checked_sub_months(months)
+                            }
+                            tui::Direction::Down | tui::Direction::Right => {
+                                month.checked_add_months(months)
+                            }
+                        };
+                        new.map(|new| *month = new).is_some()
+                    });
+                }
+                tui::InteractKind::Click(tui::MouseButton::Left) if is_cal => {
+                    month_tx.send_replace(first_of_month(chrono::Local::now().date_naive()));
+                }
+                kind if !is_cal => {
+                    runner.on_interact(&actions, kind);
+                }
+                _ => {}
+            });
+        }
+
+        let cal_tx = watch::Sender::new(tui::Elem::empty());
+        cx.register_menu(RegisterMenu {
+            on_tag: bar_tag.clone(),
+            on_kind: tui::InteractKind::Hover,
+            tui_rx: cal_tx.subscribe(),
+            kind: MenuKind::Tooltip,
+        });
+        actions.register_menus(&cx, &bar_tag, &cal_tx.subscribe());
+
+        let mut month_rx = month_tx.subscribe();
+        let mut last_today = None;
+        loop {
+            let now = chrono::Local::now();
+            cx.set_bar(
+                tui::Elem::text(now.format(&format), style.clone()).interactive(bar_tag.clone()),
+            );
+
+            let today = now.date_naive();
+            if last_today.is_some_and(|it| it != today) {
+                // Follow the current month once the day changes
+                month_tx.send_replace(first_of_month(today));
+            }
+            last_today = Some(today);
+
+            let month = *month_rx.borrow_and_update();
+            cal_tx.send_replace(
+                calendar(month, today, first_weekday.resolve()).interactive(cal_tag.clone()),
+            );
+
+            tokio::select! {
+                () = tokio::time::sleep(until_next_tick(now.time(), granularity)) => {}
+                Ok(()) = month_rx.changed() => {}
+                Some(()) = cx.reloaded() => {}
+            }
+        }
+    }
+}
+
+fn has_valid_format(format: &str) -> bool {
+    !chrono::format::StrftimeItems::new(format).any(|it| matches!(it, chrono::format::Item::Error))
+}
+
+fn first_of_month(date: chrono::NaiveDate) -> chrono::NaiveDate {
+    date.with_day(1).unwrap_or(date)
+}
+
+fn until_next_tick(time: chrono::NaiveTime, granularity: Granularity) -> Duration {
+    let secs = match granularity {
+        Granularity::Second => 1,
+        Granularity::Minute => 60 - u64::from(time.second()),
+    };
+    // The nanoseconds exceed a second during leap seconds
+    let nanos = u64::from(time.nanosecond().min(999_999_999));
+    // Wake up slightly late so that the new time has definitely been reached
+    Duration::from_secs(secs) - Duration::from_nanos(nanos) + Duration::from_millis(5)
+}
+
+fn calendar(month: chrono::NaiveDate, today: chrono::NaiveDate, first: Weekday) -> tui::Elem {
+    let day_style = tui::TextStyle::default();
+    let today_style = tui::TextStyle {
+        fg: Some(tui::Color::token(tui::StyleContext::ACCENT)),
+        bold: true,
+        ..Default::default()
+    };
+
+    let mut rows = vec![
+        tui::Elem::aligned(
+            tui::Elem::text(
+                month.format("%B %Y"),
+                tui::TextStyle {
+                    bold: true,
+                    ..Default::default()
+                },
+            ),
+            tui::Align2D::CENTER,
+        ),
+        tui::Elem::text(
+            std::iter::successors(Some(first), |day| Some(day.succ()))
+                .take(7)
+                .map(|day| day.to_string()[..2].to_owned())
+                .collect::<Vec<_>>()
+                .join(" "),
+            day_style.clone(),
+        ),
+    ];
+
+    // Every day takes up three cells, including the space after it
+    let offset = month.weekday().days_since(first);
+    let mut week = vec![tui::Elem::spacing(tui::Axis::X, 3 * offset as u16)];
+    for day in month
+        .iter_days()
+        .take_while(|day| day.month() == month.month())
+    {
+        let style = if day == today {
+            &today_style
+        } else {
+            &day_style
+        };
+        week.push(tui::Elem::text(
+            format_args!("{:>2}", day.day()),
+            style.clone(),
+        ));
+        week.push(tui::Elem::spacing(tui::Axis::X, 1));
+        if day.weekday().succ() == first {
+            rows.push(tui::Elem::stack(
+                tui::Axis::X,
+                std::mem::take(&mut week),
+                tui::StackOpts::default(),
+            ));
+        }
+    }
+    if !week.is_empty() {
+        rows.push(tui::Elem::stack(
+            tui::Axis::X,
+            week,
+            tui::StackOpts::default(),
+        ));
+    }
+
+    tui::Elem::stack(tui::Axis::Y, rows, tui::StackOpts::default())
+}
+
+/// Territories whose weeks start on Sunday or Saturday, according to CLDR. All others start on
+/// Monday.
+const SUNDAY_FIRST: &[&str] = &[
+    "AG", "AS", "BD", "BR", "BS", "BT", "BW", "BZ", "CA", "CN", "CO", "DM", "DO", "ET", "GT", "GU",
+    "HK", "HN", "ID", "IL", "IN", "JM", "JP", "KE", "KH", "KR", "LA", "MH", "MM", "MO", "MT", "MX",
+    "MZ", "NI", "NP", "PA", "PE", "PH", "PK", "PR", "PT", "PY", "SA", "SG", "SV", "TH", "TT", "TW",
+    "UM", "US", "VE", "VI", "WS", "YE", "ZA", "ZW",
+];
+const SATURDAY_FIRST: &[&str] = &[
+    "AE", "AF", "BH", "DJ", "DZ", "EG", "IQ", "IR", "JO", "KW", "LY", "OM", "QA", "SD", "SY",
+];
+
+fn locale_first_weekday() -> Weekday {
+    let locale = ["LC_ALL", "LC_TIME", "LANG"]
+        .into_iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|it| !it.is_empty())
+        .unwrap_or_default();
+    // language[_territory][.codeset][@modifier]
+    let territory = locale
+        .split(['.', '@'])
+        .next()
+        .and_then(|it| it.split_once('_'))
+        .map(|(_, territory)| territory);
+    match territory {
+        Some(it) if SUNDAY_FIRST.contains(&it) => Weekday::Sun,
+        Some(it) if SATURDAY_FIRST.contains(&it) => Weekday::Sat,
+        _ => Weekday::Mon,
+    }
+}
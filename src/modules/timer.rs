@@ -0,0 +1,525 @@
+//! A countdown timer that can also run pomodoros.
+
+use std::{
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Context as _;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::{
+    modules::{Module, ModuleCx, ModuleExit, fresh_tag},
+    tui,
+    utils::ResultExt as _,
+};
+
+/// Timers that ran out longer ago than this, e.g. while the computer was off, complete
+/// without running [`TimerConfig::on_complete`].
+const MAX_COMPLETION_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// Shows the remaining time of a countdown or pomodoro timer.
+///
+/// A left click starts or pauses the timer, a right click resets it and a middle click skips to
+/// the next pomodoro phase. Scrolling changes the duration of a stopped countdown by a minute.
+///
+/// The same actions are available as commands for [`TimerConfig::name`], e.g.
+/// `scratchbar msg send timer toggle`: `start`, `pause`, `toggle`, `reset`, `skip` and
+/// `set <duration>`, which sets the duration of a countdown to e.g. `90s`, `25m` or `1h30m`.
+pub struct Timer;
+
+#[derive(Debug, Clone)]
+pub struct TimerConfig {
+    /// Identifies the timer in commands and the file it is saved to. Defaults to `timer`.
+    pub name: Arc<str>,
+    pub mode: TimerMode,
+    pub style: tui::TextStyle,
+    /// The style while the timer is paused or stopped.
+    pub paused_style: tui::TextStyle,
+    pub on_complete: OnComplete,
+    /// Whether the timer is saved to `$XDG_STATE_HOME/scratchbar/`, so that a running timer
+    /// keeps running when the bar is restarted.
+    pub persist: bool,
+    #[doc(hidden)]
+    #[deprecated = warn_non_exhaustive!()]
+    pub __non_exhaustive_struct_update: (),
+}
+impl Default for TimerConfig {
+    fn default() -> Self {
+        Self {
+            name: "timer".into(),
+            mode: Default::default(),
+            style: Default::default(),
+            paused_style: tui::TextStyle {
+                fg: Some(tui::Color::Palette(8)),
+                ..Default::default()
+            },
+            on_complete: Default::default(),
+            persist: true,
+            #[expect(deprecated)]
+            __non_exhaustive_struct_update: (),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum TimerMode {
+    /// Counts down from the duration, which can be changed while the timer is stopped.
+    Countdown(Duration),
+    /// Alternates between work and breaks.
+    Pomodoro(PomodoroConfig),
+}
+impl Default for TimerMode {
+    fn default() -> Self {
+        Self::Pomodoro(Default::default())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PomodoroConfig {
+    pub work: Duration,
+    pub short_break: Duration,
+    pub long_break: Duration,
+    /// Every this many completed work phases, the break is a long one.
+    pub long_break_after: u32,
+    /// Whether the next phase starts by itself once a phase completes.
+    pub auto_start: bool,
+    #[doc(hidden)]
+    #[deprecated = warn_non_exhaustive!()]
+    pub __non_exhaustive_struct_update: (),
+}
+impl Default for PomodoroConfig {
+    fn default() -> Self {
+        Self {
+            work: Duration::from_secs(25 * 60),
+            short_break: Duration::from_secs(5 * 60),
+            long_break: Duration::from_secs(15 * 60),
+            long_break_after: 4,
+            auto_start: false,
+            #[expect(deprecated)]
+            __non_exhaustive_struct_update: (),
+        }
+    }
+}
+
+/// What happens when the timer runs out.
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub enum OnComplete {
+    /// Send a desktop notification using `notify-send`.
+    #[default]
+    Notify,
+    /// Run the program with the arguments. `SCRATCHBAR_TIMER` is set to the name of the timer
+    /// and `SCRATCHBAR_TIMER_PHASE` to `countdown`, `work`, `short-break` or `long-break`.
+    Command(Arc<[Arc<str>]>),
+    Nothing,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+enum Phase {
+    #[default]
+    Work,
+    ShortBreak,
+    LongBreak,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+enum Run {
+    #[default]
+    Stopped,
+    Paused {
+        remaining_ms: u64,
+    },
+    /// The deadline is wall-clock time, so that it survives restarts.
+    Running {
+        deadline_unix_ms: u64,
+    },
+}
+
+/// The state that is saved across restarts.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct TimerState {
+    /// Always [`Phase::Work`] for countdowns.
+    phase: Phase,
+    /// Work phases completed since the last long break.
+    completed_work: u32,
+    /// Replaces the duration of [`TimerMode::Countdown`].
+    countdown_ms: Option<u64>,
+    run: Run,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Action {
+    Start,
+    Pause,
+    Toggle,
+    Reset,
+    Skip,
+    SetCountdown(Duration),
+    AdjustCountdown { minutes: i64 },
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+        .try_into()
+        .unwrap_or(u64::MAX)
+}
+
+fn as_ms(duration: Duration) -> u64 {
+    duration.as_millis().try_into().unwrap_or(u64::MAX)
+}
+
+impl TimerMode {
+    fn duration(&self, state: &TimerState) -> Duration {
+        match self {
+            Self::Countdown(duration) => state
+                .countdown_ms
+                .map(Duration::from_millis)
+                .unwrap_or(*duration),
+            Self::Pomodoro(pomodoro) => match state.phase {
+                Phase::Work => pomodoro.work,
+                Phase::ShortBreak => pomodoro.short_break,
+                Phase::LongBreak => pomodoro.long_break,
+            },
+        }
+    }
+
+    fn remaining_ms(&self, state: &TimerState, now: u64) -> u64 {
+        match state.run {
+            Run::Stopped => as_ms(self.duration(state)),
+            Run::Paused { remaining_ms } => remaining_ms,
+            Run::Running { deadline_unix_ms } => deadline_unix_ms.saturating_sub(now),
+        }
+    }
+
+    /// Moves on to the next pomodoro phase, or stops a countdown.
+    fn advance(&self, state: &mut TimerState, completed: bool, now: u64) {
+        let Self::Pomodoro(pomodoro) = self else {
+            state.run = Run::Stopped;
+            return;
+        };
+        state.phase = match state.phase {
+            Phase::Work => {
+                if completed {
+                    state.completed_work += 1;
+                }
+                if state.completed_work >= pomodoro.long_break_after.max(1) {
+                    state.completed_work = 0;
+                    Phase::LongBreak
+                } else {
+                    Phase::ShortBreak
+                }
+            }
+            Phase::ShortBreak | Phase::LongBreak => Phase::Work,
+        };
+        state.run = if completed && pomodoro.auto_start {
+            Run::Running {
+                deadline_unix_ms: now.saturating_add(as_ms(self.duration(state))),
+            }
+        } else {
+            Run::Stopped
+        };
+    }
+
+    /// Returns whether the state changed.
+    fn apply(&self, state: &mut TimerState, action: Action) -> bool {
+        let now = unix_ms();
+        let prev = state.clone();
+        match action {
+            Action::Start => {
+                if !matches!(state.run, Run::Running { .. }) {
+                    state.run = Run::Running {
+                        deadline_unix_ms: now.saturating_add(self.remaining_ms(state, now)),
+                    };
+                }
+            }
+            Action::Pause => {
+                if let Run::Running { deadline_unix_ms } = state.run {
+                    state.run = Run::Paused {
+                        remaining_ms: deadline_unix_ms.saturating_sub(now),
+                    };
+                }
+            }
+            Action::Toggle => {
+                let action = match state.run {
+                    Run::Running { .. } => Action::Pause,
+                    _ => Action::Start,
+                };
+                return self.apply(state, action);
+            }
+            Action::Reset => {
+                if state.run == Run::Stopped {
+                    // Resetting twice starts the pomodoro over
+                    *state = TimerState {
+                        countdown_ms: state.countdown_ms,
+                        ..Default::default()
+                    };
+                }
+                state.run = Run::Stopped;
+            }
+            Action::Skip => self.advance(state, false, now),
+            Action::SetCountdown(duration) => {
+                if !matches!(self, Self::Countdown(_)) {
+                    log::warn!("Only the duration of countdown timers can be set");
+                    return false;
+                }
+                state.countdown_ms = Some(as_ms(duration));
+                state.run = Run::Stopped;
+            }
+            Action::AdjustCountdown { minutes } => {
+                if matches!(self, Self::Countdown(_)) && state.run == Run::Stopped {
+                    let secs = self.duration(state).as_secs() as i64 + 60 * minutes;
+                    state.countdown_ms = Some(1000 * secs.max(60) as u64);
+                }
+            }
+        }
+        *state != prev
+    }
+}
+
+/// Parses a duration like `90s`, `25m` or `1h30m`. A plain number is in minutes.
+fn parse_duration(text: &str) -> Option<Duration> {
+    if let Ok(minutes) = text.parse::<u64>() {
+        return Some(Duration::from_secs(60 * minutes));
+    }
+    let mut secs = 0u64;
+    let mut rest = text;
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+        let value: u64 = rest[..digits].parse().ok()?;
+        let unit = match rest[digits..].chars().next()? {
+            'h' => 60 * 60,
+            'm' => 60,
+            's' => 1,
+            _ => return None,
+        };
+        secs = secs.checked_add(value.checked_mul(unit)?)?;
+        rest = &rest[digits + 1..];
+    }
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+fn parse_command(args: &[String]) -> Option<Action> {
+    let args: Vec<_> = args.iter().map(String::as_str).collect();
+    Some(match args[..] {
+        ["start"] => Action::Start,
+        ["pause"] => Action::Pause,
+        ["toggle"] => Action::Toggle,
+        ["reset"] => Action::Reset,
+        ["skip"] => Action::Skip,
+        ["set", duration] => Action::SetCountdown(parse_duration(duration)?),
+        _ => return None,
+    })
+}
+
+fn state_path(name: &str) -> Option<PathBuf> {
+    let state_home = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| Some(PathBuf::from(std::env::var_os("HOME")?).join(".local/state")))?;
+    Some(
+        state_home
+            .join("scratchbar")
+            .join(format!("timer-{name}.json")),
+    )
+}
+
+async fn load(path: &PathBuf) -> anyhow::Result<Option<TimerState>> {
+    let data = match tokio::fs::read(path).await {
+        Ok(data) => data,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    Ok(Some(serde_json::from_slice(&data)?))
+}
+
+async fn save(path: &PathBuf, state: &TimerState) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    // Write to a temporary file first, so that a crash does not leave a broken file behind
+    let tmp = path.with_extension("json.tmp");
+    tokio::fs::write(&tmp, serde_json::to_vec(state)?).await?;
+    tokio::fs::rename(&tmp, path).await?;
+    Ok(())
+}
+
+fn complete(name: &str, mode: &TimerMode, phase: Phase, on_complete: &OnComplete) {
+    let phase_name = match (mode, phase) {
+        (TimerMode::Countdown(_), _) => "countdown",
+        (_, Phase::Work) => "work",
+        (_, Phase::ShortBreak) => "short-break",
+        (_, Phase::LongBreak) => "long-break",
+    };
+    let mut cmd = match on_complete {
+        OnComplete::Notify => {
+            let (summary, body) = match (mode, phase) {
+                (TimerMode::Countdown(_), _) => ("Timer", "Time is up"),
+                (_, Phase::Work) => ("Pomodoro", "Time for a break"),
+                (_, Phase::ShortBreak | Phase::LongBreak) => ("Pomodoro", "Back to work"),
+            };
+            let mut cmd = tokio::process::Command::new("notify-send");
+            cmd.args(["--app-name=scratchbar", summary, body]);
+            cmd
+        }
+        OnComplete::Command(args) => {
+            let Some((program, args)) = args.split_first() else {
+                return;
+            };
+            let mut cmd = tokio::process::Command::new(&**program);
+            cmd.args(args.iter().map(|it| &**it))
+                .env("SCRATCHBAR_TIMER", name)
+                .env("SCRATCHBAR_TIMER_PHASE", phase_name);
+            cmd
+        }
+        OnComplete::Nothing => return,
+    };
+    cmd.stdin(std::process::Stdio::null());
+    tokio::spawn(async move {
+        match cmd.status().await {
+            Ok(status) if !status.success() => {
+                log::error!("Timer completion command exited with {status}")
+            }
+            Ok(_) => {}
+            Err(err) => log::error!("Failed to run timer completion command: {err}"),
+        }
+    });
+}
+
+fn format_remaining(remaining_ms: u64) -> String {
+    // Round up, so that 00:00 is only shown once the timer has run out
+    let secs = remaining_ms.div_ceil(1000);
+    let (hours, mins, secs) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if hours > 0 {
+        format!("{hours}:{mins:02}:{secs:02}")
+    } else {
+        format!("{mins:02}:{secs:02}")
+    }
+}
+
+impl Module for Timer {
+    type Config = TimerConfig;
+
+    async fn run(config: TimerConfig, mut cx: ModuleCx) -> ModuleExit {
+        let TimerConfig {
+            name,
+            mode,
+            style,
+            paused_style,
+            on_complete,
+            persist,
+            #[expect(deprecated)]
+                __non_exhaustive_struct_update: (),
+        } = config;
+
+        let path = persist.then(|| state_path(&name)).flatten();
+        let mut saved = None;
+        if let Some(path) = &path {
+            saved = load(path)
+                .await
+                .with_context(|| format!("Failed to load timer from {}", path.display()))
+                .ok_or_log()
+                .flatten();
+        }
+        let state_tx = watch::Sender::new(saved.clone().unwrap_or_default());
+
+        let mode = Arc::new(mode);
+        let bar_tag = fresh_tag();
+        {
+            let state_tx = state_tx.clone();
+            let mode = mode.clone();
+            cx.on_interact(bar_tag.clone(), move |kind| {
+                let action = match kind {
+                    tui::InteractKind::Click(tui::MouseButton::Left) => Action::Toggle,
+                    tui::InteractKind::Click(tui::MouseButton::Right) => Action::Reset,
+                    tui::InteractKind::Click(tui::MouseButton::Middle) => Action::Skip,
+                    tui::InteractKind::Scroll(tui::Direction::Up | tui::Direction::Right) => {
+                        Action::AdjustCountdown { minutes: 1 }
+                    }
+                    tui::InteractKind::Scroll(tui::Direction::Down | tui::Direction::Left) => {
+                        Action::AdjustCountdown { minutes: -1 }
+                    }
+                    _ => return,
+                };
+                state_tx.send_if_modified(|state| mode.apply(state, action));
+            });
+        }
+        {
+            let state_tx = state_tx.clone();
+            let mode = mode.clone();
+            let name = name.clone();
+            cx.on_command(name.clone(), move |args| {
+                let Some(action) = parse_command(args) else {
+                    log::warn!("Unknown command for timer {name}: {args:?}");
+                    return;
+                };
+                state_tx.send_if_modified(|state| mode.apply(state, action));
+            });
+        }
+
+        let mut state_rx = state_tx.subscribe();
+        loop {
+            let now = unix_ms();
+            let state = state_rx.borrow_and_update().clone();
+
+            if let Run::Running { deadline_unix_ms } = state.run
+                && deadline_unix_ms <= now
+            {
+                if now - deadline_unix_ms <= as_ms(MAX_COMPLETION_DELAY) {
+                    complete(&name, &mode, state.phase, &on_complete);
+                }
+                state_tx.send_modify(|state| mode.advance(state, true, now));
+                continue;
+            }
+
+            if let Some(path) = &path
+                && saved.as_ref() != Some(&state)
+            {
+                save(path, &state)
+                    .await
+                    .with_context(|| format!("Failed to save timer to {}", path.display()))
+                    .ok_or_log();
+                saved = Some(state.clone());
+            }
+
+            let remaining_ms = mode.remaining_ms(&state, now);
+            let label = match (&*mode, state.phase) {
+                (TimerMode::Countdown(_), _) => "",
+                (_, Phase::Work) => "Work ",
+                (_, Phase::ShortBreak | Phase::LongBreak) => "Break ",
+            };
+            let running = matches!(state.run, Run::Running { .. });
+            cx.set_bar(
+                tui::Elem::text(
+                    format_args!("{label}{}", format_remaining(remaining_ms)),
+                    if running { &style } else { &paused_style }.clone(),
+                )
+                .interactive(bar_tag.clone()),
+            );
+
+            let tick = async {
+                if running {
+                    // Wake up when the shown seconds change, slightly late to be safe
+                    let until_change = match remaining_ms % 1000 {
+                        0 => 1000,
+                        ms => ms,
+                    };
+                    tokio::time::sleep(Duration::from_millis(until_change + 5)).await
+                } else {
+                    std::future::pending().await
+                }
+            };
+            tokio::select! {
+                () = tick => {}
+                Ok(()) = state_rx.changed() => {}
+                Some(()) = cx.reloaded() => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,447 @@
+//! The current weather and a forecast, from a configurable [`WeatherProvider`].
+
+use std::{fmt, sync::Arc, time::Duration};
+
+use anyhow::Context as _;
+use futures::future::BoxFuture;
+use serde::Deserialize;
+use tokio::sync::watch;
+use unicode_width::UnicodeWidthStr as _;
+
+use crate::{
+    modules::{MenuKind, Module, ModuleCx, ModuleExit, RegisterMenu, fresh_tag, run_or_retry},
+    tui,
+};
+
+const RETRY_MIN_DELAY: Duration = Duration::from_secs(10);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(10 * 60);
+/// Reloads within this time of the last fetch use the cached forecast.
+const MIN_REFETCH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Shows the current temperature and weather on the bar. Hovering it shows the forecast of
+/// the next days.
+///
+/// The module does nothing unless [`WeatherConfig::location`] is set.
+pub struct Weather;
+
+#[derive(Debug, Clone)]
+pub struct WeatherConfig {
+    /// Where the weather comes from. Defaults to [`OpenMeteo`].
+    pub provider: Arc<dyn WeatherProvider>,
+    pub location: Option<Location>,
+    pub units: Units,
+    /// How often the weather is fetched.
+    pub interval: Duration,
+    /// The number of days in the forecast, including today.
+    pub days: u8,
+    pub style: tui::TextStyle,
+    #[doc(hidden)]
+    #[deprecated = warn_non_exhaustive!()]
+    pub __non_exhaustive_struct_update: (),
+}
+impl Default for WeatherConfig {
+    fn default() -> Self {
+        Self {
+            provider: Arc::new(OpenMeteo),
+            location: None,
+            units: Default::default(),
+            interval: Duration::from_secs(15 * 60),
+            days: 5,
+            style: Default::default(),
+            #[expect(deprecated)]
+            __non_exhaustive_struct_update: (),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+impl Location {
+    pub fn new(latitude: f64, longitude: f64) -> Self {
+        Self {
+            latitude,
+            longitude,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Units {
+    /// Degrees Celsius.
+    #[default]
+    Metric,
+    /// Degrees Fahrenheit.
+    Imperial,
+}
+impl Units {
+    fn temperature_symbol(self) -> &'static str {
+        match self {
+            Self::Metric => "°C",
+            Self::Imperial => "°F",
+        }
+    }
+}
+
+/// A source of weather data.
+pub trait WeatherProvider: fmt::Debug + Send + Sync + 'static {
+    /// Fetches the current weather and the forecast for the next `days` days, starting today.
+    /// Temperatures are in the given units.
+    fn fetch<'a>(
+        &'a self,
+        location: Location,
+        units: Units,
+        days: u8,
+    ) -> BoxFuture<'a, anyhow::Result<Forecast>>;
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Forecast {
+    pub current: CurrentWeather,
+    /// Starting today.
+    pub daily: Vec<DailyForecast>,
+    #[doc(hidden)]
+    #[deprecated = warn_non_exhaustive!()]
+    pub __non_exhaustive_struct_update: (),
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CurrentWeather {
+    pub temperature: f64,
+    pub condition: WeatherCondition,
+    #[doc(hidden)]
+    #[deprecated = warn_non_exhaustive!()]
+    pub __non_exhaustive_struct_update: (),
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DailyForecast {
+    pub date: chrono::NaiveDate,
+    pub min_temperature: f64,
+    pub max_temperature: f64,
+    pub condition: WeatherCondition,
+    /// The highest probability of precipitation during the day, in percent.
+    pub precipitation_probability: Option<u8>,
+    #[doc(hidden)]
+    #[deprecated = warn_non_exhaustive!()]
+    pub __non_exhaustive_struct_update: (),
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum WeatherCondition {
+    Clear,
+    PartlyCloudy,
+    Cloudy,
+    Fog,
+    Drizzle,
+    Rain,
+    Snow,
+    Thunderstorm,
+    #[default]
+    Unknown,
+}
+impl WeatherCondition {
+    /// Interprets a WMO weather interpretation code, as used by [`OpenMeteo`].
+    pub fn from_wmo_code(code: u8) -> Self {
+        match code {
+            0 => Self::Clear,
+            1 | 2 => Self::PartlyCloudy,
+            3 => Self::Cloudy,
+            45 | 48 => Self::Fog,
+            51..=57 => Self::Drizzle,
+            61..=67 | 80..=82 => Self::Rain,
+            71..=77 | 85 | 86 => Self::Snow,
+            95..=99 => Self::Thunderstorm,
+            _ => Self::Unknown,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Clear => "☀",
+            Self::PartlyCloudy => "⛅",
+            Self::Cloudy => "☁",
+            Self::Fog => "≡",
+            Self::Drizzle | Self::Rain => "☂",
+            Self::Snow => "❄",
+            Self::Thunderstorm => "⚡",
+            Self::Unknown => "?",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::Clear => "Clear",
+            Self::PartlyCloudy => "Partly cloudy",
+            Self::Cloudy => "Cloudy",
+            Self::Fog => "Fog",
+            Self::Drizzle => "Drizzle",
+            Self::Rain => "Rain",
+            Self::Snow => "Snow",
+            Self::Thunderstorm => "Thunderstorm",
+            Self::Unknown => "Unknown",
+        }
+    }
+}
+
+/// The forecast API of [Open-Meteo](https://open-meteo.com), which needs no API key.
+///
+/// Requests are made with the `curl` command.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OpenMeteo;
+
+#[derive(Deserialize)]
+struct OpenMeteoResponse {
+    current: OpenMeteoCurrent,
+    daily: OpenMeteoDaily,
+}
+#[derive(Deserialize)]
+struct OpenMeteoCurrent {
+    temperature_2m: f64,
+    weather_code: u8,
+}
+#[derive(Deserialize)]
+struct OpenMeteoDaily {
+    time: Vec<String>,
+    weather_code: Vec<u8>,
+    temperature_2m_min: Vec<f64>,
+    temperature_2m_max: Vec<f64>,
+    precipitation_probability_max: Vec<Option<u8>>,
+}
+
+impl WeatherProvider for OpenMeteo {
+    fn fetch<'a>(
+        &'a self,
+        location: Location,
+        units: Units,
+        days: u8,
+    ) -> BoxFuture<'a, anyhow::Result<Forecast>> {
+        Box::pin(async move {
+            let Location {
+                latitude,
+                longitude,
+            } = location;
+            let temperature_unit = match units {
+                Units::Metric => "celsius",
+                Units::Imperial => "fahrenheit",
+            };
+            let url = format!(
+                "https://api.open-meteo.com/v1/forecast?latitude={latitude}&longitude={longitude}\
+                &current=temperature_2m,weather_code\
+                &daily=weather_code,temperature_2m_min,temperature_2m_max,\
+                precipitation_probability_max\
+                &timezone=auto&forecast_days={days}&temperature_unit={temperature_unit}"
+            );
+            let OpenMeteoResponse { current, daily } =
+                serde_json::from_slice(&http_get(&url).await?)
+                    .context("Failed to parse Open-Meteo forecast")?;
+
+            let dates = (daily.time.iter())
+                .map(|it| it.parse::<chrono::NaiveDate>())
+                .collect::<Result<Vec<_>, _>>()
+                .context("Invalid date in Open-Meteo forecast")?;
+            let daily = (dates.into_iter())
+                .zip(daily.weather_code)
+                .zip(daily.temperature_2m_min)
+                .zip(daily.temperature_2m_max)
+                .zip(daily.precipitation_probability_max)
+                .map(
+                    |((((date, code), min), max), precipitation)| DailyForecast {
+                        date,
+                        min_temperature: min,
+                        max_temperature: max,
+                        condition: WeatherCondition::from_wmo_code(code),
+                        precipitation_probability: precipitation,
+                        ..Default::default()
+                    },
+                )
+                .collect();
+            Ok(Forecast {
+                current: CurrentWeather {
+                    temperature: current.temperature_2m,
+                    condition: WeatherCondition::from_wmo_code(current.weather_code),
+                    ..Default::default()
+                },
+                daily,
+                ..Default::default()
+            })
+        })
+    }
+}
+
+async fn http_get(url: &str) -> anyhow::Result<Vec<u8>> {
+    let std::process::Output {
+        status,
+        stdout,
+        stderr,
+    } = tokio::process::Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--location"])
+        .args(["--max-time", "30", url])
+        .stdin(std::process::Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .context("Failed to run curl")?;
+    if !status.success() {
+        anyhow::bail!(
+            "curl exited with {status}: {}",
+            String::from_utf8_lossy(&stderr).trim()
+        );
+    }
+    Ok(stdout)
+}
+
+impl Module for Weather {
+    type Config = WeatherConfig;
+
+    async fn run(config: WeatherConfig, cx: ModuleCx) -> ModuleExit {
+        let WeatherConfig {
+            provider,
+            location,
+            units,
+            interval,
+            days,
+            style,
+            #[expect(deprecated)]
+                __non_exhaustive_struct_update: (),
+        } = config;
+
+        let Some(location) = location else {
+            log::error!("The weather module has no location");
+            return ModuleExit::Done;
+        };
+
+        let bar_tag = fresh_tag();
+        let tooltip_tx = watch::Sender::new(tui::Elem::empty());
+        cx.register_menu(RegisterMenu {
+            on_tag: bar_tag.clone(),
+            on_kind: tui::InteractKind::Hover,
+            tui_rx: tooltip_tx.subscribe(),
+            kind: MenuKind::Tooltip,
+        });
+
+        let mut reload_rx = cx.subscribe_reload();
+        let mut cached: Option<(tokio::time::Instant, Forecast)> = None;
+        loop {
+            let forecast = match cached.take() {
+                Some((fetched, forecast)) if fetched.elapsed() < MIN_REFETCH_INTERVAL => {
+                    (fetched, forecast)
+                }
+                _ => {
+                    let forecast = run_or_retry(
+                        || async {
+                            provider
+                                .fetch(location, units, days)
+                                .await
+                                .context("Failed to fetch the weather")
+                        },
+                        RETRY_MIN_DELAY,
+                        RETRY_MAX_DELAY,
+                        &mut reload_rx,
+                    )
+                    .await;
+                    (tokio::time::Instant::now(), forecast)
+                }
+            };
+            let (fetched, forecast) = cached.insert(forecast);
+
+            let unit = units.temperature_symbol();
+            cx.set_bar(
+                tui::Elem::text(
+                    format_args!(
+                        "{} {:.0}{unit}",
+                        forecast.current.condition.symbol(),
+                        forecast.current.temperature,
+                    ),
+                    style.clone(),
+                )
+                .interactive(bar_tag.clone()),
+            );
+            tooltip_tx.send_replace(tooltip(forecast, unit));
+
+            let next_fetch = *fetched + interval;
+            tokio::select! {
+                () = tokio::time::sleep_until(next_fetch) => {
+                    cached = None;
+                }
+                Ok(()) = reload_rx.changed() => {}
+            }
+        }
+    }
+}
+
+fn tooltip(forecast: &Forecast, unit: &str) -> tui::Elem {
+    let today = chrono::Local::now().date_naive();
+    let rows: Vec<[String; 4]> = forecast
+        .daily
+        .iter()
+        .map(|day| {
+            [
+                if day.date == today {
+                    "Today".into()
+                } else {
+                    day.date.format("%a %d").to_string()
+                },
+                format!("{} {}", day.condition.symbol(), day.condition.description()),
+                format!(
+                    "{:.0}{unit} / {:.0}{unit}",
+                    day.max_temperature, day.min_temperature
+                ),
+                day.precipitation_probability
+                    .map(|it| format!("{it}%"))
+                    .unwrap_or_default(),
+            ]
+        })
+        .collect();
+
+    let mut widths = [0; 4];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.width());
+        }
+    }
+
+    let bold = tui::TextStyle {
+        bold: true,
+        ..Default::default()
+    };
+    let mut lines = vec![tui::Elem::text(
+        format_args!(
+            "{} {:.0}{unit}",
+            forecast.current.condition.description(),
+            forecast.current.temperature
+        ),
+        bold.clone(),
+    )];
+    lines.extend(rows.into_iter().map(|row| {
+        let cells = row
+            .into_iter()
+            .zip(widths)
+            .enumerate()
+            .map(|(i, (cell, width))| {
+                // Two cells between columns
+                let width = width as u16 + 2;
+                tui::StackItem {
+                    elem: tui::Elem::text(
+                        cell,
+                        if i == 0 {
+                            bold.clone()
+                        } else {
+                            Default::default()
+                        },
+                    ),
+                    opts: tui::StackItemOpts {
+                        constraint: Some(tui::Constraint::Exact(width)),
+                        ..Default::default()
+                    },
+                }
+            });
+        tui::Elem::stack(tui::Axis::X, cells, tui::StackOpts::default())
+    }));
+    tui::Elem::stack(tui::Axis::Y, lines, tui::StackOpts::default())
+}
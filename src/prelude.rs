@@ -0,0 +1,19 @@
+//! The types most controllers need, for glob importing.
+//!
+//! ```
+//! use scratchbar::prelude::*;
+//! ```
+//!
+//! This only contains items that are used by almost every controller. Everything else is
+//! still available from [`host`] and [`tui`].
+
+pub use crate::{host, tui};
+
+pub use crate::host::{
+    BarSelect, BarUpdate, HostConnectOpts, HostConnection, HostEvent, HostUpdate, HostUpdateSender,
+    InteractEvent, OpenMenu, OpenMenuOpts, SetBarTui, SetBarTuiOpts, TermEvent, TermInfo, TermKind,
+};
+pub use crate::tui::{
+    Align, Align2D, Axis, Constraint, CustomId, Direction, Elem, Image, InteractKind, MinAxis,
+    MouseButton, Size, StackItem, StackItemOpts, StackOpts,
+};
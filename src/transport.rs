@@ -0,0 +1,230 @@
+//! Transports for the connection between host and controller.
+
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    time::Duration,
+};
+
+use anyhow::Context as _;
+
+/// Selects the address the host listens on and the controller connects to.
+///
+/// Supported forms are `unix:<path>`, `unix-abstract:<name>` and `tcp:<host>:<port>`.
+/// If the host is started with this set and without a controller command, it waits for a
+/// controller to connect instead of spawning one.
+pub(crate) const CONNECT_VAR: &str = "SCRATCHBAR_CONNECT";
+
+#[derive(Debug, Clone)]
+pub(crate) enum ConnectAddr {
+    Unix(PathBuf),
+    UnixAbstract(String),
+    Tcp(String),
+}
+impl std::fmt::Display for ConnectAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+            Self::UnixAbstract(name) => write!(f, "unix-abstract:{name}"),
+            Self::Tcp(addr) => write!(f, "tcp:{addr}"),
+        }
+    }
+}
+impl std::str::FromStr for ConnectAddr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (scheme, rest) = s
+            .split_once(':')
+            .with_context(|| format!("Connection address {s:?} is missing a scheme"))?;
+        Ok(match scheme {
+            "unix" => Self::Unix(rest.into()),
+            "unix-abstract" => Self::UnixAbstract(rest.into()),
+            "tcp" => Self::Tcp(rest.trim_start_matches("//").into()),
+            _ => anyhow::bail!("Unknown connection scheme {scheme:?} in {s:?}"),
+        })
+    }
+}
+impl ConnectAddr {
+    pub(crate) fn from_env() -> anyhow::Result<Option<Self>> {
+        let Some(addr) = std::env::var_os(CONNECT_VAR) else {
+            return Ok(None);
+        };
+        let addr = addr
+            .into_string()
+            .map_err(|addr| anyhow::anyhow!("{CONNECT_VAR} is not valid UTF-8: {addr:?}"))?;
+        addr.parse().map(Some)
+    }
+
+    /// Connects to the address. `timeout` only applies to TCP, since unix sockets do not block
+    /// when connecting.
+    pub(crate) fn connect(&self, timeout: Option<Duration>) -> anyhow::Result<IpcStream> {
+        Ok(match self {
+            Self::Unix(path) => IpcStream::Unix(UnixStream::connect(path)?),
+            Self::UnixAbstract(name) => {
+                IpcStream::Unix(UnixStream::connect_addr(&abstract_addr(name)?)?)
+            }
+            Self::Tcp(addr) => {
+                let stream = match timeout {
+                    None => TcpStream::connect(addr)?,
+                    Some(timeout) => connect_tcp_timeout(addr, timeout)?,
+                };
+                stream.set_nodelay(true)?;
+                IpcStream::Tcp(stream)
+            }
+        })
+    }
+
+    /// Listens on the address. A socket file that is left over from a host that did not exit
+    /// cleanly is replaced, and removed again once the returned [`SocketFile`] is dropped.
+    #[cfg(feature = "__bin")]
+    pub(crate) fn listen(&self) -> anyhow::Result<(IpcListener, Option<SocketFile>)> {
+        Ok(match self {
+            Self::Unix(path) => {
+                remove_stale_socket(path)?;
+                (
+                    IpcListener::Unix(std::os::unix::net::UnixListener::bind(path)?),
+                    Some(SocketFile(path.clone())),
+                )
+            }
+            Self::UnixAbstract(name) => (
+                IpcListener::Unix(std::os::unix::net::UnixListener::bind_addr(
+                    &abstract_addr(name)?,
+                )?),
+                None,
+            ),
+            Self::Tcp(addr) => (IpcListener::Tcp(std::net::TcpListener::bind(addr)?), None),
+        })
+    }
+}
+
+/// Like [`TcpStream::connect`], which tries every address that `addr` resolves to.
+fn connect_tcp_timeout(addr: &str, timeout: Duration) -> std::io::Result<TcpStream> {
+    let mut last_err = None;
+    for addr in std::net::ToSocketAddrs::to_socket_addrs(addr)? {
+        // `connect_timeout` rejects a zero timeout
+        match TcpStream::connect_timeout(&addr, timeout.max(Duration::from_millis(1))) {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_err = Some(err),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "could not resolve to any addresses",
+        )
+    }))
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn abstract_addr(name: &str) -> anyhow::Result<std::os::unix::net::SocketAddr> {
+    use std::os::linux::net::SocketAddrExt as _;
+    Ok(std::os::unix::net::SocketAddr::from_abstract_name(name)?)
+}
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn abstract_addr(_: &str) -> anyhow::Result<std::os::unix::net::SocketAddr> {
+    anyhow::bail!("Abstract unix sockets are only supported on Linux")
+}
+
+#[derive(Debug)]
+pub(crate) enum IpcStream {
+    Unix(UnixStream),
+    Tcp(TcpStream),
+}
+impl IpcStream {
+    /// Sets the read and write timeouts.
+    pub(crate) fn set_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        match self {
+            Self::Unix(stream) => {
+                stream.set_read_timeout(timeout)?;
+                stream.set_write_timeout(timeout)
+            }
+            Self::Tcp(stream) => {
+                stream.set_read_timeout(timeout)?;
+                stream.set_write_timeout(timeout)
+            }
+        }
+    }
+    pub(crate) fn shutdown(&self, how: std::net::Shutdown) -> std::io::Result<()> {
+        match self {
+            Self::Unix(stream) => stream.shutdown(how),
+            Self::Tcp(stream) => stream.shutdown(how),
+        }
+    }
+}
+impl Read for &IpcStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            IpcStream::Unix(stream) => (&*stream).read(buf),
+            IpcStream::Tcp(stream) => (&*stream).read(buf),
+        }
+    }
+}
+impl Write for &IpcStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            IpcStream::Unix(stream) => (&*stream).write(buf),
+            IpcStream::Tcp(stream) => (&*stream).write(buf),
+        }
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            IpcStream::Unix(stream) => (&*stream).flush(),
+            IpcStream::Tcp(stream) => (&*stream).flush(),
+        }
+    }
+}
+
+/// Removes the socket file at `path` if it is left over from a host that did not exit cleanly,
+/// so that it can be bound again. Fails if another host is still listening on it.
+#[cfg(feature = "__bin")]
+pub(crate) fn remove_stale_socket(path: &std::path::Path) -> anyhow::Result<()> {
+    if UnixStream::connect(path).is_ok() {
+        anyhow::bail!("Another host is already listening on {path:?}");
+    }
+    // The socket is stale if nobody is listening on it
+    match std::fs::remove_file(path) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+            Err(err).context("Failed to remove stale socket")
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Removes the socket file of a [`ConnectAddr::Unix`] listener when dropped. Separate from the
+/// [`IpcListener`], which may be stuck in a blocking accept when the host exits.
+#[cfg(feature = "__bin")]
+#[derive(Debug)]
+pub(crate) struct SocketFile(PathBuf);
+#[cfg(feature = "__bin")]
+impl Drop for SocketFile {
+    fn drop(&mut self) {
+        use crate::utils::ResultExt as _;
+
+        std::fs::remove_file(&self.0)
+            .with_context(|| format!("Failed to remove socket {:?}", self.0))
+            .ok_or_debug();
+    }
+}
+
+#[cfg(feature = "__bin")]
+#[derive(Debug)]
+pub(crate) enum IpcListener {
+    Unix(std::os::unix::net::UnixListener),
+    Tcp(std::net::TcpListener),
+}
+#[cfg(feature = "__bin")]
+impl IpcListener {
+    pub(crate) fn accept(&self) -> std::io::Result<IpcStream> {
+        Ok(match self {
+            Self::Unix(listener) => IpcStream::Unix(listener.accept()?.0),
+            Self::Tcp(listener) => {
+                let (stream, _) = listener.accept()?;
+                stream.set_nodelay(true)?;
+                IpcStream::Tcp(stream)
+            }
+        })
+    }
+}
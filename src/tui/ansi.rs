@@ -0,0 +1,174 @@
+//! Conversion of text with ANSI escape sequences into elements, see [`Elem::from_ansi`].
+
+use std::{fmt, ops::Range, rc::Rc};
+
+use crate::tui::*;
+
+/// The error returned by [`Elem::from_ansi`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AnsiError {
+    /// An escape sequence other than SGR (`ESC [ ... m`), e.g. one that moves the cursor.
+    UnsupportedEscape { offset: usize },
+    /// A control character other than newline, carriage return and tab.
+    ControlChar { offset: usize, char: char },
+}
+impl fmt::Display for AnsiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedEscape { offset } => {
+                write!(f, "Unsupported escape sequence at byte {offset}")
+            }
+            Self::ControlChar { offset, char } => {
+                write!(f, "Unsupported control character {char:?} at byte {offset}")
+            }
+        }
+    }
+}
+impl std::error::Error for AnsiError {}
+
+const TAB_WIDTH: usize = 8;
+
+/// A line of output, starting with the SGR sequences that are active at its start.
+struct Line {
+    raw: String,
+    width: usize,
+    /// The visible graphemes in `raw` with the SGR sequences that are active for them.
+    graphemes: Vec<(Range<usize>, Rc<str>)>,
+}
+impl Line {
+    fn new(active_sgr: &Rc<str>) -> Self {
+        Self {
+            raw: active_sgr.to_string(),
+            width: 0,
+            graphemes: Vec::new(),
+        }
+    }
+    fn push_grapheme(&mut self, grapheme: &str, active_sgr: &Rc<str>) {
+        let start = self.raw.len();
+        self.raw.push_str(grapheme);
+        self.graphemes
+            .push((start..self.raw.len(), active_sgr.clone()));
+    }
+    /// Reorders right-to-left text for drawing, see [`bidi`]. Since runs of graphemes change
+    /// places, each run starts with its own SGR sequences.
+    fn into_visual(self) -> Self {
+        let graphemes: Vec<_> = self
+            .graphemes
+            .iter()
+            .map(|(range, _)| &self.raw[range.clone()])
+            .collect();
+        let Some(order) = bidi::visual_order(&graphemes) else {
+            return self;
+        };
+        let mut raw = String::with_capacity(self.raw.len());
+        let mut sgr = None;
+        for (i, mirrored) in order.graphemes {
+            let (_, active_sgr) = &self.graphemes[i];
+            if bidi::is_format_char(graphemes[i]) {
+                continue;
+            }
+            if sgr != Some(active_sgr) {
+                raw.push_str("\x1b[0m");
+                raw.push_str(active_sgr);
+                sgr = Some(active_sgr);
+            }
+            match mirrored {
+                Some(c) => raw.push(c),
+                None => raw.push_str(graphemes[i]),
+            }
+        }
+        Self {
+            raw,
+            width: self.width,
+            graphemes: Vec::new(),
+        }
+    }
+}
+
+pub(super) fn parse(text: &str) -> Result<Elem, AnsiError> {
+    let mut lines = Vec::new();
+    // The SGR sequences since the last reset, which every line has to repeat since the lines
+    // are drawn independently
+    let mut active_sgr = Rc::<str>::from("");
+    let mut line = Line::new(&active_sgr);
+
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let offset = text.len() - rest.len();
+        match c {
+            '\x1b' => {
+                let Some(params) = rest.strip_prefix("\x1b[") else {
+                    return Err(AnsiError::UnsupportedEscape { offset });
+                };
+                let len = params
+                    .find(|c: char| !(c.is_ascii_digit() || c == ';' || c == ':'))
+                    .ok_or(AnsiError::UnsupportedEscape { offset })?;
+                if !params[len..].starts_with('m') {
+                    return Err(AnsiError::UnsupportedEscape { offset });
+                }
+                let seq = &rest[..2 + len + 1];
+                active_sgr = if matches!(&params[..len], "" | "0") {
+                    "".into()
+                } else {
+                    format!("{active_sgr}{seq}").into()
+                };
+                line.raw.push_str(seq);
+                rest = &rest[seq.len()..];
+                continue;
+            }
+            '\n' => {
+                lines.push(std::mem::replace(&mut line, Line::new(&active_sgr)).into_visual());
+            }
+            '\r' => {}
+            '\t' => {
+                let spaces = TAB_WIDTH - line.width % TAB_WIDTH;
+                for _ in 0..spaces {
+                    line.push_grapheme(" ", &active_sgr);
+                }
+                line.width += spaces;
+            }
+            c if c.is_control() => {
+                return Err(AnsiError::ControlChar { offset, char: c });
+            }
+            _ => {
+                let grapheme = unicode_segmentation::UnicodeSegmentation::graphemes(rest, true)
+                    .next()
+                    .unwrap_or_default();
+                // Control characters may only start a grapheme
+                if let Some((i, c)) = grapheme
+                    .char_indices()
+                    .skip(1)
+                    .find(|(_, c)| c.is_control())
+                {
+                    return Err(AnsiError::ControlChar {
+                        offset: offset + i,
+                        char: c,
+                    });
+                }
+                line.push_grapheme(grapheme, &active_sgr);
+                line.width += width::grapheme_width(grapheme, AmbiguousWidth::Narrow);
+                rest = &rest[grapheme.len()..];
+                continue;
+            }
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    // Like `str::lines`, a trailing newline does not start another line
+    if !text.is_empty() && !text.ends_with('\n') {
+        lines.push(line.into_visual());
+    }
+
+    Ok(Elem::stack(
+        Axis::Y,
+        lines.into_iter().map(|Line { mut raw, width, .. }| {
+            raw.push_str("\x1b[0m");
+            // Only SGR sequences are left after parsing
+            Elem::trusted_raw_print(raw).with_min_size(Size {
+                width: width.try_into().unwrap_or(u16::MAX),
+                height: 1,
+            })
+        }),
+        StackOpts::default(),
+    ))
+}
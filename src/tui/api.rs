@@ -1,6 +1,6 @@
 use crate::tui::*;
 use serde::{Deserialize, Serialize};
-use std::{fmt, sync::Arc};
+use std::{fmt, hash::Hash, ops::RangeInclusive, sync::Arc};
 
 /// Custom ID specified by the user. Holds custom bytes.
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
@@ -10,7 +10,7 @@ impl fmt::Debug for CustomId {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "0x")?;
         for byte in self.0.iter() {
-            write!(f, "{byte:x}")?;
+            write!(f, "{byte:02x}")?;
         }
         Ok(())
     }
@@ -22,7 +22,10 @@ impl CustomId {
     }
 }
 
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+/// The minimum width of [`Elem::slider`].
+pub const SLIDER_MIN_WIDTH: u16 = 10;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Size {
     pub width: u16,
     pub height: u16,
@@ -40,18 +43,78 @@ impl Axis {
         }
     }
 }
+/// The position of an element along an axis of a larger area, see [`Elem::aligned`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Align {
+    /// Left or top.
+    #[default]
+    Start,
+    Center,
+    /// Right or bottom.
+    End,
+}
+/// The position of an element within a larger area, see [`Elem::aligned`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Align2D {
+    pub x: Align,
+    pub y: Align,
+}
+impl Align2D {
+    pub const CENTER: Self = Self::new(Align::Center, Align::Center);
+    pub const LEFT: Self = Self::new(Align::Start, Align::Center);
+    pub const RIGHT: Self = Self::new(Align::End, Align::Center);
+    pub const TOP: Self = Self::new(Align::Center, Align::Start);
+    pub const BOTTOM: Self = Self::new(Align::Center, Align::End);
+
+    pub const fn new(x: Align, y: Align) -> Self {
+        Self { x, y }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub enum MouseButton {
     Left,
     Right,
     Middle,
 }
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[non_exhaustive]
 pub enum InteractKind {
     Click(MouseButton),
     Scroll(Direction),
     Hover,
+    /// A left click on a checkbox or radio button, with the state that it should change to.
+    /// See [`Elem::checkbox`].
+    Toggle(bool),
+    /// A slider was clicked, dragged or scrolled, with the value that it should change to.
+    /// See [`Elem::slider`].
+    ValueChange(f32),
+}
+// Values are compared by their bits, so that kinds can be used as keys.
+impl PartialEq for InteractKind {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Click(a), Self::Click(b)) => a == b,
+            (Self::Scroll(a), Self::Scroll(b)) => a == b,
+            (Self::Hover, Self::Hover) => true,
+            (Self::Toggle(a), Self::Toggle(b)) => a == b,
+            (Self::ValueChange(a), Self::ValueChange(b)) => a.to_bits() == b.to_bits(),
+            _ => false,
+        }
+    }
+}
+impl Eq for InteractKind {}
+impl Hash for InteractKind {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Self::Click(button) => button.hash(state),
+            Self::Scroll(dir) => dir.hash(state),
+            Self::Hover => {}
+            Self::Toggle(on) => on.hash(state),
+            Self::ValueChange(value) => value.to_bits().hash(state),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -75,9 +138,29 @@ impl From<Elem> for StackItem {
         }
     }
 }
+/// Bounds for the length of a stack item along the stack's axis, see
+/// [`StackItemOpts::constraint`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum Constraint {
+    /// At least this many cells, more if the content needs it.
+    Min(u16),
+    /// At most this many cells, even if the content needs more.
+    Max(u16),
+    /// At most this percentage of the space left over by the other items.
+    Percent(u8),
+    /// Exactly this many cells.
+    Exact(u16),
+}
+
 #[derive(Default, Debug, Clone)]
 pub struct StackItemOpts {
+    /// The share of the stack's leftover space that the item grows by, relative to the other
+    /// items. The item does not grow past the upper bound of its constraint.
     pub fill_weight: u16,
+    /// Without a constraint, the item is as long as its content, plus what it gets from
+    /// filling.
+    pub constraint: Option<Constraint>,
     // TODO: Spacing
     #[deprecated = warn_non_exhaustive!()]
     #[doc(hidden)]
@@ -85,12 +168,85 @@ pub struct StackItemOpts {
 }
 #[derive(Default, Debug, Clone)]
 pub struct StackOpts {
+    /// Lays out the items from the end of the axis, e.g. from right to left in a horizontal
+    /// stack for right-to-left locales. Items that do not fit are cut off at the start.
+    pub reverse: bool,
     #[deprecated = warn_non_exhaustive!()]
     #[doc(hidden)]
     // TODO: Spacing
     pub __non_exhaustive_struct_update: (),
 }
 
+#[derive(Debug, Clone)]
+pub struct TextInputOpts {
+    /// Shown while the input is empty and not focused.
+    pub placeholder: String,
+    pub style: TextStyle,
+    /// The minimum width of the input. Longer text scrolls.
+    pub width: u16,
+    #[deprecated = warn_non_exhaustive!()]
+    #[doc(hidden)]
+    pub __non_exhaustive_struct_update: (),
+}
+impl Default for TextInputOpts {
+    fn default() -> Self {
+        Self {
+            placeholder: String::new(),
+            style: Default::default(),
+            width: 20,
+            #[expect(deprecated)]
+            __non_exhaustive_struct_update: (),
+        }
+    }
+}
+
+/// How an element enters, see [`Elem::with_transition`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum TransitionKind {
+    /// Fades the text in from the background. RGB colors are interpolated from the background
+    /// color if it is known, i.e. if it is an RGB color set on the text or assigned to
+    /// [`StyleContext::BG`]. Other text is drawn faint for the first half of the transition.
+    Fade,
+    /// Moves the element into place from `distance` cells towards `from`. Elements that slide in
+    /// from the right or bottom are clipped to their area, the others are drawn over their
+    /// neighbors until they are in place.
+    Slide { from: Direction, distance: u16 },
+}
+
+#[derive(Debug, Clone)]
+pub struct Transition {
+    pub kind: TransitionKind,
+    pub duration: std::time::Duration,
+    #[deprecated = warn_non_exhaustive!()]
+    #[doc(hidden)]
+    pub __non_exhaustive_struct_update: (),
+}
+impl Default for Transition {
+    fn default() -> Self {
+        Self {
+            kind: TransitionKind::Fade,
+            duration: std::time::Duration::from_millis(150),
+            #[expect(deprecated)]
+            __non_exhaustive_struct_update: (),
+        }
+    }
+}
+
+/// What happens to the space of an [`Elem::collapsible`] while it is hidden.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum HiddenSpace {
+    /// The space stays reserved, so that the neighbors of the element do not move.
+    Reserve,
+    /// The element shrinks along `axis` until it takes no space, and grows back when it is
+    /// shown again. Its content is only drawn while it is fully grown.
+    Collapse {
+        axis: Axis,
+        duration: std::time::Duration,
+    },
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct MinAxis {
     pub axis: Axis,
@@ -99,6 +255,76 @@ pub struct MinAxis {
     pub aspect_height: u32,
 }
 
+/// RGBA pixel data, displayed using [`Elem::image`].
+///
+/// Each terminal receives the pixels of an image only once. Afterwards, it is placed by an id
+/// derived from its content, so reusing images is cheap. Images that have more pixels than the
+/// cells they are displayed in are downscaled by the host before they are sent to the terminal.
+/// The whole image is still sent to the host, so it is worth downscaling large images first.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Image(pub(crate) Arc<ImageRepr>);
+impl Image {
+    /// Returns `None` if `rgba` does not consist of exactly `width * height` pixels of 4 bytes.
+    pub fn from_rgba(width: u32, height: u32, rgba: Vec<u8>) -> Option<Self> {
+        let len = u64::from(width) * u64::from(height) * 4;
+        if len == 0 || u64::try_from(rgba.len()).ok()? != len {
+            return None;
+        }
+
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&width.to_le_bytes());
+        hasher.update(&height.to_le_bytes());
+        hasher.update(&rgba);
+        let id = std::num::NonZeroU32::new(hasher.finalize()).unwrap_or(std::num::NonZeroU32::MIN);
+
+        Some(Self(Arc::new(ImageRepr {
+            id,
+            width,
+            height,
+            rgba,
+        })))
+    }
+    pub fn width(&self) -> u32 {
+        self.0.width
+    }
+    pub fn height(&self) -> u32 {
+        self.0.height
+    }
+}
+
+/// SVG data, displayed using [`Elem::svg`].
+///
+/// The host rasterizes SVGs at the size they are displayed at, which requires it to be built
+/// with the `svg` feature. Otherwise, they are displayed as empty space.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Svg(pub(crate) Arc<SvgRepr>);
+impl Svg {
+    pub fn from_data(data: Vec<u8>) -> Self {
+        let hash = crc32fast::hash(&data);
+        Self(Arc::new(SvgRepr { hash, data }))
+    }
+}
+
+/// How an image is sized, see [`Elem::svg`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum ImageSizeMode {
+    /// `len` cells along `axis`, keeping the aspect ratio.
+    Axis { axis: Axis, len: u16 },
+    /// Exactly this many cells, stretching the image if necessary.
+    Cells(Size),
+}
+
+/// Where lines are broken, see [`Elem::wrapped_text`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum WrapMode {
+    /// Between words. Words that do not fit into a line on their own are broken anywhere.
+    Word,
+    /// Anywhere.
+    Char,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Elem(pub(crate) Arc<ElemRepr>);
 
@@ -106,6 +332,7 @@ impl Elem {
     pub fn empty() -> Self {
         ElemRepr::Print(PrintRepr {
             raw: Default::default(),
+            trusted: true,
         })
         .into()
     }
@@ -123,6 +350,8 @@ impl Elem {
             tag,
             normal: self,
             hovered: None,
+            pressed: None,
+            widget: None,
         })
         .into()
     }
@@ -132,10 +361,172 @@ impl Elem {
             tag,
             normal: self,
             hovered: Some(hovered),
+            pressed: None,
+            widget: None,
+        })
+        .into()
+    }
+
+    /// A label with padding that is highlighted when hovered and while the mouse button is held
+    /// down on it. Clicks are reported like for [`Self::interactive`].
+    pub fn button(label: impl fmt::Display, tag: CustomId) -> Self {
+        let label = format!(" {label} ");
+        let text = |style| Self::text(&label, style);
+        ElemRepr::Interact(InteractRepr {
+            tag,
+            normal: text(TextStyle {
+                bg: Some(Color::Palette(8)),
+                ..Default::default()
+            }),
+            hovered: Some(text(TextStyle {
+                bg: Some(Color::Palette(8)),
+                ..widget_hover_style()
+            })),
+            pressed: Some(text(TextStyle {
+                fg: Some(Color::Palette(0)),
+                bg: Some(Color::Palette(7)),
+                bold: true,
+                ..Default::default()
+            })),
+            widget: None,
         })
         .into()
     }
 
+    /// A checkbox that reports [`InteractKind::Toggle`] with the negation of `checked` when
+    /// clicked. The controller is expected to update the element with the new state.
+    pub fn checkbox(checked: bool, tag: CustomId) -> Self {
+        let glyph = if checked { "[✓]" } else { "[ ]" };
+        Self::widget(
+            Self::text(glyph, Default::default()),
+            Self::text(glyph, widget_hover_style()),
+            tag,
+            WidgetRepr::Checkbox { checked },
+        )
+    }
+
+    /// A single radio button, see [`Self::radio_group`].
+    pub fn radio(selected: bool, tag: CustomId) -> Self {
+        let glyph = radio_glyph(selected);
+        Self::widget(
+            Self::text(glyph, Default::default()),
+            Self::text(glyph, widget_hover_style()),
+            tag,
+            WidgetRepr::Radio { selected },
+        )
+    }
+
+    /// Radio buttons below each other, each followed by its label. Clicking an option,
+    /// including its label, reports [`InteractKind::Toggle`] with `true` for the tag of that
+    /// option.
+    pub fn radio_group(
+        options: impl IntoIterator<Item = (Elem, CustomId)>,
+        selected: Option<usize>,
+    ) -> Self {
+        let rows = options.into_iter().enumerate().map(|(i, (label, tag))| {
+            let selected = selected == Some(i);
+            let row = |style| {
+                Self::stack(
+                    Axis::X,
+                    [
+                        Self::text(radio_glyph(selected), style),
+                        Self::spacing(Axis::X, 1),
+                        label.clone(),
+                    ],
+                    StackOpts::default(),
+                )
+            };
+            Self::widget(
+                row(TextStyle::default()),
+                row(widget_hover_style()),
+                tag,
+                WidgetRepr::Radio { selected },
+            )
+        });
+        Self::stack(Axis::Y, rows, StackOpts::default())
+    }
+
+    /// A horizontal slider with a handle at `value`. Clicking or dragging along the track
+    /// reports [`InteractKind::ValueChange`] with the value under the mouse, and scrolling
+    /// changes the value by a twentieth of `range`. The controller is expected to update the
+    /// element with the new value.
+    ///
+    /// The slider is at least [`SLIDER_MIN_WIDTH`] cells wide and grows with the space it is
+    /// given, e.g. through a fill weight.
+    pub fn slider(value: f32, range: RangeInclusive<f32>, tag: CustomId) -> Self {
+        let (min, max) = range.into_inner();
+        let value = value.clamp(min.min(max), max.max(min));
+        let frac = if max == min {
+            0.0
+        } else {
+            (value - min) / (max - min)
+        };
+        let track = |filled| {
+            ElemRepr::Slider(SliderRepr {
+                frac,
+                track: TextStyle {
+                    fg: Some(Color::Palette(8)),
+                    ..Default::default()
+                },
+                filled,
+            })
+            .into()
+        };
+        Self::widget(
+            track(TextStyle::default()),
+            track(widget_hover_style()),
+            tag,
+            WidgetRepr::Slider { value, min, max },
+        )
+    }
+
+    /// A single line of editable text, which is focused by clicking it. While it is focused, the
+    /// host edits the text as keys are pressed and reports it using
+    /// [`crate::host::TermEvent::InputChanged`], and pressing enter reports
+    /// [`crate::host::TermEvent::InputSubmitted`]. Escape or clicking elsewhere removes the
+    /// focus.
+    ///
+    /// The edits are kept as long as an input with the same tag and `text` is shown, so the
+    /// controller does not need to send the edited text back. Sending a different `text`
+    /// replaces the edited text, e.g. to clear the input after it was submitted.
+    ///
+    /// Menus only receive key presses if the compositor gives them keyboard focus, which
+    /// usually happens when they are clicked.
+    pub fn text_input(tag: CustomId, text: impl fmt::Display, opts: TextInputOpts) -> Self {
+        let TextInputOpts {
+            placeholder,
+            style,
+            width,
+            #[expect(deprecated)]
+                __non_exhaustive_struct_update: (),
+        } = opts;
+        ElemRepr::Input(InputRepr {
+            tag,
+            text: text.to_string(),
+            placeholder,
+            style,
+            width,
+        })
+        .into()
+    }
+
+    fn widget(normal: Elem, hovered: Elem, tag: CustomId, widget: WidgetRepr) -> Self {
+        ElemRepr::Interact(InteractRepr {
+            tag,
+            normal,
+            hovered: Some(hovered),
+            pressed: None,
+            widget: Some(widget),
+        })
+        .into()
+    }
+
+    /// Marks this element as replaceable using [`crate::host::HostUpdate::UpdateMenuSlot`] and
+    /// [`crate::host::HostUpdate::UpdateBarSlot`].
+    pub fn slot(self, id: CustomId) -> Self {
+        ElemRepr::Slot(SlotRepr { id, elem: self }).into()
+    }
+
     pub fn fill_cells_single(symbol: impl fmt::Display) -> Self {
         ElemRepr::Fill(FillRepr {
             symbol: symbol.to_string(),
@@ -143,19 +534,77 @@ impl Elem {
         .into()
     }
 
+    /// Prints text at the position of the element, without taking up space. Control
+    /// characters and escape sequences other than SGR sequences (colors and text attributes)
+    /// are removed when rendering, since they could move the cursor or change the terminal's
+    /// state and break the whole panel.
     pub fn raw_print(raw: impl fmt::Display) -> Self {
         ElemRepr::Print(PrintRepr {
             raw: raw.to_string().into(),
+            trusted: false,
+        })
+        .into()
+    }
+
+    /// Like [`Self::raw_print`], but prints `raw` as is, including any escape sequences. Only
+    /// use it for output that is known to be well-behaved, e.g. kitty text sizing sequences
+    /// around text with its control characters removed.
+    pub fn trusted_raw_print(raw: impl fmt::Display) -> Self {
+        ElemRepr::Print(PrintRepr {
+            raw: raw.to_string().into(),
+            trusted: true,
+        })
+        .into()
+    }
+
+    /// Displays text containing SGR escape sequences (colors and text attributes), e.g. the
+    /// output of a CLI tool. The width of each line is calculated from its visible text.
+    ///
+    /// Other escape sequences, like ones that move the cursor, and control characters are
+    /// rejected. Tabs are expanded to spaces. Right-to-left text is reordered like in
+    /// [`Self::text`].
+    pub fn from_ansi(text: &str) -> Result<Self, AnsiError> {
+        super::ansi::parse(text)
+    }
+
+    /// Displays plain text, wrapped at the width of the area it is rendered in.
+    ///
+    /// On its own, the text is `width_hint` cells wide, and as high as the wrapped text.
+    /// Within a vertical stack, the height is calculated from the width of the stack instead,
+    /// so that the text can take up as many lines as it needs at that width. Control characters
+    /// other than newlines are removed.
+    pub fn wrapped_text(text: impl fmt::Display, width_hint: u16, mode: WrapMode) -> Self {
+        ElemRepr::Wrapped(WrappedRepr {
+            text: text.to_string(),
+            width_hint,
+            mode,
         })
         .into()
     }
 
+    /// Displays an image scaled to `len` cells along `axis`, keeping its aspect ratio.
+    pub fn image(image: Image, axis: Axis, len: u16) -> Self {
+        let min_axis = MinAxis {
+            axis,
+            len,
+            aspect_width: image.width(),
+            aspect_height: image.height(),
+        };
+        Self::from(ElemRepr::Image(ImageElemRepr { image, axis, len })).with_min_axis(min_axis)
+    }
+
+    /// Displays an SVG, rasterized at the pixel size of the cells it occupies.
+    pub fn svg(svg: Svg, mode: ImageSizeMode) -> Self {
+        ElemRepr::Svg(SvgElemRepr { svg, mode }).into()
+    }
+
     pub fn stack(
         axis: Axis,
         items: impl IntoIterator<Item: Into<StackItem>>,
         opts: impl Into<StackOpts>,
     ) -> Self {
         let StackOpts {
+            reverse,
             #[expect(deprecated)]
                 __non_exhaustive_struct_update: (),
         } = opts.into();
@@ -168,16 +617,128 @@ impl Elem {
                     opts:
                         StackItemOpts {
                             fill_weight,
+                            constraint,
                             #[expect(deprecated)]
                                 __non_exhaustive_struct_update: (),
                         },
                 } = item.into();
 
-                StackItemRepr { fill_weight, elem }
+                StackItemRepr {
+                    fill_weight,
+                    constraint,
+                    elem,
+                }
             })
             .collect();
 
-        ElemRepr::Stack(StackRepr { axis, items }).into()
+        ElemRepr::Stack(StackRepr {
+            axis,
+            reverse,
+            items,
+        })
+        .into()
+    }
+
+    /// Displays plain text with a style. Each line of the text is a row. Control characters
+    /// other than newlines are removed.
+    ///
+    /// Lines containing right-to-left scripts like Hebrew or Arabic are reordered for display.
+    /// If such a line starts with right-to-left text, it is cut off on the left if it does not
+    /// fit.
+    pub fn text(text: impl fmt::Display, style: TextStyle) -> Self {
+        ElemRepr::Text(TextRepr {
+            text: text.to_string(),
+            style,
+        })
+        .into()
+    }
+
+    /// Makes the tokens of `ctx` available to the [`Color::Token`]s in this element, so that
+    /// e.g. a theme can be changed in one place without rebuilding the elements that use it.
+    /// Tokens are resolved when rendering.
+    pub fn with_style_context(self, ctx: StyleContext) -> Self {
+        ElemRepr::StyleContext(StyleContextRepr { elem: self, ctx }).into()
+    }
+
+    /// Overrides the width in cells of specific graphemes in the text of this element, e.g.
+    /// for icons that the font draws wider than their Unicode width. Overrides of inner
+    /// elements take precedence. The graphemes are drawn centered in their cells using kitty's
+    /// text sizing protocol, see [`symbols::Symbol`].
+    pub fn with_glyph_widths(
+        self,
+        widths: impl IntoIterator<Item = (impl Into<String>, u8)>,
+    ) -> Self {
+        ElemRepr::GlyphWidths(GlyphWidthsRepr {
+            elem: self,
+            widths: widths
+                .into_iter()
+                .map(|(glyph, width)| (glyph.into(), width))
+                .collect(),
+        })
+        .into()
+    }
+
+    /// Plays `transition` when the element is shown, e.g. when a menu is opened or a module
+    /// appears on the bar. The transition is played again when `key` was not shown in the
+    /// previous frame, so changing the key replays it, e.g. to highlight the newly focused
+    /// workspace.
+    ///
+    /// The host draws transitions at up to [`crate::host::HostConnectOpts::max_fps`] frames
+    /// per second.
+    pub fn with_transition(self, key: CustomId, transition: Transition) -> Self {
+        let Transition {
+            kind,
+            duration,
+            #[expect(deprecated)]
+                __non_exhaustive_struct_update: (),
+        } = transition;
+        ElemRepr::Transition(TransitionRepr {
+            elem: self,
+            key,
+            kind,
+            duration_ms: duration.as_millis().try_into().unwrap_or(u32::MAX),
+        })
+        .into()
+    }
+
+    /// Shows or hides the element without changing its neighbors abruptly, see
+    /// [`HiddenSpace`]. The host measures the element even while it is hidden, so the
+    /// controller should keep sending its last content with `shown` set to `false` instead of
+    /// removing it.
+    ///
+    /// `key` identifies the element across frames. An element whose key was not shown in the
+    /// previous frame appears without animation.
+    pub fn collapsible(self, key: CustomId, shown: bool, space: HiddenSpace) -> Self {
+        ElemRepr::Collapsible(CollapsibleRepr {
+            elem: self,
+            key,
+            shown,
+            space: match space {
+                HiddenSpace::Reserve => HiddenSpaceRepr::Reserve,
+                HiddenSpace::Collapse { axis, duration } => HiddenSpaceRepr::Collapse {
+                    axis,
+                    duration_ms: duration.as_millis().try_into().unwrap_or(u32::MAX),
+                },
+            },
+        })
+        .into()
+    }
+
+    /// Positions `inner` within the area it is rendered in, instead of stretching it over the
+    /// whole area. The element is rendered at its minimum size.
+    pub fn aligned(inner: Elem, align: Align2D) -> Self {
+        ElemRepr::Aligned(AlignedRepr { elem: inner, align }).into()
+    }
+
+    /// Renders the layers on top of each other in the same area, later ones over earlier ones,
+    /// e.g. a label over a gauge. The size is the largest size of any layer.
+    ///
+    /// Where interactive elements overlap, the one in the highest layer receives the input.
+    pub fn overlay(layers: impl IntoIterator<Item = Elem>) -> Self {
+        ElemRepr::Overlay(OverlayRepr {
+            layers: layers.into_iter().collect(),
+        })
+        .into()
     }
 
     pub fn with_min_size(self, min_size: Size) -> Self {
@@ -206,4 +767,64 @@ impl Elem {
         })
         .into()
     }
+
+    /// Calls `f` with the tag of every interactive element and text input in the tree, including
+    /// the ones that are only shown while hovered or pressed. Tags may be repeated.
+    pub fn for_each_tag(&self, f: &mut impl FnMut(&CustomId)) {
+        match &*self.0 {
+            ElemRepr::Interact(InteractRepr {
+                tag,
+                normal,
+                hovered,
+                pressed,
+                widget: _,
+            }) => {
+                f(tag);
+                for elem in [Some(normal), hovered.as_ref(), pressed.as_ref()]
+                    .into_iter()
+                    .flatten()
+                {
+                    elem.for_each_tag(f);
+                }
+            }
+            ElemRepr::Input(InputRepr { tag, .. }) => f(tag),
+            ElemRepr::Stack(StackRepr { items, .. }) => {
+                for item in items {
+                    item.elem.for_each_tag(f);
+                }
+            }
+            ElemRepr::Overlay(OverlayRepr { layers }) => {
+                for layer in layers {
+                    layer.for_each_tag(f);
+                }
+            }
+            ElemRepr::Slot(SlotRepr { elem, .. })
+            | ElemRepr::MinSize(MinSizeRepr { elem, .. })
+            | ElemRepr::MinAxis(MinAxisRepr { elem, .. })
+            | ElemRepr::Aligned(AlignedRepr { elem, .. })
+            | ElemRepr::StyleContext(StyleContextRepr { elem, .. })
+            | ElemRepr::GlyphWidths(GlyphWidthsRepr { elem, .. })
+            | ElemRepr::Transition(TransitionRepr { elem, .. })
+            | ElemRepr::Collapsible(CollapsibleRepr { elem, .. }) => elem.for_each_tag(f),
+            ElemRepr::Print(_)
+            | ElemRepr::Text(_)
+            | ElemRepr::Fill(_)
+            | ElemRepr::Image(_)
+            | ElemRepr::Svg(_)
+            | ElemRepr::Wrapped(_)
+            | ElemRepr::Slider(_) => {}
+        }
+    }
+}
+
+fn radio_glyph(selected: bool) -> &'static str {
+    if selected { "(•)" } else { "( )" }
+}
+
+fn widget_hover_style() -> TextStyle {
+    TextStyle {
+        fg: Some(Color::token(StyleContext::ACCENT)),
+        bold: true,
+        ..Default::default()
+    }
 }
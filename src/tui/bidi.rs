@@ -0,0 +1,108 @@
+//! Support for right-to-left scripts. Terminals draw text from left to right, so lines
+//! containing Hebrew or Arabic are reordered from logical to visual order before drawing them,
+//! using the Unicode Bidirectional Algorithm. Every line is a paragraph of its own, which takes
+//! the direction of its first strong character.
+
+use unicode_bidi::ParagraphBidiInfo;
+
+/// Marks and overrides that only affect the order of the text. They are dropped from
+/// reordered lines.
+pub(crate) fn is_format_char(grapheme: &str) -> bool {
+    matches!(
+        grapheme.chars().next(),
+        Some(
+            '\u{200E}'
+            | '\u{200F}'
+            | '\u{061C}'
+            | '\u{202A}'..='\u{202E}'
+            | '\u{2066}'..='\u{2069}',
+        )
+    )
+}
+
+fn mirror(grapheme: &str) -> Option<char> {
+    Some(match grapheme {
+        "(" => ')',
+        ")" => '(',
+        "[" => ']',
+        "]" => '[',
+        "{" => '}',
+        "}" => '{',
+        "<" => '>',
+        ">" => '<',
+        "«" => '»',
+        "»" => '«',
+        _ => return None,
+    })
+}
+
+/// A line in visual order, see [`visual_order`].
+pub(crate) struct VisualOrder {
+    /// The indices of the graphemes from left to right, with the mirror image of brackets in
+    /// right-to-left text.
+    pub graphemes: Vec<(usize, Option<char>)>,
+    /// Whether the line starts with right-to-left text, so that it is read from the right.
+    #[cfg(feature = "__bin")]
+    pub rtl: bool,
+}
+
+/// The order to draw the graphemes of a line in, or `None` if it has no right-to-left text.
+pub(crate) fn visual_order(graphemes: &[&str]) -> Option<VisualOrder> {
+    let text = graphemes.concat();
+    let info = ParagraphBidiInfo::new(&text, None);
+    if !info.has_rtl() {
+        return None;
+    }
+    // The levels are per byte. Graphemes are moved as a whole, at the level of their first
+    // character.
+    let byte_levels = info.reordered_levels(0..text.len());
+    let mut start = 0;
+    let levels: Vec<_> = graphemes
+        .iter()
+        .map(|it| {
+            let level = byte_levels
+                .get(start)
+                .copied()
+                .unwrap_or(info.paragraph_level);
+            start += it.len();
+            level
+        })
+        .collect();
+
+    Some(VisualOrder {
+        graphemes: ParagraphBidiInfo::reorder_visual(&levels)
+            .into_iter()
+            .map(|i| {
+                (
+                    i,
+                    levels[i].is_rtl().then(|| mirror(graphemes[i])).flatten(),
+                )
+            })
+            .collect(),
+        #[cfg(feature = "__bin")]
+        rtl: info.paragraph_level.is_rtl(),
+    })
+}
+
+/// Reorders a line of plain text for drawing. Returns whether it is read from the right.
+#[cfg(feature = "__bin")]
+pub(crate) fn reorder(line: &str) -> (std::borrow::Cow<'_, str>, bool) {
+    let graphemes: Vec<_> =
+        unicode_segmentation::UnicodeSegmentation::graphemes(line, true).collect();
+    let Some(VisualOrder {
+        graphemes: order,
+        rtl,
+    }) = visual_order(&graphemes)
+    else {
+        return (line.into(), false);
+    };
+    let mut visual = String::with_capacity(line.len());
+    for (i, mirrored) in order {
+        match mirrored {
+            Some(c) => visual.push(c),
+            None if is_format_char(graphemes[i]) => {}
+            None => visual.push_str(graphemes[i]),
+        }
+    }
+    (visual.into(), rtl)
+}
@@ -0,0 +1,89 @@
+//! Downscaling of [`Image`]s that are much larger than the cells they are displayed in, so that
+//! terminals do not have to receive and store more pixels than they can show.
+
+use std::{
+    collections::HashMap,
+    num::NonZeroU32,
+    sync::{LazyLock, Mutex, MutexGuard},
+};
+
+use crate::tui::*;
+
+/// The number of downscaled images after which the cache is cleared, to bound its memory use.
+const MAX_CACHED: usize = 64;
+
+/// The downscaled images by the id of the original and the target size.
+type Cache = HashMap<(NonZeroU32, u32, u32), Image>;
+
+fn cache() -> MutexGuard<'static, Cache> {
+    static CACHE: LazyLock<Mutex<Cache>> = LazyLock::new(Default::default);
+    CACHE.lock().unwrap_or_else(|poison| poison.into_inner())
+}
+
+/// Returns a version of `image` that fits in `size` pixels, or `None` if it already fits.
+pub(super) fn fit(image: &Image, size: Vec2<u32>) -> Option<Image> {
+    let repr = &*image.0;
+    if size.x == 0 || size.y == 0 || (repr.width <= size.x && repr.height <= size.y) {
+        return None;
+    }
+    // Keep the aspect ratio
+    let scale = f64::min(
+        f64::from(size.x) / f64::from(repr.width),
+        f64::from(size.y) / f64::from(repr.height),
+    );
+    let target = Vec2 {
+        x: ((f64::from(repr.width) * scale).round() as u32).max(1),
+        y: ((f64::from(repr.height) * scale).round() as u32).max(1),
+    };
+
+    let key = (repr.id, target.x, target.y);
+    if let Some(image) = cache().get(&key) {
+        return Some(image.clone());
+    }
+    let scaled = Image::from_rgba(target.x, target.y, box_filter(repr, target))?;
+
+    let mut cache = cache();
+    if cache.len() >= MAX_CACHED {
+        cache.clear();
+    }
+    cache.insert(key, scaled.clone());
+    Some(scaled)
+}
+
+/// Averages the source pixels covered by each target pixel. Colors are weighted by alpha, so
+/// that transparent pixels do not darken the edges of icons.
+fn box_filter(src: &ImageRepr, target: Vec2<u32>) -> Vec<u8> {
+    let src_range = |i: u32, src_len: u32, target_len: u32| {
+        let start = u64::from(i) * u64::from(src_len) / u64::from(target_len);
+        let end = (u64::from(i + 1) * u64::from(src_len)).div_ceil(u64::from(target_len));
+        start as usize..end as usize
+    };
+
+    let mut rgba = Vec::with_capacity(target.x as usize * target.y as usize * 4);
+    for ty in 0..target.y {
+        let rows = src_range(ty, src.height, target.y);
+        for tx in 0..target.x {
+            let cols = src_range(tx, src.width, target.x);
+
+            let mut sum = [0u64; 4];
+            let mut count = 0u64;
+            for y in rows.clone() {
+                let row = y * src.width as usize * 4;
+                for pixel in src.rgba[row + cols.start * 4..row + cols.end * 4].chunks_exact(4) {
+                    let alpha = u64::from(pixel[3]);
+                    for c in 0..3 {
+                        sum[c] += u64::from(pixel[c]) * alpha;
+                    }
+                    sum[3] += alpha;
+                    count += 1;
+                }
+            }
+            let alpha = sum[3];
+            for &channel in &sum[..3] {
+                rgba.push(channel.checked_div(alpha).unwrap_or(0) as u8);
+            }
+            rgba.push(alpha.checked_div(count).unwrap_or(0) as u8);
+        }
+    }
+    rgba
+}
@@ -0,0 +1,325 @@
+//! A render target without a terminal: [`Grid`] interprets the output of [`render`] into a grid
+//! of cells, so that rendered content can be inspected, e.g. in bug reports.
+//!
+//! Only the escape sequences that the renderer produces are understood. Text attributes are
+//! kept as the raw SGR sequences that apply to a cell.
+
+use std::{num::NonZeroU32, sync::Arc};
+
+use unicode_segmentation::UnicodeSegmentation as _;
+
+use crate::tui::*;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Cell {
+    /// The grapheme in the cell. Empty for cells covered by a wide grapheme to their left and
+    /// for blank cells.
+    pub text: Box<str>,
+    /// The SGR sequences that were active when the text was printed, since the last reset.
+    pub sgr: Arc<str>,
+    /// The image placed on the cell, if any.
+    pub image: Option<NonZeroU32>,
+    /// Whether the cell is covered by a wide grapheme to its left.
+    pub continuation: bool,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Grid {
+    size: Vec2<u16>,
+    cells: Vec<Cell>,
+    cursor: Vec2<u16>,
+    sgr: Arc<str>,
+}
+impl Grid {
+    pub(crate) fn new(size: Vec2<u16>) -> Self {
+        Self {
+            size,
+            cells: vec![Cell::default(); usize::from(size.x) * usize::from(size.y)],
+            cursor: Vec2::default(),
+            sgr: "".into(),
+        }
+    }
+
+    pub(crate) fn size(&self) -> Vec2<u16> {
+        self.size
+    }
+
+    pub(crate) fn rows(&self) -> impl Iterator<Item = &[Cell]> {
+        self.cells.chunks(self.size.x.max(1).into())
+    }
+
+    fn index(&self, pos: Vec2<u16>) -> Option<usize> {
+        (pos.x < self.size.x && pos.y < self.size.y)
+            .then(|| usize::from(pos.y) * usize::from(self.size.x) + usize::from(pos.x))
+    }
+
+    /// The content of each row, with images as shaded cells. If `styled`, the text attributes
+    /// are included as SGR sequences, so that printing the result in a terminal shows the
+    /// content like the panel did.
+    pub(crate) fn to_text(&self, styled: bool) -> String {
+        let mut out = String::new();
+        for row in self.rows() {
+            let mut sgr = "";
+            for cell in row.iter().filter(|it| !it.continuation) {
+                if styled && *cell.sgr != *sgr {
+                    sgr = &cell.sgr;
+                    out.push_str("\x1b[0m");
+                    out.push_str(sgr);
+                }
+                out.push_str(match (&cell.image, &*cell.text) {
+                    (Some(_), _) => "▒",
+                    (None, "") => " ",
+                    (None, text) => text,
+                });
+            }
+            if styled && !sgr.is_empty() {
+                out.push_str("\x1b[0m");
+            }
+            out.truncate(out.trim_end_matches(' ').len());
+            out.push('\n');
+        }
+        out
+    }
+
+    /// The runs of cells with the same text attributes, excluding unstyled ones, as the row,
+    /// the range of columns and the SGR sequences.
+    #[cfg(feature = "testing")]
+    pub(crate) fn style_runs(&self) -> Vec<(u16, std::ops::Range<u16>, Arc<str>)> {
+        let mut runs = Vec::<(u16, std::ops::Range<u16>, Arc<str>)>::new();
+        for (y, row) in (0..).zip(self.rows()) {
+            for (x, cell) in (0..).zip(row) {
+                match runs.last_mut() {
+                    Some((run_y, cols, sgr))
+                        if *run_y == y
+                            && cols.end == x
+                            && (cell.continuation || *sgr == cell.sgr) =>
+                    {
+                        cols.end += 1;
+                    }
+                    _ if !cell.sgr.is_empty() => runs.push((y, x..x + 1, cell.sgr.clone())),
+                    _ => {}
+                }
+            }
+        }
+        runs
+    }
+
+    /// Applies output from the renderer.
+    pub(crate) fn apply(&mut self, bytes: &[u8]) {
+        let text = String::from_utf8_lossy(bytes);
+        let mut rest = &*text;
+        while !rest.is_empty() {
+            if let Some(seq) = rest.strip_prefix("\x1b[") {
+                let Some(len) = seq.find(|c: char| ('\x40'..='\x7e').contains(&c)) else {
+                    break;
+                };
+                self.apply_csi(&seq[..len], seq[len..].chars().next().unwrap_or_default());
+                rest = &seq[len + 1..];
+            } else if let Some(seq) = rest.strip_prefix("\x1b_") {
+                let len = seq.find("\x1b\\").unwrap_or(seq.len());
+                if let Some(control) = seq[..len].strip_prefix('G') {
+                    self.apply_graphics(control.split(';').next().unwrap_or_default());
+                }
+                rest = seq.get(len + 2..).unwrap_or_default();
+            } else if let Some(seq) = rest.strip_prefix("\x1b]") {
+                // OSC, e.g. hyperlinks. Terminated by BEL or ST
+                let len = seq.find(['\x07', '\x1b']).unwrap_or(seq.len());
+                // Text sizing, see `Elem::with_glyph_widths`
+                if let Some((meta, text)) = seq[..len]
+                    .strip_prefix("66;")
+                    .and_then(|it| it.split_once(';'))
+                {
+                    let width = meta
+                        .split(':')
+                        .find_map(|it| it.strip_prefix("w=")?.parse().ok());
+                    self.print(text, width);
+                }
+                let term_len = if seq[len..].starts_with('\x07') { 1 } else { 2 };
+                rest = seq.get(len + term_len..).unwrap_or_default();
+            } else if let Some(seq) = rest.strip_prefix('\x1b') {
+                rest = seq
+                    .get(seq.chars().next().map_or(0, char::len_utf8)..)
+                    .unwrap_or_default();
+            } else {
+                let grapheme = rest.graphemes(true).next().unwrap_or(rest);
+                self.print(grapheme, None);
+                rest = &rest[grapheme.len()..];
+            }
+        }
+    }
+
+    /// Prints a grapheme across `width` cells, or as many as it is wide.
+    fn print(&mut self, grapheme: &str, width: Option<usize>) {
+        match grapheme {
+            "\r" => self.cursor.x = 0,
+            "\n" | "\r\n" => {
+                self.cursor.x = 0;
+                self.cursor.y = self.cursor.y.saturating_add(1);
+            }
+            _ if grapheme.starts_with(char::is_control) => {}
+            _ => {
+                let width = width.unwrap_or_else(|| {
+                    super::width::grapheme_width(grapheme, AmbiguousWidth::Narrow)
+                });
+                let Some(idx) = self.index(self.cursor) else {
+                    // Printing past the edge is clipped, since panels do not wrap
+                    return;
+                };
+                self.cells[idx] = Cell {
+                    text: grapheme.into(),
+                    sgr: self.sgr.clone(),
+                    image: self.cells[idx].image,
+                    continuation: false,
+                };
+                for i in 1..width {
+                    let pos = Vec2 {
+                        x: self.cursor.x.saturating_add(i as u16),
+                        y: self.cursor.y,
+                    };
+                    if let Some(idx) = self.index(pos) {
+                        self.cells[idx] = Cell {
+                            continuation: true,
+                            ..Default::default()
+                        };
+                    }
+                }
+                self.cursor.x = self.cursor.x.saturating_add(width as u16);
+            }
+        }
+    }
+
+    fn apply_csi(&mut self, params: &str, fin: char) {
+        let num = |i: usize| {
+            params
+                .split(';')
+                .nth(i)
+                .and_then(|it| it.parse::<u16>().ok())
+                .unwrap_or(1)
+        };
+        match fin {
+            'H' | 'f' => {
+                self.cursor = Vec2 {
+                    x: num(1).saturating_sub(1),
+                    y: num(0).saturating_sub(1),
+                };
+            }
+            'J' => {
+                let from = match params {
+                    "" | "0" => self.index(self.cursor).unwrap_or(self.cells.len()),
+                    _ => 0,
+                };
+                self.cells[from..].fill(Cell::default());
+            }
+            'm' if matches!(params, "" | "0") => self.sgr = "".into(),
+            'm' => self.sgr = format!("{}\x1b[{params}m", self.sgr).into(),
+            // Cursor visibility and synchronized updates do not change the content
+            _ => {}
+        }
+    }
+
+    fn apply_graphics(&mut self, control: &str) {
+        let mut action = 't';
+        let mut delete = 'a';
+        let (mut id, mut cols, mut rows, mut x, mut y) = (None, 1, 1, 0, 0);
+        for (key, value) in control.split(',').filter_map(|it| it.split_once('=')) {
+            let num = || value.parse::<u32>().ok();
+            match key {
+                "a" => action = value.chars().next().unwrap_or(action),
+                "d" => delete = value.chars().next().unwrap_or(delete),
+                "i" => id = num().and_then(NonZeroU32::new),
+                "c" => cols = num().unwrap_or(cols),
+                "r" => rows = num().unwrap_or(rows),
+                "x" => x = num().unwrap_or(x),
+                "y" => y = num().unwrap_or(y),
+                _ => {}
+            }
+        }
+        match (action, delete) {
+            ('p', _) => {
+                for dy in 0..rows {
+                    for dx in 0..cols {
+                        let pos = Vec2 {
+                            x: self
+                                .cursor
+                                .x
+                                .saturating_add(dx.try_into().unwrap_or(u16::MAX)),
+                            y: self
+                                .cursor
+                                .y
+                                .saturating_add(dy.try_into().unwrap_or(u16::MAX)),
+                        };
+                        if let Some(idx) = self.index(pos) {
+                            self.cells[idx].image = id;
+                        }
+                    }
+                }
+            }
+            ('d', 'a' | 'A') => self.cells.iter_mut().for_each(|it| it.image = None),
+            ('d', 'p' | 'P') => {
+                // 1-based
+                let pos = Vec2 {
+                    x: x.saturating_sub(1).try_into().unwrap_or(u16::MAX),
+                    y: y.saturating_sub(1).try_into().unwrap_or(u16::MAX),
+                };
+                if let Some(idx) = self.index(pos) {
+                    self.cells[idx].image = None;
+                }
+            }
+            ('d', 'i' | 'I') => {
+                for cell in &mut self.cells {
+                    cell.image = cell.image.filter(|it| Some(*it) != id);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Renders `elem` into a new grid of the given size.
+pub(crate) fn render_headless(
+    elem: &Elem,
+    size: Vec2<u16>,
+    sizing: &SizingArgs,
+) -> std::io::Result<Grid> {
+    let mut buf = Vec::new();
+    render(
+        elem,
+        Area {
+            pos: Vec2::default(),
+            size,
+        },
+        &mut buf,
+        sizing,
+        &RenderedLayout {
+            finish_transitions: true,
+            ..Default::default()
+        },
+    )?;
+    let mut grid = Grid::new(size);
+    grid.apply(&buf);
+    Ok(grid)
+}
+
+/// Renders `elem` into a layout whose geometry can be checked with
+/// [`RenderedLayout::check_invariants`].
+#[cfg(feature = "testing")]
+pub(crate) fn render_checked(
+    elem: &Elem,
+    size: Vec2<u16>,
+    sizing: &SizingArgs,
+) -> std::io::Result<RenderedLayout> {
+    render(
+        elem,
+        Area {
+            pos: Vec2::default(),
+            size,
+        },
+        &mut std::io::sink(),
+        sizing,
+        &RenderedLayout {
+            finish_transitions: true,
+            check_sizes: true,
+            ..Default::default()
+        },
+    )
+}
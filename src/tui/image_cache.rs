@@ -0,0 +1,76 @@
+use std::{collections::HashMap, io::Write, num::NonZeroU32};
+
+/// The number of images that the terminal keeps after they are no longer placed, so that e.g.
+/// the frames of an animation do not have to be transmitted again on every cycle.
+const MAX_UNUSED_IMAGES: usize = 16;
+
+/// Tracks the images that were transmitted to a terminal.
+///
+/// Images are identified by a hash of their content, so identical images are only transmitted
+/// once. Images that are no longer placed are kept up to [`MAX_UNUSED_IMAGES`], after which the
+/// least recently used ones are deleted from the terminal to free their memory.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct ImageCache {
+    /// The generation in which each image was last placed.
+    last_used: HashMap<NonZeroU32, u64>,
+    generation: u64,
+}
+impl ImageCache {
+    /// Marks an image as used, returning `true` if it needs to be transmitted.
+    pub(super) fn insert(&mut self, id: NonZeroU32) -> bool {
+        self.last_used.insert(id, self.generation).is_none()
+    }
+
+    /// Deletes the least recently used images that are not in `placed` from the terminal, if
+    /// there are too many of them.
+    pub(super) fn evict(
+        &mut self,
+        placed: impl IntoIterator<Item = NonZeroU32>,
+        writer: &mut impl Write,
+    ) -> std::io::Result<()> {
+        for id in placed {
+            if let Some(used) = self.last_used.get_mut(&id) {
+                *used = self.generation;
+            }
+        }
+        let mut unused = self
+            .last_used
+            .iter()
+            .filter(|&(_, &used)| used != self.generation)
+            .map(|(&id, &used)| (used, id))
+            .collect::<Vec<_>>();
+        self.generation += 1;
+
+        let Some(excess) = unused.len().checked_sub(MAX_UNUSED_IMAGES) else {
+            return Ok(());
+        };
+        unused.sort_unstable();
+        for &(_, id) in &unused[..excess] {
+            self.last_used.remove(&id);
+            write_deletion(writer, id)?;
+        }
+        Ok(())
+    }
+}
+
+const DELETION_START: &[u8] = b"\x1b_Ga=d,d=I,";
+
+fn write_deletion(writer: &mut impl Write, id: NonZeroU32) -> std::io::Result<()> {
+    // - d=I: Delete the image with the id, including its data
+    writer.write_all(DELETION_START)?;
+    write!(writer, "i={id},q=2\x1b\\")
+}
+
+/// Finds the images deleted by [`ImageCache::evict`] in rendered output.
+pub(crate) fn image_deletions(buf: &[u8]) -> Vec<NonZeroU32> {
+    buf.windows(DELETION_START.len())
+        .enumerate()
+        .filter(|&(_, it)| it == DELETION_START)
+        .filter_map(|(start, _)| {
+            let control = &buf[start + DELETION_START.len()..];
+            let digits = control.strip_prefix(b"i=")?;
+            let len = digits.iter().take_while(|b| b.is_ascii_digit()).count();
+            std::str::from_utf8(&digits[..len]).ok()?.parse().ok()
+        })
+        .collect()
+}
@@ -0,0 +1,318 @@
+//! Editing the text of [`Elem::text_input`]s. This happens in the host, so that typing does not
+//! wait for the controller to send the new content.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use unicode_width::UnicodeWidthChar as _;
+
+use crate::tui::*;
+
+#[derive(Debug, Clone)]
+pub(crate) struct InputState {
+    /// The text of the element that the state was created from. When the controller sends a
+    /// different text, the edits are discarded.
+    initial: String,
+    text: String,
+    /// Byte offset of the cursor.
+    cursor: usize,
+    /// Byte offset of the other end of the selection, if any.
+    anchor: Option<usize>,
+    /// The number of columns scrolled out of view on the left.
+    scroll: usize,
+}
+
+/// What a key press on a focused text input did.
+pub(crate) enum InputRes {
+    Changed {
+        tag: CustomId,
+        text: String,
+    },
+    Submitted {
+        tag: CustomId,
+        text: String,
+    },
+    /// Only the cursor, selection or focus changed.
+    Redraw,
+    Ignored,
+}
+
+/// How a part of a rendered text input is drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum InputSegment {
+    Text,
+    Selected,
+    Cursor,
+    Placeholder,
+}
+
+impl InputState {
+    fn new(text: &str) -> Self {
+        Self {
+            initial: text.into(),
+            text: text.into(),
+            cursor: text.len(),
+            anchor: None,
+            scroll: 0,
+        }
+    }
+
+    fn selection(&self) -> Option<(usize, usize)> {
+        let anchor = self.anchor.filter(|&it| it != self.cursor)?;
+        Some((anchor.min(self.cursor), anchor.max(self.cursor)))
+    }
+
+    fn prev_boundary(&self, pos: usize) -> usize {
+        self.text[..pos]
+            .char_indices()
+            .next_back()
+            .map_or(0, |(i, _)| i)
+    }
+
+    fn next_boundary(&self, pos: usize) -> usize {
+        self.text[pos..]
+            .chars()
+            .next()
+            .map_or(pos, |c| pos + c.len_utf8())
+    }
+
+    /// Removes the selected text. Returns whether there was a selection.
+    fn delete_selection(&mut self) -> bool {
+        let Some((start, end)) = self.selection() else {
+            return false;
+        };
+        self.text.replace_range(start..end, "");
+        self.cursor = start;
+        self.anchor = None;
+        true
+    }
+
+    /// Moves the cursor to `pos`, extending the selection if `select` is set.
+    fn move_to(&mut self, pos: usize, select: bool) {
+        if select {
+            self.anchor.get_or_insert(self.cursor);
+        } else {
+            self.anchor = None;
+        }
+        self.cursor = pos;
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> KeyOutcome {
+        let KeyEvent {
+            code,
+            modifiers,
+            kind,
+            state: _,
+        } = key;
+        if kind == KeyEventKind::Release {
+            return KeyOutcome::Ignored;
+        }
+        let select = modifiers.contains(KeyModifiers::SHIFT);
+        let ctrl = modifiers.contains(KeyModifiers::CONTROL);
+        match code {
+            KeyCode::Char('a') if ctrl => {
+                self.anchor = Some(0);
+                self.cursor = self.text.len();
+                KeyOutcome::Moved
+            }
+            KeyCode::Char(c) if !ctrl && !modifiers.contains(KeyModifiers::ALT) => {
+                self.delete_selection();
+                self.text.insert(self.cursor, c);
+                self.cursor += c.len_utf8();
+                KeyOutcome::Changed
+            }
+            KeyCode::Backspace => {
+                if !self.delete_selection() {
+                    if self.cursor == 0 {
+                        return KeyOutcome::Ignored;
+                    }
+                    let start = self.prev_boundary(self.cursor);
+                    self.text.replace_range(start..self.cursor, "");
+                    self.cursor = start;
+                }
+                KeyOutcome::Changed
+            }
+            KeyCode::Delete => {
+                if !self.delete_selection() {
+                    if self.cursor == self.text.len() {
+                        return KeyOutcome::Ignored;
+                    }
+                    let end = self.next_boundary(self.cursor);
+                    self.text.replace_range(self.cursor..end, "");
+                }
+                KeyOutcome::Changed
+            }
+            KeyCode::Left => {
+                let pos = match self.selection() {
+                    Some((start, _)) if !select => start,
+                    _ => self.prev_boundary(self.cursor),
+                };
+                self.move_to(pos, select);
+                KeyOutcome::Moved
+            }
+            KeyCode::Right => {
+                let pos = match self.selection() {
+                    Some((_, end)) if !select => end,
+                    _ => self.next_boundary(self.cursor),
+                };
+                self.move_to(pos, select);
+                KeyOutcome::Moved
+            }
+            KeyCode::Home => {
+                self.move_to(0, select);
+                KeyOutcome::Moved
+            }
+            KeyCode::End => {
+                self.move_to(self.text.len(), select);
+                KeyOutcome::Moved
+            }
+            KeyCode::Enter => KeyOutcome::Submitted,
+            KeyCode::Esc => KeyOutcome::Unfocus,
+            _ => KeyOutcome::Ignored,
+        }
+    }
+
+    /// Places the cursor at the character under column `col` of the visible text.
+    fn click(&mut self, col: u16) {
+        let target = self.scroll + usize::from(col);
+        let mut width = 0;
+        let pos = self
+            .text
+            .char_indices()
+            .find(|&(_, c)| {
+                width += c.width().unwrap_or(0);
+                width > target
+            })
+            .map_or(self.text.len(), |(i, _)| i);
+        self.move_to(pos, false);
+    }
+
+    /// Splits the visible part of the text into segments, scrolling so that the cursor is
+    /// visible in `width` columns.
+    pub(super) fn segments(
+        &mut self,
+        width: u16,
+        focused: bool,
+        placeholder: &str,
+    ) -> Vec<(InputSegment, String)> {
+        let width = usize::from(width);
+        if self.text.is_empty() && !focused {
+            return vec![(InputSegment::Placeholder, placeholder.into())];
+        }
+
+        let cursor_col: usize = self.text[..self.cursor]
+            .chars()
+            .map(|c| c.width().unwrap_or(0))
+            .sum();
+        // The cursor needs a cell of its own at the end of the text
+        if cursor_col < self.scroll {
+            self.scroll = cursor_col;
+        } else if cursor_col >= self.scroll + width {
+            self.scroll = (cursor_col + 1).saturating_sub(width);
+        }
+
+        let selection = self.selection().filter(|_| focused);
+        let mut segments: Vec<(InputSegment, String)> = Vec::new();
+        let mut col = 0;
+        for (i, c) in self.text.char_indices() {
+            let start = col;
+            col += c.width().unwrap_or(0);
+            if start < self.scroll {
+                continue;
+            }
+            if col - self.scroll > width {
+                break;
+            }
+            let segment = if selection.is_some_and(|(start, end)| (start..end).contains(&i)) {
+                InputSegment::Selected
+            } else if focused && selection.is_none() && i == self.cursor {
+                InputSegment::Cursor
+            } else {
+                InputSegment::Text
+            };
+            match segments.last_mut() {
+                Some((last, text)) if *last == segment => text.push(c),
+                _ => segments.push((segment, c.into())),
+            }
+        }
+        if focused && selection.is_none() && self.cursor == self.text.len() {
+            segments.push((InputSegment::Cursor, " ".into()));
+        }
+        segments
+    }
+}
+
+enum KeyOutcome {
+    Changed,
+    Submitted,
+    Moved,
+    Unfocus,
+    Ignored,
+}
+
+impl RenderedLayout {
+    /// The state of the input with the given tag, reset if the controller changed its text.
+    pub(super) fn input_state(&mut self, tag: &CustomId, text: &str) -> &mut InputState {
+        let state = self
+            .inputs
+            .entry(tag.clone())
+            .or_insert_with(|| InputState::new(text));
+        if state.initial != text {
+            *state = InputState::new(text);
+        }
+        state
+    }
+
+    /// Drops the state of the inputs that are no longer shown.
+    pub(super) fn forget_hidden_inputs(&mut self) {
+        let shown = |tag: &CustomId| self.input_areas.iter().any(|(_, it)| it == tag);
+        self.inputs.retain(|tag, _| shown(tag));
+        if self.focused_input.as_ref().is_some_and(|it| !shown(it)) {
+            self.focused_input = None;
+        }
+    }
+
+    /// Focuses the text input with the given tag. The focus is dropped by the next render if
+    /// the input is not shown.
+    pub(crate) fn focus_input(&mut self, tag: CustomId) {
+        self.focused_input = Some(tag);
+    }
+
+    /// Sends a key press to the focused text input. Returns `None` if no input is focused.
+    pub(crate) fn interpret_key_event(&mut self, key: KeyEvent) -> Option<InputRes> {
+        let tag = self.focused_input.clone()?;
+        let state = self.inputs.get_mut(&tag)?;
+        Some(match state.handle_key(key) {
+            KeyOutcome::Changed => InputRes::Changed {
+                tag,
+                text: state.text.clone(),
+            },
+            KeyOutcome::Submitted => InputRes::Submitted {
+                tag,
+                text: state.text.clone(),
+            },
+            KeyOutcome::Moved => InputRes::Redraw,
+            KeyOutcome::Unfocus => {
+                self.focused_input = None;
+                InputRes::Redraw
+            }
+            KeyOutcome::Ignored => InputRes::Ignored,
+        })
+    }
+
+    /// Focuses the text input at `pos` and places its cursor there, or removes the focus if
+    /// there is none. Returns whether the inputs need to be redrawn.
+    pub(super) fn click_input(&mut self, pos: Vec2<u16>) -> bool {
+        let Some((area, tag)) = self
+            .input_areas
+            .iter()
+            .rfind(|(area, _)| area.contains(pos))
+            .cloned()
+        else {
+            return self.focused_input.take().is_some();
+        };
+        if let Some(state) = self.inputs.get_mut(&tag) {
+            state.click(pos.x - area.pos.x);
+        }
+        self.focused_input = Some(tag);
+        true
+    }
+}
@@ -8,6 +8,23 @@ pub(crate) struct Area {
     pub size: Vec2<u16>,
 }
 impl Area {
+    pub(crate) fn contains_area(self, other: Area) -> bool {
+        let Area { pos, size } = other;
+        self.contains(pos)
+            && (size.x == 0 && size.y == 0
+                || self.contains(Vec2 {
+                    x: pos.x.saturating_add(size.x).saturating_sub(1),
+                    y: pos.y.saturating_add(size.y).saturating_sub(1),
+                }))
+    }
+    /// Whether the areas share a cell.
+    pub(crate) fn intersects(self, other: Area) -> bool {
+        let overlaps = |axis: Axis| {
+            self.pos[axis] < other.pos[axis].saturating_add(other.size[axis])
+                && other.pos[axis] < self.pos[axis].saturating_add(self.size[axis])
+        };
+        overlaps(Axis::X) && overlaps(Axis::Y)
+    }
     pub(crate) fn contains(self, pos: Vec2<u16>) -> bool {
         pos.x
             .checked_sub(self.pos.x)
@@ -23,20 +40,164 @@ impl Area {
 pub(super) struct StoredInteractive {
     tag: CustomId,
     has_hover: bool,
+    has_pressed: bool,
+    /// The value of a slider is updated when it reports a change, so that scrolling adds up and
+    /// dragging only reports moves of the handle until the next render.
+    widget: Option<WidgetRepr>,
+    /// An enlarged area that receives events not hitting any element, see
+    /// [`RenderedLayout::check_interactive_sizes`].
+    hitbox: Option<Area>,
 }
 impl StoredInteractive {
     pub(crate) fn new(elem: &InteractRepr) -> Self {
         Self {
             has_hover: elem.hovered.is_some(),
+            has_pressed: elem.pressed.is_some(),
             tag: elem.tag.clone(),
+            widget: elem.widget,
+            hitbox: None,
+        }
+    }
+
+    /// The kind of a left click on the element, without a position.
+    fn click_kind(&self) -> InteractKind {
+        match self.widget {
+            Some(WidgetRepr::Checkbox { checked }) => InteractKind::Toggle(!checked),
+            Some(WidgetRepr::Radio { .. }) => InteractKind::Toggle(true),
+            Some(WidgetRepr::Slider { .. }) | None => InteractKind::Click(MouseButton::Left),
+        }
+    }
+
+    /// Translates an interaction at column `x` into what the widget reports.
+    fn widget_kind(&mut self, kind: InteractKind, area: Area, x: u16) -> InteractKind {
+        match (&mut self.widget, kind) {
+            (
+                Some(WidgetRepr::Checkbox { .. } | WidgetRepr::Radio { .. }),
+                InteractKind::Click(MouseButton::Left),
+            ) => self.click_kind(),
+            (
+                Some(WidgetRepr::Slider { value, min, max }),
+                InteractKind::Click(MouseButton::Left),
+            ) => {
+                *value = slider_value(area, x, *min, *max);
+                InteractKind::ValueChange(*value)
+            }
+            (Some(WidgetRepr::Slider { value, min, max }), InteractKind::Scroll(dir)) => {
+                let step = (*max - *min) / 20.0;
+                let delta = match dir {
+                    Direction::Up | Direction::Right => step,
+                    Direction::Down | Direction::Left => -step,
+                };
+                *value = (*value + delta).clamp(min.min(*max), max.max(*min));
+                InteractKind::ValueChange(*value)
+            }
+            (_, kind) => kind,
         }
     }
 }
+
+/// The value of a slider at column `x`, clamped to the track.
+fn slider_value(area: Area, x: u16, min: f32, max: f32) -> f32 {
+    let steps = area.size.x.saturating_sub(1);
+    if steps == 0 {
+        return min;
+    }
+    let frac = f32::from(x.saturating_sub(area.pos.x).min(steps)) / f32::from(steps);
+    min + frac * (max - min)
+}
 #[derive(Debug, Default)]
 pub(crate) struct RenderedLayout {
     pub(super) widgets: Vec<(Area, StoredInteractive)>,
+    pub(super) slots: Vec<(Area, CustomId)>,
+    /// The output of each element without children, in render order.
+    pub(super) leaves: Vec<(Area, Vec<u8>)>,
+    /// Whether the terminal still shows the leaves, i.e. whether the next render can diff.
+    pub(super) diffable: bool,
+    pub(super) diffs_since_full: u32,
+    /// The images that were transmitted to the terminal.
+    pub(super) images: ImageCache,
+    /// The images placed by the rendered content.
+    pub(super) placements: Vec<(Area, std::num::NonZeroU32)>,
     pub(super) last_mouse_pos: Option<Vec2<u16>>,
     pub(super) last_hover_elem: Option<StoredInteractive>,
+    /// The slider that the left mouse button was pressed on, which receives the drag events
+    /// until it is released.
+    pub(super) dragging: Option<CustomId>,
+    /// The element that the left mouse button is held down on, if it has a pressed state.
+    pub(super) pressed: Option<CustomId>,
+    /// The editing state of the text inputs, by tag. Kept across renders.
+    pub(super) inputs: std::collections::HashMap<CustomId, InputState>,
+    pub(super) input_areas: Vec<(Area, CustomId)>,
+    /// The text input that receives key presses.
+    pub(super) focused_input: Option<CustomId>,
+    /// When the transitions of the shown elements started, by key.
+    pub(super) transitions: std::collections::HashMap<CustomId, std::time::Instant>,
+    /// Whether a transition is still running, so that another frame is needed.
+    pub(super) animating: bool,
+    /// The sizes of the shown [`Elem::collapsible`]s, by key.
+    pub(super) collapses: std::collections::HashMap<CustomId, CollapseState>,
+    /// Draws transitions in their final state instead, for snapshots.
+    pub(super) finish_transitions: bool,
+    /// The areas of the rendered [`Elem::overlay`]s, within which leaves may overlap.
+    pub(super) overlays: Vec<Area>,
+    /// Whether to record [`Self::undersized`], which is costly since it measures every
+    /// element. Only used by [`Self::check_invariants`].
+    pub(super) check_sizes: bool,
+    /// The areas of elements that are smaller than their min size, although their parent is
+    /// not.
+    pub(super) undersized: Vec<Area>,
+    /// Draws the text faint, see [`Self::set_dimmed`].
+    pub(super) dimmed: bool,
+    /// Highlights and labels the interactive elements, see [`Self::set_showing_hints`].
+    pub(super) hints: bool,
+}
+
+/// How far an [`Elem::collapsible`] has grown or shrunk towards its current visibility.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct CollapseState {
+    shown: bool,
+    /// When the element started growing or shrinking.
+    since: std::time::Instant,
+    duration: std::time::Duration,
+}
+impl CollapseState {
+    /// The fraction of its full size that the element takes up at `now`.
+    pub(super) fn fraction(&self, now: std::time::Instant) -> f32 {
+        let t = if self.duration.is_zero() {
+            1.0
+        } else {
+            let elapsed = now.saturating_duration_since(self.since);
+            (elapsed.as_secs_f32() / self.duration.as_secs_f32()).min(1.0)
+        };
+        if self.shown { t } else { 1.0 - t }
+    }
+
+    /// The state after the element is rendered with `shown` at `now`. A change of `shown`
+    /// continues from the current size.
+    pub(super) fn update(
+        prev: Option<Self>,
+        shown: bool,
+        duration: std::time::Duration,
+        now: std::time::Instant,
+    ) -> Self {
+        let since = match prev {
+            Some(prev) if prev.shown == shown => prev.since,
+            Some(prev) => {
+                let done = if shown {
+                    prev.fraction(now)
+                } else {
+                    1.0 - prev.fraction(now)
+                };
+                now.checked_sub(duration.mul_f32(done)).unwrap_or(now)
+            }
+            None => now.checked_sub(duration).unwrap_or(now),
+        };
+        Self {
+            shown,
+            since,
+            duration,
+        }
+    }
 }
 
 pub(crate) struct MouseInteractRes {
@@ -50,18 +211,113 @@ pub(crate) enum MouseEventRes {
     MouseLeave,
 }
 
+/// Keys used to label interactive elements, in layout order.
+pub(super) const HINT_KEYS: &[u8] = b"123456789abcdefghijklmnopqrstuvwxyz";
+
 impl RenderedLayout {
+    /// The tag of the interactive element labeled with `key` when showing interactive elements,
+    /// and the kind of a left click on it. Only the first [`HINT_KEYS`] elements have a label.
+    pub(crate) fn hint_target(&self, key: char) -> Option<(CustomId, InteractKind)> {
+        let idx = HINT_KEYS.iter().position(|&it| char::from(it) == key)?;
+        self.widgets
+            .get(idx)
+            .map(|(_, it)| (it.tag.clone(), it.click_kind()))
+    }
+
     pub(super) fn insert(&mut self, area: Area, elem: &InteractRepr) {
         self.widgets.push((area, StoredInteractive::new(elem)));
     }
 
+    /// Whether the layout should be rendered again after the frame interval to advance its
+    /// transitions.
+    pub(crate) fn is_animating(&self) -> bool {
+        self.animating
+    }
+
+    pub(crate) fn slot_area(&self, id: &CustomId) -> Option<Area> {
+        self.slots
+            .iter()
+            .find(|(_, it)| it == id)
+            .map(|&(area, _)| area)
+    }
+
+    /// Makes the following renders draw the text faint (SGR 2), keeping its colors, e.g. the bar
+    /// while a menu is open. The leaves that change are redrawn like any others.
+    pub(crate) fn set_dimmed(&mut self, dimmed: bool) {
+        self.dimmed = dimmed;
+    }
+
+    /// Makes the following renders highlight the interactive elements in reverse video and
+    /// label them with their [`Self::hint_target`] key.
+    pub(crate) fn set_showing_hints(&mut self, shown: bool) {
+        self.hints = shown;
+    }
+
+    /// Makes the next [`render`] redraw the whole terminal, e.g. because the font size changed.
+    pub(crate) fn force_full_redraw(&mut self) {
+        self.diffable = false;
+    }
+
+    /// Forgets the rendered content, but not which images the terminal has.
+    pub(crate) fn reset(&mut self) {
+        *self = Self {
+            images: std::mem::take(&mut self.images),
+            ..Default::default()
+        };
+    }
+
     pub(crate) fn ext_focus_loss(&mut self) -> bool {
-        let changed = self.last_hover_elem.as_ref().is_some_and(|it| it.has_hover);
+        let changed = self.last_hover_elem.as_ref().is_some_and(|it| it.has_hover)
+            | self.pressed.take().is_some();
         self.last_mouse_pos = None;
         self.last_hover_elem = None;
         changed
     }
 
+    /// Finds the interactive elements smaller than `min`. If `expand` is set, their hitboxes are
+    /// grown to `min` (as far as `bounds` allows), centered on the element.
+    pub(crate) fn check_interactive_sizes(
+        &mut self,
+        min: Vec2<u16>,
+        bounds: Area,
+        expand: bool,
+    ) -> Vec<(CustomId, Vec2<u16>)> {
+        fn grow(pos: u16, len: u16, min: u16, start: u16, bound: u16) -> (u16, u16) {
+            if len >= min {
+                return (pos, len);
+            }
+            let end = start.saturating_add(bound);
+            let grown = min.min(bound);
+            let pos = pos
+                .saturating_sub(grown.saturating_sub(len) / 2)
+                .min(end.saturating_sub(grown))
+                .max(start);
+            (pos, grown)
+        }
+
+        let mut small = Vec::new();
+        for (area, elem) in &mut self.widgets {
+            if area.size.x >= min.x && area.size.y >= min.y {
+                continue;
+            }
+            small.push((elem.tag.clone(), area.size));
+            if expand {
+                let (x, w) = grow(area.pos.x, area.size.x, min.x, bounds.pos.x, bounds.size.x);
+                let (y, h) = grow(area.pos.y, area.size.y, min.y, bounds.pos.y, bounds.size.y);
+                elem.hitbox = Some(Area {
+                    pos: Vec2 { x, y },
+                    size: Vec2 { x: w, y: h },
+                });
+            }
+        }
+        small
+    }
+
+    /// Whether a slider is being dragged, see [`Elem::slider`].
+    pub(crate) fn is_dragging(&self) -> bool {
+        self.dragging.is_some()
+    }
+
     pub(crate) fn get_pix_location(
         &self,
         font_size: Vec2<u16>,
@@ -79,6 +335,117 @@ impl RenderedLayout {
             })
     }
 
+    /// The index of the interactive element that receives the mouse events at `pos`.
+    fn widget_at(&self, pos: Vec2<u16>) -> Option<usize> {
+        // Later widgets are drawn over earlier ones, see [`Elem::overlay`]
+        self.widgets
+            .iter()
+            .rposition(|(r, _)| r.contains(pos))
+            .or_else(|| {
+                self.widgets
+                    .iter()
+                    .position(|(_, it)| it.hitbox.is_some_and(|hitbox| hitbox.contains(pos)))
+            })
+    }
+
+    /// The area and tag of the interactive element that receives the mouse events at `pos`.
+    #[cfg(feature = "testing")]
+    pub(crate) fn hit_test(&self, pos: Vec2<u16>) -> Option<(Area, &CustomId)> {
+        self.widget_at(pos)
+            .map(|idx| (self.widgets[idx].0, &self.widgets[idx].1.tag))
+    }
+
+    /// Checks the geometry of a layout that was rendered into `area`:
+    /// - Everything is placed within `area`.
+    /// - Leaves only overlap within an [`Elem::overlay`].
+    /// - Elements are at least as large as their min size, unless their parent is not. Only
+    ///   checked if the layout was rendered by [`render_checked`].
+    /// - Mouse events hit an interactive element that contains them, if there is one.
+    #[cfg(feature = "testing")]
+    pub(crate) fn check_invariants(&self, area: Area) -> Result<(), String> {
+        let nonempty = |it: &Area| it.size.x > 0 && it.size.y > 0;
+        let placed = (self.leaves.iter().map(|(it, _)| ("leaf", it)))
+            .chain(
+                self.widgets
+                    .iter()
+                    .map(|(it, _)| ("interactive element", it)),
+            )
+            .chain(self.slots.iter().map(|(it, _)| ("slot", it)))
+            .chain(self.input_areas.iter().map(|(it, _)| ("input", it)))
+            .chain(self.placements.iter().map(|(it, _)| ("image", it)));
+        for (kind, it) in placed.filter(|(_, it)| nonempty(it)) {
+            if !area.contains_area(*it) {
+                return Err(format!("The {kind} at {it:?} is outside of {area:?}"));
+            }
+        }
+
+        let leaves = Vec::from_iter(self.leaves.iter().map(|&(it, _)| it).filter(nonempty));
+        for (i, a) in leaves.iter().enumerate() {
+            for b in leaves[i + 1..].iter().filter(|b| a.intersects(**b)) {
+                if !self
+                    .overlays
+                    .iter()
+                    .any(|it| it.contains_area(*a) && it.contains_area(*b))
+                {
+                    return Err(format!(
+                        "The leaves at {a:?} and {b:?} overlap outside of an overlay"
+                    ));
+                }
+            }
+        }
+
+        if let Some(it) = self.undersized.first() {
+            return Err(format!(
+                "The element at {it:?} is smaller than its min size, although its parent is not"
+            ));
+        }
+
+        for y in area.pos.y..area.pos.y.saturating_add(area.size.y) {
+            for x in area.pos.x..area.pos.x.saturating_add(area.size.x) {
+                let pos = Vec2 { x, y };
+                let covered = self.widgets.iter().any(|(it, _)| it.contains(pos));
+                match self.widget_at(pos) {
+                    Some(idx) => {
+                        let (hit, elem) = &self.widgets[idx];
+                        if !hit.contains(pos) && (covered || elem.hitbox.is_none()) {
+                            return Err(format!(
+                                "The mouse at {pos:?} hits the element at {hit:?}"
+                            ));
+                        }
+                    }
+                    None if covered => {
+                        return Err(format!("The mouse at {pos:?} hits no element"));
+                    }
+                    None => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves the handle of the slider that is being dragged to the column of `pos`.
+    fn drag_slider(&mut self, pos: Vec2<u16>) -> Option<MouseInteractRes> {
+        let tag = self.dragging.as_ref()?;
+        let (area, elem) = self.widgets.iter_mut().find(|(_, it)| it.tag == *tag)?;
+        let Some(WidgetRepr::Slider { value, min, max }) = &mut elem.widget else {
+            return None;
+        };
+        let new = slider_value(*area, pos.x, *min, *max);
+        // Hover events are not reported, so this only reports moves of the handle
+        let kind = if new.to_bits() == value.to_bits() {
+            InteractKind::Hover
+        } else {
+            InteractKind::ValueChange(new)
+        };
+        *value = new;
+        Some(MouseInteractRes {
+            kind,
+            tag: Some(tag.clone()),
+            changed: false,
+            rerender: false,
+        })
+    }
+
     pub(crate) fn interpret_mouse_event(
         &mut self,
         event: crossterm::event::MouseEvent,
@@ -103,6 +470,21 @@ impl RenderedLayout {
         type MK = crossterm::event::MouseEventKind;
         type MB = crossterm::event::MouseButton;
 
+        let mut redraw = false;
+        match kind {
+            MK::Drag(MB::Left) => {
+                if let Some(res) = self.drag_slider(pos) {
+                    return MouseEventRes::Interact(res);
+                }
+            }
+            MK::Up(MB::Left) => {
+                self.dragging = None;
+                redraw = self.pressed.take().is_some();
+            }
+            MK::Down(MB::Left) => redraw = self.click_input(pos),
+            _ => {}
+        }
+
         let kind = match kind {
             MK::Down(MB::Left) => IK::Click(MouseButton::Left),
             MK::Down(MB::Right) => IK::Click(MouseButton::Right),
@@ -117,21 +499,33 @@ impl RenderedLayout {
             }
         };
 
-        let Some((_, elem)) = self.widgets.iter().find(|(r, _)| r.contains(pos)) else {
+        let Some(idx) = self.widget_at(pos) else {
             let cur = self.last_hover_elem.take();
             return MouseEventRes::Interact(MouseInteractRes {
                 kind,
                 tag: None,
                 changed: cur.is_some(),
-                rerender: cur.is_some_and(|it| it.has_hover),
+                rerender: redraw || cur.is_some_and(|it| it.has_hover),
             });
         };
+        let (area, elem) = &mut self.widgets[idx];
+
+        let kind = elem.widget_kind(kind, *area, pos.x);
+        if let (IK::ValueChange(_), MK::Down(MB::Left)) = (&kind, event.kind) {
+            self.dragging = Some(elem.tag.clone());
+        }
+        if elem.has_pressed && event.kind == MK::Down(MB::Left) {
+            self.pressed = Some(elem.tag.clone());
+            redraw = true;
+        }
+        let elem = elem.clone();
 
         let prev = self.last_hover_elem.replace(elem.clone());
 
         let changed = prev.as_ref().is_none_or(|it| it.tag != elem.tag);
 
-        let rerender = changed && (prev.as_ref().is_some_and(|it| it.has_hover) || elem.has_hover);
+        let rerender =
+            redraw || changed && (prev.as_ref().is_some_and(|it| it.has_hover) || elem.has_hover);
 
         MouseEventRes::Interact(MouseInteractRes {
             kind,
@@ -142,20 +536,121 @@ impl RenderedLayout {
     }
 }
 
+/// How a stack item wants to be sized along the stack's axis, see [`solve_lens`].
+#[derive(Debug, Clone, Copy)]
+pub(super) struct LenRequest {
+    /// The length of the item's content.
+    pub content: u16,
+    pub fill_weight: u16,
+    pub constraint: Option<Constraint>,
+}
+impl LenRequest {
+    /// The length of the item in a stack that is as small as possible. Relative constraints
+    /// cannot be resolved without the stack's length and are ignored.
+    pub(super) fn min_len(self) -> u16 {
+        match self.constraint {
+            None | Some(Constraint::Percent(_)) => self.content,
+            Some(Constraint::Exact(len)) => len,
+            Some(Constraint::Min(len)) => self.content.max(len),
+            Some(Constraint::Max(len)) => self.content.min(len),
+        }
+    }
+}
+
+/// Distributes `total` cells among the items of a stack.
+///
+/// Each item starts out at the length of its content, clamped to its constraint. Percentages
+/// are taken of what is left after the other items. The remaining space is then shared
+/// among the items with a fill weight, in proportion to their weights, without growing any of
+/// them past their upper bound. Also returns whether the items fit.
+pub(super) fn solve_lens(total: u16, items: &[LenRequest]) -> (Vec<u16>, bool) {
+    let fixed = items
+        .iter()
+        .filter(|it| !matches!(it.constraint, Some(Constraint::Percent(_))))
+        .fold(0u16, |acc, it| acc.saturating_add(it.min_len()));
+    let relative_base = total.saturating_sub(fixed);
+
+    let upper = |item: &LenRequest| match item.constraint {
+        Some(Constraint::Exact(len) | Constraint::Max(len)) => len,
+        Some(Constraint::Percent(percent)) => {
+            u16::try_from(u32::from(relative_base) * u32::from(percent.min(100)) / 100)
+                .expect("bounded by total")
+        }
+        Some(Constraint::Min(_)) | None => u16::MAX,
+    };
+    let uppers: Vec<_> = items.iter().map(upper).collect();
+    let mut lens: Vec<_> = std::iter::zip(items, &uppers)
+        .map(|(item, &upper)| item.min_len().min(upper))
+        .collect();
+
+    let Some(mut rem_len) = lens
+        .iter()
+        .try_fold(total, |rem, &len| rem.checked_sub(len))
+    else {
+        return (lens, false);
+    };
+
+    while rem_len > 0 {
+        let growable: Vec<_> = (0..items.len())
+            .filter(|&i| items[i].fill_weight > 0 && lens[i] < uppers[i])
+            .collect();
+        let total_weight: u64 = growable
+            .iter()
+            .map(|&i| u64::from(items[i].fill_weight))
+            .sum();
+        if total_weight == 0 {
+            break;
+        }
+
+        let mut capped = false;
+        let mut given = 0;
+        for &i in &growable {
+            let share =
+                u16::try_from(u64::from(rem_len) * u64::from(items[i].fill_weight) / total_weight)
+                    .expect("bounded by render area");
+            let room = uppers[i] - lens[i];
+            capped |= share >= room;
+            let extra = share.min(room);
+            lens[i] += extra;
+            given += extra;
+        }
+        rem_len = rem_len
+            .checked_sub(given)
+            .expect("bounded by partition via floor div");
+
+        if !capped {
+            // The shares were rounded down, so fewer cells than items are left. They go to the
+            // items with the lowest weights, one cell each, none of which is at its bound.
+            let mut fills: Vec<_> = growable
+                .into_iter()
+                .map(|i| (items[i].fill_weight, lens[i], i))
+                .collect();
+            fills.sort();
+            for (_, _, i) in fills.into_iter().take(rem_len.into()) {
+                lens[i] += 1;
+            }
+            break;
+        }
+    }
+
+    (lens, true)
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub(crate) struct Sizes {
     pub cell_size: Vec2<u16>,
     pub pix_size: Vec2<u16>,
 }
 impl Sizes {
+    /// The size of a cell in pixels, at least 1x1 even if the sizes are degenerate.
     pub(crate) fn font_size(self) -> Vec2<u16> {
         let Self {
             cell_size: Vec2 { x: w, y: h },
             pix_size: Vec2 { x: pw, y: ph },
         } = self;
         Vec2 {
-            x: pw / w,
-            y: ph / h,
+            x: (pw / w.max(1)).max(1),
+            y: (ph / h.max(1)).max(1),
         }
     }
     pub(crate) fn query() -> anyhow::Result<Option<Self>> {
@@ -165,7 +660,8 @@ impl Sizes {
             width,
             height,
         } = crossterm::terminal::window_size()?;
-        if width == 0 || height == 0 {
+        // Terminals briefly report empty sizes while they are being resized
+        if width == 0 || height == 0 || rows == 0 || columns == 0 {
             return Ok(None);
         }
         Ok(Some(Self {
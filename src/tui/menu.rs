@@ -0,0 +1,243 @@
+//! Declarative menus that are built into an [`Elem`] with consistent styling.
+//!
+//! Instead of laying out the rows of a menu and their hover states by hand, a driver describes
+//! the items of the menu and gives each one a tag. The controller handles the tags like any other
+//! interactive element, usually by acting on a left click.
+
+use std::sync::Arc;
+
+use super::{Axis, Color, CustomId, Elem, Size, StackOpts, StyleContext, TextStyle};
+
+const CHECKMARK: &str = "✓";
+
+#[derive(Debug, Clone, Default)]
+pub struct Menu {
+    /// Shown in bold above the items.
+    pub title: Option<Arc<str>>,
+    pub items: Vec<MenuItem>,
+    pub style: MenuStyle,
+    #[doc(hidden)]
+    #[deprecated = warn_non_exhaustive!()]
+    pub __non_exhaustive_struct_update: (),
+}
+
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum MenuItem {
+    Entry(MenuEntry),
+    /// A line between groups of items.
+    Separator,
+}
+impl From<MenuEntry> for MenuItem {
+    fn from(entry: MenuEntry) -> Self {
+        Self::Entry(entry)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MenuEntry {
+    pub label: Arc<str>,
+    /// Shown in front of the label. Should be one cell high and at most two cells wide.
+    pub icon: Option<Elem>,
+    /// Whether a checkmark is shown in front of the entry. `None` for entries that cannot be
+    /// checked.
+    pub checked: Option<bool>,
+    /// The tag of the entry, which is highlighted when hovered. Entries without a tag and
+    /// disabled entries are not interactive.
+    pub on_activate: Option<CustomId>,
+    pub enabled: bool,
+    /// Shown indented below the entry.
+    pub submenu: Vec<MenuItem>,
+    #[doc(hidden)]
+    #[deprecated = warn_non_exhaustive!()]
+    pub __non_exhaustive_struct_update: (),
+}
+impl Default for MenuEntry {
+    fn default() -> Self {
+        Self {
+            label: "".into(),
+            icon: None,
+            checked: None,
+            on_activate: None,
+            enabled: true,
+            submenu: Vec::new(),
+            #[expect(deprecated)]
+            __non_exhaustive_struct_update: (),
+        }
+    }
+}
+impl MenuEntry {
+    /// An entry that can be activated through `tag`.
+    pub fn new(label: impl Into<Arc<str>>, tag: CustomId) -> Self {
+        Self {
+            label: label.into(),
+            on_activate: Some(tag),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MenuStyle {
+    pub title: TextStyle,
+    pub entry: TextStyle,
+    /// Replaces [`Self::entry`] while the entry is hovered.
+    pub hovered: TextStyle,
+    pub disabled: TextStyle,
+    pub separator: TextStyle,
+    #[doc(hidden)]
+    #[deprecated = warn_non_exhaustive!()]
+    pub __non_exhaustive_struct_update: (),
+}
+impl Default for MenuStyle {
+    fn default() -> Self {
+        Self {
+            title: TextStyle {
+                bold: true,
+                ..Default::default()
+            },
+            entry: Default::default(),
+            hovered: TextStyle {
+                fg: Some(Color::token(StyleContext::ACCENT)),
+                underline: true,
+                ..Default::default()
+            },
+            disabled: TextStyle {
+                fg: Some(Color::Palette(8)),
+                ..Default::default()
+            },
+            separator: TextStyle {
+                fg: Some(Color::Palette(8)),
+                ..Default::default()
+            },
+            #[expect(deprecated)]
+            __non_exhaustive_struct_update: (),
+        }
+    }
+}
+
+/// The columns that are shared by all rows of a menu, so that the labels line up.
+#[derive(Default)]
+struct Columns {
+    checkmark: bool,
+    icon: bool,
+    /// The widest row, including indentation.
+    width: usize,
+}
+impl Columns {
+    fn measure(items: &[MenuItem], depth: usize, cols: &mut Self) {
+        for item in items {
+            let MenuItem::Entry(entry) = item else {
+                continue;
+            };
+            cols.checkmark |= entry.checked.is_some();
+            cols.icon |= entry.icon.is_some();
+            cols.width = cols.width.max(2 * depth + label_width(&entry.label));
+            Self::measure(&entry.submenu, depth + 1, cols);
+        }
+    }
+
+    fn prefix_width(&self) -> usize {
+        2 * usize::from(self.checkmark) + 3 * usize::from(self.icon)
+    }
+}
+
+impl Menu {
+    pub fn build(&self) -> Elem {
+        let Self {
+            title,
+            items,
+            style,
+            #[expect(deprecated)]
+                __non_exhaustive_struct_update: (),
+        } = self;
+
+        let mut cols = Columns::default();
+        Columns::measure(items, 0, &mut cols);
+        let title_width = title.as_deref().map_or(0, label_width);
+        let width = (cols.prefix_width() + cols.width).max(title_width);
+
+        let mut rows = Vec::new();
+        if let Some(title) = title {
+            rows.push(Elem::text(title, style.title.clone()));
+        }
+        push_rows(&mut rows, items, 0, &cols, width, style);
+        Elem::stack(Axis::Y, rows, StackOpts::default())
+    }
+}
+impl From<Menu> for Elem {
+    fn from(menu: Menu) -> Self {
+        menu.build()
+    }
+}
+
+/// Menus are built by the controller, which does not know the host's [`super::AmbiguousWidth`].
+fn label_width(label: &str) -> usize {
+    super::width::str_width(label, super::AmbiguousWidth::Narrow)
+}
+
+fn push_rows(
+    rows: &mut Vec<Elem>,
+    items: &[MenuItem],
+    depth: usize,
+    cols: &Columns,
+    width: usize,
+    style: &MenuStyle,
+) {
+    for item in items {
+        match item {
+            MenuItem::Separator => {
+                rows.push(Elem::text("─".repeat(width), style.separator.clone()));
+            }
+            MenuItem::Entry(entry) => {
+                rows.push(entry_row(entry, depth, cols, style));
+                push_rows(rows, &entry.submenu, depth + 1, cols, width, style);
+            }
+        }
+    }
+}
+
+fn entry_row(entry: &MenuEntry, depth: usize, cols: &Columns, style: &MenuStyle) -> Elem {
+    let MenuEntry {
+        label,
+        icon,
+        checked,
+        on_activate,
+        enabled,
+        submenu: _,
+        #[expect(deprecated)]
+            __non_exhaustive_struct_update: (),
+    } = entry;
+
+    let row = |text_style: &TextStyle| {
+        let mut items = vec![Elem::spacing(Axis::X, (2 * depth) as u16)];
+        if cols.checkmark {
+            let mark = if *checked == Some(true) {
+                CHECKMARK
+            } else {
+                " "
+            };
+            items.push(Elem::text(format_args!("{mark} "), text_style.clone()));
+        }
+        if cols.icon {
+            items.push(match icon {
+                Some(icon) => icon.clone().with_min_size(Size {
+                    width: 2,
+                    height: 1,
+                }),
+                None => Elem::spacing(Axis::X, 2),
+            });
+            items.push(Elem::spacing(Axis::X, 1));
+        }
+        items.push(Elem::text(label, text_style.clone()));
+        Elem::stack(Axis::X, items, StackOpts::default())
+    };
+
+    match on_activate {
+        Some(tag) if *enabled => {
+            row(&style.entry).interactive_hover(tag.clone(), row(&style.hovered))
+        }
+        _ if !*enabled => row(&style.disabled),
+        _ => row(&style.entry),
+    }
+}
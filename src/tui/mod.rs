@@ -8,9 +8,61 @@ mod layout;
 #[cfg(feature = "__bin")]
 pub(crate) use layout::*;
 
+#[cfg(feature = "__bin")]
+mod svg;
+
+#[cfg(feature = "__bin")]
+mod wrap;
+
+#[cfg(feature = "__bin")]
+mod sanitize;
+
+#[cfg(feature = "__bin")]
+mod downscale;
+
+#[cfg(feature = "__bin")]
+mod input;
+#[cfg(feature = "__bin")]
+pub(crate) use input::*;
+
+#[cfg(feature = "__bin")]
+mod headless;
+#[cfg(feature = "__bin")]
+pub(crate) use headless::*;
+
+#[cfg(feature = "__bin")]
+mod raster;
+#[cfg(feature = "__bin")]
+pub(crate) use raster::*;
+
+#[cfg(feature = "testing")]
+pub mod testing;
+
+#[cfg(feature = "__bin")]
+mod image_cache;
+#[cfg(feature = "__bin")]
+pub(crate) use image_cache::*;
+
 mod api;
 pub use api::*;
 
+mod ansi;
+pub use ansi::AnsiError;
+
+mod bidi;
+
+mod width;
+pub use width::AmbiguousWidth;
+#[cfg(feature = "__bin")]
+pub(crate) use width::TextWidth;
+
+mod style;
+pub use style::*;
+
+pub mod menu;
+
+pub mod symbols;
+
 mod repr;
 pub(crate) use repr::*;
 
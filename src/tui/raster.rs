@@ -0,0 +1,280 @@
+//! Rasterizes a [`Grid`] to a PNG image, so that rendered content can be attached to bug
+//! reports without a compositor screenshot.
+//!
+//! There is no font rasterizer, so text is drawn with a small built-in bitmap font. Letters
+//! are drawn in upper case and other characters than ASCII as blocks, which is enough to
+//! recognize the content, its colors and its layout.
+
+use crate::tui::*;
+
+/// The size of a cell in the image, in pixels.
+const CELL_PX: Vec2<usize> = Vec2 { x: 8, y: 16 };
+/// The glyphs are scaled up by this factor and centered in their cells.
+const GLYPH_SCALE: usize = 2;
+const GLYPH_SIZE: Vec2<usize> = Vec2 { x: 3, y: 5 };
+/// The rows between the grids passed to [`grids_to_png`].
+const GRID_GAP_PX: usize = 8;
+
+/// The panels' colors, see `KittyBackend`.
+const DEFAULT_FG: [u8; 3] = [0xff, 0xff, 0xff];
+const DEFAULT_BG: [u8; 3] = [0, 0, 0];
+const IMAGE_SHADE: [u8; 3] = [0x80, 0x80, 0x80];
+
+/// 3x5 glyphs of `' '..='`'` followed by `'{'..='~'`, top row first, with the leftmost pixel of
+/// each row in its most significant bit.
+#[rustfmt::skip]
+const GLYPHS: [u16; 69] = [
+    0x0000, 0x2482, 0x5a00, 0x5f7d, 0x3c9e, 0x52a5, 0x2aab, 0x2400, 0x1491, 0x4494, 0x0aa8, 0x05d0,
+    0x0014, 0x01c0, 0x0002, 0x12a4, 0x7b6f, 0x2c97, 0x73e7, 0x72cf, 0x5bc9, 0x79cf, 0x79ef, 0x7252,
+    0x7bef, 0x7bcf, 0x0410, 0x0414, 0x1511, 0x0e38, 0x4454, 0x6282, 0x7be7, 0x2bed, 0x6bae, 0x3923,
+    0x6b6e, 0x79a7, 0x79a4, 0x396b, 0x5bed, 0x7497, 0x126a, 0x5bad, 0x4927, 0x5fed, 0x6b6d, 0x2b6a,
+    0x6ba4, 0x2b73, 0x6bad, 0x388e, 0x7492, 0x5b6f, 0x5b6a, 0x5bfd, 0x5aad, 0x5a92, 0x72a7, 0x6926,
+    0x4889, 0x324b, 0x2a00, 0x0007, 0x4400, 0x3593, 0x2492, 0x64d6, 0x0780,
+];
+/// Drawn for characters without a glyph.
+const BLOCK_GLYPH: u16 = 0x0ff8;
+
+fn glyph(text: &str) -> u16 {
+    let mut chars = text.chars();
+    let (Some(ch), None) = (chars.next(), chars.next()) else {
+        return BLOCK_GLYPH;
+    };
+    match ch.to_ascii_uppercase() {
+        ch @ ' '..='`' => GLYPHS[usize::from(ch as u8 - b' ')],
+        ch @ '{'..='~' => GLYPHS[65 + usize::from(ch as u8 - b'{')],
+        _ => BLOCK_GLYPH,
+    }
+}
+
+/// How a cell is drawn, from the SGR sequences that apply to it, see [`cell_style`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CellStyle {
+    pub fg: [u8; 3],
+    pub bg: [u8; 3],
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub strikethrough: bool,
+}
+impl Default for CellStyle {
+    fn default() -> Self {
+        Self {
+            fg: DEFAULT_FG,
+            bg: DEFAULT_BG,
+            bold: false,
+            italic: false,
+            underline: false,
+            strikethrough: false,
+        }
+    }
+}
+
+pub(crate) fn cell_style(sgr: &str) -> CellStyle {
+    let (mut fg, mut bg) = (None, None);
+    let (mut dim, mut reverse, mut hidden) = (false, false, false);
+    let mut style = CellStyle::default();
+    for seq in sgr.split("\x1b[").filter_map(|it| it.strip_suffix('m')) {
+        let mut params = seq.split(';').map(|it| it.parse::<u8>().unwrap_or(0));
+        while let Some(param) = params.next() {
+            let mut extended = || match params.next() {
+                Some(2) => Some([(); 3].map(|()| params.next().unwrap_or(0))),
+                Some(5) => Some(palette(params.next().unwrap_or(0))),
+                _ => None,
+            };
+            match param {
+                0 => {
+                    (fg, bg, dim, reverse, hidden) = (None, None, false, false, false);
+                    style = CellStyle::default();
+                }
+                1 => style.bold = true,
+                2 => dim = true,
+                3 => style.italic = true,
+                4 => style.underline = true,
+                7 => reverse = true,
+                8 => hidden = true,
+                9 => style.strikethrough = true,
+                22 => (style.bold, dim) = (false, false),
+                23 => style.italic = false,
+                24 => style.underline = false,
+                27 => reverse = false,
+                28 => hidden = false,
+                29 => style.strikethrough = false,
+                30..=37 => fg = Some(palette(param - 30)),
+                38 => fg = extended(),
+                39 => fg = None,
+                40..=47 => bg = Some(palette(param - 40)),
+                48 => bg = extended(),
+                49 => bg = None,
+                90..=97 => fg = Some(palette(param - 90 + 8)),
+                100..=107 => bg = Some(palette(param - 100 + 8)),
+                _ => {}
+            }
+        }
+    }
+    let (mut fg, bg) = (fg.unwrap_or(DEFAULT_FG), bg.unwrap_or(DEFAULT_BG));
+    if dim {
+        fg = [0, 1, 2].map(|i| ((u16::from(fg[i]) + u16::from(bg[i])) / 2) as u8);
+    }
+    (style.fg, style.bg) = if reverse { (bg, fg) } else { (fg, bg) };
+    if hidden {
+        style.fg = style.bg;
+    }
+    style
+}
+
+/// The color of an entry of the 256 color palette, like xterm's defaults.
+fn palette(idx: u8) -> [u8; 3] {
+    const BASE: [[u8; 3]; 16] = [
+        [0, 0, 0],
+        [0xcd, 0, 0],
+        [0, 0xcd, 0],
+        [0xcd, 0xcd, 0],
+        [0, 0, 0xee],
+        [0xcd, 0, 0xcd],
+        [0, 0xcd, 0xcd],
+        [0xe5, 0xe5, 0xe5],
+        [0x7f, 0x7f, 0x7f],
+        [0xff, 0, 0],
+        [0, 0xff, 0],
+        [0xff, 0xff, 0],
+        [0x5c, 0x5c, 0xff],
+        [0xff, 0, 0xff],
+        [0, 0xff, 0xff],
+        [0xff, 0xff, 0xff],
+    ];
+    match idx {
+        0..16 => BASE[usize::from(idx)],
+        16..232 => {
+            let level = |it: u8| if it == 0 { 0 } else { 55 + it * 40 };
+            let idx = idx - 16;
+            [level(idx / 36), level(idx / 6 % 6), level(idx % 6)]
+        }
+        232.. => [8 + (idx - 232) * 10; 3],
+    }
+}
+
+/// Rasterizes the grids below each other into a PNG image.
+pub(crate) fn grids_to_png(grids: &[&Grid]) -> Vec<u8> {
+    let width = grids
+        .iter()
+        .map(|it| usize::from(it.size().x))
+        .max()
+        .unwrap_or(0)
+        * CELL_PX.x;
+    let height = grids
+        .iter()
+        .map(|it| usize::from(it.size().y) * CELL_PX.y)
+        .sum::<usize>()
+        + GRID_GAP_PX * grids.len().saturating_sub(1);
+    let mut rgb = vec![0; width * height * 3];
+    let mut put = |x: usize, y: usize, color: [u8; 3]| {
+        let idx = (y * width + x) * 3;
+        rgb[idx..idx + 3].copy_from_slice(&color);
+    };
+
+    let mut top = 0;
+    for grid in grids {
+        for (row_idx, row) in grid.rows().enumerate() {
+            let mut style = CellStyle::default();
+            for (col_idx, cell) in row.iter().enumerate() {
+                // Wide graphemes are drawn in their first cell, on the background of all cells
+                if !cell.continuation {
+                    style = cell_style(&cell.sgr);
+                }
+                let CellStyle { fg, bg, .. } = style;
+                let origin = Vec2 {
+                    x: col_idx * CELL_PX.x,
+                    y: top + row_idx * CELL_PX.y,
+                };
+                for y in 0..CELL_PX.y {
+                    for x in 0..CELL_PX.x {
+                        let color = match cell.image {
+                            Some(_) if (x + y) % 4 < 2 => IMAGE_SHADE,
+                            _ => bg,
+                        };
+                        put(origin.x + x, origin.y + y, color);
+                    }
+                }
+                if cell.image.is_some() || cell.continuation || cell.text.trim().is_empty() {
+                    continue;
+                }
+                let glyph = glyph(&cell.text);
+                let offset = Vec2 {
+                    x: (CELL_PX.x - GLYPH_SIZE.x * GLYPH_SCALE) / 2,
+                    y: (CELL_PX.y - GLYPH_SIZE.y * GLYPH_SCALE) / 2,
+                };
+                for gy in 0..GLYPH_SIZE.y {
+                    for gx in 0..GLYPH_SIZE.x {
+                        let bit = (GLYPH_SIZE.y - 1 - gy) * GLYPH_SIZE.x + (GLYPH_SIZE.x - 1 - gx);
+                        if glyph >> bit & 1 == 0 {
+                            continue;
+                        }
+                        for sy in 0..GLYPH_SCALE {
+                            for sx in 0..GLYPH_SCALE {
+                                put(
+                                    origin.x + offset.x + gx * GLYPH_SCALE + sx,
+                                    origin.y + offset.y + gy * GLYPH_SCALE + sy,
+                                    fg,
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        top += usize::from(grid.size().y) * CELL_PX.y + GRID_GAP_PX;
+    }
+    encode_png(width, height, &rgb)
+}
+
+/// Encodes an 8-bit RGB image without compression, which is good enough for the few images
+/// this produces.
+fn encode_png(width: usize, height: usize, rgb: &[u8]) -> Vec<u8> {
+    fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+        out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        let start = out.len();
+        out.extend_from_slice(kind);
+        out.extend_from_slice(data);
+        let crc = crc32fast::hash(&out[start..]);
+        out.extend_from_slice(&crc.to_be_bytes());
+    }
+
+    // Each row starts with its filter type, which is 0 for none
+    let mut raw = Vec::with_capacity((width * 3 + 1) * height);
+    for row in rgb.chunks(width * 3).take(height) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    // A zlib stream of uncompressed deflate blocks
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = raw.chunks(usize::from(u16::MAX)).peekable();
+    if blocks.peek().is_none() {
+        zlib.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let len = block.len() as u16;
+        zlib.push(u8::from(blocks.peek().is_none()));
+        zlib.extend_from_slice(&len.to_le_bytes());
+        zlib.extend_from_slice(&(!len).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in &raw {
+        a = (a + u32::from(byte)) % 65521;
+        b = (b + a) % 65521;
+    }
+    zlib.extend_from_slice(&(b << 16 | a).to_be_bytes());
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // Bit depth 8, color type RGB, default compression, filtering and no interlacing
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut out = b"\x89PNG\r\n\x1a\n".to_vec();
+    chunk(&mut out, b"IHDR", &header);
+    chunk(&mut out, b"IDAT", &zlib);
+    chunk(&mut out, b"IEND", &[]);
+    out
+}
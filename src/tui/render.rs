@@ -1,22 +1,283 @@
-use std::io::Write;
+use std::{
+    io::Write,
+    num::NonZeroU32,
+    time::{Duration, Instant},
+};
 
-use crate::tui::*;
+use crate::tui::{downscale, svg, wrap, *};
 
 pub(super) trait Render {
-    fn render(&self, ctx: &mut RenderCtx<impl Write>, area: Area) -> std::io::Result<()>;
+    fn render(&self, ctx: &mut RenderCtx, area: Area) -> std::io::Result<()>;
     fn calc_min_size(&self, args: &SizingArgs) -> Vec2<u16>;
+    /// The height needed when rendered `width` cells wide. This only differs from the minimum
+    /// height for elements whose content reflows, see [`Elem::wrapped_text`].
+    fn calc_height_for_width(&self, args: &SizingArgs, width: u16) -> u16 {
+        _ = width;
+        self.calc_min_size(args).y
+    }
 }
 
 #[derive(Debug)]
-pub(super) struct RenderCtx<'a, W> {
+pub(super) struct RenderCtx<'a> {
     sizing: &'a SizingArgs,
-    writer: W,
     layout: &'a mut RenderedLayout,
+    /// Pixel data of images that the terminal does not have yet. Written before the leaves.
+    transmissions: Vec<u8>,
+    /// The number of interactive elements that the element being rendered is nested in.
+    interact_depth: u32,
+    /// The style contexts that the element being rendered is nested in, innermost last.
+    styles: Vec<StyleContext>,
+    /// The start times of the transitions in the previous frame.
+    prev_transitions: &'a std::collections::HashMap<CustomId, Instant>,
+    prev_collapses: &'a std::collections::HashMap<CustomId, CollapseState>,
+    now: Instant,
+    /// How far the text being rendered is faded in, see [`TransitionKind::Fade`].
+    opacity: f32,
+    /// Whether the text is drawn faint, see [`RenderedLayout::set_dimmed`].
+    dim: bool,
+    /// Whether the element being rendered is highlighted as interactive, see
+    /// [`RenderedLayout::set_showing_hints`].
+    highlight: bool,
+    /// Whether the parent of the element being rendered is at least as large as its min size,
+    /// see [`RenderedLayout::check_sizes`].
+    parent_fits: bool,
+    /// The axis along which the constraint of the stack item being rendered may cut it off,
+    /// e.g. [`Constraint::Max`]. Such items are not checked for [`RenderedLayout::undersized`].
+    cut_off_axis: Option<Axis>,
+}
+
+impl RenderCtx<'_> {
+    /// Renders the children of an element with different sizing, e.g. with
+    /// [`Elem::with_glyph_widths`].
+    fn with_sizing<R>(&mut self, sizing: &SizingArgs, f: impl FnOnce(&mut RenderCtx) -> R) -> R {
+        let mut ctx = RenderCtx {
+            sizing,
+            layout: self.layout,
+            transmissions: std::mem::take(&mut self.transmissions),
+            interact_depth: self.interact_depth,
+            styles: std::mem::take(&mut self.styles),
+            prev_transitions: self.prev_transitions,
+            prev_collapses: self.prev_collapses,
+            now: self.now,
+            opacity: self.opacity,
+            dim: self.dim,
+            highlight: self.highlight,
+            parent_fits: self.parent_fits,
+            cut_off_axis: self.cut_off_axis,
+        };
+        let res = f(&mut ctx);
+        self.transmissions = ctx.transmissions;
+        self.styles = ctx.styles;
+        res
+    }
+
+    /// Records the output of an element without children.
+    fn leaf(
+        &mut self,
+        area: Area,
+        f: impl FnOnce(&mut Vec<u8>) -> std::io::Result<()>,
+    ) -> std::io::Result<()> {
+        let mut buf = Vec::new();
+        f(&mut buf)?;
+        self.layout.leaves.push((area, buf));
+        Ok(())
+    }
+
+    /// Looks up the color of a token in the enclosing style contexts.
+    fn resolve_color<'c>(&'c self, mut color: &'c Color) -> Option<&'c Color> {
+        let mut depth = self.styles.len();
+        while let Color::Token(token) = color {
+            let Some((idx, found)) = self.styles[..depth]
+                .iter()
+                .enumerate()
+                .rev()
+                .find_map(|(i, it)| Some((i, it.get(token)?)))
+            else {
+                log::debug!("Color token {token:?} is not assigned");
+                return None;
+            };
+            // Tokens assigned to tokens are looked up further out, which also prevents cycles
+            depth = idx;
+            color = found;
+        }
+        Some(color)
+    }
+
+    /// The SGR sequence that applies `style`, empty for the default style.
+    fn text_sgr(&self, style: &TextStyle) -> String {
+        let TextStyle {
+            fg,
+            bg,
+            bold,
+            italic,
+            underline,
+            #[expect(deprecated)]
+                __non_exhaustive_struct_update: (),
+        } = style;
+
+        let fading = self.opacity < 1.0;
+        let bg_token = Color::token(StyleContext::BG);
+        let ctx_bg = fading.then(|| self.resolve_color(&bg_token)).flatten();
+        let fg = fg.as_ref().and_then(|it| self.resolve_color(it));
+        let bg = bg.as_ref().and_then(|it| self.resolve_color(it));
+        // The colors that the text fades in from
+        let fade_from = [bg.or(ctx_bg), ctx_bg];
+
+        let mut params = Vec::new();
+        let mut interpolated = false;
+        for ((color, base), from) in [(fg, 38), (bg, 48)].into_iter().zip(fade_from) {
+            match color {
+                Some(&Color::Rgb { r, g, b }) => {
+                    let [r, g, b] = match from {
+                        Some(&Color::Rgb {
+                            r: from_r,
+                            g: from_g,
+                            b: from_b,
+                        }) if fading => {
+                            interpolated |= base == 38;
+                            let lerp = |from: u8, to: u8| {
+                                (f32::from(from) + (f32::from(to) - f32::from(from)) * self.opacity)
+                                    .round() as u8
+                            };
+                            [lerp(from_r, r), lerp(from_g, g), lerp(from_b, b)]
+                        }
+                        _ => [r, g, b],
+                    };
+                    params.push(format!("{base};2;{r};{g};{b}"));
+                }
+                Some(&Color::Palette(idx)) => params.push(format!("{base};5;{idx}")),
+                Some(Color::Token(_)) | None => {}
+            }
+        }
+        if self.dim || (fading && !interpolated && self.opacity < 0.5) {
+            params.push("2".into());
+        }
+        for (set, param) in [
+            (bold, "1"),
+            (italic, "3"),
+            (underline, "4"),
+            (&self.highlight, "7"),
+        ] {
+            if *set {
+                params.push(param.into());
+            }
+        }
+        if params.is_empty() {
+            String::new()
+        } else {
+            format!("\x1b[{}m", params.join(";"))
+        }
+    }
+
+    /// The SGR sequence for output that is not styled by a [`TextStyle`], e.g. to dim or
+    /// highlight it.
+    fn plain_sgr(&self) -> String {
+        self.text_sgr(&TextStyle::default())
+    }
+}
+
+impl RenderCtx<'_> {
+    /// How far the transition with `key` has progressed, eased so that it slows down towards
+    /// the end. Starts the transition if it was not shown in the previous frame.
+    fn transition_progress(&mut self, key: &CustomId, duration_ms: u32) -> f32 {
+        if self.layout.finish_transitions {
+            return 1.0;
+        }
+        let started = self.prev_transitions.get(key).copied().unwrap_or(self.now);
+        self.layout.transitions.insert(key.clone(), started);
+
+        let elapsed = self.now.saturating_duration_since(started).as_secs_f32();
+        let t = elapsed / (duration_ms as f32 / 1000.0);
+        if t >= 1.0 || !t.is_finite() {
+            return 1.0;
+        }
+        self.layout.animating = true;
+        1.0 - (1.0 - t).powi(3)
+    }
+}
+
+/// The area of an element that is `offset` cells away from its place towards `from`, see
+/// [`TransitionKind::Slide`].
+fn slide_area(mut area: Area, from: Direction, offset: u16) -> Area {
+    match from {
+        Direction::Right => {
+            area.pos.x = area.pos.x.saturating_add(offset);
+            area.size.x = area.size.x.saturating_sub(offset);
+        }
+        Direction::Down => {
+            area.pos.y = area.pos.y.saturating_add(offset);
+            area.size.y = area.size.y.saturating_sub(offset);
+        }
+        Direction::Left => area.pos.x = area.pos.x.saturating_sub(offset),
+        Direction::Up => area.pos.y = area.pos.y.saturating_sub(offset),
+    }
+    area
+}
+
+/// The lines of the text of [`Elem::text`], without control characters.
+fn text_lines(text: &str) -> impl Iterator<Item = String> {
+    text.split('\n')
+        .map(|line| line.chars().filter(|it| !it.is_control()).collect())
 }
 
 #[derive(Debug, Clone)]
 pub(crate) struct SizingArgs {
     pub font_size: Vec2<u16>,
+    /// The fractions of their full size that [`Elem::collapsible`]s take up in the frame being
+    /// rendered, by key. Others take up the size of their visibility.
+    collapses: std::collections::HashMap<CustomId, f32>,
+    /// Whether the terminal supports kitty's graphics protocol. Without it, images are left
+    /// blank.
+    images: bool,
+    text_width: TextWidth,
+}
+impl SizingArgs {
+    /// Empty font sizes are taken as 1x1, like [`Sizes::font_size`] does.
+    pub(crate) fn new(font_size: Vec2<u16>) -> Self {
+        Self {
+            font_size: font_size.combine(Vec2 { x: 1, y: 1 }, std::cmp::max),
+            collapses: Default::default(),
+            images: true,
+            text_width: Default::default(),
+        }
+    }
+
+    pub(crate) fn with_images(self, images: bool) -> Self {
+        Self { images, ..self }
+    }
+
+    pub(crate) fn with_ambiguous_width(mut self, ambiguous: AmbiguousWidth) -> Self {
+        self.text_width.ambiguous = ambiguous;
+        self
+    }
+
+    fn with_glyph_widths(&self, widths: &[(String, u8)]) -> Self {
+        Self {
+            text_width: self.text_width.with_glyphs(widths),
+            ..self.clone()
+        }
+    }
+
+    /// The sizing for a frame at `now`, which continues the animations of `layout`.
+    fn at(&self, layout: &RenderedLayout, now: Instant) -> Self {
+        Self {
+            font_size: self.font_size,
+            collapses: layout
+                .collapses
+                .iter()
+                .map(|(key, state)| (key.clone(), state.fraction(now)))
+                .collect(),
+            images: self.images,
+            text_width: self.text_width.clone(),
+        }
+    }
+
+    fn collapse_fraction(&self, key: &CustomId, shown: bool) -> f32 {
+        self.collapses
+            .get(key)
+            .copied()
+            .unwrap_or(if shown { 1.0 } else { 0.0 })
+    }
 }
 impl<T> Vec2<T> {
     fn combine<U, R>(self, other: Vec2<U>, mut f: impl FnMut(T, U) -> R) -> Vec2<R> {
@@ -30,6 +291,87 @@ pub(crate) fn calc_min_size(elem: &Elem, args: &SizingArgs) -> Vec2<u16> {
     elem.calc_min_size(args)
         .combine(Vec2 { x: 1, y: 1 }, std::cmp::max)
 }
+/// Like [`calc_min_size`], but at most `max_width` cells wide. Wider elements are narrowed,
+/// which makes their [`Elem::wrapped_text`]s take more lines instead of being clipped.
+pub(crate) fn calc_min_size_within(elem: &Elem, args: &SizingArgs, max_width: u16) -> Vec2<u16> {
+    let size = calc_min_size(elem, args);
+    let max_width = max_width.max(1);
+    if size.x <= max_width {
+        return size;
+    }
+    Vec2 {
+        x: max_width,
+        y: elem.calc_height_for_width(args, max_width).max(1),
+    }
+}
+/// Splits the items of a vertical [`Elem::stack`] into columns that are at most `max_height`
+/// cells high, where possible. Returns `None` for other elements.
+pub(crate) fn split_columns(elem: &Elem, args: &SizingArgs, max_height: u16) -> Option<Elem> {
+    const COLUMN_GAP: u16 = 2;
+
+    let ElemRepr::Stack(StackRepr {
+        axis: Axis::Y,
+        reverse,
+        items,
+    }) = &*elem.0
+    else {
+        return None;
+    };
+    let mut columns = Vec::<Vec<StackItemRepr>>::new();
+    let mut column_height = 0u16;
+    for item in items {
+        let height = item.elem.calc_min_size(args).y;
+        let item = StackItemRepr {
+            fill_weight: item.fill_weight,
+            constraint: item.constraint,
+            elem: item.elem.clone(),
+        };
+        match columns.last_mut() {
+            Some(column) if column_height.saturating_add(height) <= max_height => {
+                column.push(item);
+                column_height = column_height.saturating_add(height);
+            }
+            _ => {
+                columns.push(vec![item]);
+                column_height = height;
+            }
+        }
+    }
+    if columns.len() < 2 {
+        return None;
+    }
+
+    let mut row = Vec::new();
+    for column in columns {
+        if !row.is_empty() {
+            row.push(StackItemRepr {
+                fill_weight: 0,
+                constraint: None,
+                elem: Elem::spacing(Axis::X, COLUMN_GAP),
+            });
+        }
+        row.push(StackItemRepr {
+            fill_weight: 0,
+            constraint: None,
+            elem: ElemRepr::Stack(StackRepr {
+                axis: Axis::Y,
+                reverse: *reverse,
+                items: column,
+            })
+            .into(),
+        });
+    }
+    Some(
+        ElemRepr::Stack(StackRepr {
+            axis: Axis::X,
+            reverse: false,
+            items: row,
+        })
+        .into(),
+    )
+}
+/// Renders `elem`, only redrawing the leaf elements whose output changed since `old_layout` if
+/// the arrangement of the leaf elements is the same. Otherwise, the whole terminal is redrawn.
 pub(crate) fn render(
     elem: &Elem,
     area: Area,
@@ -37,52 +379,411 @@ pub(crate) fn render(
     sizing: &SizingArgs,
     old_layout: &RenderedLayout,
 ) -> std::io::Result<RenderedLayout> {
-    crossterm::queue!(
-        writer,
-        crossterm::terminal::BeginSynchronizedUpdate,
-        crossterm::terminal::Clear(crossterm::terminal::ClearType::All),
-    )?;
     let mut layout = RenderedLayout {
         widgets: Default::default(),
+        slots: Default::default(),
+        leaves: Default::default(),
+        diffable: true,
+        diffs_since_full: 0,
+        images: old_layout.images.clone(),
+        placements: Default::default(),
         last_mouse_pos: old_layout.last_mouse_pos,
         last_hover_elem: None,
+        dragging: old_layout.dragging.clone(),
+        pressed: old_layout.pressed.clone(),
+        inputs: old_layout.inputs.clone(),
+        input_areas: Default::default(),
+        focused_input: old_layout.focused_input.clone(),
+        transitions: Default::default(),
+        animating: false,
+        collapses: Default::default(),
+        finish_transitions: old_layout.finish_transitions,
+        overlays: Default::default(),
+        check_sizes: old_layout.check_sizes,
+        undersized: Default::default(),
+        dimmed: old_layout.dimmed,
+        hints: old_layout.hints,
     };
-    elem.render(
-        &mut RenderCtx {
-            sizing,
-            writer: &mut *writer,
-            layout: &mut layout,
-        },
-        area,
-    )?;
+    let now = Instant::now();
+    let sizing = &sizing.at(old_layout, now);
+    let mut ctx = RenderCtx {
+        sizing,
+        layout: &mut layout,
+        transmissions: Vec::new(),
+        interact_depth: 0,
+        styles: Vec::new(),
+        prev_transitions: &old_layout.transitions,
+        prev_collapses: &old_layout.collapses,
+        now,
+        opacity: 1.0,
+        dim: old_layout.dimmed,
+        highlight: false,
+        parent_fits: true,
+        cut_off_axis: None,
+    };
+    elem.render(&mut ctx, area)?;
+    let RenderCtx { transmissions, .. } = ctx;
+    layout.forget_hidden_inputs();
+    let mut deletions = Vec::new();
+    layout
+        .images
+        .evict(layout.placements.iter().map(|&(_, id)| id), &mut deletions)?;
+
+    let same_arrangement = old_layout.diffable
+        && old_layout.diffs_since_full < MAX_DIFFS_SINCE_FULL
+        && old_layout.leaves.len() == layout.leaves.len()
+        && std::iter::zip(&old_layout.leaves, &layout.leaves)
+            .all(|((old_area, _), (new_area, _))| old_area == new_area);
+
+    if same_arrangement {
+        layout.diffs_since_full = old_layout.diffs_since_full + 1;
+
+        let mut dirty: Vec<_> = std::iter::zip(&old_layout.leaves, &layout.leaves)
+            .map(|((_, old), (_, new))| old != new)
+            .collect();
+        if !dirty.contains(&true) && transmissions.is_empty() && deletions.is_empty() {
+            return Ok(layout);
+        }
+        // Clearing a leaf also clears the leaves it overlaps with, see [`Elem::overlay`]
+        let mut spread = true;
+        while spread {
+            spread = false;
+            for i in 0..layout.leaves.len() {
+                if !dirty[i]
+                    && layout
+                        .leaves
+                        .iter()
+                        .zip(&dirty)
+                        .any(|((area, _), &it)| it && area.intersects(layout.leaves[i].0))
+                {
+                    dirty[i] = true;
+                    spread = true;
+                }
+            }
+        }
+        let redraw = || {
+            layout
+                .leaves
+                .iter()
+                .zip(&dirty)
+                .filter_map(|(leaf, &it)| it.then_some(leaf))
+        };
+
+        crossterm::queue!(writer, crossterm::terminal::BeginSynchronizedUpdate)?;
+        writer.write_all(&transmissions)?;
+        for (area, _) in redraw() {
+            clear_area(writer, *area)?;
+        }
+        for (_, buf) in redraw() {
+            // In a full frame, the leaf would inherit the attributes left over by the previous one
+            write!(writer, "\x1b[0m")?;
+            writer.write_all(buf)?;
+        }
+    } else {
+        crossterm::queue!(writer, crossterm::terminal::BeginSynchronizedUpdate)?;
+        writer.write_all(CLEAR_FRAME)?;
+        writer.write_all(&transmissions)?;
+        for (_, buf) in &layout.leaves {
+            writer.write_all(buf)?;
+        }
+    }
+    writer.write_all(&deletions)?;
     crossterm::execute!(writer, crossterm::terminal::EndSynchronizedUpdate)?;
     Ok(layout)
 }
 
+/// Diffs are appended to the last full frame to restore restarted panels, so a full frame is
+/// forced regularly to keep that bounded.
+const MAX_DIFFS_SINCE_FULL: u32 = 64;
+
+/// Clears the screen, keeping the data of images with ids so they can be placed again.
+///
+/// In kitty, `ED 2` would also free all images that are no longer placed, so the screen is
+/// cleared from the top left corner instead, and the placements are deleted separately.
+const CLEAR_FRAME: &[u8] = b"\x1b[H\x1b[J\x1b_Ga=d,d=a,q=2\x1b\\";
+
+/// Clears the text and image placements (deleted by intersecting cell, 1-based) in an area.
+fn clear_area(writer: &mut impl Write, area: Area) -> std::io::Result<()> {
+    for y in area.pos.y..area.pos.y.saturating_add(area.size.y) {
+        crossterm::queue!(writer, crossterm::cursor::MoveTo(area.pos.x, y))?;
+        write!(writer, "\x1b[0m{:w$}", "", w = area.size.x.into())?;
+        for x in area.pos.x..area.pos.x.saturating_add(area.size.x) {
+            write!(writer, "\x1b_Ga=d,d=p,x={},y={},q=2\x1b\\", x + 1, y + 1)?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether the output of [`render`] redraws the whole terminal, as opposed to e.g. the output of
+/// [`render_slot`].
+pub(crate) fn is_full_frame(buf: &[u8]) -> bool {
+    buf.windows(CLEAR_FRAME.len())
+        .take(32)
+        .any(|it| it == CLEAR_FRAME)
+}
+
+/// Kitty limits the payload of a single escape code, so larger data is sent in chunks.
+const TRANSMISSION_CHUNK: usize = 4096;
+const TRANSMISSION_START: &[u8] = b"\x1b_Ga=t,";
+
+fn write_transmission(writer: &mut impl Write, image: &ImageRepr) -> std::io::Result<()> {
+    use base64::Engine as _;
+
+    // https://sw.kovidgoyal.net/kitty/graphics-protocol/#control-data-reference
+    // - a=t: Transmit without displaying
+    // - f=32: 32-bit RGBA
+    // - q=2: Suppress responses, which would otherwise be read as input
+    // - m=1: More chunks follow
+    let data = base64::engine::general_purpose::STANDARD.encode(&image.rgba);
+    let mut chunks = data.as_bytes().chunks(TRANSMISSION_CHUNK).peekable();
+    let mut first = true;
+    while let Some(chunk) = chunks.next() {
+        let more = u8::from(chunks.peek().is_some());
+        if first {
+            writer.write_all(TRANSMISSION_START)?;
+            write!(
+                writer,
+                "f=32,q=2,i={},s={},v={},m={more};",
+                image.id, image.width, image.height,
+            )?;
+            first = false;
+        } else {
+            write!(writer, "\x1b_Gm={more};")?;
+        }
+        writer.write_all(chunk)?;
+        write!(writer, "\x1b\\")?;
+    }
+    Ok(())
+}
+
+/// Finds the image transmissions in rendered output, so that they can be sent to a new terminal
+/// that should show the same content.
+pub(crate) fn image_transmissions(buf: &[u8]) -> Vec<(NonZeroU32, &[u8])> {
+    const APC_END: &[u8] = b"\x1b\\";
+    fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+        haystack.windows(needle.len()).position(|it| it == needle)
+    }
+
+    let mut found = Vec::new();
+    let mut rest = buf;
+    while let Some(start) = find(rest, TRANSMISSION_START) {
+        rest = &rest[start..];
+        let control = &rest[TRANSMISSION_START.len()..];
+        let id = control
+            .split(|&b| b == b',' || b == b';')
+            .find_map(|it| it.strip_prefix(b"i="))
+            .and_then(|it| std::str::from_utf8(it).ok()?.parse().ok());
+
+        // The transmission ends with the first chunk without m=1
+        let mut len = 0;
+        while let Some(end) = find(&rest[len..], APC_END) {
+            let chunk = &rest[len..len + end];
+            len += end + APC_END.len();
+            let chunk_control = chunk.split(|&b| b == b';').next().unwrap_or_default();
+            let chunk_control = chunk_control
+                .strip_prefix(b"\x1b_G")
+                .unwrap_or(chunk_control);
+            if !chunk_control.split(|&b| b == b',').any(|it| it == b"m=1") {
+                break;
+            }
+        }
+        if len == 0 {
+            break;
+        }
+        if let Some(id) = id {
+            found.push((id, &rest[..len]));
+        }
+        rest = &rest[len..];
+    }
+    found
+}
+
+/// Redraws only the area of a slot in a previously rendered layout with new content.
+///
+/// The content should have the same minimum size as the old one, so that the rest of the layout
+/// is unaffected.
+pub(crate) fn render_slot(
+    content: &Elem,
+    area: Area,
+    writer: &mut impl Write,
+    sizing: &SizingArgs,
+    layout: &mut RenderedLayout,
+) -> std::io::Result<()> {
+    let mut slot_layout = RenderedLayout {
+        widgets: Default::default(),
+        slots: Default::default(),
+        leaves: Default::default(),
+        diffable: false,
+        diffs_since_full: 0,
+        images: std::mem::take(&mut layout.images),
+        placements: Default::default(),
+        last_mouse_pos: layout.last_mouse_pos,
+        last_hover_elem: None,
+        dragging: None,
+        pressed: layout.pressed.clone(),
+        inputs: std::mem::take(&mut layout.inputs),
+        input_areas: Default::default(),
+        focused_input: layout.focused_input.clone(),
+        transitions: Default::default(),
+        animating: false,
+        collapses: Default::default(),
+        finish_transitions: layout.finish_transitions,
+        overlays: Default::default(),
+        check_sizes: layout.check_sizes,
+        undersized: Default::default(),
+        dimmed: layout.dimmed,
+        hints: layout.hints,
+    };
+    let now = Instant::now();
+    let sizing = &sizing.at(layout, now);
+    let mut ctx = RenderCtx {
+        sizing,
+        layout: &mut slot_layout,
+        transmissions: Vec::new(),
+        interact_depth: 0,
+        styles: Vec::new(),
+        prev_transitions: &layout.transitions,
+        prev_collapses: &layout.collapses,
+        now,
+        opacity: 1.0,
+        dim: layout.dimmed,
+        highlight: false,
+        parent_fits: true,
+        cut_off_axis: None,
+    };
+    let res = content.render(&mut ctx, area);
+    let RenderCtx { transmissions, .. } = ctx;
+    layout.images = std::mem::take(&mut slot_layout.images);
+    layout.inputs = std::mem::take(&mut slot_layout.inputs);
+    layout.transitions.extend(slot_layout.transitions.drain());
+    layout.collapses.extend(slot_layout.collapses.drain());
+    layout.animating |= slot_layout.animating;
+    res?;
+
+    crossterm::queue!(writer, crossterm::terminal::BeginSynchronizedUpdate)?;
+    writer.write_all(&transmissions)?;
+    clear_area(writer, area)?;
+    for (_, buf) in &slot_layout.leaves {
+        writer.write_all(buf)?;
+    }
+
+    layout.widgets.retain(|&(it, _)| !area.contains_area(it));
+    layout.widgets.extend(slot_layout.widgets);
+    layout
+        .input_areas
+        .retain(|&(it, _)| !area.contains_area(it));
+    layout.input_areas.extend(slot_layout.input_areas);
+    layout.forget_hidden_inputs();
+    layout
+        .slots
+        .retain(|&(it, _)| it == area || !area.contains_area(it));
+    layout.slots.extend(slot_layout.slots);
+    layout.placements.retain(|&(it, _)| !area.contains_area(it));
+    layout.placements.extend(slot_layout.placements);
+    layout.overlays.retain(|&it| !area.contains_area(it));
+    layout.overlays.extend(slot_layout.overlays);
+    layout
+        .images
+        .evict(layout.placements.iter().map(|&(_, id)| id), &mut *writer)?;
+    // Keep the leaves in render order, so that the next render can still diff against them
+    let first_leaf = layout
+        .leaves
+        .iter()
+        .position(|&(it, _)| area.contains_area(it))
+        .unwrap_or(layout.leaves.len());
+    layout.leaves.retain(|&(it, _)| !area.contains_area(it));
+    layout
+        .leaves
+        .splice(first_leaf..first_leaf, slot_layout.leaves);
+    layout.diffs_since_full = layout.diffs_since_full.saturating_add(1);
+    if slot_layout.last_hover_elem.is_some() {
+        layout.last_hover_elem = slot_layout.last_hover_elem;
+    }
+
+    crossterm::execute!(writer, crossterm::terminal::EndSynchronizedUpdate)
+}
+
+/// Labels the `idx`th interactive element with its [`RenderedLayout::hint_target`] key, in the
+/// top left corner of its area.
+fn hint_label(ctx: &mut RenderCtx, area: Area, idx: usize) -> std::io::Result<()> {
+    let Some(&key) = HINT_KEYS.get(idx) else {
+        if idx == HINT_KEYS.len() {
+            log::warn!(
+                "Only the first {} interactive elements can be selected by key",
+                HINT_KEYS.len()
+            );
+        }
+        return Ok(());
+    };
+    // The label covers the content of the element
+    ctx.layout.overlays.push(area);
+    let label = Area {
+        pos: area.pos,
+        size: Vec2 { x: 1, y: 1 },
+    };
+    ctx.leaf(label, |buf| {
+        crossterm::queue!(buf, crossterm::cursor::MoveTo(area.pos.x, area.pos.y))?;
+        // Bold black on yellow
+        write!(buf, "\x1b[0;1;30;43m{}\x1b[0m", char::from(key))
+    })
+}
+
 impl Render for Elem {
-    fn render(&self, ctx: &mut RenderCtx<impl Write>, area: Area) -> std::io::Result<()> {
-        self.0.render(ctx, area)
+    fn render(&self, ctx: &mut RenderCtx, area: Area) -> std::io::Result<()> {
+        let cut_off_axis = ctx.cut_off_axis.take();
+        if !ctx.layout.check_sizes {
+            return self.0.render(ctx, area);
+        }
+        let min_size = self.calc_min_size(ctx.sizing);
+        let height = self.calc_height_for_width(ctx.sizing, area.size.x);
+        // Wider elements may need fewer rows, like wrapped text
+        let fits = area.size.x >= min_size.x && area.size.y >= min_size.y.min(height);
+        if ctx.parent_fits && !fits && cut_off_axis.is_none() {
+            ctx.layout.undersized.push(area);
+        }
+        // Children are arranged by the height for the width, which may exceed the min size if
+        // an item is cut off
+        let fits = area.size.x >= min_size.x && area.size.y >= height;
+        let parent_fits = std::mem::replace(&mut ctx.parent_fits, fits);
+        let res = self.0.render(ctx, area);
+        ctx.parent_fits = parent_fits;
+        res
     }
     fn calc_min_size(&self, args: &SizingArgs) -> Vec2<u16> {
         self.0.calc_min_size(args)
     }
+    fn calc_height_for_width(&self, args: &SizingArgs, width: u16) -> u16 {
+        self.0.calc_height_for_width(args, width)
+    }
 }
 impl Render for ElemRepr {
-    fn render(&self, ctx: &mut RenderCtx<impl Write>, area: Area) -> std::io::Result<()> {
-        crossterm::queue!(
-            ctx.writer,
-            crossterm::cursor::MoveTo(area.pos.x, area.pos.y),
-        )?;
+    fn render(&self, ctx: &mut RenderCtx, area: Area) -> std::io::Result<()> {
         match self {
             Self::Stack(repr) => repr.render(ctx, area),
-            Self::Print(PrintRepr { raw }) => {
+            Self::Print(PrintRepr { raw, trusted }) => {
                 if raw.starts_with(b"\x1b_") {
                     log::debug!("{area:?}");
                 }
-                ctx.writer.write_all(raw)
+                let raw = if *trusted {
+                    raw.into()
+                } else {
+                    super::sanitize::sanitize(raw)
+                };
+                // Raw output that sets its own attributes may reset the faintness
+                let sgr = ctx.plain_sgr();
+                ctx.leaf(area, |buf| {
+                    crossterm::queue!(buf, crossterm::cursor::MoveTo(area.pos.x, area.pos.y))?;
+                    buf.write_all(sgr.as_bytes())?;
+                    buf.write_all(&raw)?;
+                    if !sgr.is_empty() {
+                        buf.write_all(b"\x1b[0m")?;
+                    }
+                    Ok(())
+                })
             }
             Self::MinSize(MinSizeRepr { elem, .. }) => elem.render(ctx, area),
             Self::Interact(repr) => {
+                let idx = ctx.layout.widgets.len();
                 ctx.layout.insert(area, repr);
 
                 let hovered = if ctx
@@ -90,33 +791,343 @@ impl Render for ElemRepr {
                     .last_mouse_pos
                     .is_some_and(|it| area.contains(it))
                 {
-                    if ctx.layout.last_hover_elem.is_some() {
-                        log::warn!("Nested interactivity is unsupported");
-                        None
-                    } else {
+                    if ctx.layout.last_hover_elem.is_none() {
                         ctx.layout.last_hover_elem = Some(StoredInteractive::new(repr));
-                        repr.hovered.as_ref()
+                        repr.pressed
+                            .as_ref()
+                            .filter(|_| ctx.layout.pressed.as_ref() == Some(&repr.tag))
+                            .or(repr.hovered.as_ref())
+                    } else {
+                        // Otherwise, it is covered by a higher layer of an overlay
+                        if ctx.interact_depth > 0 {
+                            log::warn!("Nested interactivity is unsupported");
+                        }
+                        None
                     }
                 } else {
                     None
                 };
 
-                hovered.unwrap_or(&repr.normal).render(ctx, area)
+                let outer = ctx.highlight;
+                ctx.highlight = ctx.layout.hints;
+                ctx.interact_depth += 1;
+                let res = hovered.unwrap_or(&repr.normal).render(ctx, area);
+                ctx.interact_depth -= 1;
+                ctx.highlight = outer;
+                res?;
+
+                if ctx.layout.hints && area.size.x > 0 && area.size.y > 0 {
+                    hint_label(ctx, area, idx)?;
+                }
+                Ok(())
             }
-            Self::Fill(FillRepr { symbol }) => {
-                log::debug!("{symbol:?}, {area:?}");
-                for y_off in 0..area.size.y {
-                    crossterm::queue!(
-                        ctx.writer,
-                        crossterm::cursor::MoveTo(area.pos.x, area.pos.y.saturating_add(y_off))
-                    )?;
-                    for _ in 0..area.size.x {
-                        ctx.writer.write_all(symbol.as_bytes())?;
+            Self::Text(TextRepr { text, style }) => {
+                let sgr = ctx.text_sgr(style);
+                ctx.leaf(area, |buf| {
+                    for (y_off, line) in (0..area.size.y).zip(text_lines(text)) {
+                        let (line, rtl) = super::bidi::reorder(&line);
+                        crossterm::queue!(
+                            buf,
+                            crossterm::cursor::MoveTo(area.pos.x, area.pos.y.saturating_add(y_off))
+                        )?;
+                        buf.write_all(sgr.as_bytes())?;
+                        // Clip to the area, since the terminal would continue on the next line.
+                        // Right-to-left lines lose their left end, which is the end of the text.
+                        let text_width = &ctx.sizing.text_width;
+                        let mut excess = if rtl {
+                            text_width.str(&line).saturating_sub(area.size.x.into())
+                        } else {
+                            0
+                        };
+                        let mut width = 0;
+                        for grapheme in
+                            unicode_segmentation::UnicodeSegmentation::graphemes(&*line, true)
+                        {
+                            let grapheme_width = text_width.grapheme(grapheme);
+                            if excess > 0 {
+                                excess = excess.saturating_sub(grapheme_width);
+                                continue;
+                            }
+                            width += grapheme_width;
+                            if width > area.size.x.into() {
+                                break;
+                            }
+                            match text_width.overridden(grapheme) {
+                                // Make the terminal advance by the same number of cells, with
+                                // the glyph centered in them, using kitty's text sizing protocol
+                                Some(cells @ 1..) => {
+                                    write!(buf, "\x1b]66;w={cells}:n=1:d=1:h=2;{grapheme}\x07")?
+                                }
+                                _ => buf.write_all(grapheme.as_bytes())?,
+                            }
+                        }
+                        if !sgr.is_empty() {
+                            buf.write_all(b"\x1b[0m")?;
+                        }
+                    }
+                    Ok(())
+                })
+            }
+            Self::Transition(TransitionRepr {
+                elem,
+                key,
+                kind,
+                duration_ms,
+            }) => {
+                let progress = ctx.transition_progress(key, *duration_ms);
+                match *kind {
+                    TransitionKind::Fade => {
+                        let outer = ctx.opacity;
+                        ctx.opacity *= progress;
+                        let res = elem.render(ctx, area);
+                        ctx.opacity = outer;
+                        res
+                    }
+                    TransitionKind::Slide { from, distance } => {
+                        let offset = (f32::from(distance) * (1.0 - progress)).round() as u16;
+                        elem.render(ctx, slide_area(area, from, offset))
+                    }
+                }
+            }
+            Self::Collapsible(CollapsibleRepr {
+                elem,
+                key,
+                shown,
+                space,
+            }) => {
+                let fraction = match *space {
+                    HiddenSpaceRepr::Reserve => f32::from(u8::from(*shown)),
+                    HiddenSpaceRepr::Collapse {
+                        axis: _,
+                        duration_ms,
+                    } => {
+                        let fraction = ctx.sizing.collapse_fraction(key, *shown);
+                        let duration = Duration::from_millis(duration_ms.into());
+                        let state = CollapseState::update(
+                            ctx.prev_collapses.get(key).copied(),
+                            *shown,
+                            duration,
+                            ctx.now,
+                        );
+                        if state.fraction(ctx.now) != f32::from(u8::from(*shown)) {
+                            ctx.layout.animating = true;
+                        }
+                        ctx.layout.collapses.insert(key.clone(), state);
+                        fraction
+                    }
+                };
+                if *shown && fraction >= 1.0 {
+                    elem.render(ctx, area)
+                } else {
+                    Ok(())
+                }
+            }
+            Self::StyleContext(StyleContextRepr { elem, ctx: style }) => {
+                ctx.styles.push(style.clone());
+                let res = elem.render(ctx, area);
+                ctx.styles.pop();
+                res
+            }
+            Self::GlyphWidths(GlyphWidthsRepr { elem, widths }) => {
+                let sizing = ctx.sizing.with_glyph_widths(widths);
+                ctx.with_sizing(&sizing, |ctx| elem.render(ctx, area))
+            }
+            Self::Aligned(AlignedRepr { elem, align }) => {
+                let min_size = elem.calc_min_size(ctx.sizing);
+                let width = min_size.x.min(area.size.x);
+                let size = Vec2 {
+                    x: width,
+                    y: elem
+                        .calc_height_for_width(ctx.sizing, width)
+                        .min(area.size.y),
+                };
+                let offset = |align: Align, axis: Axis| {
+                    let space = area.size[axis] - size[axis];
+                    match align {
+                        Align::Start => 0,
+                        Align::Center => space / 2,
+                        Align::End => space,
                     }
+                };
+                let pos = Vec2 {
+                    x: area.pos.x.saturating_add(offset(align.x, Axis::X)),
+                    y: area.pos.y.saturating_add(offset(align.y, Axis::Y)),
+                };
+                elem.render(ctx, Area { pos, size })
+            }
+            Self::Overlay(OverlayRepr { layers }) => {
+                // The layers are rendered from the top, so that the topmost interactive layer
+                // under the mouse is the hovered one. Their output is then put back in order, so
+                // that higher layers are drawn over lower ones.
+                ctx.layout.overlays.push(area);
+                let mut rendered = Vec::with_capacity(layers.len());
+                for layer in layers.iter().rev() {
+                    let leaves = ctx.layout.leaves.len();
+                    let widgets = ctx.layout.widgets.len();
+                    layer.render(ctx, area)?;
+                    rendered.push((
+                        ctx.layout.leaves.split_off(leaves),
+                        ctx.layout.widgets.split_off(widgets),
+                    ));
+                }
+                for (leaves, widgets) in rendered.into_iter().rev() {
+                    ctx.layout.leaves.extend(leaves);
+                    ctx.layout.widgets.extend(widgets);
                 }
                 Ok(())
             }
+            Self::Fill(FillRepr { symbol }) => {
+                log::debug!("{symbol:?}, {area:?}");
+                let sgr = ctx.plain_sgr();
+                ctx.leaf(area, |buf| {
+                    buf.write_all(sgr.as_bytes())?;
+                    for y_off in 0..area.size.y {
+                        crossterm::queue!(
+                            buf,
+                            crossterm::cursor::MoveTo(area.pos.x, area.pos.y.saturating_add(y_off))
+                        )?;
+                        for _ in 0..area.size.x {
+                            buf.write_all(symbol.as_bytes())?;
+                        }
+                    }
+                    if !sgr.is_empty() {
+                        buf.write_all(b"\x1b[0m")?;
+                    }
+                    Ok(())
+                })
+            }
             Self::MinAxis(repr) => repr.render(ctx, area),
+            Self::Slot(SlotRepr { id, elem }) => {
+                ctx.layout.slots.push((area, id.clone()));
+                elem.render(ctx, area)
+            }
+            Self::Image(ImageElemRepr { image, axis, len }) => {
+                let mut cells = Vec2::default();
+                cells[*axis] = Some(*len);
+                place_image(ctx, image, area, cells)
+            }
+            Self::Svg(SvgElemRepr { svg, mode }) => {
+                let font_size = ctx.sizing.font_size;
+                let pixels = |cells: u16, font_len: u16| u32::from(cells) * u32::from(font_len);
+                let (size, cells) = match *mode {
+                    ImageSizeMode::Axis { axis, len } => {
+                        let aspect = svg::svg_aspect(&svg.0);
+                        let mut size = Vec2::default();
+                        size[axis] = pixels(len, font_size[axis]);
+                        size[axis.flip()] = (u64::from(size[axis])
+                            * u64::from(aspect[axis.flip()])
+                            / u64::from(aspect[axis]))
+                        .try_into()
+                        .unwrap_or(u32::MAX);
+                        let mut cells = Vec2::default();
+                        cells[axis] = Some(len);
+                        (size, cells)
+                    }
+                    ImageSizeMode::Cells(Size { width, height }) => (
+                        Vec2 {
+                            x: pixels(width, font_size.x),
+                            y: pixels(height, font_size.y),
+                        },
+                        Vec2 {
+                            x: Some(width),
+                            y: Some(height),
+                        },
+                    ),
+                };
+                match svg::rasterize(&svg.0, size) {
+                    Some(image) => place_image(ctx, &image, area, cells),
+                    None => Ok(()),
+                }
+            }
+            Self::Slider(SliderRepr {
+                frac,
+                track,
+                filled,
+            }) => {
+                if area.size.x == 0 || area.size.y == 0 {
+                    return Ok(());
+                }
+                let handle = (frac.clamp(0.0, 1.0) * f32::from(area.size.x - 1)).round() as u16;
+                let track_sgr = ctx.text_sgr(track);
+                let filled_sgr = ctx.text_sgr(filled);
+                ctx.leaf(area, |buf| {
+                    crossterm::queue!(buf, crossterm::cursor::MoveTo(area.pos.x, area.pos.y))?;
+                    buf.write_all(filled_sgr.as_bytes())?;
+                    for _ in 0..handle {
+                        buf.write_all("━".as_bytes())?;
+                    }
+                    buf.write_all("●".as_bytes())?;
+                    buf.write_all(b"\x1b[0m")?;
+                    buf.write_all(track_sgr.as_bytes())?;
+                    for _ in handle + 1..area.size.x {
+                        buf.write_all("─".as_bytes())?;
+                    }
+                    buf.write_all(b"\x1b[0m")
+                })
+            }
+            Self::Input(InputRepr {
+                tag,
+                text,
+                placeholder,
+                style,
+                width: _,
+            }) => {
+                if area.size.x == 0 || area.size.y == 0 {
+                    return Ok(());
+                }
+                ctx.layout.input_areas.push((area, tag.clone()));
+                let focused = ctx.layout.focused_input.as_ref() == Some(tag);
+                let segments =
+                    ctx.layout
+                        .input_state(tag, text)
+                        .segments(area.size.x, focused, placeholder);
+                let sgr = ctx.text_sgr(style);
+                let placeholder_sgr = ctx.text_sgr(&TextStyle {
+                    fg: Some(Color::Palette(8)),
+                    ..style.clone()
+                });
+                ctx.leaf(area, |buf| {
+                    crossterm::queue!(buf, crossterm::cursor::MoveTo(area.pos.x, area.pos.y))?;
+                    for (segment, text) in segments {
+                        match segment {
+                            InputSegment::Text => buf.write_all(sgr.as_bytes())?,
+                            InputSegment::Placeholder => {
+                                buf.write_all(placeholder_sgr.as_bytes())?
+                            }
+                            // Reversed, so that they are visible in any color
+                            InputSegment::Selected | InputSegment::Cursor => {
+                                buf.write_all(sgr.as_bytes())?;
+                                buf.write_all(b"\x1b[7m")?;
+                            }
+                        }
+                        buf.write_all(text.as_bytes())?;
+                        buf.write_all(b"\x1b[0m")?;
+                    }
+                    Ok(())
+                })
+            }
+            Self::Wrapped(WrappedRepr {
+                text,
+                width_hint: _,
+                mode,
+            }) => {
+                let sgr = ctx.plain_sgr();
+                ctx.leaf(area, |buf| {
+                    let lines = wrap::wrap_lines(text, area.size.x, *mode, &ctx.sizing.text_width);
+                    buf.write_all(sgr.as_bytes())?;
+                    for (y_off, line) in (0..area.size.y).zip(lines) {
+                        crossterm::queue!(
+                            buf,
+                            crossterm::cursor::MoveTo(area.pos.x, area.pos.y.saturating_add(y_off))
+                        )?;
+                        buf.write_all(line.as_bytes())?;
+                    }
+                    if !sgr.is_empty() {
+                        buf.write_all(b"\x1b[0m")?;
+                    }
+                    Ok(())
+                })
+            }
         }
     }
     fn calc_min_size(&self, args: &SizingArgs) -> Vec2<u16> {
@@ -129,12 +1140,193 @@ impl Render for ElemRepr {
             Self::Interact(repr) => repr.normal.calc_min_size(args),
             Self::Fill(_) => Vec2::default(),
             Self::MinAxis(repr) => repr.calc_min_size(args),
+            Self::Slot(SlotRepr { elem, .. }) => elem.calc_min_size(args),
+            Self::Image(_) => Vec2::default(),
+            Self::Slider(_) => Vec2 {
+                x: SLIDER_MIN_WIDTH,
+                y: 1,
+            },
+            &Self::Input(InputRepr { width, .. }) => Vec2 { x: width, y: 1 },
+            Self::Svg(SvgElemRepr { svg, mode }) => match *mode {
+                ImageSizeMode::Axis { axis, len } => {
+                    axis_min_size(axis, len, svg::svg_aspect(&svg.0), args)
+                }
+                ImageSizeMode::Cells(size) => size.into(),
+            },
+            &Self::Wrapped(WrappedRepr {
+                ref text,
+                width_hint,
+                mode,
+            }) => Vec2 {
+                x: width_hint,
+                y: wrap::wrapped_height(text, width_hint, mode, &args.text_width),
+            },
+            Self::Aligned(AlignedRepr { elem, .. }) => elem.calc_min_size(args),
+            Self::StyleContext(StyleContextRepr { elem, .. }) => elem.calc_min_size(args),
+            Self::GlyphWidths(GlyphWidthsRepr { elem, widths }) => {
+                elem.calc_min_size(&args.with_glyph_widths(widths))
+            }
+            Self::Transition(TransitionRepr { elem, .. }) => elem.calc_min_size(args),
+            Self::Collapsible(CollapsibleRepr {
+                elem,
+                key,
+                shown,
+                space,
+            }) => {
+                let size = elem.calc_min_size(args);
+                match *space {
+                    HiddenSpaceRepr::Reserve => size,
+                    HiddenSpaceRepr::Collapse { axis, .. } => {
+                        let fraction = args.collapse_fraction(key, *shown);
+                        let mut size = size;
+                        size[axis] = (f32::from(size[axis]) * fraction).round() as u16;
+                        size
+                    }
+                }
+            }
+            Self::Text(TextRepr { text, style: _ }) => {
+                text_lines(text).fold(Vec2::default(), |size, line| Vec2 {
+                    x: size
+                        .x
+                        .max(args.text_width.str(&line).try_into().unwrap_or(u16::MAX)),
+                    y: size.y.saturating_add(1),
+                })
+            }
+            Self::Overlay(OverlayRepr { layers }) => layers
+                .iter()
+                .map(|it| it.calc_min_size(args))
+                .fold(Vec2::default(), |acc, it| acc.combine(it, std::cmp::max)),
+        }
+    }
+    fn calc_height_for_width(&self, args: &SizingArgs, width: u16) -> u16 {
+        match self {
+            Self::Stack(repr) => repr.calc_height_for_width(args, width),
+            Self::MinSize(MinSizeRepr { elem, size }) => {
+                elem.calc_height_for_width(args, width).max(size.y)
+            }
+            Self::Interact(repr) => repr.normal.calc_height_for_width(args, width),
+            Self::MinAxis(repr) => repr.calc_height_for_width(args, width),
+            Self::Slot(SlotRepr { elem, .. }) => elem.calc_height_for_width(args, width),
+            &Self::Wrapped(WrappedRepr {
+                ref text,
+                width_hint: _,
+                mode,
+            }) => wrap::wrapped_height(text, width, mode, &args.text_width),
+            // Aligned elements are no wider than their min size, see `render`
+            Self::Aligned(AlignedRepr { elem, .. }) => {
+                let width = elem.calc_min_size(args).x.min(width);
+                elem.calc_height_for_width(args, width)
+            }
+            Self::StyleContext(StyleContextRepr { elem, .. })
+            | Self::Transition(TransitionRepr { elem, .. }) => {
+                elem.calc_height_for_width(args, width)
+            }
+            Self::GlyphWidths(GlyphWidthsRepr { elem, widths }) => {
+                elem.calc_height_for_width(&args.with_glyph_widths(widths), width)
+            }
+            Self::Collapsible(CollapsibleRepr {
+                elem, key, shown, ..
+            }) => {
+                if args.collapse_fraction(key, *shown) >= 1.0 {
+                    elem.calc_height_for_width(args, width)
+                } else {
+                    self.calc_min_size(args).y
+                }
+            }
+            Self::Overlay(OverlayRepr { layers }) => layers
+                .iter()
+                .map(|it| it.calc_height_for_width(args, width))
+                .max()
+                .unwrap_or(0),
+            Self::Print(_)
+            | Self::Text(_)
+            | Self::Fill(_)
+            | Self::Image(_)
+            | Self::Svg(_)
+            | Self::Slider(_)
+            | Self::Input(_) => self.calc_min_size(args).y,
         }
     }
 }
 
+/// Places an image scaled to the given number of columns and rows. If only one of them is
+/// given, the aspect ratio is kept.
+fn place_image(
+    ctx: &mut RenderCtx,
+    image: &Image,
+    area: Area,
+    cells: Vec2<Option<u16>>,
+) -> std::io::Result<()> {
+    if !ctx.sizing.images {
+        return ctx.leaf(area, |_| Ok(()));
+    }
+    // Images are often much larger than the cells they are shown in, e.g. tray icons. Since the
+    // terminal cannot show the extra pixels anyway, they are not transmitted.
+    let font_size = ctx.sizing.font_size;
+    let max_pixels = Vec2 {
+        x: cells
+            .x
+            .map_or(u32::MAX, |it| u32::from(it) * u32::from(font_size.x)),
+        y: cells
+            .y
+            .map_or(u32::MAX, |it| u32::from(it) * u32::from(font_size.y)),
+    };
+    let downscaled = downscale::fit(image, max_pixels);
+    let image = &*downscaled.as_ref().unwrap_or(image).0;
+    if ctx.layout.images.insert(image.id) {
+        write_transmission(&mut ctx.transmissions, image)?;
+    }
+    ctx.layout.placements.push((area, image.id));
+    // - a=p: Place a transmitted image
+    // - C=1: Do not move the cursor behind the image after drawing. If the image is on the last
+    //   line, the first line would move to scrollback.
+    ctx.leaf(area, |buf| {
+        crossterm::queue!(buf, crossterm::cursor::MoveTo(area.pos.x, area.pos.y))?;
+        write!(buf, "\x1b_Ga=p,i={},C=1,q=2", image.id)?;
+        if let Some(cols) = cells.x {
+            write!(buf, ",c={cols}")?;
+        }
+        if let Some(rows) = cells.y {
+            write!(buf, ",r={rows}")?;
+        }
+        write!(buf, "\x1b\\")
+    })
+}
+
+/// The size of an element that is `len` cells long along `axis` and has the given aspect ratio.
+fn axis_min_size(axis: Axis, len: u16, aspect: Vec2<u32>, args: &SizingArgs) -> Vec2<u16> {
+    fn widen_mul32(a: u32, b: u32) -> u64 {
+        u64::from(a)
+            .checked_mul(u64::from(b))
+            .expect("u32 multiplication cannot overflow a u64")
+    }
+
+    let mut size = Vec2::default();
+    size[axis] = len;
+
+    // Find the length of the fill axis in pixels
+    let pixel_axis_len = u32::from(len)
+        .checked_mul(u32::from(args.font_size[axis]))
+        .expect("u16 multiplication cannot overflow a u32");
+
+    // Invert the aspect ratio to find the pixel length of the other axis, then find the cell length.
+    //
+    // We assume that the aspect ratio is exact.
+    // Hence, part of the next cell is used when the remainder is nonzero.
+    // Thus we round up. Doing the divisions in one step avoids inaccuracy.
+    size[axis.flip()] = widen_mul32(pixel_axis_len, aspect[axis.flip()])
+        .div_ceil(widen_mul32(
+            aspect[axis],
+            args.font_size[axis.flip()].into(),
+        ))
+        .try_into()
+        .unwrap_or(u16::MAX);
+
+    size
+}
+
 impl Render for MinAxisRepr {
-    fn render(&self, ctx: &mut RenderCtx<impl Write>, area: Area) -> std::io::Result<()> {
+    fn render(&self, ctx: &mut RenderCtx, area: Area) -> std::io::Result<()> {
         self.elem.render(ctx, area)
     }
 
@@ -146,100 +1338,74 @@ impl Render for MinAxisRepr {
             aspect,
         } = self;
 
-        fn widen_mul32(a: u32, b: u32) -> u64 {
-            u64::from(a)
-                .checked_mul(u64::from(b))
-                .expect("u32 multiplication cannot overflow a u64")
-        }
-
-        let mut size = Vec2::default();
-        size[axis] = len;
-
-        // Find the length of the fill axis in pixels
-        let pixel_axis_len = u32::from(len)
-            .checked_mul(u32::from(args.font_size[axis]))
-            .expect("u16 multiplication cannot overflow a u32");
-
-        // Invert the aspect ratio to find the pixel length of the other axis, then find the cell length.
-        //
-        // We assume that the aspect ratio is exact.
-        // Hence, part of the next cell is used when the remainder is nonzero.
-        // Thus we round up. Doing the divisions in one step avoids inaccuracy.
-        size[axis.flip()] = widen_mul32(pixel_axis_len, aspect[axis.flip()])
-            .div_ceil(widen_mul32(
-                aspect[axis],
-                args.font_size[axis.flip()].into(),
-            ))
-            .try_into()
-            .unwrap_or(u16::MAX);
-
+        let size = axis_min_size(axis, len, aspect, args);
         log::debug!("{size:?}");
 
         size.combine(elem.calc_min_size(args), std::cmp::max)
     }
+
+    fn calc_height_for_width(&self, args: &SizingArgs, width: u16) -> u16 {
+        let &Self {
+            ref elem,
+            axis,
+            len,
+            aspect,
+        } = self;
+
+        axis_min_size(axis, len, aspect, args)
+            .y
+            .max(elem.calc_height_for_width(args, width))
+    }
 }
 
-impl Render for StackRepr {
-    fn render(&self, ctx: &mut RenderCtx<impl Write>, area: Area) -> std::io::Result<()> {
-        let mut lens = Vec::with_capacity(self.items.len());
-        let mut total_weight = 0u64;
-        let mut rem_len = Some(area.size[self.axis]);
-        for part in self.items.iter() {
-            total_weight += u64::from(part.fill_weight);
-            let len = part.elem.calc_min_size(ctx.sizing)[self.axis];
-            if let Some(rlen) = rem_len {
-                rem_len = rlen.checked_sub(len);
-            }
-            lens.push(len)
-        }
-        assert_eq!(lens.len(), self.items.len());
+impl StackRepr {
+    /// Measures the items and distributes the space of an area of the given size among them.
+    ///
+    /// In a vertical stack, the items are measured at the width of the area, so that content
+    /// that reflows can take up the lines it needs. Also returns whether the items fit.
+    fn arrange(&self, args: &SizingArgs, size: Vec2<u16>) -> (Vec<u16>, bool) {
+        let requests: Vec<_> = self
+            .items
+            .iter()
+            .map(|part| LenRequest {
+                content: match self.axis {
+                    Axis::X => part.elem.calc_min_size(args).x,
+                    Axis::Y => part.elem.calc_height_for_width(args, size.x),
+                },
+                fill_weight: part.fill_weight,
+                constraint: part.constraint,
+            })
+            .collect();
+        solve_lens(size[self.axis], &requests)
+    }
+}
 
-        let tot_fill_len = rem_len.unwrap_or_else(|| {
+impl Render for StackRepr {
+    fn render(&self, ctx: &mut RenderCtx, area: Area) -> std::io::Result<()> {
+        let (lens, fits) = self.arrange(ctx.sizing, area.size);
+        if !fits {
             log::warn!("Stack does not fit into {area:?}: {self:?}");
-            0
-        });
-
-        if total_weight > 0 {
-            let mut rem_fill_len = tot_fill_len;
-
-            for (part, len) in self.items.iter().zip(&mut lens) {
-                let extra_len = u16::try_from(
-                    u64::from(tot_fill_len) * u64::from(part.fill_weight) / total_weight,
-                )
-                .expect("bounded by render area");
-                *len = len.checked_add(extra_len).unwrap_or_else(|| {
-                    log::error!("Element is way too large");
-                    *len
-                });
-                rem_fill_len = rem_fill_len
-                    .checked_sub(extra_len)
-                    .expect("bounded by partition via floor div");
-            }
-            if rem_fill_len > 0 {
-                let mut fills: Vec<_> = self
-                    .items
-                    .iter()
-                    .zip(&mut lens)
-                    .filter_map(|(part, len)| {
-                        (part.fill_weight > 0).then_some((part.fill_weight, len))
-                    })
-                    .collect();
-                fills.sort();
-                for (_, len) in fills.into_iter().take(rem_fill_len.into()) {
-                    *len += 1;
-                }
-            }
         }
 
         let mut offset = 0;
         for (part, len) in self.items.iter().zip(lens) {
             let mut subarea = area;
+            // Items that do not fit are cut off at the end of the stack
+            let len = len.min(area.size[self.axis].saturating_sub(offset));
             subarea.size[self.axis] = len;
-            subarea.pos[self.axis] += offset;
+            subarea.pos[self.axis] = subarea.pos[self.axis].saturating_add(if self.reverse {
+                area.size[self.axis] - offset - len
+            } else {
+                offset
+            });
 
+            ctx.cut_off_axis = part
+                .constraint
+                .filter(|it| !matches!(it, Constraint::Min(_)))
+                .map(|_| self.axis);
             part.elem.render(ctx, subarea)?;
 
-            offset += len;
+            offset = offset.saturating_add(len);
         }
 
         Ok(())
@@ -249,11 +1415,211 @@ impl Render for StackRepr {
         let mut tot = Vec2::default();
         for part in self.items.iter() {
             let size = part.elem.calc_min_size(args);
+            let len = LenRequest {
+                content: size[self.axis],
+                fill_weight: part.fill_weight,
+                constraint: part.constraint,
+            }
+            .min_len();
 
-            tot[self.axis] = size[self.axis].saturating_add(tot[self.axis]);
+            tot[self.axis] = len.saturating_add(tot[self.axis]);
 
             tot[self.axis.flip()] = size[self.axis.flip()].max(tot[self.axis.flip()]);
         }
         tot
     }
+
+    fn calc_height_for_width(&self, args: &SizingArgs, width: u16) -> u16 {
+        match self.axis {
+            Axis::Y => self.items.iter().fold(0, |tot: u16, part| {
+                let len = LenRequest {
+                    content: part.elem.calc_height_for_width(args, width),
+                    fill_weight: part.fill_weight,
+                    constraint: part.constraint,
+                }
+                .min_len();
+                tot.saturating_add(len)
+            }),
+            Axis::X => {
+                let (lens, _) = self.arrange(
+                    args,
+                    Vec2 {
+                        x: width,
+                        y: u16::MAX,
+                    },
+                );
+                std::iter::zip(&self.items, lens)
+                    .map(|(part, len)| part.elem.calc_height_for_width(args, len))
+                    .max()
+                    .unwrap_or(0)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Elem {
+        let image = Image::from_rgba(2, 2, vec![255; 16]).unwrap();
+        let row = Elem::stack(
+            Axis::X,
+            [
+                Elem::text("left", Default::default()),
+                Elem::fill_cells_single("·"),
+                Elem::image(image, Axis::Y, 1),
+                Elem::text("right", Default::default()).interactive(CustomId::from_bytes(b"r")),
+            ],
+            StackOpts::default(),
+        );
+        Elem::stack(
+            Axis::Y,
+            [
+                row,
+                Elem::wrapped_text("some wrapped text", 8, WrapMode::Word),
+                Elem::overlay([Elem::text("under", Default::default()), Elem::empty()]),
+            ],
+            StackOpts::default(),
+        )
+    }
+
+    const DEGENERATE: [Vec2<u16>; 5] = [
+        Vec2 { x: 0, y: 0 },
+        Vec2 { x: 0, y: 3 },
+        Vec2 { x: 12, y: 0 },
+        Vec2 { x: 1, y: 1 },
+        Vec2 { x: 1, y: 40 },
+    ];
+
+    #[test]
+    fn degenerate_areas() {
+        let sizing = SizingArgs::new(Vec2 { x: 8, y: 16 });
+        for size in DEGENERATE {
+            let grid = render_headless(&sample(), size, &sizing).unwrap();
+            assert_eq!(grid.size(), size);
+            for row in grid.rows().filter(|_| size.x > 0) {
+                assert_eq!(row.len(), usize::from(size.x));
+            }
+        }
+    }
+
+    #[test]
+    fn degenerate_stack_areas() {
+        let sizing = SizingArgs::new(Vec2 { x: 8, y: 16 });
+        let ElemRepr::Stack(stack) = &*sample().0 else {
+            unreachable!()
+        };
+        for size in DEGENERATE {
+            let (lens, fits) = stack.arrange(&sizing, size);
+            assert_eq!(lens.len(), stack.items.len());
+            // Items that do not fit are cut off when rendering
+            assert!(!fits || lens.iter().sum::<u16>() <= size.y);
+            assert!(!fits || size.y >= stack.calc_min_size(&sizing).y);
+            render_headless(&sample(), size, &sizing).unwrap();
+        }
+    }
+
+    #[test]
+    fn overridden_glyph_widths() {
+        let elem = Elem::stack(
+            Axis::X,
+            [
+                symbols::Symbol::nerd(symbols::TERMINAL).render(),
+                Elem::text("x", Default::default()),
+            ],
+            StackOpts::default(),
+        );
+        let sizing = SizingArgs::new(Vec2 { x: 8, y: 16 });
+        assert_eq!(calc_min_size(&elem, &sizing), Vec2 { x: 3, y: 1 });
+        let grid = render_headless(&elem, Vec2 { x: 3, y: 1 }, &sizing).unwrap();
+        assert_eq!(grid.to_text(false), format!("{}x\n", symbols::TERMINAL));
+    }
+
+    #[test]
+    fn interact_hints() {
+        let size = Vec2 { x: 12, y: 3 };
+        let mut buf = Vec::new();
+        render(
+            &sample(),
+            Area {
+                pos: Vec2::default(),
+                size,
+            },
+            &mut buf,
+            &SizingArgs::new(Vec2 { x: 8, y: 16 }),
+            &RenderedLayout {
+                finish_transitions: true,
+                hints: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mut grid = Grid::new(size);
+        grid.apply(&buf);
+        let row = grid.rows().next().unwrap();
+        let start = row.iter().position(|it| &*it.text == "1").unwrap();
+        assert_eq!(&*row[start].sgr, "\x1b[0;1;30;43m");
+        for cell in &row[start + 1..start + 5] {
+            assert_eq!(&*cell.sgr, "\x1b[7m", "{cell:?}");
+        }
+        // Only the interactive element is highlighted
+        assert!(row[..start].iter().all(|it| it.sgr.is_empty()));
+    }
+
+    #[test]
+    fn dimmed_text() {
+        let size = Vec2 { x: 12, y: 3 };
+        let mut buf = Vec::new();
+        render(
+            &sample(),
+            Area {
+                pos: Vec2::default(),
+                size,
+            },
+            &mut buf,
+            &SizingArgs::new(Vec2 { x: 8, y: 16 }),
+            &RenderedLayout {
+                finish_transitions: true,
+                dimmed: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mut grid = Grid::new(size);
+        grid.apply(&buf);
+        for cell in grid.rows().flatten() {
+            if !cell.text.is_empty() && cell.image.is_none() {
+                assert_eq!(&*cell.sgr, "\x1b[2m", "{cell:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn degenerate_font_sizes() {
+        for cell_size in [0, 1, 30] {
+            for pix_size in [0, 1, 300] {
+                let sizes = Sizes {
+                    cell_size: Vec2 {
+                        x: cell_size,
+                        y: cell_size,
+                    },
+                    pix_size: Vec2 {
+                        x: pix_size,
+                        y: pix_size,
+                    },
+                };
+                let font_size = sizes.font_size();
+                assert!(font_size.x >= 1 && font_size.y >= 1, "{sizes:?}");
+
+                let sizing = SizingArgs::new(font_size);
+                assert!(calc_min_size(&sample(), &sizing).x >= 1);
+                render_headless(&sample(), sizes.cell_size, &sizing).unwrap();
+            }
+        }
+        // Sizing arguments that do not come from `Sizes::font_size`
+        let sizing = SizingArgs::new(Vec2 { x: 0, y: 0 });
+        calc_min_size(&sample(), &sizing);
+        render_headless(&sample(), Vec2 { x: 20, y: 5 }, &sizing).unwrap();
+    }
 }
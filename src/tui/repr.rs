@@ -12,6 +12,19 @@ pub(crate) enum ElemRepr {
     Fill(FillRepr),
     MinSize(MinSizeRepr),
     MinAxis(MinAxisRepr),
+    Slot(SlotRepr),
+    Image(ImageElemRepr),
+    Svg(SvgElemRepr),
+    Wrapped(WrappedRepr),
+    Overlay(OverlayRepr),
+    Aligned(AlignedRepr),
+    Text(TextRepr),
+    StyleContext(StyleContextRepr),
+    Slider(SliderRepr),
+    Input(InputRepr),
+    Transition(TransitionRepr),
+    Collapsible(CollapsibleRepr),
+    GlyphWidths(GlyphWidthsRepr),
 }
 
 impl From<ElemRepr> for Elem {
@@ -28,6 +41,7 @@ pub(crate) struct MinSizeRepr {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct StackItemRepr {
     pub fill_weight: u16,
+    pub constraint: Option<Constraint>,
     pub elem: Elem,
 }
 #[derive(Debug, Serialize, Deserialize)]
@@ -35,12 +49,51 @@ pub(crate) struct InteractRepr {
     pub tag: CustomId,
     pub normal: Elem,
     pub hovered: Option<Elem>,
+    /// Shown while the left mouse button is held down on the element.
+    pub pressed: Option<Elem>,
+    pub widget: Option<WidgetRepr>,
+}
+/// Changes how the mouse events on an interactive element are reported.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub(crate) enum WidgetRepr {
+    /// Left clicks are reported as [`InteractKind::Toggle`].
+    Checkbox { checked: bool },
+    /// Left clicks are reported as [`InteractKind::Toggle`].
+    Radio { selected: bool },
+    /// Left clicks, drags and scrolling are reported as [`InteractKind::ValueChange`].
+    Slider { value: f32, min: f32, max: f32 },
+}
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct SliderRepr {
+    /// The position of the handle, from `0.0` to `1.0`.
+    pub frac: f32,
+    pub track: TextStyle,
+    pub filled: TextStyle,
+}
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct InputRepr {
+    pub tag: CustomId,
+    pub text: String,
+    pub placeholder: String,
+    pub style: TextStyle,
+    pub width: u16,
+}
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct SlotRepr {
+    pub id: CustomId,
+    pub elem: Elem,
 }
 #[derive(Debug, Serialize, Deserialize)]
 pub(crate) struct FillRepr {
     pub symbol: String,
 }
 #[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct WrappedRepr {
+    pub text: String,
+    pub width_hint: u16,
+    pub mode: WrapMode,
+}
+#[derive(Debug, Serialize, Deserialize)]
 pub(crate) struct MinAxisRepr {
     pub elem: Elem,
     pub axis: Axis,
@@ -48,18 +101,334 @@ pub(crate) struct MinAxisRepr {
     pub aspect: Vec2<u32>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ImageElemRepr {
+    pub image: Image,
+    pub axis: Axis,
+    pub len: u16,
+}
+#[derive(Serialize, Deserialize)]
+pub(crate) struct ImageRepr {
+    /// The kitty graphics protocol image id, derived from the content.
+    pub id: std::num::NonZeroU32,
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+impl std::fmt::Debug for ImageRepr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Image")
+            .field("id", &self.id)
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct SvgElemRepr {
+    pub svg: Svg,
+    pub mode: ImageSizeMode,
+}
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SvgRepr {
+    /// Identifies the SVG in the host's rasterization cache.
+    pub hash: u32,
+    pub data: Vec<u8>,
+}
+impl std::fmt::Debug for SvgRepr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Svg")
+            .field("hash", &self.hash)
+            .field("len", &self.data.len())
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct TextRepr {
+    pub text: String,
+    pub style: TextStyle,
+}
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct StyleContextRepr {
+    pub elem: Elem,
+    pub ctx: StyleContext,
+}
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct GlyphWidthsRepr {
+    pub elem: Elem,
+    pub widths: Vec<(String, u8)>,
+}
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct TransitionRepr {
+    pub elem: Elem,
+    /// The transition is played when no element with this key was shown in the previous frame.
+    pub key: CustomId,
+    pub kind: TransitionKind,
+    pub duration_ms: u32,
+}
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct CollapsibleRepr {
+    pub elem: Elem,
+    /// Identifies the element across frames, to animate changes of `shown`.
+    pub key: CustomId,
+    pub shown: bool,
+    pub space: HiddenSpaceRepr,
+}
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub(crate) enum HiddenSpaceRepr {
+    Reserve,
+    Collapse { axis: Axis, duration_ms: u32 },
+}
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct AlignedRepr {
+    pub elem: Elem,
+    pub align: Align2D,
+}
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct OverlayRepr {
+    pub layers: Vec<Elem>,
+}
+
 // TODO: Use a DST struct to hold the tail of these
 #[derive(Debug, Serialize, Deserialize)]
 pub(crate) struct StackRepr {
     pub axis: Axis,
+    pub reverse: bool,
     pub items: Vec<StackItemRepr>,
 }
 #[derive(Serialize, Deserialize)]
 pub(crate) struct PrintRepr {
+    #[serde(with = "raw_text")]
     pub raw: Vec<u8>,
+    /// See [`Elem::trusted_raw_print`].
+    #[serde(default)]
+    pub trusted: bool,
+}
+/// Serializes the raw text as a string in human readable formats (i.e. JSON). It is always
+/// created from [`std::fmt::Display`] output, so it is valid UTF-8.
+mod raw_text {
+    use serde::{Deserialize as _, Deserializer, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(raw: &[u8], ser: S) -> Result<S::Ok, S::Error> {
+        if ser.is_human_readable() {
+            ser.serialize_str(&String::from_utf8_lossy(raw))
+        } else {
+            ser.serialize_bytes(raw)
+        }
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(de: D) -> Result<Vec<u8>, D::Error> {
+        if de.is_human_readable() {
+            return String::deserialize(de).map(String::into_bytes);
+        }
+        struct Visitor;
+        impl serde::de::Visitor<'_> for Visitor {
+            type Value = Vec<u8>;
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("bytes")
+            }
+            fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+                Ok(v.to_vec())
+            }
+            fn visit_byte_buf<E: serde::de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+                Ok(v)
+            }
+        }
+        de.deserialize_byte_buf(Visitor)
+    }
 }
 impl std::fmt::Debug for PrintRepr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         std::fmt::Debug::fmt(&self.raw.utf8_chunks(), f)
     }
 }
+
+#[cfg(feature = "__bin")]
+impl Elem {
+    /// Replaces the content of the first slot with the given id, returning the new tree and the
+    /// old content.
+    pub(crate) fn replace_slot(&self, id: &CustomId, content: &Elem) -> Option<(Elem, Elem)> {
+        let (repr, old) = match &*self.0 {
+            ElemRepr::Slot(SlotRepr { id: slot_id, elem }) if slot_id == id => (
+                ElemRepr::Slot(SlotRepr {
+                    id: slot_id.clone(),
+                    elem: content.clone(),
+                }),
+                elem.clone(),
+            ),
+            ElemRepr::Slot(SlotRepr { id: slot_id, elem }) => {
+                let (elem, old) = elem.replace_slot(id, content)?;
+                (
+                    ElemRepr::Slot(SlotRepr {
+                        id: slot_id.clone(),
+                        elem,
+                    }),
+                    old,
+                )
+            }
+            ElemRepr::Print(_)
+            | ElemRepr::Text(_)
+            | ElemRepr::Fill(_)
+            | ElemRepr::Image(_)
+            | ElemRepr::Svg(_)
+            | ElemRepr::Wrapped(_)
+            | ElemRepr::Slider(_)
+            | ElemRepr::Input(_) => {
+                return None;
+            }
+            ElemRepr::Stack(StackRepr {
+                axis,
+                reverse,
+                items,
+            }) => {
+                let (idx, (elem, old)) = items
+                    .iter()
+                    .enumerate()
+                    .find_map(|(i, item)| Some((i, item.elem.replace_slot(id, content)?)))?;
+                let mut items = items.clone();
+                items[idx].elem = elem;
+                (
+                    ElemRepr::Stack(StackRepr {
+                        axis: *axis,
+                        reverse: *reverse,
+                        items,
+                    }),
+                    old,
+                )
+            }
+            ElemRepr::Overlay(OverlayRepr { layers }) => {
+                let (idx, (elem, old)) = layers
+                    .iter()
+                    .enumerate()
+                    .find_map(|(i, layer)| Some((i, layer.replace_slot(id, content)?)))?;
+                let mut layers = layers.clone();
+                layers[idx] = elem;
+                (ElemRepr::Overlay(OverlayRepr { layers }), old)
+            }
+            ElemRepr::Interact(InteractRepr {
+                tag,
+                normal,
+                hovered,
+                pressed,
+                widget,
+            }) => {
+                // Replace in all of them, so that hovering does not bring back the old content
+                let normal_res = normal.replace_slot(id, content);
+                let hovered_res = hovered
+                    .as_ref()
+                    .and_then(|hovered| hovered.replace_slot(id, content));
+                let pressed_res = pressed
+                    .as_ref()
+                    .and_then(|pressed| pressed.replace_slot(id, content));
+                let old = normal_res
+                    .as_ref()
+                    .or(hovered_res.as_ref())
+                    .or(pressed_res.as_ref())?
+                    .1
+                    .clone();
+                (
+                    ElemRepr::Interact(InteractRepr {
+                        tag: tag.clone(),
+                        normal: normal_res.map_or_else(|| normal.clone(), |(elem, _)| elem),
+                        hovered: hovered_res
+                            .map(|(elem, _)| elem)
+                            .or_else(|| hovered.clone()),
+                        pressed: pressed_res
+                            .map(|(elem, _)| elem)
+                            .or_else(|| pressed.clone()),
+                        widget: *widget,
+                    }),
+                    old,
+                )
+            }
+            ElemRepr::StyleContext(StyleContextRepr { elem, ctx }) => {
+                let (elem, old) = elem.replace_slot(id, content)?;
+                (
+                    ElemRepr::StyleContext(StyleContextRepr {
+                        elem,
+                        ctx: ctx.clone(),
+                    }),
+                    old,
+                )
+            }
+            ElemRepr::GlyphWidths(GlyphWidthsRepr { elem, widths }) => {
+                let (elem, old) = elem.replace_slot(id, content)?;
+                (
+                    ElemRepr::GlyphWidths(GlyphWidthsRepr {
+                        elem,
+                        widths: widths.clone(),
+                    }),
+                    old,
+                )
+            }
+            ElemRepr::Transition(TransitionRepr {
+                elem,
+                key,
+                kind,
+                duration_ms,
+            }) => {
+                let (elem, old) = elem.replace_slot(id, content)?;
+                (
+                    ElemRepr::Transition(TransitionRepr {
+                        elem,
+                        key: key.clone(),
+                        kind: *kind,
+                        duration_ms: *duration_ms,
+                    }),
+                    old,
+                )
+            }
+            ElemRepr::Collapsible(CollapsibleRepr {
+                elem,
+                key,
+                shown,
+                space,
+            }) => {
+                let (elem, old) = elem.replace_slot(id, content)?;
+                (
+                    ElemRepr::Collapsible(CollapsibleRepr {
+                        elem,
+                        key: key.clone(),
+                        shown: *shown,
+                        space: *space,
+                    }),
+                    old,
+                )
+            }
+            ElemRepr::Aligned(AlignedRepr { elem, align }) => {
+                let (elem, old) = elem.replace_slot(id, content)?;
+                (
+                    ElemRepr::Aligned(AlignedRepr {
+                        elem,
+                        align: *align,
+                    }),
+                    old,
+                )
+            }
+            ElemRepr::MinSize(MinSizeRepr { elem, size }) => {
+                let (elem, old) = elem.replace_slot(id, content)?;
+                (ElemRepr::MinSize(MinSizeRepr { elem, size: *size }), old)
+            }
+            ElemRepr::MinAxis(MinAxisRepr {
+                elem,
+                axis,
+                len,
+                aspect,
+            }) => {
+                let (elem, old) = elem.replace_slot(id, content)?;
+                (
+                    ElemRepr::MinAxis(MinAxisRepr {
+                        elem,
+                        axis: *axis,
+                        len: *len,
+                        aspect: *aspect,
+                    }),
+                    old,
+                )
+            }
+        };
+        Some((repr.into(), old))
+    }
+}
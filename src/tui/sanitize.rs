@@ -0,0 +1,93 @@
+//! Removal of control characters and escape sequences from [`Elem::raw_print`], which could
+//! otherwise move the cursor or change the terminal's state and break the whole panel.
+
+use std::borrow::Cow;
+
+#[derive(Clone, Copy)]
+enum Seq {
+    /// `ESC [` or `CSI`, followed by parameters and a final byte.
+    Csi,
+    /// OSC, DCS, APC, PM and SOS, which run until a string terminator.
+    String,
+    /// `ESC` followed by intermediate bytes and a final byte, e.g. `ESC c`.
+    Esc,
+}
+
+/// Removes C0 and C1 control characters and escape sequences other than SGR sequences
+/// (`ESC [ ... m`). Invalid UTF-8 is replaced.
+pub(crate) fn sanitize(raw: &[u8]) -> Cow<'_, [u8]> {
+    let text = String::from_utf8_lossy(raw);
+    if !text.contains(char::is_control) {
+        return match text {
+            Cow::Borrowed(_) => Cow::Borrowed(raw),
+            Cow::Owned(text) => Cow::Owned(text.into_bytes()),
+        };
+    }
+    let mut sanitized = String::with_capacity(text.len());
+    let mut rest = &*text;
+    while let Some(c) = rest.chars().next() {
+        if !c.is_control() {
+            sanitized.push(c);
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+        let (control, tail) = rest.split_at(control_len(rest));
+        if is_sgr(control) {
+            sanitized.push_str(control);
+        } else {
+            log::trace!("Removed control sequence {control:?}");
+        }
+        rest = tail;
+    }
+    Cow::Owned(sanitized.into_bytes())
+}
+
+/// The length of the escape sequence or control character that `text` starts with.
+fn control_len(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    let (seq, start) = match text.chars().next() {
+        Some('\x1b') => match bytes.get(1) {
+            Some(b'[') => (Seq::Csi, 2),
+            Some(b']' | b'P' | b'_' | b'^' | b'X') => (Seq::String, 2),
+            Some(0x20..=0x7E) => (Seq::Esc, 1),
+            _ => return 1,
+        },
+        // C1 controls are two bytes long in UTF-8
+        Some('\u{9B}') => (Seq::Csi, 2),
+        Some('\u{90}' | '\u{98}' | '\u{9D}' | '\u{9E}' | '\u{9F}') => (Seq::String, 2),
+        Some(c) => return c.len_utf8(),
+        None => return 0,
+    };
+    let skip = |mut i: usize, range: std::ops::RangeInclusive<u8>| {
+        while bytes.get(i).is_some_and(|it| range.contains(it)) {
+            i += 1;
+        }
+        i
+    };
+    match seq {
+        Seq::Csi => {
+            let end = skip(skip(start, 0x30..=0x3F), 0x20..=0x2F);
+            end + usize::from(bytes.get(end).is_some_and(|it| (0x40..=0x7E).contains(it)))
+        }
+        Seq::Esc => {
+            let end = skip(start, 0x20..=0x2F);
+            end + usize::from(bytes.get(end).is_some_and(|it| (0x30..=0x7E).contains(it)))
+        }
+        // Unterminated strings run until the end
+        Seq::String => ["\x07", "\x1b\\", "\u{9C}"]
+            .into_iter()
+            .filter_map(|st| Some(start + text[start..].find(st)? + st.len()))
+            .min()
+            .unwrap_or(text.len()),
+    }
+}
+
+fn is_sgr(seq: &str) -> bool {
+    seq.strip_prefix("\x1b[")
+        .and_then(|it| it.strip_suffix('m'))
+        .is_some_and(|params| {
+            params
+                .chars()
+                .all(|c| c.is_ascii_digit() || c == ';' || c == ':')
+        })
+}
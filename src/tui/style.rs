@@ -0,0 +1,88 @@
+//! Colors that are resolved when rendering, see
+//! [`Elem::with_style_context`](super::Elem::with_style_context).
+
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+/// A color used by [`Elem::text`](super::Elem::text).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum Color {
+    Rgb {
+        r: u8,
+        g: u8,
+        b: u8,
+    },
+    /// An entry of the terminal's 256 color palette.
+    Palette(u8),
+    /// The color that the nearest enclosing [`StyleContext`] with this token assigns to it.
+    /// Without one, the terminal's default color is used.
+    Token(Arc<str>),
+}
+impl Color {
+    pub fn token(name: impl Into<Arc<str>>) -> Self {
+        Self::Token(name.into())
+    }
+}
+
+/// The style of [`Elem::text`](super::Elem::text).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextStyle {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    #[deprecated = warn_non_exhaustive!()]
+    #[doc(hidden)]
+    #[serde(skip)]
+    pub __non_exhaustive_struct_update: (),
+}
+
+/// Assigns colors to tokens for the descendants of an element, see
+/// [`Elem::with_style_context`](super::Elem::with_style_context).
+///
+/// Tokens that are not assigned are looked up in the enclosing contexts. A token may be
+/// assigned to another token, which is then also looked up in the enclosing contexts.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StyleContext {
+    pub(crate) tokens: Vec<(Arc<str>, Color)>,
+}
+impl StyleContext {
+    /// The token for the foreground color in [`StyleContext::theme`].
+    pub const FG: &str = "fg";
+    /// The token for the background color in [`StyleContext::theme`].
+    pub const BG: &str = "bg";
+    /// The token for the accent color in [`StyleContext::theme`].
+    pub const ACCENT: &str = "accent";
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A context that assigns the common tokens [`Self::FG`], [`Self::BG`] and
+    /// [`Self::ACCENT`].
+    pub fn theme(fg: Color, bg: Color, accent: Color) -> Self {
+        Self::new()
+            .with(Self::FG, fg)
+            .with(Self::BG, bg)
+            .with(Self::ACCENT, accent)
+    }
+
+    /// Assigns `color` to `token`, replacing a previous assignment.
+    pub fn with(mut self, token: impl Into<Arc<str>>, color: Color) -> Self {
+        let token = token.into();
+        self.tokens.retain(|(it, _)| *it != token);
+        self.tokens.push((token, color));
+        self
+    }
+
+    #[cfg(feature = "__bin")]
+    pub(crate) fn get(&self, token: &str) -> Option<&Color> {
+        self.tokens
+            .iter()
+            .find_map(|(it, color)| (**it == *token).then_some(color))
+    }
+}
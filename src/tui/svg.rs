@@ -0,0 +1,116 @@
+//! Rasterization of [`Svg`]s for [`Elem::svg`].
+
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex, MutexGuard},
+};
+
+use crate::tui::*;
+
+/// The number of entries after which each map of the cache is cleared, to bound its memory use.
+const MAX_CACHED: usize = 64;
+
+#[derive(Default)]
+struct SvgCache {
+    /// The intrinsic size of each SVG, by hash.
+    aspects: HashMap<u32, Vec2<u32>>,
+    /// The rasterized images by hash and pixel size. `None` if rasterization failed.
+    images: HashMap<(u32, u32, u32), Option<Image>>,
+}
+
+fn cache() -> MutexGuard<'static, SvgCache> {
+    static CACHE: LazyLock<Mutex<SvgCache>> = LazyLock::new(Default::default);
+    CACHE.lock().unwrap_or_else(|poison| poison.into_inner())
+}
+
+/// The aspect ratio of the SVG, or 1:1 if it cannot be parsed.
+pub(super) fn svg_aspect(svg: &SvgRepr) -> Vec2<u32> {
+    if let Some(&aspect) = cache().aspects.get(&svg.hash) {
+        return aspect;
+    }
+    let aspect = imp::intrinsic_size(svg)
+        .filter(|size| size.x > 0 && size.y > 0)
+        .unwrap_or(Vec2 { x: 1, y: 1 });
+
+    let mut cache = cache();
+    if cache.aspects.len() >= MAX_CACHED {
+        cache.aspects.clear();
+    }
+    cache.aspects.insert(svg.hash, aspect);
+    aspect
+}
+
+/// Rasterizes the SVG to exactly `size` pixels.
+pub(super) fn rasterize(svg: &SvgRepr, size: Vec2<u32>) -> Option<Image> {
+    let key = (svg.hash, size.x, size.y);
+    if let Some(image) = cache().images.get(&key) {
+        return image.clone();
+    }
+    let image = imp::render(svg, size);
+
+    let mut cache = cache();
+    if cache.images.len() >= MAX_CACHED {
+        cache.images.clear();
+    }
+    cache.images.insert(key, image.clone());
+    image
+}
+
+#[cfg(feature = "svg")]
+mod imp {
+    use anyhow::Context as _;
+
+    use crate::{tui::*, utils::ResultExt as _};
+
+    fn parse(svg: &SvgRepr) -> Option<resvg::usvg::Tree> {
+        resvg::usvg::Tree::from_data(&svg.data, &resvg::usvg::Options::default())
+            .context("Failed to parse SVG")
+            .ok_or_log()
+    }
+
+    pub(super) fn intrinsic_size(svg: &SvgRepr) -> Option<Vec2<u32>> {
+        let size = parse(svg)?.size();
+        Some(Vec2 {
+            x: size.width().ceil() as u32,
+            y: size.height().ceil() as u32,
+        })
+    }
+
+    pub(super) fn render(svg: &SvgRepr, size: Vec2<u32>) -> Option<Image> {
+        let tree = parse(svg)?;
+        let mut pixmap = resvg::tiny_skia::Pixmap::new(size.x, size.y)?;
+        let tree_size = tree.size();
+        resvg::render(
+            &tree,
+            resvg::tiny_skia::Transform::from_scale(
+                size.x as f32 / tree_size.width(),
+                size.y as f32 / tree_size.height(),
+            ),
+            &mut pixmap.as_mut(),
+        );
+        // The kitty graphics protocol expects straight (not premultiplied) alpha
+        let rgba = pixmap
+            .pixels()
+            .iter()
+            .flat_map(|pixel| {
+                let color = pixel.demultiply();
+                [color.red(), color.green(), color.blue(), color.alpha()]
+            })
+            .collect();
+        Image::from_rgba(size.x, size.y, rgba)
+    }
+}
+
+#[cfg(not(feature = "svg"))]
+mod imp {
+    use crate::tui::*;
+
+    pub(super) fn intrinsic_size(_: &SvgRepr) -> Option<Vec2<u32>> {
+        None
+    }
+
+    pub(super) fn render(_: &SvgRepr, _: Vec2<u32>) -> Option<Image> {
+        log::warn!("Cannot display SVG because the host was built without the svg feature");
+        None
+    }
+}
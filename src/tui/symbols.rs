@@ -0,0 +1,58 @@
+//! Nerd Font glyphs and a helper for displaying symbols.
+//!
+//! Nerd Font glyphs are drawn across two cells, but `unicode-width` reports them as one, so
+//! they would overlap whatever comes after them. [`Symbol`] reserves the cells a symbol is drawn
+//! across and centers the symbol in them, see [`Elem::with_glyph_widths`].
+
+use super::{Elem, TextStyle};
+
+pub const BALANCE_SCALE: &str = "\u{f24e}";
+pub const BOLT: &str = "\u{f0e7}";
+pub const LEAF: &str = "\u{f06c}";
+pub const MICROPHONE: &str = "\u{f130}";
+pub const MICROPHONE_SLASH: &str = "\u{f131}";
+pub const TERMINAL: &str = "\u{f120}";
+pub const VOLUME_HIGH: &str = "\u{f028}";
+pub const VOLUME_XMARK: &str = "\u{eee8}";
+
+/// The number of cells that Nerd Font glyphs are drawn across.
+pub const NERD_FONT_WIDTH: u8 = 2;
+
+#[derive(Clone, Debug)]
+pub struct Symbol {
+    symbol: String,
+    width: u8,
+    style: TextStyle,
+}
+impl Symbol {
+    /// A Nerd Font glyph, centered in [`NERD_FONT_WIDTH`] cells.
+    pub fn nerd(glyph: impl Into<String>) -> Self {
+        Self::with_width(glyph, NERD_FONT_WIDTH)
+    }
+    /// A symbol (e.g. an emoji), centered in as many cells as `unicode-width` reports.
+    pub fn text(symbol: impl Into<String>) -> Self {
+        let symbol = symbol.into();
+        let width = super::width::str_width(&symbol, Default::default());
+        Self::with_width(symbol, width.clamp(1, u8::MAX.into()) as u8)
+    }
+    /// A symbol centered in `width` cells, at least one.
+    pub fn with_width(symbol: impl Into<String>, width: u8) -> Self {
+        Self {
+            symbol: symbol.into(),
+            width: width.max(1),
+            style: TextStyle::default(),
+        }
+    }
+    pub fn with_style(self, style: TextStyle) -> Self {
+        Self { style, ..self }
+    }
+    pub fn render(&self) -> Elem {
+        Elem::text(&self.symbol, self.style.clone())
+            .with_glyph_widths([(self.symbol.clone(), self.width)])
+    }
+}
+impl From<Symbol> for Elem {
+    fn from(symbol: Symbol) -> Self {
+        symbol.render()
+    }
+}
@@ -0,0 +1,164 @@
+//! Utilities for testing the content of bars and menus without a terminal.
+//!
+//! Besides snapshots, [`RenderedElem`] checks the geometry of a layout, which lends itself to
+//! property testing with tools like `proptest`: Random element trees rendered at sizes of at
+//! least their [`min_size`] should always pass [`RenderedElem::check_invariants`], and mouse
+//! events should hit what [`RenderedElem::hit_test`] reports.
+
+use std::fmt::Write as _;
+
+use crate::tui::*;
+
+/// The font size that images and SVGs are scaled with.
+pub const FONT_SIZE: Size = Size {
+    width: 8,
+    height: 16,
+};
+
+/// Renders `elem` into an area of the given size and returns a plain text snapshot of the
+/// result, for use with snapshot testing tools like `insta`.
+///
+/// The snapshot consists of the text of each row, with trailing spaces removed and cells
+/// covered by images shown as `▒`. If any cells have text attributes, a line `styles:` follows,
+/// listing the runs of cells with the same attributes as `<row>:<columns> <SGR parameters>`.
+///
+/// # Panics
+/// If rendering fails, which only happens for elements that are not supported headless.
+pub fn render_to_string(elem: &Elem, size: Size) -> String {
+    let sizing = SizingArgs::new(FONT_SIZE.into());
+    let grid = render_headless(elem, size.into(), &sizing).expect("Failed to render element");
+
+    let mut out = grid.to_text(false);
+    let runs = grid.style_runs();
+    if !runs.is_empty() {
+        out.push_str("styles:\n");
+        for (y, cols, sgr) in runs {
+            let params: Vec<_> = sgr
+                .split("\x1b[")
+                .filter_map(|it| it.strip_suffix('m'))
+                .collect();
+            _ = writeln!(out, "{y}:{}..{} {}", cols.start, cols.end, params.join(";"));
+        }
+    }
+    out
+}
+
+/// Renders `elem` into an area of the given size like a panel does and returns what is written
+/// to the terminal, e.g. to benchmark rendering.
+///
+/// # Panics
+/// If rendering fails, which only happens for elements that are not supported headless.
+pub fn render_to_bytes(elem: &Elem, size: Size) -> Vec<u8> {
+    let mut buf = Vec::new();
+    render(
+        elem,
+        Area {
+            pos: Vec2::default(),
+            size: size.into(),
+        },
+        &mut buf,
+        &SizingArgs::new(FONT_SIZE.into()),
+        &RenderedLayout {
+            finish_transitions: true,
+            ..Default::default()
+        },
+    )
+    .expect("Failed to render element");
+    buf
+}
+
+/// The smallest size that `elem` can be rendered at without cutting it off.
+pub fn min_size(elem: &Elem) -> Size {
+    calc_min_size(elem, &SizingArgs::new(FONT_SIZE.into())).into()
+}
+
+/// An element rendered into an area of a given size, whose layout can be inspected.
+pub struct RenderedElem {
+    layout: RenderedLayout,
+    size: Vec2<u16>,
+}
+impl RenderedElem {
+    /// # Panics
+    /// If rendering fails, which only happens for elements that are not supported headless.
+    pub fn render(elem: &Elem, size: Size) -> Self {
+        let size = size.into();
+        let layout = render_checked(elem, size, &SizingArgs::new(FONT_SIZE.into()))
+            .expect("Failed to render element");
+        Self { layout, size }
+    }
+
+    /// Checks that:
+    /// - Everything is placed within the size that the element was rendered at.
+    /// - The texts, images and other elements without children only overlap within an
+    ///   [`Elem::overlay`].
+    /// - Every element is at least as large as its min size, unless its parent is not. This
+    ///   fails if the size is smaller than [`min_size`].
+    /// - The mouse hits an interactive element that contains it everywhere that there is one.
+    ///
+    /// Returns a description of the first violation.
+    pub fn check_invariants(&self) -> Result<(), String> {
+        self.layout.check_invariants(Area {
+            pos: Vec2::default(),
+            size: self.size,
+        })
+    }
+
+    /// The tag of the interactive element that receives the mouse events at the given column
+    /// and row.
+    pub fn hit_test(&self, column: u16, row: u16) -> Option<CustomId> {
+        self.layout
+            .hit_test(Vec2 { x: column, y: row })
+            .map(|(_, tag)| tag.clone())
+    }
+
+    /// Simulates the mouse interacting with the cell at the given column and row, like
+    /// [`crate::host::testing::SimulatedPanel::mouse`]. Returns the tag of the element that is
+    /// hit and the interaction that the controller receives.
+    pub fn mouse(
+        &mut self,
+        kind: InteractKind,
+        column: u16,
+        row: u16,
+    ) -> Option<(CustomId, InteractKind)> {
+        let event = mouse_event(mouse_event_kind(kind), column, row);
+        match self.layout.interpret_mouse_event(event, FONT_SIZE.into()) {
+            MouseEventRes::Interact(res) => res.tag.map(|tag| (tag, res.kind)),
+            MouseEventRes::MouseLeave => None,
+        }
+    }
+}
+
+/// The mouse event that simulates `kind`. [`InteractKind::Toggle`] and
+/// [`InteractKind::ValueChange`] are simulated as a left click.
+pub(crate) fn mouse_event_kind(kind: InteractKind) -> crossterm::event::MouseEventKind {
+    use crossterm::event::{MouseButton as MB, MouseEventKind as MK};
+
+    match kind {
+        InteractKind::Click(MouseButton::Left)
+        | InteractKind::Toggle(_)
+        | InteractKind::ValueChange(_) => MK::Down(MB::Left),
+        InteractKind::Click(MouseButton::Right) => MK::Down(MB::Right),
+        InteractKind::Click(MouseButton::Middle) => MK::Down(MB::Middle),
+        InteractKind::Scroll(Direction::Up) => MK::ScrollUp,
+        InteractKind::Scroll(Direction::Down) => MK::ScrollDown,
+        InteractKind::Scroll(Direction::Left) => MK::ScrollLeft,
+        InteractKind::Scroll(Direction::Right) => MK::ScrollRight,
+        InteractKind::Hover => MK::Moved,
+    }
+}
+
+/// A mouse event at the center of the cell at the given column and row. Panels report the
+/// mouse position in pixels of [`FONT_SIZE`].
+pub(crate) fn mouse_event(
+    kind: crossterm::event::MouseEventKind,
+    column: u16,
+    row: u16,
+) -> crossterm::event::MouseEvent {
+    let font = Vec2::from(FONT_SIZE);
+    crossterm::event::MouseEvent {
+        kind,
+        column: column.saturating_mul(font.x).saturating_add(font.x / 2),
+        row: row.saturating_mul(font.y).saturating_add(font.y / 2),
+        modifiers: crossterm::event::KeyModifiers::NONE,
+    }
+}
@@ -0,0 +1,75 @@
+//! The width of text in cells, measured per grapheme cluster like terminals draw it.
+
+use serde::{Deserialize, Serialize};
+
+/// How wide characters of ambiguous width are drawn, e.g. `±` or `×`. Terminals usually draw
+/// them narrow, but some fonts and terminals configured for CJK locales draw them wide.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum AmbiguousWidth {
+    #[default]
+    Narrow,
+    Wide,
+}
+
+/// The width of a grapheme cluster. Emoji joined with zero width joiners take up the cells of
+/// a single emoji, even if `unicode-width` does not know the sequence and adds up its parts.
+pub(crate) fn grapheme_width(grapheme: &str, ambiguous: AmbiguousWidth) -> usize {
+    let width = |text: &str| match ambiguous {
+        AmbiguousWidth::Narrow => unicode_width::UnicodeWidthStr::width(text),
+        AmbiguousWidth::Wide => unicode_width::UnicodeWidthStr::width_cjk(text),
+    };
+    let joined_emoji = grapheme.contains('\u{200D}')
+        && grapheme
+            .chars()
+            .next()
+            .is_some_and(|it| width(it.encode_utf8(&mut [0; 4])) == 2);
+    if joined_emoji { 2 } else { width(grapheme) }
+}
+
+/// The width of `text`, see [`grapheme_width`].
+pub(crate) fn str_width(text: &str, ambiguous: AmbiguousWidth) -> usize {
+    unicode_segmentation::UnicodeSegmentation::graphemes(text, true)
+        .map(|it| grapheme_width(it, ambiguous))
+        .sum()
+}
+
+/// How text is measured while sizing and rendering elements.
+#[cfg(feature = "__bin")]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TextWidth {
+    pub ambiguous: AmbiguousWidth,
+    /// See [`super::Elem::with_glyph_widths`]. The innermost elements' overrides come first.
+    pub glyphs: Vec<(String, u8)>,
+}
+#[cfg(feature = "__bin")]
+impl TextWidth {
+    pub(crate) fn grapheme(&self, grapheme: &str) -> usize {
+        match self.overridden(grapheme) {
+            Some(width) => width.into(),
+            None => grapheme_width(grapheme, self.ambiguous),
+        }
+    }
+
+    /// The width of `grapheme` if an element overrides it.
+    pub(crate) fn overridden(&self, grapheme: &str) -> Option<u8> {
+        self.glyphs
+            .iter()
+            .find(|(glyph, _)| glyph == grapheme)
+            .map(|&(_, width)| width)
+    }
+
+    pub(crate) fn str(&self, text: &str) -> usize {
+        unicode_segmentation::UnicodeSegmentation::graphemes(text, true)
+            .map(|it| self.grapheme(it))
+            .sum()
+    }
+
+    /// Adds the overrides of an element, which take precedence over the current ones.
+    pub(crate) fn with_glyphs(&self, glyphs: &[(String, u8)]) -> Self {
+        Self {
+            ambiguous: self.ambiguous,
+            glyphs: glyphs.iter().chain(&self.glyphs).cloned().collect(),
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! Line breaking for [`Elem::wrapped_text`].
+
+use unicode_segmentation::UnicodeSegmentation as _;
+
+use crate::tui::*;
+
+/// Breaks `text` into lines that are at most `width` cells wide. Explicit newlines are kept.
+///
+/// Graphemes that are wider than `width` on their own get a line each.
+pub(crate) fn wrap_lines(
+    text: &str,
+    width: u16,
+    mode: WrapMode,
+    text_width: &TextWidth,
+) -> Vec<String> {
+    let width = usize::from(width.max(1));
+    let mut lines = Vec::new();
+    for paragraph in text.split('\n') {
+        let mut line = Line {
+            text: String::new(),
+            width: 0,
+            text_width,
+        };
+        match mode {
+            WrapMode::Char => {
+                for grapheme in paragraph.graphemes(true) {
+                    line.push_grapheme(grapheme, width, &mut lines);
+                }
+            }
+            WrapMode::Word => {
+                let mut after_break = false;
+                for word in words(paragraph) {
+                    if word.trim().is_empty() {
+                        // Whitespace is dropped at line breaks, but indentation is kept
+                        if !(after_break && line.width == 0) {
+                            line.push(word);
+                        }
+                        continue;
+                    }
+                    let word_width = text_width.str(word);
+                    if line.width > 0 && line.width + word_width > width {
+                        line.finish(&mut lines);
+                        after_break = true;
+                    }
+                    if word_width <= width {
+                        line.push(word);
+                    } else {
+                        for grapheme in word.graphemes(true) {
+                            line.push_grapheme(grapheme, width, &mut lines);
+                        }
+                    }
+                }
+            }
+        }
+        line.finish(&mut lines);
+    }
+    lines
+}
+
+struct Line<'a> {
+    text: String,
+    width: usize,
+    text_width: &'a TextWidth,
+}
+impl Line<'_> {
+    fn push(&mut self, text: &str) {
+        // Control characters would move the cursor or otherwise mess up the layout
+        let start = self.text.len();
+        self.text.extend(text.chars().filter(|it| !it.is_control()));
+        self.width += self.text_width.str(&self.text[start..]);
+    }
+
+    fn push_grapheme(&mut self, grapheme: &str, width: usize, lines: &mut Vec<String>) {
+        if self.width > 0 && self.width + self.text_width.grapheme(grapheme) > width {
+            self.finish(lines);
+        }
+        self.push(grapheme);
+    }
+
+    fn finish(&mut self, lines: &mut Vec<String>) {
+        self.width = 0;
+        lines.push(std::mem::take(&mut self.text).trim_end().to_owned());
+    }
+}
+
+/// Splits text into runs of whitespace and runs of other characters.
+fn words(text: &str) -> impl Iterator<Item = &str> {
+    let mut rest = text;
+    std::iter::from_fn(move || {
+        let first = rest.chars().next()?;
+        let len = rest
+            .find(|it: char| it.is_whitespace() != first.is_whitespace())
+            .unwrap_or(rest.len());
+        let (word, tail) = rest.split_at(len);
+        rest = tail;
+        Some(word)
+    })
+}
+
+/// The number of lines that `text` takes up when wrapped at `width`.
+pub(crate) fn wrapped_height(
+    text: &str,
+    width: u16,
+    mode: WrapMode,
+    text_width: &TextWidth,
+) -> u16 {
+    wrap_lines(text, width, mode, text_width)
+        .len()
+        .try_into()
+        .unwrap_or(u16::MAX)
+}
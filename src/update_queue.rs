@@ -0,0 +1,204 @@
+//! The updates of a controller connection that are waiting to be sent, see
+//! [`crate::host::HostConnectOpts::max_buffered_updates`] and
+//! [`crate::host::HostConnectOpts::reconnect`].
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+
+use crate::{
+    framing,
+    host::{BarSelect, BarUpdate, HostUpdate},
+    tui,
+    utils::ResultExt as _,
+};
+
+/// What part of the host's state an update replaces, if any.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum UpdateKey {
+    DefaultTui,
+    /// `None` for all bars.
+    BarTui(Option<Arc<str>>),
+    BarVisibility(Option<Arc<str>>),
+    BarSlot(tui::CustomId),
+    LogLevel,
+    EventFilter,
+    ModuleStatus,
+    Menu,
+    MenuSlot(tui::CustomId),
+    Osd,
+    Prompt,
+}
+impl UpdateKey {
+    pub(crate) fn of(update: &HostUpdate) -> Option<Self> {
+        let monitor = |select: &BarSelect| match select {
+            BarSelect::All => None,
+            BarSelect::OnMonitor { monitor_name } => Some(monitor_name.clone()),
+        };
+        Some(match update {
+            HostUpdate::SetDefaultTui(_) => Self::DefaultTui,
+            HostUpdate::UpdateBars(select, BarUpdate::SetTui(_)) => Self::BarTui(monitor(select)),
+            HostUpdate::UpdateBars(select, BarUpdate::Hide | BarUpdate::Show) => {
+                Self::BarVisibility(monitor(select))
+            }
+            HostUpdate::UpdateBarSlot(update) => Self::BarSlot(update.slot.clone()),
+            HostUpdate::SetLogLevel(_) => Self::LogLevel,
+            HostUpdate::SetEventFilter(_) => Self::EventFilter,
+            HostUpdate::ModuleStatus(_) => Self::ModuleStatus,
+            HostUpdate::OpenMenu(_) | HostUpdate::CloseMenu => Self::Menu,
+            HostUpdate::UpdateMenuSlot(update) => Self::MenuSlot(update.slot.clone()),
+            HostUpdate::ShowOsd(_) | HostUpdate::HideOsd => Self::Osd,
+            HostUpdate::OpenPrompt(_) | HostUpdate::ClosePrompt => Self::Prompt,
+            HostUpdate::QueryPanels
+            | HostUpdate::QueryMetrics
+            | HostUpdate::ToggleScratch(_)
+            | HostUpdate::RunCommand(_)
+            | HostUpdate::Shutdown { .. }
+            | HostUpdate::Query { .. }
+            | HostUpdate::Batch(_) => return None,
+        })
+    }
+
+    /// Whether an update with this key makes an earlier update with `old` irrelevant.
+    fn supersedes(&self, old: &Self) -> bool {
+        match (self, old) {
+            // Slots get their content from the new bars
+            (Self::BarTui(None), Self::BarTui(_) | Self::BarSlot(_))
+            | (Self::BarVisibility(None), Self::BarVisibility(_))
+            | (Self::Menu, Self::MenuSlot(_)) => true,
+            _ => self == old,
+        }
+    }
+
+    /// Whether the update is part of the bars' state, which is sent again after reconnecting.
+    /// Menus, popups and prompts are not.
+    fn is_state(&self) -> bool {
+        matches!(
+            self,
+            Self::DefaultTui
+                | Self::BarTui(_)
+                | Self::BarVisibility(_)
+                | Self::BarSlot(_)
+                | Self::LogLevel
+                | Self::EventFilter
+        )
+    }
+}
+
+/// The encoded updates that make up the current state of the bars, in the order they were sent.
+#[derive(Default)]
+pub(crate) struct SentState {
+    frames: Vec<(UpdateKey, Vec<u8>)>,
+}
+impl SentState {
+    pub(crate) fn record(&mut self, key: &UpdateKey, frame: &[u8]) {
+        if key.is_state() {
+            self.frames.retain(|(old, _)| !key.supersedes(old));
+            self.frames.push((key.clone(), frame.to_vec()));
+        }
+    }
+    /// Records the updates of a [`HostUpdate::Batch`] one by one.
+    pub(crate) fn record_batch(&mut self, updates: &[HostUpdate], format: framing::Format) {
+        for update in updates {
+            match UpdateKey::of(update) {
+                Some(key) if key.is_state() => {
+                    if let Some(frame) = format.encode(update).ok_or_log() {
+                        self.record(&key, &frame);
+                    }
+                }
+                Some(_) => {}
+                None => {
+                    if let HostUpdate::Batch(updates) = update {
+                        self.record_batch(updates, format);
+                    }
+                }
+            }
+        }
+    }
+    pub(crate) fn frames(&self) -> impl Iterator<Item = &[u8]> {
+        self.frames.iter().map(|(_, frame)| &frame[..])
+    }
+}
+
+pub(crate) enum Next {
+    Updates(Vec<(Option<UpdateKey>, HostUpdate)>),
+    /// All senders were dropped and every update was taken.
+    Closed,
+    /// See [`UpdateQueue::disconnect`].
+    Disconnected,
+}
+
+#[derive(Default)]
+struct QueueState {
+    updates: VecDeque<(Option<UpdateKey>, HostUpdate)>,
+    closed: bool,
+    disconnected: bool,
+}
+
+pub(crate) struct UpdateQueue {
+    state: Mutex<QueueState>,
+    cond: Condvar,
+    max_buffered: Option<std::num::NonZeroUsize>,
+}
+impl UpdateQueue {
+    pub(crate) fn new(max_buffered: Option<std::num::NonZeroUsize>) -> Self {
+        Self {
+            state: Default::default(),
+            cond: Condvar::new(),
+            max_buffered,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(|pe| pe.into_inner())
+    }
+
+    pub(crate) fn push(&self, update: HostUpdate) {
+        let key = UpdateKey::of(&update);
+        let mut state = self.lock();
+        if let Some(max) = self.max_buffered
+            && state.updates.len() >= max.get()
+            && let Some(key) = &key
+        {
+            let len = state.updates.len();
+            state
+                .updates
+                .retain(|(old, _)| !old.as_ref().is_some_and(|old| key.supersedes(old)));
+            log::trace!("Coalesced {} buffered updates", len - state.updates.len());
+        }
+        state.updates.push_back((key, update));
+        self.cond.notify_one();
+    }
+
+    pub(crate) fn close(&self) {
+        self.lock().closed = true;
+        self.cond.notify_one();
+    }
+
+    /// Wakes up [`Self::next`] when the connection was lost while it waits for updates.
+    pub(crate) fn disconnect(&self) {
+        self.lock().disconnected = true;
+        self.cond.notify_one();
+    }
+
+    /// Called when a new connection is established, before taking its updates.
+    pub(crate) fn reconnected(&self) {
+        self.lock().disconnected = false;
+    }
+
+    /// Waits for updates and takes all of them.
+    pub(crate) fn next(&self) -> Next {
+        let mut state = self
+            .cond
+            .wait_while(self.lock(), |it| {
+                it.updates.is_empty() && !it.closed && !it.disconnected
+            })
+            .unwrap_or_else(|pe| pe.into_inner());
+        if state.disconnected {
+            Next::Disconnected
+        } else if state.updates.is_empty() {
+            Next::Closed
+        } else {
+            Next::Updates(state.updates.drain(..).collect())
+        }
+    }
+}
@@ -0,0 +1,173 @@
+//! Property tests of the layout of random element trees, see [`scratchbar::tui::testing`].
+
+use proptest::prelude::*;
+use scratchbar::tui::{self, testing};
+
+fn leaf() -> impl Strategy<Value = tui::Elem> {
+    prop_oneof![
+        Just(tui::Elem::empty()),
+        "[a-z ]{0,12}".prop_map(|text| tui::Elem::text(text, Default::default())),
+        ("[a-z ]{0,30}", 1..10u16).prop_map(|(text, hint)| tui::Elem::wrapped_text(
+            text,
+            hint,
+            tui::WrapMode::Word
+        )),
+        Just(tui::Elem::fill_cells_single("·")),
+    ]
+}
+
+fn constraint() -> impl Strategy<Value = Option<tui::Constraint>> {
+    prop_oneof![
+        Just(None),
+        (0..6u16).prop_map(|it| Some(tui::Constraint::Min(it))),
+        (0..6u16).prop_map(|it| Some(tui::Constraint::Max(it))),
+        (0..=100u8).prop_map(|it| Some(tui::Constraint::Percent(it))),
+        (0..6u16).prop_map(|it| Some(tui::Constraint::Exact(it))),
+    ]
+}
+
+fn align() -> impl Strategy<Value = tui::Align> {
+    prop_oneof![
+        Just(tui::Align::Start),
+        Just(tui::Align::Center),
+        Just(tui::Align::End),
+    ]
+}
+
+fn elem() -> impl Strategy<Value = tui::Elem> {
+    leaf().prop_recursive(4, 32, 4, |inner| {
+        let item =
+            (inner.clone(), 0..3u16, constraint()).prop_map(|(elem, fill_weight, constraint)| {
+                tui::StackItem {
+                    elem,
+                    opts: tui::StackItemOpts {
+                        fill_weight,
+                        constraint,
+                        ..Default::default()
+                    },
+                }
+            });
+        prop_oneof![
+            (
+                prop_oneof![Just(tui::Axis::X), Just(tui::Axis::Y)],
+                prop::collection::vec(item, 0..4),
+                any::<bool>(),
+            )
+                .prop_map(|(axis, items, reverse)| {
+                    tui::Elem::stack(
+                        axis,
+                        items,
+                        tui::StackOpts {
+                            reverse,
+                            ..Default::default()
+                        },
+                    )
+                }),
+            prop::collection::vec(inner.clone(), 1..3).prop_map(tui::Elem::overlay),
+            (inner.clone(), align(), align())
+                .prop_map(|(elem, x, y)| tui::Elem::aligned(elem, tui::Align2D::new(x, y))),
+            (inner, any::<u8>())
+                .prop_map(|(elem, tag)| elem.interactive(tui::CustomId::from_bytes(&[tag]))),
+        ]
+    })
+}
+
+fn mouse_kind() -> impl Strategy<Value = tui::InteractKind> {
+    prop_oneof![
+        Just(tui::InteractKind::Hover),
+        Just(tui::InteractKind::Click(tui::MouseButton::Left)),
+        Just(tui::InteractKind::Click(tui::MouseButton::Right)),
+        Just(tui::InteractKind::Scroll(tui::Direction::Up)),
+        Just(tui::InteractKind::Scroll(tui::Direction::Down)),
+    ]
+}
+
+/// An element with a size that is at least its min size.
+fn elem_with_size() -> impl Strategy<Value = (tui::Elem, tui::Size)> {
+    (elem(), 0..8u16, 0..4u16).prop_map(|(elem, extra_width, extra_height)| {
+        let min_size = testing::min_size(&elem);
+        let size = tui::Size {
+            width: min_size.width + extra_width,
+            height: min_size.height + extra_height,
+        };
+        (elem, size)
+    })
+}
+
+proptest! {
+    #[test]
+    fn layout_invariants((elem, size) in elem_with_size()) {
+        let rendered = testing::RenderedElem::render(&elem, size);
+        prop_assert_eq!(rendered.check_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn mouse_hits_the_element_under_it(
+        (elem, size) in elem_with_size(),
+        events in prop::collection::vec((mouse_kind(), any::<u16>(), any::<u16>()), 1..8),
+    ) {
+        let mut rendered = testing::RenderedElem::render(&elem, size);
+        for (kind, column, row) in events {
+            let (column, row) = (column % size.width, row % size.height);
+            let expected = rendered.hit_test(column, row);
+            let hit = rendered.mouse(kind.clone(), column, row).map(|(tag, _)| tag);
+            prop_assert_eq!(hit, expected, "{:?} at {}, {}", kind, column, row);
+        }
+    }
+}
+
+/// Items of a stack that is too small were placed past its end.
+#[test]
+fn stack_items_stay_within_small_stacks() {
+    let elem = tui::Elem::stack(
+        tui::Axis::X,
+        [
+            tui::Elem::text("abc", Default::default()),
+            tui::Elem::text("def", Default::default()).interactive(tui::CustomId::from_bytes(b"d")),
+        ],
+        tui::StackOpts::default(),
+    );
+    for width in 0..6 {
+        let rendered = testing::RenderedElem::render(&elem, tui::Size { width, height: 1 });
+        // The stack is smaller than its min size, but its items must not leave it
+        if let Err(err) = rendered.check_invariants() {
+            assert!(!err.contains("outside"), "{err}");
+        }
+    }
+}
+
+/// Aligned elements reported the height of their content at the full width, although they are
+/// rendered at their min width, where wrapped text takes more lines.
+#[test]
+fn aligned_wrapped_text_gets_its_lines() {
+    let elem = tui::Elem::stack(
+        tui::Axis::Y,
+        [
+            tui::Elem::aligned(
+                tui::Elem::wrapped_text("aaa bbb ccc", 3, tui::WrapMode::Word),
+                tui::Align2D::CENTER,
+            ),
+            tui::Elem::text("below", Default::default()),
+        ],
+        tui::StackOpts::default(),
+    );
+    let min_size = testing::min_size(&elem);
+    let rendered = testing::RenderedElem::render(
+        &elem,
+        tui::Size {
+            width: 20,
+            height: min_size.height,
+        },
+    );
+    assert_eq!(rendered.check_invariants(), Ok(()));
+    assert_eq!(
+        testing::render_to_string(
+            &elem,
+            tui::Size {
+                width: 20,
+                height: min_size.height
+            }
+        ),
+        "        aaa\n        bbb\n        ccc\nbelow\n",
+    );
+}